@@ -73,6 +73,8 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let inode = root_inode.create(name).unwrap();
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
+        // apps can be run, see `open_exec` in the kernel
+        inode.set_mode(0o755);
     }
     // list apps
     for app in root_inode.ls() {
@@ -1,13 +1,27 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use easy_fs::{
+    BlockDevice, EasyFileSystem, FsError, Inode, FEATURE_COMPAT_MODES, FEATURE_COMPAT_TIMESTAMPS,
+    FEATURE_INCOMPAT_FIFO, FEATURE_INCOMPAT_QUOTA, FEATURE_INCOMPAT_REMAP, NAME_LENGTH_LIMIT,
+};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The kernel's SHA-256, so that the manifest is hashed the way it checks
+#[path = "../../os6/src/crypto/sha256.rs"]
+mod sha256;
 
 /// Use a block size of 512 bytes
 const BLOCK_SZ: usize = 512;
 const BLOCK_NUM: usize = 131072; //64*2048
+/// Blocks past the filesystem reserved for a snapshot of it, see
+/// `EasyFileSystem::snapshot`
+const SNAPSHOT_BLOCKS: usize = 65536;
+/// Data blocks kept to stand in for blocks the disk fails to write, see
+/// `easy_fs::RemapDevice`
+const SPARE_BLOCKS: u32 = 32;
 
 /// Wrapper for turning a File into a BlockDevice
 struct BlockFile(Mutex<File>);
@@ -29,12 +43,14 @@ impl BlockDevice for BlockFile {
     }
 }
 
-fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
+/// Seconds since the epoch by the host clock, to stamp the packed files
+fn host_clock() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32)
 }
 
-/// Pack a directory into a easy-fs disk image
-fn easy_fs_pack() -> std::io::Result<()> {
+fn main() {
     let matches = App::new("EasyFileSystem packer")
         .arg(
             Arg::with_name("source")
@@ -50,22 +66,131 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("data")
+                .short("d")
+                .long("data")
+                .takes_value(true)
+                .help("Data file dir(with backslash), packed under their own names"),
+        )
+        .subcommand(
+            SubCommand::with_name("core")
+                .about("Print a core file written by the kernel")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("easy-fs disk image"),
+                )
+                .arg(
+                    Arg::with_name("hex")
+                        .short("x")
+                        .long("hex")
+                        .help("Also dump the saved memory, skipping zero lines"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .help("Core file name, like core.5"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unpack")
+                .about("Copy a file out of an easy-fs image, to check what the kernel wrote")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("easy-fs disk image"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("Host file to write, the standard output if not given"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .help("File name in the image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Print the version, features and usage of an easy-fs image")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("easy-fs disk image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check an easy-fs image like the kernel does at boot")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("easy-fs disk image"),
+                )
+                .arg(
+                    Arg::with_name("repair")
+                        .short("r")
+                        .long("repair")
+                        .help("Fix what is found, the image is only read otherwise"),
+                ),
+        )
         .get_matches();
+    if let Some(matches) = matches.subcommand_matches("core") {
+        print_core(matches).expect("Error when reading core file!");
+    } else if let Some(matches) = matches.subcommand_matches("unpack") {
+        easy_fs_unpack(matches).expect("Error when unpacking easy-fs!");
+    } else if let Some(matches) = matches.subcommand_matches("info") {
+        match image_info(matches.value_of("image").unwrap()) {
+            Ok(info) => print!("{}", info),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("fsck") {
+        let consistent = easy_fs_check(matches).expect("Error when checking easy-fs!");
+        std::process::exit(if consistent { 0 } else { 1 });
+    } else {
+        easy_fs_pack(&matches).expect("Error when packing easy-fs!");
+    }
+}
+
+/// Pack a directory into a easy-fs disk image
+fn easy_fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     println!("src_path = {}\ntarget_path = {}", src_path, target_path);
+    easy_fs::set_clock(host_clock);
+    easy_fs::set_log(|args| eprintln!("{}", args));
     let block_file = Arc::new(BlockFile(Mutex::new({
         let f = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f.set_len(((BLOCK_NUM + SNAPSHOT_BLOCKS) * BLOCK_SZ) as u64).unwrap();
         f
     })));
-    let efs = EasyFileSystem::create(block_file.clone(), BLOCK_NUM as u32, 1);
+    let efs = EasyFileSystem::create_with_spares(block_file.clone(), BLOCK_NUM as u32, 1, SPARE_BLOCKS);
+    efs.lock().reserve_snapshot(SNAPSHOT_BLOCKS as u32).map_err(fs_error)?;
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
-    let apps: Vec<_> = read_dir(src_path)
+    let apps: Vec<String> = read_dir(src_path)
         .unwrap()
         .into_iter()
         .map(|dir_entry| {
@@ -74,7 +199,7 @@ fn easy_fs_pack() -> std::io::Result<()> {
             name_with_ext
         })
         .collect();
-    for app in apps {
+    for app in apps.iter() {
         // load app data (elf) from host file system
         let mut host_file = File::open(format!("{}{}", target_path, app)).unwrap();
         let mut all_data: Vec<u8> = Vec::new();
@@ -83,7 +208,22 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let inode = root_inode.create(app.as_str()).unwrap();
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
+        // apps can be run, see `open_exec` in the kernel
+        inode.set_mode(0o755);
     }
+    if let Some(data_path) = matches.value_of("data") {
+        for dir_entry in read_dir(data_path)? {
+            let name = dir_entry?.file_name().into_string().unwrap();
+            let mut all_data: Vec<u8> = Vec::new();
+            File::open(format!("{}{}", data_path, name))?.read_to_end(&mut all_data)?;
+            let inode = root_inode.create(name.as_str()).unwrap();
+            inode.write_at(0, all_data.as_slice());
+            inode.set_mode(0o644);
+        }
+    }
+    write_manifest(&root_inode, &apps)?;
+    // writes are cached, put them on the image
+    root_inode.sync_all();
     // list apps
     for app in root_inode.ls() {
         println!("{}", app);
@@ -91,6 +231,270 @@ fn easy_fs_pack() -> std::io::Result<()> {
     Ok(())
 }
 
+/// Write `manifest` under `root_inode`, a line `name size sha256` for each
+/// of `names` as packed, for the kernel to check them before they run.
+/// It comes after every packed file, see `fs::manifest` in the kernel.
+fn write_manifest(root_inode: &Inode, names: &[String]) -> std::io::Result<()> {
+    let mut manifest = String::new();
+    for name in names {
+        let data = read_file(root_inode, name)?;
+        let digest: String = sha256::sha256(&data).iter().map(|byte| format!("{:02x}", byte)).collect();
+        manifest += &format!("{} {} {}\n", name, data.len(), digest);
+    }
+    let inode = root_inode.create("manifest").map_err(fs_error)?;
+    inode.write_at(0, manifest.as_bytes());
+    inode.set_mode(0o444);
+    Ok(())
+}
+
+/// Read the whole file `name` under `root_inode`
+fn read_file(root_inode: &Inode, name: &str) -> std::io::Result<Vec<u8>> {
+    let inode = root_inode.find(name).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no {} in the image", name))
+    })?;
+    let mut data = Vec::new();
+    let mut buffer = [0u8; BLOCK_SZ];
+    loop {
+        let len = inode.read_at(data.len(), &mut buffer);
+        if len == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(&buffer[..len]);
+    }
+}
+
+/// Copy a file out of a easy-fs disk image
+fn easy_fs_unpack(matches: &ArgMatches) -> std::io::Result<()> {
+    let image = matches.value_of("image").unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).open(image)?,
+    )));
+    let efs = EasyFileSystem::open(block_file, true).map_err(fs_error)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let data = read_file(&root_inode, matches.value_of("name").unwrap())?;
+    match matches.value_of("output") {
+        Some(output) => File::create(output)?.write_all(&data),
+        None => std::io::stdout().write_all(&data),
+    }
+}
+
+/// An I/O error for an image easy-fs refuses to open
+fn fs_error(err: FsError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Names of the compatible and the incompatible features
+const COMPAT_NAMES: [(u32, &str); 2] = [
+    (FEATURE_COMPAT_TIMESTAMPS, "timestamps"),
+    (FEATURE_COMPAT_MODES, "modes"),
+];
+const INCOMPAT_NAMES: [(u32, &str); 3] = [
+    (FEATURE_INCOMPAT_FIFO, "fifo"),
+    (FEATURE_INCOMPAT_QUOTA, "quota"),
+    (FEATURE_INCOMPAT_REMAP, "remap"),
+];
+
+/// The names of the bits in `features`, unknown ones in hex
+fn feature_names(features: u32, names: &[(u32, &str)]) -> String {
+    let mut v: Vec<String> = names
+        .iter()
+        .filter(|(bit, _)| features & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let unknown = names.iter().fold(features, |features, (bit, _)| features & !bit);
+    if unknown != 0 {
+        v.push(format!("{:#x}", unknown));
+    }
+    if v.is_empty() {
+        v.push("none".to_string());
+    }
+    v.join(" ")
+}
+
+/// The version, features and usage of an easy-fs image
+fn image_info(image: &str) -> std::io::Result<String> {
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).open(image)?,
+    )));
+    let efs = EasyFileSystem::open(block_file, true).map_err(fs_error)?;
+    let efs = efs.lock();
+    let stat = efs.stat_fs();
+    let mut info = format!(
+        "version {}\ncompat features: {}\nincompat features: {}\n\
+         {} blocks, data blocks {} free of {}, inodes {} free of {}\n",
+        efs.version(),
+        feature_names(efs.compat_features(), &COMPAT_NAMES),
+        feature_names(efs.incompat_features(), &INCOMPAT_NAMES),
+        stat.total_blocks,
+        stat.free_data_blocks,
+        stat.data_blocks,
+        stat.free_inodes,
+        stat.total_inodes,
+    );
+    if efs.snapshot_blocks() > 0 {
+        info += &format!("snapshot region: {} blocks\n", efs.snapshot_blocks());
+    }
+    if stat.spare_blocks > 0 {
+        info += &format!("spare blocks: {}, {} remapped\n", stat.spare_blocks, stat.remapped_blocks);
+    }
+    let limit = |max: u32| if max == 0 { "no limit".to_string() } else { max.to_string() };
+    for (dir, quota) in efs.quotas() {
+        info += &format!(
+            "quota of directory {}: blocks {} of {}, inodes {} of {}\n",
+            dir,
+            quota.used_blocks,
+            limit(quota.max_blocks),
+            quota.used_inodes,
+            limit(quota.max_inodes),
+        );
+    }
+    Ok(info)
+}
+
+/// Check an easy-fs image and print the report, whether it is consistent
+/// afterwards
+fn easy_fs_check(matches: &ArgMatches) -> std::io::Result<bool> {
+    let image = matches.value_of("image").unwrap();
+    let repair = matches.is_present("repair");
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(repair).open(image)?,
+    )));
+    let efs = EasyFileSystem::open(block_file, !repair).map_err(fs_error)?;
+    let report = efs.lock().fsck(repair);
+    print!("{}", report);
+    Ok(report.is_consistent())
+}
+
+/// Layout of the core header, see `os6/src/task/coredump.rs`
+const CORE_MAGIC: &[u8; 8] = b"RCORE\0\0\x02";
+const CORE_NAME_LEN: usize = 32;
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Reads little endian u64 fields off a core file
+struct CoreReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CoreReader<'a> {
+    fn bytes(&mut self, len: usize) -> std::io::Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "core file is truncated",
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn u64(&mut self) -> std::io::Result<u64> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// `MapPermission` bits of the kernel as `rwxu`
+fn perm_str(perm: u64) -> String {
+    ["r", "w", "x", "u"]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| if perm & (1 << (i + 1)) != 0 { *c } else { "-" })
+        .collect()
+}
+
+/// Pretty-print a core file found in an easy-fs image
+fn print_core(matches: &ArgMatches) -> std::io::Result<()> {
+    let image = matches.value_of("image").unwrap();
+    let name = matches.value_of("name").unwrap();
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).open(image)?,
+    )));
+    let efs = EasyFileSystem::open(block_file, true).map_err(fs_error)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let inode = root_inode.find(name).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no {} in {}", name, image))
+    })?;
+    let mut data = Vec::new();
+    let mut buffer = [0u8; BLOCK_SZ];
+    loop {
+        let len = inode.read_at(data.len(), &mut buffer);
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..len]);
+    }
+
+    let mut reader = CoreReader { data: &data, pos: 0 };
+    if reader.bytes(CORE_MAGIC.len())? != CORE_MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is not a core file", name),
+        ));
+    }
+    let pid = reader.u64()?;
+    let signal = reader.u64()?;
+    let scause = reader.u64()?;
+    let stval = reader.u64()?;
+    let sepc = reader.u64()?;
+    let regions = reader.u64()?;
+    let task_name = reader.bytes(CORE_NAME_LEN)?;
+    let task_name_len = task_name.iter().position(|b| *b == 0).unwrap_or(CORE_NAME_LEN);
+    println!(
+        "pid {} '{}', signal {}, scause {:#x}, stval {:#x}, sepc {:#x}",
+        pid,
+        String::from_utf8_lossy(&task_name[..task_name_len]),
+        signal,
+        scause,
+        stval,
+        sepc
+    );
+    for (i, reg) in REG_NAMES.iter().enumerate() {
+        print!("{:>5} {:#018x}", reg, reader.u64()?);
+        if i % 4 == 3 {
+            println!();
+        }
+    }
+    let (stack, heap, mmap) = (reader.u64()?, reader.u64()?, reader.u64()?);
+    println!(
+        "layout offsets: stack {:#x}, heap {:#x}, mmap {:#x}",
+        stack, heap, mmap
+    );
+    for _ in 0..regions {
+        let start = reader.u64()?;
+        let size = reader.u64()?;
+        let perm = reader.u64()?;
+        let saved = reader.u64()?;
+        println!(
+            "[{:#x}, {:#x}) {} {} bytes, {} saved",
+            start,
+            start + size,
+            perm_str(perm),
+            size,
+            saved
+        );
+        let bytes = reader.bytes(saved as usize)?;
+        if matches.is_present("hex") {
+            for (i, line) in bytes.chunks(16).enumerate() {
+                if line.iter().all(|b| *b == 0) {
+                    continue;
+                }
+                print!("  {:#010x}:", start + i as u64 * 16);
+                for b in line {
+                    print!(" {:02x}", b);
+                }
+                println!();
+            }
+        }
+    }
+    Ok(())
+}
+
 #[test]
 fn efs_test() -> std::io::Result<()> {
     let block_file = Arc::new(BlockFile(Mutex::new({
@@ -99,14 +503,14 @@ fn efs_test() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open("target/fs.img")?;
-        f.set_len(BLOCK_NUM * BLOCK_SZ).unwrap();
+        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
         f
     })));
     EasyFileSystem::create(block_file.clone(), 4096, 1);
-    let efs = EasyFileSystem::open(block_file.clone());
+    let efs = EasyFileSystem::open(block_file.clone(), false).unwrap();
     let root_inode = EasyFileSystem::root_inode(&efs);
-    root_inode.create("filea");
-    root_inode.create("fileb");
+    root_inode.create("filea").unwrap();
+    root_inode.create("fileb").unwrap();
     for name in root_inode.ls() {
         println!("{}", name);
     }
@@ -153,3 +557,1273 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn efs_truncate_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_truncate.img")?;
+        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    let read_all = || {
+        let mut data = vec![0u8; file.size()];
+        assert_eq!(file.read_at(0, &mut data), data.len());
+        data
+    };
+
+    assert_eq!(file.append(b"hello "), 6);
+    assert_eq!(file.append(b"world"), 11);
+    assert_eq!(read_all(), b"hello world");
+
+    // shrink across the direct, indirect1 and indirect2 boundaries, the
+    // kept part is unchanged and a raised end reads zeros
+    for (size, keep) in [
+        (3000 * BLOCK_SZ, 1000 * BLOCK_SZ + 7),
+        (1000 * BLOCK_SZ, 100 * BLOCK_SZ),
+        (100 * BLOCK_SZ, 10 * BLOCK_SZ + 1),
+        (10 * BLOCK_SZ, 0),
+    ] {
+        let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8 + 1).collect();
+        file.truncate(0);
+        file.write_at(0, &data);
+        file.truncate(keep);
+        assert_eq!(file.size(), keep);
+        assert_eq!(read_all(), &data[..keep]);
+        file.truncate(keep + BLOCK_SZ);
+        let grown = read_all();
+        assert_eq!(&grown[..keep], &data[..keep]);
+        assert!(grown[keep..].iter().all(|b| *b == 0));
+    }
+
+    // the blocks freed are reused, without leaking the disk would fill up
+    for _ in 0..10 {
+        file.truncate(0);
+        file.write_at(0, &vec![1u8; 3000 * BLOCK_SZ]);
+    }
+    Ok(())
+}
+
+#[test]
+fn efs_stat_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static NOW: AtomicU32 = AtomicU32::new(1000);
+    easy_fs::set_clock(|| NOW.load(Ordering::Relaxed));
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_stat.img")?;
+        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    let stat = file.stat();
+    assert!(!stat.is_dir && root_inode.stat().is_dir);
+    assert_eq!((stat.size, stat.nlink), (0, 1));
+    assert_eq!((stat.atime, stat.mtime, stat.ctime), (1000, 1000, 1000));
+
+    // a write changes the data, a read is only an access, a link the status
+    NOW.store(2000, Ordering::Relaxed);
+    file.write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    NOW.store(3000, Ordering::Relaxed);
+    file.read_at(0, &mut [0u8; 10]);
+    NOW.store(4000, Ordering::Relaxed);
+    assert_eq!(root_inode.link("file", "link"), Ok(()));
+    let stat = file.stat();
+    assert_eq!((stat.size, stat.nlink), (3 * BLOCK_SZ as u64, 2));
+    assert_eq!((stat.atime, stat.mtime, stat.ctime), (3000, 2000, 4000));
+    assert_eq!(root_inode.stat().mtime, 4000);
+    Ok(())
+}
+
+#[test]
+fn efs_dir_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_dir.img")?;
+        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.create_dir("dir").unwrap();
+    assert!(dir.is_dir());
+    assert_eq!(root_inode.create_dir("dir").err(), Some(FsError::Exists));
+    assert_eq!(
+        root_inode.create("a_name_longer_than_an_entry_holds").err(),
+        Some(FsError::NameTooLong)
+    );
+    let file = dir.create("file").unwrap();
+    // neither can be created under a file
+    assert_eq!(file.create("x").err(), Some(FsError::NotDir));
+    assert_eq!(file.create_dir("x").err(), Some(FsError::NotDir));
+    assert!(file.find("x").is_none());
+    root_inode.create("gone").unwrap();
+    root_inode.create("kept").unwrap();
+    root_inode.unlink("gone");
+
+    // removed entries are skipped, the slot after an entry resumes the list
+    let mut entries = Vec::new();
+    let mut slot = 0;
+    while let Some((found, entry)) = root_inode.read_dirent(slot) {
+        entries.push((entry.name, entry.is_dir));
+        slot = found + 1;
+    }
+    assert_eq!(entries, [("dir".to_string(), true), ("kept".to_string(), false)]);
+    let (_, entry) = dir.read_dirent(0).unwrap();
+    assert_eq!((entry.name.as_str(), entry.is_dir), ("file", false));
+    assert!(dir.read_dirent(1).is_none());
+    assert!(file.read_dirent(0).is_none());
+
+    // a file linked into another directory, directories are only removed empty
+    assert_eq!(root_inode.add_link("alias", &file), Ok(()));
+    assert_eq!(file.stat().nlink, 2);
+    assert_eq!(root_inode.add_link("alias", &file), Err(FsError::Exists));
+    assert_eq!(root_inode.add_link("dir2", &dir), Err(FsError::IsDir));
+    assert_eq!(root_inode.remove_dir("dir"), -1);
+    assert_eq!(root_inode.remove_dir("kept"), -1);
+    assert_eq!(dir.unlink("file"), 0);
+    assert_eq!(root_inode.find("alias").unwrap().stat().nlink, 1);
+    assert_eq!(root_inode.remove_dir("dir"), 0);
+    assert!(root_inode.find("dir").is_none());
+    assert_eq!(root_inode.unlink("alias"), 0);
+    assert_eq!(root_inode.unlink("alias"), -1);
+    Ok(())
+}
+
+#[test]
+fn efs_name_length_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk, 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.create_dir("dir").unwrap();
+    assert_eq!(NAME_LENGTH_LIMIT, 27);
+    // names up to the limit round-trip through create, find and ls, longer
+    // ones are refused rather than cut short
+    for len in [26, 27, 28, 100] {
+        let name: String = (0..len).map(|i| (b'a' + i as u8 % 26) as char).collect();
+        let dir_name = format!("d{}", &name[1..]);
+        if len <= NAME_LENGTH_LIMIT {
+            let file = root_inode.create(&name).unwrap();
+            assert_eq!(root_inode.find(&name).unwrap().inode_id(), file.inode_id());
+            assert!(root_inode.ls().contains(&name));
+            let sub = dir.create_dir(&dir_name).unwrap();
+            assert_eq!(dir.find(&dir_name).unwrap().inode_id(), sub.inode_id());
+            assert_eq!(root_inode.create(&name).err(), Some(FsError::Exists));
+            assert_eq!(dir.add_link(&name, &file), Ok(()));
+            assert_eq!(dir.find(&name).unwrap().inode_id(), file.inode_id());
+        } else {
+            assert_eq!(root_inode.create(&name).err(), Some(FsError::NameTooLong));
+            assert_eq!(dir.create_dir(&dir_name).err(), Some(FsError::NameTooLong));
+            assert_eq!(root_inode.create_fifo(&name).err(), Some(FsError::NameTooLong));
+            let file = root_inode.find(&name[..NAME_LENGTH_LIMIT]).unwrap();
+            assert_eq!(dir.add_link(&name, &file), Err(FsError::NameTooLong));
+            assert!(root_inode.find(&name).is_none());
+            assert!(root_inode.ls().iter().all(|entry| entry.len() <= NAME_LENGTH_LIMIT));
+        }
+    }
+    assert_eq!(root_inode.create("").err(), Some(FsError::EmptyName));
+    assert_eq!(root_inode.link("missing", "other"), Err(FsError::NotFound));
+
+    // a corrupted entry filling its whole name field, and one that is not
+    // UTF-8, read up to where the field or the name ends
+    let mut entry = [b'x'; 32];
+    entry[28..].copy_from_slice(&0u32.to_le_bytes());
+    let slot = root_inode.size();
+    root_inode.write_at(slot, &entry);
+    entry[..4].copy_from_slice(&[b'y', b'z', 0xff, 0]);
+    root_inode.write_at(slot + 32, &entry);
+    let names = root_inode.ls();
+    assert!(names.contains(&"x".repeat(28)));
+    assert!(names.contains(&"yz".to_string()));
+    Ok(())
+}
+
+#[test]
+fn efs_sync_test() -> std::io::Result<()> {
+    // a boot sees a copy of the image as it is now, as if the power went
+    // off: what is still cached on the running side is lost
+    let boot = || -> std::io::Result<Inode> {
+        std::fs::copy("target/fs_sync.img", "target/fs_sync_boot.img")?;
+        let block_file = Arc::new(BlockFile(Mutex::new(
+            OpenOptions::new().read(true).write(true).open("target/fs_sync_boot.img")?,
+        )));
+        Ok(EasyFileSystem::root_inode(&EasyFileSystem::open(block_file, false).unwrap()))
+    };
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_sync.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // past the direct and indirect1 blocks
+    let data: Vec<u8> = (0..300 * BLOCK_SZ + 7).map(|i| (i % 251) as u8).collect();
+    let file = root_inode.create("file").unwrap();
+    file.write_at(0, &data);
+    file.sync();
+    assert_eq!(read_file(&boot()?, "file")?, data);
+
+    file.append(b"tail");
+    let other = root_inode.create("other").unwrap();
+    other.write_at(0, b"other");
+    root_inode.sync_all();
+    let rebooted = boot()?;
+    assert_eq!(read_file(&rebooted, "file")?.len(), data.len() + 4);
+    assert_eq!(read_file(&rebooted, "other")?, b"other");
+    Ok(())
+}
+
+#[test]
+fn efs_vectored_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_vectored.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+
+    // pieces across a block boundary, an empty one among them
+    let big = vec![7u8; BLOCK_SZ];
+    assert_eq!(file.write_at_vectored(3, &[b"head", b"", &big, b"tail"]), BLOCK_SZ + 8);
+    assert_eq!(file.size(), BLOCK_SZ + 11);
+    let (mut a, mut b, mut c) = (vec![0u8; 7], vec![0u8; 0], vec![0u8; 2 * BLOCK_SZ]);
+    let read_size = file.read_at_vectored(0, &mut [&mut a, &mut b, &mut c]);
+    assert_eq!(read_size, BLOCK_SZ + 11);
+    assert_eq!(&a, b"\0\0\0head");
+    assert_eq!(&c[..BLOCK_SZ], &big[..]);
+    assert_eq!(&c[BLOCK_SZ..BLOCK_SZ + 4], b"tail");
+    assert_eq!(file.read_at_vectored(BLOCK_SZ + 11, &mut [&mut a]), 0);
+    // an end past the largest inode size writes nothing
+    let offset = u32::MAX as usize - 1;
+    assert_eq!(file.try_write_at_vectored(offset, &[b"ab", b"c"]), Err(FsError::FileTooLarge));
+    assert_eq!(file.size(), BLOCK_SZ + 11);
+    Ok(())
+}
+
+#[test]
+fn efs_copy_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_copy.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let src = root_inode.create("src").unwrap();
+    let dst = root_inode.create("dst").unwrap();
+    let data: Vec<u8> = (0..200 * BLOCK_SZ + 3).map(|i| (i % 251) as u8).collect();
+    src.write_at(0, &data);
+
+    // unaligned on both sides, then stopping at the end of the source
+    assert_eq!(src.copy_to(7, &dst, 100, 3 * BLOCK_SZ), 3 * BLOCK_SZ);
+    let mut buf = vec![0u8; 3 * BLOCK_SZ];
+    assert_eq!(dst.read_at(100, &mut buf), 3 * BLOCK_SZ);
+    assert_eq!(&buf[..], &data[7..7 + 3 * BLOCK_SZ]);
+    assert_eq!(src.copy_to(0, &dst, 0, data.len() + 1000), data.len());
+    let mut buf = vec![0u8; data.len()];
+    assert_eq!(dst.read_at(0, &mut buf), data.len());
+    assert_eq!(buf, data);
+    assert_eq!(src.copy_to(data.len(), &dst, 0, 10), 0);
+    Ok(())
+}
+
+#[test]
+fn efs_copy_aligned_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk, 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let src = root_inode.create("src").unwrap();
+    let data: Vec<u8> = (0..200 * BLOCK_SZ + 3).map(|i| (i % 251) as u8).collect();
+    src.write_at(0, &data);
+    let contents = |inode: &Inode| {
+        let mut buf = vec![0u8; inode.size()];
+        assert_eq!(inode.read_at(0, &mut buf), buf.len());
+        buf
+    };
+
+    // whole blocks, a partial last block, past the end of the source and
+    // of the destination, then unaligned, all as a copy through a buffer
+    let cases = [
+        (0, 0, 10 * BLOCK_SZ),
+        (5 * BLOCK_SZ, 2 * BLOCK_SZ, 16 * BLOCK_SZ),
+        (0, 0, 202 * BLOCK_SZ),
+        (BLOCK_SZ, 300 * BLOCK_SZ, 3 * BLOCK_SZ),
+        (7, 0, 4 * BLOCK_SZ),
+        (0, 100, 4 * BLOCK_SZ),
+        (0, 0, 3 * BLOCK_SZ + 1),
+    ];
+    for (i, &(offset, dst_offset, len)) in cases.iter().enumerate() {
+        let fast = root_inode.create(&format!("fast{}", i)).unwrap();
+        let slow = root_inode.create(&format!("slow{}", i)).unwrap();
+        for inode in [&fast, &slow] {
+            inode.write_at(0, &[0xeeu8; 20 * BLOCK_SZ]);
+        }
+        let copied = src.copy_to(offset, &fast, dst_offset, len);
+        let mut buf = vec![0u8; len];
+        let read_size = src.read_at(offset, &mut buf);
+        assert_eq!(slow.write_at(dst_offset, &buf[..read_size]), read_size);
+        assert_eq!(copied, read_size, "case {}", i);
+        assert!(contents(&fast) == contents(&slow), "case {}", i);
+    }
+    assert_eq!(contents(&src), data);
+
+    // one block straight from another, growing the destination
+    let dst = root_inode.create("dst").unwrap();
+    let src_block_id = src.block_id(1).unwrap();
+    assert_eq!(src.block_id(201), None);
+    assert_eq!(dst.write_block_aligned(3, src_block_id), Ok(()));
+    assert_eq!(dst.size(), 4 * BLOCK_SZ);
+    let mut buf = [0u8; BLOCK_SZ];
+    dst.read_at(3 * BLOCK_SZ, &mut buf);
+    assert_eq!(&buf[..], &data[BLOCK_SZ..2 * BLOCK_SZ]);
+    dst.read_at(0, &mut buf);
+    assert_eq!(buf, [0u8; BLOCK_SZ]);
+    // only allocated data blocks, and not on a read-only filesystem
+    assert_eq!(dst.write_block_aligned(0, 0), Err(FsError::BadBlock(0)));
+    let free_block = efs.lock().metadata_blocks() + 3000;
+    assert_eq!(dst.write_block_aligned(0, free_block), Err(FsError::BadBlock(free_block)));
+    efs.lock().set_read_only(true);
+    assert_eq!(dst.write_block_aligned(0, src_block_id), Err(FsError::ReadOnly));
+    assert_eq!(dst.size(), 4 * BLOCK_SZ);
+    Ok(())
+}
+
+#[test]
+fn efs_read_only_mount_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.create("file").unwrap().write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    root_inode.create_dir("dir").unwrap().create("inner").unwrap();
+    root_inode.sync_all();
+    drop(root_inode);
+    drop(efs);
+    let image = disk.blocks.lock().unwrap().clone();
+
+    let efs = EasyFileSystem::open(disk.clone(), true).unwrap();
+    assert!(efs.lock().is_mounted_read_only());
+    // clearing the flag fsck sets does not lift it
+    efs.lock().set_read_only(false);
+    assert!(efs.lock().is_read_only());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(root_inode.read_only());
+    let file = root_inode.find("file").unwrap();
+    let dir = root_inode.find("dir").unwrap();
+    let mut buf = [0u8; 3 * BLOCK_SZ];
+    assert_eq!(file.read_at(0, &mut buf), buf.len());
+    assert_eq!(buf, [1u8; 3 * BLOCK_SZ]);
+
+    assert_eq!(root_inode.create("new").err(), Some(FsError::ReadOnly));
+    assert_eq!(root_inode.create_dir("newdir").err(), Some(FsError::ReadOnly));
+    assert_eq!(root_inode.create_fifo("fifo").err(), Some(FsError::ReadOnly));
+    assert_eq!(root_inode.link("file", "other"), Err(FsError::ReadOnly));
+    assert_eq!(root_inode.add_link("other", &file), Err(FsError::ReadOnly));
+    assert_eq!(root_inode.unlink("file"), -1);
+    assert_eq!(dir.unlink("inner"), -1);
+    assert_eq!(root_inode.remove_dir("dir"), -1);
+    assert_eq!(file.write_at(0, &[2u8; 10]), 0);
+    assert_eq!(file.write_at_vectored(0, &[&[2u8; 10]]), 0);
+    assert_eq!(file.append(&[2u8; 10]), 3 * BLOCK_SZ);
+    assert_eq!(file.copy_to(0, &file, 3 * BLOCK_SZ, BLOCK_SZ), 0);
+    let block_id = file.block_id(0).unwrap();
+    assert_eq!(file.write_block_aligned(4, block_id), Err(FsError::ReadOnly));
+    file.fallocate(100 * BLOCK_SZ);
+    file.truncate(1);
+    file.clear();
+    file.set_mode(0o600);
+    assert_eq!(file.size(), 3 * BLOCK_SZ);
+    assert_eq!(file.mode(), 0o644);
+    assert!(root_inode.find("dir").is_some());
+    let report = efs.lock().fsck(true);
+    assert!(report.is_consistent() && !report.repaired);
+    root_inode.sync_all();
+    drop((root_inode, file, dir));
+    drop(efs);
+    assert!(*disk.blocks.lock().unwrap() == image);
+
+    // opened again for writing, changes go through
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    assert!(!efs.lock().is_read_only());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(root_inode.create("new").is_ok());
+    root_inode.sync_all();
+    assert!(*disk.blocks.lock().unwrap() != image);
+    Ok(())
+}
+
+#[test]
+fn efs_stat_fs_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_stat_fs.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let before = root_inode.stat_fs();
+    assert_eq!(before.total_blocks, 4096);
+    assert_eq!(before.total_inodes, (BLOCK_SZ * 8) as u32);
+    // only the root directory so far
+    assert_eq!(before.free_inodes, before.total_inodes - 1);
+    assert!(before.data_blocks < before.total_blocks);
+    assert!(before.free_data_blocks <= before.data_blocks);
+
+    let file = root_inode.create("stat_fs").unwrap();
+    file.write_at(0, &[1u8; 10 * BLOCK_SZ]);
+    let after = root_inode.stat_fs();
+    assert_eq!(after.free_inodes, before.free_inodes - 1);
+    // ten data blocks and the root directory's first block for the entry
+    assert_eq!(after.free_data_blocks, before.free_data_blocks - 11);
+    Ok(())
+}
+
+#[test]
+fn efs_fifo_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_fifo.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let fifo = root_inode.create_fifo("fifo").unwrap();
+    assert_eq!(root_inode.create_fifo("fifo").err(), Some(FsError::Exists));
+    assert!(fifo.is_fifo() && !fifo.is_dir());
+    let stat = fifo.stat();
+    assert!(stat.is_fifo && !stat.is_dir);
+    assert_eq!(stat.size, 0);
+    let (_, entry) = root_inode.read_dirent(0).unwrap();
+    assert_eq!(entry.name, "fifo");
+    assert!(entry.is_fifo && !entry.is_dir);
+    assert!(!root_inode.create("file").unwrap().is_fifo());
+    Ok(())
+}
+
+#[test]
+fn efs_mode_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_mode.img")?;
+        f.set_len((4096 * BLOCK_SZ) as u64).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.mode(), 0o755);
+    let file = root_inode.create("file").unwrap();
+    assert_eq!(file.mode(), 0o644);
+    assert_eq!(root_inode.create_dir("dir").unwrap().mode(), 0o755);
+    assert_eq!(root_inode.create_fifo("fifo").unwrap().mode(), 0o644);
+    file.set_mode(0o4751);
+    assert_eq!(file.stat().mode, 0o751);
+    // the mode sits in the padding, neighbouring inodes are untouched
+    file.write_at(0, b"data");
+    assert_eq!(root_inode.find("file").unwrap().mode(), 0o751);
+    assert_eq!(root_inode.find("dir").unwrap().mode(), 0o755);
+    assert_eq!(file.size(), 4);
+    Ok(())
+}
+
+/// A disk in memory, remembering the most blocks one batched call moved
+#[cfg(test)]
+struct RamDisk {
+    blocks: Mutex<Vec<[u8; BLOCK_SZ]>>,
+    /// Most blocks of one `read_blocks` and of one `write_blocks` call
+    batches: Mutex<(usize, usize)>,
+}
+
+#[cfg(test)]
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.blocks.lock().unwrap()[block_id]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.blocks.lock().unwrap()[block_id].copy_from_slice(buf);
+    }
+    fn read_blocks(&self, start: usize, bufs: &mut [&mut [u8]]) {
+        let mut batches = self.batches.lock().unwrap();
+        batches.0 = batches.0.max(bufs.len());
+        for (block_id, buf) in (start..).zip(bufs.iter_mut()) {
+            self.read_block(block_id, buf);
+        }
+    }
+    fn write_blocks(&self, start: usize, bufs: &[&[u8]]) {
+        let mut batches = self.batches.lock().unwrap();
+        batches.1 = batches.1.max(bufs.len());
+        for (block_id, buf) in (start..).zip(bufs.iter()) {
+            self.write_block(block_id, buf);
+        }
+    }
+}
+
+#[test]
+fn efs_batched_io_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    EasyFileSystem::create(disk.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("batched").unwrap();
+    // the data blocks of a file written at once are consecutive
+    let data: Vec<u8> = (0..32 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    file.write_at(0, &data);
+    root_inode.sync_all();
+    assert!(disk.batches.lock().unwrap().1 > 1);
+    // push the file out of the cache, then read it back in batches
+    root_inode.create("other").unwrap().write_at(0, &[7u8; 32 * BLOCK_SZ]);
+    let mut buf = vec![0u8; data.len()];
+    assert_eq!(file.read_at(0, &mut buf), data.len());
+    assert!(buf == data);
+    assert!(disk.batches.lock().unwrap().0 > 1);
+    // a copy of the disk after the writeback reads like the cache
+    let mut oracle = data;
+    for (i, offset) in [100, 3 * BLOCK_SZ - 7, 17 * BLOCK_SZ].iter().enumerate() {
+        let patch = [i as u8 + 1; 1000];
+        file.write_at(*offset, &patch);
+        oracle[*offset..*offset + patch.len()].copy_from_slice(&patch);
+    }
+    root_inode.sync_all();
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(disk.blocks.lock().unwrap().clone()),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::open(copy, false).unwrap();
+    let file = EasyFileSystem::root_inode(&efs).find("batched").unwrap();
+    assert_eq!(file.read_at(0, &mut buf), oracle.len());
+    assert!(buf == oracle);
+    Ok(())
+}
+
+/// Offset of the link count in a disk inode, after the size, the direct
+/// blocks and the two index blocks
+#[cfg(test)]
+const NLINK_OFFSET: usize = 4 + 28 * 4 + 4 + 4;
+
+#[test]
+fn efs_dir_nlink_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.stat().nlink, 2);
+
+    // a directory counts its entry, its `.` and the `..` of each subdirectory
+    let a = root_inode.create_dir("a").unwrap();
+    let b = root_inode.create_dir("b").unwrap();
+    let a1 = a.create_dir("a1").unwrap();
+    let a2 = a.create_dir("a2").unwrap();
+    let a11 = a1.create_dir("a11").unwrap();
+    let file = a.create("file").unwrap();
+    assert_eq!(root_inode.stat().nlink, 4);
+    assert_eq!((a.stat().nlink, b.stat().nlink), (4, 2));
+    assert_eq!((a1.stat().nlink, a2.stat().nlink, a11.stat().nlink), (3, 2, 2));
+    assert_eq!(file.stat().nlink, 1);
+    // files do not count, nor do links to them
+    assert_eq!(b.add_link("alias", &file), Ok(()));
+    assert_eq!((b.stat().nlink, file.stat().nlink), (2, 2));
+    assert_eq!(efs.lock().fsck(false).problems(), 0);
+
+    // a removed directory takes its `..` with it
+    assert_eq!(a.remove_dir("a1"), -1);
+    assert_eq!(a1.remove_dir("a11"), 0);
+    assert_eq!((a1.stat().nlink, a11.stat().nlink), (2, 0));
+    assert_eq!(a.remove_dir("a1"), 0);
+    assert_eq!((a.stat().nlink, a1.stat().nlink), (3, 0));
+    assert_eq!(a.remove_dir("file"), -1);
+    assert_eq!(a.stat().nlink, 3);
+    assert_eq!(root_inode.remove_dir("b"), -1);
+    assert_eq!(b.unlink("alias"), 0);
+    assert_eq!(root_inode.remove_dir("b"), 0);
+    assert_eq!(root_inode.stat().nlink, 3);
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems(), 0);
+    assert_eq!(report.unlinked_inodes, 3);
+
+    // a count off, as on an image from before, is found and repaired
+    root_inode.sync_all();
+    let mut blocks = disk.blocks.lock().unwrap().clone();
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(a.inode_id() as u32);
+    blocks[block_id as usize][offset + NLINK_OFFSET] = 1;
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(blocks),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::open(copy.clone(), false).unwrap();
+    let report = efs.lock().fsck(true);
+    assert_eq!(report.problems(), 1);
+    assert_eq!(report.nlink_mismatches, 1);
+    assert!(report.repaired);
+    let a = EasyFileSystem::root_inode(&efs).find("a").unwrap();
+    assert_eq!(a.stat().nlink, 3);
+    assert!(efs.lock().fsck(false).is_consistent());
+    Ok(())
+}
+
+#[test]
+fn efs_fsck_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    file.write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    let dir = root_inode.create_dir("dir").unwrap();
+    dir.create("inner").unwrap().write_at(0, b"inner");
+    let dangle = root_inode.create("dangle").unwrap();
+    root_inode.create("gone").unwrap().write_at(0, &[2u8; BLOCK_SZ]);
+    assert_eq!(root_inode.unlink("gone"), 0);
+    // an unlinked inode is left behind, but that is no problem
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems(), 0);
+    assert_eq!(report.inodes, 5);
+    assert_eq!(report.unlinked_inodes, 1);
+    assert!(!root_inode.read_only());
+    root_inode.sync_all();
+
+    // a copy of the image with each kind of problem a repair fixes
+    let mut blocks = disk.blocks.lock().unwrap().clone();
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(file.inode_id() as u32);
+    blocks[block_id as usize][offset + NLINK_OFFSET] = 5;
+    let data_bitmap = efs.lock().metadata_blocks() as usize - 1;
+    // the first data block holds the entries of the root
+    blocks[data_bitmap][0] &= !1;
+    blocks[data_bitmap][1000 / 8] |= 1 << (1000 % 8);
+    let dangle_id = dangle.inode_id() as usize;
+    blocks[1][dangle_id / 8] &= !(1 << (dangle_id % 8));
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(blocks),
+        batches: Mutex::new((0, 0)),
+    });
+
+    // found at boot, read-only without a repair
+    let efs = EasyFileSystem::open(copy.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let report = efs.lock().fsck(false);
+    println!("{}", report);
+    assert_eq!(report.nlink_mismatches, 1);
+    assert_eq!(report.unmarked_blocks, 1);
+    assert_eq!(report.leaked_blocks, 1);
+    assert_eq!(report.dangling_dirents, 1);
+    assert_eq!((report.lost_inodes, report.fatal), (0, 0));
+    assert_eq!(report.problems() as usize, report.details.len());
+    assert!(!report.is_consistent());
+    assert!(root_inode.read_only());
+    assert_eq!(root_inode.create("new").err(), Some(FsError::ReadOnly));
+    assert_eq!(root_inode.unlink("file"), -1);
+    let file = root_inode.find("file").unwrap();
+    assert_eq!(file.write_at(0, b"x"), 0);
+    assert_eq!(file.nlink(), 5);
+
+    // repaired, then clean
+    let report = efs.lock().fsck(true);
+    assert!(report.repaired && report.is_consistent());
+    assert_eq!(report.problems(), 4);
+    assert_eq!(report.unlinked_inodes, 1);
+    assert!(!root_inode.read_only());
+    assert_eq!(file.nlink(), 1);
+    assert!(root_inode.find("dangle").is_none());
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems() + report.unlinked_inodes, 0);
+    assert_eq!(report.inodes, 4);
+    let stat = root_inode.stat_fs();
+    assert_eq!(stat.total_inodes - stat.free_inodes, 4);
+    // every allocated block is owned
+    assert_eq!(stat.data_blocks - stat.free_data_blocks, report.blocks);
+    let mut buf = vec![0u8; 3 * BLOCK_SZ];
+    assert_eq!(file.read_at(0, &mut buf), buf.len());
+    assert!(buf.iter().all(|byte| *byte == 1));
+    let inner = root_inode.find("dir").unwrap().find("inner").unwrap();
+    assert_eq!(inner.read_at(0, &mut buf), 5);
+    assert_eq!(&buf[..5], b"inner");
+    assert!(root_inode.create("new").is_ok());
+    Ok(())
+}
+
+#[test]
+fn efs_fsck_fatal_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let first = root_inode.create("first").unwrap();
+    first.write_at(0, b"first");
+    let second = root_inode.create("second").unwrap();
+    second.write_at(0, b"second");
+    root_inode.sync_all();
+
+    // the second file claims the block of the first one too
+    let mut blocks = disk.blocks.lock().unwrap().clone();
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(first.inode_id() as u32);
+    let direct = blocks[block_id as usize][offset + 4..offset + 8].to_vec();
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(second.inode_id() as u32);
+    blocks[block_id as usize][offset + 4..offset + 8].copy_from_slice(&direct);
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(blocks),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::open(copy.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let before = copy.blocks.lock().unwrap().clone();
+    // no repair is safe, the image stays as it is and read-only
+    let report = efs.lock().fsck(true);
+    println!("{}", report);
+    assert_eq!(report.fatal, 1);
+    // the block the second file lost
+    assert_eq!(report.leaked_blocks, 1);
+    assert!(!report.repaired && !report.is_consistent());
+    assert!(root_inode.read_only());
+    assert_eq!(root_inode.create("new").err(), Some(FsError::ReadOnly));
+    assert_eq!(root_inode.create_dir("dir").err(), Some(FsError::ReadOnly));
+    let second = root_inode.find("second").unwrap();
+    assert_eq!(second.write_at(0, b"x"), 0);
+    second.truncate(0);
+    second.set_mode(0o600);
+    assert_eq!(root_inode.unlink("first"), -1);
+    let mut buf = [0u8; 8];
+    assert_eq!(second.read_at(0, &mut buf), 6);
+    assert_eq!(&buf[..6], b"first\0");
+    root_inode.sync_all();
+    assert!(*copy.blocks.lock().unwrap() == before);
+    Ok(())
+}
+
+#[test]
+fn efs_read_ahead_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    EasyFileSystem::create(disk.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    let file = EasyFileSystem::root_inode(&efs).create("ahead").unwrap();
+    file.write_at(0, &[3u8; 20 * BLOCK_SZ]);
+    file.sync_all();
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(disk.blocks.lock().unwrap().clone()),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::open(copy.clone(), false).unwrap();
+    let file = EasyFileSystem::root_inode(&efs).find("ahead").unwrap();
+    // one batch, no more blocks than the limit and none past the end
+    file.read_ahead(BLOCK_SZ + 7, 100 * BLOCK_SZ);
+    assert_eq!(copy.batches.lock().unwrap().0, easy_fs::READ_AHEAD_BLOCKS);
+    *copy.batches.lock().unwrap() = (0, 0);
+    file.read_ahead(19 * BLOCK_SZ, 100 * BLOCK_SZ);
+    file.read_ahead(20 * BLOCK_SZ, BLOCK_SZ);
+    assert_eq!(copy.batches.lock().unwrap().0, 1);
+    let mut buf = [0u8; 100];
+    assert_eq!(file.read_at(2 * BLOCK_SZ, &mut buf), 100);
+    assert!(buf.iter().all(|byte| *byte == 3));
+    Ok(())
+}
+
+/// Offsets of the version and the feature flags in the super block, after
+/// the magic number and the sizes of the five areas
+#[cfg(test)]
+const VERSION_OFFSET: usize = 6 * 4;
+#[cfg(test)]
+const COMPAT_OFFSET: usize = 7 * 4;
+#[cfg(test)]
+const INCOMPAT_OFFSET: usize = 8 * 4;
+
+#[test]
+fn efs_features_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    EasyFileSystem::root_inode(&efs).create_fifo("pipe").unwrap();
+    EasyFileSystem::root_inode(&efs).sync_all();
+    let image = disk.blocks.lock().unwrap().clone();
+    let copy = |patch: &dyn Fn(&mut [u8; BLOCK_SZ])| {
+        let mut blocks = image.clone();
+        patch(&mut blocks[0]);
+        Arc::new(RamDisk {
+            blocks: Mutex::new(blocks),
+            batches: Mutex::new((0, 0)),
+        })
+    };
+    let set = |block: &mut [u8; BLOCK_SZ], offset: usize, value: u32| {
+        block[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    let efs = EasyFileSystem::open(copy(&|_| {}), false).unwrap();
+    assert_eq!(efs.lock().version(), easy_fs::EFS_VERSION);
+    assert_eq!(efs.lock().compat_features(), easy_fs::FEATURE_COMPAT_SUPPORTED);
+    // quotas only come with the first one
+    assert_eq!(efs.lock().incompat_features(), FEATURE_INCOMPAT_FIFO);
+
+    // an unknown compatible feature is ignored, but kept
+    let disk = copy(&|block| set(block, COMPAT_OFFSET, 1 << 31 | FEATURE_COMPAT_MODES));
+    let efs = EasyFileSystem::open(disk, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(efs.lock().compat_features(), 1 << 31 | FEATURE_COMPAT_MODES);
+    // no timestamps on this image
+    let file = root_inode.create("file").unwrap();
+    file.write_at(0, b"stale");
+    assert_eq!((file.stat().mtime, file.stat().ctime), (0, 0));
+    file.set_mode(0o600);
+    assert_eq!(file.mode(), 0o600);
+
+    // without modes, every inode has the default ones
+    let disk = copy(&|block| set(block, COMPAT_OFFSET, FEATURE_COMPAT_TIMESTAMPS));
+    let efs = EasyFileSystem::open(disk, false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    file.set_mode(0o600);
+    assert_eq!(file.mode(), 0o644);
+    assert_eq!(root_inode.mode(), 0o755);
+    // without named pipes, none are made
+    let efs = EasyFileSystem::open(copy(&|block| set(block, INCOMPAT_OFFSET, 0)), false).unwrap();
+    assert_eq!(
+        EasyFileSystem::root_inode(&efs).create_fifo("other").err(),
+        Some(FsError::NoFifos)
+    );
+
+    // an image from before the flags has all the features of version 1
+    let efs = EasyFileSystem::open(copy(&|block| {
+        set(block, VERSION_OFFSET, 0);
+        set(block, COMPAT_OFFSET, 0);
+        set(block, INCOMPAT_OFFSET, 0);
+    }), false)
+    .unwrap();
+    assert_eq!(efs.lock().version(), 0);
+    assert_eq!(efs.lock().compat_features(), FEATURE_COMPAT_TIMESTAMPS | FEATURE_COMPAT_MODES);
+    assert_eq!(efs.lock().incompat_features(), FEATURE_INCOMPAT_FIFO);
+
+    // an unknown incompatible feature is refused, by the crate and the tool
+    let fake = 1 << 30 | FEATURE_INCOMPAT_FIFO;
+    let disk = copy(&|block| set(block, INCOMPAT_OFFSET, fake));
+    assert!(
+        EasyFileSystem::open(disk.clone(), false).err()
+            == Some(FsError::Unsupported {
+                version: easy_fs::EFS_VERSION,
+                incompat: 1 << 30,
+            })
+    );
+    let write_image = |disk: &RamDisk| -> std::io::Result<()> {
+        let mut file = File::create("target/fs_features.img")?;
+        for block in disk.blocks.lock().unwrap().iter() {
+            file.write_all(block)?;
+        }
+        Ok(())
+    };
+    write_image(&disk)?;
+    let err = image_info("target/fs_features.img").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("0x40000000"), "{}", err);
+    write_image(&copy(&|block| set(block, COMPAT_OFFSET, 1 << 31 | FEATURE_COMPAT_MODES)))?;
+    let info = image_info("target/fs_features.img")?;
+    assert!(info.contains("compat features: modes 0x80000000\n"), "{}", info);
+    assert!(info.contains("incompat features: fifo\n"), "{}", info);
+    let disk = copy(&|block| block[0] = 0);
+    assert!(EasyFileSystem::open(disk, false).err() == Some(FsError::BadMagic));
+    Ok(())
+}
+
+#[test]
+fn efs_quota_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let home = root_inode.create_dir("home").unwrap();
+    let old = home.create("old").unwrap();
+    old.write_at(0, &[1u8; 10 * BLOCK_SZ]);
+    assert_eq!(old.set_quota(100, 0), Err(FsError::NotDir));
+    assert!(home.get_quota().is_none());
+    home.set_quota(100, 0).unwrap();
+    assert_ne!(efs.lock().incompat_features() & FEATURE_INCOMPAT_QUOTA, 0);
+    // counted from the tree: the entries of home and the blocks of old
+    let quota = home.get_quota().unwrap();
+    assert_eq!((quota.used_blocks, quota.used_inodes), (11, 1));
+
+    // write until refused, the last write stops at the limit
+    let big = home.create("big").unwrap();
+    let chunk = [2u8; 7 * BLOCK_SZ];
+    let mut written = 0;
+    loop {
+        match big.try_write_at(written, &chunk) {
+            Ok(len) => written += len,
+            Err(err) => {
+                assert_eq!(err, FsError::QuotaExceeded);
+                break;
+            }
+        }
+    }
+    // 88 data blocks and an index block
+    assert_eq!(written, 88 * BLOCK_SZ);
+    assert_eq!(big.size(), written);
+    assert_eq!(home.get_quota().unwrap().used_blocks, 100);
+    assert_eq!(big.append(b"more"), written);
+    assert_eq!(big.try_append(b"more"), Err(FsError::QuotaExceeded));
+    // rewriting takes no more blocks
+    assert_eq!(big.write_at(0, b"over"), 4);
+    // inodes are allowed, but not their blocks
+    let small = home.create("small").unwrap();
+    assert_eq!(small.try_write_at(0, b"x"), Err(FsError::QuotaExceeded));
+    // outside home there is no limit
+    let free = root_inode.create("free").unwrap();
+    assert_eq!(free.write_at(0, &chunk), chunk.len());
+    // a file is only linked under the quotas that count it
+    assert_eq!(root_inode.add_link("big", &big), Err(FsError::CrossDevice));
+    assert!(home.add_link("big2", &big).is_ok());
+
+    // delete a file, and write again
+    old.clear();
+    assert_eq!(home.unlink("old"), 0);
+    assert_eq!(home.get_quota().unwrap().used_blocks, 90);
+    assert_eq!(big.try_write_at(written, &[3u8; 5 * BLOCK_SZ]), Ok(5 * BLOCK_SZ));
+    assert_eq!(home.get_quota().unwrap().used_blocks, 95);
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems(), 0, "{}", report);
+
+    // the inode limit, big counted once for its two entries
+    home.set_quota(100, 3).unwrap();
+    assert_eq!(home.get_quota().unwrap().used_inodes, 2);
+    assert!(home.create("third").is_ok());
+    assert_eq!(home.create("fourth").err(), Some(FsError::QuotaExceeded));
+    assert_eq!(home.unlink("small"), 0);
+    assert!(home.create("fourth").is_ok());
+
+    // a file written after its last unlink is counted, fsck recounts
+    let third = home.find("third").unwrap();
+    assert_eq!(home.unlink("third"), 0);
+    assert_eq!(third.write_at(0, &[4u8; BLOCK_SZ]), BLOCK_SZ);
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.quota_mismatches, 1);
+    let report = efs.lock().fsck(true);
+    assert!(report.repaired);
+    let quota = home.get_quota().unwrap();
+    assert_eq!((quota.used_blocks, quota.used_inodes), (95, 2));
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems(), 0, "{}", report);
+
+    // kept on the image
+    root_inode.sync_all();
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    let home = EasyFileSystem::root_inode(&efs).find("home").unwrap();
+    assert_eq!(home.get_quota(), Some(quota));
+    let mut file = File::create("target/fs_quota.img")?;
+    for block in disk.blocks.lock().unwrap().iter() {
+        file.write_all(block)?;
+    }
+    drop(file);
+    let info = image_info("target/fs_quota.img")?;
+    assert!(info.contains("incompat features: fifo quota\n"), "{}", info);
+    assert!(
+        info.contains(&format!("quota of directory {}: blocks 95 of 100, inodes 2 of 3\n", home.inode_id())),
+        "{}",
+        info
+    );
+    home.remove_quota().unwrap();
+    assert!(home.get_quota().is_none());
+    // removing a directory drops its quota
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.create_dir("tmp").unwrap().set_quota(10, 10).unwrap();
+    assert_eq!(root_inode.remove_dir("tmp"), 0);
+    assert!(efs.lock().quotas().is_empty());
+    Ok(())
+}
+
+#[test]
+fn efs_manifest_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk, 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.create("abc").unwrap().write_at(0, b"abc");
+    root_inode.create("empty").unwrap();
+    let long = root_inode.create("long").unwrap();
+    long.write_at(0, &[b'a'; 3 * BLOCK_SZ]);
+    let names: Vec<String> = ["abc", "empty", "long"].iter().map(|name| name.to_string()).collect();
+    write_manifest(&root_inode, &names)?;
+    let manifest = String::from_utf8(read_file(&root_inode, "manifest")?).unwrap();
+    let lines: Vec<&str> = manifest.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "abc 3 ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        lines[1],
+        "empty 0 e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert!(lines[2].starts_with(&format!("long {} ", 3 * BLOCK_SZ)));
+    // hashed across blocks like at once
+    let mut hasher = sha256::Sha256::new();
+    for chunk in [b'a'; 3 * BLOCK_SZ].chunks(100) {
+        hasher.update(chunk);
+    }
+    let digest: String = hasher.finish().iter().map(|byte| format!("{:02x}", byte)).collect();
+    assert!(lines[2].ends_with(&digest));
+    // after every packed file, read-only
+    let inode = root_inode.find("manifest").unwrap();
+    assert!(inode.inode_id() > long.inode_id());
+    assert_eq!(inode.mode(), 0o444);
+    // a name that was not packed
+    let names = vec!["missing".to_string()];
+    assert_eq!(
+        write_manifest(&root_inode, &names).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+    Ok(())
+}
+
+#[test]
+fn efs_writeback_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static TICKS: AtomicU64 = AtomicU64::new(0);
+    easy_fs::set_ticks(|| TICKS.load(Ordering::Relaxed));
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        batches: Mutex::new((0, 0)),
+    });
+    EasyFileSystem::create(disk.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    let data: Vec<u8> = (0..100 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    file.write_at(0, &data);
+    // nothing is old enough yet
+    TICKS.store(5, Ordering::Relaxed);
+    assert_eq!(easy_fs::block_cache_sync_older_than(10, 4), 0);
+    // past the interval, without a sync, a pass at a time
+    TICKS.store(20, Ordering::Relaxed);
+    let aged = easy_fs::block_cache_stats().aged_writebacks;
+    let mut total = 0;
+    loop {
+        let written = easy_fs::block_cache_sync_older_than(10, 4);
+        assert!(written <= 4);
+        if written == 0 {
+            break;
+        }
+        total += written;
+    }
+    assert!(easy_fs::block_cache_stats().aged_writebacks - aged >= total);
+    // a copy of the disk now is what survives a crash
+    let copy = Arc::new(RamDisk {
+        blocks: Mutex::new(disk.blocks.lock().unwrap().clone()),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::open(copy, false).unwrap();
+    let crashed = EasyFileSystem::root_inode(&efs);
+    assert_eq!(read_file(&crashed, "file")?, data);
+    Ok(())
+}
+
+#[test]
+fn efs_snapshot_test() -> std::io::Result<()> {
+    let disk = Arc::new(RamDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096 + 2048]),
+        batches: Mutex::new((0, 0)),
+    });
+    let efs = EasyFileSystem::create(disk.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let kept: Vec<u8> = (0..40 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    let changed: Vec<u8> = (0..3 * BLOCK_SZ).map(|i| (i % 13) as u8).collect();
+    root_inode.create("kept").unwrap().write_at(0, &kept);
+    root_inode.create("changed").unwrap().write_at(0, &changed);
+    root_inode.create("gone").unwrap().write_at(0, b"gone");
+    // nothing reserved, nothing taken yet
+    assert_eq!(efs.lock().snapshot(), Err(FsError::SnapshotTooLarge));
+    assert_eq!(efs.lock().restore(), Err(FsError::NoSnapshot));
+    efs.lock().reserve_snapshot(2048).unwrap();
+    assert_eq!(efs.lock().snapshot_blocks(), 2048);
+    assert_eq!(efs.lock().restore(), Err(FsError::NoSnapshot));
+    let copied = efs.lock().snapshot().unwrap();
+    assert!(copied > 44 && copied < 2048);
+
+    // change everything, then go back
+    let kept_inode = root_inode.find("kept").unwrap();
+    let changed_inode = root_inode.find("changed").unwrap();
+    changed_inode.write_at(BLOCK_SZ, &[0xff; 5 * BLOCK_SZ]);
+    assert_eq!(root_inode.unlink("gone"), 0);
+    let new = root_inode.create("new").unwrap();
+    new.write_at(0, &[1; 8 * BLOCK_SZ]);
+    let free_before = efs.lock().stat_fs().free_data_blocks;
+    assert_eq!(efs.lock().restore(), Ok(copied));
+    assert_eq!(read_file(&root_inode, "kept")?, kept);
+    assert_eq!(read_file(&root_inode, "changed")?, changed);
+    assert_eq!(read_file(&root_inode, "gone")?, b"gone");
+    assert!(root_inode.find("new").is_none());
+    assert!(efs.lock().stat_fs().free_data_blocks > free_before);
+    // inodes found before stay usable, unless the restore freed them
+    assert!(!root_inode.is_stale() && !kept_inode.is_stale() && !changed_inode.is_stale());
+    assert!(new.is_stale());
+    assert_eq!(changed_inode.size(), changed.len());
+    // the freed inode is taken again under a new generation
+    let again = root_inode.create("again").unwrap();
+    assert!(new.is_stale() && !again.is_stale());
+
+    // the snapshot outlives a reopen, and does not fit a smaller region
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    assert_eq!(efs.lock().restore(), Ok(copied));
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(root_inode.find("again").is_none());
+    efs.lock().reserve_snapshot(16).unwrap();
+    assert_eq!(efs.lock().snapshot(), Err(FsError::SnapshotTooLarge));
+    assert_eq!(efs.lock().restore(), Err(FsError::NoSnapshot));
+    Ok(())
+}
+
+#[cfg(test)]
+use easy_fs::BlockError;
+
+/// A disk in memory failing every write to the blocks in `bad`
+#[cfg(test)]
+struct FailingDisk {
+    blocks: Mutex<Vec<[u8; BLOCK_SZ]>>,
+    bad: Mutex<Vec<usize>>,
+}
+
+#[cfg(test)]
+impl BlockDevice for FailingDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.blocks.lock().unwrap()[block_id]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.try_write_block(block_id, buf).expect("write to a bad block");
+    }
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        if self.bad.lock().unwrap().contains(&block_id) {
+            return Err(BlockError(block_id));
+        }
+        self.blocks.lock().unwrap()[block_id].copy_from_slice(buf);
+        Ok(())
+    }
+    fn try_write_blocks(&self, start: usize, bufs: &[&[u8]]) -> Result<(), BlockError> {
+        for (block_id, buf) in (start..).zip(bufs.iter()) {
+            self.try_write_block(block_id, buf)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn efs_remap_test() -> std::io::Result<()> {
+    let disk = Arc::new(FailingDisk {
+        blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; 4096]),
+        bad: Mutex::new(Vec::new()),
+    });
+    let efs = EasyFileSystem::create_with_spares(disk.clone(), 4096, 1, 4);
+    assert!(efs.lock().has_spares());
+    let stat = efs.lock().stat_fs();
+    assert_eq!((stat.spare_blocks, stat.remapped_blocks), (4, 0));
+    // the remap table and the spares are never free
+    assert_eq!(stat.free_data_blocks, stat.data_blocks - 5);
+    // the block of the root inode, and two blocks of the data of "data"
+    // past the table, the spares and the first block of the root entries
+    let data_start = efs.lock().metadata_blocks() as usize;
+    let (root_block, _) = efs.lock().get_disk_inode_pos(0);
+    let bad = vec![root_block as usize, data_start + 10, data_start + 30];
+    *disk.bad.lock().unwrap() = bad.clone();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let data: Vec<u8> = (0..40 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    root_inode.create("data").unwrap().write_at(0, &data);
+    root_inode.create_dir("dir").unwrap().create("inner").unwrap().write_at(0, b"inner");
+    root_inode.sync_all();
+    assert_eq!(efs.lock().remapped_blocks(), 3);
+    assert!(!efs.lock().has_failed());
+    // the bad blocks were never written
+    for &block_id in bad.iter() {
+        assert!(block_id == root_block as usize || disk.blocks.lock().unwrap()[block_id] == [0u8; BLOCK_SZ]);
+    }
+
+    // the table outlives a reopen, the cached blocks aside
+    let efs = EasyFileSystem::open(disk.clone(), false).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(read_file(&root_inode, "data")?, data);
+    let inner = root_inode.find("dir").unwrap().find("inner").unwrap();
+    let mut buf = [0u8; 5];
+    assert_eq!(inner.read_at(0, &mut buf), 5);
+    assert_eq!(&buf, b"inner");
+    let report = efs.lock().fsck(false);
+    assert_eq!(report.problems(), 0);
+    assert_eq!((report.remapped, report.spares), (3, 4));
+    let stat = efs.lock().stat_fs();
+    assert_eq!((stat.spare_blocks, stat.remapped_blocks), (4, 3));
+
+    // one spare left: the first failed write takes it, the next is lost
+    disk.bad.lock().unwrap().extend(data_start + 60..data_start + 200);
+    root_inode.create("more").unwrap().write_at(0, &[7u8; 60 * BLOCK_SZ]);
+    root_inode.sync_all();
+    assert_eq!(efs.lock().remapped_blocks(), 4);
+    assert!(efs.lock().has_failed());
+    assert!(root_inode.has_failed());
+    assert!(root_inode.read_only());
+    assert_eq!(root_inode.create("after").err(), Some(FsError::Io));
+    assert_eq!(root_inode.find("data").unwrap().try_write_at(0, b"x"), Err(FsError::Io));
+    assert_eq!(read_file(&root_inode, "data")?, data);
+    Ok(())
+}
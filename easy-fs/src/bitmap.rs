@@ -1,4 +1,5 @@
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use super::{
     BlockDevice,
     BLOCK_SZ,
@@ -70,8 +71,65 @@ impl Bitmap {
             bitmap_block[bits64_pos] -= 1u64 << inner_pos;
         });
     }
+    /// Whether `bit` is allocated
+    pub fn is_allocated(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().read(0, |bitmap_block: &BitmapBlock| {
+            bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0
+        })
+    }
+    /// Allocate the given `bit`, which a check found in use but free
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().modify(0, |bitmap_block: &mut BitmapBlock| {
+            bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+        });
+    }
+    /// The allocated bits below `bound` in order, one bitmap block read at
+    /// a time
+    pub fn allocated_bits(&self, block_device: &Arc<dyn BlockDevice>, bound: usize) -> Vec<usize> {
+        let mut v = Vec::new();
+        for block_id in 0..self.blocks {
+            get_block_cache(
+                block_id + self.start_block_id,
+                Arc::clone(block_device),
+            ).lock().read(0, |bitmap_block: &BitmapBlock| {
+                for (bits64_pos, bits64) in bitmap_block.iter().enumerate() {
+                    let mut bits64 = *bits64;
+                    while bits64 != 0 {
+                        let bit = block_id * BLOCK_BITS + bits64_pos * 64
+                            + bits64.trailing_zeros() as usize;
+                        if bit < bound {
+                            v.push(bit);
+                        }
+                        bits64 &= bits64 - 1;
+                    }
+                }
+            });
+        }
+        v
+    }
     /// Get the max number of allocatable blocks
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
     }
+    /// Count the allocated bits
+    pub fn allocated(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        (0..self.blocks)
+            .map(|block_id| {
+                get_block_cache(
+                    block_id + self.start_block_id,
+                    Arc::clone(block_device),
+                ).lock().read(0, |bitmap_block: &BitmapBlock| {
+                    bitmap_block.iter().map(|bits64| bits64.count_ones() as usize).sum::<usize>()
+                })
+            })
+            .sum()
+    }
 }
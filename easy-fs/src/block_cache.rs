@@ -4,8 +4,11 @@ use super::{
 };
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
-use spin::Mutex;
+use crate::clock::ticks;
+use crate::lock::{Mutex, MutexGuard};
 
 /// Cached block inside memory
 pub struct BlockCache {
@@ -17,21 +20,38 @@ pub struct BlockCache {
     block_device: Arc<dyn BlockDevice>,
     /// whether the block is dirty
     modified: bool,
+    /// tick at which the block became dirty, see [`crate::set_ticks`]
+    dirtied: u64,
+    /// whether its device is mounted read-only, the block is then never
+    /// written back
+    read_only: bool,
 }
 
 impl BlockCache {
     /// Load a new BlockCache from disk.
     pub fn new(
         block_id: usize,
-        block_device: Arc<dyn BlockDevice>
+        block_device: Arc<dyn BlockDevice>,
+        read_only: bool,
     ) -> Self {
         let mut cache = [0u8; BLOCK_SZ];
         block_device.read_block(block_id, &mut cache);
+        Self::loaded(block_id, block_device, cache, read_only)
+    }
+    /// A BlockCache of data already read from disk
+    fn loaded(
+        block_id: usize,
+        block_device: Arc<dyn BlockDevice>,
+        cache: [u8; BLOCK_SZ],
+        read_only: bool,
+    ) -> Self {
         Self {
             cache,
             block_id,
             block_device,
             modified: false,
+            dirtied: 0,
+            read_only,
         }
     }
     /// Get the address of an offset inside the cached block data
@@ -49,6 +69,9 @@ impl BlockCache {
     pub fn get_mut<T>(&mut self, offset: usize) -> &mut T where T: Sized {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SZ);
+        if !self.modified {
+            self.dirtied = ticks();
+        }
         self.modified = true;
         let addr = self.addr_of_offset(offset);
         unsafe { &mut *(addr as *mut T) }
@@ -63,8 +86,9 @@ impl BlockCache {
     }
 
     pub fn sync(&mut self) {
-        if self.modified {
+        if self.modified && !self.read_only {
             self.modified = false;
+            WRITEBACKS.fetch_add(1, Ordering::Relaxed);
             self.block_device.write_block(self.block_id, &self.cache);
         }
     }
@@ -78,14 +102,103 @@ impl Drop for BlockCache {
 
 /// Use a block cache of 16 blocks
 const BLOCK_CACHE_SIZE: usize = 16;
+/// Blocks a read fetches ahead at most, leaving half of the cache alone
+pub const READ_AHEAD_BLOCKS: usize = BLOCK_CACHE_SIZE / 2;
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+static WRITEBACKS: AtomicUsize = AtomicUsize::new(0);
+static AGED_WRITEBACKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters of the global block cache since boot, see [`block_cache_stats`]
+#[derive(Debug, Clone, Copy)]
+pub struct BlockCacheStats {
+    /// Lookups served from the cache
+    pub hits: usize,
+    /// Lookups that read the block from its device
+    pub misses: usize,
+    /// Dirty blocks written back to their device
+    pub writebacks: usize,
+    /// Of the writebacks, those of [`block_cache_sync_older_than`]
+    pub aged_writebacks: usize,
+    /// Blocks cached now, of `capacity`
+    pub cached: usize,
+    pub capacity: usize,
+}
 
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    /// Cached blocks with their block id and the address of their device,
+    /// so that blocks of different devices are never mixed up
+    queue: VecDeque<(usize, usize, Arc<Mutex<BlockCache>>)>,
+    /// Addresses of the devices mounted read-only
+    read_only: Vec<usize>,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
-        Self { queue: VecDeque::new() }
+        Self { queue: VecDeque::new(), read_only: Vec::new() }
+    }
+
+    /// Never write the blocks of `block_device` back, or do again
+    pub fn set_read_only(&mut self, block_device: &Arc<dyn BlockDevice>, read_only: bool) {
+        let device = Arc::as_ptr(block_device) as *const u8 as usize;
+        self.read_only.retain(|&other| other != device);
+        if read_only {
+            self.read_only.push(device);
+        }
+        for pair in self.queue.iter().filter(|pair| pair.1 == device) {
+            pair.2.lock().read_only = read_only;
+        }
+    }
+
+    /// Make room for one more block if the cache is full
+    fn evict(&mut self) {
+        if self.queue.len() == BLOCK_CACHE_SIZE {
+            // from front to tail
+            if let Some((idx, _)) = self.queue
+                .iter()
+                .enumerate()
+                .find(|(_, pair)| Arc::strong_count(&pair.2) == 1) {
+                self.queue.drain(idx..=idx);
+            } else {
+                panic!("Run out of BlockCache!");
+            }
+        }
+    }
+
+    /// Load the blocks of `block_ids` not cached yet, reading each run of
+    /// consecutive ones with a single device call. Loads no more blocks than
+    /// there is room for without evicting the ones just loaded.
+    pub fn read_ahead(&mut self, block_ids: &[usize], block_device: &Arc<dyn BlockDevice>) {
+        let device = Arc::as_ptr(block_device) as *const u8 as usize;
+        let read_only = self.read_only.contains(&device);
+        let mut missing: Vec<usize> = Vec::new();
+        for &block_id in block_ids {
+            if !missing.contains(&block_id)
+                && !self.queue.iter().any(|pair| pair.0 == block_id && pair.1 == device)
+            {
+                missing.push(block_id);
+            }
+        }
+        let evictable = self.queue.iter().filter(|pair| Arc::strong_count(&pair.2) == 1).count();
+        missing.truncate(BLOCK_CACHE_SIZE - self.queue.len() + evictable);
+        let mut start = 0;
+        while start < missing.len() {
+            let mut end = start + 1;
+            while end < missing.len() && missing[end] == missing[end - 1] + 1 {
+                end += 1;
+            }
+            let mut blocks = alloc::vec![[0u8; BLOCK_SZ]; end - start];
+            let mut bufs: Vec<&mut [u8]> = blocks.iter_mut().map(|block| &mut block[..]).collect();
+            block_device.read_blocks(missing[start], &mut bufs);
+            for (block_id, cache) in (missing[start]..).zip(blocks) {
+                self.evict();
+                MISSES.fetch_add(1, Ordering::Relaxed);
+                let block_cache = BlockCache::loaded(block_id, Arc::clone(block_device), cache, read_only);
+                self.queue.push_back((block_id, device, Arc::new(Mutex::new(block_cache))));
+            }
+            start = end;
+        }
     }
 
     pub fn get_block_cache(
@@ -93,31 +206,59 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
+        let device = Arc::as_ptr(&block_device) as *const u8 as usize;
         if let Some(pair) = self.queue
             .iter()
-            .find(|pair| pair.0 == block_id) {
-                Arc::clone(&pair.1)
+            .find(|pair| pair.0 == block_id && pair.1 == device) {
+                HITS.fetch_add(1, Ordering::Relaxed);
+                Arc::clone(&pair.2)
         } else {
+            MISSES.fetch_add(1, Ordering::Relaxed);
             // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self.queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1) {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
-            }
+            self.evict();
             // load block into mem and push back
+            let read_only = self.read_only.contains(&device);
             let block_cache = Arc::new(Mutex::new(
-                BlockCache::new(block_id, Arc::clone(&block_device))
+                BlockCache::new(block_id, Arc::clone(&block_device), read_only)
             ));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
+            self.queue.push_back((block_id, device, Arc::clone(&block_cache)));
             block_cache
         }
     }
+
+    /// Write back at most `batch` of the blocks dirtied at least `age`
+    /// ticks ago, the oldest first. A block locked by an operation is left
+    /// for a later call rather than waited for, so no block is written in
+    /// the middle of an update. Returns the blocks written back.
+    pub fn sync_older_than(&self, age: u64, batch: usize) -> usize {
+        let now = ticks();
+        let mut old: Vec<MutexGuard<BlockCache>> = self.queue
+            .iter()
+            .filter_map(|pair| pair.2.try_lock())
+            .filter(|cache| {
+                cache.modified && !cache.read_only && now.saturating_sub(cache.dirtied) >= age
+            })
+            .collect();
+        old.sort_by_key(|cache| cache.dirtied);
+        old.truncate(batch);
+        let written = old.len();
+        write_batched(old);
+        AGED_WRITEBACKS.fetch_add(written, Ordering::Relaxed);
+        written
+    }
+
+    /// Drop every cached block of `block_device`, dirty ones unwritten, so
+    /// the next lookups read what is on it now. A block still in use
+    /// elsewhere is only forgotten by the cache.
+    pub fn invalidate_all(&mut self, block_device: &Arc<dyn BlockDevice>) {
+        let device = Arc::as_ptr(block_device) as *const u8 as usize;
+        self.queue.retain(|(_, cache_device, cache)| {
+            if *cache_device == device {
+                cache.lock().modified = false;
+            }
+            *cache_device != device
+        });
+    }
 }
 
 lazy_static! {
@@ -135,10 +276,93 @@ pub fn get_block_cache(
     BLOCK_CACHE_MANAGER.lock().get_block_cache(block_id, block_device)
 }
 
+/// Stop or resume writing back the cached blocks of `block_device`
+pub fn block_cache_set_read_only(block_device: &Arc<dyn BlockDevice>, read_only: bool) {
+    BLOCK_CACHE_MANAGER.lock().set_read_only(block_device, read_only);
+}
+
+/// Snapshot the counters of the global block cache
+pub fn block_cache_stats() -> BlockCacheStats {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    BlockCacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        writebacks: WRITEBACKS.load(Ordering::Relaxed),
+        aged_writebacks: AGED_WRITEBACKS.load(Ordering::Relaxed),
+        cached: manager.queue.len(),
+        capacity: BLOCK_CACHE_SIZE,
+    }
+}
+
+/// Load the blocks of `block_ids` into the cache with batched reads, see
+/// [`BlockCacheManager::read_ahead`]
+pub fn block_cache_read_ahead(block_ids: &[usize], block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().read_ahead(block_ids, block_device);
+}
+
+/// Write the dirty blocks among `caches` back, see [`write_batched`]
+fn sync_batched<'a>(caches: impl Iterator<Item = &'a Arc<Mutex<BlockCache>>>) {
+    write_batched(
+        caches
+            .map(|cache| cache.lock())
+            .filter(|cache| cache.modified && !cache.read_only)
+            .collect(),
+    );
+}
+
+/// Write the locked dirty blocks `dirty` back, each run of consecutive
+/// blocks of one device with a single device call
+fn write_batched(mut dirty: Vec<MutexGuard<BlockCache>>) {
+    let device = |cache: &BlockCache| Arc::as_ptr(&cache.block_device) as *const u8 as usize;
+    dirty.sort_by_key(|cache| (device(cache), cache.block_id));
+    let mut start = 0;
+    while start < dirty.len() {
+        let mut end = start + 1;
+        while end < dirty.len()
+            && device(&dirty[end]) == device(&dirty[start])
+            && dirty[end].block_id == dirty[end - 1].block_id + 1
+        {
+            end += 1;
+        }
+        let bufs: Vec<&[u8]> = dirty[start..end].iter().map(|cache| &cache.cache[..]).collect();
+        dirty[start].block_device.write_blocks(dirty[start].block_id, &bufs);
+        for cache in dirty[start..end].iter_mut() {
+            cache.modified = false;
+            WRITEBACKS.fetch_add(1, Ordering::Relaxed);
+        }
+        start = end;
+    }
+}
+
 /// Sync all block cache to block device
 pub fn block_cache_sync_all() {
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
-    }
+    sync_batched(manager.queue.iter().map(|pair| &pair.2));
+}
+
+/// Sync the cached blocks of `block_device` whose id satisfies `filter`
+pub fn block_cache_sync_where(
+    block_device: &Arc<dyn BlockDevice>,
+    filter: impl Fn(usize) -> bool,
+) {
+    let device = Arc::as_ptr(block_device) as *const u8 as usize;
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    sync_batched(
+        manager.queue
+            .iter()
+            .filter(|pair| pair.1 == device && filter(pair.0))
+            .map(|pair| &pair.2),
+    );
+}
+
+/// Forget every cached block of `block_device`, see
+/// [`BlockCacheManager::invalidate_all`]
+pub fn block_cache_invalidate_all(block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().invalidate_all(block_device);
+}
+
+/// Write back the blocks dirtied at least `age` ticks ago, at most `batch`
+/// of them, see [`BlockCacheManager::sync_older_than`]
+pub fn block_cache_sync_older_than(age: u64, batch: usize) -> usize {
+    BLOCK_CACHE_MANAGER.lock().sync_older_than(age, batch)
 }
@@ -1,8 +1,43 @@
 use core::any::Any;
 
+/// A block the device failed to write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockError(pub usize);
+
 /// Trait for block devices
 /// which reads and writes data in the unit of blocks
 pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Read the blocks from `start` on into `bufs`, one block each.
+    /// Devices able to work on several requests at once override it.
+    fn read_blocks(&self, start: usize, bufs: &mut [&mut [u8]]) {
+        for (block_id, buf) in (start..).zip(bufs.iter_mut()) {
+            self.read_block(block_id, buf);
+        }
+    }
+    /// Write `bufs` to the blocks from `start` on, like [`Self::read_blocks`]
+    fn write_blocks(&self, start: usize, bufs: &[&[u8]]) {
+        for (block_id, buf) in (start..).zip(bufs.iter()) {
+            self.write_block(block_id, buf);
+        }
+    }
+    /// [`Self::write_block`], but telling a block the device failed to
+    /// write. Devices that cannot tell never fail.
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_block(block_id, buf);
+        Ok(())
+    }
+    /// [`Self::write_blocks`], but telling the first block the device
+    /// failed to write, the others may or may not be written
+    fn try_write_blocks(&self, start: usize, bufs: &[&[u8]]) -> Result<(), BlockError> {
+        self.write_blocks(start, bufs);
+        Ok(())
+    }
+    /// Finish the requests the device is done with, on its interrupt.
+    /// Devices completing each request before returning have none.
+    fn handle_irq(&self) {}
+    /// Wait until no request is in flight, before the machine powers off.
+    /// Devices completing each request before returning have none.
+    fn quiesce(&self) {}
 }
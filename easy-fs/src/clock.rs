@@ -0,0 +1,31 @@
+//! Time source for inode timestamps
+
+use spin::Mutex;
+
+static CLOCK: Mutex<Option<fn() -> u32>> = Mutex::new(None);
+static TICKS: Mutex<Option<fn() -> u64>> = Mutex::new(None);
+
+/// Stamp inodes with `now`, the seconds since the epoch. Without a clock
+/// all timestamps are 0.
+pub fn set_clock(now: fn() -> u32) {
+    *CLOCK.lock() = Some(now);
+}
+
+/// The current time by the clock set with [`set_clock`]
+pub(crate) fn now() -> u32 {
+    let clock = *CLOCK.lock();
+    clock.map_or(0, |now| now())
+}
+
+/// Age dirty blocks by `ticks`, a count that never goes back, see
+/// [`crate::block_cache_sync_older_than`]. Without it every block is dirtied
+/// at tick 0.
+pub fn set_ticks(ticks: fn() -> u64) {
+    *TICKS.lock() = Some(ticks);
+}
+
+/// The current tick by the count set with [`set_ticks`]
+pub(crate) fn ticks() -> u64 {
+    let ticks = *TICKS.lock();
+    ticks.map_or(0, |ticks| ticks())
+}
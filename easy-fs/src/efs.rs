@@ -1,5 +1,6 @@
 use alloc::sync::Arc;
-use spin::Mutex;
+use alloc::vec::Vec;
+use crate::lock::Mutex;
 use super::{
     BlockDevice,
     Bitmap,
@@ -7,10 +8,29 @@ use super::{
     DiskInode,
     DiskInodeType,
     Inode,
+    InodeGenerations,
+    GENERATIONS_OFFSET,
+    INODES_PER_BLOCK,
     get_block_cache,
+    RemapDevice,
+    REMAP_SLOTS,
+    block_cache_invalidate_all,
+    block_cache_set_read_only,
     block_cache_sync_all,
 };
-use crate::BLOCK_SZ;
+use crate::{
+    BLOCK_SZ,
+    EFS_VERSION,
+    FEATURE_COMPAT_MODES,
+    FEATURE_COMPAT_SUPPORTED,
+    FEATURE_COMPAT_TIMESTAMPS,
+    FEATURE_INCOMPAT_FIFO,
+    FEATURE_INCOMPAT_QUOTA,
+    FEATURE_INCOMPAT_REMAP,
+    FEATURE_INCOMPAT_SUPPORTED,
+};
+use crate::clock::now;
+use core::fmt::{self, Display, Formatter};
 
 /// An easy fs over a block device
 pub struct EasyFileSystem {
@@ -19,6 +39,115 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    /// Set when a check left problems behind, see [`EasyFileSystem::fsck`]:
+    /// nothing is written and every change through an [`Inode`] fails
+    read_only: bool,
+    /// Opened read-only, the same but for good and with the block cache
+    /// never writing the device back either
+    mounted_read_only: bool,
+    pub(crate) version: u32,
+    pub(crate) compat_features: u32,
+    pub(crate) incompat_features: u32,
+    /// Block of the quota records, 0 until the first quota
+    pub(crate) quota_block: u32,
+    /// What `block_device` is, on an image with spare blocks
+    pub(crate) remap: Option<Arc<RemapDevice>>,
+}
+
+/// Why [`EasyFileSystem::open`] refused a block device, or an [`Inode`]
+/// a change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsError {
+    /// There is no easy-fs super block, the magic number is wrong
+    BadMagic,
+    /// The image uses the incompatible features `incompat` that this crate
+    /// does not know
+    Unsupported { version: u32, incompat: u32 },
+    /// The filesystem refuses changes, see [`EasyFileSystem::is_read_only`]
+    ReadOnly,
+    /// The block is not an allocated block of the data area
+    BadBlock(u32),
+    /// The name is longer than [`crate::NAME_LENGTH_LIMIT`] bytes, an entry
+    /// cannot hold it
+    NameTooLong,
+    /// The name is empty
+    EmptyName,
+    /// The name is taken in the directory
+    Exists,
+    /// There is no entry of the name in the directory
+    NotFound,
+    /// A directory is needed but the inode is not one
+    NotDir,
+    /// Directories cannot be linked
+    IsDir,
+    /// A link to an inode of another filesystem, or to one counted by
+    /// other quotas
+    CrossDevice,
+    /// The image has no named pipes, see [`EasyFileSystem::has_fifos`]
+    NoFifos,
+    /// A quota over the inode does not allow the blocks or the inode, see
+    /// [`Inode::set_quota`]
+    QuotaExceeded,
+    /// The quota block holds no more quotas
+    TooManyQuotas,
+    /// The blocks in use do not fit in the snapshot region, or there is
+    /// none, see [`EasyFileSystem::snapshot`]
+    SnapshotTooLarge,
+    /// The snapshot region holds no whole snapshot of this filesystem
+    NoSnapshot,
+    /// The write would end past the largest size an inode holds, `u32::MAX`
+    FileTooLarge,
+    /// A block failed to write and no spare block was left to take its
+    /// place, or it cannot have one, see [`crate::RemapDevice`]. The write
+    /// was lost.
+    Io,
+}
+
+impl Display for FsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::BadMagic => write!(f, "not an easy-fs image, bad magic number"),
+            FsError::Unsupported { version, incompat } => write!(
+                f,
+                "easy-fs version {} image with unsupported incompatible features {:#x}",
+                version, incompat,
+            ),
+            FsError::ReadOnly => write!(f, "read-only filesystem"),
+            FsError::BadBlock(block_id) => write!(f, "block {} is not an allocated data block", block_id),
+            FsError::NameTooLong => write!(f, "name longer than {} bytes", crate::NAME_LENGTH_LIMIT),
+            FsError::EmptyName => write!(f, "empty name"),
+            FsError::Exists => write!(f, "name exists"),
+            FsError::NotFound => write!(f, "no such entry"),
+            FsError::NotDir => write!(f, "not a directory"),
+            FsError::IsDir => write!(f, "is a directory"),
+            FsError::CrossDevice => write!(f, "link across filesystems or quotas"),
+            FsError::NoFifos => write!(f, "image without named pipes"),
+            FsError::QuotaExceeded => write!(f, "quota exceeded"),
+            FsError::TooManyQuotas => write!(f, "no room for another quota"),
+            FsError::SnapshotTooLarge => write!(f, "no room for a snapshot"),
+            FsError::NoSnapshot => write!(f, "no snapshot to restore"),
+            FsError::FileTooLarge => write!(f, "file too large"),
+            FsError::Io => write!(f, "block device write failed"),
+        }
+    }
+}
+
+/// Usage of a filesystem, see [`EasyFileSystem::stat_fs`]
+#[derive(Debug, Clone, Copy)]
+pub struct FsStat {
+    /// Blocks of the whole device
+    pub total_blocks: u32,
+    /// Blocks of the data area
+    pub data_blocks: u32,
+    /// Data blocks not allocated
+    pub free_data_blocks: u32,
+    /// Data blocks kept to stand in for blocks that fail to write, never
+    /// free, see [`crate::RemapDevice`]
+    pub spare_blocks: u32,
+    /// Spare blocks standing in for one
+    pub remapped_blocks: u32,
+    pub total_inodes: u32,
+    pub free_inodes: u32,
 }
 
 /// A data block of block size
@@ -31,6 +160,20 @@ impl EasyFileSystem {
         total_blocks: u32,
         inode_bitmap_blocks: u32,
     ) -> Arc<Mutex<Self>> {
+        Self::create_with_spares(block_device, total_blocks, inode_bitmap_blocks, 0)
+    }
+    /// Create a filesystem from a block device, keeping `spares` data
+    /// blocks, at most [`crate::REMAP_SLOTS`], to stand in for blocks that
+    /// fail to write, see [`crate::RemapDevice`]. With none it is
+    /// [`EasyFileSystem::create`].
+    pub fn create_with_spares(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        spares: u32,
+    ) -> Arc<Mutex<Self>> {
+        assert!(spares as usize <= REMAP_SLOTS, "more spare blocks than a remap table holds");
+        block_cache_set_read_only(&block_device, false);
         // calculate block size of areas & create bitmaps
         let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
         let inode_num = inode_bitmap.maximum();
@@ -50,6 +193,13 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            read_only: false,
+            mounted_read_only: false,
+            version: EFS_VERSION,
+            compat_features: FEATURE_COMPAT_SUPPORTED,
+            incompat_features: FEATURE_INCOMPAT_FIFO,
+            quota_block: 0,
+            remap: None,
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -84,18 +234,67 @@ impl EasyFileSystem {
         )
         .lock()
         .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-            disk_inode.initialize(DiskInodeType::Directory);
+            disk_inode.initialize(DiskInodeType::Directory, efs.now());
         });
+        if spares > 0 {
+            efs.reserve_spares(spares);
+        }
         block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
-    /// Open a block device as a filesystem
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+    /// Allocate the remap table and `spares` spare blocks of a new image,
+    /// and reach the device through them from now on
+    fn reserve_spares(&mut self, spares: u32) {
+        let table_block = self.alloc_data();
+        let spares: Vec<u32> = (0..spares).map(|_| self.alloc_data()).collect();
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                super_block.incompat_features |= FEATURE_INCOMPAT_REMAP;
+                super_block.remap_block = table_block;
+            });
+        self.incompat_features |= FEATURE_INCOMPAT_REMAP;
+        let remap = RemapDevice::create(Arc::clone(&self.block_device), table_block, &spares)
+            .expect("cannot write the remap table");
+        self.use_remap(Arc::new(remap), false);
+    }
+    /// Reach the device through `remap`, the blocks cached under the bare
+    /// device written back and dropped
+    fn use_remap(&mut self, remap: Arc<RemapDevice>, read_only: bool) {
+        block_cache_sync_all();
+        block_cache_invalidate_all(&self.block_device);
+        let block_device: Arc<dyn BlockDevice> = remap.clone();
+        block_cache_set_read_only(&block_device, read_only);
+        self.block_device = block_device;
+        self.remap = Some(remap);
+    }
+    /// Open a block device as a filesystem. Fails if it holds no easy-fs
+    /// image, or one with incompatible features this crate does not know.
+    /// Unknown compatible features are ignored. An image with spare blocks
+    /// is reached through a [`RemapDevice`] over `block_device`.
+    ///
+    /// With `read_only` every change through an [`Inode`] fails and no
+    /// cached block of the device is written back, until it is opened
+    /// again without.
+    pub fn open(
+        block_device: Arc<dyn BlockDevice>,
+        read_only: bool,
+    ) -> Result<Arc<Mutex<Self>>, FsError> {
+        block_cache_set_read_only(&block_device, read_only);
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let (mut efs, remap_block) = get_block_cache(0, Arc::clone(&block_device))
             .lock()
             .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
+                if !super_block.is_valid() {
+                    return Err(FsError::BadMagic);
+                }
+                let (compat_features, incompat_features) = super_block.features();
+                if incompat_features & !FEATURE_INCOMPAT_SUPPORTED != 0 {
+                    return Err(FsError::Unsupported {
+                        version: super_block.version,
+                        incompat: incompat_features & !FEATURE_INCOMPAT_SUPPORTED,
+                    });
+                }
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
@@ -110,9 +309,30 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    read_only: false,
+                    mounted_read_only: read_only,
+                    version: super_block.version,
+                    compat_features,
+                    incompat_features,
+                    quota_block: if incompat_features & FEATURE_INCOMPAT_QUOTA != 0 {
+                        super_block.quota_block
+                    } else {
+                        0
+                    },
+                    remap: None,
                 };
-                Arc::new(Mutex::new(efs))
-            })
+                let remap_block = if incompat_features & FEATURE_INCOMPAT_REMAP != 0 {
+                    super_block.remap_block
+                } else {
+                    0
+                };
+                Ok((efs, remap_block))
+            })?;
+        if remap_block != 0 {
+            let remap = RemapDevice::open(Arc::clone(&efs.block_device), remap_block);
+            efs.use_remap(Arc::new(remap), read_only);
+        }
+        Ok(Arc::new(Mutex::new(efs)))
     }
     /// Get the root inode of the filesystem
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
@@ -135,13 +355,134 @@ impl EasyFileSystem {
         let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
         (block_id, (inode_id % inodes_per_block) as usize * inode_size)
     }
+    /// Number of blocks before the data area, the super block, the
+    /// bitmaps and the inode area
+    pub fn metadata_blocks(&self) -> u32 {
+        self.data_area_start_block
+    }
+    /// Count the total and free blocks and inodes from the super block and
+    /// the bitmaps
+    pub fn stat_fs(&self) -> FsStat {
+        let (total_blocks, data_blocks) = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                (super_block.total_blocks, super_block.data_area_blocks)
+            });
+        let total_inodes = self.inode_bitmap.maximum() as u32;
+        FsStat {
+            total_blocks,
+            data_blocks,
+            free_data_blocks: data_blocks - self.data_bitmap.allocated(&self.block_device) as u32,
+            spare_blocks: self.spare_blocks(),
+            remapped_blocks: self.remapped_blocks(),
+            total_inodes,
+            free_inodes: total_inodes - self.inode_bitmap.allocated(&self.block_device) as u32,
+        }
+    }
+    /// Whether the filesystem refuses changes, also once a write was lost
+    /// for want of a spare block
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || self.mounted_read_only || self.has_failed()
+    }
+    /// Fail with why the filesystem refuses changes, [`FsError::Io`] once
+    /// a write was lost and [`FsError::ReadOnly`] otherwise
+    pub fn check_writable(&self) -> Result<(), FsError> {
+        if self.has_failed() {
+            Err(FsError::Io)
+        } else if self.is_read_only() {
+            Err(FsError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+    /// Whether it was opened read-only, see [`EasyFileSystem::open`]
+    pub fn is_mounted_read_only(&self) -> bool {
+        self.mounted_read_only
+    }
+    /// Refuse or allow changes through the inodes, one mounted read-only
+    /// refuses them anyway
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+    /// Layout version of the image, see [`crate::EFS_VERSION`]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    /// Compatible features of the image, `FEATURE_COMPAT_*`, including
+    /// unknown ones
+    pub fn compat_features(&self) -> u32 {
+        self.compat_features
+    }
+    /// Incompatible features of the image, `FEATURE_INCOMPAT_*`
+    pub fn incompat_features(&self) -> u32 {
+        self.incompat_features
+    }
+    /// Whether inodes keep permission bits
+    pub fn has_modes(&self) -> bool {
+        self.compat_features & FEATURE_COMPAT_MODES != 0
+    }
+    /// Whether named pipes may be created
+    pub fn has_fifos(&self) -> bool {
+        self.incompat_features & FEATURE_INCOMPAT_FIFO != 0
+    }
+    /// Whether directories may have quotas, see [`Inode::set_quota`]
+    pub fn has_quotas(&self) -> bool {
+        self.incompat_features & FEATURE_INCOMPAT_QUOTA != 0
+    }
+    /// Time to stamp inodes with, 0 on an image without timestamps
+    pub fn now(&self) -> u32 {
+        if self.compat_features & FEATURE_COMPAT_TIMESTAMPS != 0 {
+            now()
+        } else {
+            0
+        }
+    }
+    /// Whether `block_id` is an allocated block of the data area
+    pub fn is_data_block(&self, block_id: u32) -> bool {
+        if block_id < self.data_area_start_block {
+            return false;
+        }
+        let bit = (block_id - self.data_area_start_block) as usize;
+        bit < self.data_bitmap.maximum() && self.data_bitmap.is_allocated(&self.block_device, bit)
+    }
     /// Get data block by id
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
-    /// Allocate a new inode
+    /// Allocate a new inode, of a generation no inode had before
     pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+        let inode_id = self.inode_bitmap.alloc(&self.block_device).unwrap() as u32;
+        let generation = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                let generation = super_block.next_generation;
+                super_block.next_generation = generation.wrapping_add(1);
+                generation
+            });
+        let (block_id, _) = self.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(GENERATIONS_OFFSET, |generations: &mut InodeGenerations| {
+                generations[inode_id as usize % INODES_PER_BLOCK] = generation;
+            });
+        inode_id
+    }
+    /// Generation of inode `inode_id`, telling it apart from the inodes
+    /// that had its number before. An inode found before a restore of a
+    /// snapshot is the same after it only if it is still allocated and of
+    /// the same generation.
+    pub fn inode_generation(&self, inode_id: u32) -> u32 {
+        let (block_id, _) = self.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(GENERATIONS_OFFSET, |generations: &InodeGenerations| {
+                generations[inode_id as usize % INODES_PER_BLOCK]
+            })
+    }
+    /// Whether inode `inode_id` is allocated
+    pub fn is_inode_allocated(&self, inode_id: u32) -> bool {
+        (inode_id as usize) < self.inode_bitmap.maximum()
+            && self.inode_bitmap.is_allocated(&self.block_device, inode_id as usize)
     }
     /// Allocate a data block
     pub fn alloc_data(&mut self) -> u32 {
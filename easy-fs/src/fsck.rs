@@ -0,0 +1,432 @@
+use super::{
+    DirEntry,
+    DiskInode,
+    EasyFileSystem,
+    Quota,
+    SuperBlock,
+    DIRENT_SZ,
+    get_block_cache,
+    block_cache_sync_all,
+};
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+/// Most problems described one by one in a report, the rest are only
+/// counted
+const MAX_DETAILS: usize = 32;
+
+/// What [`EasyFileSystem::fsck`] found, and fixed when asked to
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Inodes reachable from the root
+    pub inodes: u32,
+    /// Data and index blocks owned by the inodes, reachable or not
+    pub blocks: u32,
+    /// Reachable inodes whose link count differs from the entries naming
+    /// them, or for a directory from 2 plus its subdirectories
+    pub nlink_mismatches: u32,
+    /// Entries naming a free inode or one past the inode area
+    pub dangling_dirents: u32,
+    /// Unreachable inodes that still count links
+    pub lost_inodes: u32,
+    /// Unreachable inodes without links, what an unlink leaves behind as
+    /// it never frees the inode. Reclaimed, but not a problem.
+    pub unlinked_inodes: u32,
+    /// Data blocks allocated in the bitmap that no inode owns
+    pub leaked_blocks: u32,
+    /// Data blocks owned by an inode but free in the bitmap
+    pub unmarked_blocks: u32,
+    /// Quotas whose usage differs from what is under their directory, or
+    /// of a directory that is gone
+    pub quota_mismatches: u32,
+    /// Problems with no safe fix: blocks out of the data area or owned
+    /// twice, broken directories. Nothing is repaired when there is one.
+    pub fatal: u32,
+    /// Spare blocks standing in for blocks that failed to write, see
+    /// [`crate::RemapDevice`]. Not a problem.
+    pub remapped: u32,
+    /// Spare blocks reserved
+    pub spares: u32,
+    /// Whether the problems found were fixed
+    pub repaired: bool,
+    /// One line for each of the first problems
+    pub details: Vec<String>,
+}
+
+impl FsckReport {
+    /// Inconsistencies found, the unlinked inodes aside
+    pub fn problems(&self) -> u32 {
+        self.nlink_mismatches
+            + self.dangling_dirents
+            + self.lost_inodes
+            + self.leaked_blocks
+            + self.unmarked_blocks
+            + self.quota_mismatches
+            + self.fatal
+    }
+    /// Whether the filesystem is consistent after the check, with nothing
+    /// found or everything found fixed
+    pub fn is_consistent(&self) -> bool {
+        self.problems() == 0 || self.repaired
+    }
+    fn note(&mut self, detail: String) {
+        if self.details.len() < MAX_DETAILS {
+            self.details.push(detail);
+        }
+    }
+}
+
+impl Display for FsckReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "fsck: {} inodes, {} blocks, {} problems{}",
+            self.inodes,
+            self.blocks,
+            self.problems(),
+            if self.repaired { ", repaired" } else { "" },
+        )?;
+        writeln!(
+            f,
+            "  nlink mismatches {}, dangling entries {}, lost inodes {}, leaked blocks {}, unmarked blocks {}, quota mismatches {}, fatal {}, unlinked inodes {}",
+            self.nlink_mismatches,
+            self.dangling_dirents,
+            self.lost_inodes,
+            self.leaked_blocks,
+            self.unmarked_blocks,
+            self.quota_mismatches,
+            self.fatal,
+            self.unlinked_inodes,
+        )?;
+        if self.spares > 0 {
+            writeln!(f, "  remapped blocks {} of {} spares", self.remapped, self.spares)?;
+        }
+        for detail in self.details.iter() {
+            writeln!(f, "  {}", detail)?;
+        }
+        if (self.details.len() as u32) < self.problems() {
+            writeln!(f, "  ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bit `bit` of a bitset in words
+fn test_bit(bits: &[u64], bit: usize) -> bool {
+    bits[bit / 64] & (1u64 << (bit % 64)) > 0
+}
+
+impl EasyFileSystem {
+    /// Check the filesystem in one walk over the inodes reachable from the
+    /// root and one over each bitmap. The link count of a file should be
+    /// the number of entries naming it, that of a directory 2 plus its
+    /// subdirectories, and a data block should be allocated exactly when
+    /// an inode owns it, or is the quota block, the remap table or a spare
+    /// block. The usage of a quota should be what is under its directory.
+    /// The spare blocks in use are counted.
+    ///
+    /// With `repair`, everything found is fixed: dangling entries are
+    /// removed, link counts and quota usage set, quotas of directories
+    /// that are gone dropped, bitmap bits set or cleared, and the
+    /// unreachable inodes freed with their blocks. A fatal problem, or a
+    /// filesystem [mounted read-only](EasyFileSystem::open), leaves the
+    /// disk as it is. The filesystem turns read-only unless the report
+    /// [is consistent](FsckReport::is_consistent).
+    pub fn fsck(&mut self, repair: bool) -> FsckReport {
+        let mut report = FsckReport::default();
+        let block_device = Arc::clone(&self.block_device);
+        let data_area_blocks = get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.data_area_blocks);
+        let data_start = self.metadata_blocks();
+        let data_end = data_start + data_area_blocks;
+        let in_data_area = |block_id: u32| block_id >= data_start && block_id < data_end;
+        let inode_count = self.inode_bitmap.maximum();
+        let mut inode_used = vec![false; inode_count];
+        for inode_id in self.inode_bitmap.allocated_bits(&block_device, inode_count) {
+            inode_used[inode_id] = true;
+        }
+        // entries naming each inode, and the data blocks owned so far
+        let mut refs = vec![0u32; inode_count];
+        let mut reached = vec![false; inode_count];
+        let mut parent = vec![0u32; inode_count];
+        let mut owned = vec![0u64; (data_area_blocks as usize + 63) / 64];
+        let mut inode_blocks = vec![0u32; inode_count];
+        if self.quota_block != 0 {
+            if in_data_area(self.quota_block) {
+                let bit = (self.quota_block - data_start) as usize;
+                owned[bit / 64] |= 1u64 << (bit % 64);
+                report.blocks += 1;
+            } else {
+                report.fatal += 1;
+                report.note(format!("quota block {} out of the data area", self.quota_block));
+            }
+        }
+        for block_id in self.remap_blocks() {
+            if in_data_area(block_id) {
+                let bit = (block_id - data_start) as usize;
+                owned[bit / 64] |= 1u64 << (bit % 64);
+                report.blocks += 1;
+            } else {
+                report.fatal += 1;
+                report.note(format!("spare or remap table block {} out of the data area", block_id));
+            }
+        }
+        report.spares = self.spare_blocks();
+        report.remapped = self.remapped_blocks();
+        // claim the blocks of an inode, false if one cannot be its own
+        let mut claim = |report: &mut FsckReport, inode_id: u32, blocks: Option<Vec<u32>>| {
+            let blocks = match blocks {
+                Some(blocks) => blocks,
+                None => {
+                    report.fatal += 1;
+                    report.note(format!("inode {}: bad size or index block", inode_id));
+                    return false;
+                }
+            };
+            for block_id in blocks {
+                if !in_data_area(block_id) {
+                    report.fatal += 1;
+                    report.note(format!("inode {}: block {} out of the data area", inode_id, block_id));
+                    return false;
+                }
+                let bit = (block_id - data_start) as usize;
+                if test_bit(&owned, bit) {
+                    report.fatal += 1;
+                    report.note(format!("inode {}: block {} owned twice", inode_id, block_id));
+                    return false;
+                }
+                owned[bit / 64] |= 1u64 << (bit % 64);
+                report.blocks += 1;
+            }
+            true
+        };
+
+        // the reachable inodes, breadth first from the root
+        let mut dirs: Vec<u32> = Vec::new();
+        let mut dangling: Vec<(u32, usize)> = Vec::new();
+        let mut queue: VecDeque<u32> = VecDeque::new();
+        if inode_used[0] {
+            reached[0] = true;
+            queue.push_back(0);
+        } else {
+            report.fatal += 1;
+            report.note(String::from("root inode is free"));
+        }
+        while let Some(inode_id) = queue.pop_front() {
+            report.inodes += 1;
+            let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+            let inode_cache = get_block_cache(block_id as usize, Arc::clone(&block_device));
+            let (is_dir, size, blocks) = inode_cache.lock().read(block_offset, |disk_inode: &DiskInode| {
+                (
+                    disk_inode.is_dir(),
+                    disk_inode.size as usize,
+                    disk_inode.checked_blocks(&block_device, in_data_area),
+                )
+            });
+            inode_blocks[inode_id as usize] = blocks.as_ref().map_or(0, |blocks| blocks.len() as u32);
+            if !claim(&mut report, inode_id, blocks) || !is_dir {
+                continue;
+            }
+            dirs.push(inode_id);
+            if size % DIRENT_SZ != 0 {
+                report.fatal += 1;
+                report.note(format!("directory {}: size {} is not whole entries", inode_id, size));
+            }
+            let entries: Vec<(usize, u32)> = inode_cache.lock().read(block_offset, |disk_inode: &DiskInode| {
+                let mut dirent = DirEntry::empty();
+                (0..size / DIRENT_SZ)
+                    .filter_map(|slot| {
+                        disk_inode.read_at(slot * DIRENT_SZ, dirent.as_bytes_mut(), &block_device);
+                        if dirent.name().is_empty() {
+                            None
+                        } else {
+                            Some((slot, dirent.inode_number()))
+                        }
+                    })
+                    .collect()
+            });
+            for (slot, child) in entries {
+                if child as usize >= inode_count || !inode_used[child as usize] {
+                    report.dangling_dirents += 1;
+                    report.note(format!("directory {}: entry {} names free inode {}", inode_id, slot, child));
+                    dangling.push((inode_id, slot));
+                    continue;
+                }
+                refs[child as usize] += 1;
+                if !reached[child as usize] {
+                    reached[child as usize] = true;
+                    parent[child as usize] = inode_id;
+                    queue.push_back(child);
+                }
+            }
+        }
+        // a directory is named once, by its parent, and the root by nobody
+        for &inode_id in dirs.iter() {
+            let expected = if inode_id == 0 { 0 } else { 1 };
+            if refs[inode_id as usize] != expected {
+                report.fatal += 1;
+                report.note(format!("directory {}: named {} times", inode_id, refs[inode_id as usize]));
+            }
+        }
+        // and linked by its entry or the root's `..`, its `.` and the `..`
+        // of each subdirectory
+        let mut dir_links: Vec<Option<u32>> = vec![None; inode_count];
+        for &inode_id in dirs.iter() {
+            dir_links[inode_id as usize] = Some(2);
+        }
+        for &inode_id in dirs.iter().filter(|&&inode_id| inode_id != 0) {
+            if let Some(links) = dir_links[parent[inode_id as usize] as usize].as_mut() {
+                *links += 1;
+            }
+        }
+
+        // the usage of each quota, from the reachable inodes under its
+        // directory
+        let mut quotas: Vec<(u32, Quota)> = Vec::new();
+        for (dir, quota) in self.quotas() {
+            if dir as usize >= inode_count || dir_links[dir as usize].is_none() {
+                report.quota_mismatches += 1;
+                report.note(format!("quota of inode {}: not a reachable directory", dir));
+                continue;
+            }
+            let mut used_blocks = inode_blocks[dir as usize];
+            let mut used_inodes = 0;
+            for inode_id in (0..inode_count as u32).filter(|&inode_id| reached[inode_id as usize]) {
+                let mut ancestor = inode_id;
+                while ancestor != 0 {
+                    ancestor = parent[ancestor as usize];
+                    if ancestor == dir {
+                        used_blocks += inode_blocks[inode_id as usize];
+                        used_inodes += 1;
+                        break;
+                    }
+                }
+            }
+            if (used_blocks, used_inodes) != (quota.used_blocks, quota.used_inodes) {
+                report.quota_mismatches += 1;
+                report.note(format!(
+                    "quota of directory {}: {} blocks and {} inodes used, counted {} and {}",
+                    dir, used_blocks, used_inodes, quota.used_blocks, quota.used_inodes,
+                ));
+            }
+            quotas.push((dir, Quota { used_blocks, used_inodes, ..quota }));
+        }
+
+        // link counts of the reachable inodes, and the unreachable ones
+        let mut nlinks: Vec<(u32, u32)> = Vec::new();
+        let mut orphans: Vec<u32> = Vec::new();
+        for inode_id in 0..inode_count as u32 {
+            if !inode_used[inode_id as usize] {
+                continue;
+            }
+            let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+            let (nlink, blocks) = get_block_cache(block_id as usize, Arc::clone(&block_device))
+                .lock()
+                .read(block_offset, |disk_inode: &DiskInode| {
+                    let blocks = if reached[inode_id as usize] {
+                        None
+                    } else {
+                        Some(disk_inode.checked_blocks(&block_device, in_data_area))
+                    };
+                    (disk_inode.nlink, blocks)
+                });
+            match blocks {
+                None => {
+                    let expected = dir_links[inode_id as usize].unwrap_or(refs[inode_id as usize]);
+                    if nlink != expected {
+                        report.nlink_mismatches += 1;
+                        report.note(format!("inode {}: nlink {}, expected {}", inode_id, nlink, expected));
+                        nlinks.push((inode_id, expected));
+                    }
+                }
+                Some(blocks) => {
+                    if !claim(&mut report, inode_id, blocks) {
+                        continue;
+                    }
+                    if nlink == 0 {
+                        report.unlinked_inodes += 1;
+                    } else {
+                        report.lost_inodes += 1;
+                        report.note(format!("inode {}: nlink {}, unreachable", inode_id, nlink));
+                    }
+                    orphans.push(inode_id);
+                }
+            }
+        }
+
+        // the data bitmap against the blocks owned
+        let mut marked = vec![0u64; owned.len()];
+        let mut leaked: Vec<usize> = Vec::new();
+        for bit in self.data_bitmap.allocated_bits(&block_device, data_area_blocks as usize) {
+            marked[bit / 64] |= 1u64 << (bit % 64);
+            if !test_bit(&owned, bit) {
+                report.leaked_blocks += 1;
+                report.note(format!("block {}: allocated, owned by no inode", bit as u32 + data_start));
+                leaked.push(bit);
+            }
+        }
+        let mut unmarked: Vec<usize> = Vec::new();
+        for (word, (own, mark)) in owned.iter().zip(marked.iter()).enumerate() {
+            let mut bits = own & !mark;
+            while bits != 0 {
+                let bit = word * 64 + bits.trailing_zeros() as usize;
+                report.unmarked_blocks += 1;
+                report.note(format!("block {}: owned, free in the bitmap", bit as u32 + data_start));
+                unmarked.push(bit);
+                bits &= bits - 1;
+            }
+        }
+
+        if repair && report.fatal == 0 && !self.is_mounted_read_only() {
+            for (inode_id, slot) in dangling {
+                let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+                get_block_cache(block_id as usize, Arc::clone(&block_device))
+                    .lock()
+                    .modify(block_offset, |disk_inode: &mut DiskInode| {
+                        disk_inode.write_at(slot * DIRENT_SZ, DirEntry::empty().as_bytes(), &block_device);
+                    });
+            }
+            if report.quota_mismatches > 0 {
+                self.write_quotas(&quotas);
+            }
+            for (inode_id, nlink) in nlinks {
+                let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+                get_block_cache(block_id as usize, Arc::clone(&block_device))
+                    .lock()
+                    .modify(block_offset, |disk_inode: &mut DiskInode| {
+                        disk_inode.nlink = nlink;
+                    });
+            }
+            // mark first, the orphans free their blocks through the bitmap
+            for bit in unmarked {
+                self.data_bitmap.set(&block_device, bit);
+            }
+            for bit in leaked {
+                self.dealloc_data(bit as u32 + data_start);
+            }
+            for inode_id in orphans {
+                let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+                let blocks = get_block_cache(block_id as usize, Arc::clone(&block_device))
+                    .lock()
+                    .modify(block_offset, |disk_inode: &mut DiskInode| {
+                        disk_inode.nlink = 0;
+                        disk_inode.clear_size(&block_device)
+                    });
+                for block_id in blocks {
+                    self.dealloc_data(block_id);
+                }
+                self.inode_bitmap.dealloc(&block_device, inode_id as usize);
+            }
+            block_cache_sync_all();
+            report.repaired = true;
+        }
+        self.set_read_only(!report.is_consistent());
+        report
+    }
+}
@@ -2,17 +2,49 @@ use core::fmt::{Debug, Formatter, Result};
 use super::{
     BLOCK_SZ,
     BlockDevice,
+    EasyFileSystem,
+    FsError,
     get_block_cache,
+    block_cache_read_ahead,
+    READ_AHEAD_BLOCKS,
 };
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 /// Magic number for sanity check
 const EFS_MAGIC: u32 = 0x3b800001;
+/// Version of the layout [`SuperBlock::initialize`] writes. Images of
+/// version 0 were made before the feature flags and have all the features
+/// of version 1.
+pub const EFS_VERSION: u32 = 1;
+/// Compatible feature: inodes keep their access and change times. An
+/// implementation without it leaves them stale.
+pub const FEATURE_COMPAT_TIMESTAMPS: u32 = 1 << 0;
+/// Compatible feature: inodes keep permission bits. An implementation
+/// without it treats every inode as having the default ones.
+pub const FEATURE_COMPAT_MODES: u32 = 1 << 1;
+/// Incompatible feature: there may be named pipe inodes, whose type an
+/// implementation without it cannot read
+pub const FEATURE_INCOMPAT_FIFO: u32 = 1 << 0;
+/// Incompatible feature: directories may have quotas, kept in the block
+/// [`SuperBlock::quota_block`]. An implementation without it would take
+/// that block for leaked and free it. Set with the first quota.
+pub const FEATURE_INCOMPAT_QUOTA: u32 = 1 << 1;
+/// Incompatible feature: blocks that failed to write live in spare blocks
+/// of the data area, mapped by the block [`SuperBlock::remap_block`]. An
+/// implementation without it would read the failed blocks, and take the
+/// spares for leaked and free them.
+pub const FEATURE_INCOMPAT_REMAP: u32 = 1 << 2;
+/// Compatible features this crate knows, unknown ones are ignored
+pub const FEATURE_COMPAT_SUPPORTED: u32 = FEATURE_COMPAT_TIMESTAMPS | FEATURE_COMPAT_MODES;
+/// Incompatible features this crate knows, an image with any other is
+/// refused
+pub const FEATURE_INCOMPAT_SUPPORTED: u32 =
+    FEATURE_INCOMPAT_FIFO | FEATURE_INCOMPAT_QUOTA | FEATURE_INCOMPAT_REMAP;
 /// The max number of direct inodes
 const INODE_DIRECT_COUNT: usize = 28;
 /// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 27;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// The max number of indirect2 inodes
@@ -22,7 +54,6 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode index
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
 
 /// Super block of a filesystem
@@ -34,6 +65,24 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    pub version: u32,
+    /// Features an implementation may ignore, `FEATURE_COMPAT_*`
+    pub compat_features: u32,
+    /// Features an implementation must know to use the image,
+    /// `FEATURE_INCOMPAT_*`
+    pub incompat_features: u32,
+    /// Data block of the quota records, only with
+    /// [`FEATURE_INCOMPAT_QUOTA`]
+    pub quota_block: u32,
+    /// Blocks reserved for a snapshot past `total_blocks`, 0 for none, see
+    /// [`EasyFileSystem::snapshot`]
+    pub snapshot_blocks: u32,
+    /// Generation of the next inode allocated, see
+    /// [`EasyFileSystem::inode_generation`]
+    pub next_generation: u32,
+    /// Data block of the remap table, only with
+    /// [`FEATURE_INCOMPAT_REMAP`]
+    pub remap_block: u32,
 }
 
 impl Debug for SuperBlock {
@@ -44,12 +93,20 @@ impl Debug for SuperBlock {
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
             .field("data_area_blocks", &self.data_area_blocks)
+            .field("version", &self.version)
+            .field("compat_features", &self.compat_features)
+            .field("incompat_features", &self.incompat_features)
+            .field("quota_block", &self.quota_block)
+            .field("snapshot_blocks", &self.snapshot_blocks)
+            .field("next_generation", &self.next_generation)
+            .field("remap_block", &self.remap_block)
             .finish()
     }
 }
 
 impl SuperBlock {
-    /// Initialize a super block
+    /// Initialize a super block of the current version, with all the
+    /// features this crate knows but quotas, which come with the first one
     pub fn initialize(
         &mut self,
         total_blocks: u32,
@@ -65,12 +122,28 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
+            version: EFS_VERSION,
+            compat_features: FEATURE_COMPAT_SUPPORTED,
+            incompat_features: FEATURE_INCOMPAT_FIFO,
+            quota_block: 0,
+            snapshot_blocks: 0,
+            next_generation: 0,
+            remap_block: 0,
         }
     }
     /// Check if a super block is valid using efs magic
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+    /// The compatible and incompatible features of the image, those of
+    /// version 1 for an image from before the flags
+    pub fn features(&self) -> (u32, u32) {
+        if self.version == 0 {
+            (FEATURE_COMPAT_TIMESTAMPS | FEATURE_COMPAT_MODES, FEATURE_INCOMPAT_FIFO)
+        } else {
+            (self.compat_features, self.incompat_features)
+        }
+    }
 }
 
 /// Type of a disk inode
@@ -78,6 +151,9 @@ impl SuperBlock {
 pub enum DiskInodeType {
     File,
     Directory,
+    /// A named pipe, the data only lives in the kernel and never gets any
+    /// blocks
+    Fifo,
 }
 
 /// A indirect block
@@ -93,20 +169,70 @@ pub struct DiskInode {
     pub indirect1: u32,
     pub indirect2: u32,
     pub nlink: u32,
+    /// Last access, seconds since the epoch
+    pub atime: u32,
+    /// Last change of the data
+    pub mtime: u32,
+    /// Last change of the data or the metadata
+    pub ctime: u32,
     type_: DiskInodeType,
+    /// Permission bits, `rwx` for the owner, the group and others like
+    /// `0o755`. It fits in the padding after `type_`.
+    pub mode: u16,
 }
 
+/// Disk inodes in a block of the inode area
+pub const INODES_PER_BLOCK: usize = BLOCK_SZ / core::mem::size_of::<DiskInode>();
+/// Where the generations of the inodes of a block start, in the room the
+/// inodes leave at its end. An image from before them has zeros there.
+pub const GENERATIONS_OFFSET: usize = INODES_PER_BLOCK * core::mem::size_of::<DiskInode>();
+/// Generations of the inodes of a block, see
+/// [`EasyFileSystem::inode_generation`]
+pub type InodeGenerations = [u32; INODES_PER_BLOCK];
+
+const _: () = assert!(GENERATIONS_OFFSET + core::mem::size_of::<InodeGenerations>() <= BLOCK_SZ);
+
+/// Permission bits of a new directory
+pub const DIR_MODE: u16 = 0o755;
+/// Permission bits of a new file or named pipe
+pub const FILE_MODE: u16 = 0o644;
+
 impl DiskInode {
     /// Initialize a disk inode, as well as all direct inodes under it
     /// indirect1 and indirect2 block are allocated only when they are needed
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
-        self.nlink = 1;
+        // a directory is also named by its own `.`
+        self.nlink = match type_ {
+            DiskInodeType::Directory => 2,
+            _ => 1,
+        };
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+        self.mode = match type_ {
+            DiskInodeType::Directory => DIR_MODE,
+            _ => FILE_MODE,
+        };
         self.type_ = type_;
     }
+    /// Permission bits of current disk inode on `fs`, the default ones on
+    /// an image without them
+    pub fn mode_in(&self, fs: &EasyFileSystem) -> u16 {
+        match (fs.has_modes(), self.is_dir()) {
+            (true, _) => self.mode,
+            (false, true) => DIR_MODE,
+            (false, false) => FILE_MODE,
+        }
+    }
+    /// Record a change of the data at `now`
+    pub fn touch_data(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
+    }
     /// Whether this inode is a directory
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
@@ -116,6 +242,10 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether this inode is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
     /// Get the number of data blocks corresponding to size
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -175,6 +305,77 @@ impl DiskInode {
             })
         }
     }
+    /// Get ids of all blocks of current disk inode, the data blocks
+    /// together with the indirect blocks indexing them
+    pub fn blocks(&self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        let data_blocks = self.data_blocks() as usize;
+        let mut v: Vec<u32> = Vec::new();
+        v.extend_from_slice(&self.direct[..data_blocks.min(INODE_DIRECT_COUNT)]);
+        if data_blocks > INODE_DIRECT_COUNT {
+            v.push(self.indirect1);
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect1: &IndirectBlock| {
+                    v.extend_from_slice(
+                        &indirect1[..data_blocks.min(INDIRECT1_BOUND) - INODE_DIRECT_COUNT],
+                    );
+                });
+        }
+        if data_blocks > INDIRECT1_BOUND {
+            v.push(self.indirect2);
+            let last = data_blocks - INDIRECT1_BOUND;
+            let sub_count = (last + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            let mut subs: Vec<u32> = Vec::new();
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    subs.extend_from_slice(&indirect2[..sub_count]);
+                });
+            for (i, sub) in subs.into_iter().enumerate() {
+                v.push(sub);
+                let count = (last - i * INODE_INDIRECT1_COUNT).min(INODE_INDIRECT1_COUNT);
+                get_block_cache(sub as usize, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |indirect1: &IndirectBlock| {
+                        v.extend_from_slice(&indirect1[..count]);
+                    });
+            }
+        }
+        v
+    }
+    /// Like [`DiskInode::blocks`] for an inode that may be corrupted:
+    /// `None` if the size is past what the index blocks can hold, or an
+    /// index block fails `valid` before it is read. The data blocks are not
+    /// checked.
+    pub fn checked_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        valid: impl Fn(u32) -> bool,
+    ) -> Option<Vec<u32>> {
+        let data_blocks = self.data_blocks() as usize;
+        if data_blocks > INDIRECT2_BOUND {
+            return None;
+        }
+        if data_blocks > INODE_DIRECT_COUNT && !valid(self.indirect1) {
+            return None;
+        }
+        if data_blocks > INDIRECT1_BOUND {
+            if !valid(self.indirect2) {
+                return None;
+            }
+            let last = data_blocks - INDIRECT1_BOUND;
+            let sub_count = (last + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            let subs_valid = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    indirect2[..sub_count].iter().all(|sub| valid(*sub))
+                });
+            if !subs_valid {
+                return None;
+            }
+        }
+        Some(self.blocks(block_device))
+    }
     /// Inncrease the size of current disk inode
     pub fn increase_size(
         &mut self,
@@ -257,6 +458,58 @@ impl DiskInode {
             }
         });
     }
+    /// Shrink the size to `new_size` and return blocks that should be
+    /// deallocated, data blocks past the new end as well as the indirect
+    /// blocks no longer needed. The tail of the last block kept is zeroed,
+    /// so that growing the file again reads zeros there.
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        let mut v: Vec<u32> = (new_blocks..old_blocks)
+            .map(|inner_id| self.get_block_id(inner_id as u32, block_device))
+            .collect();
+        let tail = new_size as usize % BLOCK_SZ;
+        if tail > 0 {
+            get_block_cache(
+                self.get_block_id(new_blocks as u32 - 1, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                data_block[tail..].iter_mut().for_each(|p| *p = 0);
+            });
+        }
+        // low-level indirect1 blocks under indirect2 holding no block any more
+        let sub_blocks = |data_blocks: usize| {
+            (data_blocks.saturating_sub(INDIRECT1_BOUND) + INODE_INDIRECT1_COUNT - 1)
+                / INODE_INDIRECT1_COUNT
+        };
+        if old_blocks > INDIRECT1_BOUND {
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    v.extend_from_slice(&indirect2[sub_blocks(new_blocks)..sub_blocks(old_blocks)]);
+                });
+            if new_blocks <= INDIRECT1_BOUND {
+                v.push(self.indirect2);
+                self.indirect2 = 0;
+            }
+        }
+        if old_blocks > INODE_DIRECT_COUNT && new_blocks <= INODE_DIRECT_COUNT {
+            v.push(self.indirect1);
+            self.indirect1 = 0;
+        }
+        for direct in self.direct.iter_mut().take(old_blocks).skip(new_blocks) {
+            *direct = 0;
+        }
+        self.size = new_size;
+        v
+    }
     /// Clear size to zero and return blocks that should be deallocated
     /// and clear the block contents to zero later
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
@@ -345,6 +598,21 @@ impl DiskInode {
         self.indirect2 = 0;
         v
     }
+    /// Load the blocks holding `len` bytes from `offset` into the block
+    /// cache with batched reads, at most [`READ_AHEAD_BLOCKS`] of them and
+    /// none past the end of current disk inode
+    pub fn read_ahead(&self, offset: usize, len: usize, block_device: &Arc<dyn BlockDevice>) {
+        let end = (offset + len).min(self.size as usize);
+        if offset >= end {
+            return;
+        }
+        let first_block = offset / BLOCK_SZ;
+        let last_block = ((end - 1) / BLOCK_SZ).min(first_block + READ_AHEAD_BLOCKS - 1);
+        let ahead: Vec<usize> = (first_block..=last_block)
+            .map(|inner_id| self.get_block_id(inner_id as u32, block_device) as usize)
+            .collect();
+        block_cache_read_ahead(&ahead, block_device);
+    }
     /// Read data from current disk inode
     pub fn read_at(
         &self,
@@ -358,8 +626,14 @@ impl DiskInode {
             return 0;
         }
         let mut start_block = start / BLOCK_SZ;
+        let first_block = start_block;
+        let last_block = (end - 1) / BLOCK_SZ;
         let mut read_size = 0usize;
         loop {
+            // fetch the next blocks of a longer read with batched requests
+            if last_block > first_block && (start_block - first_block) % READ_AHEAD_BLOCKS == 0 {
+                self.read_ahead(start_block * BLOCK_SZ, (last_block + 1 - start_block) * BLOCK_SZ, block_device);
+            }
             // calculate end of current block
             let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
             end_current_block = end_current_block.min(end);
@@ -422,6 +696,83 @@ impl DiskInode {
     }
 }
 
+/// Spare blocks a remap table holds at most
+pub const REMAP_SLOTS: usize = (BLOCK_SZ - 8) / 8;
+
+/// The block [`SuperBlock::remap_block`], see [`crate::RemapDevice`]. Spare
+/// `i` stands in for block `bad[i]` once `i` is below `remapped`; a block
+/// is where the last spare standing in for it is.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RemapTable {
+    /// Spare blocks reserved
+    pub spares: u32,
+    /// Spare blocks in use, the first ones
+    pub remapped: u32,
+    pub spare: [u32; REMAP_SLOTS],
+    pub bad: [u32; REMAP_SLOTS],
+}
+
+const _: () = assert!(core::mem::size_of::<RemapTable>() <= BLOCK_SZ);
+
+impl RemapTable {
+    /// The block id `block_id` is written to and read from
+    pub fn lookup(&self, block_id: u32) -> u32 {
+        (0..self.remapped as usize)
+            .rev()
+            .find(|&i| self.bad[i] == block_id)
+            .map_or(block_id, |i| self.spare[i])
+    }
+    /// The spare blocks reserved
+    pub fn spares(&self) -> &[u32] {
+        &self.spare[..self.spares as usize]
+    }
+}
+
+/// Magic number of a snapshot header
+const SNAPSHOT_MAGIC: u32 = 0x3b800002;
+
+/// First block of the snapshot region, see [`EasyFileSystem::snapshot`].
+/// The blocks after it are copies of the metadata blocks, then of the
+/// allocated data blocks in the order of the data bitmap among them.
+#[repr(C)]
+pub struct SnapshotHeader {
+    magic: u32,
+    /// Blocks copied after the header
+    pub blocks: u32,
+    /// Of them, the blocks before the data area
+    pub metadata_blocks: u32,
+    /// When it was taken, seconds since the epoch
+    pub time: u32,
+}
+
+impl SnapshotHeader {
+    /// The header of a snapshot of `blocks` blocks, the first
+    /// `metadata_blocks` of them before the data area
+    pub fn new(blocks: u32, metadata_blocks: u32, time: u32) -> Self {
+        Self {
+            magic: SNAPSHOT_MAGIC,
+            blocks,
+            metadata_blocks,
+            time,
+        }
+    }
+    /// The header of a region holding no snapshot
+    pub fn empty() -> Self {
+        Self {
+            magic: 0,
+            blocks: 0,
+            metadata_blocks: 0,
+            time: 0,
+        }
+    }
+    /// Whether the region holds a whole snapshot. The header is written
+    /// last, so one cut short is not.
+    pub fn is_valid(&self) -> bool {
+        self.magic == SNAPSHOT_MAGIC
+    }
+}
+
 /// A directory entry
 #[repr(C)]
 pub struct DirEntry {
@@ -440,14 +791,19 @@ impl DirEntry {
             inode_number: 0,
         }
     }
-    /// Crate a directory entry from name and inode number
-    pub fn new(name: &str, inode_number: u32) -> Self {
+    /// Create a directory entry from name and inode number. Fails if the
+    /// name does not fit with its NUL, that is if it is longer than
+    /// [`NAME_LENGTH_LIMIT`] bytes.
+    pub fn try_new(name: &str, inode_number: u32) -> core::result::Result<Self, FsError> {
+        if name.len() > NAME_LENGTH_LIMIT {
+            return Err(FsError::NameTooLong);
+        }
         let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
         bytes[..name.len()].copy_from_slice(name.as_bytes());
-        Self {
+        Ok(Self {
             name: bytes,
             inode_number,
-        }
+        })
     }
     /// Serialize into bytes
     pub fn as_bytes(&self) -> &[u8] {
@@ -467,10 +823,15 @@ impl DirEntry {
             )
         }
     }
-    /// Get name of the entry
+    /// Get name of the entry. Never reads past the name field: a corrupted
+    /// entry without a NUL ends there, and one that is not UTF-8 ends
+    /// before the first bad byte.
     pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(self.name.len());
+        match core::str::from_utf8(&self.name[..len]) {
+            Ok(name) => name,
+            Err(err) => core::str::from_utf8(&self.name[..err.valid_up_to()]).unwrap(),
+        }
     }
     /// Get inode number of the entry
     pub fn inode_number(&self) -> u32 {
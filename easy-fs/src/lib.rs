@@ -8,12 +8,36 @@ mod efs;
 mod bitmap;
 mod vfs;
 mod block_cache;
+mod clock;
+mod lock;
+mod fsck;
+mod quota;
+mod snapshot;
+mod remap;
+mod log;
 
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
-pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
-pub use vfs::Inode;
+pub use block_dev::{BlockDevice, BlockError};
+pub use efs::{EasyFileSystem, FsError, FsStat};
+pub use layout::{
+    EFS_VERSION, FEATURE_COMPAT_MODES, FEATURE_COMPAT_SUPPORTED, FEATURE_COMPAT_TIMESTAMPS,
+    FEATURE_INCOMPAT_FIFO, FEATURE_INCOMPAT_QUOTA, FEATURE_INCOMPAT_REMAP, FEATURE_INCOMPAT_SUPPORTED,
+    NAME_LENGTH_LIMIT, DIRENT_SZ, REMAP_SLOTS,
+};
+pub use fsck::FsckReport;
+pub use quota::Quota;
+pub use vfs::{DirEntryInfo, Inode, InodeStat};
+pub use clock::{set_clock, set_ticks};
+pub use lock::set_relax;
+pub use log::set_log;
+pub use remap::RemapDevice;
+pub use block_cache::{
+    block_cache_stats, block_cache_sync_older_than, BlockCacheStats, READ_AHEAD_BLOCKS,
+};
 use layout::*;
 use bitmap::Bitmap;
-use block_cache::{get_block_cache, block_cache_sync_all};
+use block_cache::{
+    get_block_cache, block_cache_invalidate_all, block_cache_read_ahead, block_cache_set_read_only,
+    block_cache_sync_all, block_cache_sync_where,
+};
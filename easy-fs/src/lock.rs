@@ -0,0 +1,42 @@
+//! Locks that may be held across block device requests
+//!
+//! A kernel that puts a task to sleep while its request is in flight sets a
+//! hook with [`set_relax`]: a task waiting for a lock the sleeper holds then
+//! makes way for others instead of spinning. Without a hook it spins.
+
+pub use spin::MutexGuard;
+
+static RELAX: spin::Mutex<Option<fn()>> = spin::Mutex::new(None);
+
+/// Call `relax` each time a lock is found taken
+pub fn set_relax(relax: fn()) {
+    *RELAX.lock() = Some(relax);
+}
+
+/// A spin lock calling the [`set_relax`] hook while it waits
+pub struct Mutex<T: ?Sized>(spin::Mutex<T>);
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self(spin::Mutex::new(value))
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.0.try_lock() {
+                return guard;
+            }
+            let relax = *RELAX.lock();
+            match relax {
+                Some(relax) => relax(),
+                None => core::hint::spin_loop(),
+            }
+        }
+    }
+    /// The lock if it is free, without waiting
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.0.try_lock()
+    }
+}
@@ -0,0 +1,20 @@
+//! Messages about the device, like a block standing in for another
+
+use core::fmt;
+use spin::Mutex;
+
+static LOG: Mutex<Option<fn(fmt::Arguments)>> = Mutex::new(None);
+
+/// Pass the messages of the filesystem to `log`. Without it they are
+/// dropped.
+pub fn set_log(log: fn(fmt::Arguments)) {
+    *LOG.lock() = Some(log);
+}
+
+/// Pass a message to the hook set with [`set_log`]
+pub(crate) fn log(args: fmt::Arguments) {
+    let log = *LOG.lock();
+    if let Some(log) = log {
+        log(args);
+    }
+}
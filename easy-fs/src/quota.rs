@@ -0,0 +1,219 @@
+use super::{
+    EasyFileSystem,
+    FsError,
+    SuperBlock,
+    BLOCK_SZ,
+    FEATURE_INCOMPAT_QUOTA,
+    get_block_cache,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Limits of a directory and what it uses with everything under it, see
+/// [`crate::Inode::set_quota`]. The blocks are the data and index blocks
+/// of the directory and of every inode under it, the inodes those under
+/// it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quota {
+    /// Blocks allowed, 0 for no limit
+    pub max_blocks: u32,
+    /// Inodes allowed, 0 for no limit
+    pub max_inodes: u32,
+    pub used_blocks: u32,
+    pub used_inodes: u32,
+}
+
+impl Quota {
+    /// Whether `blocks` and `inodes` more stay within the limits. Only
+    /// what grows is checked, a quota lowered under its usage still
+    /// allows the rest.
+    fn allows(&self, blocks: u32, inodes: u32) -> bool {
+        let fits = |more: u32, used: u32, max: u32| {
+            more == 0 || max == 0 || used.saturating_add(more) <= max
+        };
+        fits(blocks, self.used_blocks, self.max_blocks)
+            && fits(inodes, self.used_inodes, self.max_inodes)
+    }
+}
+
+/// A slot of the quota block
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct QuotaRecord {
+    /// Nonzero when the slot holds a quota
+    in_use: u32,
+    /// Inode of the directory
+    dir: u32,
+    quota: Quota,
+}
+
+/// Slots of the quota block
+const QUOTA_RECORDS: usize = BLOCK_SZ / core::mem::size_of::<QuotaRecord>();
+/// The quota block
+type QuotaBlock = [QuotaRecord; QUOTA_RECORDS];
+
+impl EasyFileSystem {
+    /// Call a function over the records of the quota block to read them,
+    /// `None` without one
+    fn read_quotas<V>(&self, f: impl FnOnce(&QuotaBlock) -> V) -> Option<V> {
+        if self.quota_block == 0 {
+            return None;
+        }
+        Some(
+            get_block_cache(self.quota_block as usize, Arc::clone(&self.block_device))
+                .lock()
+                .read(0, f),
+        )
+    }
+    /// Call a function over the records of the quota block to modify
+    /// them, `None` without one
+    fn modify_quotas<V>(&self, f: impl FnOnce(&mut QuotaBlock) -> V) -> Option<V> {
+        if self.quota_block == 0 {
+            return None;
+        }
+        Some(
+            get_block_cache(self.quota_block as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, f),
+        )
+    }
+    /// The quota of directory `dir`
+    pub fn quota(&self, dir: u32) -> Option<Quota> {
+        self.read_quotas(|records| {
+            records
+                .iter()
+                .find(|record| record.in_use != 0 && record.dir == dir)
+                .map(|record| record.quota)
+        })
+        .flatten()
+    }
+    /// Directories with a quota and their quotas
+    pub fn quotas(&self) -> Vec<(u32, Quota)> {
+        self.read_quotas(|records| {
+            records
+                .iter()
+                .filter(|record| record.in_use != 0)
+                .map(|record| (record.dir, record.quota))
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+    /// Give directory `dir` the quota `quota`, or take its quota away with
+    /// `None`. The first quota allocates the quota block and marks the
+    /// image with [`FEATURE_INCOMPAT_QUOTA`].
+    pub(crate) fn set_quota(&mut self, dir: u32, quota: Option<Quota>) -> Result<(), FsError> {
+        if self.quota_block == 0 {
+            if quota.is_none() {
+                return Ok(());
+            }
+            // a freed block was zeroed, so every slot is free
+            let quota_block = self.alloc_data();
+            get_block_cache(0, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |super_block: &mut SuperBlock| {
+                    // an image from before the flags gets them
+                    let (compat_features, incompat_features) = super_block.features();
+                    super_block.version = super_block.version.max(1);
+                    super_block.compat_features = compat_features;
+                    super_block.incompat_features = incompat_features | FEATURE_INCOMPAT_QUOTA;
+                    super_block.quota_block = quota_block;
+                });
+            self.incompat_features |= FEATURE_INCOMPAT_QUOTA;
+            self.quota_block = quota_block;
+        }
+        self.modify_quotas(|records| {
+            let slot = records
+                .iter()
+                .position(|record| record.in_use != 0 && record.dir == dir)
+                .or_else(|| records.iter().position(|record| record.in_use == 0));
+            match (slot, quota) {
+                (Some(slot), Some(quota)) => {
+                    records[slot] = QuotaRecord { in_use: 1, dir, quota };
+                    Ok(())
+                }
+                (Some(slot), None) => {
+                    records[slot].in_use = 0;
+                    Ok(())
+                }
+                (None, Some(_)) => Err(FsError::TooManyQuotas),
+                (None, None) => Ok(()),
+            }
+        })
+        .unwrap()
+    }
+    /// Count `blocks` and `inodes` more against the quotas of the
+    /// directories `dirs`. Nothing is counted if one of them does not
+    /// allow it.
+    pub(crate) fn quota_charge(&self, dirs: &[u32], blocks: u32, inodes: u32) -> Result<(), FsError> {
+        if blocks == 0 && inodes == 0 {
+            return Ok(());
+        }
+        self.modify_quotas(|records| {
+            let charged = |record: &QuotaRecord| record.in_use != 0 && dirs.contains(&record.dir);
+            if records
+                .iter()
+                .any(|record| charged(record) && !record.quota.allows(blocks, inodes))
+            {
+                return Err(FsError::QuotaExceeded);
+            }
+            for record in records.iter_mut().filter(|record| charged(record)) {
+                record.quota.used_blocks += blocks;
+                record.quota.used_inodes += inodes;
+            }
+            Ok(())
+        })
+        .unwrap_or(Ok(()))
+    }
+    /// Count `blocks` and `inodes` less against the quotas of the
+    /// directories `dirs`
+    pub(crate) fn quota_release(&self, dirs: &[u32], blocks: u32, inodes: u32) {
+        if blocks == 0 && inodes == 0 {
+            return;
+        }
+        self.modify_quotas(|records| {
+            for record in records
+                .iter_mut()
+                .filter(|record| record.in_use != 0 && dirs.contains(&record.dir))
+            {
+                record.quota.used_blocks = record.quota.used_blocks.saturating_sub(blocks);
+                record.quota.used_inodes = record.quota.used_inodes.saturating_sub(inodes);
+            }
+        });
+    }
+    /// The fewest blocks the quotas of the directories `dirs` still allow,
+    /// `u32::MAX` without a limit
+    pub(crate) fn quota_room(&self, dirs: &[u32]) -> u32 {
+        self.read_quotas(|records| {
+            records
+                .iter()
+                .filter(|record| record.in_use != 0 && dirs.contains(&record.dir))
+                .filter(|record| record.quota.max_blocks != 0)
+                .map(|record| record.quota.max_blocks.saturating_sub(record.quota.used_blocks))
+                .min()
+                .unwrap_or(u32::MAX)
+        })
+        .unwrap_or(u32::MAX)
+    }
+    /// Whether the same quotas count the directories `dirs` and `others`
+    pub(crate) fn same_quotas(&self, dirs: &[u32], others: &[u32]) -> bool {
+        self.read_quotas(|records| {
+            records
+                .iter()
+                .filter(|record| record.in_use != 0)
+                .all(|record| dirs.contains(&record.dir) == others.contains(&record.dir))
+        })
+        .unwrap_or(true)
+    }
+    /// Replace the records of the quota block, for fsck
+    pub(crate) fn write_quotas(&self, quotas: &[(u32, Quota)]) {
+        self.modify_quotas(|records| {
+            for (slot, record) in records.iter_mut().enumerate() {
+                *record = match quotas.get(slot) {
+                    Some(&(dir, quota)) => QuotaRecord { in_use: 1, dir, quota },
+                    None => QuotaRecord { in_use: 0, dir: 0, quota: Quota::default() },
+                };
+            }
+        });
+    }
+}
@@ -0,0 +1,228 @@
+//! Spare blocks standing in for blocks that fail to write
+//!
+//! An image made with spare blocks, see
+//! [`EasyFileSystem::create_with_spares`], has [`FEATURE_INCOMPAT_REMAP`]
+//! and the filesystem reaches its device through a [`RemapDevice`]. When
+//! the device fails to write a block, the next spare takes its place: the
+//! remap table names it for the block and is written to the device, then
+//! the write is tried again on the spare. Reads and later writes of the
+//! block go to its spare. The blocks are cached under the [`RemapDevice`],
+//! which is the same as the device but for the blocks remapped.
+//!
+//! The table is only ever read and written here, directly on the device,
+//! never through the block cache. The super block and the table block
+//! have no spare, and there is no spare left once all are in use: the
+//! write is lost then, and every change to the filesystem fails with
+//! [`FsError::Io`] from then on.
+
+use super::{
+    BlockDevice,
+    BlockError,
+    EasyFileSystem,
+    FsError,
+    RemapTable,
+    BLOCK_SZ,
+    FEATURE_INCOMPAT_REMAP,
+    REMAP_SLOTS,
+};
+use crate::lock::Mutex;
+use crate::log::log;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A block device whose blocks that fail to write live in spare blocks
+pub struct RemapDevice {
+    device: Arc<dyn BlockDevice>,
+    /// Block of the remap table
+    table_block: u32,
+    table: Mutex<RemapTable>,
+    /// Set once a write was lost
+    failed: AtomicBool,
+}
+
+impl RemapDevice {
+    /// Reach `device` through a new table at `table_block`, with the blocks
+    /// `spares` to stand in for those that fail. Fails if the table cannot
+    /// be written.
+    pub fn create(
+        device: Arc<dyn BlockDevice>,
+        table_block: u32,
+        spares: &[u32],
+    ) -> Result<Self, FsError> {
+        let mut table = RemapTable {
+            spares: spares.len() as u32,
+            remapped: 0,
+            spare: [0; REMAP_SLOTS],
+            bad: [0; REMAP_SLOTS],
+        };
+        table.spare[..spares.len()].copy_from_slice(spares);
+        let remap = Self {
+            device,
+            table_block,
+            table: Mutex::new(table),
+            failed: AtomicBool::new(false),
+        };
+        remap.write_table(&table).map_err(|_| FsError::Io)?;
+        Ok(remap)
+    }
+    /// Reach `device` through the table at `table_block` on it
+    pub fn open(device: Arc<dyn BlockDevice>, table_block: u32) -> Self {
+        let mut block = [0u8; BLOCK_SZ];
+        device.read_block(table_block as usize, &mut block);
+        let table = unsafe { (block.as_ptr() as *const RemapTable).read_unaligned() };
+        Self {
+            device,
+            table_block,
+            table: Mutex::new(table),
+            failed: AtomicBool::new(false),
+        }
+    }
+    /// Block of the remap table
+    pub fn table_block(&self) -> u32 {
+        self.table_block
+    }
+    /// The spare blocks, those in use or not
+    pub fn spares(&self) -> Vec<u32> {
+        self.table.lock().spares().to_vec()
+    }
+    /// Spare blocks in use
+    pub fn remapped(&self) -> u32 {
+        self.table.lock().remapped
+    }
+    /// Whether a write was lost, see [`FsError::Io`]
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+    /// Where the block `block_id` lives
+    fn lookup(&self, block_id: usize) -> usize {
+        self.table.lock().lookup(block_id as u32) as usize
+    }
+    /// Whether one of the `count` blocks from `start` on is remapped
+    fn remaps_any(&self, start: usize, count: usize) -> bool {
+        let table = self.table.lock();
+        let remapped = &table.bad[..table.remapped as usize];
+        remapped.iter().any(|&bad| (start..start + count).contains(&(bad as usize)))
+    }
+    fn write_table(&self, table: &RemapTable) -> Result<(), BlockError> {
+        let mut block = [0u8; BLOCK_SZ];
+        unsafe { (block.as_mut_ptr() as *mut RemapTable).write_unaligned(*table) };
+        self.device.try_write_block(self.table_block as usize, &block)
+    }
+    /// Put the next spare in place of `block_id`, and return it. None if
+    /// the block cannot have one or none is left.
+    fn remap(&self, block_id: usize) -> Option<usize> {
+        if block_id == 0 || block_id == self.table_block as usize {
+            return None;
+        }
+        let mut table = self.table.lock();
+        let slot = table.remapped as usize;
+        if slot == table.spares as usize {
+            return None;
+        }
+        table.bad[slot] = block_id as u32;
+        table.remapped += 1;
+        if self.write_table(&table).is_err() {
+            table.remapped -= 1;
+            return None;
+        }
+        let spare = table.spare[slot];
+        log(format_args!(
+            "easy-fs: block {} failed to write, remapped to spare {} ({} of {} in use)",
+            block_id,
+            spare,
+            table.remapped,
+            table.spares,
+        ));
+        Some(spare as usize)
+    }
+}
+
+impl BlockDevice for RemapDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.device.read_block(self.lookup(block_id), buf);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        // a lost write is told by `failed`
+        let _ = self.try_write_block(block_id, buf);
+    }
+    fn read_blocks(&self, start: usize, bufs: &mut [&mut [u8]]) {
+        if !self.remaps_any(start, bufs.len()) {
+            return self.device.read_blocks(start, bufs);
+        }
+        for (block_id, buf) in (start..).zip(bufs.iter_mut()) {
+            self.read_block(block_id, buf);
+        }
+    }
+    fn write_blocks(&self, start: usize, bufs: &[&[u8]]) {
+        let _ = self.try_write_blocks(start, bufs);
+    }
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut target = self.lookup(block_id);
+        // a spare may fail too, the next one takes its place then
+        while self.device.try_write_block(target, buf).is_err() {
+            target = match self.remap(block_id) {
+                Some(spare) => spare,
+                None => {
+                    self.failed.store(true, Ordering::Relaxed);
+                    log(format_args!(
+                        "easy-fs: block {} failed to write and has no spare, write lost",
+                        block_id,
+                    ));
+                    return Err(BlockError(block_id));
+                }
+            };
+        }
+        Ok(())
+    }
+    fn try_write_blocks(&self, start: usize, bufs: &[&[u8]]) -> Result<(), BlockError> {
+        if !self.remaps_any(start, bufs.len()) && self.device.try_write_blocks(start, bufs).is_ok() {
+            return Ok(());
+        }
+        // one at a time, writing again those that made it
+        let mut result = Ok(());
+        for (block_id, buf) in (start..).zip(bufs.iter()) {
+            if let Err(err) = self.try_write_block(block_id, buf) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+    fn handle_irq(&self) {
+        self.device.handle_irq();
+    }
+    fn quiesce(&self) {
+        self.device.quiesce();
+    }
+}
+
+impl EasyFileSystem {
+    /// Whether blocks that fail to write may live in spare blocks
+    pub fn has_spares(&self) -> bool {
+        self.incompat_features & FEATURE_INCOMPAT_REMAP != 0
+    }
+    /// The blocks of the data area the remap table and the spares take,
+    /// allocated for good, none without them
+    pub(crate) fn remap_blocks(&self) -> Vec<u32> {
+        match &self.remap {
+            Some(remap) => {
+                let mut blocks = remap.spares();
+                blocks.push(remap.table_block());
+                blocks
+            }
+            None => Vec::new(),
+        }
+    }
+    /// Spare blocks reserved, 0 without them
+    pub fn spare_blocks(&self) -> u32 {
+        self.remap.as_ref().map_or(0, |remap| remap.spares().len() as u32)
+    }
+    /// Blocks that failed to write and live in spare blocks
+    pub fn remapped_blocks(&self) -> u32 {
+        self.remap.as_ref().map_or(0, |remap| remap.remapped())
+    }
+    /// Whether a write was lost for want of a spare, see [`FsError::Io`]
+    pub fn has_failed(&self) -> bool {
+        self.remap.as_ref().map_or(false, |remap| remap.failed())
+    }
+}
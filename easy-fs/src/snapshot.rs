@@ -0,0 +1,211 @@
+//! Snapshot of a whole filesystem
+//!
+//! An image may reserve blocks past the filesystem for one snapshot, see
+//! [`EasyFileSystem::reserve_snapshot`]. Taking it copies the metadata
+//! blocks and the allocated data blocks there, restoring copies them back.
+//! Both write the block cache back first and then copy between the blocks
+//! on the device directly, in batches. The remap table and the spare
+//! blocks are left out, see [`crate::RemapDevice`]: they belong to the
+//! device more than to the filesystem. Every operation through an
+//! [`crate::Inode`] holds the filesystem lock, and so does the caller of
+//! these, so none runs meanwhile.
+
+use super::{
+    BlockDevice,
+    EasyFileSystem,
+    FsError,
+    SnapshotHeader,
+    SuperBlock,
+    BLOCK_SZ,
+    get_block_cache,
+    block_cache_invalidate_all,
+    block_cache_sync_all,
+};
+use crate::clock::now;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Most blocks copied with one device call
+const COPY_BATCH: usize = 64;
+
+/// Copy the `count` blocks from `from` on to those from `to` on
+fn copy_blocks(block_device: &Arc<dyn BlockDevice>, from: usize, to: usize, count: usize) {
+    let mut blocks = vec![[0u8; BLOCK_SZ]; count.min(COPY_BATCH)];
+    let mut done = 0;
+    while done < count {
+        let batch = (count - done).min(COPY_BATCH);
+        let mut bufs: Vec<&mut [u8]> = blocks[..batch].iter_mut().map(|block| &mut block[..]).collect();
+        block_device.read_blocks(from + done, &mut bufs);
+        let bufs: Vec<&[u8]> = blocks[..batch].iter().map(|block| &block[..]).collect();
+        block_device.write_blocks(to + done, &bufs);
+        done += batch;
+    }
+}
+
+/// Zero the `count` blocks from `start` on
+fn zero_blocks(block_device: &Arc<dyn BlockDevice>, start: usize, count: usize) {
+    let zeros = [0u8; BLOCK_SZ];
+    let mut done = 0;
+    while done < count {
+        let batch = (count - done).min(COPY_BATCH);
+        let bufs: Vec<&[u8]> = (0..batch).map(|_| &zeros[..]).collect();
+        block_device.write_blocks(start + done, &bufs);
+        done += batch;
+    }
+}
+
+/// Call `f` with the first bit, the index of that bit among `bits` and the
+/// length of each run of consecutive bits of the sorted `bits`
+fn for_each_run(bits: &[usize], mut f: impl FnMut(usize, usize, usize)) {
+    let mut start = 0;
+    while start < bits.len() {
+        let mut end = start + 1;
+        while end < bits.len() && bits[end] == bits[end - 1] + 1 {
+            end += 1;
+        }
+        f(bits[start], start, end - start);
+        start = end;
+    }
+}
+
+/// Where the region of a filesystem is, from its super block
+struct Region {
+    /// Data blocks of the filesystem, bits of the data bitmap
+    data_area_blocks: usize,
+    /// The header, the first block past the filesystem
+    header: usize,
+    /// Blocks of the region, the header included
+    blocks: usize,
+}
+
+impl EasyFileSystem {
+    fn snapshot_region(&self) -> Region {
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| Region {
+                data_area_blocks: super_block.data_area_blocks as usize,
+                header: super_block.total_blocks as usize,
+                blocks: super_block.snapshot_blocks as usize,
+            })
+    }
+    /// The allocated bits of the data bitmap but those of the remap table
+    /// and the spare blocks, which stay as they are through a restore
+    fn copied_bits(&self, data_area_blocks: usize) -> Vec<usize> {
+        let metadata_blocks = self.metadata_blocks() as usize;
+        let remap_blocks = self.remap_blocks();
+        self.data_bitmap
+            .allocated_bits(&self.block_device, data_area_blocks)
+            .into_iter()
+            .filter(|bit| !remap_blocks.contains(&((metadata_blocks + bit) as u32)))
+            .collect()
+    }
+    /// Blocks reserved past the filesystem for a snapshot, 0 for none
+    pub fn snapshot_blocks(&self) -> u32 {
+        self.snapshot_region().blocks as u32
+    }
+    /// Reserve the `blocks` blocks past the filesystem for a snapshot, the
+    /// device must have them. An image without them has no room for one.
+    pub fn reserve_snapshot(&mut self, blocks: u32) -> Result<(), FsError> {
+        self.check_writable()?;
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                super_block.snapshot_blocks = blocks;
+            });
+        block_cache_sync_all();
+        Ok(())
+    }
+    /// Copy the blocks in use, the metadata blocks and the allocated data
+    /// blocks, to the snapshot region, replacing the snapshot there.
+    /// Returns the blocks copied. Fails if they do not fit, leaving no
+    /// snapshot, or if the filesystem is read-only.
+    pub fn snapshot(&self) -> Result<u32, FsError> {
+        self.check_writable()?;
+        let region = self.snapshot_region();
+        block_cache_sync_all();
+        let metadata_blocks = self.metadata_blocks() as usize;
+        let data = self.copied_bits(region.data_area_blocks);
+        let blocks = metadata_blocks + data.len();
+        if region.blocks == 0 || 1 + blocks > region.blocks {
+            return Err(FsError::SnapshotTooLarge);
+        }
+        // a snapshot cut short must not look whole
+        self.write_snapshot_header(region.header, SnapshotHeader::empty());
+        copy_blocks(&self.block_device, 0, region.header + 1, metadata_blocks);
+        let first = region.header + 1 + metadata_blocks;
+        for_each_run(&data, |bit, index, count| {
+            let block_id = metadata_blocks + bit;
+            copy_blocks(&self.block_device, block_id, first + index, count);
+        });
+        let header = SnapshotHeader::new(blocks as u32, metadata_blocks as u32, now());
+        self.write_snapshot_header(region.header, header);
+        Ok(blocks as u32)
+    }
+    /// Write the snapshot header `header` at `block_id` to the device
+    fn write_snapshot_header(&self, block_id: usize, header: SnapshotHeader) {
+        get_block_cache(block_id, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |old: &mut SnapshotHeader| *old = header);
+        block_cache_sync_all();
+    }
+    /// Copy the snapshot back over the filesystem. The data blocks
+    /// allocated since are freed and zeroed, and the inodes allocated since
+    /// are free again; the generations go on from the highest one given,
+    /// so an inode allocated again has a new one, see
+    /// [`EasyFileSystem::inode_generation`]. Every cached block is dropped.
+    /// Returns the blocks copied. Fails if there is no snapshot of this
+    /// filesystem or it is read-only.
+    pub fn restore(&mut self) -> Result<u32, FsError> {
+        self.check_writable()?;
+        let region = self.snapshot_region();
+        let metadata_blocks = self.metadata_blocks() as usize;
+        let header = match region.blocks {
+            0 => None,
+            _ => get_block_cache(region.header, Arc::clone(&self.block_device))
+                .lock()
+                .read(0, |header: &SnapshotHeader| {
+                    let whole = header.is_valid()
+                        && header.metadata_blocks as usize == metadata_blocks
+                        && 1 + header.blocks as usize <= region.blocks;
+                    if whole { Some(header.blocks) } else { None }
+                }),
+        };
+        let blocks = header.ok_or(FsError::NoSnapshot)?;
+        let next_generation = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.next_generation);
+        block_cache_sync_all();
+        let allocated = self.copied_bits(region.data_area_blocks);
+        block_cache_invalidate_all(&self.block_device);
+        copy_blocks(&self.block_device, region.header + 1, 0, metadata_blocks);
+        // the bitmaps just read are stale
+        block_cache_invalidate_all(&self.block_device);
+        let restored = self.copied_bits(region.data_area_blocks);
+        let first = region.header + 1 + metadata_blocks;
+        for_each_run(&restored, |bit, index, count| {
+            let block_id = metadata_blocks + bit;
+            copy_blocks(&self.block_device, first + index, block_id, count);
+        });
+        // a free block is zeroed, like `dealloc_data` leaves it
+        let freed: Vec<usize> = allocated
+            .into_iter()
+            .filter(|bit| restored.binary_search(bit).is_err())
+            .collect();
+        for_each_run(&freed, |bit, _, count| {
+            zero_blocks(&self.block_device, metadata_blocks + bit, count);
+        });
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                super_block.next_generation = super_block.next_generation.max(next_generation);
+                let (compat_features, incompat_features) = super_block.features();
+                self.version = super_block.version;
+                self.compat_features = compat_features;
+                self.incompat_features = incompat_features;
+                self.quota_block = super_block.quota_block;
+            });
+        block_cache_sync_all();
+        Ok(blocks)
+    }
+}
@@ -4,14 +4,47 @@ use super::{
     DiskInodeType,
     DirEntry,
     EasyFileSystem,
+    FsError,
+    FsStat,
+    Quota,
+    BLOCK_SZ,
     DIRENT_SZ,
     get_block_cache,
     block_cache_sync_all,
+    block_cache_sync_where,
 };
+use alloc::collections::BTreeSet;
 use alloc::sync::Arc;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
-use spin::{Mutex, MutexGuard};
+use crate::lock::{Mutex, MutexGuard};
+
+/// Metadata of an inode, see [`Inode::stat`]
+pub struct InodeStat {
+    pub ino: u64,
+    /// Size in bytes
+    pub size: u64,
+    pub nlink: u32,
+    pub is_dir: bool,
+    pub is_fifo: bool,
+    /// Permission bits, see [`Inode::set_mode`]
+    pub mode: u16,
+    /// Last access, seconds since the epoch
+    pub atime: u64,
+    /// Last change of the data
+    pub mtime: u64,
+    /// Last change of the data or the metadata
+    pub ctime: u64,
+}
+
+/// An entry of a directory, see [`Inode::read_dirent`]
+pub struct DirEntryInfo {
+    pub name: String,
+    pub ino: u64,
+    pub is_dir: bool,
+    pub is_fifo: bool,
+}
 
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
@@ -20,10 +53,16 @@ pub struct Inode {
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
+    /// The directories from the one it was reached from down to current
+    /// inode, whose quotas count its blocks and what is created under it
+    quota_dirs: Vec<u32>,
+    /// Generation of the inode when it was found, see [`Inode::is_stale`]
+    generation: u32,
 }
 
 impl Inode {
-    /// Create a vfs inode
+    /// Create a vfs inode, the top of the directories whose quotas count
+    /// it
     pub fn new(
         inode_id: u32,
         block_id: u32,
@@ -31,12 +70,31 @@ impl Inode {
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
+        let generation = fs.lock().inode_generation(inode_id);
         Self {
             inode_id: inode_id as usize,
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
+            quota_dirs: vec![inode_id],
+            generation,
+        }
+    }
+    /// The vfs inode of `inode_id` in current directory, counted by the
+    /// quotas over current directory too
+    fn child(&self, inode_id: u32, fs: &EasyFileSystem) -> Inode {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let mut quota_dirs = self.quota_dirs.clone();
+        quota_dirs.push(inode_id);
+        Self {
+            inode_id: inode_id as usize,
+            block_id: block_id as usize,
+            block_offset,
+            fs: self.fs.clone(),
+            block_device: self.block_device.clone(),
+            quota_dirs,
+            generation: fs.inode_generation(inode_id),
         }
     }
     /// Call a function over a disk inode to read it
@@ -53,6 +111,17 @@ impl Inode {
             Arc::clone(&self.block_device)
         ).lock().modify(self.block_offset, f)
     }
+    /// Call a function over a disk inode to read its data, recording the
+    /// access unless the filesystem is read-only
+    fn access_disk_inode<V>(&self, fs: &EasyFileSystem, f: impl FnOnce(&DiskInode) -> V) -> V {
+        if fs.is_read_only() {
+            return self.read_disk_inode(f);
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.atime = fs.now();
+            f(disk_inode)
+        })
+    }
     /// Find inode under a disk inode by name
     fn find_inode_id(
         &self,
@@ -78,51 +147,132 @@ impl Inode {
         }
         None
     }
-    /// Find inode under current inode by name
+    /// Find inode under current inode by name, `None` if current inode is
+    /// not a directory
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
+        // an empty name would match the slots of removed entries
+        if name.is_empty() {
+            return None;
+        }
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
             self.find_inode_id(name, disk_inode)
-            .map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    inode_id,
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        })?;
+        // its generation may share the block of current inode
+        Some(Arc::new(self.child(inode_id, &fs)))
     }
-    /// Increase the size of a disk inode
+    /// Increase the size of a disk inode, counting the new blocks against
+    /// the quotas over current inode. Nothing is allocated if one of them
+    /// does not allow all of them.
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> Result<(), FsError> {
         if new_size < disk_inode.size {
-            return;
+            return Ok(());
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
+        fs.quota_charge(&self.quota_dirs, blocks_needed, 0)?;
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..blocks_needed {
             v.push(fs.alloc_data());
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        Ok(())
     }
-    /// Create inode under current inode by name
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+    /// Increase the size of a disk inode toward `new_size`, as far as the
+    /// quotas over current inode allow. Returns the size reached, fails
+    /// only if it is not past `offset`.
+    fn increase_size_within_quota(
+        &self,
+        offset: usize,
+        new_size: u32,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Result<usize, FsError> {
+        let err = match self.increase_size(new_size, disk_inode, fs) {
+            Ok(()) => return Ok(new_size as usize),
+            Err(err) => err,
+        };
+        let room = fs.quota_room(&self.quota_dirs);
+        let (mut low, mut high) = (disk_inode.size, new_size);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            if disk_inode.blocks_num_needed(mid) <= room {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        if low as usize <= offset {
+            return Err(err);
+        }
+        self.increase_size(low, disk_inode, fs)?;
+        Ok(low as usize)
+    }
+    /// Create a file under current inode by name
+    pub fn create(&self, name: &str) -> Result<Arc<Inode>, FsError> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+    /// Create an empty directory under current inode by name
+    pub fn create_dir(&self, name: &str) -> Result<Arc<Inode>, FsError> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+    /// Create a named pipe under current inode by name
+    pub fn create_fifo(&self, name: &str) -> Result<Arc<Inode>, FsError> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+    /// Fail with why `name` cannot be added to current inode, see
+    /// [`FsError`]
+    fn check_new_name(&self, fs: &EasyFileSystem, name: &str) -> Result<(), FsError> {
+        fs.check_writable()?;
+        if name.is_empty() {
+            return Err(FsError::EmptyName);
+        }
+        // fits in an entry
+        DirEntry::try_new(name, 0)?;
+        self.read_disk_inode(|root_inode| {
+            if !root_inode.is_dir() {
+                Err(FsError::NotDir)
+            } else if self.find_inode_id(name, root_inode).is_some() {
+                Err(FsError::Exists)
+            } else {
+                Ok(())
+            }
+        })
+    }
+    /// Create inode under current inode by name. Fails if current inode is
+    /// not a directory, the name is taken or does not fit in an entry, the
+    /// quotas over current inode do not allow the inode or a larger
+    /// directory, or the filesystem is read-only. Named pipes need an
+    /// image with them.
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Result<Arc<Inode>, FsError> {
         let mut fs = self.fs.lock();
-        if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
-        }).is_some() {
-            return None;
+        self.check_new_name(&fs, name)?;
+        if type_ == DiskInodeType::Fifo && !fs.has_fifos() {
+            return Err(FsError::NoFifos);
         }
+        let is_dir = type_ == DiskInodeType::Directory;
+        fs.quota_charge(&self.quota_dirs, 0, 1)?;
+        // room for the entry first, so that nothing is left behind if the
+        // quotas do not allow it
+        let grown = self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs).map(|_| file_count)
+        });
+        let file_count = match grown {
+            Ok(file_count) => file_count,
+            Err(err) => {
+                fs.quota_release(&self.quota_dirs, 0, 1);
+                return Err(err);
+            }
+        };
         // create a new file
         // alloc a inode with an indirect block
         let new_inode_id = fs.alloc_inode();
@@ -133,35 +283,63 @@ impl Inode {
             new_inode_block_id as usize,
             Arc::clone(&self.block_device)
         ).lock().modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-            new_inode.initialize(DiskInodeType::File);
+            new_inode.initialize(type_, fs.now());
         });
         self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
+            // write dirent, the name was checked to fit
+            let dirent = DirEntry::try_new(name, new_inode_id).unwrap();
             root_inode.write_at(
                 file_count * DIRENT_SZ,
                 dirent.as_bytes(),
                 &self.block_device,
             );
+            root_inode.touch_data(fs.now());
+            // the `..` of a new directory
+            if is_dir {
+                root_inode.nlink += 1;
+            }
         });
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
         block_cache_sync_all();
         // return inode
-        Some(Arc::new(Self::new(
-            new_inode_id,
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Ok(Arc::new(self.child(new_inode_id, &fs)))
         // release efs lock automatically by compiler
     }
+    /// The first entry of current directory in slot `slot` or after it,
+    /// with the slot it is in. Removed entries are skipped, `None` past the
+    /// last one.
+    pub fn read_dirent(&self, slot: usize) -> Option<(usize, DirEntryInfo)> {
+        let fs = self.fs.lock();
+        let (slot, name, inode_id) = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            let mut dirent = DirEntry::empty();
+            for i in slot..file_count {
+                assert_eq!(
+                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device),
+                    DIRENT_SZ,
+                );
+                if !dirent.name().is_empty() {
+                    return Some((i, String::from(dirent.name()), dirent.inode_number()));
+                }
+            }
+            None
+        })?;
+        // the entry may share a block with current inode, read it only after
+        // releasing that one
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let (is_dir, is_fifo) = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(block_offset, |disk_inode: &DiskInode| (disk_inode.is_dir(), disk_inode.is_fifo()));
+        Some((slot, DirEntryInfo {
+            name,
+            ino: inode_id as u64,
+            is_dir,
+            is_fifo,
+        }))
+    }
     /// List inodes under current inode
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
@@ -185,69 +363,404 @@ impl Inode {
     }
     /// Read data from current inode
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let fs = self.fs.lock();
+        self.access_disk_inode(&fs, |disk_inode| {
+            disk_inode.read_at(offset, buf, &self.block_device)
+        })
+    }
+    /// Load the blocks holding `len` bytes of current inode from `offset`
+    /// into the block cache ahead of the reads that need them, see
+    /// [`DiskInode::read_ahead`]
+    pub fn read_ahead(&self, offset: usize, len: usize) {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            disk_inode.read_at(offset, buf, &self.block_device)
+            disk_inode.read_ahead(offset, len, &self.block_device)
         })
     }
-    /// Write data to current inode
+    /// Write data to current inode, nothing on a read-only filesystem.
+    /// Past the end, only as much as the quotas over current inode allow.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.try_write_at(offset, buf).unwrap_or(0)
+    }
+    /// [`Inode::write_at`], but failing with why nothing was written
+    pub fn try_write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        self.try_write_at_vectored(offset, &[buf])
+    }
+    /// Read into `bufs` one after another from `offset`, stopping at the
+    /// end of current inode. Returns the total bytes read.
+    pub fn read_at_vectored(&self, offset: usize, bufs: &mut [&mut [u8]]) -> usize {
+        let fs = self.fs.lock();
+        self.access_disk_inode(&fs, |disk_inode| {
+            let mut total = 0;
+            for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+                let read_size = disk_inode.read_at(offset + total, buf, &self.block_device);
+                total += read_size;
+                if read_size < buf.len() {
+                    break;
+                }
+            }
+            total
+        })
+    }
+    /// Write `bufs` one after another from `offset`, growing current inode
+    /// once for all of them, as far as the quotas over it allow. Returns
+    /// the total bytes written, none on a read-only filesystem.
+    pub fn write_at_vectored(&self, offset: usize, bufs: &[&[u8]]) -> usize {
+        self.try_write_at_vectored(offset, bufs).unwrap_or(0)
+    }
+    /// [`Inode::write_at_vectored`], but failing with why nothing was
+    /// written, [`FsError::FileTooLarge`] if it would end past `u32::MAX`
+    pub fn try_write_at_vectored(&self, offset: usize, bufs: &[&[u8]]) -> Result<usize, FsError> {
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let new_size = offset
+            .checked_add(total)
+            .and_then(|end| u32::try_from(end).ok())
+            .ok_or(FsError::FileTooLarge)?;
+        let mut fs = self.fs.lock();
+        fs.check_writable()?;
+        self.modify_disk_inode(|disk_inode| {
+            let end = self.increase_size_within_quota(offset, new_size, disk_inode, &mut fs)?;
+            disk_inode.touch_data(fs.now());
+            let mut write_size = 0;
+            for buf in bufs.iter().filter(|buf| !buf.is_empty()) {
+                let len = buf.len().min(end - offset - write_size);
+                if len == 0 {
+                    break;
+                }
+                write_size += disk_inode.write_at(offset + write_size, &buf[..len], &self.block_device);
+            }
+            Ok(write_size)
+        })
+    }
+    /// The disk block holding block `block_index` of current inode, `None`
+    /// past its end
+    pub fn block_id(&self, block_index: usize) -> Option<u32> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if block_index < disk_inode.data_blocks() as usize {
+                Some(disk_inode.get_block_id(block_index as u32, &self.block_device))
+            } else {
+                None
+            }
+        })
+    }
+    /// Overwrite block `block_index` of current inode with the disk block
+    /// `src_block_id`, growing current inode to the end of the block if it
+    /// is shorter and its quotas allow it. The data goes from one cached
+    /// block to the other, with no buffer in between. `src_block_id` must
+    /// be an allocated data block, like one from [`Inode::block_id`].
+    pub fn write_block_aligned(&self, block_index: usize, src_block_id: u32) -> Result<(), FsError> {
+        let mut fs = self.fs.lock();
+        self.write_block(&mut fs, block_index, src_block_id)
+    }
+    /// [`Inode::write_block_aligned`] under the lock `fs`
+    fn write_block(
+        &self,
+        fs: &mut MutexGuard<EasyFileSystem>,
+        block_index: usize,
+        src_block_id: u32,
+    ) -> Result<(), FsError> {
+        fs.check_writable()?;
+        if !fs.is_data_block(src_block_id) {
+            return Err(FsError::BadBlock(src_block_id));
+        }
+        self.modify_disk_inode(|disk_inode| {
+            self.increase_size(((block_index + 1) * BLOCK_SZ) as u32, disk_inode, fs)?;
+            disk_inode.touch_data(fs.now());
+            let dst_block_id = disk_inode.get_block_id(block_index as u32, &self.block_device);
+            if dst_block_id == src_block_id {
+                return Ok(());
+            }
+            let src = get_block_cache(src_block_id as usize, Arc::clone(&self.block_device));
+            let dst = get_block_cache(dst_block_id as usize, Arc::clone(&self.block_device));
+            src.lock().read(0, |src: &[u8; BLOCK_SZ]| {
+                dst.lock().modify(0, |dst: &mut [u8; BLOCK_SZ]| dst.copy_from_slice(src));
+            });
+            Ok(())
+        })
+    }
+    /// Copy `len` bytes of current inode from `offset` into `dst` at
+    /// `dst_offset`, stopping at the end of current inode or when `dst`
+    /// takes no more, on a read-only filesystem for one. Returns the
+    /// bytes copied. When both offsets and `len` are block-aligned, whole
+    /// blocks go from one cached block to the other like
+    /// [`Inode::write_block_aligned`], each looked up and written under
+    /// one lock so that it cannot be freed in between. Anything else goes
+    /// through a block sized buffer.
+    pub fn copy_to(&self, offset: usize, dst: &Inode, dst_offset: usize, len: usize) -> usize {
+        let mut copied = 0;
+        if offset % BLOCK_SZ == 0
+            && dst_offset % BLOCK_SZ == 0
+            && len % BLOCK_SZ == 0
+            && self.same_fs(dst)
+        {
+            while copied < len {
+                let mut fs = self.fs.lock();
+                let block_index = (offset + copied) / BLOCK_SZ;
+                let src_block_id = self.access_disk_inode(&fs, |disk_inode| {
+                    // a partial last block goes through the buffer
+                    if (block_index + 1) * BLOCK_SZ <= disk_inode.size as usize {
+                        Some(disk_inode.get_block_id(block_index as u32, &self.block_device))
+                    } else {
+                        None
+                    }
+                });
+                let written = src_block_id.map_or(false, |src_block_id| {
+                    dst.write_block(&mut fs, (dst_offset + copied) / BLOCK_SZ, src_block_id).is_ok()
+                });
+                if !written {
+                    break;
+                }
+                copied += BLOCK_SZ;
+            }
+        }
+        let mut buf = [0u8; BLOCK_SZ];
+        while copied < len {
+            // to the end of the source block, whole blocks move once the
+            // offset is aligned
+            let chunk = (BLOCK_SZ - (offset + copied) % BLOCK_SZ).min(len - copied);
+            let read_size = self.read_at(offset + copied, &mut buf[..chunk]);
+            if read_size == 0 {
+                break;
+            }
+            let write_size = dst.write_at(dst_offset + copied, &buf[..read_size]);
+            copied += write_size;
+            if write_size < read_size {
+                break;
+            }
+        }
+        copied
+    }
+    /// Grow current inode to at least `size` bytes, allocating its blocks
+    /// without writing them. Nothing grows if the quotas over it do not
+    /// allow all of them.
+    pub fn fallocate(&self, size: usize) {
         let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+        if fs.is_read_only() {
+            return;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            if self.increase_size(size as u32, disk_inode, &mut fs).is_ok() {
+                disk_inode.touch_data(fs.now());
+            }
+        });
+        block_cache_sync_all();
+    }
+    /// Append `buf` at the end of current inode, returns the new size. The
+    /// end is found and written under the filesystem lock, so concurrent
+    /// appends never overlap. Nothing is appended on a read-only
+    /// filesystem, or if the quotas over current inode do not allow all of
+    /// `buf`.
+    pub fn append(&self, buf: &[u8]) -> usize {
+        self.try_append(buf)
+            .unwrap_or_else(|_| self.read_disk_inode(|disk_inode| disk_inode.size as usize))
+    }
+    /// [`Inode::append`], but failing with why nothing was appended
+    pub fn try_append(&self, buf: &[u8]) -> Result<usize, FsError> {
+        let mut fs = self.fs.lock();
+        fs.check_writable()?;
+        self.modify_disk_inode(|disk_inode| {
+            let offset = disk_inode.size as usize;
+            let new_size = u32::try_from(offset + buf.len()).map_err(|_| FsError::FileTooLarge)?;
+            self.increase_size(new_size, disk_inode, &mut fs)?;
+            disk_inode.touch_data(fs.now());
+            disk_inode.write_at(offset, buf, &self.block_device);
+            Ok(disk_inode.size as usize)
+        })
+    }
+    /// Set the size of current inode to `size`, freeing the blocks past a
+    /// lowered end. A raised end reads as zeros, and is not raised if the
+    /// quotas over current inode do not allow it.
+    pub fn truncate(&self, size: usize) {
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.touch_data(fs.now());
+            if size as u32 >= disk_inode.size {
+                let _ = self.increase_size(size as u32, disk_inode, &mut fs);
+                return;
+            }
+            let data_blocks_dealloc = disk_inode.decrease_size(size as u32, &self.block_device);
+            fs.quota_release(&self.quota_dirs, data_blocks_dealloc.len() as u32, 0);
+            for data_block in data_blocks_dealloc {
+                fs.dealloc_data(data_block);
+            }
         });
         block_cache_sync_all();
-        size
     }
     /// Clear the data in current inode
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return;
+        }
+        self.clear_data(&mut fs);
+        block_cache_sync_all();
+    }
+    /// [`Inode::clear`] under the lock `fs`
+    fn clear_data(&self, fs: &mut MutexGuard<EasyFileSystem>) {
         self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
+            disk_inode.touch_data(fs.now());
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
             assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            fs.quota_release(&self.quota_dirs, data_blocks_dealloc.len() as u32, 0);
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
         });
+    }
+    /// Write the cached blocks of current inode back to the device: its
+    /// data and index blocks, and the metadata blocks holding its inode
+    /// and the bitmaps
+    pub fn sync(&self) {
+        let fs = self.fs.lock();
+        let mut blocks = self.read_disk_inode(|disk_inode| {
+            disk_inode.blocks(&self.block_device)
+        });
+        blocks.sort_unstable();
+        let metadata_blocks = fs.metadata_blocks() as usize;
+        block_cache_sync_where(&self.block_device, |block_id| {
+            block_id < metadata_blocks || blocks.binary_search(&(block_id as u32)).is_ok()
+        });
+    }
+    /// Write every cached block back to the devices
+    pub fn sync_all(&self) {
+        let _fs = self.fs.lock();
         block_cache_sync_all();
-    }    
+    }
+    /// Usage of the filesystem holding this inode
+    pub fn stat_fs(&self) -> FsStat {
+        self.fs.lock().stat_fs()
+    }
+    /// Whether the filesystem holding this inode refuses changes, see
+    /// [`EasyFileSystem::fsck`]
+    pub fn read_only(&self) -> bool {
+        self.fs.lock().is_read_only()
+    }
+    /// Whether a write to the filesystem holding this inode was lost, see
+    /// [`FsError::Io`]
+    pub fn has_failed(&self) -> bool {
+        self.fs.lock().has_failed()
+    }
+    /// Snapshot the filesystem holding this inode, see
+    /// [`EasyFileSystem::snapshot`]
+    pub fn snapshot_fs(&self) -> Result<u32, FsError> {
+        self.fs.lock().snapshot()
+    }
+    /// Restore the snapshot of the filesystem holding this inode, see
+    /// [`EasyFileSystem::restore`]. Current inode and the others found
+    /// before may be stale afterwards.
+    pub fn restore_fs(&self) -> Result<u32, FsError> {
+        self.fs.lock().restore()
+    }
+    /// Whether the inode was freed, or freed and allocated again, since it
+    /// was found. Only a restore of a snapshot does that to an inode in
+    /// use; what it was is gone then.
+    pub fn is_stale(&self) -> bool {
+        let fs = self.fs.lock();
+        let inode_id = self.inode_id as u32;
+        !fs.is_inode_allocated(inode_id) || fs.inode_generation(inode_id) != self.generation
+    }
     
     
-    pub fn link(&self, old_name: &str, new_name: &str) -> isize {
-        if old_name == new_name {
-            return -1;
+    /// Link `old_name` in current directory as `new_name` in it too
+    pub fn link(&self, old_name: &str, new_name: &str) -> Result<(), FsError> {
+        match self.find(old_name) {
+            Some(old_inode) => self.add_link(new_name, &old_inode),
+            None => Err(FsError::NotFound),
         }
-        if let Some(old_inode) = self.find(old_name) {
-            let mut fs = self.fs.lock();
-            self.modify_disk_inode(|root_inode| {
-                let file_count = (root_inode.size as usize) / DIRENT_SZ;
-                let new_size = (file_count + 1) * DIRENT_SZ;
-                self.increase_size(new_size as u32, root_inode, &mut fs);
-                let dirent = DirEntry::new(new_name, old_inode.inode_id as u32);
-                root_inode.write_at(
-                    file_count * DIRENT_SZ,
-                    dirent.as_bytes(),
-                    &self.block_device,
-                );
-            });
-            old_inode.modify_disk_inode(|disk_inode: &mut DiskInode| {
-                disk_inode.nlink += 1;
-                disk_inode.nlink
-            });
-            block_cache_sync_all();
-            return 0;
+    }
+
+    /// Add an entry `name` to current directory for the file `target`, which
+    /// may be in any directory under the same quotas. Directories cannot be
+    /// linked.
+    pub fn add_link(&self, name: &str, target: &Inode) -> Result<(), FsError> {
+        if target.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        if !self.same_fs(target) {
+            return Err(FsError::CrossDevice);
+        }
+        let mut fs = self.fs.lock();
+        self.check_new_name(&fs, name)?;
+        let dirent = DirEntry::try_new(name, target.inode_id as u32)?;
+        // a file is counted once, by the quotas over all its entries
+        let target_dirs = &target.quota_dirs[..target.quota_dirs.len() - 1];
+        if !fs.same_quotas(&self.quota_dirs, target_dirs) {
+            return Err(FsError::CrossDevice);
         }
-        -1
+        self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs)?;
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+            root_inode.touch_data(fs.now());
+            Ok(())
+        })?;
+        // the target may share a block with current inode, only modify it
+        // after releasing that one
+        target.modify_disk_inode(|disk_inode: &mut DiskInode| {
+            disk_inode.nlink += 1;
+            disk_inode.ctime = fs.now();
+        });
+        block_cache_sync_all();
+        Ok(())
     }
 
+    /// Remove the entry `name` from current directory, dropping a link to
+    /// its inode. The last link gives its blocks and the inode back to the
+    /// quotas over current directory.
     pub fn unlink(&self, name: &str) -> isize {
         let fs = self.fs.lock();
+        if fs.is_read_only() || name.is_empty() {
+            return -1;
+        }
+        let inode_id = match self.remove_entry(&fs, name) {
+            Some(inode_id) => inode_id,
+            None => return -1,
+        };
+        // the inode may share a block with current inode, only modify it
+        // after releasing that one
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, |di: &mut DiskInode| {
+                di.nlink -= 1;
+                di.ctime = fs.now();
+                if di.nlink == 0 {
+                    fs.quota_release(&self.quota_dirs, DiskInode::total_blocks(di.size), 1);
+                }
+                // 清理会超时
+                // if di.nlink == 0 {
+                //     let size = di.size;
+                //     let data_blocks_dealloc = di.clear_size(&self.block_device);
+                //     assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+                //     for data_block in data_blocks_dealloc.into_iter() {
+                //         fs.dealloc_data(data_block);
+                //     }
+                // }
+            });
+        block_cache_sync_all();
+        0
+    }
+
+    /// Clear the entry `name` of current directory under the lock `fs`,
+    /// returns the inode it named
+    fn remove_entry(&self, fs: &EasyFileSystem, name: &str) -> Option<u32> {
         self.modify_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
             let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            (0..file_count).for_each(|i| {
-                let mut dirent = DirEntry::empty();
+            let mut dirent = DirEntry::empty();
+            for i in 0..file_count {
                 assert_eq!(
                     disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
                     DIRENT_SZ,
@@ -258,35 +771,181 @@ impl Inode {
                         DirEntry::empty().as_bytes(),
                         &self.block_device,
                     );
-                    let (block_id, block_offset) = fs.get_disk_inode_pos(dirent.inode_number());
-                    get_block_cache(block_id as usize, Arc::clone(&self.block_device))
-                        .lock()
-                        .modify(block_offset, |di: &mut DiskInode| {
-                            di.nlink -= 1;
-                            // 清理会超时
-                            // if di.nlink == 0 {
-                            //     let size = di.size;
-                            //     let data_blocks_dealloc = di.clear_size(&self.block_device);
-                            //     assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-                            //     for data_block in data_blocks_dealloc.into_iter() {
-                            //         fs.dealloc_data(data_block);
-                            //     }
-                            // }
-                        });
-                    }
-            })
-        });
+                    disk_inode.touch_data(fs.now());
+                    return Some(dirent.inode_number());
+                }
+            }
+            None
+        })
+    }
+
+    /// Whether the directory `disk_inode` has an entry left
+    fn has_entries(&self, disk_inode: &DiskInode) -> bool {
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        (0..file_count).any(|i| {
+            assert_eq!(
+                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device),
+                DIRENT_SZ,
+            );
+            !dirent.name().is_empty()
+        })
+    }
 
+    /// Remove the empty directory `name` from current directory, freeing its
+    /// blocks. It is found, checked and removed under one lock, so no entry
+    /// gets into it in between, and it is only cleared once removed.
+    pub fn remove_dir(&self, name: &str) -> isize {
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() || name.is_empty() {
+            return -1;
+        }
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
+            self.find_inode_id(name, disk_inode)
+        });
+        let dir = match inode_id {
+            Some(inode_id) => self.child(inode_id, &fs),
+            None => return -1,
+        };
+        // the directory may share a block with current inode, only read it
+        // after releasing that one
+        if !dir.read_disk_inode(|disk_inode| disk_inode.is_dir() && !dir.has_entries(disk_inode)) {
+            return -1;
+        }
+        if self.remove_entry(&fs, name).is_none() {
+            return -1;
+        }
+        dir.clear_data(&mut fs);
+        // its entry, its `.`, and its `..` naming current directory
+        let now = fs.now();
+        dir.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink -= 2;
+            disk_inode.ctime = now;
+        });
+        fs.quota_release(&self.quota_dirs, 0, 1);
+        let _ = fs.set_quota(dir.inode_id as u32, None);
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink -= 1;
+            disk_inode.ctime = now;
+        });
         block_cache_sync_all();
         0
     }
 
+    /// Whether `other` is on the same filesystem as current inode
+    pub fn same_fs(&self, other: &Inode) -> bool {
+        Arc::ptr_eq(&self.fs, &other.fs)
+    }
+
     pub fn nlink(&self) -> u32 {
         self.read_disk_inode(|disk_inode|{
             disk_inode.nlink
         })
     }
 
+    /// Metadata of current inode, all read at once
+    pub fn stat(&self) -> InodeStat {
+        let fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| InodeStat {
+            ino: self.inode_id as u64,
+            size: disk_inode.size as u64,
+            nlink: disk_inode.nlink,
+            is_dir: disk_inode.is_dir(),
+            is_fifo: disk_inode.is_fifo(),
+            mode: disk_inode.mode_in(&fs),
+            atime: disk_inode.atime as u64,
+            mtime: disk_inode.mtime as u64,
+            ctime: disk_inode.ctime as u64,
+        })
+    }
+
+    /// Permission bits of current inode
+    pub fn mode(&self) -> u16 {
+        let fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.mode_in(&fs))
+    }
+    /// Replace the permission bits, `rwx` for the owner, the group and
+    /// others. Other bits of `mode` are ignored, and all of them on an image
+    /// without permission bits.
+    pub fn set_mode(&self, mode: u16) {
+        let fs = self.fs.lock();
+        if fs.is_read_only() || !fs.has_modes() {
+            return;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & 0o777;
+            disk_inode.ctime = fs.now();
+        });
+    }
+
+    /// Limit the blocks and inodes of current directory and everything
+    /// under it, 0 for no limit, see [`Quota`]. Their usage is counted from
+    /// the tree, and may be over the limits already: then nothing more is
+    /// allocated under current directory until enough is freed.
+    pub fn set_quota(&self, max_blocks: u32, max_inodes: u32) -> Result<(), FsError> {
+        let mut fs = self.fs.lock();
+        fs.check_writable()?;
+        if !self.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return Err(FsError::NotDir);
+        }
+        let (used_blocks, used_inodes) = self.tree_usage(&fs);
+        fs.set_quota(self.inode_id as u32, Some(Quota {
+            max_blocks,
+            max_inodes,
+            used_blocks,
+            used_inodes,
+        }))?;
+        block_cache_sync_all();
+        Ok(())
+    }
+    /// The quota of current directory, `None` without one
+    pub fn get_quota(&self) -> Option<Quota> {
+        self.fs.lock().quota(self.inode_id as u32)
+    }
+    /// Take the quota of current directory away
+    pub fn remove_quota(&self) -> Result<(), FsError> {
+        let mut fs = self.fs.lock();
+        fs.check_writable()?;
+        fs.set_quota(self.inode_id as u32, None)?;
+        block_cache_sync_all();
+        Ok(())
+    }
+    /// The blocks of current inode and everything under it, and the inodes
+    /// under it, each counted once however many entries name it
+    fn tree_usage(&self, fs: &EasyFileSystem) -> (u32, u32) {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![self.inode_id as u32];
+        seen.insert(self.inode_id as u32);
+        let mut blocks = 0;
+        while let Some(inode_id) = stack.pop() {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .read(block_offset, |disk_inode: &DiskInode| {
+                    blocks += DiskInode::total_blocks(disk_inode.size);
+                    if !disk_inode.is_dir() {
+                        return;
+                    }
+                    let mut dirent = DirEntry::empty();
+                    for i in 0..disk_inode.size as usize / DIRENT_SZ {
+                        disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+                        if !dirent.name().is_empty() && seen.insert(dirent.inode_number()) {
+                            stack.push(dirent.inode_number());
+                        }
+                    }
+                });
+        }
+        (blocks, seen.len() as u32 - 1)
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
     pub fn inode_id(&self) -> u64 {
         self.inode_id as u64
     }
@@ -299,4 +958,8 @@ impl Inode {
             }
         })
     }
+    /// Whether this is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
 }
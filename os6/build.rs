@@ -1,6 +1,189 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::{Entry, Type};
+use xmas_elf::ElfFile;
+
 static TARGET_PATH: &str = "../user/target/riscv64gc-unknown-none-elf/release/";
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    gen_symbols();
+    gen_version();
+}
+
+/// Pass the commit and the date of the build to the kernel as
+/// `KERNEL_GIT_HASH` and `KERNEL_BUILD_DATE`, for `sys_uname`.
+/// `SOURCE_DATE_EPOCH` fixes the date for reproducible builds.
+fn gen_version() {
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/logs/HEAD");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"));
+    let secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    println!("cargo:rustc-env=KERNEL_GIT_HASH={}", hash);
+    println!("cargo:rustc-env=KERNEL_BUILD_DATE={:04}-{:02}-{:02}", year, month, day);
+}
+
+/// The date `days` after 1970-01-01, after Howard Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Set for the build [`gen_symbols`] takes the symbol table from
+const STAGE_ENV: &str = "OS_SYMBOLS_STAGE";
+
+/// Write the `(address, name)` of every function in `.text` of the kernel,
+/// sorted by address, to `symbols.bin` for the panic backtrace, and
+/// `symbols.rs` to put it in the `.symbols` section.
+///
+/// The addresses are those of the kernel being built: it is first built
+/// once more into `OUT_DIR`, with an empty table, and the table is read
+/// from that. `.symbols` lies after `.text`, `.rodata` and `.data`, and
+/// the kernel finds it only through `ssymbols` and `esymbols`, so the
+/// code of both builds is the same. The table keeps the address, length
+/// and hash of the `.text` it was read from, and the kernel ignores a
+/// table that does not match its own.
+fn gen_symbols() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // the table follows every change to the code
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=../easy-fs/src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.cargo");
+    println!("cargo:rerun-if-env-changed={}", STAGE_ENV);
+    let table = if env::var_os(STAGE_ENV).is_some() {
+        Vec::new()
+    } else {
+        let elf_path = build_stage(&out_dir);
+        let data = fs::read(&elf_path).expect("no kernel from the build for the symbol table");
+        symbol_table(&data).expect("no symbol table in the build for it")
+    };
+    fs::write(out_dir.join("symbols.bin"), &table).unwrap();
+    let src = format!(
+        "#[used]\n#[allow(dead_code)]\n#[link_section = \".symbols\"]\n\
+         static SYMBOL_TABLE: [u8; {}] = *include_bytes!(concat!(env!(\"OUT_DIR\"), \"/symbols.bin\"));\n",
+        table.len()
+    );
+    fs::write(out_dir.join("symbols.rs"), src).unwrap();
+}
+
+/// Build the kernel with the same target, profile and features into
+/// `OUT_DIR`, with an empty symbol table, and return the path of its elf
+fn build_stage(out_dir: &Path) -> PathBuf {
+    let target = env::var("TARGET").unwrap();
+    let profile = env::var("PROFILE").unwrap();
+    let features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            let feature = key.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    let target_dir = out_dir.join("stage");
+    let mut cargo = Command::new(env::var("CARGO").unwrap());
+    cargo
+        .current_dir(env::var("CARGO_MANIFEST_DIR").unwrap())
+        .args(["build", "--target", &target, "--features", &features.join(",")])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env(STAGE_ENV, "1");
+    if profile == "release" {
+        cargo.arg("--release");
+    }
+    let status = cargo.status().expect("cannot run cargo for the symbol table");
+    assert!(status.success(), "the build for the symbol table failed");
+    target_dir.join(&target).join(&profile).join("os")
+}
+
+/// The table the panic handler reads, see `SymbolTable` in
+/// `src/lang_items.rs`: the address, length and [`text_hash`] of `.text`,
+/// the number of symbols, that many `(address, name offset, name length)`,
+/// then the names, all numbers little-endian u64
+fn symbol_table(data: &[u8]) -> Option<Vec<u8>> {
+    let elf = ElfFile::new(data).ok()?;
+    let text = elf.find_section_by_name(".text")?;
+    let mut symbols = read_symbols(&elf)?;
+    symbols.sort();
+    symbols.dedup_by_key(|(addr, _)| *addr);
+    let mut table = Vec::new();
+    let mut names = Vec::new();
+    let names_offset = (4 + 3 * symbols.len()) * 8;
+    let header = [
+        text.address(),
+        text.size(),
+        text_hash(text.raw_data(&elf)),
+        symbols.len() as u64,
+    ];
+    for word in header {
+        table.extend_from_slice(&word.to_le_bytes());
+    }
+    for (addr, name) in symbols {
+        let offset = (names_offset + names.len()) as u64;
+        for word in [addr, offset, name.len() as u64] {
+            table.extend_from_slice(&word.to_le_bytes());
+        }
+        names.extend_from_slice(name.as_bytes());
+    }
+    table.extend_from_slice(&names);
+    Some(table)
+}
+
+/// FNV-1a over the whole little-endian u64 words of `text`, as in
+/// `SymbolTable::text_hash` of the kernel
+fn text_hash(text: &[u8]) -> u64 {
+    text.chunks_exact(8).fold(0xcbf29ce484222325, |hash, word| {
+        (hash ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn read_symbols(elf: &ElfFile) -> Option<Vec<(u64, String)>> {
+    let text = elf
+        .section_iter()
+        .position(|section| section.get_name(elf) == Ok(".text"))? as u16;
+    let symtab = elf.find_section_by_name(".symtab")?;
+    let entries = match symtab.get_data(elf).ok()? {
+        SectionData::SymbolTable64(entries) => entries,
+        _ => return None,
+    };
+    Some(
+        entries
+            .iter()
+            .filter(|entry| entry.shndx() == text)
+            .filter(|entry| matches!(entry.get_type(), Ok(Type::Func) | Ok(Type::NoType)))
+            .filter_map(|entry| {
+                let name = entry.get_name(elf).ok()?;
+                // local labels and mapping symbols of the assembler
+                if name.is_empty() || name.starts_with(".L") || name.starts_with('$') {
+                    return None;
+                }
+                Some((entry.value(), format!("{:#}", rustc_demangle::demangle(name))))
+            })
+            .collect(),
+    )
 }
@@ -1,17 +1,100 @@
 //! Constants used in rCore
 
+/// Populated part of a new user stack, the rest of the reserved range is
+/// populated as the stack grows
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Shared memory segments are attached at the first free range from here
+pub const SHM_BASE: usize = 0x6000_0000;
+/// With the `aslr` feature, the user stack, the heap and the shared memory
+/// base move up by a random number of pages below these windows
+pub const ASLR_STACK_WINDOW: usize = 0x100_0000;
+pub const ASLR_HEAP_WINDOW: usize = 0x100_0000;
+pub const ASLR_MMAP_WINDOW: usize = 0x1000_0000;
+/// Virtual range reserved for a user stack
+pub const USER_STACK_LIMIT: usize = 0x10_0000;
+/// How far below sp a fault may land and still grow the stack
+pub const USER_STACK_GROWTH_WINDOW: usize = 0x1_0000;
+/// Page slots of the swap file, easy-fs files are limited to about 8 MiB
+pub const SWAP_SLOTS: usize = 1024;
+/// Swap slots only page faults may fill, so that a swapped out process can
+/// always be brought back in after fork and the like have used up swap
+pub const SWAP_RESERVED_SLOTS: usize = SWAP_SLOTS / 8;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 20;
+/// Kernel stacks of exited processes kept mapped for reuse
+pub const KERNEL_STACK_CACHE: usize = 4;
+/// A hart taking no timer interrupt for this long ran with interrupts off
+/// all along, which the lockup detector reports
+pub const LOCKUP_STALL_MS: usize = 1000;
+/// Timer ticks a task may run without being scheduled out before the
+/// lockup detector reports it, and before it kills it, 0 to never kill
+pub const LOCKUP_TICKS: usize = 500;
+pub const LOCKUP_KILL_TICKS: usize = 0;
+/// How long shutdown waits for the killed tasks to exit before it writes
+/// the disk back anyway
+pub const SHUTDOWN_REAP_MS: usize = 1000;
+/// How long a block may stay dirty in the block cache before a timer tick
+/// writes it back, 0 to leave it to syncs and eviction
+pub const WRITEBACK_INTERVAL_MS: usize = 5000;
+/// Most blocks one writeback pass writes, the rest wait for the next
+pub const WRITEBACK_BATCH: usize = 8;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
+/// Heap bytes syscalls leave to the kernel, they fail with ENOMEM beyond
+pub const KERNEL_HEAP_RESERVE: usize = KERNEL_HEAP_SIZE / 16;
 pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// Most processes alive or unreaped at once, fork and spawn fail beyond it
+pub const MAX_TASKS: usize = 128;
+/// Hard cap of open file descriptors per process
+pub const RLIMIT_FDS_MAX: usize = 1024;
+/// Hard cap of children per process
+pub const RLIMIT_CHILDREN_MAX: usize = 64;
+/// Default and hard cap of user address space per process in pages (128 MiB
+/// and 256 MiB). Pages are populated lazily, so this may exceed memory
+pub const RLIMIT_USER_PAGES_DEFAULT: usize = 32768;
+pub const RLIMIT_USER_PAGES_MAX: usize = 65536;
+/// Longest task name kept, in bytes
+pub const MAX_TASK_NAME_LEN: usize = 32;
+/// Longest path or name string taken from user space, including the NUL
+pub const PATH_MAX: usize = 256;
+/// Largest core file written on a fatal fault, in bytes
+pub const CORE_DUMP_LIMIT: usize = 0x10_0000;
+/// Harts the kernel brings up, any hart id beyond this is left parked.
+/// `entry.asm` reserves one boot stack for each of them
+pub const MAX_HARTS: usize = 4;
 pub const BIG_STRIDE: u64 = 60000;
+/// Smallest priority accepted by `sys_set_priority`
+pub const MIN_PRIORITY: isize = 2;
+/// Largest priority accepted by `sys_set_priority`; any larger value would
+/// give the same stride of 1, so it is rejected instead of silently clamped
+pub const MAX_PRIORITY: isize = BIG_STRIDE as isize;
+/// Timer ticks between two aging rounds of the stride scheduler
+pub const AGING_INTERVAL: usize = 10;
+/// Time slice of a stride task, one timer tick, in microseconds
+pub const STRIDE_SLICE_US: usize = 10_000;
+/// Least share of its stride a task is charged per run, in percent
+pub const STRIDE_MIN_CHARGE_PERCENT: usize = 10;
+/// Whether a task woken from a wait starts from the global minimum pass
+/// even if it is ahead of it, so it runs next rather than after a round
+pub const WAKEUP_BOOST: bool = true;
+/// Priority of the first run after a boost, times the task's own; 1 for no
+/// bump. Decays once the task has run
+pub const WAKEUP_BOOST_PRIORITY_FACTOR: isize = 2;
+/// Least time between two boosts of one task, in microseconds, so a task
+/// sleeping a millisecond over and over cannot take the CPU
+pub const WAKEUP_BOOST_INTERVAL_US: usize = 50_000;
+/// Timer ticks a task may run before the round-robin scheduler preempts it
+pub const RR_TIME_SLICE: usize = 5;
 
+/// User areas lie below this, in the lower half of the Sv39 address space.
+/// Only the trap context and the trampoline are above, in the upper half
+pub const USER_SPACE_END: usize = 1 << 38;
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 pub const CLOCK_FREQ: usize = 12500000;
 pub const MMIO: &[(usize, usize)] = &[
-    (0x10001000, 0x1000),
+    (0x0c00_0000, 0x21_0000), // PLIC
+    (0x1000_0000, 0x1000),    // UART0
+    (0x10001000, 0x1000),     // VIRTIO0
 ];
@@ -0,0 +1,46 @@
+//! Self-tests of the hashes, see [`crate::ktest`]
+
+use super::{sha256, Digest, Sha256};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+fn hex(digest: &Digest) -> String {
+    let mut s = String::new();
+    for byte in digest {
+        write!(s, "{:02x}", byte).unwrap();
+    }
+    s
+}
+
+/// The test vectors of FIPS 180-4, one and two blocks long once padded
+pub fn sha256_vectors() -> Result<(), &'static str> {
+    kassert!(
+        hex(&sha256(b"")) == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        "wrong digest of nothing"
+    );
+    kassert!(
+        hex(&sha256(b"abc")) == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        "wrong digest of abc"
+    );
+    kassert!(
+        hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"))
+            == "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        "wrong digest of two blocks"
+    );
+    Ok(())
+}
+
+/// Data fed in pieces of any length hashes like data fed at once
+pub fn sha256_pieces() -> Result<(), &'static str> {
+    let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let digest = sha256(&data);
+    for piece in [1, 7, 63, 64, 65, 500] {
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(piece) {
+            hasher.update(chunk);
+        }
+        kassert!(hasher.finish() == digest, "digest depends on the pieces");
+    }
+    Ok(())
+}
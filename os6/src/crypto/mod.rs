@@ -0,0 +1,10 @@
+//! Hashes for checking data, not for keeping secrets
+//!
+//! Only SHA-256 for now, to check the packed executables against the
+//! manifest the packer writes, see `crate::fs::verify_exec`.
+
+mod sha256;
+#[cfg(feature = "ktest")]
+pub mod ktest;
+
+pub use sha256::{sha256, Digest, Sha256};
@@ -1,5 +1,7 @@
 mod virtio_blk;
 
+use super::plic;
+
 use lazy_static::*;
 use alloc::sync::Arc;
 use easy_fs::BlockDevice;
@@ -9,6 +11,15 @@ lazy_static! {
     pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
 }
 
+/// Source of VIRTIO0 on the PLIC
+const VIRTIO0_IRQ: u32 = 1;
+
+/// Finish the requests of the block device on its interrupt
+pub fn init() {
+    plic::register(VIRTIO0_IRQ, || BLOCK_DEVICE.handle_irq());
+    plic::enable(VIRTIO0_IRQ, 1);
+}
+
 #[allow(unused)]
 pub fn block_device_test() {
     let block_device = BLOCK_DEVICE.clone();
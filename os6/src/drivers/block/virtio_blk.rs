@@ -1,42 +1,138 @@
+//! Block device on virtio, driven by its interrupt
+//!
+//! A request is queued under the device lock, which is let go while the
+//! device works on it. Requests are told apart by the head of their
+//! descriptor chain, so several may be in flight, from one task batching
+//! blocks or from several tasks, as many as the queue has descriptors for.
+//! If the caller may switch tasks, see [`may_block`], it sleeps until the
+//! interrupt handler finds its request done and wakes it, so other tasks
+//! run meanwhile. Otherwise, like while mounting the filesystem before the
+//! first task runs or under a SpinLock, it polls the device.
+//!
+//! Each request and the time until it is done are charged to the task that
+//! issued it, see [`charge_block_io`]. A write the device reports failed is
+//! told to easy-fs, which puts a spare block in its place; a failed read is
+//! fatal.
+//!
+//! The sleeper holds the easy-fs locks of its operation. Tasks wanting them
+//! make way for it, see [`relax`], but a kernel path holding a SpinLock can
+//! only spin, and with a single hart the sleeper would never run again. So
+//! no such path goes to easy-fs: a fault reads a page of a file mapping or
+//! of swap, swapping writes pages out and shared file mappings are written
+//! back, all with the TCB lock let go.
 
-use virtio_drivers::{VirtIOBlk, VirtIOHeader};
+use virtio_drivers::{BlkResp, Error, RespStatus, VirtIOBlk, VirtIOHeader};
 use crate::mm::{
     PhysAddr,
     VirtAddr,
-    frame_alloc,
-    frame_dealloc,
+    frame_alloc_contiguous,
     PhysPageNum,
-    FrameTracker,
+    FrameRangeTracker,
     PageTable,
-    StepByOne,
     kernel_token,
 };
 use super::BlockDevice;
-use crate::sync::UPSafeCell;
+use easy_fs::BlockError;
+use crate::sync::SpinLock;
+use crate::task::{
+    block_current_and_run_next, charge_block_io, current_task, may_block, relax, touch_watchdog,
+    wakeup_task, TaskControlBlock, TaskStatus,
+};
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
 
 #[allow(unused)]
 const VIRTIO0: usize = 0x10001000;
 
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static>>);
+pub struct VirtIOBlock(SpinLock<VirtIOBlockInner>);
+
+struct VirtIOBlockInner {
+    blk: VirtIOBlk<'static>,
+    /// Requests queued and not yet taken back by their submitter, by token
+    requests: BTreeMap<u16, Request>,
+}
+
+#[derive(Default)]
+struct Request {
+    done: bool,
+    /// The task sleeping until the request is done
+    waiter: Option<Arc<TaskControlBlock>>,
+}
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { 
-        UPSafeCell::new(Vec::new())
-    };
+    static ref QUEUE_FRAMES: SpinLock<Vec<FrameRangeTracker>> = SpinLock::new(Vec::new());
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0.exclusive_access()
-        .read_block(block_id, buf)
-        .expect("Error when reading VirtIOBlk");
+        let mut resp = BlkResp::default();
+        // the device fills `buf` and `resp` until `wait` returns
+        let start = get_time_us();
+        let token = self.submit(|blk| unsafe { blk.read_block_nb(block_id, buf, &mut resp) });
+        self.wait_all(start, &[token], false);
+        assert_eq!(resp.status(), RespStatus::Ok, "Error when reading VirtIOBlk");
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0.exclusive_access()
-        .write_block(block_id, buf)
-        .expect("Error when writing VirtIOBlk");
+        self.try_write_block(block_id, buf).expect("Error when writing VirtIOBlk");
+    }
+    fn read_blocks(&self, start: usize, bufs: &mut [&mut [u8]]) {
+        let mut resps: Vec<BlkResp> = bufs.iter().map(|_| BlkResp::default()).collect();
+        let submitted = get_time_us();
+        let tokens: Vec<u16> = (start..)
+            .zip(bufs.iter_mut().zip(resps.iter_mut()))
+            .map(|(block_id, (buf, resp))| {
+                self.submit(|blk| unsafe { blk.read_block_nb(block_id, buf, resp) })
+            })
+            .collect();
+        self.wait_all(submitted, &tokens, false);
+        for resp in resps.iter() {
+            assert_eq!(resp.status(), RespStatus::Ok, "Error when reading VirtIOBlk");
+        }
+    }
+    fn write_blocks(&self, start: usize, bufs: &[&[u8]]) {
+        self.try_write_blocks(start, bufs).expect("Error when writing VirtIOBlk");
+    }
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut resp = BlkResp::default();
+        let start = get_time_us();
+        let token = self.submit(|blk| unsafe { blk.write_block_nb(block_id, buf, &mut resp) });
+        self.wait_all(start, &[token], true);
+        match resp.status() {
+            RespStatus::Ok => Ok(()),
+            _ => Err(BlockError(block_id)),
+        }
+    }
+    fn try_write_blocks(&self, start: usize, bufs: &[&[u8]]) -> Result<(), BlockError> {
+        let mut resps: Vec<BlkResp> = bufs.iter().map(|_| BlkResp::default()).collect();
+        let submitted = get_time_us();
+        let tokens: Vec<u16> = (start..)
+            .zip(bufs.iter().zip(resps.iter_mut()))
+            .map(|(block_id, (buf, resp))| {
+                self.submit(|blk| unsafe { blk.write_block_nb(block_id, buf, resp) })
+            })
+            .collect();
+        self.wait_all(submitted, &tokens, true);
+        match resps.iter().position(|resp| resp.status() != RespStatus::Ok) {
+            Some(failed) => Err(BlockError(start + failed)),
+            None => Ok(()),
+        }
+    }
+    fn handle_irq(&self) {
+        let mut inner = self.0.exclusive_access();
+        inner.blk.ack_interrupt();
+        let woken = inner.reap();
+        drop(inner);
+        woken.into_iter().for_each(wakeup_task);
+    }
+    fn quiesce(&self) {
+        // a request stays until its submitter takes it back, which
+        // `relax` lets it do
+        while !self.0.exclusive_access().requests.is_empty() {
+            relax();
+        }
     }
 }
 
@@ -44,33 +140,126 @@ impl VirtIOBlock {
     #[allow(unused)]
     pub fn new() -> Self {
         unsafe {
-            Self(UPSafeCell::new(VirtIOBlk::new(
-                &mut *(VIRTIO0 as *mut VirtIOHeader)
-            ).unwrap()))
+            Self(SpinLock::new(VirtIOBlockInner {
+                blk: VirtIOBlk::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
+                requests: BTreeMap::new(),
+            }))
+        }
+    }
+    /// Queue a request with `start`, waiting while the queue is full
+    fn submit(
+        &self,
+        mut start: impl FnMut(&mut VirtIOBlk<'static>) -> virtio_drivers::Result<u16>,
+    ) -> u16 {
+        loop {
+            let mut inner = self.0.exclusive_access();
+            match start(&mut inner.blk) {
+                Ok(token) => {
+                    inner.requests.insert(token, Request::default());
+                    return token;
+                }
+                // no descriptors left until some request is done
+                Err(Error::BufferTooSmall) => {}
+                Err(err) => panic!("Error when queueing to VirtIOBlk: {:?}", err),
+            }
+            drop(inner);
+            relax();
+        }
+    }
+    /// Wait until the requests `tokens`, submitted from `start` on in
+    /// microseconds, are done, and charge them to the current task
+    fn wait_all(&self, start: usize, tokens: &[u16], write: bool) {
+        tokens.iter().for_each(|&token| self.wait(token));
+        charge_block_io(tokens.len(), write, get_time_us() - start);
+    }
+    /// Wait until the request `token` is done and forget it
+    fn wait(&self, token: u16) {
+        let sleep = may_block();
+        loop {
+            // marked blocked before the waiter is registered, so that a
+            // wakeup in between is not lost, and never under the device
+            // lock, which a swapping hart takes holding another TCB
+            let task = if sleep {
+                let task = current_task().unwrap();
+                let mut task_inner = task.inner_exclusive_access();
+                task_inner.task_status = TaskStatus::Blocked;
+                // SIGKILL must wait, the device still writes to our stack
+                task_inner.in_kernel_wait = true;
+                drop(task_inner);
+                Some(task)
+            } else {
+                None
+            };
+            let mut inner = self.0.exclusive_access();
+            let woken = inner.reap();
+            let request = inner.requests.get_mut(&token).unwrap();
+            let done = request.done;
+            if !done {
+                request.waiter = task.clone();
+            } else {
+                inner.requests.remove(&token);
+            }
+            drop(inner);
+            woken.into_iter().for_each(wakeup_task);
+            match task {
+                Some(task) if done => {
+                    let mut task_inner = task.inner_exclusive_access();
+                    task_inner.task_status = TaskStatus::Running;
+                    task_inner.in_kernel_wait = false;
+                }
+                Some(task) => {
+                    drop(task);
+                    block_current_and_run_next();
+                }
+                None => {
+                    // the disk takes its time, we are not stuck
+                    touch_watchdog();
+                    core::hint::spin_loop();
+                }
+            }
+            if done {
+                return;
+            }
         }
     }
 }
 
+impl VirtIOBlockInner {
+    /// Mark the requests the device returned done, handing out their waiters
+    fn reap(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        let mut woken = Vec::new();
+        while let Ok(token) = self.blk.pop_used() {
+            let request = self.requests.get_mut(&token).expect("unknown VirtIOBlk token");
+            request.done = true;
+            woken.extend(request.waiter.take());
+        }
+        woken
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
-    let mut ppn_base = PhysPageNum(0);
-    for i in 0..pages {
-        let frame = frame_alloc().unwrap();
-        if i == 0 { ppn_base = frame.ppn; }
-        assert_eq!(frame.ppn.0, ppn_base.0 + i);
-        QUEUE_FRAMES.exclusive_access().push(frame);
-    }
+    // the queue must be physically contiguous, round up to a whole block
+    let order = pages.next_power_of_two().trailing_zeros() as usize;
+    let frames = frame_alloc_contiguous(order).unwrap();
+    let ppn_base = frames.ppn;
+    QUEUE_FRAMES.exclusive_access().push(frames);
     ppn_base.into()
 }
 
 #[no_mangle]
 pub extern "C" fn virtio_dma_dealloc(pa: PhysAddr, pages: usize) -> i32 {
-    let mut ppn_base: PhysPageNum = pa.into();
-    for _ in 0..pages {
-        frame_dealloc(ppn_base);
-        ppn_base.step();
+    let ppn_base: PhysPageNum = pa.into();
+    let mut queue_frames = QUEUE_FRAMES.exclusive_access();
+    match queue_frames.iter().position(|frames| frames.ppn == ppn_base) {
+        Some(idx) => {
+            assert!(queue_frames[idx].pages() >= pages);
+            // dropping the tracker gives the block back
+            queue_frames.swap_remove(idx);
+            0
+        }
+        None => -1,
     }
-    0
 }
 
 #[no_mangle]
@@ -1,3 +1,29 @@
 mod block;
+mod plic;
+mod uart;
 
 pub use block::BLOCK_DEVICE;
+pub use uart::getchar;
+
+use crate::fs::handle_console_input;
+
+/// Set up the devices and their interrupts, before any hart runs
+/// [`init_interrupts`]
+pub fn init() {
+    block::init();
+    uart::init(handle_console_input);
+}
+
+/// Take device interrupts on the current hart
+pub fn init_interrupts() {
+    plic::init(crate::task::hart_id());
+}
+
+/// Handle the device interrupt pending on the current hart
+pub fn handle_external_interrupt() {
+    // another hart may have claimed it already
+    if let Some(irq) = plic::claim() {
+        plic::dispatch(irq);
+        plic::complete(irq);
+    }
+}
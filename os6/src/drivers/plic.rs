@@ -0,0 +1,109 @@
+//! Platform-Level Interrupt Controller of the QEMU virt machine
+//!
+//! Every hart has a context for M mode and one for S mode. A source raises
+//! an external interrupt in a context that enables it if its priority is
+//! above the context's threshold. The handler claims the source, which
+//! stays masked until the claim is completed.
+//!
+//! Drivers [`register`] a handler for their source and [`enable`] it before
+//! the harts run [`init`]. Handlers run with interrupts disabled, from the
+//! trap of a task in user mode or from the idle loop, never while the
+//! kernel holds a lock or a `UPSafeCell` borrow, so they may take any lock
+//! but must not switch tasks.
+
+use crate::task::hart_id;
+use crate::sync::SpinLock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::ptr;
+use lazy_static::*;
+use riscv::register::sie;
+
+const PLIC_BASE: usize = 0x0c00_0000;
+/// One priority word per source
+const PRIORITY: usize = 0x0;
+/// One bit per source for each context
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+/// Threshold, then the claim and complete register, of each context
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+/// Sources of the virt machine
+const SOURCES: u32 = 96;
+
+type Handler = Arc<dyn Fn() + Send + Sync>;
+
+/// Handlers by source, and the sources enabled for every hart
+struct Sources {
+    handlers: BTreeMap<u32, Handler>,
+    enabled: [u32; SOURCES as usize / 32],
+}
+
+lazy_static! {
+    static ref SOURCES_TABLE: SpinLock<Sources> = SpinLock::new(Sources {
+        handlers: BTreeMap::new(),
+        enabled: [0; SOURCES as usize / 32],
+    });
+}
+
+/// The S mode context of `hart`
+fn supervisor_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (PLIC_BASE + offset) as *mut u32
+}
+
+fn claim_reg(hart: usize) -> *mut u32 {
+    reg(CONTEXT + supervisor_context(hart) * CONTEXT_STRIDE + 4)
+}
+
+/// Call `handler` on interrupts from `irq`
+pub fn register(irq: u32, handler: impl Fn() + Send + Sync + 'static) {
+    SOURCES_TABLE.exclusive_access().handlers.insert(irq, Arc::new(handler));
+}
+
+/// Let `irq` interrupt the harts initialized from now on, with `priority`
+/// above 0
+pub fn enable(irq: u32, priority: u32) {
+    assert!(irq > 0 && irq < SOURCES && priority > 0);
+    unsafe { ptr::write_volatile(reg(PRIORITY + 4 * irq as usize), priority) }
+    SOURCES_TABLE.exclusive_access().enabled[irq as usize / 32] |= 1 << (irq % 32);
+}
+
+/// Take the enabled sources on the S mode of `hart`, the current one
+pub fn init(hart: usize) {
+    let enabled = SOURCES_TABLE.exclusive_access().enabled;
+    let context = supervisor_context(hart);
+    for (word, bits) in enabled.iter().enumerate() {
+        unsafe { ptr::write_volatile(reg(ENABLE + context * ENABLE_STRIDE + 4 * word), *bits) }
+    }
+    unsafe {
+        ptr::write_volatile(reg(CONTEXT + context * CONTEXT_STRIDE), 0);
+        sie::set_sext();
+    }
+}
+
+/// The pending source of highest priority for the current hart, `None` if
+/// another hart claimed it first
+pub fn claim() -> Option<u32> {
+    match unsafe { ptr::read_volatile(claim_reg(hart_id())) } {
+        0 => None,
+        irq => Some(irq),
+    }
+}
+
+/// Call the handler of `irq`, claimed by the current hart
+pub fn dispatch(irq: u32) {
+    let handler = SOURCES_TABLE.exclusive_access().handlers.get(&irq).cloned();
+    match handler {
+        Some(handler) => handler(),
+        None => warn!("[kernel] external interrupt {} has no handler", irq),
+    }
+}
+
+/// Unmask `irq` again after [`claim`]
+pub fn complete(irq: u32) {
+    unsafe { ptr::write_volatile(claim_reg(hart_id()), irq) }
+}
@@ -0,0 +1,91 @@
+//! Receive side of the NS16550A UART of the QEMU virt machine
+//!
+//! Output still goes through SBI. Each byte received raises an external
+//! interrupt, whose handler moves what the UART holds into a ring for the
+//! console to read. A full ring drops the bytes that arrive, keeping those
+//! typed first, the way the console drops input beyond its own limit.
+
+use super::plic;
+use crate::sync::SpinLock;
+use core::ptr;
+
+const UART_BASE: usize = 0x1000_0000;
+/// Source of UART0 on the PLIC
+const UART0_IRQ: u32 = 10;
+/// Receiver buffer
+const RBR: usize = 0;
+/// Interrupt enable
+const IER: usize = 1;
+/// FIFO control
+const FCR: usize = 2;
+/// Modem control
+const MCR: usize = 4;
+/// Line status
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+/// DTR, RTS, and OUT2, which gates the interrupt line
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const LSR_DATA_READY: u8 = 1 << 0;
+
+/// Bytes received and not taken by the console yet
+const INPUT_SIZE: usize = 1024;
+
+struct InputRing {
+    buf: [u8; INPUT_SIZE],
+    head: usize,
+    len: usize,
+}
+
+static INPUT: SpinLock<InputRing> = SpinLock::new(InputRing {
+    buf: [0; INPUT_SIZE],
+    head: 0,
+    len: 0,
+});
+
+fn read_reg(reg: usize) -> u8 {
+    unsafe { ptr::read_volatile((UART_BASE + reg) as *const u8) }
+}
+
+fn write_reg(reg: usize, value: u8) {
+    unsafe { ptr::write_volatile((UART_BASE + reg) as *mut u8, value) }
+}
+
+/// Interrupt on every byte received, keeping the line settings of the
+/// firmware. The handler calls `on_input` once the bytes are in the ring.
+pub fn init(on_input: fn()) {
+    plic::register(UART0_IRQ, move || {
+        receive();
+        on_input();
+    });
+    plic::enable(UART0_IRQ, 1);
+    write_reg(FCR, FCR_FIFO_ENABLE);
+    write_reg(MCR, MCR_DTR_RTS_OUT2);
+    write_reg(IER, IER_RX_AVAILABLE);
+}
+
+/// Move the bytes the UART holds into the ring, which ends the interrupt
+fn receive() {
+    let mut input = INPUT.exclusive_access();
+    while read_reg(LSR) & LSR_DATA_READY != 0 {
+        let c = read_reg(RBR);
+        if input.len < INPUT_SIZE {
+            let tail = (input.head + input.len) % INPUT_SIZE;
+            input.buf[tail] = c;
+            input.len += 1;
+        }
+    }
+}
+
+/// The oldest byte received, if any is left
+pub fn getchar() -> Option<u8> {
+    let mut input = INPUT.exclusive_access();
+    if input.len == 0 {
+        return None;
+    }
+    let c = input.buf[input.head];
+    input.head = (input.head + 1) % INPUT_SIZE;
+    input.len -= 1;
+    Some(c)
+}
@@ -1,12 +1,28 @@
+    # a0 = hartid, each hart gets its own boot stack and keeps its id in tp
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    mv tp, a0
+    addi t0, a0, 1
+    slli t0, t0, 16
+    la sp, boot_stack
+    add sp, sp, t0
     call rust_main
 
+    # entry of the other harts, started by the boot hart through SBI HSM
+    .globl _start_secondary
+_start_secondary:
+    mv tp, a0
+    addi t0, a0, 1
+    slli t0, t0, 16
+    la sp, boot_stack
+    add sp, sp, t0
+    call rust_main_secondary
+
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # 4096 * 16 bytes for each of MAX_HARTS harts
+    .space 4096 * 16 * 4
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
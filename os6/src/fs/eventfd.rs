@@ -0,0 +1,156 @@
+//! Event counters
+//!
+//! An eventfd is a pipe that only says "something happened": a `u64`
+//! counter that writes add to and reads take. Reads and writes move exactly
+//! 8 bytes, a shorter buffer moves nothing. `dup` and fork share the
+//! counter like any open file.
+
+use super::pipe::{wait_on, wakeup_all};
+use super::{File, PollEvents, Stat, StatMode};
+use crate::mm::UserBuffer;
+use crate::sync::SpinLock;
+use crate::task::{block_current_killable, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The counter never gets past this, writers block instead
+const COUNTER_MAX: u64 = u64::MAX - 1;
+
+/// An event counter
+pub struct EventFd {
+    /// Reads take 1 instead of the whole count
+    semaphore: bool,
+    /// `O_NONBLOCK`
+    nonblocking: AtomicBool,
+    inner: SpinLock<EventFdInner>,
+}
+
+struct EventFdInner {
+    count: u64,
+    /// Readers waiting for the counter to leave 0
+    readers: Vec<Arc<TaskControlBlock>>,
+    /// Writers waiting for room in the counter
+    writers: Vec<Arc<TaskControlBlock>>,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, nonblocking: bool) -> Arc<Self> {
+        Arc::new(Self {
+            semaphore,
+            nonblocking: AtomicBool::new(nonblocking),
+            inner: SpinLock::new(EventFdInner {
+                count: initval,
+                readers: Vec::new(),
+                writers: Vec::new(),
+            }),
+        })
+    }
+    /// Take the count, or 1 of it in semaphore mode, blocking while it is 0
+    /// or returning `None` then unless `block`
+    fn read_or_block(&self, buf: UserBuffer, block: bool) -> Option<usize> {
+        if buf.len() < 8 {
+            return Some(0);
+        }
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.count == 0 {
+                if !block {
+                    return None;
+                }
+                wait_on(&mut inner.readers);
+                drop(inner);
+                if !block_current_killable() {
+                    return Some(0);
+                }
+                continue;
+            }
+            let value = if self.semaphore { 1 } else { inner.count };
+            inner.count -= value;
+            let writers = core::mem::take(&mut inner.writers);
+            drop(inner);
+            wakeup_all(writers);
+            for (byte_ref, byte) in buf.into_iter().zip(value.to_ne_bytes().iter()) {
+                unsafe { *byte_ref = *byte; }
+            }
+            return Some(8);
+        }
+    }
+    /// Add the value in `buf`, blocking while the counter has no room for
+    /// it or returning `None` then unless `block`
+    fn write_or_block(&self, buf: UserBuffer, block: bool) -> Option<usize> {
+        if buf.len() < 8 {
+            return Some(0);
+        }
+        let mut bytes = [0u8; 8];
+        for (byte, byte_ref) in bytes.iter_mut().zip(buf.into_iter()) {
+            *byte = unsafe { *byte_ref };
+        }
+        let value = u64::from_ne_bytes(bytes);
+        if value > COUNTER_MAX {
+            return Some(0);
+        }
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.count > COUNTER_MAX - value {
+                if !block {
+                    return None;
+                }
+                wait_on(&mut inner.writers);
+                drop(inner);
+                if !block_current_killable() {
+                    return Some(0);
+                }
+                continue;
+            }
+            inner.count += value;
+            let readers = core::mem::take(&mut inner.readers);
+            drop(inner);
+            wakeup_all(readers);
+            return Some(8);
+        }
+    }
+}
+
+impl File for EventFd {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { true }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.read_or_block(buf, true).unwrap()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.write_or_block(buf, true).unwrap()
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+    fn read_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        self.read_or_block(buf, false)
+    }
+    fn write_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        self.write_or_block(buf, false)
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::empty();
+        ready.set(PollEvents::POLLIN, inner.count > 0);
+        ready.set(PollEvents::POLLOUT, inner.count < COUNTER_MAX);
+        ready & events
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.readers.push(task.clone());
+        inner.writers.push(task.clone());
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.readers.retain(|waiter| !Arc::ptr_eq(waiter, task));
+        inner.writers.retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+    fn fstat(&self) -> Stat {
+        Stat::special(StatMode::NULL, 0)
+    }
+}
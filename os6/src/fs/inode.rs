@@ -1,71 +1,211 @@
 use easy_fs::{
     EasyFileSystem,
+    FsError,
     Inode,
+    BLOCK_SZ,
+    DIRENT_SZ,
+    READ_AHEAD_BLOCKS,
 };
 use crate::drivers::BLOCK_DEVICE;
-use crate::sync::UPSafeCell;
+use crate::sync::Mutex;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::*;
 use bitflags::*;
-use alloc::vec::Vec;
-use super::{File, Stat, StatMode};
-use crate::mm::UserBuffer;
+use super::{DirError, File, SeekError, SeekFrom, Stat, StatMode};
+use crate::mm::{write_back_pages, UserBuffer};
+use crate::timer::{get_realtime_ns, get_time_ms};
+use crate::task::relax;
+
+/// Permission bits that are enforced. There are no users yet, so only the
+/// owner's bits count.
+const MODE_OWNER_W: u16 = 0o200;
+const MODE_OWNER_X: u16 = 0o100;
+
+/// Type of a directory record for a named pipe, as `d_type`
+const DT_FIFO: u8 = 1;
+/// Type of a directory record for a directory
+const DT_DIR: u8 = 4;
+/// Type of a directory record for a regular file
+const DT_REG: u8 = 8;
+/// Bytes before the name in a directory record: the inode number, the
+/// record length and the type
+const DIRENT_HEADER: usize = 11;
+
+/// Bytes fetched ahead of the first sequential read
+const READ_AHEAD_MIN: usize = 2 * BLOCK_SZ;
+/// Most bytes fetched ahead, as many blocks as one batch loads
+const READ_AHEAD_MAX: usize = READ_AHEAD_BLOCKS * BLOCK_SZ;
+/// Reads of this many bytes fetch ahead as much as they can right away,
+/// the reader is clearly going through the file
+const READ_FAST_MIN: usize = 8 * 1024;
 
 /// A wrapper around a filesystem inode
-/// to implement File trait atop
+/// to implement File trait atop.
+/// This is the open file: opening a path makes a new one, while `dup` and
+/// fork share it, offset included. Each read or write takes the offset
+/// and moves it under one lock, so tasks sharing the file never write at
+/// the same offset.
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    inner: UPSafeCell<OSInodeInner>,
+    /// Every write goes to the end of the file, `O_APPEND`
+    append: AtomicBool,
+    /// Whether the file was written through this open file since the
+    /// last sync
+    written: AtomicBool,
+    inner: Mutex<OSInodeInner>,
 }
 
-/// The OS inode inner in 'UPSafeCell'
+/// The OS inode inner in 'Mutex', held across disk requests
 pub struct OSInodeInner {
     offset: usize,
     inode: Arc<Inode>,
+    read_ahead: ReadAhead,
+}
+
+/// How far ahead of the reads of an open file its blocks are fetched
+#[derive(Default)]
+struct ReadAhead {
+    /// Where the last read ended, a read starting there is sequential
+    next: usize,
+    /// Where the blocks fetched ahead end
+    end: usize,
+    /// Bytes to fetch ahead, doubling on each sequential read and back to
+    /// none on a jump
+    window: usize,
+}
+
+impl OSInodeInner {
+    /// After a read of `len` bytes at `offset`, fetch the blocks the next
+    /// sequential reads need with one batched request, once less than
+    /// half of the window is left fetched
+    fn read_ahead(&mut self, offset: usize, len: usize) {
+        let ahead = &mut self.read_ahead;
+        if offset != ahead.next || len == 0 {
+            *ahead = ReadAhead {
+                next: offset + len,
+                ..ReadAhead::default()
+            };
+            return;
+        }
+        ahead.next = offset + len;
+        ahead.window = if len >= READ_FAST_MIN {
+            READ_AHEAD_MAX
+        } else {
+            (ahead.window * 2).max(READ_AHEAD_MIN).min(READ_AHEAD_MAX)
+        };
+        if ahead.end < ahead.next + ahead.window / 2 {
+            let start = ahead.end.max(ahead.next);
+            let end = ahead.next + ahead.window;
+            ahead.end = end;
+            self.inode.read_ahead(start, end - start);
+        }
+    }
+}
+
+/// Live open files of inodes, see `/proc/files`
+static OPEN_INODES: AtomicUsize = AtomicUsize::new(0);
+
+/// Open files of inodes alive, however many descriptors share each
+pub fn open_inodes() -> usize {
+    OPEN_INODES.load(Ordering::Relaxed)
 }
 
 impl OSInode {
+    /// Policy: sync a written file when its last descriptor is closed, so
+    /// that what a process wrote is on the disk once it exits
+    pub const SYNC_ON_CLOSE: bool = true;
     /// Construct an OS inode from a inode
     pub fn new(
         readable: bool,
         writable: bool,
+        append: bool,
         inode: Arc<Inode>,
     ) -> Self {
+        OPEN_INODES.fetch_add(1, Ordering::Relaxed);
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner {
+            append: AtomicBool::new(append),
+            written: AtomicBool::new(false),
+            inner: Mutex::new(OSInodeInner {
                 offset: 0,
                 inode,
-            })},
+                read_ahead: ReadAhead::default(),
+            }),
         }
     }
-    /// Read all data inside a inode into vector
-    pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.exclusive_access();
-        let mut buffer = [0u8; 512];
-        let mut v: Vec<u8> = Vec::new();
-        loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
-            if len == 0 {
-                break;
-            }
-            inner.offset += len;
-            v.extend_from_slice(&buffer[..len]);
+}
+
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        OPEN_INODES.fetch_sub(1, Ordering::Relaxed);
+        if Self::SYNC_ON_CLOSE && self.written.load(Ordering::Relaxed) {
+            self.inner.lock().inode.sync();
         }
-        v
     }
 }
 
 lazy_static! {
     /// The root of all inodes, or '/' in short
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        easy_fs::set_clock(|| (get_realtime_ns() / 1_000_000_000).max(0) as u32);
+        easy_fs::set_ticks(|| get_time_ms() as u64);
+        easy_fs::set_relax(relax);
+        easy_fs::set_log(|args| warn!("[kernel] {}", args));
+        let efs = match EasyFileSystem::open(BLOCK_DEVICE.clone(), cfg!(feature = "ro-root")) {
+            Ok(efs) => efs,
+            Err(err) => panic!("[kernel] cannot mount the filesystem: {}", err),
+        };
+        info!(
+            "[kernel] easy-fs version {}, features {:#x} {:#x}",
+            efs.lock().version(),
+            efs.lock().compat_features(),
+            efs.lock().incompat_features()
+        );
+        // every fix fsck knows is safe, so it always repairs unless mounted
+        // read-only; what it cannot fix leaves the filesystem read-only
+        let report = efs.lock().fsck(true);
+        if report.problems() > 0 {
+            print!("[kernel] {}", report);
+            if !report.is_consistent() {
+                println!("[kernel] filesystem is read-only");
+            }
+        }
+        if efs.lock().is_mounted_read_only() {
+            info!("[kernel] root filesystem mounted read-only");
+        }
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
 
+/// Write every cached block back to the disk, after the pages of shared
+/// file mappings queued for it
+pub fn sync_all() {
+    write_back_pages();
+    ROOT_INODE.sync_all();
+}
+
+/// Whether a write to the root filesystem was lost for want of a spare
+/// block, it refuses changes since
+pub fn fs_failed() -> bool {
+    ROOT_INODE.has_failed()
+}
+
+/// Snapshot the root filesystem, returns the blocks copied
+pub fn snapshot_fs() -> Result<u32, FsError> {
+    ROOT_INODE.snapshot_fs()
+}
+
+/// Restore the snapshot of the root filesystem, returns the blocks copied.
+/// The files open on it are stale afterwards if their inode was freed or
+/// replaced, see [`File::stale`].
+pub fn restore_fs() -> Result<u32, FsError> {
+    ROOT_INODE.restore_fs()
+}
+
 /// List all files in the filesystems
 pub fn list_apps() {
     println!("/**** APPS ****");
@@ -83,6 +223,12 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const APPEND = 1 << 11;
+        /// Reads and writes fail with EAGAIN rather than block
+        const NONBLOCK = 1 << 12;
+        /// `FD_CLOEXEC` on the new descriptor from the start, also taken
+        /// by `sys_pipe2` and `sys_dup3`
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -91,7 +237,7 @@ impl OpenFlags {
     /// does not check validity for simplicity
     /// returns (readable, writable)
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        if (*self & (Self::WRONLY | Self::RDWR)).is_empty() {
             (true, false)
         } else if self.contains(Self::WRONLY) {
             (false, true)
@@ -101,90 +247,406 @@ impl OpenFlags {
     }
 }
 
-/// Open a file by path
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(
-                readable,
-                writable,
-                inode,
-            )))
-        } else {
-            // create file
-            ROOT_INODE.create(name)
-                .map(|inode| {
-                    Arc::new(OSInode::new(
-                        readable,
-                        writable,
-                        inode,
-                    ))
-                })
-        }
+/// The inode at `path`, relative to `base` unless it starts with `/`
+fn lookup(base: &Arc<Inode>, path: &str) -> Option<Arc<Inode>> {
+    let mut inode = if path.starts_with('/') {
+        ROOT_INODE.clone()
     } else {
-        ROOT_INODE.find(name)
-            .map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(OSInode::new(
-                    readable,
-                    writable,
-                    inode
-                ))
-            })
+        base.clone()
+    };
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        inode = inode.find(name)?;
+    }
+    Some(inode)
+}
+
+/// Why an operation on a path failed
+#[derive(Debug)]
+pub enum PathError {
+    /// The directory holding the last name does not exist or is a file
+    NoParent,
+    /// The last name does not exist, or there is none, like in `/`
+    NotFound,
+    /// The new name is taken
+    Exists,
+    /// The new name does not fit in a directory entry
+    NameTooLong,
+    /// A directory where a file is expected
+    IsDir,
+    /// A file where a directory is expected
+    NotDir,
+    /// The directory to remove still has entries
+    NotEmpty,
+    /// A hard link between two filesystems
+    CrossDevice,
+    /// A named pipe opened to write without blocking, but not to read
+    NoReader,
+    /// The permission bits do not allow the access
+    Access,
+    /// The kernel heap is out of memory
+    NoMemory,
+    /// A change to a filesystem that a boot check left read-only
+    ReadOnly,
+    /// The filesystem cannot make this kind of file
+    NotSupported,
+    /// A quota over the directory does not allow more blocks or inodes
+    QuotaExceeded,
+    /// The filesystem has no room for what the change needs
+    NoSpace,
+    /// A write past the largest file the filesystem holds
+    FileTooLarge,
+    /// A write to the disk was lost, the filesystem refuses changes since
+    Io,
+    /// A blocking open cut short by SIGKILL
+    Interrupted,
+}
+
+impl From<FsError> for PathError {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::NameTooLong => PathError::NameTooLong,
+            FsError::EmptyName | FsError::NotFound => PathError::NotFound,
+            FsError::Exists => PathError::Exists,
+            FsError::NotDir => PathError::NotDir,
+            FsError::IsDir => PathError::IsDir,
+            FsError::CrossDevice => PathError::CrossDevice,
+            FsError::ReadOnly => PathError::ReadOnly,
+            FsError::QuotaExceeded => PathError::QuotaExceeded,
+            FsError::TooManyQuotas | FsError::SnapshotTooLarge => PathError::NoSpace,
+            FsError::NoSnapshot => PathError::NotFound,
+            FsError::FileTooLarge => PathError::FileTooLarge,
+            FsError::Io => PathError::Io,
+            FsError::NoFifos
+            | FsError::BadMagic
+            | FsError::Unsupported { .. }
+            | FsError::BadBlock(_) => PathError::NotSupported,
+        }
+    }
+}
+
+/// Fails with [`PathError::ReadOnly`] if `inode` is on a read-only
+/// filesystem
+fn check_writable(inode: &Inode) -> Result<(), PathError> {
+    if inode.read_only() {
+        return Err(PathError::ReadOnly);
+    }
+    Ok(())
+}
+
+/// The directory holding `path` and the last name in it
+fn lookup_parent<'a>(
+    base: &Arc<Inode>,
+    path: &'a str,
+) -> Result<(Arc<Inode>, &'a str), PathError> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        Some(i) => (&path[..=i], &path[i + 1..]),
+        None => ("", path),
+    };
+    if name.is_empty() {
+        return Err(PathError::NotFound);
+    }
+    match lookup(base, dir) {
+        Some(dir) if dir.is_dir() => Ok((dir, name)),
+        _ => Err(PathError::NoParent),
+    }
+}
+
+/// Open a file by path, relative to the root. An existing file opened
+/// writable is emptied with `TRUNC`, or with `CREATE` unless it is opened
+/// to `APPEND` to. A directory can only be opened to read its entries,
+/// opening it writable fails with [`PathError::IsDir`]. Opening a file
+/// writable that lacks the write bit fails with [`PathError::Access`],
+/// before anything is emptied, and on a read-only filesystem with
+/// [`PathError::ReadOnly`]. A named pipe is
+/// opened like an empty file, `sys_open` turns it into a pipe end.
+pub fn open_file(path: &str, flags: OpenFlags) -> Result<Arc<OSInode>, PathError> {
+    let (readable, writable) = flags.read_write();
+    if path.is_empty() {
+        return Err(PathError::NotFound);
     }
+    let inode = match lookup(&ROOT_INODE, path) {
+        Some(inode) if inode.is_dir() => {
+            if writable {
+                return Err(PathError::IsDir);
+            }
+            inode
+        }
+        Some(inode) => {
+            if writable && inode.mode() & MODE_OWNER_W == 0 {
+                return Err(PathError::Access);
+            }
+            if writable {
+                check_writable(&inode)?;
+            }
+            if writable
+                && (flags.contains(OpenFlags::TRUNC)
+                    || flags.contains(OpenFlags::CREATE) && !flags.contains(OpenFlags::APPEND))
+            {
+                inode.truncate(0);
+            }
+            inode
+        }
+        None if flags.contains(OpenFlags::CREATE) => {
+            let (dir, name) = lookup_parent(&ROOT_INODE, path)?;
+            check_writable(&dir)?;
+            dir.create(name)?
+        }
+        None => return Err(PathError::NotFound),
+    };
+    Ok(Arc::new(OSInode::new(
+        readable,
+        writable,
+        flags.contains(OpenFlags::APPEND),
+        inode,
+    )))
 }
 
 impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
+    /// All the pieces of the buffer in one pass over the inode, see
+    /// `sys_readv`. Sequential reads find their blocks fetched ahead.
     fn read(&self, mut buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
-        total_read_size
+        let mut inner = self.inner.lock();
+        let offset = inner.offset;
+        let read_size = inner.inode.read_at_vectored(offset, &mut buf.buffers);
+        inner.offset += read_size;
+        inner.read_ahead(offset, read_size);
+        read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
+        self.try_write(buf).unwrap_or(0)
+    }
+    /// Short of the buffer past the end of the file when its quotas do not
+    /// allow all of it, see [`Inode::try_write_at_vectored`]
+    fn try_write(&self, buf: UserBuffer) -> Result<usize, FsError> {
+        let mut inner = self.inner.lock();
+        self.written.store(true, Ordering::Relaxed);
+        if self.append() {
+            // a single append, so that other writers cannot get in between
+            // the pages of the buffer
+            let data: Vec<u8> = buf.buffers.iter().flat_map(|slice| slice.iter().copied()).collect();
+            inner.offset = inner.inode.try_append(&data)?;
+            return Ok(data.len());
+        }
+        let slices: Vec<&[u8]> = buf.buffers.iter().map(|slice| &**slice).collect();
+        let write_size = inner.inode.try_write_at_vectored(inner.offset, &slices)?;
+        inner.offset += write_size;
+        Ok(write_size)
+    }
+    fn append(&self) -> bool {
+        self.append.load(Ordering::Relaxed)
+    }
+    fn set_append(&self, append: bool) {
+        self.append.store(append, Ordering::Relaxed);
+    }
+    /// The offset of a directory is the byte offset of its next entry on
+    /// disk, see [`File::getdents`], and must fall between two entries. No
+    /// offset goes past `u32::MAX`, the largest size of an inode.
+    fn seek(&self, pos: SeekFrom) -> Result<usize, SeekError> {
+        let mut inner = self.inner.lock();
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (0, offset as isize),
+            SeekFrom::Current(delta) => (inner.offset, delta),
+            SeekFrom::End(delta) => (inner.inode.size(), delta),
+        };
+        let offset = (base as isize)
+            .checked_add(delta)
+            .and_then(|offset| u32::try_from(offset).ok())
+            .ok_or(SeekError::Invalid)? as usize;
+        if inner.inode.is_dir() && offset % DIRENT_SZ != 0 {
+            return Err(SeekError::Invalid);
         }
-        total_write_size
+        inner.offset = offset;
+        Ok(inner.offset)
+    }
+    fn stale(&self) -> bool {
+        self.inner.lock().inode.is_stale()
+    }
+    fn sync(&self) {
+        let inner = self.inner.lock();
+        self.written.store(false, Ordering::Relaxed);
+        inner.inode.sync();
     }
     fn fstat(&self) -> Stat {
-        let mut inner = self.inner.exclusive_access();
+        let inner = self.inner.lock();
+        let stat = inner.inode.stat();
         Stat {
-            dev:0,
-            ino: inner.inode.inode_id(),
-            mode: match inner.inode.is_dir() {
-                true => StatMode::DIR,
-                false => StatMode::FILE
+            ino: stat.ino,
+            mode: match (stat.is_dir, stat.is_fifo) {
+                (true, _) => StatMode::DIR,
+                (false, true) => StatMode::FIFO,
+                (false, false) => StatMode::FILE
             },
-            nlink:inner.inode.nlink(),
-            pad:[0;7],
+            nlink: stat.nlink,
+            atime: stat.atime,
+            mtime: stat.mtime,
+            ctime: stat.ctime,
+            perm: stat.mode as u32,
+            ..Stat::special(StatMode::NULL, stat.size)
+        }
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        let inner = self.inner.lock();
+        if inner.inode.is_dir() {
+            None
+        } else {
+            Some(Arc::clone(&inner.inode))
         }
     }
+    fn dir(&self) -> Option<Arc<Inode>> {
+        let inner = self.inner.lock();
+        if inner.inode.is_dir() {
+            Some(Arc::clone(&inner.inode))
+        } else {
+            None
+        }
+    }
+    /// The offset of a directory is the byte offset of its next entry on
+    /// disk, the slot times [`DIRENT_SZ`]. Removed entries keep their
+    /// slots, so a saved offset resumes where it was taken.
+    fn getdents(&self, buf: &mut [u8]) -> Result<usize, DirError> {
+        let mut inner = self.inner.lock();
+        if !inner.inode.is_dir() {
+            return Err(DirError::NotDir);
+        }
+        let mut written = 0;
+        while let Some((slot, entry)) = inner.inode.read_dirent(inner.offset / DIRENT_SZ) {
+            let name = entry.name.as_bytes();
+            // the name is NUL-terminated, and the next record 8-byte aligned
+            let reclen = (DIRENT_HEADER + name.len() + 1 + 7) & !7;
+            if written + reclen > buf.len() {
+                if written == 0 {
+                    return Err(DirError::BufferTooSmall);
+                }
+                break;
+            }
+            let record = &mut buf[written..written + reclen];
+            record.fill(0);
+            record[..8].copy_from_slice(&entry.ino.to_le_bytes());
+            record[8..10].copy_from_slice(&(reclen as u16).to_le_bytes());
+            record[10] = match (entry.is_dir, entry.is_fifo) {
+                (true, _) => DT_DIR,
+                (false, true) => DT_FIFO,
+                (false, false) => DT_REG,
+            };
+            record[DIRENT_HEADER..DIRENT_HEADER + name.len()].copy_from_slice(name);
+            written += reclen;
+            inner.offset = (slot + 1) * DIRENT_SZ;
+        }
+        Ok(written)
+    }
+}
+
+/// Set the permission bits of the file or directory at `path`
+pub fn chmod_file(base: &Arc<Inode>, path: &str, mode: u16) -> Result<(), PathError> {
+    if path.is_empty() {
+        return Err(PathError::NotFound);
+    }
+    let inode = lookup(base, path).ok_or(PathError::NotFound)?;
+    check_writable(&inode)?;
+    inode.set_mode(mode);
+    Ok(())
+}
+
+/// The regular file at `path` to run, failing with [`PathError::Access`]
+/// unless it is one with the execute bit
+pub fn open_exec(path: &str) -> Result<Arc<Inode>, PathError> {
+    let inode = open_file(path, OpenFlags::RDONLY)?
+        .inode()
+        .ok_or(PathError::Access)?;
+    if inode.is_fifo() || inode.mode() & MODE_OWNER_X == 0 {
+        return Err(PathError::Access);
+    }
+    Ok(inode)
 }
 
-pub fn link_file(oldname: &str, newname: &str) -> isize {
-    ROOT_INODE.link(oldname, newname)
+/// Create a directory at `path`. Like all the paths below, `path` is
+/// relative to `base` unless it starts with `/`.
+pub fn make_dir(base: &Arc<Inode>, path: &str) -> Result<(), PathError> {
+    let (dir, name) = lookup_parent(base, path)?;
+    if dir.find(name).is_some() {
+        return Err(PathError::Exists);
+    }
+    check_writable(&dir)?;
+    dir.create_dir(name)?;
+    Ok(())
 }
 
-pub fn unlink_file(name: &str) -> isize {
-    ROOT_INODE.unlink(name)
+/// Create a named pipe at `path`
+pub fn make_fifo(base: &Arc<Inode>, path: &str) -> Result<(), PathError> {
+    let (dir, name) = lookup_parent(base, path)?;
+    if dir.find(name).is_some() {
+        return Err(PathError::Exists);
+    }
+    check_writable(&dir)?;
+    dir.create_fifo(name)?;
+    Ok(())
+}
+
+/// Create `name` in the root directory, or empty it if it exists, for the
+/// kernel itself to write
+pub fn create_kernel_file(name: &str) -> Option<Arc<Inode>> {
+    if let Some(inode) = ROOT_INODE.find(name) {
+        inode.clear();
+        Some(inode)
+    } else {
+        ROOT_INODE.create(name).ok()
+    }
+}
+
+/// Open `name` in the root directory, creating it if it does not exist,
+/// for the kernel itself to use
+pub fn open_kernel_file(name: &str) -> Option<Arc<Inode>> {
+    ROOT_INODE.find(name).or_else(|| ROOT_INODE.create(name).ok())
+}
+
+/// Link the file at `old_path` as `new_path`, which may be in another
+/// directory of the same filesystem
+pub fn link_file(
+    old_base: &Arc<Inode>,
+    old_path: &str,
+    new_base: &Arc<Inode>,
+    new_path: &str,
+) -> Result<(), PathError> {
+    let (old_dir, old_name) = lookup_parent(old_base, old_path)?;
+    let inode = old_dir.find(old_name).ok_or(PathError::NotFound)?;
+    if inode.is_dir() {
+        return Err(PathError::IsDir);
+    }
+    let (new_dir, new_name) = lookup_parent(new_base, new_path)?;
+    if !new_dir.same_fs(&inode) {
+        return Err(PathError::CrossDevice);
+    }
+    if new_dir.find(new_name).is_some() {
+        return Err(PathError::Exists);
+    }
+    check_writable(&new_dir)?;
+    new_dir.add_link(new_name, &inode)?;
+    Ok(())
+}
+
+/// Remove the file at `path`. Directories are only removed by
+/// [`remove_dir`], which makes sure no entries are lost.
+pub fn unlink_file(base: &Arc<Inode>, path: &str) -> Result<(), PathError> {
+    let (dir, name) = lookup_parent(base, path)?;
+    check_writable(&dir)?;
+    match dir.find(name) {
+        Some(inode) if inode.is_dir() => Err(PathError::IsDir),
+        Some(_) if dir.unlink(name) == 0 => Ok(()),
+        _ => Err(PathError::NotFound),
+    }
+}
+
+/// Remove the empty directory at `path`
+pub fn remove_dir(base: &Arc<Inode>, path: &str) -> Result<(), PathError> {
+    let (dir, name) = lookup_parent(base, path)?;
+    check_writable(&dir)?;
+    match dir.find(name) {
+        Some(inode) if !inode.is_dir() => Err(PathError::NotDir),
+        Some(_) if dir.remove_dir(name) == 0 => Ok(()),
+        Some(_) => Err(PathError::NotEmpty),
+        None => Err(PathError::NotFound),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+//! Self-tests of the filesystem on the real image, see [`crate::ktest`]
+//!
+//! They work in a scratch directory in the root and remove it again. On a
+//! read-only root they have nothing to do and pass.
+
+use super::manifest::{check_exec, Manifest};
+use super::{VerifyError, ROOT_INODE};
+use crate::crypto::sha256;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use easy_fs::{block_cache_stats, Inode, BLOCK_SZ};
+
+/// The scratch directory, in the root
+const SCRATCH: &str = "ktest.tmp";
+/// The file the tests write, in the scratch directory
+const FILE: &str = "file";
+
+/// Remove the scratch directory with its file, freeing their blocks
+fn remove_scratch() -> Result<(), &'static str> {
+    if let Some(dir) = ROOT_INODE.find(SCRATCH) {
+        if let Some(file) = dir.find(FILE) {
+            file.clear();
+            kassert!(dir.unlink(FILE) == 0, "cannot unlink the file");
+        }
+    }
+    kassert!(ROOT_INODE.remove_dir(SCRATCH) == 0, "cannot remove the scratch directory");
+    Ok(())
+}
+
+/// Create the scratch directory and the file in it, after removing what a
+/// run that failed half-way left behind
+fn create_scratch() -> Result<Arc<Inode>, &'static str> {
+    if ROOT_INODE.find(SCRATCH).is_some() {
+        remove_scratch()?;
+    }
+    let dir = ROOT_INODE.create_dir(SCRATCH).or(Err("cannot create the scratch directory"))?;
+    dir.create(FILE).or(Err("cannot create the file"))
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// A file created in the scratch directory reads back what was written and
+/// is gone once unlinked. Its data blocks and those of the directory are
+/// free again afterwards; inodes are not checked, unlinking leaves them to
+/// fsck.
+pub fn file_roundtrip() -> Result<(), &'static str> {
+    if ROOT_INODE.read_only() {
+        return Ok(());
+    }
+    let free = ROOT_INODE.stat_fs().free_data_blocks;
+    let file = create_scratch()?;
+    let data = pattern(10 * BLOCK_SZ + 7);
+    kassert!(file.write_at(0, &data) == data.len(), "short write");
+    kassert!(file.size() == data.len(), "wrong size after writing");
+    let mut buf = alloc::vec![0u8; data.len() + 100];
+    kassert!(file.read_at(0, &mut buf) == data.len(), "short read");
+    kassert!(buf[..data.len()] == data[..], "read back other data");
+    kassert!(file.read_at(data.len(), &mut buf) == 0, "read past the end");
+    let dir = ROOT_INODE.find(SCRATCH).ok_or("scratch directory not found")?;
+    let found = dir.find(FILE).ok_or("file not found")?;
+    kassert!(found.inode_id() == file.inode_id(), "file found as another inode");
+    drop(found);
+    drop(file);
+    remove_scratch()?;
+    kassert!(dir.find(FILE).is_none(), "file found after unlinking");
+    kassert!(ROOT_INODE.find(SCRATCH).is_none(), "scratch directory found after removing");
+    kassert!(ROOT_INODE.stat_fs().free_data_blocks == free, "data blocks not freed");
+    Ok(())
+}
+
+/// Reading a file larger than the block cache evicts blocks rather than
+/// growing the cache past its capacity
+pub fn block_cache_bounded() -> Result<(), &'static str> {
+    if ROOT_INODE.read_only() {
+        return Ok(());
+    }
+    let capacity = block_cache_stats().capacity;
+    let file = create_scratch()?;
+    let data = pattern(4 * capacity * BLOCK_SZ);
+    kassert!(file.write_at(0, &data) == data.len(), "short write");
+    let misses = block_cache_stats().misses;
+    let mut buf = [0u8; BLOCK_SZ];
+    for offset in (0..data.len()).step_by(BLOCK_SZ) {
+        kassert!(file.read_at(offset, &mut buf) == BLOCK_SZ, "short read");
+        kassert!(buf[..] == data[offset..offset + BLOCK_SZ], "read back other data");
+        kassert!(block_cache_stats().cached <= capacity, "cache grew past its capacity");
+    }
+    // the last blocks written are the only ones of the file still cached
+    kassert!(
+        block_cache_stats().misses - misses >= 3 * capacity,
+        "blocks read without going to the device"
+    );
+    drop(file);
+    remove_scratch()
+}
+
+/// A manifest line for `data` as the file in the scratch directory
+fn manifest_line(data: &[u8]) -> String {
+    let mut line = format!("{} {} ", FILE, data.len());
+    for byte in sha256(data) {
+        write!(line, "{:02x}", byte).unwrap();
+    }
+    line.push('\n');
+    line
+}
+
+/// A file matching its manifest line may run and one with a byte flipped
+/// may not. A file packed with the manifest but not listed may not run
+/// either, one created since may, and so may anything without a manifest.
+pub fn manifest_check() -> Result<(), &'static str> {
+    if ROOT_INODE.read_only() {
+        return Ok(());
+    }
+    let file = create_scratch()?;
+    let data = pattern(3 * BLOCK_SZ + 5);
+    kassert!(file.write_at(0, &data) == data.len(), "short write");
+    let dir = ROOT_INODE.find(SCRATCH).ok_or("scratch directory not found")?;
+    // a manifest written after the file
+    let ino = file.inode_id() + 1;
+    let manifest = Manifest::parse(&manifest_line(&data), &dir, ino).ok_or("manifest does not parse")?;
+    kassert!(check_exec(Some(&manifest), FILE, &file).is_ok(), "matching file refused");
+    file.write_at(BLOCK_SZ, &[data[BLOCK_SZ] ^ 1]);
+    kassert!(
+        check_exec(Some(&manifest), FILE, &file) == Err(VerifyError::Mismatch),
+        "flipped byte not found"
+    );
+    kassert!(check_exec(None, FILE, &file).is_ok(), "refused without a manifest");
+    file.write_at(BLOCK_SZ, &data[BLOCK_SZ..BLOCK_SZ + 1]);
+    kassert!(check_exec(Some(&manifest), FILE, &file).is_ok(), "restored file refused");
+    file.write_at(data.len(), b"tail");
+    kassert!(
+        check_exec(Some(&manifest), FILE, &file) == Err(VerifyError::Mismatch),
+        "longer file not found"
+    );
+    let unlisted = Manifest::parse("", &dir, ino).ok_or("empty manifest does not parse")?;
+    kassert!(
+        check_exec(Some(&unlisted), FILE, &file) == Err(VerifyError::Unlisted),
+        "unlisted packed file not found"
+    );
+    let older = Manifest::parse("", &dir, 0).ok_or("empty manifest does not parse")?;
+    kassert!(check_exec(Some(&older), FILE, &file).is_ok(), "file created since refused");
+    kassert!(Manifest::parse("file 12 xyz\n", &dir, ino).is_none(), "malformed manifest parsed");
+    drop(file);
+    remove_scratch()
+}
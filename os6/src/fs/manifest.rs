@@ -0,0 +1,147 @@
+//! The integrity manifest of the packed executables
+//!
+//! The packer writes `/manifest` last, one line `name size sha256` for each
+//! executable it packed in the root, the digest in hex. With the
+//! `verify-exec` feature, exec and spawn hash the whole file before running
+//! it and refuse one that differs from its line. A file the manifest does
+//! not list is refused if it was packed with it, which its inode being
+//! older than the manifest's tells, and runs otherwise: files created
+//! since are not checked. Without a manifest, or with one that does not
+//! parse, everything runs after a warning at the first exec.
+//!
+//! The manifest is read once, so changing it needs a reboot.
+
+use super::ROOT_INODE;
+use crate::crypto::{Digest, Sha256};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::{Inode, BLOCK_SZ};
+use lazy_static::*;
+
+/// Name of the manifest in the root
+const MANIFEST_NAME: &str = "manifest";
+
+/// Why an executable may not run, see [`verify_exec`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Packed with the manifest but not listed in it
+    Unlisted,
+    /// The size or the digest differs from the manifest
+    Mismatch,
+}
+
+/// The executables listed in a manifest
+pub struct Manifest {
+    /// Size and digest of each, by inode
+    entries: BTreeMap<u64, (usize, Digest)>,
+    /// Inode of the manifest, those before it were packed with it
+    ino: u64,
+}
+
+impl Manifest {
+    /// Parse the lines of a manifest `ino` listing files in `dir`. Names
+    /// no longer in `dir` are skipped, `None` if a line is malformed.
+    pub fn parse(text: &str, dir: &Inode, ino: u64) -> Option<Self> {
+        let mut entries = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let mut fields = line.split(' ');
+            let (name, size, digest) = (fields.next()?, fields.next()?, fields.next()?);
+            if fields.next().is_some() {
+                return None;
+            }
+            let size = size.parse().ok()?;
+            let digest = parse_digest(digest)?;
+            if let Some(inode) = dir.find(name) {
+                entries.insert(inode.inode_id(), (size, digest));
+            }
+        }
+        Some(Self { entries, ino })
+    }
+    /// Whether the executable `inode` may run
+    pub fn check(&self, inode: &Inode) -> Result<(), VerifyError> {
+        match self.entries.get(&inode.inode_id()) {
+            Some(&(size, digest)) => {
+                if inode.size() == size && hash_file(inode) == digest {
+                    Ok(())
+                } else {
+                    Err(VerifyError::Mismatch)
+                }
+            }
+            None if inode.inode_id() < self.ino => Err(VerifyError::Unlisted),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A digest in 64 hex digits
+fn parse_digest(hex: &str) -> Option<Digest> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// The SHA-256 digest of the whole of `inode`
+fn hash_file(inode: &Inode) -> Digest {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 8 * BLOCK_SZ];
+    let mut offset = 0;
+    loop {
+        let len = inode.read_at(offset, &mut buf);
+        if len == 0 {
+            return hasher.finish();
+        }
+        hasher.update(&buf[..len]);
+        offset += len;
+    }
+}
+
+/// Read and parse `/manifest`, warning when there is none to check against
+fn load() -> Option<Manifest> {
+    let inode = match ROOT_INODE.find(MANIFEST_NAME) {
+        Some(inode) => inode,
+        None => {
+            warn!("[kernel] no /{}, executables run unchecked", MANIFEST_NAME);
+            return None;
+        }
+    };
+    let mut data: Vec<u8> = vec![0u8; inode.size()];
+    let len = inode.read_at(0, &mut data);
+    data.truncate(len);
+    let manifest = String::from_utf8(data)
+        .ok()
+        .and_then(|text| Manifest::parse(&text, &ROOT_INODE, inode.inode_id()));
+    if manifest.is_none() {
+        warn!("[kernel] malformed /{}, executables run unchecked", MANIFEST_NAME);
+    }
+    manifest
+}
+
+lazy_static! {
+    /// The manifest of the root, read at the first exec
+    static ref MANIFEST: Option<Manifest> = load();
+}
+
+/// Whether the executable `inode` opened at `path` may run, always with
+/// the `verify-exec` feature off
+pub fn verify_exec(path: &str, inode: &Inode) -> Result<(), VerifyError> {
+    if !cfg!(feature = "verify-exec") {
+        return Ok(());
+    }
+    check_exec(MANIFEST.as_ref(), path, inode)
+}
+
+/// [`verify_exec`] against `manifest`, everything runs without one
+pub fn check_exec(manifest: Option<&Manifest>, path: &str, inode: &Inode) -> Result<(), VerifyError> {
+    let result = manifest.map_or(Ok(()), |manifest| manifest.check(inode));
+    if let Err(err) = result {
+        warn!("[kernel] {} refused by /{}: {:?}", path, MANIFEST_NAME, err);
+    }
+    result
+}
@@ -1,7 +1,19 @@
 mod stdio;
 mod inode;
+mod pipe;
+mod eventfd;
+mod device;
+mod proc;
+mod manifest;
+mod writeback;
+#[cfg(feature = "ktest")]
+pub mod ktest;
 
 use crate::mm::UserBuffer;
+use crate::task::TaskControlBlock;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::{FsError, Inode};
 
 /// The common abstraction of all IO resources
 pub trait File : Send + Sync {
@@ -10,6 +22,156 @@ pub trait File : Send + Sync {
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
     fn fstat(&self) -> Stat;
+    /// The inode of a regular file, for mapping it into memory
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// The inode of a directory, for paths relative to it
+    fn dir(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// Write records of the next entries of a directory into `buf`, see
+    /// `sys_getdents`. Returns the bytes written, 0 at the end.
+    fn getdents(&self, _buf: &mut [u8]) -> Result<usize, DirError> {
+        Err(DirError::NotDir)
+    }
+    /// Whether this is the console, which `sys_ioctl` configures
+    fn is_tty(&self) -> bool {
+        false
+    }
+    /// The end of a pipe, whose capacity `sys_fcntl` configures
+    fn pipe(&self) -> Option<&Pipe> {
+        None
+    }
+    /// Whether `O_NONBLOCK` is set. Only files that can block keep it, it
+    /// belongs to the open file and so is shared by `dup` and `fork`.
+    fn nonblocking(&self) -> bool {
+        false
+    }
+    fn set_nonblocking(&self, _nonblocking: bool) {}
+    /// Whether `O_APPEND` is set, only regular files keep it
+    fn append(&self) -> bool {
+        false
+    }
+    fn set_append(&self, _append: bool) {}
+    /// Like [`File::read`], but `None` where it would block
+    fn read_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        Some(self.read(buf))
+    }
+    /// Like [`File::write`], but `None` where it would block before
+    /// writing anything
+    fn write_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        Some(self.write(buf))
+    }
+    /// Like [`File::write`], but failing with why the filesystem took
+    /// nothing
+    fn try_write(&self, buf: UserBuffer) -> Result<usize, FsError> {
+        Ok(self.write(buf))
+    }
+    /// Which of `events` would not block now, plus `POLLHUP` or `POLLERR`
+    /// whatever `events` are. Files that never block are always ready.
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        ready.set(PollEvents::POLLIN, self.readable());
+        ready.set(PollEvents::POLLOUT, self.writable());
+        ready & events
+    }
+    /// Queue `task` to be woken when the file may have become ready, see
+    /// `sys_ppoll`
+    fn poll_wait(&self, _task: &Arc<TaskControlBlock>) {}
+    /// Take `task` off the queues [`File::poll_wait`] put it on
+    fn poll_cancel(&self, _task: &Arc<TaskControlBlock>) {}
+    /// Write what is cached of the file back to the disk, nothing to do
+    /// for files not on one
+    fn sync(&self) {}
+    /// Move the offset, returns the new one
+    fn seek(&self, _pos: SeekFrom) -> Result<usize, SeekError> {
+        Err(SeekError::NotSeekable)
+    }
+    /// Whether the file on the disk is gone since it was opened, replaced
+    /// by a restore of a snapshot, never for files not on one
+    fn stale(&self) -> bool {
+        false
+    }
+}
+
+/// A slot of the descriptor table of a process. `dup` and fork copy the
+/// slot, and the copies share the open file: its offset and its status
+/// flags. The descriptor flags belong to the slot alone.
+pub struct FdSlot {
+    pub file: Arc<dyn File + Send + Sync>,
+    /// `FD_CLOEXEC`, exec empties the slot
+    pub cloexec: bool,
+}
+
+/// Descriptors open in all processes, see `/proc/files`
+static OPEN_FDS: AtomicUsize = AtomicUsize::new(0);
+
+impl FdSlot {
+    pub fn new(file: Arc<dyn File + Send + Sync>) -> Self {
+        Self::with_cloexec(file, false)
+    }
+    pub fn with_cloexec(file: Arc<dyn File + Send + Sync>, cloexec: bool) -> Self {
+        OPEN_FDS.fetch_add(1, Ordering::Relaxed);
+        Self { file, cloexec }
+    }
+}
+
+impl Clone for FdSlot {
+    fn clone(&self) -> Self {
+        Self::with_cloexec(self.file.clone(), self.cloexec)
+    }
+}
+
+impl Drop for FdSlot {
+    fn drop(&mut self) {
+        OPEN_FDS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Descriptors open in all processes
+pub fn open_fds() -> usize {
+    OPEN_FDS.load(Ordering::Relaxed)
+}
+
+/// Where [`File::seek`] counts from, see `sys_lseek`
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
+
+/// Why [`File::seek`] failed
+#[derive(Debug)]
+pub enum SeekError {
+    /// A pipe or the console
+    NotSeekable,
+    /// The offset would be negative
+    Invalid,
+}
+
+bitflags! {
+    /// Events of `sys_ppoll`
+    pub struct PollEvents: u16 {
+        /// There is data to read
+        const POLLIN = 0x001;
+        /// Writing does not block
+        const POLLOUT = 0x004;
+        /// All read ends of a pipe are closed
+        const POLLERR = 0x008;
+        /// All write ends of a pipe are closed
+        const POLLHUP = 0x010;
+        /// The fd is not open
+        const POLLNVAL = 0x020;
+    }
+}
+
+/// Why [`File::getdents`] failed
+#[derive(Debug)]
+pub enum DirError {
+    NotDir,
+    /// Not even the next record fits in the buffer
+    BufferTooSmall,
 }
 
 /// The stat of a inode
@@ -24,8 +186,37 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// size in bytes, for a pipe the bytes waiting to be read
+    pub size: u64,
+    /// time of last access, seconds since the epoch
+    pub atime: u64,
+    /// time of last modification
+    pub mtime: u64,
+    /// time of last status change
+    pub ctime: u64,
+    /// permission bits, `rwx` for the owner, the group and others like
+    /// `0o644`, 0 outside the filesystem. `mode` only tells the type.
+    pub perm: u32,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u32; 5],
+}
+
+impl Stat {
+    /// The stat of an object outside the filesystem, like the console
+    pub fn special(mode: StatMode, size: u64) -> Self {
+        Self {
+            dev: 0,
+            ino: 0,
+            mode,
+            nlink: 1,
+            size,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            perm: 0,
+            pad: [0; 5],
+        }
+    }
 }
 
 bitflags! {
@@ -33,6 +224,10 @@ bitflags! {
     /// whether a directory or a file
     pub struct StatMode: u32 {
         const NULL  = 0;
+        /// FIFO, a pipe
+        const FIFO  = 0o010000;
+        /// character device, the console
+        const CHR   = 0o020000;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
@@ -40,5 +235,18 @@ bitflags! {
     }
 }    
 
-pub use stdio::{Stdin, Stdout};
-pub use inode::{OSInode, open_file, OpenFlags, list_apps, link_file, unlink_file};
\ No newline at end of file
+pub use stdio::{
+    console_foreground, console_modes, handle_console_input, set_console_foreground,
+    set_console_modes, LocalModes, Stdin, Stdout,
+};
+pub use pipe::{make_pipe, open_fifo, Pipe, PipeSizeError};
+pub use eventfd::EventFd;
+pub use device::open_device;
+pub use proc::open_proc;
+pub use manifest::{verify_exec, VerifyError};
+pub use writeback::writeback_tick;
+pub use inode::{
+    OSInode, chmod_file, open_inodes, open_exec, open_file, OpenFlags, list_apps, link_file, unlink_file, create_kernel_file,
+    open_kernel_file, fs_failed, make_dir, make_fifo, remove_dir, restore_fs, snapshot_fs, sync_all, PathError,
+    ROOT_INODE,
+};
\ No newline at end of file
@@ -0,0 +1,438 @@
+//! Anonymous and named pipes
+//!
+//! All ends of a pipe share one [`PipeRingBuffer`]. An end stays open as
+//! long as some fd table holds its `Arc`; the buffer counts the ends of each
+//! side, so a side is closed exactly when the last of its descriptors is
+//! gone. A named pipe gets a new end on every open, its buffer is found
+//! through [`FIFOS`] by inode number for as long as any end is open.
+//!
+//! Writes of at most [`PIPE_BUF`] bytes are atomic: they wait until there
+//! is room for all of their bytes and put them in at once. Larger writes
+//! put in what fits each time and may be interleaved with other writers.
+
+use super::{File, OpenFlags, PathError, PollEvents, Stat, StatMode};
+use crate::config::PAGE_SIZE;
+use crate::mm::UserBuffer;
+use crate::sync::SpinLock;
+use crate::task::{
+    block_current_killable, current_task, wakeup_task, SignalFlags, TaskControlBlock,
+    TaskStatus,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// Bytes a pipe holds before writers block, until
+/// [`Pipe::set_capacity`] changes it
+const DEFAULT_CAPACITY: usize = 4096;
+/// Most bytes [`Pipe::set_capacity`] lets a pipe hold
+pub const PIPE_MAX_SIZE: usize = 64 * 1024;
+/// Writes of at most this many bytes are never interleaved with others.
+/// No pipe holds less.
+pub const PIPE_BUF: usize = 512;
+
+/// Why [`Pipe::set_capacity`] failed
+#[derive(Debug)]
+pub enum PipeSizeError {
+    /// Above [`PIPE_MAX_SIZE`]
+    TooLarge,
+    /// Below the bytes the pipe holds now
+    Busy,
+    /// The kernel heap has no room for the new buffer
+    NoMemory,
+}
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    /// `O_NONBLOCK`
+    nonblocking: AtomicBool,
+    buffer: Arc<SpinLock<PipeRingBuffer>>,
+}
+
+/// The data in flight and the tasks waiting on either side
+pub struct PipeRingBuffer {
+    arr: Vec<u8>,
+    head: usize,
+    len: usize,
+    /// Ends open for reading, and for writing
+    read_ends: usize,
+    write_ends: usize,
+    /// Ends ever opened for reading, and for writing
+    read_opens: usize,
+    write_opens: usize,
+    /// Readers waiting for data or for the write end to close
+    readers: Vec<Arc<TaskControlBlock>>,
+    /// Writers waiting for room or for the read end to close
+    writers: Vec<Arc<TaskControlBlock>>,
+    /// Opens of a named pipe waiting for the other side
+    openers: Vec<Arc<TaskControlBlock>>,
+}
+
+impl PipeRingBuffer {
+    /// None if the kernel heap has no room for the buffer
+    fn new(capacity: usize) -> Option<Self> {
+        let mut arr = Vec::new();
+        arr.try_reserve_exact(capacity).ok()?;
+        arr.resize(capacity, 0);
+        Some(Self {
+            arr,
+            head: 0,
+            len: 0,
+            read_ends: 0,
+            write_ends: 0,
+            read_opens: 0,
+            write_opens: 0,
+            readers: Vec::new(),
+            writers: Vec::new(),
+            openers: Vec::new(),
+        })
+    }
+    fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+    /// Bytes that can be written without blocking
+    fn room(&self) -> usize {
+        self.capacity() - self.len
+    }
+    /// Move the data into a new buffer of `capacity` bytes, which must hold
+    /// it. None if the kernel heap has no room for the buffer.
+    fn resize(&mut self, capacity: usize) -> Option<()> {
+        let mut arr = Vec::new();
+        arr.try_reserve_exact(capacity).ok()?;
+        let len = self.len;
+        while self.len > 0 {
+            arr.push(self.read_byte());
+        }
+        arr.resize(capacity, 0);
+        self.arr = arr;
+        self.head = 0;
+        self.len = len;
+        Some(())
+    }
+    fn read_byte(&mut self) -> u8 {
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
+        c
+    }
+    fn write_byte(&mut self, c: u8) {
+        let tail = (self.head + self.len) % self.capacity();
+        self.arr[tail] = c;
+        self.len += 1;
+    }
+    fn all_read_ends_closed(&self) -> bool {
+        self.read_ends == 0
+    }
+    fn all_write_ends_closed(&self) -> bool {
+        self.write_ends == 0
+    }
+}
+
+lazy_static! {
+    /// Buffers of the named pipes, by inode number. An entry outlives its
+    /// buffer until the next open sweeps it.
+    static ref FIFOS: SpinLock<BTreeMap<u64, Weak<SpinLock<PipeRingBuffer>>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Create a pipe, returns its read end and its write end, or None if the
+/// kernel heap is out of memory
+pub fn make_pipe(nonblocking: bool) -> Option<(Arc<Pipe>, Arc<Pipe>)> {
+    let buffer = Arc::new(SpinLock::new(PipeRingBuffer::new(DEFAULT_CAPACITY)?));
+    let read_end = Pipe::open_end(buffer.clone(), true, false, nonblocking);
+    let write_end = Pipe::open_end(buffer, false, true, nonblocking);
+    Some((read_end, write_end))
+}
+
+/// Open a new end of the named pipe with inode number `ino`. Opening one
+/// side blocks until the other side is opened as well, unless `NONBLOCK`
+/// is set: then a read end opens at once, while a write end fails with
+/// [`PathError::NoReader`] if no read end is open. Opening both sides
+/// together never blocks.
+pub fn open_fifo(ino: u64, flags: OpenFlags) -> Result<Arc<Pipe>, PathError> {
+    let (readable, writable) = flags.read_write();
+    let nonblocking = flags.contains(OpenFlags::NONBLOCK);
+    let buffer = {
+        let mut fifos = FIFOS.exclusive_access();
+        fifos.retain(|_, buffer| buffer.strong_count() > 0);
+        match fifos.get(&ino).and_then(Weak::upgrade) {
+            Some(buffer) => buffer,
+            None => {
+                let buffer =
+                    PipeRingBuffer::new(DEFAULT_CAPACITY).ok_or(PathError::NoMemory)?;
+                let buffer = Arc::new(SpinLock::new(buffer));
+                fifos.insert(ino, Arc::downgrade(&buffer));
+                buffer
+            }
+        }
+    };
+    if nonblocking && !readable && buffer.exclusive_access().all_read_ends_closed() {
+        return Err(PathError::NoReader);
+    }
+    let pipe = Pipe::open_end(buffer, readable, writable, nonblocking);
+    if nonblocking || readable && writable {
+        return Ok(pipe);
+    }
+    // wait for an end of the other side, even if it is closed again before
+    // this task gets to run
+    let mut seen = None;
+    loop {
+        let mut ring = pipe.buffer.exclusive_access();
+        let (ends, opens) = if readable {
+            (ring.write_ends, ring.write_opens)
+        } else {
+            (ring.read_ends, ring.read_opens)
+        };
+        if ends > 0 || seen.map_or(false, |seen| seen != opens) {
+            drop(ring);
+            return Ok(pipe);
+        }
+        seen = Some(opens);
+        wait_on(&mut ring.openers);
+        drop(ring);
+        if !block_current_killable() {
+            return Err(PathError::Interrupted);
+        }
+    }
+}
+
+/// Wake all of `waiters`, each checks the pipe again for itself
+pub(super) fn wakeup_all(waiters: Vec<Arc<TaskControlBlock>>) {
+    for task in waiters {
+        wakeup_task(task);
+    }
+}
+
+/// Mark the current task `Blocked` and queue it on `waiters`, the caller
+/// blocks after releasing the pipe, see [`block_current_killable`]
+pub(super) fn wait_on(waiters: &mut Vec<Arc<TaskControlBlock>>) {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    waiters.push(task);
+}
+
+impl Pipe {
+    /// Count a new end of `buffer` and wake the opens waiting for it
+    fn open_end(
+        buffer: Arc<SpinLock<PipeRingBuffer>>,
+        readable: bool,
+        writable: bool,
+        nonblocking: bool,
+    ) -> Arc<Self> {
+        let mut ring = buffer.exclusive_access();
+        if readable {
+            ring.read_ends += 1;
+            ring.read_opens += 1;
+        }
+        if writable {
+            ring.write_ends += 1;
+            ring.write_opens += 1;
+        }
+        let openers = core::mem::take(&mut ring.openers);
+        drop(ring);
+        wakeup_all(openers);
+        Arc::new(Self {
+            readable,
+            writable,
+            nonblocking: AtomicBool::new(nonblocking),
+            buffer,
+        })
+    }
+    /// Read what is there, blocking only while the pipe is empty, or
+    /// returning `None` then unless `block`. Returns 0 once it is empty and
+    /// all write ends are closed.
+    fn read_or_block(&self, buf: UserBuffer, block: bool) -> Option<usize> {
+        assert!(self.readable());
+        let want = buf.len();
+        if want == 0 {
+            return Some(0);
+        }
+        let mut buf_iter = buf.into_iter();
+        loop {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.len == 0 {
+                if ring.all_write_ends_closed() {
+                    return Some(0);
+                }
+                if !block {
+                    return None;
+                }
+                wait_on(&mut ring.readers);
+                drop(ring);
+                if !block_current_killable() {
+                    return Some(0);
+                }
+                continue;
+            }
+            let mut read_size = 0;
+            while read_size < want && ring.len > 0 {
+                let byte_ref = buf_iter.next().unwrap();
+                unsafe { *byte_ref = ring.read_byte(); }
+                read_size += 1;
+            }
+            let writers = core::mem::take(&mut ring.writers);
+            drop(ring);
+            wakeup_all(writers);
+            return Some(read_size);
+        }
+    }
+    /// Write all of `buf`, blocking while the pipe has no room, or until
+    /// it has room for all of `buf` if that is at most [`PIPE_BUF`] bytes.
+    /// Unless `block`, stop there instead, returning `None` if nothing was
+    /// written. If all read ends are closed, SIGPIPE is raised and the
+    /// bytes written so far returned, see [`Pipe::broken`].
+    fn write_or_block(&self, buf: UserBuffer, block: bool) -> Option<usize> {
+        assert!(self.writable());
+        let want = buf.len();
+        let atomic = want <= PIPE_BUF;
+        let mut buf_iter = buf.into_iter();
+        let mut write_size = 0;
+        while write_size < want {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.all_read_ends_closed() {
+                drop(ring);
+                current_task()
+                    .unwrap()
+                    .inner_exclusive_access()
+                    .signals
+                    .insert(SignalFlags::SIGPIPE);
+                break;
+            }
+            if ring.room() == 0 || atomic && ring.room() < want {
+                if !block {
+                    if write_size == 0 {
+                        return None;
+                    }
+                    break;
+                }
+                wait_on(&mut ring.writers);
+                drop(ring);
+                if !block_current_killable() {
+                    break;
+                }
+                continue;
+            }
+            while write_size < want && ring.room() > 0 {
+                let byte_ref = buf_iter.next().unwrap();
+                ring.write_byte(unsafe { *byte_ref });
+                write_size += 1;
+            }
+            let readers = core::mem::take(&mut ring.readers);
+            drop(ring);
+            wakeup_all(readers);
+        }
+        Some(write_size)
+    }
+    /// Bytes the pipe holds before writers block, `F_GETPIPE_SZ`
+    pub fn capacity(&self) -> usize {
+        self.buffer.exclusive_access().capacity()
+    }
+    /// Let the pipe hold `size` bytes, rounded up to whole pages,
+    /// `F_SETPIPE_SZ`. Returns the new capacity.
+    pub fn set_capacity(&self, size: usize) -> Result<usize, PipeSizeError> {
+        if size > PIPE_MAX_SIZE {
+            return Err(PipeSizeError::TooLarge);
+        }
+        let capacity = (size.max(1) + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let mut ring = self.buffer.exclusive_access();
+        if capacity < ring.len {
+            return Err(PipeSizeError::Busy);
+        }
+        if capacity != ring.capacity() {
+            ring.resize(capacity).ok_or(PipeSizeError::NoMemory)?;
+        }
+        let writers = core::mem::take(&mut ring.writers);
+        drop(ring);
+        wakeup_all(writers);
+        Ok(capacity)
+    }
+    /// Whether this is a write end with all read ends closed, writes then
+    /// fail with `EPIPE`
+    pub fn broken(&self) -> bool {
+        self.writable && self.buffer.exclusive_access().all_read_ends_closed()
+    }
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool { self.readable }
+    fn writable(&self) -> bool { self.writable }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.read_or_block(buf, true).unwrap()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.write_or_block(buf, true).unwrap()
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+    fn read_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        self.read_or_block(buf, false)
+    }
+    fn write_nonblocking(&self, buf: UserBuffer) -> Option<usize> {
+        self.write_or_block(buf, false)
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let ring = self.buffer.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if self.readable {
+            ready.set(PollEvents::POLLIN, ring.len > 0 && events.contains(PollEvents::POLLIN));
+            ready.set(PollEvents::POLLHUP, ring.all_write_ends_closed());
+        }
+        if self.writable {
+            // room for an atomic write
+            ready.set(
+                PollEvents::POLLOUT,
+                ring.room() >= PIPE_BUF && events.contains(PollEvents::POLLOUT),
+            );
+            ready.set(PollEvents::POLLERR, ring.all_read_ends_closed());
+        }
+        ready
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        let mut ring = self.buffer.exclusive_access();
+        if self.readable {
+            ring.readers.push(task.clone());
+        }
+        if self.writable {
+            ring.writers.push(task.clone());
+        }
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        let mut ring = self.buffer.exclusive_access();
+        ring.readers.retain(|waiter| !Arc::ptr_eq(waiter, task));
+        ring.writers.retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+    fn pipe(&self) -> Option<&Pipe> {
+        Some(self)
+    }
+    fn fstat(&self) -> Stat {
+        let len = self.buffer.exclusive_access().len;
+        Stat::special(StatMode::FIFO, len as u64)
+    }
+}
+
+impl Drop for Pipe {
+    /// The last descriptor of this end is gone, wake the other side to see
+    /// end of file or a broken pipe
+    fn drop(&mut self) {
+        let mut ring = self.buffer.exclusive_access();
+        let mut waiters = Vec::new();
+        if self.readable {
+            ring.read_ends -= 1;
+            waiters.append(&mut ring.writers);
+        }
+        if self.writable {
+            ring.write_ends -= 1;
+            waiters.append(&mut ring.readers);
+        }
+        drop(ring);
+        wakeup_all(waiters);
+    }
+}
@@ -0,0 +1,232 @@
+//! Virtual files under `/proc`
+//!
+//! Like the devices, they are not on the disk: [`open_proc`] catches their
+//! paths before easy-fs sees them. Each file formats its content once when
+//! it is opened, so reads see one consistent snapshot, even of a process
+//! that exits in between. Paths under `/proc` it does not know fall through
+//! to easy-fs, which has no such directory.
+
+use super::{open_fds, open_inodes, File, SeekError, SeekFrom, Stat, StatMode, ROOT_INODE};
+use crate::config::PAGE_SIZE;
+use crate::mm::{
+    frame_allocator_stats, frames_high_water, free_swap_slots, heap_stats, kernel_heap_stats,
+    UserBuffer,
+};
+use crate::perf::{sched_profiles, syscall_profiles};
+use crate::sync::SpinLock;
+use crate::task::{
+    cached_kernel_stack_pages, current_task, pid2task, IoKind, TaskControlBlock, TaskStatus,
+};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use easy_fs::block_cache_stats;
+
+/// A read-only file serving the text formatted on open
+pub struct ProcFile {
+    content: String,
+    offset: SpinLock<usize>,
+}
+
+/// The file under `/proc` at `path`, relative to the root like all paths
+pub fn open_proc(path: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    let path = path.trim_start_matches('/').strip_prefix("proc/")?;
+    let content = match path {
+        "meminfo" => meminfo(),
+        "fs" => fs_info(),
+        "syscalls" => syscall_profiles(),
+        "sched_hist" => sched_profiles(),
+        "files" => files_info(),
+        _ => {
+            let (pid, name) = path.split_once('/')?;
+            let task = match pid {
+                "self" => current_task().unwrap(),
+                _ => pid2task(pid.parse::<usize>().ok()?)?,
+            };
+            match name {
+                "stat" => task_stat(&task),
+                "fd" => task_fds(&task),
+                "io" => task_io(&task),
+                _ => return None,
+            }
+        }
+    };
+    Some(Arc::new(ProcFile {
+        content,
+        offset: SpinLock::new(0),
+    }))
+}
+
+/// One line like Linux's `/proc/<pid>/stat`: pid, name, state, stride
+/// priority and pass, user and system time in milliseconds and resident
+/// pages. The state is R for ready or running, S for blocked, Z for zombie
+/// and I before the first dispatch.
+fn task_stat(task: &Arc<TaskControlBlock>) -> String {
+    let inner = task.inner_exclusive_access();
+    let state = match inner.task_status {
+        TaskStatus::UnInit => 'I',
+        TaskStatus::Ready | TaskStatus::Running => 'R',
+        TaskStatus::Blocked => 'S',
+        TaskStatus::Zombie => 'Z',
+    };
+    format!(
+        "{} ({}) {} {} {} {} {} {}\n",
+        task.getpid(),
+        inner.name,
+        state,
+        inner.priority,
+        inner.pass.value(),
+        inner.utime_us / 1000,
+        inner.stime_us / 1000,
+        inner.memory_set.user_pages(),
+    )
+}
+
+/// What a task read and wrote, in bytes by kind of file, and the block
+/// device requests it issued and the microseconds it waited for them, see
+/// `task::io`
+fn task_io(task: &Arc<TaskControlBlock>) -> String {
+    let io = task.io.stat();
+    let (file, pipe, console) = (IoKind::File as usize, IoKind::Pipe as usize, IoKind::Console as usize);
+    format!(
+        "FileRead: {}\nFileWritten: {}\nPipeRead: {}\nPipeWritten: {}\n\
+         ConsoleRead: {}\nConsoleWritten: {}\nBlockReads: {}\nBlockWrites: {}\n\
+         BlockWaitUs: {}\n",
+        io.read_bytes[file],
+        io.written_bytes[file],
+        io.read_bytes[pipe],
+        io.written_bytes[pipe],
+        io.read_bytes[console],
+        io.written_bytes[console],
+        io.block_reads,
+        io.block_writes,
+        io.block_wait_us,
+    )
+}
+
+/// The open descriptors of a task, one per line: the descriptor, the kind
+/// of file, its inode number and its offset, `-` where it has none. The
+/// descriptor being opened to read this is not listed yet.
+fn task_fds(task: &Arc<TaskControlBlock>) -> String {
+    let files: Vec<_> = task
+        .inner_exclusive_access()
+        .fd_table
+        .iter_open(0, usize::MAX)
+        .map(|(fd, slot)| (fd, slot.file.clone()))
+        .collect();
+    // files are asked without the TCB lock, an inode takes the fs lock
+    let mut content = String::new();
+    for (fd, file) in files {
+        let stat = file.fstat();
+        let kind = if stat.mode.contains(StatMode::DIR) {
+            "dir"
+        } else if stat.mode.contains(StatMode::FILE) {
+            "file"
+        } else if stat.mode.contains(StatMode::FIFO) {
+            "fifo"
+        } else if stat.mode.contains(StatMode::CHR) {
+            "chr"
+        } else {
+            "anon"
+        };
+        let _ = match file.seek(SeekFrom::Current(0)) {
+            Ok(offset) => writeln!(content, "{} {} {} {}", fd, kind, stat.ino, offset),
+            Err(_) => writeln!(content, "{} {} {} -", fd, kind, stat.ino),
+        };
+    }
+    content
+}
+
+/// Descriptors open in all processes and the open files of inodes they
+/// share, to tell a leak of either
+fn files_info() -> String {
+    format!("OpenDescriptors: {}\nOpenInodeFiles: {}\n", open_fds(), open_inodes())
+}
+
+/// Frames in kB like Linux's `/proc/meminfo`, the kernel heap in bytes
+fn meminfo() -> String {
+    let kb = |pages: usize| pages * PAGE_SIZE / 1024;
+    let (total, free) = frame_allocator_stats();
+    let (heap_user, heap_actual, heap_total) = heap_stats();
+    let heap = kernel_heap_stats();
+    format!(
+        "MemTotal: {} kB\nMemFree: {} kB\nMemPeak: {} kB\nKernelStackCache: {} kB\n\
+         SwapFreeSlots: {}\nHeapTotal: {} B\nHeapRequested: {} B\nHeapAllocated: {} B\n\
+         HeapPeak: {} B\nHeapFailures: {}\n",
+        kb(total),
+        kb(free),
+        kb(frames_high_water()),
+        kb(cached_kernel_stack_pages()),
+        free_swap_slots(),
+        heap_total,
+        heap_user,
+        heap_actual,
+        heap.peak,
+        heap.failures,
+    )
+}
+
+/// Usage of the root filesystem and counters of the block cache
+fn fs_info() -> String {
+    let fs = ROOT_INODE.stat_fs();
+    let cache = block_cache_stats();
+    format!(
+        "Blocks: {}\nDataBlocks: {}\nDataBlocksFree: {}\nSpareBlocks: {}\nRemappedBlocks: {}\n\
+         Inodes: {}\nInodesFree: {}\nCacheHits: {}\nCacheMisses: {}\nCacheWritebacks: {}\nCacheAgedWritebacks: {}\n\
+         CacheBlocks: {}/{}\n",
+        fs.total_blocks,
+        fs.data_blocks,
+        fs.free_data_blocks,
+        fs.spare_blocks,
+        fs.remapped_blocks,
+        fs.total_inodes,
+        fs.free_inodes,
+        cache.hits,
+        cache.misses,
+        cache.writebacks,
+        cache.aged_writebacks,
+        cache.cached,
+        cache.capacity,
+    )
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { false }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for slice in buf.buffers.iter_mut() {
+            let rest = &self.content.as_bytes()[(*offset).min(self.content.len())..];
+            let len = rest.len().min(slice.len());
+            slice[..len].copy_from_slice(&rest[..len]);
+            *offset += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        *offset - start
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn seek(&self, pos: SeekFrom) -> Result<usize, SeekError> {
+        let mut offset = self.offset.exclusive_access();
+        let (base, delta) = match pos {
+            SeekFrom::Start(start) => (0, start as isize),
+            SeekFrom::Current(delta) => (*offset, delta),
+            SeekFrom::End(delta) => (self.content.len(), delta),
+        };
+        let new = base as isize + delta;
+        if new < 0 {
+            return Err(SeekError::Invalid);
+        }
+        *offset = new as usize;
+        Ok(*offset)
+    }
+    fn fstat(&self) -> Stat {
+        Stat::special(StatMode::FILE, self.content.len() as u64)
+    }
+}
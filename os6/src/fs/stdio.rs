@@ -1,38 +1,281 @@
-use super::{File, Stat, StatMode};
+//! The console: standard input with a line discipline, standard output
+//!
+//! Typed bytes arrive by the UART interrupt, which hands them to
+//! [`handle_console_input`]. In canonical mode they are echoed and gathered
+//! into a line that can be edited with backspace, and only a finished line
+//! becomes readable. Ctrl-C drops the line and sends SIGINT to every process
+//! of the foreground group. `sys_ioctl` turns each of these off, see
+//! [`LocalModes`].
+
+use super::{File, PollEvents, Stat, StatMode};
 use crate::mm::{UserBuffer};
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::drivers::getchar;
+use crate::sbi::console_putchar;
+use crate::sync::SpinLock;
+use crate::task::{
+    block_current_killable, current_task, kill_group, wakeup_task, SignalFlags,
+    TaskControlBlock, TaskStatus,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
 
 /// The standard input
-pub struct Stdin;
+pub struct Stdin {
+    /// `O_NONBLOCK`
+    nonblocking: AtomicBool,
+}
 /// The standard output
 pub struct Stdout;
 
-impl File for Stdin {
-    fn readable(&self) -> bool { true }
-    fn writable(&self) -> bool { false }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
+const CTRL_C: u8 = 0x03;
+const BS: u8 = 0x08;
+const LF: u8 = 0x0a;
+const CR: u8 = 0x0d;
+const DL: u8 = 0x7f;
+
+/// Bytes the console holds, counting the line being edited. Input beyond
+/// that is dropped until a reader makes room.
+const INPUT_MAX: usize = 4096;
+
+bitflags! {
+    /// Local modes of the console, the `c_lflag` bits of Linux
+    pub struct LocalModes: u32 {
+        /// Ctrl-C sends SIGINT
+        const ISIG   = 0o1;
+        /// Input is edited and read line by line
+        const ICANON = 0o2;
+        /// Typed bytes are echoed
+        const ECHO   = 0o10;
+    }
+}
+
+struct Tty {
+    modes: LocalModes,
+    /// The line being edited in canonical mode
+    line: Vec<u8>,
+    /// Bytes ready to be read
+    input: VecDeque<u8>,
+    /// Readers waiting for input
+    readers: Vec<Arc<TaskControlBlock>>,
+    /// The process group Ctrl-C is sent to, 0 for none
+    foreground: usize,
+}
+
+impl Tty {
+    fn echo(&self, bytes: &[u8]) {
+        if self.modes.contains(LocalModes::ECHO) {
+            for c in bytes {
+                console_putchar(*c as usize);
+            }
+        }
+    }
+    fn is_full(&self) -> bool {
+        self.line.len() + self.input.len() >= INPUT_MAX
+    }
+    /// Take in a typed byte. Returns whether it made input readable.
+    fn receive(&mut self, c: u8) -> bool {
+        if !self.modes.contains(LocalModes::ICANON) {
+            if self.is_full() {
+                return false;
+            }
+            self.input.push_back(c);
+            self.echo(&[c]);
+            return true;
+        }
+        match c {
+            BS | DL => {
+                if self.line.pop().is_some() {
+                    self.echo(&[BS, b' ', BS]);
+                }
+                false
+            }
+            LF | CR => {
+                // the newline always fits, even in a full line
+                self.input.extend(self.line.drain(..));
+                self.input.push_back(LF);
+                self.echo(&[LF]);
+                true
+            }
+            _ => {
+                if !self.is_full() {
+                    self.line.push(c);
+                    self.echo(&[c]);
+                }
+                false
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref TTY: SpinLock<Tty> = SpinLock::new(Tty {
+        modes: LocalModes::all(),
+        line: Vec::new(),
+        input: VecDeque::new(),
+        readers: Vec::new(),
+        foreground: 0,
+    });
+}
+
+/// Take in what the UART received, called on its interrupt
+pub fn handle_console_input() {
+    let mut tty = TTY.exclusive_access();
+    let mut readable = false;
+    let mut interrupted = false;
+    while let Some(c) = getchar() {
+        if c == CTRL_C && tty.modes.contains(LocalModes::ISIG) {
+            tty.line.clear();
+            tty.echo(b"^C\n");
+            interrupted = true;
+        } else {
+            readable |= tty.receive(c);
+        }
+    }
+    let readers = if readable || interrupted {
+        core::mem::take(&mut tty.readers)
+    } else {
+        Vec::new()
+    };
+    let foreground = tty.foreground;
+    drop(tty);
+    // signal first, so that a woken reader sees it
+    if interrupted && foreground != 0 {
+        kill_group(foreground, SignalFlags::SIGINT);
+    }
+    for task in readers {
+        wakeup_task(task);
+    }
+}
+
+/// The local modes of the console
+pub fn console_modes() -> LocalModes {
+    TTY.exclusive_access().modes
+}
+
+/// Switch the local modes of the console. Leaving canonical mode makes the
+/// line being edited readable as it is.
+pub fn set_console_modes(modes: LocalModes) {
+    let mut tty = TTY.exclusive_access();
+    if !modes.contains(LocalModes::ICANON) {
+        let line = core::mem::take(&mut tty.line);
+        tty.input.extend(line);
+    }
+    tty.modes = modes;
+}
+
+/// The process group Ctrl-C is sent to, 0 for none
+pub fn console_foreground() -> usize {
+    TTY.exclusive_access().foreground
+}
+
+/// Send Ctrl-C to process group `pgid` from now on, or to none if it is 0
+pub fn set_console_foreground(pgid: usize) {
+    TTY.exclusive_access().foreground = pgid;
+}
+
+/// Whether a signal is waiting for the current task
+fn signal_pending() -> bool {
+    !current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .signals
+        .is_empty()
+}
+
+impl Stdin {
+    pub fn new() -> Self {
+        Self {
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+    /// Read what is there, up to the end of a line in canonical mode,
+    /// blocking while there is nothing, or returning `None` then unless
+    /// `block`. Returns 0 if a signal comes first.
+    fn read_or_block(&self, user_buf: UserBuffer, block: bool) -> Option<usize> {
+        let want = user_buf.len();
+        if want == 0 {
+            return Some(0);
+        }
+        let mut buf_iter = user_buf.into_iter();
         loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
+            let mut tty = TTY.exclusive_access();
+            if tty.input.is_empty() {
+                if !block {
+                    return None;
+                }
+                if signal_pending() {
+                    return Some(0);
+                }
+                let task = current_task().unwrap();
+                task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+                tty.readers.push(task);
+                drop(tty);
+                if !block_current_killable() {
+                    return Some(0);
+                }
                 continue;
-            } else {
-                break;
             }
+            let canonical = tty.modes.contains(LocalModes::ICANON);
+            let mut read_size = 0;
+            while read_size < want {
+                let c = match tty.input.pop_front() {
+                    Some(c) => c,
+                    None => break,
+                };
+                unsafe { *buf_iter.next().unwrap() = c; }
+                read_size += 1;
+                if canonical && c == LF {
+                    break;
+                }
+            }
+            return Some(read_size);
         }
-        let ch = c as u8;
-        unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch); }
-        1
+    }
+}
+
+impl File for Stdin {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { false }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        self.read_or_block(user_buf, true).unwrap()
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+    fn read_nonblocking(&self, user_buf: UserBuffer) -> Option<usize> {
+        self.read_or_block(user_buf, false)
+    }
+    /// Readable once there is a whole line in canonical mode, any byte
+    /// otherwise
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        if TTY.exclusive_access().input.is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::POLLIN
+        }
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        TTY.exclusive_access().readers.push(task.clone());
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        TTY.exclusive_access()
+            .readers
+            .retain(|reader| !Arc::ptr_eq(reader, task));
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
     fn fstat(&self) -> Stat {
-        panic!("Cannot get stdin stat!");
+        Stat::special(StatMode::CHR, 0)
+    }
+    fn is_tty(&self) -> bool {
+        true
     }
 }
 
@@ -49,6 +292,9 @@ impl File for Stdout {
         user_buf.len()
     }
     fn fstat(&self) -> Stat {
-        panic!("Cannot get stdout stat!");
+        Stat::special(StatMode::CHR, 0)
+    }
+    fn is_tty(&self) -> bool {
+        true
     }
 }
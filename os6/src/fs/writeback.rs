@@ -0,0 +1,36 @@
+//! Periodic writeback of the block cache
+//!
+//! A block written without a sync stays dirty in the cache until it is
+//! evicted, which may be never. Every [`WRITEBACK_INTERVAL_MS`] a timer tick
+//! writes back at most [`WRITEBACK_BATCH`] blocks dirtied at least that long
+//! ago, on the time of the task it interrupted. Blocks are aged in
+//! milliseconds.
+
+use crate::config::{WRITEBACK_BATCH, WRITEBACK_INTERVAL_MS};
+use crate::timer::get_time_ms;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::block_cache_sync_older_than;
+
+/// When the next pass is due, taken by one hart each time
+static NEXT_PASS_MS: AtomicUsize = AtomicUsize::new(0);
+
+/// Write back the blocks dirty for too long if a pass is due, called on
+/// each timer tick
+pub fn writeback_tick() {
+    if WRITEBACK_INTERVAL_MS == 0 {
+        return;
+    }
+    let now = get_time_ms();
+    let next = NEXT_PASS_MS.load(Ordering::Relaxed);
+    if now < next
+        || NEXT_PASS_MS
+            .compare_exchange(next, now + WRITEBACK_INTERVAL_MS, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    let written = block_cache_sync_older_than(WRITEBACK_INTERVAL_MS as u64, WRITEBACK_BATCH);
+    if written != 0 {
+        debug!("[kernel] writeback: {} blocks", written);
+    }
+}
@@ -0,0 +1,61 @@
+//! Kernel self-tests, built with the `ktest` feature
+//!
+//! User programs only see the kernel through syscalls, these tests check
+//! invariants inside it: frames and heap coming back, page tables mapping
+//! what they were told, the filesystem on the real image, the scheduler's
+//! passes and wakeups, the hashes. Each subsystem keeps its tests in its own `ktest`
+//! module and lists them in [`TESTS`].
+//!
+//! [`run_all`] runs them once the filesystem is up and before initproc,
+//! then carries on booting if all pass and shuts down with a failure code
+//! otherwise, so `make ktest` exits non-zero.
+
+/// A test, `Err` tells what went wrong
+pub type KTestFn = fn() -> Result<(), &'static str>;
+
+/// Fail the test with `msg` unless `cond` holds
+macro_rules! kassert {
+    ($cond:expr, $msg:expr) => {
+        if !$cond {
+            return Err($msg);
+        }
+    };
+}
+
+/// Every test, by name, in the order run
+static TESTS: &[(&str, KTestFn)] = &[
+    ("mm::frame_balance", crate::mm::ktest::frame_balance),
+    ("mm::heap_balance", crate::mm::ktest::heap_balance),
+    ("mm::page_table_roundtrip", crate::mm::ktest::page_table_roundtrip),
+    ("fs::file_roundtrip", crate::fs::ktest::file_roundtrip),
+    ("fs::block_cache_bounded", crate::fs::ktest::block_cache_bounded),
+    ("fs::manifest_check", crate::fs::ktest::manifest_check),
+    ("crypto::sha256_vectors", crate::crypto::ktest::sha256_vectors),
+    ("crypto::sha256_pieces", crate::crypto::ktest::sha256_pieces),
+    ("task::pass_ordering", crate::task::ktest::pass_ordering),
+    ("task::pass_boost", crate::task::ktest::pass_boost),
+    ("task::wake_once", crate::task::ktest::wake_once),
+    ("task::fork_density", crate::task::ktest::fork_density),
+    ("task::lazy_trap_cx", crate::task::ktest::lazy_trap_cx),
+];
+
+/// Run every test, printing how each went, and shut down if any failed
+pub fn run_all() {
+    println!("[ktest] running {} tests", TESTS.len());
+    let mut failed = 0;
+    for (name, test) in TESTS {
+        match test() {
+            Ok(()) => {
+                println!("[ktest] {} ... ok", name);
+            }
+            Err(msg) => {
+                println!("[ktest] {} ... FAILED: {}", name, msg);
+                failed += 1;
+            }
+        }
+    }
+    println!("[ktest] {} passed, {} failed", TESTS.len() - failed, failed);
+    if failed > 0 {
+        crate::sbi::shutdown(true);
+    }
+}
@@ -1,9 +1,128 @@
 //! The panic handler
+//!
+//! Besides the message it dumps the trap registers, the current task and the
+//! use of the kernel heap, then walks the frame pointers (the kernel is built
+//! with `-Cforce-frame-pointers`) and names every return address with the
+//! symbol table that `build.rs` puts in the `.symbols` section.
 
 use crate::console::ANSICON;
+use crate::mm::kernel_heap_stats;
 use crate::sbi::shutdown;
+use crate::task::{hart_id, kernel_stack_position, try_current_task};
 
+use core::arch::asm;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use riscv::register::{scause, sepc, stval};
+
+mod symbols {
+    include!(concat!(env!("OUT_DIR"), "/symbols.rs"));
+}
+
+/// The symbol table in `.symbols`, see `symbol_table` in `build.rs`: the
+/// address, length and [`Self::text_hash`] of the `.text` it was read from,
+/// the number of symbols, that many `(address, name offset, name length)`,
+/// then the names, all numbers little-endian u64
+struct SymbolTable {
+    data: &'static [u8],
+    len: usize,
+}
+
+impl SymbolTable {
+    const HEADER_WORDS: usize = 4;
+
+    /// The table, None if it is empty or describes other code than ours
+    fn get() -> Option<Self> {
+        extern "C" {
+            fn stext();
+            fn ssymbols();
+            fn esymbols();
+        }
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                ssymbols as usize as *const u8,
+                esymbols as usize - ssymbols as usize,
+            )
+        };
+        let mut table = Self { data, len: 0 };
+        if data.len() < Self::HEADER_WORDS * 8 {
+            return None;
+        }
+        let (text_start, text_len) = (table.word(0), table.word(1));
+        if text_start != stext as usize || table.word(2) != Self::text_hash(text_start, text_len) {
+            return None;
+        }
+        table.len = table.word(3);
+        if data.len() < (Self::HEADER_WORDS + 3 * table.len) * 8 {
+            return None;
+        }
+        Some(table)
+    }
+
+    fn word(&self, i: usize) -> usize {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.data[i * 8..i * 8 + 8]);
+        u64::from_le_bytes(bytes) as usize
+    }
+
+    /// FNV-1a over the whole u64 words of `[start, start + len)`
+    fn text_hash(start: usize, len: usize) -> usize {
+        let words = unsafe { core::slice::from_raw_parts(start as *const u64, len / 8) };
+        words.iter().fold(0xcbf29ce484222325u64, |hash, word| {
+            (hash ^ u64::from_le(*word)).wrapping_mul(0x100000001b3)
+        }) as usize
+    }
+
+    /// The address and name of symbol `i`
+    fn symbol(&self, i: usize) -> (usize, &'static str) {
+        let entry = Self::HEADER_WORDS + 3 * i;
+        let (offset, len) = (self.word(entry + 1), self.word(entry + 2));
+        let name = self
+            .data
+            .get(offset..offset + len)
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .unwrap_or("?");
+        (self.word(entry), name)
+    }
+
+    /// The function holding `addr` and the offset into it
+    fn symbolize(&self, addr: usize) -> Option<(&'static str, usize)> {
+        extern "C" {
+            fn stext();
+            fn etext();
+        }
+        if addr < stext as usize || addr >= etext as usize {
+            return None;
+        }
+        // the first symbol past `addr`
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.symbol(mid).0 <= addr {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        match low {
+            0 => None,
+            i => {
+                let (start, name) = self.symbol(i - 1);
+                Some((name, addr - start))
+            }
+        }
+    }
+}
+
+/// Frames printed at most, should the frame pointers form a loop
+const MAX_FRAMES: usize = 64;
+/// Per hart, as laid out in entry.asm and trap.S
+const BOOT_STACK_SIZE: usize = 4096 * 16;
+const KERNEL_TRAP_STACK_SIZE: usize = 4096 * 8;
+
+/// Set by the first panic, a panic in the dump or the walk only prints its
+/// message
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
 #[panic_handler]
 /// panic handler
@@ -25,5 +144,104 @@ fn panic(info: &PanicInfo) -> ! {
             info.message().unwrap()
         );
     }
-    shutdown()
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        dump_registers();
+        backtrace();
+    }
+    shutdown(true)
+}
+
+/// The trap registers keep the last trap, the cause of a panic in
+/// `trap_handler` or `trap_from_kernel`
+fn dump_registers() {
+    let sstatus: usize;
+    unsafe {
+        asm!("csrr {}, sstatus", out(reg) sstatus);
+    }
+    println!(
+        "[kernel] hart {}, sstatus = {:#x}, last trap {:?} at sepc = {:#x}, stval = {:#x}",
+        hart_id(),
+        sstatus,
+        scause::read().cause(),
+        sepc::read(),
+        stval::read()
+    );
+    // the lock or the processor may be held by the code that panicked
+    match try_current_task() {
+        Some(task) => match task.try_inner_exclusive_access() {
+            Some(inner) => {
+                println!("[kernel] current task: pid {} ({})", task.pid.0, inner.name);
+            }
+            None => {
+                println!("[kernel] current task: pid {}", task.pid.0);
+            }
+        },
+        None => {
+            println!("[kernel] no current task");
+        }
+    }
+    println!("[kernel] {}", kernel_heap_stats());
+}
+
+/// The stacks the kernel may run on in this hart, as `(bottom, top)`
+fn kernel_stacks() -> [Option<(usize, usize)>; 3] {
+    extern "C" {
+        fn boot_stack();
+        fn kernel_trap_stack();
+    }
+    let hart = hart_id();
+    let boot = boot_stack as usize + hart * BOOT_STACK_SIZE;
+    let trap = kernel_trap_stack as usize + hart * KERNEL_TRAP_STACK_SIZE;
+    [
+        Some((boot, boot + BOOT_STACK_SIZE)),
+        Some((trap, trap + KERNEL_TRAP_STACK_SIZE)),
+        try_current_task().map(|task| kernel_stack_position(task.pid.0)),
+    ]
+}
+
+/// Walk the frames from here on up. A frame pointer `fp` has the return
+/// address at `fp - 8` and the caller's frame pointer at `fp - 16`. It is
+/// only followed while it lies in one of our stacks, which are mapped, and
+/// only upwards unless a trap stack was entered from another stack, so a
+/// corrupted stack ends the walk instead of faulting.
+fn backtrace() {
+    let stacks = kernel_stacks();
+    let stack_of = |fp: usize| {
+        stacks.iter().position(|stack| {
+            matches!(stack, Some((bottom, top)) if fp % 8 == 0 && fp >= bottom + 16 && fp <= *top)
+        })
+    };
+    let mut fp: usize;
+    unsafe {
+        asm!("mv {}, s0", out(reg) fp);
+    }
+    let symbols = SymbolTable::get();
+    if symbols.is_none() {
+        println!("[kernel] no symbol table of this kernel, return addresses only");
+    }
+    println!("[kernel] backtrace:");
+    for _ in 0..MAX_FRAMES {
+        let stack = match stack_of(fp) {
+            Some(stack) => stack,
+            None => break,
+        };
+        let (ra, caller_fp) = unsafe { (*((fp - 8) as *const usize), *((fp - 16) as *const usize)) };
+        if ra == 0 {
+            break;
+        }
+        // the call is before the return address, which may already be in
+        // the next function after a call that does not return
+        match symbols.as_ref().and_then(|symbols| symbols.symbolize(ra - 1)) {
+            Some((name, offset)) => {
+                println!("  {:#x} {}+{:#x}", ra, name, offset + 1);
+            }
+            None => {
+                println!("  {:#x} ?", ra);
+            }
+        }
+        if caller_fp <= fp && stack_of(caller_fp) == Some(stack) {
+            break;
+        }
+        fp = caller_fp;
+    }
 }
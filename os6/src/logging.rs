@@ -1,13 +1,88 @@
 //! Global logger
+//!
+//! The level is the `max_level` of the `log` crate, which the macros check
+//! before building the record, so a message below it costs one atomic load.
+//! On top of it the records of the `task`, `mm`, `fs`, `drivers` and
+//! `syscall` modules can be turned off by module, which the logger checks
+//! before formatting anything. Other modules are only filtered by level.
+//!
+//! Both start from the `LOG` and `LOG_MODULES` build variables, e.g.
+//! `make run LOG=INFO LOG_MODULES=mm,fs,pid`, and can be changed at runtime
+//! with [`set_log_level`] and [`set_log_modules`] or by `sys_log_ctl`.
 
+use crate::task::try_current_task;
+use crate::timer::get_time_us;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
+pub const LOG_TASK: usize = 1 << 0;
+pub const LOG_MM: usize = 1 << 1;
+pub const LOG_FS: usize = 1 << 2;
+pub const LOG_DRIVERS: usize = 1 << 3;
+pub const LOG_SYSCALL: usize = 1 << 4;
+pub const LOG_ALL_MODULES: usize = (1 << 5) - 1;
+/// Prefix every line with the pid of the current task
+pub const LOG_PID: usize = 1 << 8;
+/// Prefix every line with the time since boot
+pub const LOG_TIME: usize = 1 << 9;
+
+/// Names of the bits of the module mask, as in `LOG_MODULES`
+const MODULE_NAMES: [(&str, usize); 7] = [
+    ("task", LOG_TASK),
+    ("mm", LOG_MM),
+    ("fs", LOG_FS),
+    ("drivers", LOG_DRIVERS),
+    ("syscall", LOG_SYSCALL),
+    ("pid", LOG_PID),
+    ("time", LOG_TIME),
+];
+
+/// Modules logged and decorations of the lines
+static MODULES: AtomicUsize = AtomicUsize::new(LOG_ALL_MODULES);
+
+/// The kernel module a record comes from, `os::mm::memory_set` is `mm`
+fn module_of(target: &str) -> &str {
+    target.split("::").nth(1).unwrap_or("kernel")
+}
+
+fn module_bit(module: &str) -> Option<usize> {
+    MODULE_NAMES
+        .iter()
+        .find(|(name, _)| *name == module)
+        .map(|(_, bit)| *bit)
+}
+
+/// The time and pid prefixes `mask` asks for
+struct Decorations(usize);
+
+impl fmt::Display for Decorations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 & LOG_TIME != 0 {
+            let us = get_time_us();
+            write!(f, "[{:>5}.{:06}]", us / 1_000_000, us % 1_000_000)?;
+        }
+        if self.0 & LOG_PID != 0 {
+            // the code logging may hold the processor
+            match try_current_task() {
+                Some(task) => write!(f, "[pid {}]", task.getpid())?,
+                None => write!(f, "[idle]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 /// a simple logger
 struct SimpleLogger;
 
 impl Log for SimpleLogger {
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && match module_bit(module_of(metadata.target())) {
+                Some(bit) => MODULES.load(Ordering::Relaxed) & bit != 0,
+                None => true,
+            }
     }
     fn log(&self, record: &Record) {
         if !self.enabled(record.metadata()) {
@@ -21,20 +96,37 @@ impl Log for SimpleLogger {
             Level::Trace => 90, // BrightBlack
         };
         println!(
-            "\u{1B}[{}m[{:>5}] {}\u{1B}[0m",
+            "\u{1B}[{}m[{:>5}]{}[{}] {}\u{1B}[0m",
             color,
             record.level(),
+            Decorations(MODULES.load(Ordering::Relaxed)),
+            module_of(record.target()),
             record.args(),
         );
     }
     fn flush(&self) {}
 }
 
+/// Log records up to `level` from now on
+pub fn set_log_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Log the modules and add the decorations in `mask` from now on
+pub fn set_log_modules(mask: usize) {
+    MODULES.store(mask, Ordering::Relaxed);
+}
+
+/// The current module mask
+pub fn log_modules() -> usize {
+    MODULES.load(Ordering::Relaxed)
+}
+
 /// initiate logger
 pub fn init() {
     static LOGGER: SimpleLogger = SimpleLogger;
     log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(match option_env!("LOG") {
+    set_log_level(match option_env!("LOG") {
         Some("ERROR") => LevelFilter::Error,
         Some("WARN") => LevelFilter::Warn,
         Some("INFO") => LevelFilter::Info,
@@ -42,4 +134,11 @@ pub fn init() {
         Some("TRACE") => LevelFilter::Trace,
         _ => LevelFilter::Off,
     });
+    if let Some(names) = option_env!("LOG_MODULES") {
+        let mask = names
+            .split(',')
+            .filter_map(|name| module_bit(name.trim()))
+            .fold(0, |mask, bit| mask | bit);
+        set_log_modules(mask);
+    }
 }
@@ -12,8 +12,9 @@
 //! initialize various pieces of functionality. (See its source code for
 //! details.)
 //!
-//! We then call [`task::run_first_task()`] and for the first time go to
-//! userspace.
+//! We then call [`task::run_tasks()`] and for the first time go to
+//! userspace. The boot hart also starts the other harts, which enter
+//! [`rust_main_secondary()`] and share the same ready queue.
 
 #![no_std]
 #![no_main]
@@ -29,10 +30,16 @@ extern crate alloc;
 
 #[macro_use]
 mod console;
+#[cfg(feature = "ktest")]
+#[macro_use]
+mod ktest;
 mod config;
+mod crypto;
 mod lang_items;
 mod logging;
 mod mm;
+mod perf;
+mod rand;
 mod sbi;
 mod sync;
 mod syscall;
@@ -56,19 +63,58 @@ fn clear_bss() {
     }
 }
 
+/// start every other hart in [0, MAX_HARTS) at `_start_secondary`
+fn start_other_harts(boot_hartid: usize) {
+    extern "C" {
+        fn _start_secondary();
+    }
+    // everything initialized so far must be visible to the new harts
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    for hartid in (0..config::MAX_HARTS).filter(|&id| id != boot_hartid) {
+        // harts missing from the machine just fail to start
+        if sbi::hart_start(hartid, _start_secondary as usize, 0) == 0 {
+            debug!("[kernel] starting hart {}", hartid);
+        }
+    }
+}
+
 #[no_mangle]
 /// the rust entry-point of os
-pub fn rust_main() -> ! {
+pub fn rust_main(hartid: usize) -> ! {
     clear_bss();
     logging::init();
     println!("[kernel] Hello, world!");
+    assert!(hartid < config::MAX_HARTS, "boot hart {} out of range", hartid);
     mm::init();
     mm::remap_test();
+    mm::frame_allocator_fragmentation_test();
     trap::init();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
+    perf::init(true);
+    drivers::init();
+    drivers::init_interrupts();
+    rand::init();
     fs::list_apps();
+    #[cfg(feature = "ktest")]
+    ktest::run_all();
+    mm::init_swap();
     task::add_initproc();
+    start_other_harts(hartid);
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+#[no_mangle]
+/// the rust entry-point of the other harts, once the boot hart is done
+pub fn rust_main_secondary(hartid: usize) -> ! {
+    mm::KERNEL_SPACE.exclusive_access().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    perf::init(false);
+    drivers::init_interrupts();
+    info!("[kernel] hart {} is online", hartid);
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
@@ -3,9 +3,12 @@
 
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::sync::{spin_locks_held, SpinLock};
+use crate::task::swap_out_pages;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 
 /// manage a frame which has the same lifecycle as the tracker
@@ -36,61 +39,148 @@ impl Drop for FrameTracker {
     }
 }
 
+/// manage `1 << order` physically contiguous frames starting at `ppn`
+pub struct FrameRangeTracker {
+    pub ppn: PhysPageNum,
+    pub order: usize,
+}
+
+impl FrameRangeTracker {
+    pub fn new(ppn: PhysPageNum, order: usize) -> Self {
+        for i in 0..1 << order {
+            PhysPageNum(ppn.0 + i).get_bytes_array().fill(0);
+        }
+        Self { ppn, order }
+    }
+    pub fn pages(&self) -> usize {
+        1 << self.order
+    }
+}
+
+impl Debug for FrameRangeTracker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "FrameRangeTracker:PPN={:#x},pages={}",
+            self.ppn.0,
+            self.pages()
+        ))
+    }
+}
+
+impl Drop for FrameRangeTracker {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR
+            .exclusive_access()
+            .dealloc_contiguous(self.ppn, self.order);
+    }
+}
+
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// Number of frames that can still be allocated
+    fn available(&self) -> usize;
+    /// Number of frames managed in all
+    fn total(&self) -> usize;
 }
 
-/// an implementation for frame allocator
-pub struct StackFrameAllocator {
-    current: usize,
+/// Largest contiguous run handed out is `1 << MAX_ORDER` frames
+pub const MAX_ORDER: usize = 10;
+
+/// Buddy allocator over the frame range. A free block of order `k` is
+/// `1 << k` frames aligned to its size relative to the first frame, its
+/// buddy is the block it was split from and is merged back with.
+pub struct BuddyFrameAllocator {
+    start: usize,
     end: usize,
-    recycled: Vec<usize>,
+    /// Offsets from `start` of the free blocks of each order
+    free_lists: [BTreeSet<usize>; MAX_ORDER + 1],
+    free: usize,
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+        self.start = l.0;
         self.end = r.0;
-        info!("last {} Physical Frames.", self.end - self.current);
+        // carve the range into the largest aligned blocks
+        let mut offset = 0;
+        let total = self.end - self.start;
+        while offset < total {
+            let mut order = MAX_ORDER;
+            while offset % (1 << order) != 0 || offset + (1 << order) > total {
+                order -= 1;
+            }
+            self.free_lists[order].insert(offset);
+            offset += 1 << order;
+        }
+        self.free = total;
+        info!("last {} Physical Frames.", total);
+    }
+    /// Allocate `1 << order` contiguous frames, returns the first one
+    pub fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        let from = (order..=MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let offset = *self.free_lists[from].iter().next().unwrap();
+        self.free_lists[from].remove(&offset);
+        // hand the upper halves back while splitting down to `order`
+        for k in (order..from).rev() {
+            self.free_lists[k].insert(offset + (1 << k));
+        }
+        self.free -= 1 << order;
+        Some(PhysPageNum(self.start + offset))
+    }
+    /// Free the `1 << order` frames from `ppn`, merging with free buddies
+    pub fn dealloc_contiguous(&mut self, ppn: PhysPageNum, order: usize) {
+        let mut offset = ppn.0.wrapping_sub(self.start);
+        // validity check
+        if ppn.0 < self.start || ppn.0 + (1 << order) > self.end || offset % (1 << order) != 0 {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn.0);
+        }
+        if (0..=MAX_ORDER).any(|k| self.free_lists[k].contains(&(offset & !((1 << k) - 1)))) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn.0);
+        }
+        self.free += 1 << order;
+        let mut order = order;
+        while order < MAX_ORDER && self.free_lists[order].remove(&(offset ^ (1 << order))) {
+            offset &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(offset);
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            start: 0,
             end: 0,
-            recycled: Vec::new(),
+            free_lists: Default::default(),
+            free: 0,
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None
-        } else {
-            self.current += 1;
-            Some((self.current - 1).into())
-        }
+        self.alloc_contiguous(0)
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        let ppn = ppn.0;
-        // validity check
-        if ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
-            panic!("Frame ppn={:#x} has not been allocated!", ppn);
-        }
-        // recycle
-        self.recycled.push(ppn);
+        self.dealloc_contiguous(ppn, 0);
+    }
+    fn available(&self) -> usize {
+        self.free
+    }
+    fn total(&self) -> usize {
+        self.end - self.start
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinLock<FrameAllocatorImpl> =
+        SpinLock::new(FrameAllocatorImpl::new());
 }
 
 pub fn init_frame_allocator() {
@@ -103,12 +193,61 @@ pub fn init_frame_allocator() {
     );
 }
 
-/// initiate the frame allocator using `ekernel` and `MEMORY_END`
+lazy_static! {
+    /// The frame untouched anonymous pages read from until their first
+    /// write, it is only ever mapped read-only
+    static ref ZERO_FRAME: FrameTracker = frame_alloc().expect("no frame for the zero page");
+}
+
+/// Allocate the zero frame up front, rather than on the first read fault
+pub fn init_zero_frame() {
+    lazy_static::initialize(&ZERO_FRAME);
+}
+
+/// The shared frame of zeros
+pub fn zero_frame() -> PhysPageNum {
+    ZERO_FRAME.ppn
+}
+
+/// Most frames ever in use at once
+static FRAMES_HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+
+/// Allocate a zeroed frame, swapping out a page of another process if
+/// memory runs out. None if there is still no frame then.
+///
+/// Swapping waits for the disk, so a caller holding a SpinLock gets None
+/// right away. Such paths make room up front, see [`frames_available`].
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+    let ppn = match alloc_one() {
+        Some(ppn) => ppn,
+        None if spin_locks_held() == 0 => {
+            // make room by swapping out a page of some other process
+            swap_out_pages(1, false, 0);
+            alloc_one()?
+        }
+        None => return None,
+    };
+    Some(FrameTracker::new(ppn))
+}
+
+fn alloc_one() -> Option<PhysPageNum> {
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    let ppn = allocator.alloc()?;
+    let in_use = allocator.total() - allocator.available();
+    drop(allocator);
+    FRAMES_HIGH_WATER.fetch_max(in_use, Ordering::Relaxed);
+    Some(ppn)
+}
+
+/// Allocate `1 << order` zeroed, physically contiguous frames, None if no
+/// free run is large enough
+pub fn frame_alloc_contiguous(order: usize) -> Option<FrameRangeTracker> {
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    let ppn = allocator.alloc_contiguous(order)?;
+    let in_use = allocator.total() - allocator.available();
+    drop(allocator);
+    FRAMES_HIGH_WATER.fetch_max(in_use, Ordering::Relaxed);
+    Some(FrameRangeTracker::new(ppn, order))
 }
 
 /// deallocate a frame
@@ -116,6 +255,28 @@ pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
+/// Number of frames that can still be allocated
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().available()
+}
+
+/// Frames managed in all and still free
+pub fn frame_allocator_stats() -> (usize, usize) {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    (allocator.total(), allocator.available())
+}
+
+/// Most frames ever in use at once
+pub fn frames_high_water() -> usize {
+    FRAMES_HIGH_WATER.load(Ordering::Relaxed)
+}
+
+/// Whether `pages` frames, plus the page tables to map them, can be allocated
+/// right now. Paths that must not panic on exhaustion check it up front.
+pub fn frames_available(pages: usize) -> bool {
+    FRAME_ALLOCATOR.exclusive_access().available() >= pages + pages / 512 + 3
+}
+
 #[allow(unused)]
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
@@ -134,3 +295,36 @@ pub fn frame_allocator_test() {
     drop(v);
     info!("frame_allocator_test passed!");
 }
+
+/// Exhaust the frames, give them back in an interleaved order and check that
+/// they coalesce into exactly the free blocks there were before, then do the
+/// same with blocks of mixed orders
+pub fn frame_allocator_fragmentation_test() {
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    let free_lists = allocator.free_lists.clone();
+    let free = allocator.available();
+    let mut frames = Vec::with_capacity(free);
+    while let Some(ppn) = allocator.alloc() {
+        frames.push(ppn);
+    }
+    assert_eq!(frames.len(), free);
+    assert!(allocator.alloc_contiguous(0).is_none());
+    for ppn in frames.iter().step_by(2).chain(frames.iter().skip(1).step_by(2)) {
+        allocator.dealloc(*ppn);
+    }
+    assert_eq!(allocator.available(), free);
+    assert!(allocator.free_lists == free_lists);
+    drop(frames);
+
+    let blocks: Vec<(PhysPageNum, usize)> = (0..64)
+        .map(|i| (allocator.alloc_contiguous(i % 4).unwrap(), i % 4))
+        .collect();
+    for (ppn, order) in blocks.iter().skip(1).step_by(2).chain(blocks.iter().step_by(2)) {
+        allocator.dealloc_contiguous(*ppn, *order);
+    }
+    assert!(allocator.free_lists == free_lists);
+    let ppn = allocator.alloc_contiguous(MAX_ORDER).unwrap();
+    assert_eq!((ppn.0 - allocator.start) % (1 << MAX_ORDER), 0);
+    allocator.dealloc_contiguous(ppn, MAX_ORDER);
+    info!("frame_allocator_fragmentation_test passed!");
+}
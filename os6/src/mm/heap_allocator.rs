@@ -1,11 +1,56 @@
 //! The global allocator
+//!
+//! A buddy allocator over a static array, wrapped to count what is
+//! allocated. An allocation the kernel cannot do without still panics when
+//! the heap runs out. The larger ones that a syscall can fail instead go
+//! through `try_reserve`, or check [`heap_low`] first, and return ENOMEM.
 
-use crate::config::KERNEL_HEAP_SIZE;
+use crate::config::{KERNEL_HEAP_RESERVE, KERNEL_HEAP_SIZE};
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The buddy allocator counting its allocations, with atomics so that the
+/// panic handler reads them without the heap lock
+struct TrackedHeap {
+    heap: LockedHeap,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for TrackedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.heap.alloc(layout);
+        if ptr.is_null() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.allocs.fetch_add(1, Ordering::Relaxed);
+            let used = self.used.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(used, Ordering::Relaxed);
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap.dealloc(ptr, layout);
+        self.frees.fetch_add(1, Ordering::Relaxed);
+        self.used.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
 
 #[global_allocator]
 /// heap allocator instance
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: TrackedHeap = TrackedHeap {
+    heap: LockedHeap::empty(),
+    used: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+    allocs: AtomicUsize::new(0),
+    frees: AtomicUsize::new(0),
+    failures: AtomicUsize::new(0),
+};
 
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
@@ -20,11 +65,60 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+/// Bytes of the kernel heap: requested by allocations, taken by them after
+/// rounding up to a power of two, and in total
+pub fn heap_stats() -> (usize, usize, usize) {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    (heap.stats_alloc_user(), heap.stats_alloc_actual(), heap.stats_total_bytes())
+}
+
+/// Use of the kernel heap since boot, bytes as requested
+#[derive(Debug, Clone, Copy)]
+pub struct KernelHeapStats {
+    pub used: usize,
+    pub peak: usize,
+    pub total: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    /// Allocations the heap could not satisfy, failed gracefully or not
+    pub failures: usize,
+}
+
+impl fmt::Display for KernelHeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "heap {} of {} bytes in use, peak {}, {} allocs, {} frees, {} failed",
+            self.used, self.total, self.peak, self.allocs, self.frees, self.failures
+        )
+    }
+}
+
+/// Read without taking the heap lock, the counters may be a moment apart
+pub fn kernel_heap_stats() -> KernelHeapStats {
+    KernelHeapStats {
+        used: HEAP_ALLOCATOR.used.load(Ordering::Relaxed),
+        peak: HEAP_ALLOCATOR.peak.load(Ordering::Relaxed),
+        total: KERNEL_HEAP_SIZE,
+        allocs: HEAP_ALLOCATOR.allocs.load(Ordering::Relaxed),
+        frees: HEAP_ALLOCATOR.frees.load(Ordering::Relaxed),
+        failures: HEAP_ALLOCATOR.failures.load(Ordering::Relaxed),
+    }
+}
+
+/// Whether less than [`KERNEL_HEAP_RESERVE`] of the heap is left, which is
+/// kept for the allocations that cannot fail
+pub fn heap_low() -> bool {
+    let (_, actual, total) = heap_stats();
+    total - actual < KERNEL_HEAP_RESERVE
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
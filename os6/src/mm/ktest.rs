@@ -0,0 +1,83 @@
+//! Self-tests of memory management, see [`crate::ktest`]
+
+use super::{
+    frame_alloc, frame_alloc_contiguous, free_frames, kernel_heap_stats, FrameTracker,
+    PTEFlags, PageTable, VirtPageNum,
+};
+use alloc::vec::Vec;
+
+/// Frames taken one by one and as a contiguous range are distinct, counted
+/// and all given back when dropped
+pub fn frame_balance() -> Result<(), &'static str> {
+    let free = free_frames();
+    let frames: Vec<FrameTracker> = (0..64).filter_map(|_| frame_alloc()).collect();
+    kassert!(frames.len() == 64, "out of frames");
+    kassert!(free_frames() == free - 64, "single frames miscounted");
+    let mut ppns: Vec<usize> = frames.iter().map(|frame| frame.ppn.0).collect();
+    ppns.sort_unstable();
+    ppns.dedup();
+    kassert!(ppns.len() == 64, "a frame handed out twice");
+    let range = frame_alloc_contiguous(3).ok_or("out of contiguous frames")?;
+    kassert!(range.pages() == 8, "range of the wrong size");
+    kassert!(free_frames() == free - 64 - 8, "contiguous frames miscounted");
+    kassert!(
+        ppns.iter().all(|&ppn| ppn < range.ppn.0 || ppn >= range.ppn.0 + 8),
+        "range overlaps a single frame"
+    );
+    drop(range);
+    drop(frames);
+    kassert!(free_frames() == free, "frames not given back");
+    Ok(())
+}
+
+/// The kernel heap uses as much after freeing what it allocated as before
+pub fn heap_balance() -> Result<(), &'static str> {
+    let used = kernel_heap_stats().used;
+    let blocks: Vec<Vec<u8>> = (0..32).map(|i| alloc::vec![i as u8; 64 << (i % 8)]).collect();
+    kassert!(kernel_heap_stats().used > used, "allocations not counted");
+    kassert!(
+        blocks.iter().enumerate().all(|(i, block)| block.iter().all(|&b| b == i as u8)),
+        "allocations overlap"
+    );
+    drop(blocks);
+    kassert!(kernel_heap_stats().used == used, "heap not given back");
+    Ok(())
+}
+
+/// Pages mapped into a fresh page table translate to their frames with
+/// their flags, are gone once unmapped, and the table gives all its
+/// frames back when dropped
+pub fn page_table_roundtrip() -> Result<(), &'static str> {
+    let free = free_frames();
+    let mut page_table = PageTable::new().ok_or("out of frames")?;
+    // across the boundary of two leaf tables
+    let vpns: Vec<VirtPageNum> = (0x1fe..0x202).map(VirtPageNum).collect();
+    let frames: Vec<FrameTracker> = vpns.iter().filter_map(|_| frame_alloc()).collect();
+    kassert!(frames.len() == vpns.len(), "out of frames");
+    for (&vpn, frame) in vpns.iter().zip(frames.iter()) {
+        kassert!(page_table.try_map(vpn, frame.ppn, PTEFlags::R | PTEFlags::W), "cannot map");
+    }
+    for (&vpn, frame) in vpns.iter().zip(frames.iter()) {
+        let pte = page_table.translate(vpn).ok_or("mapped page not found")?;
+        kassert!(pte.is_valid() && pte.ppn() == frame.ppn, "mapped to another frame");
+        kassert!(pte.readable() && pte.writable() && !pte.executable(), "mapped with other flags");
+    }
+    let unmapped = VirtPageNum(0x300);
+    kassert!(
+        page_table.translate(unmapped).map_or(true, |pte| !pte.is_valid()),
+        "page never mapped found"
+    );
+    for &vpn in vpns.iter() {
+        page_table.unmap(vpn);
+    }
+    for &vpn in vpns.iter() {
+        kassert!(
+            page_table.translate(vpn).map_or(true, |pte| !pte.is_valid()),
+            "unmapped page still found"
+        );
+    }
+    drop(page_table);
+    drop(frames);
+    kassert!(free_frames() == free, "page table frames not given back");
+    Ok(())
+}
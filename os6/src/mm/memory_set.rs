@@ -1,14 +1,26 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
+use super::shm::ShmAttachment;
+use super::frame_allocator::zero_frame;
+use super::swap::{is_pinned, SwapSlot};
+use super::{frame_alloc, frame_allocator_stats, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE, MMIO};
-use crate::sync::UPSafeCell;
-use alloc::collections::BTreeMap;
+use crate::config::{
+    ASLR_HEAP_WINDOW, ASLR_MMAP_WINDOW, ASLR_STACK_WINDOW, MEMORY_END, MMIO, PAGE_SIZE,
+    TRAMPOLINE, TRAP_CONTEXT, USER_SPACE_END, USER_STACK_GROWTH_WINDOW, USER_STACK_LIMIT,
+    USER_STACK_SIZE,
+};
+use crate::rand::rand_below;
+use crate::sync::SpinLock;
+use crate::syscall::errno::{EACCES, EEXIST, EINVAL, ENOMEM};
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
+use easy_fs::Inode;
+use xmas_elf::header::{Class, Machine};
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -19,16 +31,116 @@ extern "C" {
     fn erodata();
     fn sdata();
     fn edata();
+    fn ssymbols();
+    fn esymbols();
     fn sbss_with_stack();
     fn ebss();
     fn ekernel();
     fn strampoline();
 }
 
+/// Size of the elf header of a 64-bit elf file
+const ELF_HEADER_SIZE: usize = 64;
+/// Size of a program header of a 64-bit elf file
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
+/// `e_machine` of RISC-V, which xmas-elf does not name
+const EM_RISCV: u16 = 0xf3;
+
+/// Why [`MemorySet::from_elf`] refused an executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// No elf magic, or a header xmas-elf cannot parse
+    NotElf,
+    /// Not a 64-bit elf file
+    WrongClass,
+    /// Built for another machine than RISC-V
+    WrongMachine,
+    /// The headers or a segment reach past the end of the file
+    Truncated,
+    /// A segment larger in the file than in memory, sharing a page with
+    /// another, beyond the user address space or misaligned in the file,
+    /// or no segment to load at all
+    BadSegment,
+    /// Frames ran out
+    NoMemory,
+}
+
 lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> =
+        Arc::new(SpinLock::new(MemorySet::new_kernel()));
+    /// Dirty pages of shared file mappings given up under the TCB lock,
+    /// waiting for [`write_back_pages`]
+    static ref DIRTY_PAGES: SpinLock<Vec<DirtyPage>> = SpinLock::new(Vec::new());
+}
+
+/// A page of a shared file mapping to be written back at `pos` of `inode`
+struct DirtyPage {
+    inode: Arc<Inode>,
+    pos: usize,
+    frame: Arc<FrameTracker>,
+}
+
+/// Write back the dirty pages of shared file mappings that were unmapped,
+/// synced or torn down with their address space locked. Not beyond the end
+/// of the file, and not at all once a restore of a snapshot replaced it.
+///
+/// This waits for the disk, the caller must not hold a SpinLock.
+pub fn write_back_pages() {
+    let pages = core::mem::take(&mut *DIRTY_PAGES.exclusive_access());
+    for page in pages {
+        if page.inode.is_stale() {
+            continue;
+        }
+        let len = page.inode.size().saturating_sub(page.pos).min(PAGE_SIZE);
+        if len > 0 {
+            page.inode.write_at(page.pos, &page.frame.ppn.get_bytes_array()[..len]);
+        }
+    }
+}
+
+/// How far [`MemorySet::handle_page_fault`] got
+pub enum Fault {
+    /// The page is there now, or the access is not allowed if false
+    Done(bool),
+    /// The page is still to be read in, see [`PageIn`]
+    PageIn(PageIn),
+}
+
+/// A page to read from a file or swap. The read waits for the disk, so it
+/// is done with the TCB lock let go, and the page is mapped after by
+/// [`MemorySet::finish_page_in`].
+pub struct PageIn {
+    vpn: VirtPageNum,
+    write: bool,
+    source: PageSource,
+}
+
+enum PageSource {
+    Swap(Arc<SwapSlot>),
+    /// The first `len` bytes of the page from `pos` of `inode`, the rest
+    /// reads as zero
+    File {
+        inode: Arc<Inode>,
+        pos: usize,
+        len: usize,
+    },
+}
+
+impl PageIn {
+    /// Read the page into a new frame, None if frames run out. The caller
+    /// must not hold a SpinLock.
+    pub fn read(&self) -> Option<FrameTracker> {
+        let frame = frame_alloc()?;
+        match &self.source {
+            PageSource::Swap(slot) => slot.read(frame.ppn.get_bytes_array()),
+            PageSource::File { inode, pos, len } => {
+                inode.read_at(*pos, &mut frame.ppn.get_bytes_array()[..*len]);
+            }
+        }
+        Some(frame)
+    }
 }
 
 /// Get the token of the kernel memory space
@@ -40,29 +152,124 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// The unmapped page right below the user stack
+    stack_guard: Option<VirtPageNum>,
+    /// The elf file the user areas were loaded from
+    executable: Option<Arc<Inode>>,
+    /// Most frames ever backing the areas at once
+    peak_resident: usize,
 }
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    /// None if there is no frame left for the root page table
+    pub fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
             areas: Vec::new(),
-        }
+            stack_guard: None,
+            executable: None,
+            peak_resident: 0,
+        })
     }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
-    /// Assume that no conflicts.
+    /// Assume that no conflicts. Returns false, leaving nothing mapped, if
+    /// frames run out.
+    #[must_use]
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
-        self.push(
+    ) -> bool {
+        self.try_push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
+        )
+    }
+    /// Insert a framed area on `[start_va, end_va)` for user space, failing
+    /// with -EEXIST if it overlaps an existing area or -ENOMEM if it leaves
+    /// the user half of the address space.
+    /// Nothing is mapped until the pages are touched.
+    pub fn insert_framed_area_checked(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        backing: MapAreaBacking,
+    ) -> isize {
+        if usize::from(end_va) > USER_SPACE_END {
+            return -ENOMEM;
+        }
+        if self.conflict_with_range(start_va, end_va) {
+            return -EEXIST;
+        }
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        map_area.backing = backing;
+        self.areas.push(map_area);
+        0
+    }
+    /// The lowest page-aligned address from `from` where `len` bytes fit
+    /// below the TrapContext without overlapping an area
+    pub fn find_free_range(&self, from: VirtAddr, len: usize) -> Option<VirtAddr> {
+        let mut start: usize = VirtAddr::from(from.ceil()).into();
+        loop {
+            let end = start.checked_add(len).filter(|&end| end <= USER_SPACE_END)?;
+            match self
+                .areas
+                .iter()
+                .find(|area| area.conflict_with_range(start.into(), end.into()))
+            {
+                Some(area) => start = VirtAddr::from(area.vpn_range.get_end()).into(),
+                None => return Some(start.into()),
+            }
+        }
+    }
+    /// Map the `frames` of a shared memory segment at `start_va`, which must
+    /// be free, see [`Self::find_free_range`]. Returns false, leaving nothing
+    /// mapped, if frames for the page table run out.
+    #[must_use]
+    pub fn insert_shm_area(
+        &mut self,
+        start_va: VirtAddr,
+        attachment: ShmAttachment,
+        frames: Vec<Arc<FrameTracker>>,
+    ) -> bool {
+        let end_va = VirtAddr::from(usize::from(start_va) + frames.len() * PAGE_SIZE);
+        let mut map_area = MapArea::new(
+            start_va,
+            end_va,
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
         );
+        map_area.backing = MapAreaBacking::Shm(attachment);
+        for (vpn, frame) in map_area.vpn_range.into_iter().zip(frames) {
+            if !self.page_table.try_map(vpn, frame.ppn, map_area.pte_flags(vpn)) {
+                map_area.unmap(&mut self.page_table);
+                return false;
+            }
+            map_area.data_frames.insert(vpn, frame);
+        }
+        self.areas.push(map_area);
+        self.update_peak();
+        true
+    }
+    /// Detach the shared memory segment attached at `start_vpn`. Returns
+    /// false if there is none.
+    pub fn remove_shm_area(&mut self, start_vpn: VirtPageNum) -> bool {
+        match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start_vpn && area.is_shm())
+        {
+            Some(idx) => {
+                let mut area = self.areas.remove(idx);
+                area.unmap(&mut self.page_table);
+                true
+            }
+            None => false,
+        }
     }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -75,30 +282,41 @@ impl MemorySet {
             self.areas.remove(idx);
         }
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        assert!(self.try_push(map_area, data), "out of frames");
+    }
+    /// Map and add `map_area`, returns false if frames run out
+    fn try_push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> bool {
+        if !map_area.map(&mut self.page_table) {
+            return false;
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        self.update_peak();
+        true
     }
     /// Mention that trampoline is not collected by areas.
-    fn map_trampoline(&mut self) {
-        self.page_table.map(
+    #[must_use]
+    fn map_trampoline(&mut self) -> bool {
+        self.page_table.try_map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        let free_before = frame_allocator_stats().1;
+        let mut memory_set = Self::new_bare().unwrap();
         // map trampoline
-        memory_set.map_trampoline();
+        assert!(memory_set.map_trampoline());
         // map kernel sections
         info!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         info!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
         info!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
+        info!(".symbols [{:#x}, {:#x})", ssymbols as usize, esymbols as usize);
         info!(
             ".bss [{:#x}, {:#x})",
             sbss_with_stack as usize, ebss as usize
@@ -133,6 +351,16 @@ impl MemorySet {
             ),
             None,
         );
+        info!("mapping .symbols section");
+        memory_set.push(
+            MapArea::new(
+                (ssymbols as usize).into(),
+                (esymbols as usize).into(),
+                MapType::Identical,
+                MapPermission::R,
+            ),
+            None,
+        );
         info!("mapping .bss section");
         memory_set.push(
             MapArea::new(
@@ -164,95 +392,222 @@ impl MemorySet {
                 ),
             None);
         }
+        // identical areas own no frames, all of them went to the page table
+        info!(
+            "kernel page table: {} frames, {} saved by megapages",
+            free_before - frame_allocator_stats().1,
+            memory_set.page_table.megapages()
+        );
         memory_set
     }
-    /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// Include sections in the elf file `elf_inode` and trampoline and user
+    /// stack, also returns user_sp, the base of the
+    /// empty heap, entry point and the offsets the stack and heap were
+    /// moved by. Only the headers are read here, the segments are paged in
+    /// from the file as they are touched.
+    /// Fails if the file is not a 64-bit RISC-V executable we can load, see
+    /// [`LoadError`], or if frames run out; nothing is left allocated then.
+    pub fn from_elf(
+        elf_inode: &Arc<Inode>,
+    ) -> Result<(Self, usize, usize, usize, LayoutOffsets), LoadError> {
+        let file_size = elf_inode.size();
+        // read the elf header, then again up to the end of the program headers.
+        // xmas-elf slices the input as the headers say, so their sizes are
+        // checked before it parses any further
+        let mut elf_data = vec![0u8; ELF_HEADER_SIZE];
+        if elf_inode.read_at(0, &mut elf_data) < ELF_HEADER_SIZE {
+            return Err(LoadError::Truncated);
+        }
+        let elf = xmas_elf::ElfFile::new(&elf_data).map_err(|_| LoadError::NotElf)?;
+        let elf_header = elf.header;
+        if elf_header.pt1.magic != ELF_MAGIC {
+            return Err(LoadError::NotElf);
+        }
+        if elf_header.pt1.class() != Class::SixtyFour {
+            return Err(LoadError::WrongClass);
+        }
+        if elf_header.pt2.machine().as_machine() != Machine::Other(EM_RISCV) {
+            return Err(LoadError::WrongMachine);
+        }
+        let ph_count = elf_header.pt2.ph_count() as usize;
+        if ph_count == 0 || elf_header.pt2.ph_entry_size() as usize != PROGRAM_HEADER_SIZE {
+            return Err(LoadError::BadSegment);
+        }
+        let ph_end = elf_header.pt2.ph_offset() as usize + ph_count * PROGRAM_HEADER_SIZE;
+        if ph_end > file_size {
+            return Err(LoadError::Truncated);
+        }
+        let mut elf_data = vec![0u8; ph_end.max(ELF_HEADER_SIZE)];
+        if elf_inode.read_at(0, &mut elf_data) < elf_data.len() {
+            return Err(LoadError::Truncated);
+        }
+        let elf = xmas_elf::ElfFile::new(&elf_data).map_err(|_| LoadError::NotElf)?;
+        let mut memory_set = Self::new_bare().ok_or(LoadError::NoMemory)?;
+        memory_set.executable = Some(Arc::clone(elf_inode));
         // map trampoline
-        memory_set.map_trampoline();
+        if !memory_set.map_trampoline() {
+            return Err(LoadError::NoMemory);
+        }
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+            let ph = elf.program_header(i as u16).map_err(|_| LoadError::BadSegment)?;
+            if ph.get_type() != Ok(xmas_elf::program::Type::Load) {
+                continue;
+            }
+            let (offset, file_len) = (ph.offset() as usize, ph.file_size() as usize);
+            let (vaddr, mem_len) = (ph.virtual_addr() as usize, ph.mem_size() as usize);
+            if mem_len < file_len {
+                return Err(LoadError::BadSegment);
+            }
+            if offset.checked_add(file_len).map_or(true, |end| end > file_size) {
+                return Err(LoadError::Truncated);
             }
+            // below the user address space end, and so below the trap
+            // context and the trampoline
+            let end = match vaddr.checked_add(mem_len) {
+                Some(end) if end <= USER_SPACE_END => end,
+                _ => return Err(LoadError::BadSegment),
+            };
+            let start_va: VirtAddr = vaddr.into();
+            let end_va: VirtAddr = end.into();
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+            // segments are mapped by the page, so no two may share one
+            let start_vpn = map_area.vpn_range.get_start();
+            let end_vpn = map_area.vpn_range.get_end();
+            if memory_set.areas.iter().any(|area| {
+                area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+            }) {
+                return Err(LoadError::BadSegment);
+            }
+            max_end_vpn = max_end_vpn.max(end_vpn);
+            // pages come from the file, so the segment must sit at the
+            // same offset within a page in the file as in memory
+            let page_offset = start_va.page_offset();
+            if offset % PAGE_SIZE != page_offset {
+                return Err(LoadError::BadSegment);
+            }
+            // private, writes to data pages stay in the process, and
+            // the bss past the file size reads as zero
+            map_area.backing = MapAreaBacking::File {
+                inode: Arc::clone(elf_inode),
+                offset: offset - page_offset,
+                len: page_offset + file_len,
+                shared: false,
+                writable: false,
+            };
+            memory_set.areas.push(map_area);
+        }
+        if max_end_vpn == VirtPageNum(0) {
+            return Err(LoadError::BadSegment);
         }
         // map user stack with U flags
+        let layout = LayoutOffsets::new();
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
+        let mut user_stack_bottom: usize = usize::from(max_end_va) + layout.stack;
+        // guard page, a fault there is reported as a stack overflow
+        memory_set.stack_guard = Some(VirtAddr::from(user_stack_bottom).floor());
         user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
+        let user_stack_top = user_stack_bottom + USER_STACK_LIMIT;
+        // only the top of the stack is populated, it grows on faults below
+        let mut stack_area = MapArea::new(
+            user_stack_bottom.into(),
+            user_stack_top.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        // map TrapContext
+        let populated = VPNRange::new(
+            VirtAddr::from(user_stack_top - USER_STACK_SIZE).floor(),
+            VirtAddr::from(user_stack_top).floor(),
+        );
+        for vpn in populated {
+            if !stack_area.map_one(&mut memory_set.page_table, vpn) {
+                return Err(LoadError::NoMemory);
+            }
+        }
+        memory_set.areas.push(stack_area);
+        // the heap grows from above the stack top, see `resize_area`
+        let heap_base = user_stack_top + layout.heap;
         memory_set.push(
             MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
+                heap_base.into(),
+                heap_base.into(),
                 MapType::Framed,
-                MapPermission::R | MapPermission::W,
+                MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
         );
-        (
+        Ok((
             memory_set,
             user_stack_top,
+            heap_base,
             elf.header.pt2.entry_point() as usize,
-        )
+            layout,
+        ))
     }
-    /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
-        let mut memory_set = Self::new_bare();
+    /// Copy an identical user_space, None if frames run out
+    pub fn from_existed_user(user_space: &MemorySet) -> Option<MemorySet> {
+        let mut memory_set = Self::new_bare()?;
+        memory_set.stack_guard = user_space.stack_guard;
+        memory_set.executable = user_space.executable.clone();
         // map trampoline
-        memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        if !memory_set.map_trampoline() {
+            return None;
+        }
+        // copy data sections/user_stack, the TrapContext is mapped by
+        // `map_trap_context` when the copy first runs
+        let trap_cx_vpn = VirtAddr::from(TRAP_CONTEXT).floor();
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            if area.vpn_range.get_start() == trap_cx_vpn {
+                continue;
+            }
+            let mut new_area = MapArea::from_another(area);
+            let mapped = if !area.map_perm.contains(MapPermission::W)
+                || area.is_shared_file()
+                || area.is_shm()
+            {
+                // code and read-only data never change, share their frames,
+                // as do both sides of a shared file mapping or an attached
+                // shared memory segment
+                new_area.map_shared(&mut memory_set.page_table, area)
+            } else {
+                new_area.map_copied(&mut memory_set.page_table, area)
+            };
+            // the partial copy goes away with its page table
+            if !mapped {
+                return None;
             }
+            memory_set.areas.push(new_area);
         }
-        memory_set
+        memory_set.update_peak();
+        Some(memory_set)
+    }
+    /// Map the TrapContext, which [`Self::from_elf`] and
+    /// [`Self::from_existed_user`] leave out. None if frames run out.
+    pub fn map_trap_context(&mut self) -> Option<PhysPageNum> {
+        if !self.try_push(
+            MapArea::new(
+                TRAP_CONTEXT.into(),
+                TRAMPOLINE.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W,
+            ),
+            None,
+        ) {
+            return None;
+        }
+        Some(self.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap().ppn())
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -269,17 +624,289 @@ impl MemorySet {
             area.conflict_with_range(start_va, end_va)
         }).is_some()
     }
-    pub fn unmap_area_by_exact_range(&mut self, start_vn: VirtPageNum, end_vn: VirtPageNum) -> isize {
-        for i in 0..self.areas.len(){
-            let area = &mut self.areas[i];
-            let vrange = area.vpn_range;
-            if start_vn == vrange.get_start() && end_vn == vrange.get_end() {
-                area.unmap(&mut self.page_table);
-                self.areas.swap_remove(i);
-                return 0;
+    /// Whether every page of `[start_vpn, end_vpn)` lies in a user area
+    fn covered_by_user_areas(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let mut covered = 0;
+        for area in self.areas.iter() {
+            let (start, end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if end <= start_vpn || start >= end_vpn {
+                continue;
+            }
+            if !area.map_perm.contains(MapPermission::U) {
+                return false;
+            }
+            covered += end.min(end_vpn).0 - start.max(start_vpn).0;
+        }
+        covered == end_vpn.0 - start_vpn.0
+    }
+    /// Unmap the pages of `[start_vpn, end_vpn)` and free their frames.
+    ///
+    /// Every page in the range must be mapped by user areas. Areas sticking
+    /// out of the range are split and keep the pages outside it. Returns
+    /// -EINVAL otherwise, leaving everything mapped.
+    pub fn remove_area_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> isize {
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -EINVAL;
+        }
+        let mut tails = Vec::new();
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            let (start, end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if end <= start_vpn || start >= end_vpn {
+                return true;
+            }
+            if end > end_vpn {
+                tails.push(area.split_off(end_vpn));
+            }
+            if start < start_vpn {
+                let mut hole = area.split_off(start_vpn);
+                hole.unmap(page_table);
+                true
+            } else {
+                area.unmap(page_table);
+                false
+            }
+        });
+        self.areas.extend(tails);
+        0
+    }
+    /// Change the permissions of the pages of `[start_vpn, end_vpn)` to
+    /// `perm`, the U flag is added.
+    ///
+    /// Every page in the range must be mapped by user areas, and a shared
+    /// file mapping can only become writable if its file was opened for
+    /// writing. Areas sticking out of the range are split. Returns -ENOMEM
+    /// for an unmapped page or if frames run out while copying pages shared
+    /// with a forked address space, -EACCES for a shared mapping that cannot
+    /// become writable, leaving the permissions unchanged.
+    pub fn protect_area_range(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        perm: MapPermission,
+    ) -> isize {
+        let in_range = |area: &MapArea| {
+            area.vpn_range.get_end() > start_vpn && area.vpn_range.get_start() < end_vpn
+        };
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -ENOMEM;
+        }
+        if perm.contains(MapPermission::W)
+            && self.areas.iter().any(|area| in_range(area) && !area.may_write())
+        {
+            return -EACCES;
+        }
+        let mut parts = Vec::new();
+        for area in self.areas.iter_mut().filter(|area| in_range(area)) {
+            if area.vpn_range.get_end() > end_vpn {
+                parts.push(area.split_off(end_vpn));
+            }
+            if area.vpn_range.get_start() < start_vpn {
+                parts.push(area.split_off(start_vpn));
+            }
+        }
+        self.areas.extend(parts);
+        let changed: Vec<(usize, MapPermission)> = (0..self.areas.len())
+            .filter(|idx| in_range(&self.areas[*idx]))
+            .map(|idx| (idx, self.areas[idx].map_perm))
+            .collect();
+        for (n, (idx, _)) in changed.iter().enumerate() {
+            if !self.areas[*idx].protect(&mut self.page_table, perm | MapPermission::U) {
+                // put back the areas changed so far, which needs no frames as
+                // none of them shares frames any more
+                for (idx, old) in changed[..n].iter() {
+                    assert!(self.areas[*idx].protect(&mut self.page_table, *old));
+                }
+                return -ENOMEM;
+            }
+        }
+        0
+    }
+    /// Move the end of the user area starting at `start_vpn` to `end_vpn`.
+    /// Pages past a lowered end are unmapped and freed, pages below a raised
+    /// end are populated on first touch. Returns false if there is no such
+    /// area or it would run into another area or the trampoline.
+    pub fn resize_area(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let idx = match self.areas.iter().position(|area| {
+            area.vpn_range.get_start() == start_vpn && area.map_perm.contains(MapPermission::U)
+        }) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let end = self.areas[idx].vpn_range.get_end();
+        if end_vpn < start_vpn {
+            return false;
+        }
+        if end_vpn > end {
+            let (grow_start, grow_end): (VirtAddr, VirtAddr) = (end.into(), end_vpn.into());
+            if usize::from(grow_end) > USER_SPACE_END
+                || self.conflict_with_range(grow_start, grow_end)
+            {
+                return false;
+            }
+        }
+        self.areas[idx].resize(&mut self.page_table, end_vpn);
+        true
+    }
+    /// Whether `va` lies in the guard page below the user stack
+    pub fn is_stack_guard(&self, va: VirtAddr) -> bool {
+        self.stack_guard == Some(va.floor())
+    }
+    /// Whether `vpn` is mapped
+    pub fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.translate(vpn).map_or(false, |pte| pte.is_valid())
+    }
+    /// Resolve a fault at `va` from a user access needing `access`, with the
+    /// user sp at `sp`: populate a page of a lazily mapped area or of the
+    /// stack, or let a clean page of a shared file mapping be written.
+    /// A page that must be read from a file or swap is left to the caller.
+    pub fn handle_page_fault(&mut self, va: VirtAddr, access: MapPermission, sp: usize) -> Fault {
+        let vpn = va.floor();
+        let stack_bottom = self.stack_guard.map(|guard| VirtPageNum(guard.0 + 1));
+        let page_table = &mut self.page_table;
+        let handled = match self.areas.iter_mut().find(|area| {
+            area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end()
+        }) {
+            Some(area) if area.map_perm.contains(access | MapPermission::U) => {
+                // the stack grows down to around sp, a fault far below it is
+                // a stray access into the reserved range
+                if Some(area.vpn_range.get_start()) == stack_bottom
+                    && va.0 + USER_STACK_GROWTH_WINDOW < sp
+                {
+                    return Fault::Done(false);
+                }
+                area.fault_in(page_table, vpn, access)
             }
+            _ => Fault::Done(false),
+        };
+        if let Fault::Done(true) = handled {
+            self.update_peak();
         }
-        -1
+        handled
+    }
+    /// Populate the page at `vpn` before the kernel reads it, or writes it
+    /// if `write`, on behalf of the user, like [`Self::handle_page_fault`].
+    /// Nothing happens if the area does not allow the user that access.
+    pub fn populate_for_kernel(&mut self, vpn: VirtPageNum, write: bool) -> Fault {
+        let access = if write { MapPermission::W } else { MapPermission::R };
+        let page_table = &mut self.page_table;
+        let fault = match self.areas.iter_mut().find(|area| {
+            area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+                && area.map_perm.contains(access | MapPermission::U)
+        }) {
+            Some(area) => area.fault_in(page_table, vpn, access),
+            None => Fault::Done(false),
+        };
+        if let Fault::Done(true) = fault {
+            self.update_peak();
+        }
+        fault
+    }
+    /// Map the page `page_in` read into `frame`. If the page was brought in
+    /// or its area changed while the TCB lock was let go, the frame is
+    /// dropped and the access retried. Returns false if frames for the page
+    /// table run out.
+    pub fn finish_page_in(&mut self, page_in: PageIn, frame: FrameTracker) -> bool {
+        let vpn = page_in.vpn;
+        let page_table = &mut self.page_table;
+        let area = match self.areas.iter_mut().find(|area| {
+            area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end()
+        }) {
+            Some(area) if area.awaits(&page_in) => area,
+            _ => return true,
+        };
+        if !area.map_read_in(page_table, vpn, page_in.write, frame) {
+            return false;
+        }
+        self.update_peak();
+        true
+    }
+    /// Queue the dirty pages of shared file mappings in `[start_vpn, end_vpn)`
+    /// to be written back to their files, see [`write_back_pages`]. Returns
+    /// -ENOMEM if a page in the range is unmapped.
+    pub fn sync_area_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> isize {
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -ENOMEM;
+        }
+        for area in self.areas.iter_mut() {
+            let dirty: Vec<VirtPageNum> = area
+                .dirty_pages
+                .range(start_vpn..end_vpn)
+                .copied()
+                .collect();
+            for vpn in dirty {
+                area.write_back(vpn);
+                // the next write marks it dirty again
+                area.dirty_pages.remove(&vpn);
+                let ppn = area.data_frames[&vpn].ppn;
+                self.page_table.unmap(vpn);
+                self.page_table.map(vpn, ppn, area.pte_flags(vpn));
+            }
+        }
+        0
+    }
+    /// Move the clock hand `hand` over the swappable pages from `hand` on,
+    /// clearing the accessed bit of each. The first page found that was not
+    /// accessed since the hand last passed it is swapped out, its slot is
+    /// returned to be written out. None if the hand reached the end of the
+    /// address space instead.
+    pub fn swap_out_next(&mut self, hand: &mut VirtPageNum) -> Option<Arc<SwapSlot>> {
+        let mut order: Vec<usize> = (0..self.areas.len())
+            .filter(|idx| self.areas[*idx].is_swappable())
+            .collect();
+        order.sort_by_key(|idx| self.areas[*idx].vpn_range.get_start());
+        let page_table = &mut self.page_table;
+        for idx in order {
+            let area = &mut self.areas[idx];
+            while let Some(vpn) = area.data_frames.range(*hand..).next().map(|(vpn, _)| *vpn) {
+                *hand = VirtPageNum(vpn.0 + 1);
+                if !page_table.take_accessed(vpn) {
+                    if let Some(slot) = area.swap_out(page_table, vpn) {
+                        return Some(slot);
+                    }
+                }
+            }
+        }
+        None
+    }
+    /// The swap slot of the page at `vpn`, None if it is not swapped out
+    pub fn swap_slot(&self, vpn: VirtPageNum) -> Option<Arc<SwapSlot>> {
+        self.areas
+            .iter()
+            .find_map(|area| area.swapped.get(&vpn))
+            .cloned()
+    }
+    /// Page ranges and permissions of the areas accessible from user mode
+    pub fn user_areas(&self) -> Vec<(VPNRange, MapPermission)> {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| (area.vpn_range, area.map_perm))
+            .collect()
+    }
+    /// Number of frames backing user data, stack and trap context. Frames
+    /// shared with other address spaces, code after fork or shared memory,
+    /// count for each of them, pages on the zero frame do not count.
+    pub fn user_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.data_frames.len()).sum()
+    }
+    /// Most frames [`Self::user_pages`] ever counted, since the program was
+    /// loaded or the address space forked
+    pub fn peak_user_pages(&self) -> usize {
+        self.peak_resident
+    }
+    /// Raise the peak after pages may have been populated
+    fn update_peak(&mut self) {
+        self.peak_resident = self.peak_resident.max(self.user_pages());
+    }
+    /// Number of pages in the user areas, populated or not
+    pub fn mapped_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| area.vpn_range.get_end().0 - area.vpn_range.get_start().0)
+            .sum()
     }
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
@@ -287,12 +914,72 @@ impl MemorySet {
     }
 }
 
+/// How far ASLR moved the parts of a user address space up, in bytes
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LayoutOffsets {
+    /// User stack, from right above the elf segments
+    pub stack: usize,
+    /// Heap, from the stack top
+    pub heap: usize,
+    /// Search start for shared memory segments, from `SHM_BASE`
+    pub mmap: usize,
+}
+
+impl LayoutOffsets {
+    /// Fresh random offsets for a new program image, all 0 without the
+    /// `aslr` feature
+    pub fn new() -> Self {
+        if !cfg!(feature = "aslr") {
+            return Self::default();
+        }
+        let pages = |window: usize| rand_below(window / PAGE_SIZE) * PAGE_SIZE;
+        Self {
+            stack: pages(ASLR_STACK_WINDOW),
+            heap: pages(ASLR_HEAP_WINDOW),
+            mmap: pages(ASLR_MMAP_WINDOW),
+        }
+    }
+}
+
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// Frames of read-only areas are shared between forked address spaces
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    backing: MapAreaBacking,
+    /// Pages of a shared file mapping written since they were read in
+    dirty_pages: BTreeSet<VirtPageNum>,
+    /// Pages swapped out, read back in on the next access
+    swapped: BTreeMap<VirtPageNum, Arc<SwapSlot>>,
+    /// Anonymous pages only read so far, mapped read-only to the shared
+    /// zero frame until the first write
+    zero_pages: BTreeSet<VirtPageNum>,
+}
+
+/// Where the pages of a framed area come from
+#[derive(Clone)]
+pub enum MapAreaBacking {
+    /// Zeroed frames, allocated when the area is mapped or, for user
+    /// areas inserted by [`MemorySet::insert_framed_area_checked`], when the
+    /// pages are first touched
+    Anonymous,
+    /// Pages of a file starting at `offset`, read in on first access. Only
+    /// the first `len` bytes of the area come from the file, the rest reads
+    /// as zero. Writes to a shared mapping go back to the file, a private
+    /// mapping keeps its own copy. A shared mapping can only be writable if
+    /// the file was opened `writable`.
+    File {
+        inode: Arc<Inode>,
+        offset: usize,
+        len: usize,
+        shared: bool,
+        writable: bool,
+    },
+    /// Frames of a shared memory segment, mapped when attached
+    Shm(ShmAttachment),
 }
 
 impl MapArea {
@@ -309,6 +996,10 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            backing: MapAreaBacking::Anonymous,
+            dirty_pages: BTreeSet::new(),
+            swapped: BTreeMap::new(),
+            zero_pages: BTreeSet::new(),
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -317,40 +1008,374 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            backing: another.backing.clone(),
+            dirty_pages: BTreeSet::new(),
+            swapped: BTreeMap::new(),
+            zero_pages: BTreeSet::new(),
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
-        match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
+    /// Shrink the area to end at `at` and return the rest as a new area,
+    /// taking the frames mapped and the pages swapped there along with it
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        assert!(start < at && at < end);
+        self.vpn_range = VPNRange::new(start, at);
+        let backing = match &self.backing {
+            MapAreaBacking::Anonymous => MapAreaBacking::Anonymous,
+            MapAreaBacking::File { inode, offset, len, shared, writable } => {
+                MapAreaBacking::File {
+                    inode: Arc::clone(inode),
+                    offset: offset + (at.0 - start.0) * PAGE_SIZE,
+                    len: len.saturating_sub((at.0 - start.0) * PAGE_SIZE),
+                    shared: *shared,
+                    writable: *writable,
+                }
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+            // both parts hold the segment attached
+            MapAreaBacking::Shm(attachment) => MapAreaBacking::Shm(attachment.clone()),
+        };
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            backing,
+            dirty_pages: self.dirty_pages.split_off(&at),
+            swapped: self.swapped.split_off(&at),
+            zero_pages: self.zero_pages.split_off(&at),
+        }
+    }
+    /// Move the end of the area, unmapping the pages past a lowered end
+    pub fn resize(&mut self, page_table: &mut PageTable, end_vpn: VirtPageNum) {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        if end_vpn < end {
+            for vpn in VPNRange::new(end_vpn, end) {
+                self.unmap_one(page_table, vpn);
             }
         }
+        self.vpn_range = VPNRange::new(start, end_vpn);
+    }
+    pub fn is_shared_file(&self) -> bool {
+        matches!(self.backing, MapAreaBacking::File { shared: true, .. })
+    }
+    pub fn is_shm(&self) -> bool {
+        matches!(self.backing, MapAreaBacking::Shm(_))
+    }
+    /// Whether the backing allows the area to be writable
+    fn may_write(&self) -> bool {
+        !matches!(self.backing, MapAreaBacking::File { shared: true, writable: false, .. })
+    }
+    /// Only anonymous user pages go to swap, the trap context has no U flag
+    fn is_swappable(&self) -> bool {
+        self.map_type == MapType::Framed
+            && self.map_perm.contains(MapPermission::U)
+            && matches!(self.backing, MapAreaBacking::Anonymous)
+    }
+    /// Clean pages of a shared file mapping are mapped read-only, so that
+    /// the first write faults and marks them dirty, as are pages on the zero
+    /// frame, so that it gets replaced
+    fn pte_flags(&self, vpn: VirtPageNum) -> PTEFlags {
+        let mut perm = self.map_perm;
+        if (self.is_shared_file() && !self.dirty_pages.contains(&vpn))
+            || self.zero_pages.contains(&vpn)
+        {
+            perm.remove(MapPermission::W);
+        }
+        PTEFlags::from_bits(perm.bits).unwrap()
+    }
+    /// Bring in the page at `vpn` for an access needing `access`, a page
+    /// coming from a file or swap is left to the caller to read in
+    fn fault_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, access: MapPermission) -> Fault {
+        let write = access.contains(MapPermission::W);
+        if self.zero_pages.contains(&vpn) {
+            if !write {
+                return Fault::Done(false);
+            }
+            // the first write gives the page a frame of its own
+            let frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return Fault::Done(false),
+            };
+            self.zero_pages.remove(&vpn);
+            page_table.unmap(vpn);
+            page_table.map(vpn, frame.ppn, self.pte_flags(vpn));
+            self.data_frames.insert(vpn, Arc::new(frame));
+            return Fault::Done(true);
+        }
+        if let Some(frame) = self.data_frames.get(&vpn) {
+            if !write || !self.is_shared_file() || self.dirty_pages.contains(&vpn) {
+                return Fault::Done(false);
+            }
+            let ppn = frame.ppn;
+            self.dirty_pages.insert(vpn);
+            page_table.unmap(vpn);
+            page_table.map(vpn, ppn, self.pte_flags(vpn));
+            return Fault::Done(true);
+        }
+        if !write && self.is_swappable() && !self.swapped.contains_key(&vpn) {
+            // an anonymous page only read reads zeros, it needs no frame yet
+            self.zero_pages.insert(vpn);
+            if !page_table.try_map(vpn, zero_frame(), self.pte_flags(vpn)) {
+                self.zero_pages.remove(&vpn);
+                return Fault::Done(false);
+            }
+            return Fault::Done(true);
+        }
+        if let Some(source) = self.page_source(vpn) {
+            return Fault::PageIn(PageIn { vpn, write, source });
+        }
+        let frame = match frame_alloc() {
+            Some(frame) => frame,
+            None => return Fault::Done(false),
+        };
+        Fault::Done(self.map_read_in(page_table, vpn, write, frame))
+    }
+    /// Where the missing page at `vpn` is read from, None if it starts out
+    /// zeroed: past `len` or the end of the file, or anonymous
+    fn page_source(&self, vpn: VirtPageNum) -> Option<PageSource> {
+        if let Some(slot) = self.swapped.get(&vpn) {
+            return Some(PageSource::Swap(Arc::clone(slot)));
+        }
+        match &self.backing {
+            MapAreaBacking::File { inode, offset, len, .. } => {
+                let pos = (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+                (pos < *len).then(|| PageSource::File {
+                    inode: Arc::clone(inode),
+                    pos: offset + pos,
+                    len: (len - pos).min(PAGE_SIZE),
+                })
+            }
+            _ => None,
+        }
+    }
+    /// Whether the page `page_in` was read for is still missing and comes
+    /// from the same place
+    fn awaits(&self, page_in: &PageIn) -> bool {
+        let vpn = page_in.vpn;
+        if self.data_frames.contains_key(&vpn) || self.zero_pages.contains(&vpn) {
+            return false;
+        }
+        match (&page_in.source, self.page_source(vpn)) {
+            (PageSource::Swap(read), Some(PageSource::Swap(slot))) => Arc::ptr_eq(read, &slot),
+            (
+                PageSource::File { inode: read, pos: read_pos, .. },
+                Some(PageSource::File { inode, pos, .. }),
+            ) => Arc::ptr_eq(read, &inode) && *read_pos == pos,
+            _ => false,
+        }
+    }
+    /// Map `frame`, holding the page at `vpn` as read in, for an access
+    /// that is a write if `write`. Returns false if frames run out.
+    fn map_read_in(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        write: bool,
+        frame: FrameTracker,
+    ) -> bool {
+        if write && self.is_shared_file() {
+            self.dirty_pages.insert(vpn);
+        }
+        if !page_table.try_map(vpn, frame.ppn, self.pte_flags(vpn)) {
+            self.dirty_pages.remove(&vpn);
+            return false;
+        }
+        self.swapped.remove(&vpn);
+        self.data_frames.insert(vpn, Arc::new(frame));
+        true
+    }
+    /// Take the page at `vpn` out to a free swap slot and unmap it, the
+    /// slot is returned to be written out. None if the frame is shared with
+    /// another address space or pinned, or swap is full.
+    ///
+    /// The page is written even if it has not been dirtied since it was read
+    /// in: the kernel writes user pages through its own mapping, which
+    /// leaves the D bit of the user pte clear.
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<Arc<SwapSlot>> {
+        let frame = &self.data_frames[&vpn];
+        if Arc::strong_count(frame) > 1 || is_pinned(frame.ppn) {
+            return None;
+        }
+        let slot = Arc::new(SwapSlot::alloc(Arc::clone(frame))?);
+        page_table.unmap(vpn);
+        self.data_frames.remove(&vpn);
+        self.swapped.insert(vpn, Arc::clone(&slot));
+        Some(slot)
+    }
+
+    /// Change the permissions of the area to `perm`. Before a private area
+    /// becomes writable, the frames it still shares with a forked address
+    /// space are copied. Returns false if frames run out, the permissions
+    /// are unchanged then.
+    #[must_use]
+    pub fn protect(&mut self, page_table: &mut PageTable, perm: MapPermission) -> bool {
+        if perm.contains(MapPermission::W)
+            && !self.map_perm.contains(MapPermission::W)
+            && !self.is_shared_file()
+            && !self.is_shm()
+        {
+            let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+            for (vpn, frame) in self.data_frames.iter_mut() {
+                if Arc::strong_count(frame) == 1 {
+                    continue;
+                }
+                let copy = match frame_alloc() {
+                    Some(copy) => copy,
+                    None => return false,
+                };
+                copy.ppn
+                    .get_bytes_array()
+                    .copy_from_slice(frame.ppn.get_bytes_array());
+                page_table.unmap(*vpn);
+                page_table.map(*vpn, copy.ppn, pte_flags);
+                *frame = Arc::new(copy);
+            }
+        }
+        self.map_perm = perm;
+        for vpn in self.data_frames.keys() {
+            page_table.unmap(*vpn);
+            page_table.map(*vpn, self.data_frames[vpn].ppn, self.pte_flags(*vpn));
+        }
+        for vpn in self.zero_pages.iter() {
+            page_table.unmap(*vpn);
+            page_table.map(*vpn, zero_frame(), self.pte_flags(*vpn));
+        }
+        true
+    }
+
+    /// Queue a page of a shared file mapping to be written back, see
+    /// [`write_back_pages`]
+    fn write_back(&self, vpn: VirtPageNum) {
+        if let MapAreaBacking::File { inode, offset, shared: true, .. } = &self.backing {
+            DIRTY_PAGES.exclusive_access().push(DirtyPage {
+                inode: Arc::clone(inode),
+                pos: offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE,
+                frame: Arc::clone(&self.data_frames[&vpn]),
+            });
+        }
+    }
+    /// Map the page at `vpn`, returns false if frames run out
+    #[must_use]
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        match self.map_type {
+            MapType::Identical => page_table.try_map(vpn, PhysPageNum(vpn.0), pte_flags),
+            MapType::Framed => {
+                let frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                if !page_table.try_map(vpn, frame.ppn, pte_flags) {
+                    return false;
+                }
+                self.data_frames.insert(vpn, Arc::new(frame));
+                true
+            }
+        }
+    }
+
+    /// Map the frames of `another`, an area of the same range in another
+    /// address space, instead of allocating new ones. Its swapped out pages
+    /// keep their slots, see [`Self::share_swapped`].
+    /// Returns false if frames run out
+    #[must_use]
+    pub fn map_shared(&mut self, page_table: &mut PageTable, another: &MapArea) -> bool {
+        for (vpn, frame) in another.data_frames.iter() {
+            if !page_table.try_map(*vpn, frame.ppn, self.pte_flags(*vpn)) {
+                return false;
+            }
+            self.data_frames.insert(*vpn, Arc::clone(frame));
+        }
+        self.share_swapped(another);
+        self.map_zero_pages(page_table, another)
+    }
+
+    /// Map the pages `another` reads from the zero frame the same way
+    #[must_use]
+    fn map_zero_pages(&mut self, page_table: &mut PageTable, another: &MapArea) -> bool {
+        for vpn in another.zero_pages.iter() {
+            self.zero_pages.insert(*vpn);
+            if !page_table.try_map(*vpn, zero_frame(), self.pte_flags(*vpn)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Share the slots of the pages `another` has swapped out, neither side
+    /// writes a slot again
+    fn share_swapped(&mut self, another: &MapArea) {
+        self.swapped
+            .extend(another.swapped.iter().map(|(vpn, slot)| (*vpn, Arc::clone(slot))));
+    }
+
+    /// Map copies of the frames of `another`, an area of the same range in
+    /// another address space, sharing the slots of the pages it has swapped
+    /// out
+    /// Returns false if frames run out
+    #[must_use]
+    pub fn map_copied(&mut self, page_table: &mut PageTable, another: &MapArea) -> bool {
+        for (vpn, src) in another.data_frames.iter() {
+            let frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(src.ppn.get_bytes_array());
+            if !page_table.try_map(*vpn, frame.ppn, self.pte_flags(*vpn)) {
+                return false;
+            }
+            self.data_frames.insert(*vpn, Arc::new(frame));
+        }
+        self.share_swapped(another);
+        self.map_zero_pages(page_table, another)
     }
 
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         #[allow(clippy::single_match)]
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                if self.dirty_pages.remove(&vpn) {
+                    self.write_back(vpn);
+                }
+                if self.data_frames.remove(&vpn).is_none() && !self.zero_pages.remove(&vpn) {
+                    // never touched, or swapped out
+                    self.swapped.remove(&vpn);
+                    return;
+                }
             }
             _ => {}
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Map every page, or none of them if frames run out. Identical areas
+    /// use megapages where they can.
+    #[must_use]
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
+        if self.map_type == MapType::Identical {
+            let start = self.vpn_range.get_start();
+            let pages = self.vpn_range.get_end().0 - start.0;
+            let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+            return page_table.try_map_range(start, PhysPageNum(start.0), pages, pte_flags);
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if !self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
         }
+        true
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identical {
+            let start = self.vpn_range.get_start();
+            page_table.unmap_range(start, self.vpn_range.get_end().0 - start.0);
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
@@ -384,6 +1409,17 @@ impl MapArea {
     }
 }
 
+impl Drop for MapArea {
+    /// Address spaces are torn down by dropping their areas, shared file
+    /// mappings must not lose their writes then. Mostly that is under the TCB
+    /// lock, so the pages are only queued.
+    fn drop(&mut self) {
+        for vpn in self.dirty_pages.iter() {
+            self.write_back(*vpn);
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 /// map type for memory set: identical or framed
 pub enum MapType {
@@ -10,19 +10,35 @@
 mod address;
 mod frame_allocator;
 mod heap_allocator;
+#[cfg(feature = "ktest")]
+pub mod ktest;
 mod memory_set;
 mod page_table;
+mod shm;
+mod swap;
+mod uaccess;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-pub use memory_set::{remap_test, kernel_token};
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, copy_kernel_to_user, translated_refmut, translated_str, PTEFlags, PageTable, PageTableEntry, UserBuffer};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_allocator_fragmentation_test,
+    frame_allocator_stats, frame_dealloc, frames_available, frames_high_water, free_frames,
+    FrameRangeTracker, FrameTracker,
+};
+pub use heap_allocator::{heap_low, heap_stats, kernel_heap_stats};
+pub use memory_set::{remap_test, kernel_token, write_back_pages};
+pub use memory_set::{
+    Fault, LayoutOffsets, LoadError, MapAreaBacking, MapPermission, MemorySet, PageIn, KERNEL_SPACE,
+};
+pub use shm::{shm_get, shm_pages, ShmAttachment, IPC_PRIVATE};
+pub use swap::{free_swap_slots, init_swap, swap_in_use, PinnedFrames, SwapSlot};
+pub use page_table::{translated_byte_buffer, translated_readable_buffer, translated_refmut, PTEFlags, PageTable, PageTableEntry, UserBuffer};
+pub use uaccess::{copy_from_user, copy_to_user, copy_user_bytes, strncpy_from_user, UaccessError};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
+    frame_allocator::init_zero_frame();
     KERNEL_SPACE.exclusive_access().activate();
 }
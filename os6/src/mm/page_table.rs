@@ -1,7 +1,9 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
+use super::swap::PinnedFrames;
 use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-use alloc::string::String;
+use crate::task::populate_user_page;
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
@@ -54,22 +56,32 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+    /// Valid and pointing to memory rather than to the next level table
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && self.flags().intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
 }
 
+/// 4 KiB pages in a 2 MiB megapage
+const MEGAPAGE_PAGES: usize = 512;
+
 /// page table structure
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
 }
 
-/// Assume that it won't oom when creating/mapping.
+/// Creating and mapping fail with None or false when frames run out.
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+    pub fn new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
@@ -89,22 +101,50 @@ impl PageTable {
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
+            } else if i == 1 && pte.is_leaf() {
+                // a single page of a megapage is wanted
+                self.split_megapage(pte)?;
             }
             ppn = pte.ppn();
         }
         result
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+    /// The level 1 entry covering `vpn`, creating the root entry if needed
+    fn find_megapage_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let root = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !root.is_valid() {
+            let frame = frame_alloc()?;
+            *root = PageTableEntry::new(frame.ppn, PTEFlags::V);
+            self.frames.push(frame);
+        }
+        Some(&mut root.ppn().get_pte_array()[idxs[1]])
+    }
+    /// Replace the megapage leaf `pte` with a table of 4 KiB leaves mapping
+    /// the same memory with the same flags
+    fn split_megapage(&mut self, pte: &mut PageTableEntry) -> Option<()> {
+        let frame = frame_alloc()?;
+        let flags = pte.flags();
+        for (i, leaf) in frame.ppn.get_pte_array().iter_mut().enumerate() {
+            *leaf = PageTableEntry::new(PhysPageNum(pte.ppn().0 + i), flags);
+        }
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+        self.frames.push(frame);
+        Some(())
+    }
+    /// The entry mapping `vpn` and its level, stopping early at a megapage
+    /// leaf at level 1
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&PageTableEntry> = None;
+        let mut result: Option<(&PageTableEntry, usize)> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
+            if i == 2 || (i == 1 && pte.is_leaf()) {
+                result = Some((pte, i));
                 break;
             }
             if !pte.is_valid() {
@@ -114,23 +154,107 @@ impl PageTable {
         }
         result
     }
+    /// Map `vpn` to `ppn`, returns false if there is no frame left for the
+    /// page table itself
+    #[must_use]
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        match self.find_pte_create(vpn) {
+            Some(pte) => {
+                assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+                *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Map `pages` pages from `vpn` on to the frames from `ppn` on, with a
+    /// megapage wherever both are 2 MiB aligned and the rest of the range
+    /// covers one. Returns false, with nothing mapped, if frames run out
+    #[must_use]
+    pub fn try_map_range(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        pages: usize,
+        flags: PTEFlags,
+    ) -> bool {
+        let mut mapped = 0;
+        while mapped < pages {
+            let (v, p) = (VirtPageNum(vpn.0 + mapped), PhysPageNum(ppn.0 + mapped));
+            let step = if v.0 % MEGAPAGE_PAGES == 0
+                && p.0 % MEGAPAGE_PAGES == 0
+                && pages - mapped >= MEGAPAGE_PAGES
+            {
+                match self.find_megapage_pte_create(v) {
+                    Some(pte) => {
+                        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", v);
+                        *pte = PageTableEntry::new(p, flags | PTEFlags::V);
+                        MEGAPAGE_PAGES
+                    }
+                    None => 0,
+                }
+            } else if self.try_map(v, p, flags) {
+                1
+            } else {
+                0
+            };
+            if step == 0 {
+                self.unmap_range(vpn, mapped);
+                return false;
+            }
+            mapped += step;
+        }
+        true
+    }
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
-        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
-        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        assert!(self.try_map(vpn, ppn, flags), "out of frames mapping vpn {:?}", vpn);
     }
+    /// Unmap `vpn`, a page inside a megapage splits it first
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Unmap `pages` pages from `vpn` on, megapages inside the range are
+    /// dropped whole
+    pub fn unmap_range(&mut self, vpn: VirtPageNum, pages: usize) {
+        let mut unmapped = 0;
+        while unmapped < pages {
+            let v = VirtPageNum(vpn.0 + unmapped);
+            if v.0 % MEGAPAGE_PAGES == 0 && pages - unmapped >= MEGAPAGE_PAGES {
+                if let Some((_, 1)) = self.find_pte(v) {
+                    *self.find_megapage_pte_create(v).unwrap() = PageTableEntry::empty();
+                    unmapped += MEGAPAGE_PAGES;
+                    continue;
+                }
+            }
+            self.unmap(v);
+            unmapped += 1;
+        }
+    }
+    /// Clear the accessed bit of the mapped page `vpn`, returns whether it
+    /// was set
+    pub fn take_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        let pte = self.find_pte_create(vpn).unwrap();
+        let accessed = pte.flags().contains(PTEFlags::A);
+        pte.bits &= !(PTEFlags::A.bits as usize);
+        accessed
+    }
+    /// The entry of `vpn`, for a page inside a megapage an entry as if it
+    /// were mapped on its own
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).copied()
+        self.find_pte(vpn).map(|(pte, level)| match level {
+            1 => PageTableEntry::new(
+                PhysPageNum(pte.ppn().0 + vpn.0 % MEGAPAGE_PAGES),
+                pte.flags(),
+            ),
+            _ => *pte,
+        })
     }
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate(va.clone().floor()).map(|pte| {
             //println!("translate_va:va = {:?}", va);
             let aligned_pa: PhysAddr = pte.ppn().into();
             //println!("translate_va:pa_align = {:?}", aligned_pa);
@@ -139,21 +263,66 @@ impl PageTable {
             (aligned_pa_usize + offset).into()
         })
     }
+    /// Number of megapages mapped, each saves a frame for a level 2 table
+    pub fn megapages(&self) -> usize {
+        self.root_ppn
+            .get_pte_array()
+            .iter()
+            .filter(|pte| pte.is_valid() && !pte.is_leaf())
+            .map(|pte| pte.ppn().get_pte_array().iter().filter(|pte| pte.is_leaf()).count())
+            .sum()
+    }
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// Translate `vpn` of the current user space for the kernel to read it, or
+/// to write it if `write`, populating the page first if it is not mapped yet
+/// or not writable. None if it is not user memory allowing that access.
+fn translate_user(page_table: &PageTable, vpn: VirtPageNum, write: bool) -> Option<PageTableEntry> {
+    if usize::from(VirtAddr::from(vpn)) >= USER_SPACE_END {
+        return None;
+    }
+    let allowed = |pte: &PageTableEntry| {
+        pte.is_valid() && pte.is_user() && pte.readable() && (!write || pte.writable())
+    };
+    match page_table.translate(vpn).filter(allowed) {
+        Some(pte) => Some(pte),
+        None => {
+            populate_user_page(vpn, write);
+            page_table.translate(vpn).filter(allowed)
+        }
+    }
+}
+
+fn translate_user_va(page_table: &PageTable, va: VirtAddr, write: bool) -> Option<PhysAddr> {
+    translate_user(page_table, va.floor(), write).map(|pte| {
+        let aligned_pa: PhysAddr = pte.ppn().into();
+        (usize::from(aligned_pa) + va.page_offset()).into()
+    })
+}
+
+/// Translate the user buffer at `ptr` page by page, every page is checked
+/// before the caller touches any of them
+fn translate_user_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> Option<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len)?;
     let mut v = Vec::new();
+    // reading in a later page lets the task sleep, when the pages already
+    // translated could be swapped out
+    let mut pins = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translate_user(&page_table, vpn, write)?.ppn();
+        pins.push(PinnedFrames::new(vec![ppn]));
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -164,68 +333,62 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
 
-/// 复制内核空间地址数据到用户空间地址
-/// 参数 -- token: 用户地址空间token，dst_user_va：用户空间目标地址，内核空间源数据地址，len：数据字节长度
-pub fn copy_kernel_to_user(token: usize, kernel_src_va: *const u8, user_dst_va: usize, len: usize) {
-    // 用户空间采用Framed映射，内核空间采用恒等映射，所以只需要翻译用户空间地址
-    let page_table = PageTable::from_token(token);
-    let user_start_va = VirtAddr::from(user_dst_va);
-    let user_start_vpn = user_start_va.floor();
-    let user_start_ppn = page_table.translate(user_start_vpn).unwrap().ppn();
-    let user_dst_pa = &mut user_start_ppn.get_bytes_array()[user_start_va.page_offset()..user_start_va.page_offset() + len];
-    unsafe {
-        user_dst_pa.copy_from_slice(core::slice::from_raw_parts(kernel_src_va, len));
-    };
+/// translate a pointer to a mutable u8 Vec through page table, None if any
+/// part of it is not writable user memory
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Option<Vec<&'static mut [u8]>> {
+    translate_user_buffer(token, ptr, len, true)
 }
 
-
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
-        }
-    }
-    string
+/// Like [`translated_byte_buffer`] for a buffer the kernel only reads, which
+/// needs to be readable only
+pub fn translated_readable_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Option<Vec<&'static mut [u8]>> {
+    translate_user_buffer(token, ptr, len, false)
 }
 
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+/// The user page holding a `T` at `va`, None if `va` is misaligned or the
+/// object would straddle two pages
+fn translate_user_object<T>(token: usize, va: usize, write: bool) -> Option<PhysAddr> {
+    if va % core::mem::align_of::<T>() != 0
+        || va % PAGE_SIZE + core::mem::size_of::<T>() > PAGE_SIZE
+    {
+        return None;
+    }
     let page_table = PageTable::from_token(token);
-    page_table.translate_va(VirtAddr::from(ptr as usize)).unwrap().get_mut()
+    translate_user_va(&page_table, VirtAddr::from(va), write)
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    //println!("into translated_refmut!");
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
+    translate_user_object::<T>(token, ptr as usize, true).map(|pa| pa.get_mut())
 }
 
 /// An abstraction over a buffer passed from user space to kernel space
 pub struct UserBuffer {
     pub buffers: Vec<&'static mut [u8]>,
+    /// The frames stay in memory while the buffer is in use
+    pins: PinnedFrames,
 }
 
 impl UserBuffer {
     /// Constuct a UserBuffer
     pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
-        Self { buffers }
+        let pins = PinnedFrames::new(
+            buffers
+                .iter()
+                .map(|buffer| PhysAddr::from(buffer.as_ptr() as usize).floor())
+                .collect(),
+        );
+        Self { buffers, pins }
     }
     /// Get the length of a UserBuffer
     pub fn len(&self) -> usize {
@@ -243,6 +406,7 @@ impl IntoIterator for UserBuffer {
     fn into_iter(self) -> Self::IntoIter {
         UserBufferIterator {
             buffers: self.buffers,
+            _pins: self.pins,
             current_buffer: 0,
             current_idx: 0,
         }
@@ -252,6 +416,7 @@ impl IntoIterator for UserBuffer {
 // An iterator over a UserBuffer
 pub struct UserBufferIterator {
     buffers: Vec<&'static mut [u8]>,
+    _pins: PinnedFrames,
     current_buffer: usize,
     current_idx: usize,
 }
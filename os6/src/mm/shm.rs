@@ -0,0 +1,114 @@
+//! System V style shared memory segments
+//!
+//! A segment owns its frames in a global table until its last attachment
+//! goes away. Each attached area holds a [`ShmAttachment`] in its backing,
+//! so cloning the area on fork attaches again and dropping it on detach,
+//! exec or exit detaches.
+
+use super::{frame_alloc, frames_available, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::sync::SpinLock;
+use crate::syscall::errno::{EINVAL, ENOMEM};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Key that always creates a new segment
+pub const IPC_PRIVATE: usize = 0;
+
+struct ShmSegment {
+    key: usize,
+    frames: Vec<Arc<FrameTracker>>,
+    /// Number of live attachments
+    attached: usize,
+}
+
+struct ShmTable {
+    next_id: usize,
+    segments: BTreeMap<usize, ShmSegment>,
+}
+
+lazy_static! {
+    static ref SHM_TABLE: SpinLock<ShmTable> = SpinLock::new(ShmTable {
+        next_id: 0,
+        segments: BTreeMap::new(),
+    });
+}
+
+/// Find the segment of `key`, or create one of `size` bytes of zeroed
+/// frames. Returns its id, or -EINVAL if an existing segment is smaller
+/// than `size` or `size` is 0, -ENOMEM if the frames cannot be allocated.
+pub fn shm_get(key: usize, size: usize) -> Result<usize, isize> {
+    let mut table = SHM_TABLE.exclusive_access();
+    if key != IPC_PRIVATE {
+        if let Some((&shmid, segment)) = table.segments.iter().find(|(_, s)| s.key == key) {
+            return (segment.frames.len() * PAGE_SIZE >= size).then(|| shmid).ok_or(-EINVAL);
+        }
+    }
+    let pages = size.checked_add(PAGE_SIZE - 1).ok_or(-EINVAL)? / PAGE_SIZE;
+    if pages == 0 {
+        return Err(-EINVAL);
+    }
+    if !frames_available(pages) {
+        return Err(-ENOMEM);
+    }
+    let mut frames = Vec::with_capacity(pages);
+    for _ in 0..pages {
+        frames.push(Arc::new(frame_alloc().ok_or(-ENOMEM)?));
+    }
+    let shmid = table.next_id;
+    table.next_id += 1;
+    table.segments.insert(
+        shmid,
+        ShmSegment {
+            key,
+            frames,
+            attached: 0,
+        },
+    );
+    Ok(shmid)
+}
+
+/// Size of segment `shmid` in pages
+pub fn shm_pages(shmid: usize) -> Option<usize> {
+    let table = SHM_TABLE.exclusive_access();
+    table.segments.get(&shmid).map(|segment| segment.frames.len())
+}
+
+/// One attachment of a segment
+pub struct ShmAttachment {
+    shmid: usize,
+}
+
+impl ShmAttachment {
+    /// Attach segment `shmid`, returns the attachment with the frames to map
+    pub fn new(shmid: usize) -> Option<(Self, Vec<Arc<FrameTracker>>)> {
+        let mut table = SHM_TABLE.exclusive_access();
+        let segment = table.segments.get_mut(&shmid)?;
+        segment.attached += 1;
+        Some((Self { shmid }, segment.frames.clone()))
+    }
+}
+
+impl Clone for ShmAttachment {
+    fn clone(&self) -> Self {
+        let mut table = SHM_TABLE.exclusive_access();
+        table.segments.get_mut(&self.shmid).unwrap().attached += 1;
+        Self { shmid: self.shmid }
+    }
+}
+
+impl Drop for ShmAttachment {
+    /// The frames go back once no one has the segment attached any more
+    fn drop(&mut self) {
+        let mut table = SHM_TABLE.exclusive_access();
+        let segment = table.segments.get_mut(&self.shmid).unwrap();
+        segment.attached -= 1;
+        if segment.attached == 0 {
+            let segment = table.segments.remove(&self.shmid);
+            drop(table);
+            drop(segment);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Swap space for user pages
+//!
+//! Evicted pages go to page-sized slots of a file allocated once at boot.
+//! An area keeps a [`SwapSlot`] for each of its pages that is swapped out,
+//! so the slot is given back when the page is read in again or the area
+//! goes away. A slot is written once, so forked address spaces share the
+//! slots of the pages swapped out before.
+//!
+//! A page is taken out of its address space under the TCB lock, but only
+//! written to its slot once the lock is let go: the write waits for the
+//! disk. Until then the slot keeps the frame and reads come from there.
+
+use super::{FrameTracker, PhysPageNum};
+use crate::config::{PAGE_SIZE, SWAP_SLOTS};
+use crate::fs::{open_kernel_file, ROOT_INODE};
+use crate::sync::SpinLock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::Inode;
+use lazy_static::*;
+
+/// Name of the swap file in the root directory
+const SWAP_FILE: &str = "swap";
+
+struct SwapSpace {
+    inode: Arc<Inode>,
+    free_slots: Vec<usize>,
+}
+
+lazy_static! {
+    static ref SWAP: SpinLock<Option<SwapSpace>> = SpinLock::new(None);
+    /// Frames the kernel is using on behalf of a user, by number of users
+    static ref PINNED_FRAMES: SpinLock<BTreeMap<PhysPageNum, usize>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Allocate the swap file, pages are not swapped out before this. There
+/// is no swap on a read-only filesystem.
+pub fn init_swap() {
+    if ROOT_INODE.read_only() {
+        warn!("[kernel] swap: off, the filesystem is read-only");
+        return;
+    }
+    let inode = open_kernel_file(SWAP_FILE).expect("cannot create the swap file");
+    inode.fallocate(SWAP_SLOTS * PAGE_SIZE);
+    assert!(inode.size() >= SWAP_SLOTS * PAGE_SIZE);
+    *SWAP.exclusive_access() = Some(SwapSpace {
+        inode,
+        free_slots: (0..SWAP_SLOTS).rev().collect(),
+    });
+    info!("[kernel] swap: {} slots in '{}'", SWAP_SLOTS, SWAP_FILE);
+}
+
+/// Number of free swap slots
+pub fn free_swap_slots() -> usize {
+    SWAP.exclusive_access()
+        .as_ref()
+        .map_or(0, |swap| swap.free_slots.len())
+}
+
+/// Whether a page is swapped out, the swap file holds the only copy
+pub fn swap_in_use() -> bool {
+    SWAP.exclusive_access()
+        .as_ref()
+        .map_or(false, |swap| swap.free_slots.len() < SWAP_SLOTS)
+}
+
+/// A slot of the swap file holding one page, freed when dropped
+pub struct SwapSlot {
+    slot: usize,
+    inode: Arc<Inode>,
+    /// The frame of the page until it is written out
+    pending: SpinLock<Option<Arc<FrameTracker>>>,
+}
+
+impl SwapSlot {
+    /// Take a free slot for the page in `frame`, None if swap is full or not
+    /// set up yet. The page is written by [`Self::write_out`].
+    pub fn alloc(frame: Arc<FrameTracker>) -> Option<Self> {
+        let mut swap = SWAP.exclusive_access();
+        let swap = swap.as_mut()?;
+        let slot = swap.free_slots.pop()?;
+        Some(Self {
+            slot,
+            inode: Arc::clone(&swap.inode),
+            pending: SpinLock::new(Some(frame)),
+        })
+    }
+    /// Write the page to the slot and let its frame go. This waits for the
+    /// disk, the caller must not hold a SpinLock.
+    pub fn write_out(&self) {
+        let frame = self.pending.exclusive_access().clone();
+        if let Some(frame) = frame {
+            let page = frame.ppn.get_bytes_array();
+            assert_eq!(self.inode.write_at(self.slot * PAGE_SIZE, page), PAGE_SIZE);
+            self.pending.exclusive_access().take();
+        }
+    }
+    /// Copy the page to `page`. Unless it is still in its frame this waits
+    /// for the disk, the caller must not hold a SpinLock.
+    pub fn read(&self, page: &mut [u8]) {
+        let frame = self.pending.exclusive_access().clone();
+        match frame {
+            Some(frame) => page.copy_from_slice(frame.ppn.get_bytes_array()),
+            None => assert_eq!(self.inode.read_at(self.slot * PAGE_SIZE, page), PAGE_SIZE),
+        }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        if let Some(swap) = SWAP.exclusive_access().as_mut() {
+            swap.free_slots.push(self.slot);
+        }
+    }
+}
+
+/// Keeps frames from being swapped out while the kernel reads or writes
+/// them for a user, which may block
+pub struct PinnedFrames {
+    ppns: Vec<PhysPageNum>,
+}
+
+impl PinnedFrames {
+    pub fn new(ppns: Vec<PhysPageNum>) -> Self {
+        let mut pinned = PINNED_FRAMES.exclusive_access();
+        for ppn in ppns.iter() {
+            *pinned.entry(*ppn).or_insert(0) += 1;
+        }
+        Self { ppns }
+    }
+}
+
+impl Drop for PinnedFrames {
+    fn drop(&mut self) {
+        let mut pinned = PINNED_FRAMES.exclusive_access();
+        for ppn in self.ppns.iter() {
+            let count = pinned.get_mut(ppn).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                pinned.remove(ppn);
+            }
+        }
+    }
+}
+
+/// Whether the frame at `ppn` is pinned
+pub fn is_pinned(ppn: PhysPageNum) -> bool {
+    PINNED_FRAMES.exclusive_access().contains_key(&ppn)
+}
@@ -0,0 +1,84 @@
+//! Copying between kernel and user memory
+//!
+//! Every page of the user range is checked before anything is copied, so a
+//! bad pointer fails the whole copy without side effects. Objects and
+//! strings may straddle page boundaries.
+
+use super::page_table::{translated_byte_buffer, translated_readable_buffer};
+use crate::config::PAGE_SIZE;
+use alloc::string::String;
+use core::mem::{size_of, MaybeUninit};
+
+/// Why a user memory access failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UaccessError {
+    /// Part of the range is not user memory allowing the access
+    Fault,
+    /// No NUL within the length limit
+    TooLong,
+}
+
+pub type Result<T> = core::result::Result<T, UaccessError>;
+
+/// Fill `dst` from user memory at `src`
+fn read_bytes(token: usize, src: *const u8, dst: &mut [u8]) -> Result<()> {
+    let buffers =
+        translated_readable_buffer(token, src, dst.len()).ok_or(UaccessError::Fault)?;
+    let mut start = 0;
+    for buffer in buffers {
+        dst[start..start + buffer.len()].copy_from_slice(buffer);
+        start += buffer.len();
+    }
+    Ok(())
+}
+
+/// Copy `src` to user memory at `dst`
+pub fn copy_user_bytes(token: usize, dst: *mut u8, src: &[u8]) -> Result<()> {
+    let buffers = translated_byte_buffer(token, dst, src.len()).ok_or(UaccessError::Fault)?;
+    let mut start = 0;
+    for buffer in buffers {
+        buffer.copy_from_slice(&src[start..start + buffer.len()]);
+        start += buffer.len();
+    }
+    Ok(())
+}
+
+/// Read a `T` from user memory at `ptr`, which may be misaligned. `T` must
+/// be plain data, valid for any bytes the user put there.
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T) -> Result<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    read_bytes(token, ptr as *const u8, bytes)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Write `value` to user memory at `ptr`, which may be misaligned
+pub fn copy_to_user<T>(token: usize, ptr: *mut T, value: &T) -> Result<()> {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    copy_user_bytes(token, ptr as *mut u8, bytes)
+}
+
+/// Copy the NUL-terminated string at `ptr`, reading at most `max_len` bytes
+/// including the NUL. Fails with `TooLong` if there is no NUL among them.
+pub fn strncpy_from_user(token: usize, ptr: *const u8, max_len: usize) -> Result<String> {
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    let mut read = 0;
+    while read < max_len {
+        // one page at a time, the string may end before the next one
+        let len = (PAGE_SIZE - va % PAGE_SIZE).min(max_len - read);
+        let buffers =
+            translated_readable_buffer(token, va as *const u8, len).ok_or(UaccessError::Fault)?;
+        for &ch in buffers.iter().flat_map(|buffer| buffer.iter()) {
+            if ch == 0 {
+                return Ok(string);
+            }
+            string.push(ch as char);
+        }
+        va += len;
+        read += len;
+    }
+    Err(UaccessError::TooLong)
+}
@@ -0,0 +1,282 @@
+//! Cycle and instruction counters, counted per task
+//!
+//! `cycle` and `instret` run all the time on every hart. A task takes a mark
+//! of both when it is dispatched and is charged the difference when it is
+//! switched out, see `TaskControlBlockInner::account_perf`. Both are 64 bit
+//! and differences wrap, so a counter wrapping around in between is fine.
+//!
+//! User mode may read `cycle`, `time` and `instret` itself where the SBI
+//! lets us hand them on through `scounteren`, it then sees the raw counters
+//! of the hart.
+//!
+//! While profiling is on, [`crate::syscall::syscall()`] also times every
+//! call and adds the latency to a histogram of its id, see
+//! [`syscall_profiles`], and the scheduler times every switch and the slice
+//! it ends, see [`sched_profiles`]. Off, which it is at boot, this costs
+//! one load.
+
+use crate::config::MAX_SYSCALL_NUM;
+use crate::sync::SpinLock;
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Reported instead of a count the hart does not implement
+pub const PERF_UNSUPPORTED: usize = usize::MAX;
+
+/// `scounteren` bits of `cycle`, `time` and `instret`
+const COUNTEREN_CY_TM_IR: usize = 0b111;
+
+static CYCLE_SUPPORTED: AtomicBool = AtomicBool::new(false);
+static INSTRET_SUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Bucket `i` of a latency histogram counts latencies of 2^i to 2^(i+1) - 1
+/// cycles, the last one everything longer, 0 falls into bucket 0
+const PROFILE_BUCKETS: usize = 32;
+
+/// Latencies of one syscall id, or durations of one kind
+#[derive(Clone, Copy)]
+struct Histogram {
+    count: u64,
+    total: u64,
+    max: u64,
+    buckets: [u32; PROFILE_BUCKETS],
+}
+
+const EMPTY: Histogram = Histogram {
+    count: 0,
+    total: 0,
+    max: 0,
+    buckets: [0; PROFILE_BUCKETS],
+};
+
+impl Histogram {
+    fn add(&mut self, value: u64) {
+        let bucket = match value {
+            0 => 0,
+            _ => ((u64::BITS - 1 - value.leading_zeros()) as usize).min(PROFILE_BUCKETS - 1),
+        };
+        self.count += 1;
+        self.total = self.total.wrapping_add(value);
+        self.max = self.max.max(value);
+        self.buckets[bucket] += 1;
+    }
+    /// Append the count, total, maximum and `i:n` for each bucket i
+    /// holding n values to `text`, after a space
+    fn write_to(&self, text: &mut String) {
+        write!(text, " {} {} {}", self.count, self.total, self.max).unwrap();
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            if *count != 0 {
+                write!(text, " {}:{}", bucket, count).unwrap();
+            }
+        }
+    }
+}
+
+/// How a task came to switch out, see [`record_switch`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SwitchKind {
+    /// It yielded, blocked or exited
+    Voluntary,
+    /// The timer took the CPU away
+    Preempted,
+}
+
+/// Switch costs and slice lengths of one [`SwitchKind`]
+#[derive(Clone, Copy)]
+struct SchedProfile {
+    switch: Histogram,
+    slice: Histogram,
+}
+
+const NO_SWITCHES: SchedProfile = SchedProfile {
+    switch: EMPTY,
+    slice: EMPTY,
+};
+
+static PROFILING: AtomicBool = AtomicBool::new(false);
+static SYSCALL_PROFILES: SpinLock<[Histogram; MAX_SYSCALL_NUM]> =
+    SpinLock::new([EMPTY; MAX_SYSCALL_NUM]);
+/// Voluntary switches, then preemptions
+static SCHED_PROFILES: SpinLock<[SchedProfile; 2]> = SpinLock::new([NO_SWITCHES; 2]);
+
+/// Cycles and instructions retired, see `sys_perf_read`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PerfStat {
+    pub cycles: usize,
+    pub instret: usize,
+}
+
+impl PerfStat {
+    /// The counters of the current hart
+    pub fn now() -> Self {
+        let (cycles, instret): (usize, usize);
+        unsafe {
+            core::arch::asm!("rdcycle {}", "rdinstret {}", out(reg) cycles, out(reg) instret);
+        }
+        Self { cycles, instret }
+    }
+    /// The counts from `earlier` to `self`
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            cycles: self.cycles.wrapping_sub(earlier.cycles),
+            instret: self.instret.wrapping_sub(earlier.instret),
+        }
+    }
+    /// `PERF_UNSUPPORTED` in place of the counts the harts do not implement
+    pub fn masked(self) -> Self {
+        let mask = |supported: &AtomicBool, count| {
+            if supported.load(Ordering::Relaxed) {
+                count
+            } else {
+                PERF_UNSUPPORTED
+            }
+        };
+        Self {
+            cycles: mask(&CYCLE_SUPPORTED, self.cycles),
+            instret: mask(&INSTRET_SUPPORTED, self.instret),
+        }
+    }
+}
+
+impl core::ops::AddAssign for PerfStat {
+    fn add_assign(&mut self, other: Self) {
+        self.cycles = self.cycles.wrapping_add(other.cycles);
+        self.instret = self.instret.wrapping_add(other.instret);
+    }
+}
+
+/// Let user mode read the counters of this hart. The boot hart also finds
+/// out which counters run, one hardwired to zero stays put.
+pub fn init(boot: bool) {
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) COUNTEREN_CY_TM_IR);
+    }
+    if boot {
+        let before = PerfStat::now();
+        let mut spin = 0;
+        for i in 0..1000 {
+            unsafe { core::ptr::write_volatile(&mut spin, i) }
+        }
+        let after = PerfStat::now();
+        let cycle = after.cycles != before.cycles;
+        let instret = after.instret != before.instret;
+        CYCLE_SUPPORTED.store(cycle, Ordering::Relaxed);
+        INSTRET_SUPPORTED.store(instret, Ordering::Relaxed);
+        if !cycle {
+            warn!("[kernel] cycle counter not implemented");
+        }
+        if !instret {
+            warn!("[kernel] instret counter not implemented");
+        }
+    }
+}
+
+/// `cycle` of this hart, or `time` if the harts have no cycle counter
+fn profile_clock() -> usize {
+    if CYCLE_SUPPORTED.load(Ordering::Relaxed) {
+        PerfStat::now().cycles
+    } else {
+        crate::timer::get_time()
+    }
+}
+
+/// The time a syscall starts if profiling is on, for [`profile_syscall`]
+pub fn profile_start() -> Option<usize> {
+    if PROFILING.load(Ordering::Relaxed) {
+        Some(profile_clock())
+    } else {
+        None
+    }
+}
+
+/// Add a call of `syscall_id` that started at `start` to its histogram. A
+/// call that blocked may end on another hart whose counter is not in step,
+/// one that seems to end before it started counts as 0.
+pub fn profile_syscall(syscall_id: usize, start: usize) {
+    let latency = profile_clock().saturating_sub(start) as u64;
+    if let Some(profile) = SYSCALL_PROFILES.exclusive_access().get_mut(syscall_id) {
+        profile.add(latency);
+    }
+}
+
+/// Add a switch of `kind` to its histograms: the switch started at `start`
+/// by [`profile_start`] and ends now, back in the idle control flow of the
+/// hart, and ended a slice of `slice_us` microseconds since the task was
+/// dispatched
+pub fn record_switch(kind: SwitchKind, start: usize, slice_us: usize) {
+    let cost = profile_clock().saturating_sub(start) as u64;
+    let mut profiles = SCHED_PROFILES.exclusive_access();
+    let profile = &mut profiles[(kind == SwitchKind::Preempted) as usize];
+    profile.switch.add(cost);
+    profile.slice.add(slice_us as u64);
+}
+
+/// Whether syscall profiling is on
+pub fn profiling() -> bool {
+    PROFILING.load(Ordering::Relaxed)
+}
+
+/// Turn syscall profiling on or off, returns whether it was on
+pub fn set_profiling(on: bool) -> bool {
+    PROFILING.swap(on, Ordering::Relaxed)
+}
+
+/// Forget all latencies, switches and slices recorded so far
+pub fn reset_profiles() {
+    SYSCALL_PROFILES.exclusive_access().fill(EMPTY);
+    SCHED_PROFILES.exclusive_access().fill(NO_SWITCHES);
+}
+
+/// Unit of the latencies and switch costs
+fn profile_unit() -> &'static str {
+    if CYCLE_SUPPORTED.load(Ordering::Relaxed) {
+        "cycles"
+    } else {
+        "ticks"
+    }
+}
+
+/// The histograms as text, a header naming the unit and one line per
+/// syscall id called so far: id, calls, total and worst latency, then
+/// `i:n` for each bucket i holding n calls
+pub fn syscall_profiles() -> String {
+    let mut text = format!("# id count total max log2({}):count\n", profile_unit());
+    let profiles = SYSCALL_PROFILES.exclusive_access();
+    for (id, profile) in profiles.iter().enumerate() {
+        if profile.count == 0 {
+            continue;
+        }
+        write!(text, "{}", id).unwrap();
+        profile.write_to(&mut text);
+        text.push('\n');
+    }
+    text
+}
+
+/// The scheduler histograms as text, a header naming the units and four
+/// lines like those of [`syscall_profiles`], named `switch_voluntary`,
+/// `switch_preempted`, `slice_voluntary` and `slice_preempted`. A switch
+/// is timed from the task calling `__switch` until the idle control flow
+/// runs, a slice from the dispatch of the task until it switches out.
+pub fn sched_profiles() -> String {
+    let mut text = format!(
+        "# name count total max log2:count, switch in {}, slice in us\n",
+        profile_unit()
+    );
+    let profiles = *SCHED_PROFILES.exclusive_access();
+    let kinds = [("voluntary", &profiles[0]), ("preempted", &profiles[1])];
+    for (kind, profile) in kinds.iter() {
+        write!(text, "switch_{}", kind).unwrap();
+        profile.switch.write_to(&mut text);
+        text.push('\n');
+    }
+    for (kind, profile) in kinds.iter() {
+        write!(text, "slice_{}", kind).unwrap();
+        profile.slice.write_to(&mut text);
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,59 @@
+//! Kernel entropy source
+//!
+//! A xorshift64* generator seeded from the time counter at boot. Good
+//! enough to spread address space layouts, not for anything secret.
+
+use crate::sync::SpinLock;
+use lazy_static::*;
+use riscv::register::time;
+
+/// A xorshift64* generator
+pub struct XorShift64(u64);
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves 0
+        Self(if seed == 0 { 1 } else { seed })
+    }
+    /// Next random number
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+lazy_static! {
+    static ref STATE: SpinLock<XorShift64> = SpinLock::new(XorShift64::new(0x9e37_79b9_7f4a_7c15));
+}
+
+/// Mix the time counter into the state, the time boot took varies a little
+/// from run to run
+pub fn init() {
+    let mut state = STATE.exclusive_access();
+    let seed = state.0 ^ (time::read() as u64).wrapping_mul(0x2545_f491_4f6c_dd1d);
+    *state = XorShift64::new(seed);
+}
+
+/// The cycle counter, which unlike the time counter moves on every
+/// instruction
+pub fn cycle() -> u64 {
+    let cycle: u64;
+    unsafe {
+        core::arch::asm!("rdcycle {}", out(reg) cycle);
+    }
+    cycle
+}
+
+/// Next random number
+pub fn rand_u64() -> u64 {
+    STATE.exclusive_access().next_u64()
+}
+
+/// Random number in `[0, bound)`, `bound` must not be 0
+pub fn rand_below(bound: usize) -> usize {
+    (rand_u64() % bound as u64) as usize
+}
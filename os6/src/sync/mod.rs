@@ -1,5 +1,9 @@
 //! Synchronization and interior mutability primitives
 
+mod mutex;
+mod spin;
 mod up;
 
+pub use mutex::{Mutex, MutexGuard};
+pub use spin::{pop_off, push_off, spin_locks_held, SpinLock, SpinLockGuard};
 pub use up::UPSafeCell;
@@ -0,0 +1,134 @@
+//! Spin lock usable across harts
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use crate::config::MAX_HARTS;
+use crate::task::hart_id;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+const NONE_HELD: AtomicUsize = AtomicUsize::new(0);
+const NOT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// SpinLocks held on each hart, see [`spin_locks_held`]
+static HELD: [AtomicUsize; MAX_HARTS] = [NONE_HELD; MAX_HARTS];
+/// Depth of [`push_off`] on each hart
+static NOFF: [AtomicUsize; MAX_HARTS] = [NONE_HELD; MAX_HARTS];
+/// Whether `sstatus.SIE` was set before the outermost [`push_off`]
+static INTENA: [AtomicBool; MAX_HARTS] = [NOT_ENABLED; MAX_HARTS];
+
+/// Disable supervisor interrupts on this hart until the matching
+/// [`pop_off`], like xv6. Calls nest, and only the outermost pair saves and
+/// restores `sstatus.SIE`, so guards may be dropped in any order.
+pub fn push_off() {
+    let sie = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+    }
+    // interrupts are off, nothing else touches this hart's entries now
+    let hart = hart_id();
+    if NOFF[hart].fetch_add(1, Ordering::Relaxed) == 0 {
+        INTENA[hart].store(sie, Ordering::Relaxed);
+    }
+}
+
+/// Undo one [`push_off`], enabling interrupts again after the outermost one
+/// if they were enabled before it
+pub fn pop_off() {
+    assert!(!sstatus::read().sie(), "pop_off with interrupts enabled");
+    let hart = hart_id();
+    let depth = NOFF[hart].fetch_sub(1, Ordering::Relaxed);
+    assert!(depth > 0, "pop_off without push_off");
+    if depth == 1 && INTENA[hart].load(Ordering::Relaxed) {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+}
+
+/// Number of SpinLocks the current hart holds.
+///
+/// A guard is dropped on the hart that took it: nothing switches tasks while
+/// holding a SpinLock, so this is also what the current task holds.
+pub fn spin_locks_held() -> usize {
+    HELD[hart_id()].load(Ordering::Relaxed)
+}
+
+/// A busy-waiting lock shared between harts.
+///
+/// Supervisor interrupts are disabled while the lock is held, see
+/// [`push_off`], so an interrupt handler on the same hart can never spin on
+/// a lock its own hart owns.
+///
+/// In order to get mutable reference of inner data, call
+/// `exclusive_access`.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    /// inner data
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinLock<T> {}
+
+/// Holds a [`SpinLock`] until dropped
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(value),
+        }
+    }
+    /// Spin until the lock is free, then take it with interrupts disabled.
+    pub fn exclusive_access(&self) -> SpinLockGuard<'_, T> {
+        push_off();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        HELD[hart_id()].fetch_add(1, Ordering::Relaxed);
+        SpinLockGuard { lock: self }
+    }
+    /// Take the lock with interrupts disabled if it is free, without spinning.
+    pub fn try_exclusive_access(&self) -> Option<SpinLockGuard<'_, T>> {
+        push_off();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            HELD[hart_id()].fetch_add(1, Ordering::Relaxed);
+            Some(SpinLockGuard { lock: self })
+        } else {
+            pop_off();
+            None
+        }
+    }
+}
+
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        HELD[hart_id()].fetch_sub(1, Ordering::Relaxed);
+        pop_off();
+    }
+}
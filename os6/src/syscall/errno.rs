@@ -0,0 +1,82 @@
+//! Error numbers of the syscalls
+//!
+//! A failed syscall returns the negated error number, like Linux, whose
+//! numbers these are. The user library includes this very file, so both
+//! sides agree on them.
+//!
+//! mmap, munmap and waitpid keep the -1 of the original ABI, which the
+//! ci-user tests are graded on, and the -2 of a waitpid with no child
+//! exited yet. mmap_file, munmap2 and wait4 do the same with the errno.
+
+/// The caller may not do this: not privileged, the file differs from the
+/// exec manifest, or a pipe capacity above the most the kernel allows
+pub const EPERM: isize = 1;
+/// No such file or directory, or no such executable
+pub const ENOENT: isize = 2;
+/// No such process or process group
+pub const ESRCH: isize = 3;
+/// A blocking call cut short by SIGKILL, never seen by user mode
+pub const EINTR: isize = 4;
+/// A named pipe opened to write without blocking has no reader
+pub const ENXIO: isize = 6;
+/// A write to the disk failed and no spare block took its place, see
+/// `easy_fs::RemapDevice`
+pub const EIO: isize = 5;
+/// Exec of a file that is not a loadable executable
+pub const ENOEXEC: isize = 8;
+/// The descriptor is not open, or not open for the access
+pub const EBADF: isize = 9;
+/// No child matches `sys_wait4`
+pub const ECHILD: isize = 10;
+/// Would block under `O_NONBLOCK`, a futex word changed, a process limit
+/// is reached, or no child matching `sys_wait4` has exited yet
+pub const EAGAIN: isize = 11;
+/// Out of memory: frames, kernel heap or `RLIMIT_AS`
+pub const ENOMEM: isize = 12;
+/// The permission bits do not allow the access
+pub const EACCES: isize = 13;
+/// Bad user memory
+pub const EFAULT: isize = 14;
+/// A pipe capacity below the bytes in the pipe, or a restore of the
+/// filesystem while pages are swapped out to it
+pub const EBUSY: isize = 16;
+/// The name is taken, or the range is mapped already
+pub const EEXIST: isize = 17;
+/// A hard link between two filesystems
+pub const EXDEV: isize = 18;
+/// Mapping a file that has no inode, like a pipe
+pub const ENODEV: isize = 19;
+/// A file where a directory is expected
+pub const ENOTDIR: isize = 20;
+/// A directory where a file is expected
+pub const EISDIR: isize = 21;
+/// Invalid argument
+pub const EINVAL: isize = 22;
+/// No free descriptor below `RLIMIT_NOFILE`
+pub const EMFILE: isize = 24;
+/// The descriptor is not the console
+pub const ENOTTY: isize = 25;
+/// A write past the largest file the filesystem holds
+pub const EFBIG: isize = 27;
+/// No room left on the filesystem
+pub const ENOSPC: isize = 28;
+/// Seeking a pipe or the console
+pub const ESPIPE: isize = 29;
+/// A change to a read-only filesystem
+pub const EROFS: isize = 30;
+/// A write to a pipe with no read end left
+pub const EPIPE: isize = 32;
+/// A name longer than a directory entry holds, or a path longer than
+/// `PATH_MAX`
+pub const ENAMETOOLONG: isize = 36;
+/// The kernel does not have this syscall
+pub const ENOSYS: isize = 38;
+/// The directory to remove still has entries
+pub const ENOTEMPTY: isize = 39;
+/// No wakeup came in time
+pub const ETIMEDOUT: isize = 110;
+/// The file is gone, a restore of a filesystem snapshot freed or replaced
+/// its inode
+pub const ESTALE: isize = 116;
+/// A write over a directory quota
+pub const EDQUOT: isize = 122;
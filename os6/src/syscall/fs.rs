@@ -1,26 +1,132 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::{UserBuffer, translated_byte_buffer, translated_str, translated_refmut};
+use crate::config::{PAGE_SIZE, PATH_MAX};
+use crate::mm::{
+    UserBuffer, copy_from_user, copy_to_user, copy_user_bytes, strncpy_from_user, translated_byte_buffer,
+    translated_readable_buffer, heap_low, swap_in_use,
+};
 use crate::task::current_user_token;
 use crate::task::current_task;
-use crate::fs::{OpenFlags, Stat, open_file, link_file, unlink_file};
+use crate::fs::{
+    DirError, EventFd, FdSlot, File, LocalModes, OpenFlags, PipeSizeError, PollEvents,
+    SeekError, SeekFrom,
+    Stat, ROOT_INODE,
+    chmod_file, console_foreground, fs_failed, console_modes, make_dir, make_fifo, make_pipe, open_device, open_fifo, open_file, open_proc, link_file,
+    remove_dir, restore_fs, set_console_foreground, set_console_modes, snapshot_fs, sync_all,
+    unlink_file,
+};
+use crate::task::{
+    arm_wait_timeout, block_current_killable, disarm_wait_timeout, group_exists, IoKind,
+    TaskStatus,
+};
+use crate::timer::get_time_ms;
+use super::errno::*;
+use super::{path_errno, uaccess_errno};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::Inode;
+
+/// Whence of `sys_lseek`
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+/// `dirfd` standing for the current directory, which is always the root
+const AT_FDCWD: isize = -100;
+/// `sys_unlinkat` flag to remove a directory instead of a file
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// The directory paths relative to `dirfd` start from, the root for
+/// `AT_FDCWD`. Fails with the negated errno if `dirfd` is not open or not a
+/// directory.
+fn base_dir(dirfd: isize) -> Result<Arc<Inode>, isize> {
+    if dirfd == AT_FDCWD {
+        return Ok(ROOT_INODE.clone());
+    }
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    match inner.get_file(dirfd as usize) {
+        Some(file) if file.stale() => Err(-ESTALE),
+        Some(file) => file.dir().ok_or(-ENOTDIR),
+        None => Err(-EBADF),
+    }
+}
+
+/// Whether `file` may be read, or written unless `read`. Directories are
+/// neither, their entries are read with `sys_getdents`, and neither is a
+/// stale file, see [`File::stale`].
+fn check_access(file: &Arc<dyn File + Send + Sync>, read: bool) -> Result<(), isize> {
+    if file.stale() {
+        Err(-ESTALE)
+    } else if file.dir().is_some() {
+        Err(-EISDIR)
+    } else if read && !file.readable() || !read && !file.writable() {
+        Err(-EBADF)
+    } else {
+        Ok(())
+    }
+}
+
+/// Write `buf` to `file` and charge the bytes to the caller, -EAGAIN if it
+/// would block under `O_NONBLOCK`, -EPIPE if nothing went into a pipe
+/// without readers
+fn write_file(file: &Arc<dyn File + Send + Sync>, buf: UserBuffer) -> isize {
+    let want = buf.len();
+    let written = if file.nonblocking() {
+        file.write_nonblocking(buf).map_or(-EAGAIN, |len| len as isize)
+    } else {
+        file.try_write(buf).map_or_else(|err| path_errno(err.into()), |len| len as isize)
+    };
+    if written == 0 && want > 0 && file.pipe().map_or(false, |pipe| pipe.broken()) {
+        -EPIPE
+    } else {
+        if written > 0 {
+            current_task().unwrap().io.charge_write(io_kind(file), written as usize);
+        }
+        written
+    }
+}
+
+/// Read `file` into `buf` and charge the bytes to the caller, -EAGAIN if it
+/// would block under `O_NONBLOCK`
+fn read_file(file: &Arc<dyn File + Send + Sync>, buf: UserBuffer) -> isize {
+    let read = if file.nonblocking() {
+        file.read_nonblocking(buf).map_or(-EAGAIN, |len| len as isize)
+    } else {
+        file.read(buf) as isize
+    };
+    if read > 0 {
+        current_task().unwrap().io.charge_read(io_kind(file), read as usize);
+    }
+    read
+}
+
+/// What the bytes moved through `file` count as, see `IoStat`
+fn io_kind(file: &Arc<dyn File + Send + Sync>) -> IoKind {
+    if file.pipe().is_some() {
+        IoKind::Pipe
+    } else if file.is_tty() {
+        IoKind::Console
+    } else {
+        IoKind::File
+    }
+}
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
+    if let Some(file) = inner.get_file(fd) {
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.write(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
+        if let Err(errno) = check_access(&file, false) {
+            return errno;
+        }
+        match translated_readable_buffer(token, buf, len) {
+            Some(buffers) => write_file(&file, UserBuffer::new(buffers)),
+            None => -EFAULT,
+        }
     } else {
-        -1
+        -EBADF
     }
 }
 
@@ -28,78 +134,847 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
     let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
+    if let Some(file) = inner.get_file(fd) {
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.read(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
+        if let Err(errno) = check_access(&file, true) {
+            return errno;
+        }
+        match translated_byte_buffer(token, buf, len) {
+            Some(buffers) => read_file(&file, UserBuffer::new(buffers)),
+            None => -EFAULT,
+        }
     } else {
-        -1
+        -EBADF
+    }
+}
+
+/// A buffer of `sys_readv` and `sys_writev`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IoVec {
+    pub base: usize,
+    pub len: usize,
+}
+
+/// Most iovecs a single `sys_readv` or `sys_writev` takes
+const IOV_MAX: usize = 64;
+
+/// Translate the buffers of the `iovcnt` iovecs at `iov` into a single
+/// buffer, writable by the kernel if `write`
+fn translated_iovecs(iov: *const IoVec, iovcnt: usize, write: bool) -> Result<UserBuffer, isize> {
+    if iovcnt > IOV_MAX {
+        return Err(-EINVAL);
+    }
+    let token = current_user_token();
+    let mut buffers = Vec::new();
+    let mut total: usize = 0;
+    for i in 0..iovcnt {
+        let iov = copy_from_user(token, iov.wrapping_add(i)).map_err(|_| -EFAULT)?;
+        total = total
+            .checked_add(iov.len)
+            .filter(|total| *total <= isize::MAX as usize)
+            .ok_or(-EINVAL)?;
+        let translated = if write {
+            translated_byte_buffer(token, iov.base as *const u8, iov.len)
+        } else {
+            translated_readable_buffer(token, iov.base as *const u8, iov.len)
+        };
+        buffers.extend(translated.ok_or(-EFAULT)?);
+    }
+    Ok(UserBuffer::new(buffers))
+}
+
+/// Write the buffers of `iovcnt` iovecs at `iov` to `fd` one after another
+/// with a single write. Returns the total bytes written.
+pub fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let file = match current_task().unwrap().inner_exclusive_access().get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if let Err(errno) = check_access(&file, false) {
+        return errno;
+    }
+    match translated_iovecs(iov, iovcnt, false) {
+        Ok(buf) => write_file(&file, buf),
+        Err(errno) => errno,
+    }
+}
+
+/// Fill the buffers of `iovcnt` iovecs at `iov` one after another with a
+/// single read from `fd`. Returns the total bytes read.
+pub fn sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let file = match current_task().unwrap().inner_exclusive_access().get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if let Err(errno) = check_access(&file, true) {
+        return errno;
+    }
+    match translated_iovecs(iov, iovcnt, true) {
+        Ok(buf) => read_file(&file, buf),
+        Err(errno) => errno,
+    }
+}
+
+/// The offset at `offset` for `sys_copy_file_range`, or the offset of
+/// `file` if it is null
+fn copy_offset(
+    token: usize,
+    offset: *mut isize,
+    file: &Arc<dyn File + Send + Sync>,
+) -> Result<usize, isize> {
+    if offset.is_null() {
+        return file.seek(SeekFrom::Current(0)).map_err(|_| -EINVAL);
+    }
+    match copy_from_user(token, offset) {
+        Ok(offset) if offset >= 0 => Ok(offset as usize),
+        Ok(_) => Err(-EINVAL),
+        Err(_) => Err(-EFAULT),
+    }
+}
+
+/// Copy up to `len` bytes from `fd_in` to `fd_out` inside the kernel,
+/// without going through user memory. Each offset is read from and
+/// advanced at its pointer, or is the offset of the file if the pointer is
+/// null. Only regular files are supported, and the ranges of a file copied
+/// onto itself must not overlap. With both offsets and `len` block-aligned
+/// the data moves a block at a time from one cached block to the other,
+/// see [`easy_fs::Inode::copy_to`]. Returns the bytes copied, 0 at the end
+/// of `fd_in`.
+pub fn sys_copy_file_range(
+    fd_in: usize,
+    off_in: *mut isize,
+    fd_out: usize,
+    off_out: *mut isize,
+    len: usize,
+    flags: u32,
+) -> isize {
+    if flags != 0 {
+        return -EINVAL;
+    }
+    let (file_in, file_out) = {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        match (inner.get_file(fd_in), inner.get_file(fd_out)) {
+            (Some(file_in), Some(file_out)) => (file_in, file_out),
+            _ => return -EBADF,
+        }
+    };
+    if !file_in.readable() || !file_out.writable() || file_out.append() {
+        return -EBADF;
+    }
+    let (inode_in, inode_out) = match (file_in.inode(), file_out.inode()) {
+        (Some(inode_in), Some(inode_out)) => (inode_in, inode_out),
+        // pipes, the console and directories
+        _ => return -EINVAL,
+    };
+    let token = current_user_token();
+    let (pos_in, pos_out) = match (
+        copy_offset(token, off_in, &file_in),
+        copy_offset(token, off_out, &file_out),
+    ) {
+        (Ok(pos_in), Ok(pos_out)) => (pos_in, pos_out),
+        (Err(errno), _) | (_, Err(errno)) => return errno,
+    };
+    let len = len.min(isize::MAX as usize);
+    if inode_in.stat().ino == inode_out.stat().ino
+        && pos_in < pos_out.saturating_add(len)
+        && pos_out < pos_in.saturating_add(len)
+    {
+        return -EINVAL;
+    }
+    let copied = inode_in.copy_to(pos_in, &inode_out, pos_out, len);
+    for (offset, file, pos) in [(off_in, &file_in, pos_in), (off_out, &file_out, pos_out)] {
+        let pos = pos + copied;
+        if offset.is_null() {
+            file.seek(SeekFrom::Start(pos)).unwrap();
+        } else if copy_to_user(token, offset, &(pos as isize)).is_err() {
+            return -EFAULT;
+        }
+    }
+    copied as isize
+}
+
+/// Move the offset of the open file `fd`, shared with its duplicates, to
+/// `offset` counted from `whence`. Returns the new offset.
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let file = match current_task().unwrap().inner_exclusive_access().get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if file.stale() {
+        return -ESTALE;
+    }
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return -EINVAL,
+    };
+    match file.seek(pos) {
+        Ok(offset) => offset as isize,
+        Err(SeekError::NotSeekable) => -ESPIPE,
+        Err(SeekError::Invalid) => -EINVAL,
     }
 }
 
+/// Open the file at `path` into the lowest free descriptor, with
+/// `FD_CLOEXEC` for `O_CLOEXEC` from the start
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    if heap_low() {
+        return -ENOMEM;
+    }
     let task = current_task().unwrap();
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(
-        path.as_str(),
-        OpenFlags::from_bits(flags).unwrap()
-    ) {
-        let mut inner = task.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
+    let path = match strncpy_from_user(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let file = match open_device(&path).or_else(|| open_proc(&path)) {
+        Some(file) => file,
+        None => match open_file(path.as_str(), flags) {
+            // a named pipe only has a name on the disk, open an end instead
+            Ok(file) => match file.inode().filter(|inode| inode.is_fifo()) {
+                Some(inode) => match open_fifo(inode.inode_id(), flags) {
+                    Ok(pipe) => pipe as Arc<dyn File + Send + Sync>,
+                    Err(err) => return path_errno(err),
+                },
+                None => file as Arc<dyn File + Send + Sync>,
+            },
+            Err(err) => return path_errno(err),
+        },
+    };
+    file.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+    let mut inner = task.inner_exclusive_access();
+    if let Some(fd) = inner.alloc_fd() {
+        inner.fd_table.install(fd, FdSlot::with_cloexec(file, flags.contains(OpenFlags::CLOEXEC)));
         fd as isize
     } else {
-        -1
+        -EMFILE
     }
 }
 
 pub fn sys_close(fd: usize) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let slot = match inner.fd_table.remove(fd) {
+        Some(slot) => slot,
+        None => return -EBADF,
+    };
+    // the last reference to a pipe end wakes other tasks, which must not
+    // happen under our TCB lock
+    drop(inner);
+    drop(slot);
+    0
+}
+
+/// Close the open descriptors from `first` to `last`, both included, the
+/// ones not open skipped. No `flags` are supported.
+pub fn sys_close_range(first: usize, last: usize, flags: usize) -> isize {
+    if first > last || flags != 0 {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let closed = inner.fd_table.remove_range(first, last);
+    // like `sys_close`, pipe ends are closed without our TCB lock
+    drop(inner);
+    drop(closed);
+    0
+}
+
+/// Duplicate `fd` into the lowest free descriptor. Both refer to the same
+/// open file, sharing its offset and flags.
+pub fn sys_dup(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    match inner.alloc_fd() {
+        Some(new_fd) => {
+            inner.fd_table.install(new_fd, FdSlot::new(file));
+            new_fd as isize
+        }
+        None => -EMFILE,
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+}
+
+/// Make `new_fd` refer to the open file of `old_fd` like [`sys_dup`], closing
+/// it first if it is open, and leaving it as it is if it is `old_fd`.
+/// Returns `new_fd`, or -EBADF if `old_fd` is not open or `new_fd` is beyond
+/// the `RLIMIT_NOFILE` limit.
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    dup_to(old_fd, new_fd, false)
+}
+
+/// [`sys_dup2`] setting `FD_CLOEXEC` on `new_fd` for `O_CLOEXEC`, the only
+/// flag, together with the duplicate. -EINVAL for other flags and for
+/// `old_fd` equal to `new_fd`.
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    if flags & !OpenFlags::CLOEXEC.bits() != 0 || old_fd == new_fd {
+        return -EINVAL;
+    }
+    dup_to(old_fd, new_fd, flags != 0)
+}
+
+/// Duplicate `old_fd` into `new_fd`, with `FD_CLOEXEC` if `cloexec`, under
+/// one hold of the TCB lock, so no fork in between sees it without
+fn dup_to(old_fd: usize, new_fd: usize, cloexec: bool) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.get_file(old_fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if old_fd == new_fd {
+        return new_fd as isize;
+    }
+    let max_fds = inner.rlimits.max_fds;
+    if new_fd >= max_fds {
+        return -EBADF;
+    }
+    if !inner.fd_table.reserve(new_fd, max_fds) {
+        return -ENOMEM;
+    }
+    let old = inner.fd_table.install(new_fd, FdSlot::with_cloexec(file, cloexec));
+    drop(inner);
+    drop(old);
+    new_fd as isize
+}
+
+/// Create a pipe, storing the descriptors of its read end and its write end
+/// into `pipe_fd[0]` and `pipe_fd[1]`. `flags` are `O_NONBLOCK` and
+/// `O_CLOEXEC`; the pipe holds 4096 bytes until `F_SETPIPE_SZ`.
+pub fn sys_pipe2(pipe_fd: *mut u32, flags: u32) -> isize {
+    if flags & !(OpenFlags::CLOEXEC | OpenFlags::NONBLOCK).bits() != 0 {
+        return -EINVAL;
+    }
+    if heap_low() {
+        return -ENOMEM;
+    }
+    let cloexec = flags & OpenFlags::CLOEXEC.bits() != 0;
+    let task = current_task().unwrap();
+    let (pipe_read, pipe_write) = match make_pipe(flags & OpenFlags::NONBLOCK.bits() != 0) {
+        Some(pipe) => pipe,
+        None => return -ENOMEM,
+    };
+    let mut inner = task.inner_exclusive_access();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table.install(read_fd, FdSlot::with_cloexec(pipe_read, cloexec));
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            let pipe_read = inner.fd_table.remove(read_fd);
+            drop(inner);
+            drop(pipe_read);
+            return -EMFILE;
+        }
+    };
+    inner.fd_table.install(write_fd, FdSlot::with_cloexec(pipe_write, cloexec));
+    drop(inner);
+    let fds = [read_fd as u32, write_fd as u32];
+    if copy_to_user(current_user_token(), pipe_fd as *mut [u32; 2], &fds).is_err() {
+        sys_close(read_fd);
+        sys_close(write_fd);
+        return -EFAULT;
     }
-    inner.fd_table[fd].take();
     0
 }
 
-// YOUR JOB: 扩展 easy-fs 和内核以实现以下三个 syscall
-pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
+/// `sys_eventfd` flag: reads take 1 instead of the whole count
+const EFD_SEMAPHORE: u32 = 1;
+/// `sys_eventfd` flag: `FD_CLOEXEC` on the new fd
+const EFD_CLOEXEC: u32 = 1 << 19;
+
+/// Create an event counter starting at `initval`, see `EventFd`. `flags`
+/// are `EFD_SEMAPHORE`, `EFD_CLOEXEC` and `O_NONBLOCK`. Returns the new fd.
+pub fn sys_eventfd(initval: u32, flags: u32) -> isize {
+    if flags & !(EFD_SEMAPHORE | EFD_CLOEXEC | OpenFlags::NONBLOCK.bits()) != 0 {
+        return -EINVAL;
+    }
+    if heap_low() {
+        return -ENOMEM;
+    }
+    let file = EventFd::new(
+        initval as u64,
+        flags & EFD_SEMAPHORE != 0,
+        flags & OpenFlags::NONBLOCK.bits() != 0,
+    );
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    if _fd >= inner.fd_table.len() || inner.fd_table[_fd].is_none() {
-        return -1;
+    match inner.alloc_fd() {
+        Some(fd) => {
+            inner.fd_table.install(fd, FdSlot::with_cloexec(file, flags & EFD_CLOEXEC != 0));
+            fd as isize
+        }
+        None => -EMFILE,
     }
-    if let Some(file) = &inner.fd_table[_fd] {
-        let file = file.clone();
+}
+
+// YOUR JOB: 扩展 easy-fs 和内核以实现以下三个 syscall
+pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if let Some(file) = inner.get_file(_fd) {
         drop(inner);
-        let st = translated_refmut(current_user_token(), _st);
-        *st = file.fstat();
-        0
+        if file.stale() {
+            return -ESTALE;
+        }
+        let st = file.fstat();
+        match copy_to_user(current_user_token(), _st, &st) {
+            Ok(()) => 0,
+            Err(_) => -EFAULT,
+        }
     } else {
-        -1
+        -EBADF
+    }
+}
+
+/// Set the permission bits of the file `fd`, like [`sys_fchmodat`]. Fails
+/// with -EINVAL for pipes and devices, which have no inode to keep them.
+pub fn sys_fchmod(fd: usize, mode: u32) -> isize {
+    let task = current_task().unwrap();
+    let file = match task.inner_exclusive_access().get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    match file.inode().or_else(|| file.dir()) {
+        Some(inode) if inode.read_only() => -EROFS,
+        Some(inode) => {
+            inode.set_mode(mode as u16);
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// Set the permission bits of the file at `path`, relative to the open
+/// directory `dirfd` like in [`sys_mkdirat`]. Only the `rwx` bits of the
+/// owner, the group and others are kept; with no users, only the owner's
+/// are enforced, on open and exec.
+pub fn sys_fchmodat(dirfd: isize, path: *const u8, mode: u32) -> isize {
+    let path = match strncpy_from_user(current_user_token(), path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let base = match base_dir(dirfd) {
+        Ok(base) => base,
+        Err(errno) => return errno,
+    };
+    match chmod_file(&base, &path, mode as u16) {
+        Ok(()) => 0,
+        Err(err) => path_errno(err),
+    }
+}
+
+/// Write what is cached of the file `fd` back to the disk. A no-op for
+/// pipes and the console. Returns -EIO once a write to the disk was lost.
+pub fn sys_fsync(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let file = match task.inner_exclusive_access().get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    file.sync();
+    if fs_failed() { -EIO } else { 0 }
+}
+
+/// Write every cached block back to the disk, -EIO once a write was lost
+pub fn sys_sync() -> isize {
+    sync_all();
+    if fs_failed() { -EIO } else { 0 }
+}
+
+/// Snapshot the root filesystem for 0, restore its snapshot for 1, see
+/// `snapshot_fs` and `restore_fs`. Returns the blocks copied, -EPERM if
+/// the caller is not privileged, -EINVAL if `op` is out of range, -EROFS if
+/// the filesystem is read-only, -ENOSPC if the snapshot does not fit, or
+/// for a restore -ENOENT if there is no snapshot and -EBUSY if pages are
+/// swapped out, the restore would lose them.
+pub fn sys_fs_snapshot(op: usize) -> isize {
+    if !current_task().unwrap().inner_exclusive_access().privileged {
+        return -EPERM;
+    }
+    let copied = match op {
+        0 => snapshot_fs(),
+        1 if swap_in_use() => return -EBUSY,
+        1 => restore_fs(),
+        _ => return -EINVAL,
+    };
+    match copied {
+        Ok(blocks) => blocks as isize,
+        Err(err) => path_errno(err.into()),
+    }
+}
+
+/// Create a named pipe at `path`, relative to the root like in
+/// [`sys_open`]. Opening it connects to the other processes that have it
+/// open, see `open_fifo`.
+pub fn sys_mkfifo(path: *const u8) -> isize {
+    let path = match strncpy_from_user(current_user_token(), path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    match make_fifo(&ROOT_INODE, &path) {
+        Ok(()) => 0,
+        Err(err) => path_errno(err),
     }
 }
 
-pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
+/// Create a directory at `path`, relative to the open directory `dirfd`
+/// or to the root for `AT_FDCWD`. There are no permissions, `mode` is
+/// ignored.
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, _mode: u32) -> isize {
+    let path = match strncpy_from_user(current_user_token(), path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let base = match base_dir(dirfd) {
+        Ok(base) => base,
+        Err(errno) => return errno,
+    };
+    match make_dir(&base, &path) {
+        Ok(()) => 0,
+        Err(err) => path_errno(err),
+    }
+}
+
+/// Fill `buf` with the records of the next entries of the directory `fd`:
+/// the inode number as a `u64`, the record length as a `u16`, the type
+/// byte and the NUL-terminated name, each record 8-byte aligned. A record
+/// is never split, the next call goes on from the first one left out.
+/// The offset of `fd` tracks the progress: `sys_lseek` to 0 starts over,
+/// to a saved offset resumes there, and from the end gives the size of
+/// the directory. Returns the bytes written, 0 at the end, or -EINVAL if even the next
+/// record does not fit.
+pub fn sys_getdents(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let file = {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        match inner.get_file(fd) {
+            Some(file) => file,
+            None => return -EBADF,
+        }
+    };
+    if file.stale() {
+        return -ESTALE;
+    }
+    let mut records = alloc::vec![0u8; len.min(PAGE_SIZE)];
+    let written = match file.getdents(&mut records) {
+        Ok(written) => written,
+        Err(DirError::NotDir) => return -ENOTDIR,
+        Err(DirError::BufferTooSmall) => return -EINVAL,
+    };
+    match copy_user_bytes(current_user_token(), buf, &records[..written]) {
+        Ok(()) => written as isize,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Link the file at `old_path` as `new_path`, each relative to its own
+/// directory fd like in [`sys_mkdirat`]. No `flags` are supported.
+pub fn sys_linkat(
+    old_dirfd: isize,
+    old_path: *const u8,
+    new_dirfd: isize,
+    new_path: *const u8,
+    flags: u32,
+) -> isize {
+    if flags != 0 {
+        return -EINVAL;
+    }
     let token = current_user_token();
-    let old_name = translated_str(token, _old_name);
-    let new_name = translated_str(token, _new_name);
-    link_file(&old_name, &new_name)
+    let (old_path, new_path) = match (
+        strncpy_from_user(token, old_path, PATH_MAX),
+        strncpy_from_user(token, new_path, PATH_MAX),
+    ) {
+        (Ok(old_path), Ok(new_path)) => (old_path, new_path),
+        (Err(err), _) | (_, Err(err)) => return uaccess_errno(err),
+    };
+    let (old_base, new_base) = match (base_dir(old_dirfd), base_dir(new_dirfd)) {
+        (Ok(old_base), Ok(new_base)) => (old_base, new_base),
+        (Err(errno), _) | (_, Err(errno)) => return errno,
+    };
+    match link_file(&old_base, &old_path, &new_base, &new_path) {
+        Ok(()) => 0,
+        Err(err) => path_errno(err),
+    }
 }
 
-pub fn sys_unlinkat(_name: *const u8) -> isize {
+/// Remove the file at `path` relative to `dirfd`, or the empty directory
+/// with `AT_REMOVEDIR`
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: u32) -> isize {
+    if flags & !AT_REMOVEDIR != 0 {
+        return -EINVAL;
+    }
+    let path = match strncpy_from_user(current_user_token(), path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let base = match base_dir(dirfd) {
+        Ok(base) => base,
+        Err(errno) => return errno,
+    };
+    let result = if flags & AT_REMOVEDIR != 0 {
+        remove_dir(&base, &path)
+    } else {
+        unlink_file(&base, &path)
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => path_errno(err),
+    }
+}
+
+/// `sys_ioctl` request: get the local modes of the console. Unlike Linux,
+/// `arg` points to just the `c_lflag` word, a `u32`, not a whole termios.
+const TCGETS: usize = 0x5401;
+/// `sys_ioctl` request: set the local modes of the console from a `u32`
+const TCSETS: usize = 0x5402;
+/// `sys_ioctl` request: get the process group Ctrl-C is sent to as an `i32`
+const TIOCGPGRP: usize = 0x540f;
+/// `sys_ioctl` request: send Ctrl-C to the process group in an `i32`, 0 for
+/// none. Every process of the group gets SIGINT.
+const TIOCSPGRP: usize = 0x5410;
+
+/// Configure the console through `fd`, which must refer to it. `arg` points
+/// to the value `request` gets or sets.
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let is_tty = {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        match inner.get_file(fd) {
+            Some(file) => file.is_tty(),
+            None => return -EBADF,
+        }
+    };
+    if !is_tty {
+        return -ENOTTY;
+    }
     let token = current_user_token();
-    let name = translated_str(token, _name);
-    unlink_file(&name)
+    let result = match request {
+        TCGETS => copy_to_user(token, arg as *mut u32, &console_modes().bits()),
+        TCSETS => match copy_from_user(token, arg as *const u32) {
+            Ok(bits) => match LocalModes::from_bits(bits) {
+                Some(modes) => {
+                    set_console_modes(modes);
+                    Ok(())
+                }
+                None => return -EINVAL,
+            },
+            Err(err) => Err(err),
+        },
+        TIOCGPGRP => copy_to_user(token, arg as *mut i32, &(console_foreground() as i32)),
+        TIOCSPGRP => match copy_from_user(token, arg as *const i32) {
+            Ok(pgid) if pgid < 0 => return -EINVAL,
+            Ok(pgid) if pgid > 0 && !group_exists(pgid as usize) => return -ESRCH,
+            Ok(pgid) => {
+                set_console_foreground(pgid as usize);
+                Ok(())
+            }
+            Err(err) => Err(err),
+        },
+        _ => return -EINVAL,
+    };
+    match result {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// `sys_fcntl` command: duplicate to the lowest free fd not below `arg`
+const F_DUPFD: usize = 0;
+/// `sys_fcntl` command: get the flags of the descriptor, `FD_CLOEXEC`
+const F_GETFD: usize = 1;
+/// `sys_fcntl` command: set the flags of the descriptor
+const F_SETFD: usize = 2;
+/// `sys_fcntl` command: get the access mode, `O_NONBLOCK` and `O_APPEND`
+const F_GETFL: usize = 3;
+/// `sys_fcntl` command: set or clear `O_NONBLOCK` and `O_APPEND`, other
+/// flags are ignored
+const F_SETFL: usize = 4;
+/// `sys_fcntl` command: [`F_DUPFD`] with `FD_CLOEXEC` on the new fd
+const F_DUPFD_CLOEXEC: usize = 1030;
+/// `sys_fcntl` command: let a pipe hold `arg` bytes, rounded up to whole
+/// pages, returns the new capacity
+const F_SETPIPE_SZ: usize = 1031;
+/// `sys_fcntl` command: get the capacity of a pipe
+const F_GETPIPE_SZ: usize = 1032;
+/// Descriptor flag: close on exec
+const FD_CLOEXEC: usize = 1;
+
+/// Manage the descriptor `fd`. The descriptor flags belong to `fd` alone,
+/// the file status flags to the open file, shared with its duplicates.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let file = match inner.get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= inner.rlimits.max_fds {
+                return -EINVAL;
+            }
+            let new_fd = match inner.alloc_fd_from(arg) {
+                Some(new_fd) => new_fd,
+                None => return -EMFILE,
+            };
+            inner.fd_table.install(new_fd, FdSlot::with_cloexec(file, cmd == F_DUPFD_CLOEXEC));
+            new_fd as isize
+        }
+        F_GETFD => {
+            if inner.fd_table.get(fd).unwrap().cloexec {
+                FD_CLOEXEC as isize
+            } else {
+                0
+            }
+        }
+        F_SETFD => {
+            inner.fd_table.get_mut(fd).unwrap().cloexec = arg & FD_CLOEXEC != 0;
+            0
+        }
+        F_GETFL => {
+            drop(inner);
+            let mut flags = match (file.readable(), file.writable()) {
+                (true, false) => OpenFlags::RDONLY,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDWR,
+            };
+            flags.set(OpenFlags::NONBLOCK, file.nonblocking());
+            flags.set(OpenFlags::APPEND, file.append());
+            flags.bits() as isize
+        }
+        F_SETFL => {
+            drop(inner);
+            let flags = OpenFlags::from_bits_truncate(arg as u32);
+            file.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+            file.set_append(flags.contains(OpenFlags::APPEND));
+            0
+        }
+        F_SETPIPE_SZ | F_GETPIPE_SZ => {
+            drop(inner);
+            let pipe = match file.pipe() {
+                Some(pipe) => pipe,
+                None => return -EBADF,
+            };
+            if cmd == F_GETPIPE_SZ {
+                return pipe.capacity() as isize;
+            }
+            match pipe.set_capacity(arg) {
+                Ok(capacity) => capacity as isize,
+                Err(PipeSizeError::TooLarge) => -EPERM,
+                Err(PipeSizeError::Busy) => -EBUSY,
+                Err(PipeSizeError::NoMemory) => -ENOMEM,
+            }
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// An entry of the `sys_ppoll` array
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    /// Entries with a negative fd are skipped
+    pub fd: i32,
+    /// [`PollEvents`] to wait for
+    pub events: u16,
+    /// [`PollEvents`] that happened, filled in by `sys_ppoll`
+    pub revents: u16,
+}
+
+/// Fill in the `revents` of `polls`, whose open files are `files`. Returns
+/// how many have some.
+fn poll_files(polls: &mut [PollFd], files: &[Option<Arc<dyn File + Send + Sync>>]) -> usize {
+    let mut ready = 0;
+    for (poll, file) in polls.iter_mut().zip(files.iter()) {
+        let revents = match file {
+            _ if poll.fd < 0 => PollEvents::empty(),
+            Some(file) => file.poll_ready(PollEvents::from_bits_truncate(poll.events)),
+            None => PollEvents::POLLNVAL,
+        };
+        poll.revents = revents.bits();
+        if !revents.is_empty() {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// Wait until one of the `nfds` entries of `fds` is ready for its events,
+/// or for `timeout_ms`, forever if it is negative. Returns how many are
+/// ready, 0 on timeout.
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout_ms: isize) -> isize {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    if nfds > task.inner_exclusive_access().rlimits.max_fds {
+        return -EINVAL;
+    }
+    let mut polls = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        match copy_from_user(token, fds.wrapping_add(i) as *const PollFd) {
+            Ok(poll) => polls.push(poll),
+            Err(_) => return -EFAULT,
+        }
+    }
+    let files: Vec<_> = {
+        let inner = task.inner_exclusive_access();
+        polls
+            .iter()
+            .map(|poll| inner.get_file(poll.fd as usize))
+            .collect()
+    };
+    let deadline = if timeout_ms >= 0 {
+        let deadline = get_time_ms() + timeout_ms as usize;
+        arm_wait_timeout(&task, deadline);
+        Some(deadline)
+    } else {
+        None
+    };
+    let expired = || deadline.map_or(false, |deadline| get_time_ms() >= deadline);
+    let mut killed = false;
+    let ready = loop {
+        let ready = poll_files(&mut polls, &files);
+        if ready > 0 || expired() || killed {
+            break ready;
+        }
+        // wait on every file, then look again in case one became ready
+        // before we were on its queue
+        task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+        for file in files.iter().flatten() {
+            file.poll_wait(&task);
+        }
+        if poll_files(&mut polls, &files) > 0 || expired() {
+            task.inner_exclusive_access().task_status = TaskStatus::Running;
+        } else {
+            killed = !block_current_killable();
+        }
+        for file in files.iter().flatten() {
+            file.poll_cancel(&task);
+        }
+    };
+    if deadline.is_some() {
+        disarm_wait_timeout(&task);
+    }
+    if killed {
+        return -EINTR;
+    }
+    for (i, poll) in polls.iter().enumerate() {
+        if copy_to_user(token, fds.wrapping_add(i), poll).is_err() {
+            return -EFAULT;
+        }
+    }
+    ready as isize
 }
@@ -1,14 +1,36 @@
 //! Process management syscalls
 
-use crate::mm::{translated_refmut, translated_str, copy_kernel_to_user, VirtAddr};
+use crate::mm::{
+    copy_from_user, copy_to_user, copy_user_bytes, strncpy_from_user, translated_byte_buffer,
+    translated_refmut, free_frames,
+    shm_get, frame_allocator_stats, frames_high_water, kernel_heap_stats, LayoutOffsets,
+    LoadError, MapAreaBacking, VirtAddr,
+};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus, set_priority, mmap, munmap
+    add_task, current_task, current_user_token, exit_current_and_run_next, exit_group_and_run_next,
+    suspend_current_and_run_next, TaskControlBlock, TaskStatus, set_priority, get_priority, nice,
+    mmap, munmap,
+    kill_task, pid2task, global_sched_stat, block_current_killable, set_itimer,
+    set_signal_action, signal_return, SignalFlags, cached_kernel_stack_pages, msync, mprotect,
+    brk, sbrk, account_cpu_time, arm_wait_timeout, disarm_wait_timeout,
+    shm_attach, shm_detach, kill_all_tasks, SpawnError, may_shut_down, group_exists, kill_group, futex_wait, futex_wake, FutexError,
+    IoStat,
+};
+use crate::drivers::BLOCK_DEVICE;
+use crate::fs::{open_exec, sync_all, verify_exec};
+use crate::sbi::shutdown;
+use crate::timer::{
+    get_realtime_ns, get_time_ms, get_time_ns, get_time_us, set_realtime_ns,
+    TICKS_PER_SEC,
 };
-use crate::fs::{open_file, OpenFlags};
-use crate::timer::get_time_us;
+use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE, PATH_MAX, SHM_BASE};
+use crate::logging::{log_modules, set_log_level, set_log_modules};
+use crate::perf::{profiling, reset_profiles, set_profiling, PerfStat};
+use easy_fs::block_cache_stats;
 use alloc::sync::Arc;
-use crate::config::MAX_SYSCALL_NUM;
+use log::LevelFilter;
+use super::errno::*;
+use super::{legacy_errno, path_errno, uaccess_errno};
 
 #[repr(C)]
 #[derive(Debug)]
@@ -17,33 +39,433 @@ pub struct TimeVal {
     pub usec: usize,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// Wall clock time, may jump when set
+pub const CLOCK_REALTIME: usize = 0;
+/// Time since boot, never goes backwards
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// Sleep on a word while it holds the expected value
+const FUTEX_WAIT: usize = 0;
+/// Wake some of the tasks sleeping on a word
+const FUTEX_WAKE: usize = 1;
+
 #[derive(Clone, Copy)]
 pub struct TaskInfo {
     pub status: TaskStatus,
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub time: usize,
+    /// Memory usage in pages, as reported by `sys_getrusage`
+    pub resident_pages: usize,
+    pub mapped_pages: usize,
+    pub peak_resident_pages: usize,
+}
+
+/// Memory usage of a process in pages, its CPU times and its I/O. Frames
+/// shared with other processes, code and read-only data after fork or
+/// shared memory segments, are charged to each of them.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Rusage {
+    /// Frames backing the address space, including the trap context
+    pub resident_pages: usize,
+    /// Pages of the user areas, populated or not
+    pub mapped_pages: usize,
+    /// Most frames resident at once since the last exec
+    pub peak_resident_pages: usize,
+    /// Microseconds spent in user mode
+    pub utime_us: usize,
+    /// Microseconds spent in the kernel on behalf of the process
+    pub stime_us: usize,
+    /// Bytes read by `sys_read` and `sys_readv`, from files of any kind
+    pub read_bytes: usize,
+    /// Bytes written by `sys_write` and `sys_writev`
+    pub written_bytes: usize,
+    /// Block device requests issued on behalf of the process, to read and
+    /// to write a block, see `task::io`
+    pub block_reads: usize,
+    pub block_writes: usize,
+    /// Microseconds waiting for them to finish
+    pub block_wait_us: usize,
+}
+
+impl Rusage {
+    /// Fill in the I/O counters from `io`
+    fn with_io(self, io: IoStat) -> Self {
+        Self {
+            read_bytes: io.total_read(),
+            written_bytes: io.total_written(),
+            block_reads: io.block_reads,
+            block_writes: io.block_writes,
+            block_wait_us: io.block_wait_us,
+            ..self
+        }
+    }
+}
+
+/// CPU times of a process in clock ticks, `TICKS_PER_SEC` a second, see
+/// `sys_times`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    /// User time of the children waited for, and of the ones they waited for
+    pub cutime: usize,
+    /// Kernel time of the same children
+    pub cstime: usize,
+}
+
+/// `sys_getrusage` of the calling process
+pub const RUSAGE_SELF: isize = 0;
+
+/// Scheduler statistics of a task, or of the whole system
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SchedStat {
+    /// Times picked from the ready queue
+    pub dispatches: usize,
+    /// Microseconds spent waiting in the ready queue
+    pub wait_us: usize,
+    /// Times preempted on a timer tick
+    pub preemptions: usize,
+    /// Switches from a task back to the idle control flow, system only
+    pub context_switches: usize,
+    /// Timer interrupts taken, system only
+    pub timer_interrupts: usize,
+    /// Tasks found queued twice or in the wrong state, see
+    /// `TaskStatus::may_become`, system only
+    pub state_violations: usize,
 }
 
 pub fn sys_exit(exit_code: i32) -> ! {
-    debug!("[kernel] Application exited with code {}", exit_code);
+    let peak = current_task().unwrap().inner_exclusive_access().memory_set.peak_user_pages();
+    debug!("[kernel] Application exited with code {}, peak resident {} pages", exit_code, peak);
     exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
+/// task exits and submit an exit code, killing all its descendants
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    let peak = current_task().unwrap().inner_exclusive_access().memory_set.peak_user_pages();
+    debug!(
+        "[kernel] Application exited with code {} killing its descendants, peak resident {} pages",
+        exit_code, peak
+    );
+    exit_group_and_run_next(exit_code);
+    panic!("Unreachable in sys_exit_group!");
+}
+
+/// Power off in order: stop creating tasks, kill every task but initproc
+/// and the caller and wait for them to exit, write the cached blocks back,
+/// wait for the disk to finish, print the final statistics, then ask SBI to
+/// power off, for a failure if `failure` is not 0. Only initproc and the
+/// shell it started may, -EPERM for any other caller.
+pub fn sys_shutdown(failure: usize) -> isize {
+    let task = current_task().unwrap();
+    if !may_shut_down(&task) {
+        return -EPERM;
+    }
+    info!("[kernel] Shutdown requested by pid {}", task.getpid());
+    drop(task);
+    let left = kill_all_tasks();
+    if left > 0 {
+        warn!("[kernel] {} tasks still alive at shutdown", left);
+    }
+    sync_all();
+    BLOCK_DEVICE.quiesce();
+    let (total, free) = frame_allocator_stats();
+    let cache = block_cache_stats();
+    println!("[kernel] {}", kernel_heap_stats());
+    println!(
+        "[kernel] frames: {} of {} free, at most {} used",
+        free,
+        total,
+        frames_high_water()
+    );
+    println!(
+        "[kernel] block cache: {} hits, {} misses, {} writebacks",
+        cache.hits, cache.misses, cache.writebacks
+    );
+    shutdown(failure != 0);
+}
+
+/// Set the kernel log level, 0 (off) to 5 (trace), and the module mask of
+/// `logging`, leaving either as it is if -1. Returns the previous mask
+/// shifted left by 3 bits ored with the previous level, -EPERM if the
+/// caller is not privileged or -EINVAL if `level` is out of range.
+pub fn sys_log_ctl(level: isize, mask: isize) -> isize {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    if !current_task().unwrap().inner_exclusive_access().privileged {
+        return -EPERM;
+    }
+    if level < -1 || level >= LEVELS.len() as isize || mask < -1 {
+        return -EINVAL;
+    }
+    let previous = (log_modules() << 3 | log::max_level() as usize) as isize;
+    if level != -1 {
+        set_log_level(LEVELS[level as usize]);
+    }
+    if mask != -1 {
+        set_log_modules(mask as usize);
+    }
+    previous
+}
+
+/// Turn syscall and scheduler profiling off for 0 and on for 1, leaving it
+/// as it is for -1, and forget what was recorded so far if `reset` is not 0.
+/// Returns 1 if profiling was on and 0 if not, -EPERM if the caller is not
+/// privileged or -EINVAL if `on` is out of range.
+pub fn sys_prof_ctl(on: isize, reset: usize) -> isize {
+    if !current_task().unwrap().inner_exclusive_access().privileged {
+        return -EPERM;
+    }
+    let was_on = match on {
+        -1 => profiling(),
+        0 | 1 => set_profiling(on == 1),
+        _ => return -EINVAL,
+    };
+    if reset != 0 {
+        reset_profiles();
+    }
+    was_on as isize
+}
+
+/// Give up the privilege of the calling process and of the children it
+/// forks from now on, for good
+pub fn sys_drop_privilege() -> isize {
+    current_task().unwrap().inner_exclusive_access().privileged = false;
+    0
+}
+
+/// Recurse in the kernel until the kernel stack runs into its guard page,
+/// which must be reported as a kernel stack overflow before the stack below
+/// is touched. The kernel does not survive it.
+#[cfg(feature = "selftest")]
+pub fn sys_overflow_kernel_stack() -> isize {
+    #[allow(unconditional_recursion)]
+    fn recurse(depth: usize) -> usize {
+        let mut frame = [0u8; 512];
+        unsafe {
+            core::ptr::write_volatile(&mut frame[0], depth as u8);
+            recurse(depth + 1) + core::ptr::read_volatile(&frame[0]) as usize
+        }
+    }
+    warn!(
+        "[kernel] pid {} overflows its kernel stack on purpose",
+        current_task().unwrap().getpid()
+    );
+    recurse(0) as isize
+}
+
+/// Built without the `selftest` feature
+#[cfg(not(feature = "selftest"))]
+pub fn sys_overflow_kernel_stack() -> isize {
+    -ENOSYS
+}
+
+/// Number of free physical frames, for tests checking that memory is reclaimed.
+/// Frames of cached kernel stacks count as free, they are given back on demand.
+pub fn sys_free_frames() -> isize {
+    (free_frames() + cached_kernel_stack_pages()) as isize
+}
+
+/// Physical frame usage, for debugging memory pressure
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FrameStats {
+    /// Frames managed by the frame allocator
+    pub total: usize,
+    /// Frames free right now, cached kernel stacks count as used
+    pub free: usize,
+    /// Most frames ever in use at once
+    pub high_water: usize,
+}
+
+pub fn sys_frame_stats(stats: *mut FrameStats) -> isize {
+    let (total, free) = frame_allocator_stats();
+    let frame_stats = FrameStats {
+        total,
+        free,
+        high_water: frames_high_water(),
+    };
+    match copy_to_user(current_user_token(), stats, &frame_stats) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Address space layout of a process, for debugging ASLR
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct AddressLayout {
+    /// Top of the user stack the program started with
+    pub stack_top: usize,
+    /// Start of the heap
+    pub heap_base: usize,
+    /// Shared memory segments are attached from here up
+    pub mmap_base: usize,
+    /// How far ASLR moved them, all 0 without the `aslr` feature
+    pub offsets: LayoutOffsets,
+}
+
+/// Length of each name of [`Utsname`], with its terminating 0
+const UTSNAME_LEN: usize = 65;
+
+/// Names of the kernel and the features it was built with, see `sys_uname`
+#[repr(C)]
+pub struct Utsname {
+    pub sysname: [u8; UTSNAME_LEN],
+    pub nodename: [u8; UTSNAME_LEN],
+    /// The version of the `os` crate
+    pub release: [u8; UTSNAME_LEN],
+    /// The commit and the date of the build
+    pub version: [u8; UTSNAME_LEN],
+    pub machine: [u8; UTSNAME_LEN],
+    pub domainname: [u8; UTSNAME_LEN],
+    /// `FEATURE_*` bits of the optional syscalls and build features
+    pub features: u64,
+    /// Clock ticks a second of `sys_times`
+    pub clock_ticks: u64,
+}
+
+/// `mmap`, `munmap`, `mprotect` and `msync`
+pub const FEATURE_MMAP: u64 = 1 << 0;
+pub const FEATURE_SPAWN: u64 = 1 << 1;
+/// `kill`, `sigaction`, `sigreturn` and `setitimer`
+pub const FEATURE_SIGNALS: u64 = 1 << 2;
+/// `shmget`, `shmat` and `shmdt`
+pub const FEATURE_SHM: u64 = 1 << 3;
+/// `pipe`, `mkfifo` and `ppoll`
+pub const FEATURE_PIPE: u64 = 1 << 4;
+/// `linkat`, `unlinkat`, `mkdirat` and `getdents`
+pub const FEATURE_FS: u64 = 1 << 5;
+/// `set_priority` with the stride scheduler
+pub const FEATURE_STRIDE: u64 = 1 << 6;
+pub const FEATURE_FUTEX: u64 = 1 << 7;
+pub const FEATURE_EVENTFD: u64 = 1 << 8;
+/// Built with the `aslr` feature
+pub const FEATURE_ASLR: u64 = 1 << 9;
+/// Built with the `selftest` feature
+pub const FEATURE_SELFTEST: u64 = 1 << 10;
+
+/// The `FEATURE_*` bits of this build
+fn kernel_features() -> u64 {
+    let mut features = FEATURE_MMAP
+        | FEATURE_SPAWN
+        | FEATURE_SIGNALS
+        | FEATURE_SHM
+        | FEATURE_PIPE
+        | FEATURE_FS
+        | FEATURE_FUTEX
+        | FEATURE_EVENTFD;
+    if !cfg!(any(feature = "sched-rr", feature = "sched-fifo")) {
+        features |= FEATURE_STRIDE;
+    }
+    if cfg!(feature = "aslr") {
+        features |= FEATURE_ASLR;
+    }
+    if cfg!(feature = "selftest") {
+        features |= FEATURE_SELFTEST;
+    }
+    features
+}
+
+/// `name` as a 0-terminated field of [`Utsname`], cut short if too long
+fn uts_field(name: &str) -> [u8; UTSNAME_LEN] {
+    let mut field = [0; UTSNAME_LEN];
+    let len = name.len().min(UTSNAME_LEN - 1);
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+    field
+}
+
+/// Describe the kernel, see [`Utsname`]
+pub fn sys_uname(buf: *mut Utsname) -> isize {
+    let version = concat!("#", env!("KERNEL_GIT_HASH"), " ", env!("KERNEL_BUILD_DATE"));
+    let uts = Utsname {
+        sysname: uts_field("rCore"),
+        nodename: uts_field("rcore"),
+        release: uts_field(env!("CARGO_PKG_VERSION")),
+        version: uts_field(version),
+        machine: uts_field("riscv64"),
+        domainname: uts_field(""),
+        features: kernel_features(),
+        clock_ticks: TICKS_PER_SEC as u64,
+    };
+    match copy_to_user(current_user_token(), buf, &uts) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+pub fn sys_get_layout(layout: *mut AddressLayout) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let offsets = inner.layout;
+    let current = AddressLayout {
+        stack_top: inner.heap_base - offsets.heap,
+        heap_base: inner.heap_base,
+        mmap_base: SHM_BASE + offsets.mmap,
+        offsets,
+    };
+    drop(inner);
+    match copy_to_user(current_user_token(), layout, &current) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// The negated errno for an executable that cannot be loaded
+fn load_errno(err: LoadError) -> isize {
+    match err {
+        LoadError::NoMemory => -ENOMEM,
+        _ => -ENOEXEC,
+    }
+}
+
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
     suspend_current_and_run_next();
     0
 }
 
+/// Block for at least `ms` milliseconds, or until killed
+pub fn sys_sleep(ms: usize) -> isize {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    arm_wait_timeout(&task, get_time_ms() + ms);
+    // not held across the wait, a killed sleeper unwinds without it
+    drop(task);
+    block_current_killable();
+    disarm_wait_timeout(&current_task().unwrap());
+    0
+}
+
 pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
-/// Syscall Fork which returns 0 for child process and child_pid for parent process
+/// Syscall Fork which returns 0 for child process and child_pid for parent process.
+/// Returns -EAGAIN if a process limit is reached, -ENOMEM if frames run out.
 pub fn sys_fork() -> isize {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = match current_task.fork() {
+        Ok(task) => task,
+        Err(err) => return err,
+    };
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -55,66 +477,273 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-/// Syscall Exec which accepts the elf path
+/// Syscall Exec which accepts the elf path. Returns -ENOENT if there is no
+/// such file, -EACCES if it lacks the execute bit, -ENOEXEC if it is not an
+/// executable we can load, -EPERM if it differs from the packed manifest
+/// (see `fs::verify_exec`), or -ENOMEM if frames run out; the caller goes
+/// on in its own image then.
 pub fn sys_exec(path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        task.exec(all_data.as_slice());
-        0
+    let path = match strncpy_from_user(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let elf_inode = match open_exec(path.as_str()) {
+        Ok(elf_inode) => elf_inode,
+        Err(err) => return path_errno(err),
+    };
+    if verify_exec(path.as_str(), &elf_inode).is_err() {
+        return -EPERM;
+    }
+    let task = current_task().unwrap();
+    match task.exec(path.as_str(), &elf_inode) {
+        Ok(()) => 0,
+        Err(err) => load_errno(err),
+    }
+}
+
+/// Send `signal` to the process `pid`, or to every process of group `-pid`
+/// if it is negative. On SIGKILL a process exits with `-SIGKILL` without
+/// running any more user code.
+/// Returns -EINVAL for an unknown signal, -ESRCH if there is no such live
+/// process and -EPERM for initproc.
+pub fn sys_kill(pid: isize, signal: i32) -> isize {
+    match SignalFlags::from_signum(signal as usize) {
+        Some(signal) if pid < 0 => kill_group(pid.unsigned_abs(), signal),
+        Some(signal) => kill_task(pid as usize, signal),
+        None => -EINVAL,
+    }
+}
+
+/// Move process `pid`, the current one if 0, into group `pgid`, a new group
+/// of its own if 0 or its pid. Only the current process and its children
+/// can be moved, and only into a group that has a process already.
+pub fn sys_setpgid(pid: usize, pgid: isize) -> isize {
+    if pgid < 0 {
+        return -EINVAL;
+    }
+    let current = current_task().unwrap();
+    let task = if pid == 0 || pid == current.getpid() {
+        current
+    } else {
+        match pid2task(pid) {
+            Some(task) => {
+                let is_child = task
+                    .inner_exclusive_access()
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| parent.upgrade())
+                    .map_or(false, |parent| Arc::ptr_eq(&parent, &current));
+                if !is_child {
+                    return -ESRCH;
+                }
+                task
+            }
+            None => return -ESRCH,
+        }
+    };
+    let pgid = if pgid == 0 { task.getpid() } else { pgid as usize };
+    if pgid != task.getpid() && !group_exists(pgid) {
+        return -EPERM;
+    }
+    task.set_pgid(pgid);
+    0
+}
+
+/// The group of process `pid`, the current one if 0
+pub fn sys_getpgid(pid: usize) -> isize {
+    let task = if pid == 0 {
+        current_task()
     } else {
-        -1
+        pid2task(pid)
+    };
+    match task {
+        Some(task) => task.getpgid() as isize,
+        None => -ESRCH,
     }
 }
 
+/// Install `handler` for `signum`, or `SIG_DFL`/`SIG_IGN`. A handler gets the
+/// signal number as its argument and must end with `sys_sigreturn`.
+/// Returns the previous handler, -EINVAL for an unknown signal or SIGKILL.
+pub fn sys_sigaction(signum: usize, handler: usize) -> isize {
+    set_signal_action(signum, handler)
+}
+
+/// Leave a signal handler, resuming where the signal interrupted the process
+pub fn sys_sigreturn() -> isize {
+    signal_return()
+}
+
+/// Only the real time timer, which raises SIGALRM, is supported
+pub const ITIMER_REAL: usize = 0;
+
+/// Raise SIGALRM in `value_ms`, then every `interval_ms` unless it is 0.
+/// `value_ms` of 0 disarms the timer.
+/// Returns the milliseconds left on the replaced timer, -EINVAL for another
+/// `which`.
+pub fn sys_setitimer(which: usize, interval_ms: usize, value_ms: usize) -> isize {
+    if which != ITIMER_REAL {
+        return -EINVAL;
+    }
+    set_itimer(value_ms, interval_ms) as isize
+}
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
+/// `sys_wait4` option: store the wait status, see [`wait_status`],
+/// rather than the bare exit code
+pub const WSTATUS: usize = 1;
+
+/// The wait status of a process that exited with `exit_code` or was killed
+/// by `term_signal`: the signal number in the low 7 bits, 0 for an exit,
+/// and the low byte of the exit code in the next byte
+fn wait_status(exit_code: i32, term_signal: Option<usize>) -> i32 {
+    match term_signal {
+        Some(signum) => signum as i32 & 0x7f,
+        None => (exit_code & 0xff) << 8,
+    }
+}
+
+/// [`sys_wait4`] of the two arguments the original waitpid takes, with no
+/// options and no usage, and its return values: -2 if no matching child
+/// has exited yet, -1 for any failure
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    match sys_wait4(pid, exit_code_ptr, 0, core::ptr::null_mut()) {
+        ret if ret == -EAGAIN => -2,
+        ret => legacy_errno(ret),
+    }
+}
+
+/// Reap a child that has exited: the child `pid` if it is positive, any
+/// child for -1, any child in the process group `-pid` below that. Its
+/// exit code, or its wait status with [`WSTATUS`] in `options`, goes to
+/// `exit_code_ptr`, and unless `usage` is null its usage goes there: the
+/// CPU times include the children it waited for, the peak is its own.
+/// Returns the pid of the child, -ECHILD if no child matches, -EAGAIN if
+/// none of them has exited yet.
+pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32, options: usize, usage: *mut Rusage) -> isize {
+    if options & !WSTATUS != 0 {
+        return -EINVAL;
+    }
+    // check the pointers first, a child must not be reaped for nothing.
+    // Translating may populate the page, which takes the lock
+    let token = current_user_token();
+    let usage_len = core::mem::size_of::<Rusage>();
+    if !usage.is_null() && translated_byte_buffer(token, usage as *const u8, usage_len).is_none() {
+        return -EFAULT;
+    }
+    let exit_code_ref = match translated_refmut(token, exit_code_ptr) {
+        Some(exit_code_ref) => exit_code_ref,
+        None => return -EFAULT,
+    };
+    let matches = |p: &Arc<TaskControlBlock>| match pid {
+        -1 => true,
+        pid if pid < -1 => p.getpgid() == pid.unsigned_abs(),
+        pid => pid as usize == p.getpid(),
+    };
     let task = current_task().unwrap();
     // find a child process
 
     // ---- access current TCB exclusively
     let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
+    if !inner.children.iter().any(matches) {
+        return -ECHILD;
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
         // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+        p.inner_exclusive_access().is_zombie() && matches(p)
         // ++++ release child PCB
     });
     if let Some((idx, _)) = pair {
         let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
+        // the hart the child exited on may still hold it until it is off the
+        // child's kernel stack, whichever reference is last deallocates it
         let found_pid = child.getpid();
         // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
+        let child_inner = child.inner_exclusive_access();
+        let exit_code = if options & WSTATUS != 0 {
+            wait_status(child_inner.exit_code, child_inner.term_signal)
+        } else {
+            child_inner.exit_code
+        };
+        // its times and those of its own children count for us now
+        let utime_us = child_inner.utime_us + child_inner.children_utime_us;
+        let stime_us = child_inner.stime_us + child_inner.children_stime_us;
+        inner.children_utime_us += utime_us;
+        inner.children_stime_us += stime_us;
+        let rusage = Rusage {
+            peak_resident_pages: child_inner.memory_set.peak_user_pages(),
+            utime_us,
+            stime_us,
+            ..Rusage::default()
+        }
+        .with_io(child.io.stat());
+        drop(child_inner);
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+        drop(inner);
+        *exit_code_ref = exit_code;
+        if !usage.is_null() {
+            copy_to_user(token, usage, &rusage).ok();
+        }
         found_pid as isize
     } else {
-        -2
+        -EAGAIN
     }
     // ---- release current PCB lock automatically
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_get_time
 pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
-    let us = get_time_us();
+    // the monotonic clock, at microsecond resolution
+    let ns = get_time_ns();
     let tmp = TimeVal {
-        sec: us / 1_000_000,
-        usec: us % 1_000_000,
+        sec: ns / 1_000_000_000,
+        usec: ns % 1_000_000_000 / 1000,
+    };
+    match copy_to_user(current_user_token(), _ts, &tmp) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Read `clock_id` into `ts` at nanosecond resolution, -EINVAL for an
+/// unknown clock or a realtime clock set before the epoch
+pub fn sys_clock_gettime(clock_id: usize, ts: *mut TimeSpec) -> isize {
+    let ns = match clock_id {
+        CLOCK_MONOTONIC => get_time_ns(),
+        CLOCK_REALTIME => {
+            let ns = get_realtime_ns();
+            if ns < 0 {
+                return -EINVAL;
+            }
+            ns as usize
+        }
+        _ => return -EINVAL,
+    };
+    let tmp = TimeSpec {
+        sec: ns / 1_000_000_000,
+        nsec: ns % 1_000_000_000,
+    };
+    match copy_to_user(current_user_token(), ts, &tmp) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Set the realtime clock from `ts`; the monotonic clock can not be set.
+/// There are no credentials in this kernel yet, so any process may do it.
+pub fn sys_clock_settime(clock_id: usize, ts: *const TimeSpec) -> isize {
+    if clock_id != CLOCK_REALTIME {
+        return -EINVAL;
+    }
+    let ts = match copy_from_user(current_user_token(), ts) {
+        Ok(ts) => ts,
+        Err(_) => return -EFAULT,
     };
-    copy_kernel_to_user(current_user_token(), &tmp as *const TimeVal as *const u8, _ts as usize, core::mem::size_of::<TimeVal>());
+    if ts.nsec >= 1_000_000_000 || ts.sec > i64::MAX as usize / 1_000_000_000 - 1 {
+        return -EINVAL;
+    }
+    set_realtime_ns((ts.sec * 1_000_000_000 + ts.nsec) as i64);
     0
 }
 
@@ -124,24 +753,146 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     let inner = task.inner_exclusive_access();
     let ti_tmp = TaskInfo {
         status: inner.task_status,
-        syscall_times: [0; MAX_SYSCALL_NUM],    
+        syscall_times: *inner.syscall_times,
         time: (get_time_us() - inner.start_time) / 1000,
+        resident_pages: inner.memory_set.user_pages(),
+        mapped_pages: inner.memory_set.mapped_pages(),
+        peak_resident_pages: inner.memory_set.peak_user_pages(),
     };
-    copy_kernel_to_user(current_user_token(), &ti_tmp as *const TaskInfo as *const u8, ti as usize, core::mem::size_of::<TaskInfo>());
-    0
+    // current_user_token() locks the TCB again
+    drop(inner);
+    match copy_to_user(current_user_token(), ti, &ti_tmp) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Copy the usage of the calling process into `usage`, only
+/// `RUSAGE_SELF` is supported for `who`
+pub fn sys_getrusage(who: isize, usage: *mut Rusage) -> isize {
+    if who != RUSAGE_SELF {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let rusage = Rusage {
+        resident_pages: inner.memory_set.user_pages(),
+        mapped_pages: inner.memory_set.mapped_pages(),
+        peak_resident_pages: inner.memory_set.peak_user_pages(),
+        utime_us: inner.utime_us,
+        stime_us: inner.stime_us,
+        ..Rusage::default()
+    }
+    .with_io(task.io.stat());
+    drop(inner);
+    match copy_to_user(current_user_token(), usage, &rusage) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Copy the CPU times of the calling process and of its children waited
+/// for into `buf`. Returns the clock ticks since boot, or -EFAULT if `buf`
+/// is not writable.
+///
+/// The times are kept in microseconds and rounded down to ticks here, so
+/// they never go back between calls even if a call charges less than a
+/// tick.
+pub fn sys_times(buf: *mut Tms) -> isize {
+    // charge this call so far
+    account_cpu_time(false);
+    let ticks = |us: usize| us / (1_000_000 / TICKS_PER_SEC);
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let tms = Tms {
+        utime: ticks(inner.utime_us),
+        stime: ticks(inner.stime_us),
+        cutime: ticks(inner.children_utime_us),
+        cstime: ticks(inner.children_stime_us),
+    };
+    drop(inner);
+    match copy_to_user(current_user_token(), buf, &tms) {
+        Ok(()) => ticks(get_time_us()) as isize,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// The task `pid` names for the priority syscalls, the current one if 0
+fn priority_target(pid: usize) -> Result<Arc<TaskControlBlock>, isize> {
+    let current = current_task().unwrap();
+    if pid == 0 || pid == current.getpid() {
+        Ok(current)
+    } else {
+        pid2task(pid).ok_or(-ESRCH)
+    }
 }
 
 // YOUR JOB: 实现sys_set_priority，为任务添加优先级
-pub fn sys_set_priority(_prio: isize) -> isize {
-    let current_task = current_task().unwrap();
-    if set_priority(&current_task, _prio) == 0 {
+/// Set the stride priority of task `pid`, the current one if 0. Returns
+/// the priority, -EINVAL if it is out of range or -ESRCH if there is no such
+/// task. Children start with the priority of their parent, and keep it
+/// across exec.
+pub fn sys_set_priority(_prio: isize, pid: usize) -> isize {
+    let task = match priority_target(pid) {
+        Ok(task) => task,
+        Err(errno) => return errno,
+    };
+    if set_priority(&task, _prio) == 0 {
         _prio
     }
     else {
-        -1
+        -EINVAL
+    }
+}
+
+/// Get the stride priority of task `pid`, the current one if 0
+pub fn sys_get_priority(pid: usize) -> isize {
+    match priority_target(pid) {
+        Ok(task) => get_priority(&task),
+        Err(errno) => errno,
+    }
+}
+
+/// Lower the stride priority of the current task by `increment`, or raise
+/// it if negative, within its bounds. Returns the new priority.
+pub fn sys_nice(increment: isize) -> isize {
+    nice(&current_task().unwrap(), increment)
+}
+
+/// Rename the current task, names longer than `MAX_TASK_NAME_LEN` are cut
+pub fn sys_set_name(name: *const u8) -> isize {
+    match strncpy_from_user(current_user_token(), name, PATH_MAX) {
+        Ok(name) => {
+            current_task().unwrap().set_name(name.as_str());
+            0
+        }
+        Err(err) => uaccess_errno(err),
+    }
+}
+
+/// Copy the name of the current task into `buf` as a NUL-terminated string,
+/// cut to `len - 1` bytes. Returns the length copied, -EINVAL if `len` is 0.
+pub fn sys_get_name(buf: *mut u8, len: usize) -> isize {
+    if len == 0 {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let mut name = task.inner_exclusive_access().name.clone().into_bytes();
+    name.truncate(len - 1);
+    let copied = name.len();
+    name.push(0);
+    match copy_user_bytes(current_user_token(), buf, &name) {
+        Ok(()) => copied as isize,
+        Err(_) => -EFAULT,
     }
 }
 
+/// Map a file, writes go back to it
+pub const MAP_SHARED: usize = 0x01;
+/// Map a file or anonymous memory, writes stay private
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
 /* 
     申请内存
@@ -152,45 +903,298 @@ pub fn sys_set_priority(_prio: isize) -> isize {
     返回值：执行成功则返回 0，错误返回 -1
 */
 pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
+    legacy_errno(sys_mmap_file(_start, _len, _port, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0))
+}
+
+/* 
+    sys_mmap 加上 flags、fd 与 offset，是另一个系统调用，原来三个参数的 sys_mmap 不变
+    flags：MAP_SHARED 或 MAP_PRIVATE 映射 fd 所指文件从 offset 起的内容，offset 要求按页对齐；
+        MAP_PRIVATE | MAP_ANONYMOUS 或 0 申请匿名内存，此时忽略 fd 与 offset
+    返回值：执行成功则返回 0，错误返回负的错误码：参数不合法为 -EINVAL，fd 未打开为 -EBADF，
+        文件不可读或不可按 port 写为 -EACCES，fd 不是普通文件为 -ENODEV，文件已被快照恢复替换为 -ESTALE，
+        与已有映射重叠为 -EEXIST，超出用户地址空间或 RLIMIT_AS 为 -ENOMEM
+*/
+pub fn sys_mmap_file(_start: usize, _len: usize, _port: usize, flags: usize, fd: usize, offset: usize) -> isize {
     let start_va = VirtAddr::from(_start);
     if ! start_va.aligned() || _port & !0x7 != 0 || _port & 0x7 == 0 {
-        return -1;
+        return -EINVAL;
     }
+    let backing = if flags == 0 || flags == MAP_PRIVATE | MAP_ANONYMOUS {
+        MapAreaBacking::Anonymous
+    } else if flags == MAP_SHARED || flags == MAP_PRIVATE {
+        let shared = flags == MAP_SHARED;
+        let file = {
+            let task = current_task().unwrap();
+            let inner = task.inner_exclusive_access();
+            match inner.get_file(fd) {
+                Some(file) => file,
+                None => return -EBADF,
+            }
+        };
+        if offset % PAGE_SIZE != 0 {
+            return -EINVAL;
+        }
+        if file.stale() {
+            return -ESTALE;
+        }
+        if !file.readable() || (shared && _port & 0x2 != 0 && !file.writable()) {
+            return -EACCES;
+        }
+        match file.inode() {
+            Some(inode) => MapAreaBacking::File {
+                inode,
+                offset,
+                len: usize::MAX,
+                shared,
+                writable: file.writable(),
+            },
+            None => return -ENODEV,
+        }
+    } else {
+        return -EINVAL;
+    };
     if _len == 0 {
         return 0;
     }
-
-    let end_va = VirtAddr::from(_start+_len);
-    mmap(start_va, end_va, _port)
+    let end_va = match _start.checked_add(_len) {
+        Some(end) => VirtAddr::from(end),
+        None => return -ENOMEM,
+    };
+    mmap(start_va, end_va, _port, backing)
 }
 
+/// [`sys_munmap2`] with the -1 of the original ABI for any failure
 pub fn sys_munmap(_start: usize, _len: usize) -> isize {
+    legacy_errno(sys_munmap2(_start, _len))
+}
+
+/// Unmap the pages of `[start, start + len)`, -EINVAL if `start` is
+/// unaligned
+pub fn sys_munmap2(_start: usize, _len: usize) -> isize {
     let start_va = VirtAddr::from(_start);
     if ! start_va.aligned() {
-        return -1;
+        return -EINVAL;
     }
     if _len == 0 {
         return 0;
     }
-    let end_va = VirtAddr::from(usize::from(start_va)+_len);
+    let end_va = match _start.checked_add(_len) {
+        Some(end) => VirtAddr::from(end),
+        None => return -ENOMEM,
+    };
     munmap(start_va, end_va)
 }
 
+/// Move the program break to `addr`, 0 just asks for it. Returns the new
+/// break, or the unchanged one if the heap cannot be moved there.
+pub fn sys_brk(addr: usize) -> isize {
+    brk(addr)
+}
+
+/// Move the program break by `increment` bytes, returns the old break or
+/// -ENOMEM if the heap cannot grow or shrink that far
+pub fn sys_sbrk(increment: isize) -> isize {
+    sbrk(increment)
+}
+
+/// Write the changes to shared file mappings in `[start, start + len)` back
+/// to the files. Returns -EINVAL if `start` is unaligned, -ENOMEM if a
+/// page in the range is unmapped.
+pub fn sys_msync(start: usize, len: usize) -> isize {
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() {
+        return -EINVAL;
+    }
+    match start.checked_add(len) {
+        Some(end) => msync(start_va, VirtAddr::from(end)),
+        None => -ENOMEM,
+    }
+}
+
+/// Change the permissions of the pages in `[start, start + len)` to `prot`,
+/// in the bits `sys_mmap` takes. Returns -EINVAL if `start` is unaligned
+/// or `prot` has unknown bits, -ENOMEM if a page in the range is unmapped
+/// or frames run out, or -EACCES if `prot` asks for writes to a shared
+/// mapping of a file not open for writing.
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() || prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return -EINVAL;
+    }
+    if len == 0 {
+        return 0;
+    }
+    match start.checked_add(len) {
+        Some(end) => mprotect(start_va, VirtAddr::from(end), prot),
+        None => -ENOMEM,
+    }
+}
+
+/// Get the shared memory segment of `key`, creating it with `size` bytes if
+/// there is none or `key` is IPC_PRIVATE (0). Returns its id, -EINVAL if
+/// an existing segment is smaller than `size` or -ENOMEM if memory runs out.
+pub fn sys_shmget(key: usize, size: usize) -> isize {
+    match shm_get(key, size) {
+        Ok(shmid) => shmid as isize,
+        Err(errno) => errno,
+    }
+}
+
+/// Attach segment `shmid` read-write at an address chosen by the kernel,
+/// returns the address, -EINVAL if there is no such segment or -ENOMEM if
+/// there is no room for it
+pub fn sys_shmat(shmid: usize) -> isize {
+    shm_attach(shmid)
+}
+
+/// Detach the segment attached at `addr`. Its memory is freed once no
+/// process has it attached.
+pub fn sys_shmdt(addr: usize) -> isize {
+    let start_va = VirtAddr::from(addr);
+    if !start_va.aligned() {
+        return -EINVAL;
+    }
+    shm_detach(start_va)
+}
+
+/// Operate on the 32-bit word at `uaddr`, which must be aligned and writable.
+///
+/// `FUTEX_WAIT` blocks while the word holds `val`, for `timeout_ms` or
+/// forever if it is negative; it returns 0 when woken, -EAGAIN if the word
+/// held something else and -ETIMEDOUT. `FUTEX_WAKE` wakes up to `val` tasks
+/// waiting on the word and returns how many.
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, timeout_ms: isize) -> isize {
+    if uaddr % core::mem::size_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let result = match op {
+        FUTEX_WAIT => {
+            let timeout_ms = if timeout_ms < 0 { None } else { Some(timeout_ms as usize) };
+            futex_wait(uaddr, val as u32, timeout_ms).map(|_| 0)
+        }
+        FUTEX_WAKE => futex_wake(uaddr, val).map(|woken| woken as isize),
+        _ => return -EINVAL,
+    };
+    match result {
+        Ok(ret) => ret,
+        Err(FutexError::Fault) => -EFAULT,
+        Err(FutexError::Again) => -EAGAIN,
+        Err(FutexError::TimedOut) => -ETIMEDOUT,
+        Err(FutexError::Interrupted) => -EINTR,
+    }
+}
+
 //
 // YOUR JOB: 实现 sys_spawn 系统调用
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC 
 pub fn sys_spawn(_path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, _path);
-    if let Some(data) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let current_task = current_task().unwrap();
-        let new_task = current_task.spawn(data.read_all().as_slice());
-        let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-        trap_cx.x[10] = 0;
-        let new_pid = new_task.pid.0;
-        add_task(new_task);
-        new_pid as isize
+    let path = match strncpy_from_user(token, _path, PATH_MAX) {
+        Ok(path) => path,
+        Err(err) => return uaccess_errno(err),
+    };
+    let elf_inode = match open_exec(path.as_str()) {
+        Ok(elf_inode) => elf_inode,
+        Err(err) => return path_errno(err),
+    };
+    if verify_exec(path.as_str(), &elf_inode).is_err() {
+        return -EPERM;
+    }
+    let current_task = current_task().unwrap();
+    let new_task = match current_task.spawn(path.as_str(), &elf_inode) {
+        Ok(task) => task,
+        Err(SpawnError::Limit) => return -EAGAIN,
+        Err(SpawnError::Load(err)) => return load_errno(err),
+    };
+    let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+    trap_cx.x[10] = 0;
+    let new_pid = new_task.pid.0;
+    add_task(new_task);
+    new_pid as isize
+}
+
+/// Read the limit of `resource` into `old` and then set it to `*new`,
+/// either pointer may be null. Returns -EINVAL for an unknown resource or
+/// -EPERM for a new limit above its hard cap, in which case nothing is
+/// changed.
+pub fn sys_prlimit(resource: usize, new: *const usize, old: *mut usize) -> isize {
+    let token = current_user_token();
+    // translate before taking the lock, populating a page takes it too
+    let new = if new.is_null() {
+        None
+    } else {
+        match copy_from_user(token, new) {
+            Ok(new) => Some(new),
+            Err(_) => return -EFAULT,
+        }
+    };
+    let old = if old.is_null() {
+        None
+    } else {
+        match translated_refmut(token, old) {
+            Some(old) => Some(old),
+            None => return -EFAULT,
+        }
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let limit = match inner.rlimits.get(resource) {
+        Some(limit) => limit,
+        None => return -EINVAL,
+    };
+    if let Some(new) = new {
+        if !inner.rlimits.set(resource, new) {
+            return -EPERM;
+        }
+    }
+    drop(inner);
+    if let Some(old) = old {
+        *old = limit;
+    }
+    0
+}
+
+/// Copy the cycles and instructions retired by process `pid` into `stat`,
+/// `pid` 0 for the caller. A process running on another hart is counted up
+/// to its last switch. Counts the harts do not implement read as
+/// `PERF_UNSUPPORTED`. Returns -ESRCH if there is no such live process.
+pub fn sys_perf_read(pid: usize, stat: *mut PerfStat) -> isize {
+    let current = current_task().unwrap();
+    let is_current = pid == 0 || pid == current.getpid();
+    let task = if is_current {
+        current
+    } else if let Some(task) = pid2task(pid) {
+        task
     } else {
-        -1
+        return -ESRCH;
+    };
+    let perf = {
+        let mut inner = task.inner_exclusive_access();
+        if is_current {
+            inner.account_perf();
+        }
+        inner.perf.masked()
+    };
+    match copy_to_user(current_user_token(), stat, &perf) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+/// Copy the scheduler statistics of process `pid` into `stat`, `pid` 0 asks
+/// for the system-wide counters instead. Returns -ESRCH if there is no such
+/// live process.
+pub fn sys_sched_stat(pid: usize, stat: *mut SchedStat) -> isize {
+    let sched_stat = if pid == 0 {
+        global_sched_stat()
+    } else if let Some(task) = pid2task(pid) {
+        let sched_stat = task.inner_exclusive_access().sched_stat;
+        sched_stat
+    } else {
+        return -ESRCH;
+    };
+    match copy_to_user(current_user_token(), stat, &sched_stat) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
     }
 }
@@ -0,0 +1,103 @@
+//! Core files written when a process dies of a fatal fault or SIGQUIT
+//!
+//! `core.<pid>` in the root directory holds, all integers little endian u64:
+//!
+//! - header: magic `b"RCORE\0\0\x02"`, pid, signal, scause, stval, sepc,
+//!   number of regions, the task name NUL padded to `MAX_TASK_NAME_LEN`
+//!   bytes, the 32 general purpose registers and the stack, heap and
+//!   shared memory offsets of the layout, see [`crate::mm::LayoutOffsets`]
+//! - per region: start address, size, `MapPermission` bits and the number
+//!   of bytes saved, followed by those bytes
+//!
+//! Regions are saved in address order until `CORE_DUMP_LIMIT` is reached,
+//! the later ones are cut short or left empty. `easy-fs-fuse core` prints
+//! such a file.
+
+use super::TaskControlBlock;
+use crate::config::{CORE_DUMP_LIMIT, MAX_TASK_NAME_LEN, PAGE_SIZE};
+use crate::fs::create_kernel_file;
+use crate::mm::{VirtAddr, VirtPageNum};
+use alloc::format;
+use alloc::vec::Vec;
+
+pub const CORE_MAGIC: &[u8; 8] = b"RCORE\0\0\x02";
+
+fn push_u64(buf: &mut Vec<u8>, value: usize) {
+    buf.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+/// Copy the page at `vpn` of `task` to `page`, zeros if it was never
+/// touched. A swapped out page is read with the TCB lock let go.
+fn copy_page(task: &TaskControlBlock, vpn: VirtPageNum, page: &mut [u8; PAGE_SIZE]) {
+    let inner = task.inner_exclusive_access();
+    let slot = match inner.memory_set.translate(vpn).filter(|pte| pte.is_valid()) {
+        Some(pte) => {
+            page.copy_from_slice(pte.ppn().get_bytes_array());
+            return;
+        }
+        None => inner.memory_set.swap_slot(vpn),
+    };
+    drop(inner);
+    match slot {
+        Some(slot) => slot.read(page),
+        None => page.fill(0),
+    }
+}
+
+/// Write the core file of `task` for `signum`, its user code must not be
+/// running.
+/// `scause` and `stval` describe the fault, 0 if there was none.
+pub fn dump_core(task: &TaskControlBlock, signum: usize, scause: usize, stval: usize) {
+    let pid = task.getpid();
+    let name = format!("core.{}", pid);
+    let inode = match create_kernel_file(name.as_str()) {
+        Some(inode) => inode,
+        None => {
+            println!("[kernel] cannot create {}", name);
+            return;
+        }
+    };
+    let inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    let mut areas = inner.memory_set.user_areas();
+    areas.sort_by_key(|(range, _)| range.get_start());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(CORE_MAGIC);
+    for value in [pid, signum, scause, stval, trap_cx.sepc, areas.len()] {
+        push_u64(&mut header, value);
+    }
+    let mut task_name = [0u8; MAX_TASK_NAME_LEN];
+    task_name[..inner.name.len()].copy_from_slice(inner.name.as_bytes());
+    header.extend_from_slice(&task_name);
+    for reg in trap_cx.x {
+        push_u64(&mut header, reg);
+    }
+    for offset in [inner.layout.stack, inner.layout.heap, inner.layout.mmap] {
+        push_u64(&mut header, offset);
+    }
+    // the file is written with the lock let go, that waits for the disk
+    drop(inner);
+    let mut offset = inode.write_at(0, &header);
+
+    for (range, perm) in areas {
+        let start = usize::from(VirtAddr::from(range.get_start()));
+        let size = usize::from(VirtAddr::from(range.get_end())) - start;
+        let record_size = 4 * core::mem::size_of::<u64>();
+        let saved = size.min(CORE_DUMP_LIMIT.saturating_sub(offset + record_size))
+            / PAGE_SIZE
+            * PAGE_SIZE;
+        let mut record = Vec::new();
+        for value in [start, size, perm.bits() as usize, saved] {
+            push_u64(&mut record, value);
+        }
+        offset += inode.write_at(offset, &record);
+        let mut page = [0u8; PAGE_SIZE];
+        for vpn in range.into_iter().take(saved / PAGE_SIZE) {
+            copy_page(task, vpn, &mut page);
+            offset += inode.write_at(offset, &page);
+        }
+    }
+    inode.sync();
+    println!("[kernel] core dumped to {} ({} bytes)", name, offset);
+}
@@ -0,0 +1,156 @@
+//! Descriptor table of a process
+//!
+//! The table is only as long as the highest descriptor in use: it grows on
+//! demand up to `RLIMIT_NOFILE`, doubling so growing is amortized O(1), and
+//! shrinks again when the highest descriptor is closed. It counts the open
+//! descriptors and knows the lowest free one, so finding the lowest free
+//! descriptor is O(1) amortized, and fork and exec walk no further than the
+//! highest open descriptor.
+
+use crate::fs::FdSlot;
+use alloc::vec::Vec;
+
+/// The open descriptors of a process, indexed by descriptor
+#[derive(Default)]
+pub struct FdTable {
+    /// No free slot at the end, but one reserved by `alloc_from` or `reserve`
+    slots: Vec<Option<FdSlot>>,
+    /// Occupied slots
+    open: usize,
+    /// Every slot below it is occupied
+    lowest_free: usize,
+}
+
+impl FdTable {
+    /// A table with `slots` from descriptor 0 on
+    pub fn from_slots(slots: Vec<FdSlot>) -> Self {
+        let open = slots.len();
+        Self {
+            slots: slots.into_iter().map(Some).collect(),
+            open,
+            lowest_free: open,
+        }
+    }
+    pub fn get(&self, fd: usize) -> Option<&FdSlot> {
+        self.slots.get(fd)?.as_ref()
+    }
+    pub fn get_mut(&mut self, fd: usize) -> Option<&mut FdSlot> {
+        self.slots.get_mut(fd)?.as_mut()
+    }
+    /// The lowest free descriptor not below `min`, with room in the table
+    /// for it. `None` if there is none below `max_fds` or no memory to grow.
+    pub fn alloc_from(&mut self, min: usize, max_fds: usize) -> Option<usize> {
+        let start = min.max(self.lowest_free);
+        let fd = self.slots[start.min(self.slots.len())..]
+            .iter()
+            .position(Option::is_none)
+            .map_or(start.max(self.slots.len()), |offset| start + offset);
+        if self.reserve(fd, max_fds) {
+            Some(fd)
+        } else {
+            None
+        }
+    }
+    /// Make room in the table for `fd`, false if it is not below `max_fds`
+    /// or there is no memory to grow
+    pub fn reserve(&mut self, fd: usize, max_fds: usize) -> bool {
+        if fd >= max_fds {
+            return false;
+        }
+        if fd < self.slots.len() {
+            return true;
+        }
+        let capacity = (self.slots.capacity() * 2).clamp(fd + 1, max_fds);
+        if capacity > self.slots.capacity()
+            && self.slots.try_reserve_exact(capacity - self.slots.len()).is_err()
+        {
+            return false;
+        }
+        self.slots.resize_with(fd + 1, || None);
+        true
+    }
+    /// Put `slot` into `fd`, which has room, returns what was there
+    pub fn install(&mut self, fd: usize, slot: FdSlot) -> Option<FdSlot> {
+        let old = self.slots[fd].replace(slot);
+        if old.is_none() {
+            self.open += 1;
+            if fd == self.lowest_free {
+                self.lowest_free = self.slots[fd + 1..]
+                    .iter()
+                    .position(Option::is_none)
+                    .map_or(self.slots.len(), |offset| fd + 1 + offset);
+            }
+        }
+        old
+    }
+    /// Empty `fd`, returns what was there
+    pub fn remove(&mut self, fd: usize) -> Option<FdSlot> {
+        let old = self.slots.get_mut(fd)?.take()?;
+        self.open -= 1;
+        self.lowest_free = self.lowest_free.min(fd);
+        self.trim();
+        Some(old)
+    }
+    /// Empty the open descriptors from `first` to `last`, both included
+    pub fn remove_range(&mut self, first: usize, last: usize) -> Vec<FdSlot> {
+        let end = self.slots.len().min(last.saturating_add(1));
+        self.remove_where(first, end, |_| true)
+    }
+    /// Empty the `FD_CLOEXEC` descriptors
+    pub fn remove_cloexec(&mut self) -> Vec<FdSlot> {
+        self.remove_where(0, self.slots.len(), |slot| slot.cloexec)
+    }
+    /// The open descriptors from `first` to `last`, both included, in order
+    pub fn iter_open(&self, first: usize, last: usize) -> impl Iterator<Item = (usize, &FdSlot)> {
+        let end = self.slots.len().min(last.saturating_add(1));
+        self.slots[first.min(end)..end]
+            .iter()
+            .enumerate()
+            .filter_map(move |(offset, slot)| Some((first + offset, slot.as_ref()?)))
+    }
+    fn remove_where(
+        &mut self,
+        first: usize,
+        end: usize,
+        mut pred: impl FnMut(&FdSlot) -> bool,
+    ) -> Vec<FdSlot> {
+        let mut removed = Vec::new();
+        for fd in first.min(end)..end {
+            if self.slots[fd].as_ref().map_or(false, &mut pred) {
+                removed.push(self.slots[fd].take().unwrap());
+                self.lowest_free = self.lowest_free.min(fd);
+            }
+        }
+        self.open -= removed.len();
+        self.trim();
+        removed
+    }
+    /// Drop the free slots at the end, and the memory once a quarter of it
+    /// is in use
+    fn trim(&mut self) {
+        while let Some(None) = self.slots.last() {
+            self.slots.pop();
+        }
+        self.lowest_free = self.lowest_free.min(self.slots.len());
+        if self.slots.len() * 4 < self.slots.capacity() {
+            self.slots.shrink_to(self.slots.len() * 2);
+        }
+    }
+}
+
+/// Fork copies the occupied slots, the open files are shared. The copy is
+/// as long as the highest open descriptor, not as the table grew.
+impl Clone for FdTable {
+    fn clone(&self) -> Self {
+        let mut slots = Vec::with_capacity(self.slots.len());
+        slots.resize_with(self.slots.len(), || None);
+        for (fd, slot) in self.iter_open(0, usize::MAX).take(self.open) {
+            slots[fd] = Some(slot.clone());
+        }
+        Self {
+            slots,
+            open: self.open,
+            lowest_free: self.lowest_free,
+        }
+    }
+}
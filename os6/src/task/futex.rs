@@ -0,0 +1,152 @@
+//! Fast user mutexes
+//!
+//! A task waits on a 32-bit word of its memory and another wakes it, with
+//! the value checked in between so no wakeup is lost. The uncontended case
+//! never enters the kernel, see the user library.
+//!
+//! Waiters are queued by the physical address of the word, so processes
+//! sharing memory meet on the same queue. The word is made private first if
+//! it is copy-on-write, and its frame is pinned while queued so that it is
+//! not swapped out to another one. A process cannot unmap memory while it
+//! sleeps, having a single thread; a killed waiter never returns to its
+//! kernel stack and is dropped from its queue, pin and all, by
+//! [`futex_forget`].
+
+use super::{
+    arm_wait_timeout, block_current_killable, current_task, disarm_wait_timeout, TaskControlBlock,
+    TaskStatus,
+};
+use crate::mm::{translated_refmut, PhysAddr, PinnedFrames};
+use crate::sync::SpinLock;
+use crate::timer::get_time_ms;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+
+/// Why a futex operation did not complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The word is misaligned or not writable user memory
+    Fault,
+    /// The word did not hold the expected value
+    Again,
+    /// No wakeup came in time
+    TimedOut,
+    /// SIGKILL cut the wait short
+    Interrupted,
+}
+
+/// A queued task, with the frame of its word pinned
+struct Waiter {
+    task: Arc<TaskControlBlock>,
+    _pin: PinnedFrames,
+}
+
+type FutexQueues = BTreeMap<usize, VecDeque<Waiter>>;
+
+lazy_static! {
+    /// Waiters by the physical address of their word, oldest first
+    static ref FUTEX_QUEUES: SpinLock<FutexQueues> = SpinLock::new(BTreeMap::new());
+}
+
+/// The kernel view of the word at `uaddr` of the current task
+fn futex_word(uaddr: usize) -> Result<&'static AtomicU32, FutexError> {
+    let token = current_task().unwrap().inner_exclusive_access().get_user_token();
+    translated_refmut(token, uaddr as *mut u32)
+        .map(|word| unsafe { &*(word as *mut u32 as *const AtomicU32) })
+        .ok_or(FutexError::Fault)
+}
+
+/// Sleep on the word at `uaddr` if it still holds `val`, until
+/// [`futex_wake`] or for `timeout_ms` if given. A wakeup may be spurious,
+/// the caller looks at the word again anyway.
+pub fn futex_wait(uaddr: usize, val: u32, timeout_ms: Option<usize>) -> Result<(), FutexError> {
+    let word = futex_word(uaddr)?;
+    let key = word as *const AtomicU32 as usize;
+    let pin = PinnedFrames::new(vec![PhysAddr::from(key).floor()]);
+    let task = current_task().unwrap();
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    // a waker changes the word before it takes the queues, so it either
+    // finds us queued or we find the new value
+    if word.load(Ordering::SeqCst) != val {
+        return Err(FutexError::Again);
+    }
+    task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    queues.entry(key).or_default().push_back(Waiter {
+        task: task.clone(),
+        _pin: pin,
+    });
+    drop(queues);
+    if let Some(timeout_ms) = timeout_ms {
+        arm_wait_timeout(&task, get_time_ms() + timeout_ms);
+    }
+    let killed = !block_current_killable();
+    if timeout_ms.is_some() {
+        disarm_wait_timeout(&task);
+    }
+    // still queued, the timer or SIGKILL woke us
+    let queued = remove_waiter(&mut FUTEX_QUEUES.exclusive_access(), key, task.getpid());
+    if killed {
+        return Err(FutexError::Interrupted);
+    }
+    if queued {
+        return Err(FutexError::TimedOut);
+    }
+    Ok(())
+}
+
+/// Wake up to `count` tasks waiting on the word at `uaddr`, returns how many
+pub fn futex_wake(uaddr: usize, count: usize) -> Result<usize, FutexError> {
+    let key = futex_word(uaddr)? as *const AtomicU32 as usize;
+    let mut woken = Vec::new();
+    {
+        let mut queues = FUTEX_QUEUES.exclusive_access();
+        if let Some(queue) = queues.get_mut(&key) {
+            while woken.len() < count {
+                match queue.pop_front() {
+                    Some(waiter) => woken.push(waiter.task),
+                    None => break,
+                }
+            }
+            if queue.is_empty() {
+                queues.remove(&key);
+            }
+        }
+    }
+    let woken_count = woken.len();
+    woken.into_iter().for_each(super::wakeup_task);
+    Ok(woken_count)
+}
+
+/// Drop the process `pid` from the queue it waits on, if any. Called when it
+/// exits, so that a later wakeup is not spent on it.
+pub fn futex_forget(pid: usize) {
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    let keys: Vec<usize> = queues.keys().copied().collect();
+    for key in keys {
+        if remove_waiter(&mut queues, key, pid) {
+            return;
+        }
+    }
+}
+
+/// Take `pid` off the queue of `key`, returns whether it was there
+fn remove_waiter(queues: &mut FutexQueues, key: usize, pid: usize) -> bool {
+    let queue = match queues.get_mut(&key) {
+        Some(queue) => queue,
+        None => return false,
+    };
+    match queue.iter().position(|waiter| waiter.task.getpid() == pid) {
+        Some(position) => {
+            queue.remove(position);
+            if queue.is_empty() {
+                queues.remove(&key);
+            }
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,103 @@
+//! Input and output counted per task
+//!
+//! `sys_read` and `sys_write` charge the bytes they move to the calling task
+//! by the kind of file, see [`IoKind`]. The block device charges each request
+//! and the time spent waiting for it to the task current on the hart that
+//! issued it. easy-fs runs in the context of the task whose operation needs
+//! the blocks, so a miss or a read-ahead counts for the reader, and a write
+//! back for the task whose sync or eviction forced it. The periodic
+//! writeback counts for the task the timer tick interrupted, like its time.
+//!
+//! The counters are atomics, bumped without the TCB lock.
+
+use super::try_current_task;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// What a read or a write went through
+#[derive(Clone, Copy)]
+pub enum IoKind {
+    /// A file on the disk, a device or a file under `/proc`
+    File = 0,
+    /// A pipe or a named pipe
+    Pipe = 1,
+    /// The console
+    Console = 2,
+}
+
+/// Counters of one task
+#[derive(Default)]
+pub struct IoAccounting {
+    read_bytes: [AtomicUsize; 3],
+    written_bytes: [AtomicUsize; 3],
+    block_reads: AtomicUsize,
+    block_writes: AtomicUsize,
+    block_wait_us: AtomicUsize,
+}
+
+/// What [`IoAccounting`] counted so far
+#[derive(Clone, Copy, Default)]
+pub struct IoStat {
+    /// Bytes read, by [`IoKind`]
+    pub read_bytes: [usize; 3],
+    /// Bytes written, by [`IoKind`]
+    pub written_bytes: [usize; 3],
+    /// Block device requests reading blocks
+    pub block_reads: usize,
+    /// Block device requests writing blocks
+    pub block_writes: usize,
+    /// Microseconds waiting for block device requests to finish
+    pub block_wait_us: usize,
+}
+
+impl IoAccounting {
+    /// Count `bytes` read through a file of `kind`
+    pub fn charge_read(&self, kind: IoKind, bytes: usize) {
+        self.read_bytes[kind as usize].fetch_add(bytes, Ordering::Relaxed);
+    }
+    /// Count `bytes` written through a file of `kind`
+    pub fn charge_write(&self, kind: IoKind, bytes: usize) {
+        self.written_bytes[kind as usize].fetch_add(bytes, Ordering::Relaxed);
+    }
+    pub fn stat(&self) -> IoStat {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        IoStat {
+            read_bytes: [
+                load(&self.read_bytes[0]),
+                load(&self.read_bytes[1]),
+                load(&self.read_bytes[2]),
+            ],
+            written_bytes: [
+                load(&self.written_bytes[0]),
+                load(&self.written_bytes[1]),
+                load(&self.written_bytes[2]),
+            ],
+            block_reads: load(&self.block_reads),
+            block_writes: load(&self.block_writes),
+            block_wait_us: load(&self.block_wait_us),
+        }
+    }
+}
+
+impl IoStat {
+    /// Bytes read through files of every kind
+    pub fn total_read(&self) -> usize {
+        self.read_bytes.iter().sum()
+    }
+    /// Bytes written through files of every kind
+    pub fn total_written(&self) -> usize {
+        self.written_bytes.iter().sum()
+    }
+}
+
+/// Charge `requests` block device requests, writing blocks if `write`, and
+/// the `wait_us` microseconds until they finished to the current task.
+/// Nothing is charged before the first task runs.
+pub fn charge_block_io(requests: usize, write: bool, wait_us: usize) {
+    let task = match try_current_task() {
+        Some(task) => task,
+        None => return,
+    };
+    let counter = if write { &task.io.block_writes } else { &task.io.block_reads };
+    counter.fetch_add(requests, Ordering::Relaxed);
+    task.io.block_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+}
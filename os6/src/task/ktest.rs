@@ -0,0 +1,200 @@
+//! Self-tests of scheduling, see [`crate::ktest`]
+
+use super::manager::{remove_from_pid2task, remove_task};
+use super::pid::release_cached_kernel_stacks;
+use super::sched::Pass;
+use super::task::TrapCxFrame;
+use super::{global_sched_stat, wakeup_task, TaskControlBlock, TaskStatus};
+use crate::config::{
+    BIG_STRIDE, KERNEL_STACK_SIZE, MIN_PRIORITY, PAGE_SIZE, STRIDE_SLICE_US,
+    WAKEUP_BOOST_INTERVAL_US, WAKEUP_BOOST_PRIORITY_FACTOR,
+};
+use crate::fs::open_exec;
+use crate::mm::frame_allocator_stats;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// A pass after running a whole slice at `priority`, starting from `pass`
+fn after_slice(mut pass: Pass, priority: isize) -> Pass {
+    pass.dispatch(0);
+    pass.charge(priority, STRIDE_SLICE_US);
+    pass
+}
+
+/// Passes order by how much their tasks ran, weighted by priority, also
+/// across wraparound, and are raised to a floor and aged as documented
+pub fn pass_ordering() -> Result<(), &'static str> {
+    let low = after_slice(Pass::new(), MIN_PRIORITY);
+    let high = after_slice(Pass::new(), 16);
+    kassert!(high < low, "a higher priority pass grows as fast");
+    kassert!(
+        low.value() == BIG_STRIDE / MIN_PRIORITY as u64,
+        "a whole slice charged other than one stride"
+    );
+    let mut short = Pass::new();
+    short.dispatch(0);
+    short.charge(16, 0);
+    kassert!(short < high, "a yielding pass charged a whole slice");
+    kassert!(short.value() > 0, "a yielding pass charged nothing");
+
+    let before_wrap = Pass::with_value(u64::MAX - 10);
+    let wrapped = after_slice(before_wrap, 16);
+    kassert!(wrapped.value() < before_wrap.value(), "pass did not wrap");
+    kassert!(before_wrap < wrapped, "wrapped pass sorts before the one it grew from");
+    kassert!(!(wrapped < before_wrap), "passes ordered both ways across wraparound");
+
+    let mut behind = Pass::new();
+    behind.raise_to(&low);
+    kassert!(behind.value() == low.value(), "pass behind the floor not raised");
+    let mut ahead = after_slice(low, MIN_PRIORITY);
+    ahead.raise_to(&low);
+    kassert!(ahead.value() == 2 * low.value(), "pass ahead of the floor moved");
+
+    // stepped since the previous round, then not
+    ahead.age(MIN_PRIORITY, &Pass::new());
+    kassert!(ahead.value() == 2 * low.value(), "a pass that ran aged");
+    ahead.age(MIN_PRIORITY, &Pass::new());
+    kassert!(ahead.value() == low.value(), "an idle pass not aged by one stride");
+    ahead.age(MIN_PRIORITY, &low);
+    kassert!(ahead.value() == low.value(), "a pass aged below the floor");
+    Ok(())
+}
+
+/// A boost lowers a pass to the floor and charges the next run only, at a
+/// higher priority, and is refused again until the interval has passed
+pub fn pass_boost() -> Result<(), &'static str> {
+    let floor = after_slice(Pass::new(), MIN_PRIORITY);
+    let stride = floor.value();
+    let mut pass = after_slice(floor, MIN_PRIORITY);
+    let now = 10 * WAKEUP_BOOST_INTERVAL_US;
+    kassert!(pass.boost(&floor, now), "first boost refused");
+    kassert!(pass.value() == stride, "boosted pass not lowered to the floor");
+    let pass = after_slice(pass, MIN_PRIORITY);
+    let boosted_stride = BIG_STRIDE / (MIN_PRIORITY * WAKEUP_BOOST_PRIORITY_FACTOR) as u64;
+    kassert!(pass.value() == stride + boosted_stride, "boosted run charged a whole stride");
+    let mut pass = after_slice(pass, MIN_PRIORITY);
+    kassert!(pass.value() == 2 * stride + boosted_stride, "boost did not decay after a run");
+
+    let before = pass.value();
+    kassert!(!pass.boost(&floor, now + WAKEUP_BOOST_INTERVAL_US - 1), "boosted too often");
+    kassert!(pass.value() == before, "refused boost moved the pass");
+    kassert!(pass.boost(&floor, now + WAKEUP_BOOST_INTERVAL_US), "boost after the interval refused");
+    let mut behind = Pass::new();
+    kassert!(behind.boost(&floor, now), "first boost refused");
+    kassert!(behind.value() == 0, "boost raised a pass behind the floor");
+    Ok(())
+}
+
+/// Two wakeups of one blocked task, as when a timer and a pipe race, queue
+/// it once and the second is no violation
+pub fn wake_once() -> Result<(), &'static str> {
+    let inode = open_exec("ch6b_initproc").map_err(|_| "no program to create a task from")?;
+    let task = Arc::new(TaskControlBlock::new(&inode));
+    let pid = task.getpid();
+    let violations = global_sched_stat().state_violations;
+    task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+    wakeup_task(task.clone());
+    wakeup_task(task.clone());
+    kassert!(
+        task.inner_exclusive_access().task_status == TaskStatus::Ready,
+        "woken task not ready"
+    );
+    kassert!(global_sched_stat().state_violations == violations, "second wakeup a violation");
+    kassert!(remove_task(pid).is_some(), "woken task not queued");
+    kassert!(remove_task(pid).is_none(), "woken task queued twice");
+    Ok(())
+}
+
+/// Frames a forked child may take: its kernel stack, its writable pages,
+/// user stack and page tables. Its read-only pages are the parent's, its
+/// trap context gets a frame only once it runs, see [`lazy_trap_cx`].
+const MAX_FRAMES_PER_CHILD: usize = KERNEL_STACK_SIZE / PAGE_SIZE + 20;
+
+/// Forking until 90% of the frames are in use, or a process limit stops
+/// it, costs each child a bounded number of frames, and all of them come
+/// back once the children are gone
+pub fn fork_density() -> Result<(), &'static str> {
+    let inode = open_exec("ch6b_initproc").map_err(|_| "no program to create a task from")?;
+    let parent = Arc::new(TaskControlBlock::new(&inode));
+    // the stacks of the tasks before are no part of what the children take
+    release_cached_kernel_stacks();
+    let (total, free) = frame_allocator_stats();
+    let mut children = Vec::new();
+    while total - frame_allocator_stats().1 < total / 10 * 9 {
+        match parent.fork() {
+            Ok(child) => children.push(child),
+            Err(_) => break,
+        }
+    }
+    kassert!(!children.is_empty(), "no child forked");
+    let used = free - frame_allocator_stats().1;
+    println!(
+        "[ktest] {} processes forked, {} frames each, {} would fit in 90% of {} frames",
+        children.len(),
+        used / children.len(),
+        total / 10 * 9 / (used / children.len()).max(1),
+        total,
+    );
+    kassert!(used / children.len() <= MAX_FRAMES_PER_CHILD, "a forked child takes too many frames");
+    // the leaf tables that mapped the kernel stacks stay in kernel space
+    let tables = children.len() * (KERNEL_STACK_SIZE / PAGE_SIZE + 1) / 512 + 2;
+    for child in children.iter() {
+        remove_from_pid2task(child.getpid());
+    }
+    // drop the last references with no lock held, see `remove_from_pid2task`
+    let reaped = core::mem::take(&mut parent.inner_exclusive_access().children);
+    drop(reaped);
+    drop(children);
+    release_cached_kernel_stacks();
+    kassert!(
+        free.saturating_sub(frame_allocator_stats().1) <= tables,
+        "frames of the children not given back"
+    );
+    Ok(())
+}
+
+/// Forked children take no frame for their trap contexts until they first
+/// run, then one each, holding the context forked from the parent
+pub fn lazy_trap_cx() -> Result<(), &'static str> {
+    const CHILDREN: usize = 8;
+    let inode = open_exec("ch6b_initproc").map_err(|_| "no program to create a task from")?;
+    let parent = Arc::new(TaskControlBlock::new(&inode));
+    let mut children = Vec::new();
+    for _ in 0..CHILDREN {
+        children.push(parent.fork().map_err(|_| "fork failed")?);
+    }
+    let free = frame_allocator_stats().1;
+    for child in children.iter() {
+        let mut child_inner = child.inner_exclusive_access();
+        kassert!(
+            matches!(child_inner.trap_cx, TrapCxFrame::Pending(_)),
+            "trap context mapped at fork"
+        );
+        // what `trap_return` does on the child's first run
+        kassert!(child_inner.map_trap_cx(), "no frame for a trap context");
+        kassert!(
+            matches!(child_inner.trap_cx, TrapCxFrame::Mapped(_)),
+            "trap context not mapped"
+        );
+    }
+    let mapped = free - frame_allocator_stats().1;
+    println!(
+        "[ktest] {} trap contexts mapped on first run took {} frames, none were taken at fork",
+        CHILDREN, mapped,
+    );
+    kassert!(mapped == CHILDREN, "a trap context takes other than one frame");
+    let sepc = parent.inner_exclusive_access().get_trap_cx().sepc;
+    kassert!(
+        children.iter().all(|child| child.inner_exclusive_access().get_trap_cx().sepc == sepc),
+        "trap context not the parent's"
+    );
+    for child in children.iter() {
+        remove_from_pid2task(child.getpid());
+    }
+    // drop the last references with no lock held, see `remove_from_pid2task`
+    let reaped = core::mem::take(&mut parent.inner_exclusive_access().children);
+    drop(reaped);
+    drop(children);
+    kassert!(frame_allocator_stats().1 >= free + CHILDREN, "trap context frames not given back");
+    Ok(())
+}
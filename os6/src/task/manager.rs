@@ -3,109 +3,245 @@
 //! It is only used to manage processes and schedule process based on ready queue.
 //! Other CPU process monitoring functions are in Processor.
 
-use super::TaskControlBlock;
-use crate::sync::UPSafeCell;
-use crate::config::BIG_STRIDE;
-use alloc::vec::Vec;
+use super::sched::{SchedPolicy, SchedPolicyImpl};
+use super::{SchedStat, TaskControlBlock, TaskStatus};
+use super::task::TaskControlBlockInner;
+use super::pid::pids_in_use;
+use crate::sync::SpinLock;
+use crate::config::{MAX_PRIORITY, MIN_PRIORITY};
+use crate::syscall::errno::EINVAL;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use core::panic::Location;
 use lazy_static::*;
-use core::cmp::Ordering;
 
+/// The ready queue, ordered by the policy selected at build time
 pub struct TaskManager {
-    ready_queue: Vec<Arc<TaskControlBlock>>,
+    policy: SchedPolicyImpl,
 }
 
-// YOUR JOB: FIFO->Stride
-/// A simple FIFO scheduler.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: Vec::new(),
+            policy: SchedPolicyImpl::new(),
         }
     }
-    /// Add process back to ready queue
+    /// Add process back to ready queue, [`add_task`] checks it may be
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push(task);
+        self.policy.add(task);
     }
     /// Take a process out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        if self.ready_queue.is_empty() {
-            return None;
+        self.policy.fetch()
+    }
+    /// Take the process with `pid` out of the ready queue, if it is queued
+    pub fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.policy.remove(pid)
+    }
+    /// Make room in the ready queue for `tasks` tasks, false if the heap is
+    /// out of memory
+    pub fn reserve(&mut self, tasks: usize) -> bool {
+        self.policy.reserve(tasks)
+    }
+    /// Let the policy account the run of `task`, which is stopping
+    pub fn switch_out(&mut self, task: &TaskControlBlock) {
+        self.policy.on_switch_out(task);
+    }
+    /// Account a timer tick to `current`, returns whether it should be preempted
+    pub fn tick(&mut self, current: &TaskControlBlock) -> bool {
+        self.policy.on_tick(current)
+    }
+}
+
+lazy_static! {
+    /// TASK_MANAGER instance through lazy_static!
+    pub static ref TASK_MANAGER: SpinLock<TaskManager> =
+        SpinLock::new(TaskManager::new());
+    /// System-wide scheduler statistics
+    static ref SCHED_STAT: SpinLock<SchedStat> = SpinLock::new(SchedStat::default());
+    /// Every live process by pid, zombies are removed
+    static ref PID2TCB: SpinLock<BTreeMap<usize, Arc<TaskControlBlock>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Put `task` into the ready queue, waking a task that has been killed
+/// meanwhile is a no-op. Only a Ready task off every hart and not queued
+/// yet may be added, anything else is reported as a violation and left out.
+#[track_caller]
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    {
+        let mut task_inner = task.inner_exclusive_access();
+        if task_inner.task_status == TaskStatus::Zombie {
+            return;
         }
-        let mut min_i = 0;
-        let mut min_pass = self.ready_queue[0].inner_exclusive_access().pass;
-        for i in 0..self.ready_queue.len() {
-            let pass = self.ready_queue[i].inner_exclusive_access().pass;
-            if pass < min_pass {
-                min_i = i;
-                min_pass = pass;
-            }
+        if task_inner.task_status != TaskStatus::Ready
+            || task_inner.on_cpu
+            || task_inner.queued_at.is_some()
+        {
+            state_violation(&task, &task_inner, "added to the ready queue");
+            return;
         }
-        Some(self.ready_queue.swap_remove(min_i))
+        task_inner.queued_at = Some(Location::caller());
+        task_inner.enqueue_time = get_time_us();
     }
+    TASK_MANAGER.exclusive_access().add(task);
 }
 
-#[derive(Copy, Clone)]
-pub struct Pass(pub u64);
-
-impl Pass {
-    pub fn new() -> Self {
-        Self(0)
+/// Report `task` breaking the state machine of [`TaskStatus`] at the
+/// caller, with where it was queued if it is. Counted in the system
+/// [`SchedStat`], fatal in debug builds.
+#[track_caller]
+fn state_violation(task: &TaskControlBlock, task_inner: &TaskControlBlockInner, what: &str) {
+    SCHED_STAT.exclusive_access().state_violations += 1;
+    let here = Location::caller();
+    match task_inner.queued_at {
+        Some(queued_at) => error!(
+            "[kernel] pid {} {} at {} while {:?}, on cpu {}, queued at {}",
+            task.getpid(),
+            what,
+            here,
+            task_inner.task_status,
+            task_inner.on_cpu,
+            queued_at
+        ),
+        None => error!(
+            "[kernel] pid {} {} at {} while {:?}, on cpu {}, not queued",
+            task.getpid(),
+            what,
+            here,
+            task_inner.task_status,
+            task_inner.on_cpu
+        ),
     }
-    pub fn step_by_prio(&mut self, priority: isize) {
-        let stride = match BIG_STRIDE as u64 / priority as u64 {
-            0 => 1,
-            o => o,
-        };
-        self.0 += stride;
+    if cfg!(debug_assertions) {
+        panic!("task state violation of pid {}", task.getpid());
     }
 }
 
-impl PartialOrd for Pass {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let overflow = self.0.abs_diff(other.0) > BIG_STRIDE/2;
-        let order = self.0 <= other.0;
-        if order ^ overflow {
-            Some(Ordering::Less)
-        }
-        else {
-            Some(Ordering::Greater)
+/// Take the task with `pid` out of the ready queue, if it is queued
+pub fn remove_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    let task = TASK_MANAGER.exclusive_access().remove(pid)?;
+    task.inner_exclusive_access().queued_at = None;
+    Some(task)
+}
+
+pub fn insert_into_pid2task(pid: usize, task: Arc<TaskControlBlock>) {
+    PID2TCB.exclusive_access().insert(pid, task);
+}
+
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TCB.exclusive_access().get(&pid).cloned()
+}
+
+pub fn remove_from_pid2task(pid: usize) {
+    // drop a last reference only after the lock is released: frame_alloc
+    // takes this lock to swap pages out, possibly with KERNEL_SPACE held,
+    // and dropping a task frees its kernel stack in KERNEL_SPACE
+    let task = PID2TCB.exclusive_access().remove(&pid);
+    drop(task);
+}
+
+/// Call `f` on every live process in pid order, starting at `from` and
+/// wrapping around, until it returns false
+pub fn for_each_task_from(from: usize, mut f: impl FnMut(&Arc<TaskControlBlock>) -> bool) {
+    let pid2tcb = PID2TCB.exclusive_access();
+    for task in pid2tcb.range(from..).chain(pid2tcb.range(..from)).map(|(_, task)| task) {
+        if !f(task) {
+            break;
         }
     }
 }
 
-impl PartialEq for Pass {
-    fn eq(&self, _other: &Self) -> bool {
-        false
+/// Notify the scheduler of a timer tick while `current` is running,
+/// returns whether `current` should be preempted
+pub fn scheduler_tick(current: &TaskControlBlock) -> bool {
+    record_timer_interrupt();
+    let preempt = TASK_MANAGER.exclusive_access().tick(current);
+    if preempt {
+        current.inner_exclusive_access().sched_stat.preemptions += 1;
+        SCHED_STAT.exclusive_access().preemptions += 1;
     }
+    preempt
 }
-lazy_static! {
-    /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+
+/// Make sure the ready queue can take every task there is plus one more
+/// without allocating, so that a new task never fails to be queued
+pub fn reserve_ready_queue() -> bool {
+    let tasks = pids_in_use() + 1;
+    TASK_MANAGER.exclusive_access().reserve(tasks)
 }
 
-pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+/// Notify the scheduler that `task` stops running on this hart
+pub fn switch_out_task(task: &TaskControlBlock) {
+    TASK_MANAGER.exclusive_access().switch_out(task);
 }
 
+/// Take the next task out of the ready queue and mark it Running. A task
+/// that should not have been there is reported and skipped.
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    let task = TASK_MANAGER.exclusive_access().fetch()?;
-    {
+    let (task, wait_us) = loop {
+        let task = TASK_MANAGER.exclusive_access().fetch()?;
         let mut task_inner = task.inner_exclusive_access();
-        let priority = task_inner.priority;
-        task_inner.pass.step_by_prio(priority);
-        info!("fetch task with PID {}, pass {}", task.pid.0, task_inner.pass.0);
-    }
+        if task_inner.on_cpu
+            || task_inner.queued_at.is_none()
+            || !task_inner.transition(TaskStatus::Ready, TaskStatus::Running)
+        {
+            state_violation(&task, &task_inner, "fetched from the ready queue");
+            continue;
+        }
+        task_inner.queued_at = None;
+        let wait_us = get_time_us() - task_inner.enqueue_time;
+        task_inner.sched_stat.dispatches += 1;
+        task_inner.sched_stat.wait_us += wait_us;
+        drop(task_inner);
+        break (task, wait_us);
+    };
+    let mut sched_stat = SCHED_STAT.exclusive_access();
+    sched_stat.dispatches += 1;
+    sched_stat.wait_us += wait_us;
     Some(task)
 }
-pub fn set_priority(task: &TaskControlBlock, priority: isize) -> isize{
-    if priority < 2 {
-        -1
-    }
-    else {
-        let mut task_inner = task.inner_exclusive_access();
-        task_inner.priority = priority;
-        0
+
+pub fn record_timer_interrupt() {
+    SCHED_STAT.exclusive_access().timer_interrupts += 1;
+}
+
+pub fn record_context_switch() {
+    SCHED_STAT.exclusive_access().context_switches += 1;
+}
+
+pub fn global_sched_stat() -> SchedStat {
+    *SCHED_STAT.exclusive_access()
+}
+
+/// Set the stride priority of `task`, which may be running or sitting in the
+/// ready queue. Returns -EINVAL if `priority` is outside
+/// [`MIN_PRIORITY`, `MAX_PRIORITY`].
+///
+/// A run is charged when it ends, so the new stride applies from the
+/// current run of a running task, or the next run of a queued one.
+pub fn set_priority(task: &TaskControlBlock, priority: isize) -> isize {
+    if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+        return -EINVAL;
     }
-}
\ No newline at end of file
+    task.inner_exclusive_access().priority = priority;
+    0
+}
+
+pub fn get_priority(task: &TaskControlBlock) -> isize {
+    task.inner_exclusive_access().priority
+}
+
+/// Lower the stride priority of `task` by `increment`, or raise it if
+/// negative, like `nice`, keeping it within [`MIN_PRIORITY`,
+/// `MAX_PRIORITY`]. Returns the new priority, which applies like with
+/// [`set_priority`].
+pub fn nice(task: &TaskControlBlock, increment: isize) -> isize {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.priority = task_inner
+        .priority
+        .saturating_sub(increment)
+        .clamp(MIN_PRIORITY, MAX_PRIORITY);
+    task_inner.priority
+}
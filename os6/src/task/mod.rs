@@ -10,31 +10,79 @@
 //! might not be what you expect.
 
 mod context;
+mod coredump;
+mod fd_table;
+mod futex;
+mod io;
+#[cfg(feature = "ktest")]
+pub mod ktest;
 mod manager;
 mod pid;
 mod processor;
+mod rlimit;
+mod sched;
+mod signal;
 mod switch;
+mod watchdog;
 #[allow(clippy::module_inception)]
 mod task;
 
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::*;
-use manager::fetch_task;
+use manager::{
+    fetch_task, for_each_task_from, insert_into_pid2task, remove_from_pid2task, remove_task,
+};
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::{spin_locks_held, SpinLock};
 use switch::__switch;
-use crate::mm::{VirtAddr, MapPermission};
-pub use crate::syscall::process::TaskInfo;
-use crate::fs::{open_file, OpenFlags};
-pub use task::{TaskControlBlock, TaskStatus};
+use crate::config::{PAGE_SIZE, SHM_BASE, SHUTDOWN_REAP_MS, SWAP_RESERVED_SLOTS};
+use crate::syscall::errno::{EINVAL, ENOMEM, EPERM, ESRCH};
+use crate::mm::{
+    free_swap_slots, frames_available, shm_pages, write_back_pages, Fault, MapAreaBacking,
+    MapPermission, MemorySet, ShmAttachment, VirtAddr, VirtPageNum,
+};
+pub use crate::syscall::process::{SchedStat, TaskInfo};
+use crate::fs::open_exec;
+use crate::perf::SwitchKind;
+use crate::timer::{add_timer, get_time_ms, get_time_us, IntervalTimer, TimerCallback, TimerHandle};
+pub use task::{SpawnError, TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
-pub use manager::{add_task, set_priority};
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use manager::{
+    add_task, get_priority, global_sched_stat, nice, pid2task, record_context_switch,
+    record_timer_interrupt, scheduler_tick, set_priority,
+};
+pub use pid::{
+    cached_kernel_stack_pages, kernel_stack_guard_owner, kernel_stack_position, pid_alloc,
+    KernelStack, PidHandle,
+};
+use pid::release_cached_kernel_stacks;
+pub use rlimit::RLimits;
+pub use coredump::dump_core;
+pub use futex::{futex_wait, futex_wake, FutexError};
+pub use io::{charge_block_io, IoKind, IoStat};
+pub use watchdog::{touch_watchdog, watchdog_tick};
+pub use signal::{SigInfo, SignalFlags, MAX_SIG, SIGALRM, SIGILL, SIGKILL, SIGSEGV, SIG_DFL, SIG_IGN};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_task, current_trap_cx, current_user_token, hart_id, run_tasks, schedule,
+    take_current_task, try_current_task,
 };
 
 /// Make current task suspended and switch to the next task
 pub fn suspend_current_and_run_next() {
+    suspend_current(SwitchKind::Voluntary);
+}
+
+/// [`suspend_current_and_run_next`] for the timer taking the CPU away
+pub fn preempt_current_and_run_next() {
+    suspend_current(SwitchKind::Preempted);
+}
+
+/// Put the current task back into the ready queue and switch to the next
+fn suspend_current(kind: SwitchKind) {
     // There must be an application running.
     let task = take_current_task().unwrap();
 
@@ -46,44 +94,487 @@ pub fn suspend_current_and_run_next() {
     drop(task_inner);
     // ---- release current PCB
 
-    // push back to ready queue.
-    add_task(task);
-    // jump to scheduling cycle
-    schedule(task_cx_ptr);
+    // jump to scheduling cycle, which pushes it back to ready queue
+    schedule(task, task_cx_ptr, kind);
+}
+
+/// Make current task wait out of the ready queue until [`wakeup_task`].
+///
+/// The caller marks the task `Blocked` before arranging for the wakeup, so
+/// one coming from another hart in the meantime is not lost.
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let task_cx_ptr = {
+        let mut task_inner = task.inner_exclusive_access();
+        &mut task_inner.task_cx as *mut TaskContext
+    };
+    schedule(task, task_cx_ptr, SwitchKind::Voluntary);
+}
+
+/// [`block_current_and_run_next`] for a wait SIGKILL cuts short. Returns
+/// false if the task is killed, then the caller unwinds, dropping what its
+/// kernel stack holds, and the task exits on its way back to user mode.
+pub fn block_current_killable() -> bool {
+    let task = current_task().unwrap();
+    {
+        // checked under the lock [`kill_task`] sends it under, so a SIGKILL
+        // either is seen here or finds the task in its wait and wakes it
+        let mut task_inner = task.inner_exclusive_access();
+        if task_inner.signals.contains(SignalFlags::SIGKILL) {
+            task_inner.task_status = TaskStatus::Running;
+            return false;
+        }
+        task_inner.in_kernel_wait = true;
+    }
+    drop(task);
+    block_current_and_run_next();
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.in_kernel_wait = false;
+    !task_inner.signals.contains(SignalFlags::SIGKILL)
+}
+
+/// Whether the kernel path running now may switch to other tasks: it runs
+/// for a task and holds no SpinLock, which would stay locked with
+/// interrupts off on a hart the task left
+pub fn may_block() -> bool {
+    spin_locks_held() == 0 && current_task().is_some()
+}
+
+/// Wait a moment for a lock another task holds, maybe asleep on the disk.
+///
+/// No interrupt reaches the kernel, so finished disk requests are picked up
+/// here, then the current task makes way for the others if it may switch.
+pub fn relax() {
+    BLOCK_DEVICE.handle_irq();
+    if may_block() {
+        let task = current_task().unwrap();
+        task.inner_exclusive_access().in_kernel_wait = true;
+        suspend_current_and_run_next();
+        task.inner_exclusive_access().in_kernel_wait = false;
+    } else {
+        core::hint::spin_loop();
+    }
+}
+
+/// Make a `Blocked` task ready again, other tasks are left alone. Of two
+/// wakeups racing, say a timer and a pipe, only the first finds it Blocked
+/// and queues it.
+#[track_caller]
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if !task_inner.transition(TaskStatus::Blocked, TaskStatus::Ready) {
+        return;
+    }
+    task_inner.woken = true;
+    // one still switching out is queued by its hart once off its kernel stack
+    let on_cpu = task_inner.on_cpu;
+    drop(task_inner);
+    if !on_cpu {
+        add_task(task);
+    }
 }
 
 /// Exit current task, recycle process resources and switch to the next task
 pub fn exit_current_and_run_next(exit_code: i32) {
     // take from Processor
     let task = take_current_task().unwrap();
-    // **** access current TCB exclusively
+    retire_task(&task, exit_code);
+    // we do not have to save task context; the idle control flow drops the
+    // task once we are off its kernel stack
+    let mut _unused = TaskContext::zero_init();
+    schedule(task, &mut _unused as *mut _, SwitchKind::Voluntary);
+}
+
+/// Send `signal` to the task with `pid`, returns -ESRCH if there is no such
+/// live task or -EPERM for initproc.
+///
+/// The signal is acted on the next time the task would return to user mode,
+/// see [`handle_signals`]. SIGKILL does not wait for that: a task sitting in
+/// the ready queue or blocked is torn down at once.
+pub fn kill_task(pid: usize, signal: SignalFlags) -> isize {
+    let task = match pid2task(pid) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    if Arc::ptr_eq(&task, &INITPROC) {
+        return -EPERM;
+    }
+    task.inner_exclusive_access().signals.insert(signal);
+    if signal == SignalFlags::SIGKILL {
+        tear_down_killed(task);
+    }
+    0
+}
+
+/// The live processes of group `pgid`
+fn group_members(pgid: usize) -> Vec<Arc<TaskControlBlock>> {
+    let mut members = Vec::new();
+    for_each_task_from(0, |task| {
+        if task.getpgid() == pgid {
+            members.push(task.clone());
+        }
+        true
+    });
+    members
+}
+
+/// Whether some live process is in group `pgid`
+pub fn group_exists(pgid: usize) -> bool {
+    let mut exists = false;
+    for_each_task_from(0, |task| {
+        exists = task.getpgid() == pgid;
+        !exists
+    });
+    exists
+}
+
+/// Send `signal` to every process of group `pgid` but initproc, see
+/// [`kill_task`]. Returns -ESRCH if none of them got it.
+pub fn kill_group(pgid: usize, signal: SignalFlags) -> isize {
+    let mut sent = false;
+    for task in group_members(pgid) {
+        sent |= kill_task(task.getpid(), signal) == 0;
+    }
+    if sent {
+        0
+    } else {
+        -ESRCH
+    }
+}
+
+/// Retire `task`, which has SIGKILL pending, unless it is running on some
+/// hart or switched out inside the kernel, where it exits by itself before
+/// returning to user mode
+fn tear_down_killed(task: Arc<TaskControlBlock>) {
+    if let Some(task) = remove_task(task.getpid()) {
+        // one switched out inside the kernel may hold locks
+        if task.inner_exclusive_access().in_kernel_wait {
+            add_task(task);
+        } else {
+            retire_killed(&task, SIGKILL);
+        }
+        return;
+    }
+    // claim a blocked task under its lock, so that a racing wakeup leaves
+    // it alone
+    let (blocked, in_kernel_wait) = {
+        let mut task_inner = task.inner_exclusive_access();
+        let in_kernel_wait =
+            task_inner.task_status == TaskStatus::Blocked && task_inner.in_kernel_wait;
+        let blocked = task_inner.task_status == TaskStatus::Blocked
+            && !task_inner.on_cpu
+            && !task_inner.in_kernel_wait;
+        if blocked {
+            task_inner.task_status = TaskStatus::Zombie;
+        }
+        (blocked, in_kernel_wait)
+    };
+    if blocked {
+        retire_killed(&task, SIGKILL);
+    } else if in_kernel_wait {
+        // wake it to unwind, a disk wait sees its request is not done yet
+        // and blocks again
+        wakeup_task(task);
+    }
+}
+
+/// SIGKILL every descendant of `task`.
+///
+/// Only one TCB is locked at a time. A task's children are read under the
+/// same lock that marks it killed, and a killed task cannot fork, so none
+/// escapes by forking meanwhile.
+fn kill_descendants(task: &Arc<TaskControlBlock>) {
+    let mut pending: Vec<Arc<TaskControlBlock>> =
+        task.inner_exclusive_access().children.clone();
+    while let Some(child) = pending.pop() {
+        {
+            let mut child_inner = child.inner_exclusive_access();
+            // a zombie has handed its children over to initproc already
+            if child_inner.is_zombie() {
+                continue;
+            }
+            child_inner.signals.insert(SignalFlags::SIGKILL);
+            pending.extend(child_inner.children.iter().cloned());
+        }
+        tear_down_killed(child);
+    }
+}
+
+/// Exit the current task as killed by `signum`, with the exit code
+/// `-signum`
+pub fn exit_killed_and_run_next(signum: usize) {
+    current_task().unwrap().inner_exclusive_access().term_signal = Some(signum);
+    exit_current_and_run_next(-(signum as i32));
+}
+
+/// Like [`exit_current_and_run_next`], killing all descendants of the
+/// current task first instead of leaving them to initproc
+pub fn exit_group_and_run_next(exit_code: i32) {
+    kill_descendants(&current_task().unwrap());
+    exit_current_and_run_next(exit_code);
+}
+
+/// Set once the system is going down, no task is created from then on
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`kill_all_tasks`] has begun, fork and spawn fail then
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Whether `task` may power the system off: initproc or the shell it
+/// started, unless it dropped its privilege
+pub fn may_shut_down(task: &Arc<TaskControlBlock>) -> bool {
+    let task_inner = task.inner_exclusive_access();
+    if !task_inner.privileged {
+        return false;
+    }
+    Arc::ptr_eq(task, &INITPROC)
+        || task_inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(false, |parent| Arc::ptr_eq(&parent, &INITPROC))
+}
+
+/// Stop creating tasks, then SIGKILL every task but initproc and the
+/// current one until they have all exited, or [`SHUTDOWN_REAP_MS`] passed.
+/// Returns how many are left.
+///
+/// The current task makes way while it waits, so that the killed ones
+/// running on some hart or waiting in the kernel, on the disk say, get to
+/// exit, closing their files. Each round kills again whatever a fork in
+/// flight added.
+pub fn kill_all_tasks() -> usize {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    let current = current_task().unwrap();
+    let deadline = get_time_ms() + SHUTDOWN_REAP_MS;
+    loop {
+        let mut alive = Vec::new();
+        for_each_task_from(0, |task| {
+            if !Arc::ptr_eq(task, &current) && !Arc::ptr_eq(task, &INITPROC) {
+                alive.push(task.clone());
+            }
+            true
+        });
+        if alive.is_empty() || get_time_ms() >= deadline {
+            return alive.len();
+        }
+        for task in alive {
+            task.inner_exclusive_access().signals.insert(SignalFlags::SIGKILL);
+            tear_down_killed(task);
+        }
+        suspend_current_and_run_next();
+    }
+}
+
+/// Act on the pending signals of the current task, called right before it
+/// returns to user mode.
+///
+/// A caught signal diverts the task to its handler, with the signal number
+/// in `a0` and for a fault the faulting address in `a1`, after saving the interrupted context for `sys_sigreturn`. No
+/// other signal is delivered until then, except SIGKILL.
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let mut task_inner = task.inner_exclusive_access();
+        if task_inner.signals.contains(SignalFlags::SIGKILL) {
+            drop(task_inner);
+            drop(task);
+            exit_killed_and_run_next(SIGKILL);
+            return;
+        }
+        if task_inner.trap_cx_backup.is_some() {
+            return;
+        }
+        let signum = match task_inner.signals.first_signum() {
+            Some(signum) => signum,
+            None => return,
+        };
+        task_inner.signals.remove(SignalFlags::from_signum(signum).unwrap());
+        match task_inner.signal_actions.table[signum] {
+            SIG_IGN => {}
+            SIG_DFL => {
+                if !signal::ignored_by_default(signum) {
+                    drop(task_inner);
+                    if signal::dumps_core_by_default(signum) {
+                        dump_core(&task, signum, 0, 0);
+                    }
+                    drop(task);
+                    exit_killed_and_run_next(signum);
+                    return;
+                }
+            }
+            handler => {
+                // a fault signal tells the handler where
+                let addr = match task_inner.fault_info {
+                    Some(info) if info.signum == signum => {
+                        task_inner.fault_info = None;
+                        info.addr
+                    }
+                    _ => 0,
+                };
+                let trap_cx = task_inner.get_trap_cx();
+                task_inner.trap_cx_backup = Some(Box::new(*trap_cx));
+                trap_cx.sepc = handler;
+                trap_cx.x[10] = signum;
+                trap_cx.x[11] = addr;
+                return;
+            }
+        }
+    }
+}
+
+/// Raise the signal of `info` in the current task for a fault it caused,
+/// delivered on its way back to user mode with the faulting address in
+/// `a1`. Returns false if the task does not catch the signal or is in a
+/// handler already, the fault is fatal then.
+pub fn catch_fault_signal(info: SigInfo) -> bool {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let handler = task_inner.signal_actions.table[info.signum];
+    if handler == SIG_DFL || handler == SIG_IGN || task_inner.trap_cx_backup.is_some() {
+        return false;
+    }
+    task_inner.signals.insert(SignalFlags::from_signum(info.signum).unwrap());
+    task_inner.fault_info = Some(info);
+    true
+}
+
+/// Install `handler` for `signum` in the current task, returns the previous
+/// one or -EINVAL for an unknown signal or SIGKILL, which cannot be caught
+pub fn set_signal_action(signum: usize, handler: usize) -> isize {
+    if signum == SIGKILL || SignalFlags::from_signum(signum).is_none() {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let old = task_inner.signal_actions.table[signum];
+    task_inner.signal_actions.table[signum] = handler;
+    old as isize
+}
+
+/// Resume the context interrupted by a signal handler, returns its `a0` so
+/// that writing back the syscall result keeps it, or -EINVAL outside a
+/// handler
+pub fn signal_return() -> isize {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    match task_inner.trap_cx_backup.take() {
+        Some(backup) => {
+            let trap_cx = task_inner.get_trap_cx();
+            *trap_cx = *backup;
+            trap_cx.x[10] as isize
+        }
+        None => -EINVAL,
+    }
+}
+
+/// Arm the `ITIMER_REAL` of the current task to expire in `value_ms`, then
+/// every `interval_ms` if that is not 0. A `value_ms` of 0 disarms it.
+/// Returns the milliseconds that were left on the previous timer.
+pub fn set_itimer(value_ms: usize, interval_ms: usize) -> usize {
+    let task = current_task().unwrap();
+    let now = get_time_ms();
+    let mut task_inner = task.inner_exclusive_access();
+    let old = task_inner.itimer.take();
+    if let Some(old) = old {
+        old.handle.cancel();
+    }
+    if value_ms != 0 {
+        let expire_ms = now + value_ms;
+        task_inner.itimer = Some(IntervalTimer {
+            expire_ms,
+            interval_ms,
+            handle: add_timer(expire_ms, TimerCallback::Alarm(task.clone())),
+        });
+    }
+    old.map_or(0, |old| old.expire_ms.saturating_sub(now))
+}
+
+/// Raise SIGALRM on `task` for its `ITIMER_REAL` timer `handle`, and re-arm
+/// it if it is periodic
+pub fn alarm_expired(task: Arc<TaskControlBlock>, handle: TimerHandle) {
+    let mut task_inner = task.inner_exclusive_access();
+    let itimer = match task_inner.itimer {
+        // replaced while firing, the new one has its own timer
+        Some(itimer) if itimer.handle == handle => itimer,
+        _ => return,
+    };
+    task_inner.signals.insert(SignalFlags::SIGALRM);
+    if itimer.interval_ms == 0 {
+        task_inner.itimer = None;
+        return;
+    }
+    let expire_ms = itimer.expire_ms + itimer.interval_ms;
+    task_inner.itimer = Some(IntervalTimer {
+        expire_ms,
+        interval_ms: itimer.interval_ms,
+        handle: add_timer(expire_ms, TimerCallback::Alarm(task.clone())),
+    });
+}
+
+/// Wake `task` at `expire_ms` unless something else wakes it first, the
+/// timeout of the sleep or wait it is about to block in
+pub fn arm_wait_timeout(task: &Arc<TaskControlBlock>, expire_ms: usize) {
+    let handle = add_timer(expire_ms, TimerCallback::Wakeup(task.clone()));
+    task.inner_exclusive_access().wait_timer = Some(handle);
+}
+
+/// Disarm the timeout of the wait `task` is done with, if it has not fired
+pub fn disarm_wait_timeout(task: &Arc<TaskControlBlock>) {
+    let handle = task.inner_exclusive_access().wait_timer.take();
+    if let Some(handle) = handle {
+        handle.cancel();
+    }
+}
+
+/// [`retire_task`] for a task killed by `signum`
+fn retire_killed(task: &Arc<TaskControlBlock>, signum: usize) {
+    task.inner_exclusive_access().term_signal = Some(signum);
+    retire_task(task, -(signum as i32));
+}
+
+/// Turn `task` into a zombie waiting for its parent, hand its children over
+/// to initproc and recycle its user space. `task` must not be running.
+fn retire_task(task: &Arc<TaskControlBlock>, exit_code: i32) {
+    remove_from_pid2task(task.getpid());
+    // a killed futex waiter is still queued, its word pinned
+    futex::futex_forget(task.getpid());
+    // **** access the TCB exclusively
     let mut inner = task.inner_exclusive_access();
     // Change status to Zombie
     inner.task_status = TaskStatus::Zombie;
     // Record exit code
     inner.exit_code = exit_code;
-    // do not move to its parent but under initproc
+    let timers = [inner.itimer.take().map(|itimer| itimer.handle), inner.wait_timer.take()];
+    let children = core::mem::take(&mut inner.children);
+    // close all files, a pipe sees its end closed without waiting for the
+    // parent to reap us
+    let fd_table = core::mem::take(&mut inner.fd_table);
+    // deallocate user space
+    inner.memory_set.recycle_data_pages();
+    drop(inner);
+    drop(fd_table);
+    // with `itimer` cleared no alarm can be re-armed behind our back
+    for handle in timers.iter().flatten() {
+        handle.cancel();
+    }
+    // **** release the PCB
+    // do not move to its parent but under initproc.
+    // initproc is always locked before its children, never while holding
+    // another TCB, so two harts reparenting at once cannot deadlock
 
     // ++++++ access initproc TCB exclusively
     {
         let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
+        for child in children {
             child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+            initproc_inner.children.push(child);
         }
     }
     // ++++++ release parent PCB
-
-    inner.children.clear();
-    // deallocate user space
-    inner.memory_set.recycle_data_pages();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
-    drop(task);
-    // we do not have to save task context
-    let mut _unused = TaskContext::zero_init();
-    schedule(&mut _unused as *mut _);
 }
 
 lazy_static! {
@@ -92,30 +583,136 @@ lazy_static! {
     /// the name "initproc" may be changed to any other app name like "usertests",
     /// but we have user_shell, so we don't need to change it.
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
-        let inode = open_file("ch6b_initproc", OpenFlags::RDONLY).unwrap();
-        let v = inode.read_all();
-        TaskControlBlock::new(v.as_slice())
+        let inode = open_exec("ch6b_initproc").unwrap();
+        TaskControlBlock::new(&inode)
     });
 }
 
 pub fn add_initproc() {
+    insert_into_pid2task(INITPROC.getpid(), INITPROC.clone());
     add_task(INITPROC.clone());
 }
 
-pub fn update_syscall_times(syscall_id: usize) {
+/// [`frames_available`], giving the cached kernel stacks back first and
+/// then swapping out pages of other processes if frames are short
+fn frames_available_reclaiming(pages: usize) -> bool {
+    if frames_available(pages) {
+        return true;
+    }
+    release_cached_kernel_stacks();
+    swap_until_available(pages, false)
+}
+
+/// Swap out pages until [`frames_available`] holds for `pages`, without
+/// touching the reserved swap slots. Returns whether it holds.
+fn swap_until_available(pages: usize, include_current: bool) -> bool {
+    while !frames_available(pages) {
+        if swap_out_pages(SWAP_BATCH, include_current, SWAP_RESERVED_SLOTS) == 0 {
+            return false;
+        }
+    }
+    true
+}
+
+/// Pages swapped out at a time while making room for a larger allocation
+const SWAP_BATCH: usize = 16;
+/// Frames a page fault may need: the page and two page table pages
+const PAGE_FAULT_FRAMES: usize = 3;
+
+lazy_static! {
+    /// Clock hand of page replacement, a pid and a page of its address space
+    static ref SWAP_CLOCK: SpinLock<(usize, VirtPageNum)> = SpinLock::new((0, VirtPageNum(0)));
+}
+
+/// Swap out up to `pages` user pages picked by a clock running over all
+/// address spaces, as long as more than `keep_slots` swap slots are free.
+/// Returns the number of pages swapped out.
+///
+/// Processes that are locked or running on some hart are passed over. The
+/// current task is included only if `include_current`, which the caller may
+/// ask for only if the kernel holds no reference into its memory and not
+/// its lock.
+///
+/// The pages are written out once the locks are let go, which waits for the
+/// disk, so the caller must not hold a SpinLock.
+pub fn swap_out_pages(pages: usize, include_current: bool, keep_slots: usize) -> usize {
+    // a hart finding another one sweeping leaves the work to it
+    let mut clock = match SWAP_CLOCK.try_exclusive_access() {
+        Some(clock) => clock,
+        None => return 0,
+    };
+    let current = current_task();
+    let (mut pid, mut hand) = *clock;
+    let mut slots = Vec::new();
+    // pages accessed since the last pass are only passed over the first time
+    for _ in 0..2 {
+        for_each_task_from(pid, |task| {
+            if task.getpid() != pid {
+                pid = task.getpid();
+                hand = VirtPageNum(0);
+            }
+            let is_current = current.as_ref().map_or(false, |current| Arc::ptr_eq(current, task));
+            if is_current && !include_current {
+                return true;
+            }
+            if let Some(mut inner) = task.try_inner_exclusive_access() {
+                if !inner.on_cpu || is_current {
+                    while slots.len() < pages && free_swap_slots() > keep_slots {
+                        match inner.memory_set.swap_out_next(&mut hand) {
+                            Some(slot) => slots.push(slot),
+                            None => break,
+                        }
+                    }
+                    if is_current {
+                        // other harts never run the pages of a task that is
+                        // not on a cpu, but this one may still cache them
+                        unsafe {
+                            core::arch::asm!("sfence.vma");
+                        }
+                    }
+                }
+            }
+            slots.len() < pages && free_swap_slots() > keep_slots
+        });
+        if slots.len() == pages || free_swap_slots() <= keep_slots {
+            break;
+        }
+    }
+    *clock = (pid, hand);
+    drop(clock);
+    for slot in slots.iter() {
+        slot.write_out();
+    }
+    slots.len()
+}
+
+/// Charge the time since the last mode switch of the current task to user
+/// mode if `user`, else to the kernel. Dispatching resets the mark, so time
+/// spent switched out counts for neither.
+pub fn account_cpu_time(user: bool) {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    inner.syscall_times[syscall_id] += 1;
+    let now = get_time_us();
+    let elapsed = now - inner.mode_time;
+    if user {
+        inner.utime_us += elapsed;
+    } else {
+        inner.stime_us += elapsed;
+    }
+    inner.mode_time = now;
 }
 
-pub fn mmap(start_va: VirtAddr, end_va: VirtAddr, port: usize) -> isize {
+/// Count a call of `syscall_id`, a bogus one past the table is not counted
+pub fn update_syscall_times(syscall_id: usize) {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    let mem_set = &mut inner.memory_set;
-    let end_va = end_va.ceil().into();
-    if mem_set.conflict_with_range(start_va, end_va) {
-        return -1;
+    if let Some(times) = inner.syscall_times.get_mut(syscall_id) {
+        *times += 1;
     }
+}
+
+/// User permissions for the `port` bits of `sys_mmap`: R, W and X from bit 0 up
+fn port_to_perm(port: usize) -> MapPermission {
     let mut perm = MapPermission::U;
     if (port & (1 << 0)) != 0 {
         perm |= MapPermission::R;
@@ -126,22 +723,193 @@ pub fn mmap(start_va: VirtAddr, end_va: VirtAddr, port: usize) -> isize {
     if (port & (1 << 2)) != 0 {
         perm |= MapPermission::X;
     }
-    mem_set.insert_framed_area(
-        start_va,
-        end_va,
-        perm
-    );
-    info!("mmap: [{:#x}, {:#x}]", usize::from(start_va), usize::from(end_va));
-    0
+    perm
 }
 
-pub fn munmap(start_va: VirtAddr, end_va: VirtAddr) -> isize {
+pub fn mmap(start_va: VirtAddr, end_va: VirtAddr, port: usize, backing: MapAreaBacking) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
+    let max_user_pages = inner.rlimits.max_user_pages;
     let mem_set = &mut inner.memory_set;
+    let end_va: VirtAddr = end_va.ceil().into();
+    let pages = (usize::from(end_va) - usize::from(start_va.floor())) / PAGE_SIZE;
+    // frames are only allocated as the pages are touched, so only the
+    // address space is limited here
+    if mem_set.mapped_pages() + pages > max_user_pages {
+        return -ENOMEM;
+    }
+    let ret = mem_set.insert_framed_area_checked(start_va, end_va, port_to_perm(port), backing);
+    info!("mmap: [{:#x}, {:#x}] = {}", usize::from(start_va), usize::from(end_va), ret);
+    ret
+}
+
+pub fn munmap(start_va: VirtAddr, end_va: VirtAddr) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
     let start_vn = start_va.floor();
     let end_vn = end_va.ceil();
-    let ret = mem_set.unmap_area_by_exact_range(start_vn, end_vn);
-    info!("munmap: [{:#x}, {:#x}]", usize::from(start_vn), usize::from(end_vn));
+    let ret = inner.memory_set.remove_area_range(start_vn, end_vn);
+    drop(inner);
+    write_back_pages();
+    info!("munmap: [{:#x}, {:#x}] = {}", usize::from(start_vn), usize::from(end_vn), ret);
     ret
-}
\ No newline at end of file
+}
+
+/// Attach shared memory segment `shmid` to the current task, returns the
+/// address, -EINVAL if there is no such segment or -ENOMEM if there is no
+/// room for it
+pub fn shm_attach(shmid: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let max_user_pages = inner.rlimits.max_user_pages;
+    let pages = match shm_pages(shmid) {
+        Some(pages) => pages,
+        None => return -EINVAL,
+    };
+    let mmap_base = SHM_BASE + inner.layout.mmap;
+    let mem_set = &mut inner.memory_set;
+    if mem_set.mapped_pages() + pages > max_user_pages {
+        return -ENOMEM;
+    }
+    let start_va = match mem_set.find_free_range(mmap_base.into(), pages * PAGE_SIZE) {
+        Some(start_va) => start_va,
+        None => return -ENOMEM,
+    };
+    // the segment may have gone since its size was looked up
+    let (attachment, frames) = match ShmAttachment::new(shmid) {
+        Some(attached) => attached,
+        None => return -EINVAL,
+    };
+    if !mem_set.insert_shm_area(start_va, attachment, frames) {
+        return -ENOMEM;
+    }
+    usize::from(start_va) as isize
+}
+
+/// Detach the shared memory segment attached at `start_va` from the current
+/// task, -EINVAL if none is attached there
+pub fn shm_detach(start_va: VirtAddr) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.memory_set.remove_shm_area(start_va.floor()) {
+        0
+    } else {
+        -EINVAL
+    }
+}
+
+/// Move the program break of the current task by `increment` bytes, returns
+/// the old break or -ENOMEM
+pub fn sbrk(increment: isize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old_top = inner.heap_top;
+    let heap_top = if increment >= 0 {
+        old_top.checked_add(increment as usize)
+    } else {
+        old_top.checked_sub(increment.unsigned_abs())
+    };
+    match heap_top {
+        Some(heap_top) if inner.set_program_break(heap_top) => old_top as isize,
+        _ => -ENOMEM,
+    }
+}
+
+/// Move the program break of the current task to `heap_top`, 0 just asks
+/// for it. Returns the new break, or the unchanged one on failure.
+pub fn brk(heap_top: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if heap_top != 0 {
+        inner.set_program_break(heap_top);
+    }
+    inner.heap_top as isize
+}
+
+pub fn msync(start_va: VirtAddr, end_va: VirtAddr) -> isize {
+    let task = current_task().unwrap();
+    let ret = task
+        .inner_exclusive_access()
+        .memory_set
+        .sync_area_range(start_va.floor(), end_va.ceil());
+    write_back_pages();
+    ret
+}
+
+/// Change the permissions of `[start_va, end_va)` of the current task to the
+/// `port` bits of `sys_mmap`
+pub fn mprotect(start_va: VirtAddr, end_va: VirtAddr, port: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let ret = inner
+        .memory_set
+        .protect_area_range(start_va.floor(), end_va.ceil(), port_to_perm(port));
+    // the tlb may still hold the old permissions
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+    ret
+}
+
+/// Resolve a fault of `task`, the current one, by calling `fault` on its
+/// address space. A page to read from a file or swap is read with the TCB
+/// lock let go, as the read waits for the disk, and mapped after. Returns
+/// false if the access is not allowed or frames run out.
+fn resolve_fault(task: &Arc<TaskControlBlock>, fault: impl Fn(&mut MemorySet) -> Fault) -> bool {
+    let page_in = match fault(&mut task.inner_exclusive_access().memory_set) {
+        Fault::Done(handled) => return handled,
+        Fault::PageIn(page_in) => page_in,
+    };
+    match page_in.read() {
+        Some(frame) => task
+            .inner_exclusive_access()
+            .memory_set
+            .finish_page_in(page_in, frame),
+        None => false,
+    }
+}
+
+/// Populate the page at `vpn` of the current task, if it belongs to a lazily
+/// mapped area, before the kernel reads it, or writes it if `write`
+pub fn populate_user_page(vpn: VirtPageNum, write: bool) {
+    if let Some(task) = current_task() {
+        resolve_fault(&task, |memory_set| memory_set.populate_for_kernel(vpn, write));
+    }
+}
+
+/// Try to resolve a page fault of the current task at `va` from an access
+/// needing `access`. Returns false if the fault is fatal.
+pub fn handle_page_fault(va: usize, access: MapPermission) -> bool {
+    let sp = current_trap_cx().x[2];
+    let task = current_task().unwrap();
+    let fault = |memory_set: &mut MemorySet| {
+        memory_set.handle_page_fault(VirtAddr::from(va), access, sp)
+    };
+    if resolve_fault(&task, fault) {
+        return true;
+    }
+    if frames_available(PAGE_FAULT_FRAMES) {
+        return false;
+    }
+    // out of frames even after swapping out other processes, the kernel
+    // holds nothing in user memory here, so the task may lose pages itself
+    swap_out_pages(PAGE_FAULT_FRAMES, true, 0);
+    resolve_fault(&task, fault)
+}
+
+/// Give the current task the frame of its trap context if it has not run
+/// yet, called right before it returns to user mode. Killed if there is no
+/// frame even after swapping out other processes.
+pub fn map_current_trap_cx() {
+    let task = current_task().unwrap();
+    if task.inner_exclusive_access().map_trap_cx() {
+        return;
+    }
+    // the lock is dropped, so pages may go to disk now
+    swap_until_available(1, false);
+    if !task.inner_exclusive_access().map_trap_cx() {
+        println!("[kernel] no frame for the trap context of pid {}, killed", task.getpid());
+        drop(task);
+        exit_killed_and_run_next(SIGKILL);
+    }
+}
@@ -2,10 +2,14 @@
 //!
 //! Assign PID to the process here. At the same time, the position of the application KernelStack
 //! is determined according to the PID.
+//!
+//! The kernel stacks of the latest exited processes stay mapped, and their
+//! pids are handed out first, so a busy fork/exit cycle does not map and
+//! unmap a kernel stack every time.
 
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{KERNEL_STACK_CACHE, KERNEL_STACK_SIZE, MEMORY_END, PAGE_SIZE, TRAMPOLINE};
 use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::SpinLock;
 use alloc::vec::Vec;
 use lazy_static::*;
 
@@ -15,6 +19,9 @@ struct PidAllocator {
     current: usize,
     /// Recycled PID sequence
     recycled: Vec<usize>,
+    /// Recycled pids whose kernel stack is still mapped, at most
+    /// `KERNEL_STACK_CACHE` of them
+    stacked: Vec<usize>,
 }
 
 impl PidAllocator {
@@ -22,10 +29,13 @@ impl PidAllocator {
         PidAllocator {
             current: 0,
             recycled: Vec::new(),
+            stacked: Vec::new(),
         }
     }
     pub fn alloc(&mut self) -> PidHandle {
-        if let Some(pid) = self.recycled.pop() {
+        if let Some(pid) = self.stacked.pop() {
+            PidHandle(pid)
+        } else if let Some(pid) = self.recycled.pop() {
             PidHandle(pid)
         } else {
             self.current += 1;
@@ -35,18 +45,24 @@ impl PidAllocator {
     pub fn dealloc(&mut self, pid: usize) {
         assert!(pid < self.current);
         assert!(
-            !self.recycled.iter().any(|ppid| *ppid == pid),
+            !self.recycled.iter().chain(self.stacked.iter()).any(|ppid| *ppid == pid),
             "pid {} has been deallocated!",
             pid
         );
-        self.recycled.push(pid);
+        // the stack may have failed to map
+        if self.stacked.len() < KERNEL_STACK_CACHE && kernel_stack_mapped(pid) {
+            self.stacked.push(pid);
+        } else {
+            unmap_kernel_stack(pid);
+            self.recycled.push(pid);
+        }
     }
 }
 
 lazy_static! {
     /// Pid allocator instance through lazy_static!
-    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
-        unsafe { UPSafeCell::new(PidAllocator::new()) };
+    static ref PID_ALLOCATOR: SpinLock<PidAllocator> =
+        SpinLock::new(PidAllocator::new());
 }
 
 /// Abstract structure of PID
@@ -63,6 +79,42 @@ pub fn pid_alloc() -> PidHandle {
     PID_ALLOCATOR.exclusive_access().alloc()
 }
 
+/// Number of pids held by live or unreaped processes
+pub fn pids_in_use() -> usize {
+    let allocator = PID_ALLOCATOR.exclusive_access();
+    allocator.current - allocator.recycled.len() - allocator.stacked.len()
+}
+
+/// Pages held by the kernel stacks kept for reuse
+pub fn cached_kernel_stack_pages() -> usize {
+    PID_ALLOCATOR.exclusive_access().stacked.len() * KERNEL_STACK_SIZE / PAGE_SIZE
+}
+
+/// Unmap the kernel stacks kept for reuse, when frames run short
+pub fn release_cached_kernel_stacks() {
+    let mut allocator = PID_ALLOCATOR.exclusive_access();
+    while let Some(pid) = allocator.stacked.pop() {
+        unmap_kernel_stack(pid);
+        allocator.recycled.push(pid);
+    }
+}
+
+fn kernel_stack_mapped(pid: usize) -> bool {
+    let (kernel_stack_bottom, _) = kernel_stack_position(pid);
+    let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+    KERNEL_SPACE
+        .exclusive_access()
+        .is_mapped(kernel_stack_bottom_va.into())
+}
+
+fn unmap_kernel_stack(pid: usize) {
+    let (kernel_stack_bottom, _) = kernel_stack_position(pid);
+    let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+    KERNEL_SPACE
+        .exclusive_access()
+        .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+}
+
 /// Return (bottom, top) of a kernel stack in kernel space.
 pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
@@ -70,21 +122,73 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     (bottom, top)
 }
 
+/// Kept in the lowest word of every kernel stack. A task running its stack
+/// down that far clobbers it, which the next trap or switch reports.
+const KERNEL_STACK_CANARY: usize = 0x6b73_7461_636b_2121;
+
+/// The pid whose kernel stack overflowed if `addr` lies in the unmapped
+/// page below a kernel stack
+pub fn kernel_stack_guard_owner(addr: usize) -> Option<usize> {
+    // kernel stacks live above the identity mapped physical memory
+    if addr < MEMORY_END || addr >= TRAMPOLINE {
+        return None;
+    }
+    let slot = KERNEL_STACK_SIZE + PAGE_SIZE;
+    let offset = TRAMPOLINE - 1 - addr;
+    (offset % slot >= KERNEL_STACK_SIZE).then(|| offset / slot)
+}
+
 /// KernelStack corresponding to PID
+///
+/// It is unmapped, or kept for reuse, when the pid is recycled rather than
+/// when it is dropped, so a pid is never handed out with its stack half torn
+/// down.
 pub struct KernelStack {
     pid: usize,
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    /// Map the kernel stack of `pid_handle`, unless it was kept from the
+    /// previous process with that pid. None if frames run out.
+    pub fn new(pid_handle: &PidHandle) -> Option<Self> {
         let pid = pid_handle.0;
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
-            kernel_stack_bottom.into(),
-            kernel_stack_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid: pid_handle.0 }
+        if !kernel_stack_mapped(pid) {
+            let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
+            let mut kernel_space = KERNEL_SPACE.exclusive_access();
+            // an overflow must fault in the gap rather than run into the
+            // stack of pid + 1
+            let guard: VirtAddr = (kernel_stack_bottom - PAGE_SIZE).into();
+            assert!(
+                !kernel_space.is_mapped(guard.into()),
+                "guard page below the kernel stack of pid {} is mapped",
+                pid
+            );
+            if !kernel_space.insert_framed_area(
+                kernel_stack_bottom.into(),
+                kernel_stack_top.into(),
+                MapPermission::R | MapPermission::W,
+            ) {
+                return None;
+            }
+        }
+        let (kernel_stack_bottom, _) = kernel_stack_position(pid);
+        unsafe {
+            // the page may have been mapped elsewhere before
+            core::arch::asm!("sfence.vma {}, zero", in(reg) kernel_stack_bottom);
+            (kernel_stack_bottom as *mut usize).write_volatile(KERNEL_STACK_CANARY);
+        }
+        Some(KernelStack { pid: pid_handle.0 })
+    }
+    /// Panic if the canary at the bottom of the stack was overwritten
+    pub fn check_canary(&self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        let canary = unsafe { (kernel_stack_bottom as *const usize).read_volatile() };
+        if canary != KERNEL_STACK_CANARY {
+            panic!(
+                "kernel stack overflow in pid {}, canary at {:#x} is {:#x}!",
+                self.pid, kernel_stack_bottom, canary
+            );
+        }
     }
     #[allow(unused)]
     /// Push a variable of type T into the top of the KernelStack and return its raw pointer
@@ -104,13 +208,3 @@ impl KernelStack {
         kernel_stack_top
     }
 }
-
-impl Drop for KernelStack {
-    fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
-        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
-        KERNEL_SPACE
-            .exclusive_access()
-            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
-    }
-}
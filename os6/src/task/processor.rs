@@ -7,11 +7,19 @@
 
 use super::__switch;
 use super::{fetch_task, TaskStatus};
+use crate::timer::{check_timer, get_time_us, set_next_trigger};
+use crate::drivers::handle_external_interrupt;
 use super::{TaskContext, TaskControlBlock};
-use crate::sync::UPSafeCell;
+use super::{add_task, record_context_switch, record_timer_interrupt};
+use super::manager::switch_out_task;
+use super::watchdog_tick;
+use crate::config::MAX_HARTS;
+use crate::perf::{profile_start, record_switch, PerfStat, SwitchKind};
+use crate::sync::SpinLock;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
 use lazy_static::*;
+use riscv::register::sip;
 
 /// Processor management structure
 pub struct Processor {
@@ -19,6 +27,14 @@ pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     /// The basic control flow of each core, helping to select and switch process
     idle_task_cx: TaskContext,
+    /// The task that just switched back to the idle control flow, kept alive
+    /// until it is no longer running on its kernel stack
+    switched_out: Option<Arc<TaskControlBlock>>,
+    /// When the current task was dispatched, in microseconds
+    dispatched_us: usize,
+    /// While profiling, how the task that just switched back left, when it
+    /// called `__switch` and how long it ran, see [`record_switch`]
+    switch_profile: Option<(SwitchKind, usize, usize)>,
 }
 
 impl Processor {
@@ -26,6 +42,9 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            switched_out: None,
+            dispatched_us: 0,
+            switch_profile: None,
         }
     }
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
@@ -40,8 +59,27 @@ impl Processor {
 }
 
 lazy_static! {
-    /// PROCESSOR instance through lazy_static!
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One Processor per hart, indexed by hart id.
+    ///
+    /// Only the owning hart ever touches its entry, but an interrupt taken
+    /// while the kernel looks at it, like a tick waking a sleeper, must not
+    /// find it borrowed, so it is locked with interrupts off.
+    pub static ref PROCESSORS: [SpinLock<Processor>; MAX_HARTS] =
+        core::array::from_fn(|_| SpinLock::new(Processor::new()));
+}
+
+/// Id of the hart we are running on, kept in `tp` while in the kernel
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// The Processor of the current hart
+fn current_processor() -> &'static SpinLock<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 /// The main part of process execution and scheduling
@@ -50,33 +88,94 @@ lazy_static! {
 /// and switch the process through __switch
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = current_processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
-            task_inner.task_status = TaskStatus::Running;
+            task_inner.on_cpu = true;
+            if task_inner.start_time == 0 {
+                task_inner.start_time = get_time_us();
+            }
+            task_inner.mode_time = get_time_us();
+            task_inner.perf_mark = PerfStat::now();
             drop(task_inner);
             // release coming task TCB manually
             processor.current = Some(task);
+            processor.dispatched_us = get_time_us();
             // release processor manually
             drop(processor);
             unsafe {
+                // kernel stacks are remapped by whichever hart frees them,
+                // so never run a task through a stale translation
+                core::arch::asm!("sfence.vma");
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // The task has saved its context and left its kernel stack, so
+            // another hart may now pick it up. Exited tasks are dropped here,
+            // blocked ones are queued by whoever wakes them from now on.
+            let (prev, switch_profile) = {
+                let mut processor = current_processor().exclusive_access();
+                (processor.switched_out.take(), processor.switch_profile.take())
+            };
+            if let Some((kind, start, slice_us)) = switch_profile {
+                record_switch(kind, start, slice_us);
+            }
+            if let Some(task) = prev {
+                let ready = {
+                    let mut task_inner = task.inner_exclusive_access();
+                    task_inner.on_cpu = false;
+                    task_inner.task_status == TaskStatus::Ready
+                };
+                if ready {
+                    add_task(task);
+                }
+            }
+        } else {
+            // never sleep while holding the Processor or TASK_MANAGER
+            drop(processor);
+            wait_for_interrupt();
         }
     }
 }
 
+/// Sleep until an interrupt is pending on this hart.
+///
+/// Interrupts stay disabled: a pending one still ends `wfi` without being
+/// taken, so one arriving between the empty queue check and `wfi` is not lost.
+/// Tasks queued by another hart are picked up on the next tick at the latest.
+fn wait_for_interrupt() {
+    unsafe {
+        core::arch::asm!("wfi");
+    }
+    // no trap handler ran, acknowledge the tick or the device ourselves or
+    // `wfi` would return at once from now on
+    if sip::read().stimer() {
+        record_timer_interrupt();
+        set_next_trigger();
+        watchdog_tick(None, 0);
+        check_timer();
+    }
+    if sip::read().sext() {
+        handle_external_interrupt();
+    }
+}
+
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    current_processor().exclusive_access().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    current_processor().exclusive_access().current()
+}
+
+/// Get a copy of the current task, `None` as well if the processor is
+/// borrowed, for the panic handler
+pub fn try_current_task() -> Option<Arc<TaskControlBlock>> {
+    current_processor().try_exclusive_access()?.current()
 }
 
 /// Get token of the address space of current task
@@ -94,11 +193,29 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
-/// Return to idle control flow for new scheduling
-pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+/// Return to idle control flow for new scheduling.
+///
+/// `task` has been taken out of the Processor already; the idle control flow
+/// puts it back to the ready queue if it is still `Ready` once `__switch` has
+/// saved its context, so no other hart can resume it halfway. `kind` is
+/// only for the profile.
+pub fn schedule(
+    task: Arc<TaskControlBlock>,
+    switched_task_cx_ptr: *mut TaskContext,
+    kind: SwitchKind,
+) {
+    task.kernel_stack.check_canary();
+    task.inner_exclusive_access().account_perf();
+    switch_out_task(&task);
+    let mut processor = current_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    processor.switched_out = Some(task);
+    if let Some(start) = profile_start() {
+        let slice_us = get_time_us().saturating_sub(processor.dispatched_us);
+        processor.switch_profile = Some((kind, start, slice_us));
+    }
     drop(processor);
+    record_context_switch();
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
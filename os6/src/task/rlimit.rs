@@ -0,0 +1,60 @@
+//! Per-process resource limits
+//!
+//! Every process carries [`RLimits`], inherited across fork. They can be read
+//! and adjusted through `sys_prlimit`, but never beyond the hard caps in
+//! [`crate::config`].
+
+use crate::config::{
+    PAGE_SIZE, RLIMIT_CHILDREN_MAX, RLIMIT_FDS_MAX, RLIMIT_USER_PAGES_DEFAULT,
+    RLIMIT_USER_PAGES_MAX,
+};
+
+/// Number of live plus unreaped children
+pub const RLIMIT_NPROC: usize = 6;
+/// Number of open file descriptors
+pub const RLIMIT_NOFILE: usize = 7;
+/// Bytes of user memory, counted in whole pages
+pub const RLIMIT_AS: usize = 9;
+
+/// Resource limits of a process
+#[derive(Copy, Clone)]
+pub struct RLimits {
+    pub max_fds: usize,
+    pub max_children: usize,
+    pub max_user_pages: usize,
+}
+
+impl RLimits {
+    /// Limits of initproc
+    pub fn new() -> Self {
+        Self {
+            max_fds: RLIMIT_FDS_MAX,
+            max_children: RLIMIT_CHILDREN_MAX,
+            max_user_pages: RLIMIT_USER_PAGES_DEFAULT,
+        }
+    }
+    /// Current limit of `resource`, `None` if it is unknown
+    pub fn get(&self, resource: usize) -> Option<usize> {
+        match resource {
+            RLIMIT_NPROC => Some(self.max_children),
+            RLIMIT_NOFILE => Some(self.max_fds),
+            RLIMIT_AS => Some(self.max_user_pages * PAGE_SIZE),
+            _ => None,
+        }
+    }
+    /// Set the limit of `resource`, returns false if it is unknown or `value`
+    /// is above its hard cap
+    pub fn set(&mut self, resource: usize, value: usize) -> bool {
+        let (limit, cap, value) = match resource {
+            RLIMIT_NPROC => (&mut self.max_children, RLIMIT_CHILDREN_MAX, value),
+            RLIMIT_NOFILE => (&mut self.max_fds, RLIMIT_FDS_MAX, value),
+            RLIMIT_AS => (&mut self.max_user_pages, RLIMIT_USER_PAGES_MAX, value / PAGE_SIZE),
+            _ => return false,
+        };
+        if value > cap {
+            return false;
+        }
+        *limit = value;
+        true
+    }
+}
@@ -0,0 +1,35 @@
+//! Non-preemptive first-come first-served scheduling
+
+use super::{SchedPolicy, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A FIFO scheduler, a task runs until it yields, blocks or exits
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl SchedPolicy for FifoScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        let i = self.ready_queue.iter().position(|task| task.getpid() == pid)?;
+        self.ready_queue.remove(i)
+    }
+    fn reserve(&mut self, tasks: usize) -> bool {
+        let additional = tasks.saturating_sub(self.ready_queue.len());
+        self.ready_queue.try_reserve(additional).is_ok()
+    }
+    fn on_tick(&mut self, _current: &TaskControlBlock) -> bool {
+        false
+    }
+}
@@ -0,0 +1,48 @@
+//! Scheduling policies behind the [`SchedPolicy`] trait
+//!
+//! [`super::manager::TaskManager`] delegates queueing and preemption decisions
+//! to the policy picked at build time through a cargo feature:
+//! `sched-fifo`, `sched-rr` or `sched-stride` (the default).
+
+mod fifo;
+mod rr;
+mod stride;
+
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+
+pub use stride::Pass;
+
+/// A scheduling policy owning the ready queue
+pub trait SchedPolicy {
+    fn new() -> Self;
+    /// Put a ready task into the queue
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Pick the next task to run
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Take the task with `pid` out of the queue, if it is queued
+    fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>>;
+    /// Make room for `tasks` tasks so that `add` does not allocate, false
+    /// if the kernel heap is out of memory
+    fn reserve(&mut self, tasks: usize) -> bool;
+    /// Called when `task` stops running, whether it yields, blocks, is
+    /// preempted or exits, before it may be put back into the queue
+    fn on_switch_out(&mut self, _task: &TaskControlBlock) {}
+    /// Called on every timer tick while `current` is running,
+    /// returns whether `current` should be preempted
+    fn on_tick(&mut self, current: &TaskControlBlock) -> bool;
+}
+
+#[cfg(any(
+    all(feature = "sched-stride", feature = "sched-rr"),
+    all(feature = "sched-stride", feature = "sched-fifo"),
+    all(feature = "sched-rr", feature = "sched-fifo"),
+))]
+compile_error!("at most one of the features `sched-stride`, `sched-rr` and `sched-fifo` may be enabled");
+
+#[cfg(feature = "sched-fifo")]
+pub type SchedPolicyImpl = fifo::FifoScheduler;
+#[cfg(feature = "sched-rr")]
+pub type SchedPolicyImpl = rr::RoundRobinScheduler;
+#[cfg(not(any(feature = "sched-fifo", feature = "sched-rr")))]
+pub type SchedPolicyImpl = stride::StrideScheduler;
@@ -0,0 +1,46 @@
+//! Round-robin scheduling with a fixed time slice
+
+use super::{SchedPolicy, TaskControlBlock};
+use crate::config::{MAX_HARTS, RR_TIME_SLICE};
+use crate::task::hart_id;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A round-robin scheduler, a task is preempted after `RR_TIME_SLICE` ticks
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// ticks consumed by the task running on each hart in its current
+    /// slice, by hart id
+    slice_ticks: [usize; MAX_HARTS],
+}
+
+impl SchedPolicy for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            slice_ticks: [0; MAX_HARTS],
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        // the hart fetching is the one about to run it
+        let task = self.ready_queue.pop_front()?;
+        self.slice_ticks[hart_id()] = 0;
+        Some(task)
+    }
+    fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        let i = self.ready_queue.iter().position(|task| task.getpid() == pid)?;
+        self.ready_queue.remove(i)
+    }
+    fn reserve(&mut self, tasks: usize) -> bool {
+        let additional = tasks.saturating_sub(self.ready_queue.len());
+        self.ready_queue.try_reserve(additional).is_ok()
+    }
+    fn on_tick(&mut self, _current: &TaskControlBlock) -> bool {
+        let slice_ticks = &mut self.slice_ticks[hart_id()];
+        *slice_ticks += 1;
+        *slice_ticks >= RR_TIME_SLICE
+    }
+}
@@ -0,0 +1,221 @@
+//! Stride scheduling
+//!
+//! Every task carries a [`Pass`]; the ready task with the smallest pass runs
+//! next. When it stops running it is charged a stride of
+//! `BIG_STRIDE / priority`, scaled by the share of its time slice it used.
+
+use super::{SchedPolicy, TaskControlBlock};
+use crate::config::{
+    AGING_INTERVAL, BIG_STRIDE, MAX_PRIORITY, STRIDE_MIN_CHARGE_PERCENT, STRIDE_SLICE_US,
+    WAKEUP_BOOST, WAKEUP_BOOST_INTERVAL_US, WAKEUP_BOOST_PRIORITY_FACTOR,
+};
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// A stride scheduler with aging, preempting the running task on every tick
+pub struct StrideScheduler {
+    ready_queue: Vec<Arc<TaskControlBlock>>,
+    /// pass of the latest fetched task, i.e. the global minimum pass
+    min_pass: Pass,
+    /// timer ticks since the last aging round
+    ticks: usize,
+}
+
+impl SchedPolicy for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: Vec::new(),
+            min_pass: Pass::new(),
+            ticks: 0,
+        }
+    }
+    /// A task whose pass lags behind the global minimum (e.g. a newly created
+    /// one) starts from the minimum, so it can not monopolize the CPU. A task
+    /// just woken from a wait starts from it too when boosted, see
+    /// [`Pass::boost`].
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        {
+            let mut task_inner = task.inner_exclusive_access();
+            if core::mem::take(&mut task_inner.woken) && WAKEUP_BOOST {
+                task_inner.pass.boost(&self.min_pass, get_time_us());
+            }
+            task_inner.pass.raise_to(&self.min_pass);
+        }
+        self.ready_queue.push(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.ready_queue.is_empty() {
+            return None;
+        }
+        let mut min_i = 0;
+        let mut min_pass = self.ready_queue[0].inner_exclusive_access().pass;
+        for i in 0..self.ready_queue.len() {
+            let pass = self.ready_queue[i].inner_exclusive_access().pass;
+            if pass < min_pass {
+                min_i = i;
+                min_pass = pass;
+            }
+        }
+        self.min_pass = min_pass;
+        let task = self.ready_queue.swap_remove(min_i);
+        {
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.pass.dispatch(get_time_us());
+            info!(
+                "fetch task with PID {} '{}', pass {}",
+                task.pid.0,
+                task_inner.name,
+                task_inner.pass.value()
+            );
+        }
+        Some(task)
+    }
+    fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        let i = self.ready_queue.iter().position(|task| task.getpid() == pid)?;
+        Some(self.ready_queue.swap_remove(i))
+    }
+    fn reserve(&mut self, tasks: usize) -> bool {
+        let additional = tasks.saturating_sub(self.ready_queue.len());
+        self.ready_queue.try_reserve(additional).is_ok()
+    }
+    fn on_switch_out(&mut self, task: &TaskControlBlock) {
+        let mut task_inner = task.inner_exclusive_access();
+        let priority = task_inner.priority;
+        task_inner.pass.charge(priority, get_time_us());
+    }
+    /// Every `AGING_INTERVAL` ticks, move the pass of each task that has not
+    /// run since the previous round one stride closer to the global minimum.
+    fn on_tick(&mut self, _current: &TaskControlBlock) -> bool {
+        self.ticks += 1;
+        if self.ticks >= AGING_INTERVAL {
+            self.ticks = 0;
+            for task in self.ready_queue.iter() {
+                let mut task_inner = task.inner_exclusive_access();
+                let priority = task_inner.priority;
+                task_inner.pass.age(priority, &self.min_pass);
+            }
+        }
+        true
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Pass {
+    /// accumulated pass value
+    value: u64,
+    /// when the task was last dispatched, in microseconds
+    dispatch_time: usize,
+    /// whether the pass has been charged since the latest aging round
+    stepped: bool,
+    /// whether the next charge is at the boosted priority
+    boosted: bool,
+    /// when the pass was last boosted, in microseconds
+    boost_time: Option<usize>,
+}
+
+impl Pass {
+    pub fn new() -> Self {
+        Self {
+            value: 0,
+            dispatch_time: 0,
+            stepped: false,
+            boosted: false,
+            boost_time: None,
+        }
+    }
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+    /// A pass of `value`, to test the comparison near wraparound
+    #[cfg(feature = "ktest")]
+    pub fn with_value(value: u64) -> Self {
+        Self {
+            value,
+            ..Self::new()
+        }
+    }
+    fn stride_of(priority: isize) -> u64 {
+        match BIG_STRIDE as u64 / priority as u64 {
+            0 => 1,
+            o => o,
+        }
+    }
+    /// Start a run at `now` microseconds
+    pub fn dispatch(&mut self, now: usize) {
+        self.dispatch_time = now;
+    }
+    /// Charge the run started by the latest dispatch and ending at `now`.
+    ///
+    /// A task yielding right away pays `STRIDE_MIN_CHARGE_PERCENT` of its
+    /// stride rather than all of it, so it is not starved by tasks using
+    /// their whole slice. The charge never exceeds one stride, which keeps
+    /// passes within `BIG_STRIDE / 2` of each other for the comparison.
+    pub fn charge(&mut self, priority: isize, now: usize) {
+        let min_used = STRIDE_SLICE_US * STRIDE_MIN_CHARGE_PERCENT / 100;
+        let used = now
+            .saturating_sub(self.dispatch_time)
+            .clamp(min_used, STRIDE_SLICE_US);
+        let priority = if core::mem::take(&mut self.boosted) {
+            priority.saturating_mul(WAKEUP_BOOST_PRIORITY_FACTOR).min(MAX_PRIORITY)
+        } else {
+            priority
+        };
+        let charge = Self::stride_of(priority) * used as u64 / STRIDE_SLICE_US as u64;
+        self.value = self.value.wrapping_add(charge.max(1));
+        self.stepped = true;
+    }
+    /// Boost a task woken at `now`: lower the pass to `floor`, the global
+    /// minimum, if it is ahead of it, and charge the next run at
+    /// `WAKEUP_BOOST_PRIORITY_FACTOR` times the priority. At most once every
+    /// `WAKEUP_BOOST_INTERVAL_US`, returns whether it was boosted.
+    pub fn boost(&mut self, floor: &Pass, now: usize) -> bool {
+        if let Some(boost_time) = self.boost_time {
+            if now.saturating_sub(boost_time) < WAKEUP_BOOST_INTERVAL_US {
+                return false;
+            }
+        }
+        self.boost_time = Some(now);
+        if *floor < *self {
+            self.value = floor.value;
+        }
+        self.boosted = true;
+        true
+    }
+    /// Raise the pass to `floor` if it is behind it
+    pub fn raise_to(&mut self, floor: &Pass) {
+        if *self < *floor {
+            self.value = floor.value;
+        }
+    }
+    /// One aging round: a pass that has not been stepped since the previous
+    /// round moves back by one stride, but never below `floor`.
+    pub fn age(&mut self, priority: isize, floor: &Pass) {
+        if self.stepped {
+            self.stepped = false;
+            return;
+        }
+        self.value = self.value.wrapping_sub(Self::stride_of(priority));
+        self.raise_to(floor);
+    }
+}
+
+impl PartialOrd for Pass {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let overflow = self.value.abs_diff(other.value) > BIG_STRIDE/2;
+        let order = self.value <= other.value;
+        if order ^ overflow {
+            Some(Ordering::Less)
+        }
+        else {
+            Some(Ordering::Greater)
+        }
+    }
+}
+
+impl PartialEq for Pass {
+    fn eq(&self, _other: &Self) -> bool {
+        false
+    }
+}
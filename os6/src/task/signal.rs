@@ -0,0 +1,97 @@
+//! Signals: pending flags, per-process actions and delivery
+//!
+//! A signal is only recorded as pending when it is sent. It takes effect on
+//! the next return of the target to user mode, see [`super::handle_signals`].
+
+use bitflags::*;
+
+/// Largest supported signal number
+pub const MAX_SIG: usize = 31;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+
+/// Action: terminate the process, dumping core for the signals listed in
+/// [`dumps_core_by_default`], or ignore for those in [`ignored_by_default`]
+pub const SIG_DFL: usize = 0;
+/// Action: discard the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, bit `n` stands for signal number `n`
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << SIGHUP;
+        const SIGINT = 1 << SIGINT;
+        const SIGQUIT = 1 << SIGQUIT;
+        const SIGILL = 1 << SIGILL;
+        const SIGKILL = 1 << SIGKILL;
+        const SIGSEGV = 1 << SIGSEGV;
+        const SIGPIPE = 1 << SIGPIPE;
+        const SIGALRM = 1 << SIGALRM;
+        const SIGTERM = 1 << SIGTERM;
+        const SIGCHLD = 1 << SIGCHLD;
+    }
+}
+
+impl SignalFlags {
+    /// The set holding just `signum`, `None` if it is not a supported signal
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            return None;
+        }
+        Self::from_bits(1 << signum)
+    }
+    /// The lowest numbered signal in the set
+    pub fn first_signum(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize)
+        }
+    }
+}
+
+/// Whether the default action of `signum` is to ignore it
+pub fn ignored_by_default(signum: usize) -> bool {
+    signum == SIGCHLD
+}
+
+/// Whether the default action of `signum` also writes a core file
+pub fn dumps_core_by_default(signum: usize) -> bool {
+    signum == SIGQUIT
+}
+
+/// The fault behind a SIGSEGV or SIGILL, like Linux's `siginfo_t`
+#[derive(Clone, Copy, Debug)]
+pub struct SigInfo {
+    pub signum: usize,
+    /// The address the access faulted on, or of the faulting instruction
+    /// for SIGILL. A handler gets it in `a1`
+    pub addr: usize,
+    /// `sepc` of the fault
+    pub pc: usize,
+    /// `scause` of the fault
+    pub scause: usize,
+}
+
+/// Handlers installed by `sys_sigaction`, indexed by signal number
+#[derive(Clone, Copy)]
+pub struct SignalActions {
+    pub table: [usize; MAX_SIG + 1],
+}
+
+impl SignalActions {
+    pub fn new() -> Self {
+        Self {
+            table: [SIG_DFL; MAX_SIG + 1],
+        }
+    }
+}
@@ -1,18 +1,35 @@
 //! Types related to task management & Functions for completely changing TCB
 
-use super::TaskContext;
-use super::manager::Pass;
+use super::{SchedStat, TaskContext};
+use super::sched::Pass;
+use super::manager::{insert_into_pid2task, reserve_ready_queue};
 use super::{pid_alloc, KernelStack, PidHandle};
-use crate::config::{TRAP_CONTEXT, MAX_SYSCALL_NUM};
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use super::pid::pids_in_use;
+use super::fd_table::FdTable;
+use super::io::IoAccounting;
+use super::rlimit::RLimits;
+use super::{frames_available_reclaiming, shutting_down, swap_until_available};
+use super::signal::{SigInfo, SignalActions, SignalFlags};
+use crate::config::{
+    KERNEL_STACK_SIZE, MAX_SYSCALL_NUM, MAX_TASKS, MAX_TASK_NAME_LEN, PAGE_SIZE, USER_STACK_SIZE,
+};
+use crate::mm::{LayoutOffsets, LoadError, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::perf::PerfStat;
+use crate::sync::{SpinLock, SpinLockGuard};
+use crate::timer::{IntervalTimer, TimerHandle};
 use crate::trap::{trap_handler, TrapContext};
+use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use core::cell::RefMut;
-use crate::fs::{File, Stdin, Stdout};
+use core::cell::UnsafeCell;
+use core::panic::Location;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::fs::{FdSlot, File, Stdin, Stdout};
+use alloc::format;
 use alloc::string::String;
 use crate::mm::translated_refmut;
+use crate::syscall::errno::{EAGAIN, ENOMEM};
+use easy_fs::Inode;
 
 /// Task control block structure
 ///
@@ -24,16 +41,21 @@ pub struct TaskControlBlock {
     /// Kernel stack corresponding to PID
     pub kernel_stack: KernelStack,
     // mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    /// Process group, read without the lock by whoever signals a group
+    pgid: AtomicUsize,
+    /// Bytes read and written and block device requests, bumped without
+    /// the lock
+    pub io: IoAccounting,
+    inner: SpinLock<TaskControlBlockInner>,
 }
 
 /// Structure containing more process content
 ///
 /// Store the contents that will change during operation
-/// and are wrapped by UPSafeCell to provide mutual exclusion
+/// and are wrapped by SpinLock to provide mutual exclusion
 pub struct TaskControlBlockInner {
-    /// The physical page number of the frame where the trap context is placed
-    pub trap_cx_ppn: PhysPageNum,
+    /// Where the trap context is placed
+    pub trap_cx: TrapCxFrame,
     /// Application data can only appear in areas
     /// where the application address space is lower than base_size
     pub base_size: usize,
@@ -43,6 +65,13 @@ pub struct TaskControlBlockInner {
     pub task_status: TaskStatus,
     /// Application address space
     pub memory_set: MemorySet,
+    /// Start of the heap area, right above the user stack
+    pub heap_base: usize,
+    /// Program break, the end of the heap moved by `sys_brk` and `sys_sbrk`
+    pub heap_top: usize,
+    /// Random offsets of the stack, heap and shared memory base, chosen on
+    /// exec and kept across fork
+    pub layout: LayoutOffsets,
     /// Parent process of the current process.
     /// Weak will not affect the reference count of the parent
     pub parent: Option<Weak<TaskControlBlock>>,
@@ -50,13 +79,67 @@ pub struct TaskControlBlockInner {
     pub children: Vec<Arc<TaskControlBlock>>,
     /// It is set when active exit or execution error occurs
     pub exit_code: i32,
+    /// The signal that killed the task, `None` if it exited by itself
+    pub term_signal: Option<usize>,
+    /// Whether the task is running or still switching out on some hart
+    pub on_cpu: bool,
+    /// Switched out in the middle of a kernel operation, waiting for a disk
+    /// request or a lock, which it must finish, or in a wait SIGKILL cuts
+    /// short, which it must leave before it may be torn down
+    pub in_kernel_wait: bool,
+    /// Made ready by [`super::wakeup_task`] and not queued since, for the
+    /// scheduler to boost it when it is
+    pub woken: bool,
+    /// Signals sent but not delivered yet
+    pub signals: SignalFlags,
+    /// Handlers installed by `sys_sigaction`, inherited across fork
+    pub signal_actions: Box<SignalActions>,
+    /// The interrupted user context while a signal handler runs
+    pub trap_cx_backup: Option<Box<TrapContext>>,
+    /// The fault behind the pending SIGSEGV or SIGILL, see
+    /// [`super::catch_fault_signal`]
+    pub fault_info: Option<SigInfo>,
+    /// `ITIMER_REAL` set by `sys_setitimer`
+    pub itimer: Option<IntervalTimer>,
+    /// The timeout of the sleep or wait the task is blocked in, see
+    /// [`super::arm_wait_timeout`]
+    pub wait_timer: Option<TimerHandle>,
+    /// Name for logs, the path of the latest exec unless set by the task
+    pub name: String,
+    /// Resource limits, inherited across fork
+    pub rlimits: RLimits,
+    /// May change settings of the whole kernel like the log level. Inherited
+    /// across fork, given up for good by `sys_drop_privilege`
+    pub privileged: bool,
+    /// Scheduler statistics, see `sys_sched_stat`
+    pub sched_stat: SchedStat,
+    /// When the task was last put into the ready queue, in microseconds
+    pub enqueue_time: usize,
+    /// Where the task was put into the ready queue, while it is there
+    pub queued_at: Option<&'static Location<'static>>,
+    /// Microseconds spent in user mode, see `account_cpu_time`
+    pub utime_us: usize,
+    /// Microseconds spent in the kernel on behalf of the task
+    pub stime_us: usize,
+    /// User microseconds of the children waited for, including the ones
+    /// they waited for in turn
+    pub children_utime_us: usize,
+    /// Kernel microseconds of the same children
+    pub children_stime_us: usize,
+    /// When the task last entered or left user mode or was dispatched
+    pub mode_time: usize,
+    /// Cycles and instructions while running, see `account_perf`
+    pub perf: PerfStat,
+    /// The counters of the hart when the task was dispatched or last charged
+    pub perf_mark: PerfStat,
     /// stride scheduling priority
     pub priority: isize,
     /// stride scheduling pass
     pub pass: Pass,
-    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// Boxed, as it is by far the largest member
+    pub syscall_times: Box<[u32; MAX_SYSCALL_NUM]>,
     pub start_time: usize,
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    pub fd_table: FdTable,
 }
 
 /// Simple access to its internal fields
@@ -67,218 +150,484 @@ impl TaskControlBlockInner {
     }
     */
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
-        self.trap_cx_ppn.get_mut()
+        match &self.trap_cx {
+            TrapCxFrame::Mapped(ppn) => ppn.get_mut(),
+            // the box stays put until `map_trap_cx` replaces it, which only
+            // the task itself does once it runs
+            TrapCxFrame::Pending(trap_cx) => unsafe { &mut *trap_cx.get() },
+        }
+    }
+    /// Move a pending trap context to its frame at `TRAP_CONTEXT`, false if
+    /// frames run out
+    pub fn map_trap_cx(&mut self) -> bool {
+        if let TrapCxFrame::Pending(trap_cx) = &self.trap_cx {
+            let trap_cx_ppn = match self.memory_set.map_trap_context() {
+                Some(ppn) => ppn,
+                None => return false,
+            };
+            *trap_cx_ppn.get_mut() = unsafe { *trap_cx.get() };
+            self.trap_cx = TrapCxFrame::Mapped(trap_cx_ppn);
+        }
+        true
     }
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
+    /// Charge the counts since `perf_mark` to the task, which is running on
+    /// the current hart
+    pub fn account_perf(&mut self) {
+        let now = PerfStat::now();
+        self.perf += now.since(&self.perf_mark);
+        self.perf_mark = now;
+    }
+    /// Move from `from` to `to` and return true, or leave the status alone
+    /// and return false if it is not `from`
+    pub fn transition(&mut self, from: TaskStatus, to: TaskStatus) -> bool {
+        debug_assert!(from.may_become(to), "task status {:?} -> {:?}", from, to);
+        if self.task_status != from {
+            return false;
+        }
+        self.task_status = to;
+        true
+    }
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len())
-            .find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+    /// Move the program break to `heap_top`, failing if it is below the heap
+    /// base, runs into another area or exceeds `RLIMIT_AS`
+    pub fn set_program_break(&mut self, heap_top: usize) -> bool {
+        if heap_top < self.heap_base {
+            return false;
+        }
+        let old_end = VirtAddr::from(self.heap_top).ceil();
+        let new_end = VirtAddr::from(heap_top).ceil();
+        if new_end > old_end
+            && self.memory_set.mapped_pages() + (new_end.0 - old_end.0) > self.rlimits.max_user_pages
+        {
+            return false;
         }
+        if !self
+            .memory_set
+            .resize_area(VirtAddr::from(self.heap_base).floor(), new_end)
+        {
+            return false;
+        }
+        self.heap_top = heap_top;
+        true
+    }
+    /// Find a free fd, `None` if the table is full up to `max_fds`
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        self.alloc_fd_from(0)
+    }
+    /// The lowest free descriptor not below `min`
+    pub fn alloc_fd_from(&mut self, min: usize) -> Option<usize> {
+        self.fd_table.alloc_from(min, self.rlimits.max_fds)
+    }
+    /// The open file of descriptor `fd`
+    pub fn get_file(&self, fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+        self.fd_table.get(fd).map(|slot| slot.file.clone())
+    }
+    /// Whether a child with `user_pages` pages of user memory can be created
+    /// without exceeding the limits or running out of frames half way.
+    /// -EAGAIN if a process limit is reached, -ENOMEM if the memory limit is
+    /// or frames run out. A killed task gets no more children, see
+    /// `kill_descendants`.
+    fn can_create_child(&self, user_pages: usize) -> Result<(), isize> {
+        if self.signals.contains(SignalFlags::SIGKILL)
+            || self.children.len() >= self.rlimits.max_children
+            || pids_in_use() >= MAX_TASKS
+        {
+            return Err(-EAGAIN);
+        }
+        if user_pages > self.rlimits.max_user_pages
+            || !frames_available_reclaiming(user_pages + KERNEL_STACK_SIZE / PAGE_SIZE)
+        {
+            return Err(-ENOMEM);
+        }
+        Ok(())
     }
 }
 
 impl TaskControlBlock {
-    /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    /// Lock the TaskControlBlockInner
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
+    /// Lock the TaskControlBlockInner if no one holds it
+    pub fn try_inner_exclusive_access(&self) -> Option<SpinLockGuard<'_, TaskControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
 
     /// Create a new process
     ///
     /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+    pub fn new(elf_inode: &Arc<Inode>) -> Self {
+        // memory_set with elf program headers/trampoline/user stack
+        let (memory_set, user_sp, heap_base, entry_point, layout) =
+            MemorySet::from_elf(elf_inode).unwrap();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).unwrap();
         let kernel_stack_top = kernel_stack.get_top();
+        // the trap context gets its frame when the task first runs
+        let trap_cx = TrapCxFrame::pending(TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        ));
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
+            pgid: AtomicUsize::new(pid_handle.0),
+            io: IoAccounting::default(),
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    priority: 16,
-                    pass: Pass::new(),
-                    start_time: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    fd_table: alloc::vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx,
+                base_size: user_sp,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                memory_set,
+                heap_base,
+                heap_top: heap_base,
+                layout,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                term_signal: None,
+                on_cpu: false,
+                in_kernel_wait: false,
+                woken: false,
+                signals: SignalFlags::empty(),
+                signal_actions: Box::new(SignalActions::new()),
+                trap_cx_backup: None,
+                fault_info: None,
+                itimer: None,
+                wait_timer: None,
+                name: String::from("initproc"),
+                rlimits: RLimits::new(),
+                privileged: true,
+                sched_stat: SchedStat::default(),
+                enqueue_time: 0,
+                queued_at: None,
+                utime_us: 0,
+                stime_us: 0,
+                children_utime_us: 0,
+                children_stime_us: 0,
+                mode_time: 0,
+                perf: PerfStat::default(),
+                perf_mark: PerfStat::default(),
+                priority: 16,
+                pass: Pass::new(),
+                start_time: 0,
+                syscall_times: Box::new([0; MAX_SYSCALL_NUM]),
+                fd_table: FdTable::from_slots(alloc::vec![
+                    // 0 -> stdin
+                    FdSlot::new(Arc::new(Stdin::new())),
+                    // 1 -> stdout
+                    FdSlot::new(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    FdSlot::new(Arc::new(Stdout)),
+                ]),
+            }),
         };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
+        task_control_block
+    }
+    /// Load a new elf to replace the original application address space and start execution.
+    /// Fails, keeping the original address space, if the file cannot be
+    /// loaded or frames run out.
+    pub fn exec(&self, name: &str, elf_inode: &Arc<Inode>) -> Result<(), LoadError> {
+        self.load(name, elf_inode, true)
+    }
+    /// [`Self::exec`], mapping the trap context right away if `map_trap_cx`,
+    /// or else when the task first runs, see
+    /// [`TaskControlBlockInner::map_trap_cx`]
+    fn load(&self, name: &str, elf_inode: &Arc<Inode>, map_trap_cx: bool) -> Result<(), LoadError> {
+        // memory_set with elf program headers/trampoline/user stack
+        let (mut memory_set, user_sp, heap_base, entry_point, layout) =
+            MemorySet::from_elf(elf_inode)?;
+        let trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
             KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
+            self.kernel_stack.get_top(),
             trap_handler as usize,
         );
-        task_control_block
-    }
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+        let trap_cx = if map_trap_cx {
+            let trap_cx_ppn = memory_set.map_trap_context().ok_or(LoadError::NoMemory)?;
+            *trap_cx_ppn.get_mut() = trap_cx;
+            TrapCxFrame::Mapped(trap_cx_ppn)
+        } else {
+            TrapCxFrame::pending(trap_cx)
+        };
         // **** access inner exclusively
         let mut inner = self.inner_exclusive_access();
         // substitute memory_set
         inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize trap_cx
-        let trap_cx = inner.get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        // **** release inner automatically
+        inner.heap_base = heap_base;
+        inner.heap_top = heap_base;
+        inner.layout = layout;
+        // update trap_cx
+        inner.trap_cx = trap_cx;
+        inner.name = task_name(name);
+        // handlers of the old image are gone
+        inner.signal_actions = Box::new(SignalActions::new());
+        inner.trap_cx_backup = None;
+        inner.fault_info = None;
+        // the new image is in place, close what it is not to see
+        let closed = inner.fd_table.remove_cloexec();
+        // **** release inner before closing, a pipe end wakes its peers
+        drop(inner);
+        drop(closed);
+        Ok(())
     }
     /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    /// Fails with -EAGAIN if a process limit would be exceeded or the system
+    /// is shutting down, or with -ENOMEM if frames run out, with everything
+    /// built so far given back
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Result<Arc<TaskControlBlock>, isize> {
+        if shutting_down() {
+            return Err(-EAGAIN);
+        }
+        // the parent's own pages may only be swapped out to make room for
+        // the copy before its lock is taken
+        let user_pages = self.inner_exclusive_access().memory_set.user_pages();
+        swap_until_available(user_pages + KERNEL_STACK_SIZE / PAGE_SIZE, true);
+        // not under our lock, the scheduler locks tasks holding its own
+        if !reserve_ready_queue() {
+            return Err(-EAGAIN);
+        }
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
+        parent_inner.can_create_child(parent_inner.memory_set.user_pages())?;
+        // copy user space, the trap context gets its frame when the child
+        // first runs
+        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set).ok_or(-ENOMEM)?;
+        let trap_cx = TrapCxFrame::pending(*parent_inner.get_trap_cx());
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).ok_or(-ENOMEM)?;
         let kernel_stack_top = kernel_stack.get_top();
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        // clone all fds from parent to child
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
+        // clone the open fds from parent to child, the open files are shared
+        let new_fd_table = parent_inner.fd_table.clone();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    priority: parent_inner.priority,
-                    pass: parent_inner.pass,
-                    start_time: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    fd_table: new_fd_table,
-                })
-            },
+            pgid: AtomicUsize::new(self.getpgid()),
+            io: IoAccounting::default(),
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx,
+                base_size: parent_inner.base_size,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                memory_set,
+                heap_base: parent_inner.heap_base,
+                heap_top: parent_inner.heap_top,
+                layout: parent_inner.layout,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                term_signal: None,
+                on_cpu: false,
+                in_kernel_wait: false,
+                woken: false,
+                signals: SignalFlags::empty(),
+                signal_actions: parent_inner.signal_actions.clone(),
+                trap_cx_backup: None,
+                fault_info: None,
+                itimer: None,
+                wait_timer: None,
+                name: task_name(&format!("fork of {}", parent_inner.name)),
+                rlimits: parent_inner.rlimits,
+                privileged: parent_inner.privileged,
+                sched_stat: SchedStat::default(),
+                enqueue_time: 0,
+                queued_at: None,
+                utime_us: 0,
+                stime_us: 0,
+                children_utime_us: 0,
+                children_stime_us: 0,
+                mode_time: 0,
+                perf: PerfStat::default(),
+                perf_mark: PerfStat::default(),
+                priority: parent_inner.priority,
+                pass: parent_inner.pass,
+                start_time: 0,
+                syscall_times: Box::new([0; MAX_SYSCALL_NUM]),
+                fd_table: new_fd_table,
+            }),
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
+        insert_into_pid2task(task_control_block.getpid(), task_control_block.clone());
         // modify kernel_sp in trap_cx
         // **** access children PCB exclusively
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Ok(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
     
     // spawn a child process
-    pub fn spawn(self: &Arc<TaskControlBlock>, elf_data: &[u8]) -> Arc<TaskControlBlock> {
+    /// Fails if any resource limit would be exceeded or the system is
+    /// shutting down, or if `elf_inode` cannot be loaded or frames run out
+    pub fn spawn(
+        self: &Arc<TaskControlBlock>,
+        name: &str,
+        elf_inode: &Arc<Inode>,
+    ) -> Result<Arc<TaskControlBlock>, SpawnError> {
+        if shutting_down() || !reserve_ready_queue() {
+            return Err(SpawnError::Limit);
+        }
         let mut parent_inner = self.inner_exclusive_access();
+        // the image is paged in on demand, so only the stacks are needed up front
+        let user_pages = USER_STACK_SIZE / PAGE_SIZE + 2;
+        let no_memory = SpawnError::Load(LoadError::NoMemory);
+        parent_inner
+            .can_create_child(user_pages)
+            .map_err(|err| if err == -ENOMEM { no_memory } else { SpawnError::Limit })?;
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).ok_or(no_memory)?;
         let kernel_stack_top = kernel_stack.get_top();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn: PhysPageNum::from(0),
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    start_time: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    memory_set: MemorySet::new_bare(),
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    priority: parent_inner.priority,
-                    pass: parent_inner.pass,
-                    exit_code: 0,
-                    fd_table: alloc::vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                })
-            },
+            pgid: AtomicUsize::new(self.getpgid()),
+            io: IoAccounting::default(),
+            inner: SpinLock::new(TaskControlBlockInner {
+                // set by `load` below
+                trap_cx: TrapCxFrame::Mapped(PhysPageNum::from(0)),
+                base_size: parent_inner.base_size,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                start_time: 0,
+                syscall_times: Box::new([0; MAX_SYSCALL_NUM]),
+                memory_set: MemorySet::new_bare().ok_or(no_memory)?,
+                heap_base: 0,
+                heap_top: 0,
+                layout: LayoutOffsets::default(),
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                priority: parent_inner.priority,
+                pass: parent_inner.pass,
+                exit_code: 0,
+                term_signal: None,
+                on_cpu: false,
+                in_kernel_wait: false,
+                woken: false,
+                signals: SignalFlags::empty(),
+                signal_actions: Box::new(SignalActions::new()),
+                trap_cx_backup: None,
+                fault_info: None,
+                itimer: None,
+                wait_timer: None,
+                name: String::new(),
+                rlimits: parent_inner.rlimits,
+                privileged: parent_inner.privileged,
+                sched_stat: SchedStat::default(),
+                enqueue_time: 0,
+                queued_at: None,
+                utime_us: 0,
+                stime_us: 0,
+                children_utime_us: 0,
+                children_stime_us: 0,
+                mode_time: 0,
+                perf: PerfStat::default(),
+                perf_mark: PerfStat::default(),
+                // shared with the parent like after fork, exec below
+                // closes the `FD_CLOEXEC` ones
+                fd_table: parent_inner.fd_table.clone(),
+            }),
         });
+        task_control_block.load(name, elf_inode, false).map_err(SpawnError::Load)?;
         parent_inner.children.push(task_control_block.clone());
-        task_control_block.exec(elf_data);
-        task_control_block
+        insert_into_pid2task(task_control_block.getpid(), task_control_block.clone());
+        Ok(task_control_block)
     }
     
     pub fn getpid(&self) -> usize {
         self.pid.0
     }
+
+    /// The process group, inherited from the parent
+    pub fn getpgid(&self) -> usize {
+        self.pgid.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pgid(&self, pgid: usize) {
+        self.pgid.store(pgid, Ordering::Relaxed);
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.inner_exclusive_access().name = task_name(name);
+    }
+}
+
+/// `name` cut down to at most `MAX_TASK_NAME_LEN` bytes
+fn task_name(name: &str) -> String {
+    let mut end = name.len().min(MAX_TASK_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    String::from(&name[..end])
+}
+
+/// Where the trap context of a task is kept
+pub enum TrapCxFrame {
+    /// On the heap, for a task created by fork or spawn that has not run
+    /// yet, which so takes no frame for it while it waits in the ready queue
+    Pending(Box<UnsafeCell<TrapContext>>),
+    /// In the frame mapped at `TRAP_CONTEXT` in the task's address space
+    Mapped(PhysPageNum),
 }
 
-#[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+impl TrapCxFrame {
+    fn pending(trap_cx: TrapContext) -> Self {
+        Self::Pending(Box::new(UnsafeCell::new(trap_cx)))
+    }
+}
+
+/// Why [`TaskControlBlock::spawn`] created no child
+#[derive(Clone, Copy, Debug)]
+pub enum SpawnError {
+    /// A resource limit, or the system is shutting down
+    Limit,
+    /// The executable cannot be loaded, or frames run out
+    Load(LoadError),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// task status: UnInit, Ready, Running, Exited, Blocked
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
     Zombie,
+    /// Waiting for a wakeup, out of the ready queue
+    Blocked,
+}
+
+impl TaskStatus {
+    /// Whether a task may go from `self` to `next`. A Ready task may also be
+    /// on its hart still, woken before it finished blocking, and then goes
+    /// on running without passing through the ready queue.
+    pub fn may_become(self, next: TaskStatus) -> bool {
+        use TaskStatus::*;
+        matches!(
+            (self, next),
+            (UnInit, Ready)
+                | (Ready, Running)
+                | (Running, Ready)
+                | (Running, Blocked)
+                | (Blocked, Ready)
+                | (Blocked, Running)
+                | (Ready | Running | Blocked, Zombie)
+        )
+    }
 }
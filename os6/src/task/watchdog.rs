@@ -0,0 +1,107 @@
+//! Lockup detector driven by the timer interrupt
+//!
+//! Every hart notes when it handles a timer tick and which task it found
+//! running. A gap of more than [`LOCKUP_STALL_MS`] between two ticks means
+//! the hart ran with interrupts disabled all along, stuck in the kernel,
+//! and is reported at the first tick it takes again. A task found running
+//! for [`LOCKUP_TICKS`] ticks without being scheduled out is reported as
+//! well, and killed after [`LOCKUP_KILL_TICKS`] if that is set.
+//!
+//! Kernel work that may rightly go on for long without ticks, like waiting
+//! for the disk without being allowed to sleep, calls [`touch_watchdog`].
+
+use super::{hart_id, kill_task, SignalFlags, TaskControlBlock};
+use crate::config::{LOCKUP_KILL_TICKS, LOCKUP_STALL_MS, LOCKUP_TICKS, MAX_HARTS};
+use crate::console::ANSICON;
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_ms;
+use alloc::format;
+use alloc::string::String;
+use lazy_static::*;
+
+#[derive(Default)]
+struct Watch {
+    /// When the last tick was handled or the watchdog touched, 0 before the
+    /// first tick
+    last_ms: usize,
+    /// Pid and dispatch count of the task running at the last tick
+    running: Option<(usize, usize)>,
+    /// Ticks `running` has been found running since it was dispatched
+    ticks: usize,
+}
+
+lazy_static! {
+    /// One per hart, only touched by its own hart with interrupts off
+    static ref WATCHES: [UPSafeCell<Watch>; MAX_HARTS] =
+        core::array::from_fn(|_| unsafe { UPSafeCell::new(Watch::default()) });
+}
+
+/// Check for lockups on a timer tick, with `current` running at `sepc`, or
+/// on an idle hart
+pub fn watchdog_tick(current: Option<&TaskControlBlock>, sepc: usize) {
+    let now = get_time_ms();
+    let mut watch = WATCHES[hart_id()].exclusive_access();
+    if watch.last_ms != 0 && now - watch.last_ms > LOCKUP_STALL_MS {
+        let last = match watch.running {
+            Some((pid, _)) => format!("last in pid {}", pid),
+            None => String::from("last idle"),
+        };
+        println_colorized!(
+            "[kernel] lockup: hart {} took no timer interrupt for {} ms, {}, now at sepc {:#x}",
+            ANSICON::FgLightRed,
+            ANSICON::BgDefault,
+            hart_id(),
+            now - watch.last_ms,
+            last,
+            sepc
+        );
+    }
+    watch.last_ms = now;
+    let task = match current {
+        Some(task) => task,
+        None => {
+            watch.running = None;
+            return;
+        }
+    };
+    let pid = task.getpid();
+    let running = Some((pid, task.inner_exclusive_access().sched_stat.dispatches));
+    if watch.running == running {
+        watch.ticks += 1;
+    } else {
+        watch.running = running;
+        watch.ticks = 0;
+    }
+    let ticks = watch.ticks;
+    drop(watch);
+    if ticks == LOCKUP_TICKS {
+        println_colorized!(
+            "[kernel] lockup: pid {} '{}' has run for {} ticks without a switch, at sepc {:#x}",
+            ANSICON::FgLightRed,
+            ANSICON::BgDefault,
+            pid,
+            task.inner_exclusive_access().name,
+            ticks,
+            sepc
+        );
+    }
+    if LOCKUP_KILL_TICKS != 0 && ticks == LOCKUP_KILL_TICKS {
+        println_colorized!(
+            "[kernel] lockup: killing pid {} after {} ticks",
+            ANSICON::FgLightRed,
+            ANSICON::BgDefault,
+            pid,
+            ticks
+        );
+        kill_task(pid, SignalFlags::SIGKILL);
+    }
+}
+
+/// Tell the watchdog the current hart is making progress without ticks
+pub fn touch_watchdog() {
+    let now = get_time_ms();
+    let mut watch = WATCHES[hart_id()].exclusive_access();
+    if watch.last_ms != 0 {
+        watch.last_ms = now;
+    }
+}
@@ -2,22 +2,290 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::SpinLock;
+use crate::task::{alarm_expired, wakeup_task, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicI64, Ordering};
+use lazy_static::*;
 use riscv::register::time;
 
-const TICKS_PER_SEC: usize = 100;
+/// Timer interrupts a second, also the clock tick of `sys_times`
+pub const TICKS_PER_SEC: usize = 100;
+const MSEC_PER_SEC: usize = 1000;
 const MICRO_PER_SEC: usize = 1_000_000;
+const NANO_PER_SEC: usize = 1_000_000_000;
+
+/// Realtime clock minus monotonic clock, in nanoseconds
+static REALTIME_OFFSET_NS: AtomicI64 = AtomicI64::new(0);
 
 /// read the `mtime` register
 pub fn get_time() -> usize {
     time::read()
 }
 
+/// get current time in milliseconds
+pub fn get_time_ms() -> usize {
+    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+}
+
 /// get current time in microseconds
 pub fn get_time_us() -> usize {
     time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
+/// get nanoseconds since boot at the full resolution of `mtime`.
+///
+/// `ticks * NANO_PER_SEC` would overflow after about 25 minutes at
+/// `CLOCK_FREQ`, so whole seconds and the remaining ticks (less than
+/// `CLOCK_FREQ`, hence at most ~2^54 once scaled) are converted apart.
+pub fn get_time_ns() -> usize {
+    let ticks = time::read();
+    ticks / CLOCK_FREQ * NANO_PER_SEC + ticks % CLOCK_FREQ * NANO_PER_SEC / CLOCK_FREQ
+}
+
+/// get the realtime clock in nanoseconds since the Unix epoch
+pub fn get_realtime_ns() -> i64 {
+    get_time_ns() as i64 + REALTIME_OFFSET_NS.load(Ordering::Relaxed)
+}
+
+/// set the realtime clock to `ns` nanoseconds since the Unix epoch
+pub fn set_realtime_ns(ns: i64) {
+    REALTIME_OFFSET_NS.store(ns - get_time_ns() as i64, Ordering::Relaxed);
+}
+
 /// set the next timer interrupt
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// What happens when a timer expires
+pub enum TimerCallback {
+    /// Wake the task up from a sleep, or from a wait with a timeout
+    Wakeup(Arc<TaskControlBlock>),
+    /// Raise SIGALRM on the task, see `sys_setitimer`
+    Alarm(Arc<TaskControlBlock>),
+}
+
+/// `ITIMER_REAL` state of a process, `handle` is its timer in the wheel
+#[derive(Copy, Clone)]
+pub struct IntervalTimer {
+    pub expire_ms: usize,
+    /// Re-armed with this period on expiry, unless it is 0
+    pub interval_ms: usize,
+    pub handle: TimerHandle,
+}
+
+/// An armed timer, for cancelling it. A handle of a timer that has fired
+/// or was cancelled matches nothing, even once its entry is reused.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TimerHandle {
+    index: usize,
+    generation: usize,
+}
+
+impl TimerHandle {
+    /// Disarm the timer. True if it was still pending, and then it never
+    /// fires; false if it has fired, or is firing on another hart.
+    pub fn cancel(self) -> bool {
+        let callback = TIMERS.exclusive_access().cancel(self);
+        // the task may go with it, not under the wheel lock
+        callback.is_some()
+    }
+}
+
+/// Bits of a deadline each level of the wheel indexes its slots with
+const WHEEL_BITS: usize = 6;
+const WHEEL_SIZE: usize = 1 << WHEEL_BITS;
+const WHEEL_MASK: usize = WHEEL_SIZE - 1;
+/// Deadlines up to `WHEEL_SIZE` to the power of this many milliseconds
+/// away, about 12 days, have their own slot. Later ones wait in the last
+/// slot of the top level and are placed again each time it comes round.
+const WHEEL_LEVELS: usize = 5;
+
+/// A timer in the list of its slot, or a free entry without a callback
+struct TimerEntry {
+    expire_ms: usize,
+    callback: Option<TimerCallback>,
+    /// Bumped when the entry is freed, so old handles miss
+    generation: usize,
+    slot: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// A hierarchical timer wheel with a slot per millisecond on level 0 and
+/// slots `WHEEL_SIZE` times as long on each level above. A timer goes to
+/// the lowest level whose slots do not come round before its deadline,
+/// and moves down a level each time its slot does, until it fires from
+/// level 0. Arming and cancelling are O(1), as is a tick with nothing to
+/// fire.
+struct TimerWheel {
+    /// Every timer up to this millisecond has fired
+    current_ms: usize,
+    /// The first entry in each slot, level by level
+    heads: Vec<Option<usize>>,
+    entries: Vec<TimerEntry>,
+    free: Vec<usize>,
+    armed: usize,
+}
+
+impl TimerWheel {
+    fn new(now_ms: usize) -> Self {
+        Self {
+            current_ms: now_ms,
+            heads: vec![None; WHEEL_LEVELS * WHEEL_SIZE],
+            entries: Vec::new(),
+            free: Vec::new(),
+            armed: 0,
+        }
+    }
+
+    fn add(&mut self, expire_ms: usize, callback: TimerCallback) -> TimerHandle {
+        // the slot of now has been looked at already
+        let expire_ms = expire_ms.max(self.current_ms + 1);
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(TimerEntry {
+                    expire_ms: 0,
+                    callback: None,
+                    generation: 0,
+                    slot: 0,
+                    prev: None,
+                    next: None,
+                });
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.expire_ms = expire_ms;
+        entry.callback = Some(callback);
+        self.armed += 1;
+        self.place(index);
+        TimerHandle {
+            index,
+            generation: self.entries[index].generation,
+        }
+    }
+
+    fn cancel(&mut self, handle: TimerHandle) -> Option<TimerCallback> {
+        let entry = self.entries.get(handle.index)?;
+        if entry.generation != handle.generation || entry.callback.is_none() {
+            return None;
+        }
+        self.unlink(handle.index);
+        Some(self.release(handle.index))
+    }
+
+    /// Link entry `index` into the slot of its deadline, which is not
+    /// before `current_ms`
+    fn place(&mut self, index: usize) {
+        let expire_ms = self.entries[index].expire_ms;
+        let delta = expire_ms - self.current_ms;
+        let mut level = 0;
+        while level + 1 < WHEEL_LEVELS && delta >> (WHEEL_BITS * (level + 1)) != 0 {
+            level += 1;
+        }
+        let expire_ms = if delta >> (WHEEL_BITS * WHEEL_LEVELS) != 0 {
+            self.current_ms + (1 << (WHEEL_BITS * WHEEL_LEVELS)) - 1
+        } else {
+            expire_ms
+        };
+        let slot = level * WHEEL_SIZE + (expire_ms >> (WHEEL_BITS * level) & WHEEL_MASK);
+        let next = self.heads[slot];
+        if let Some(next) = next {
+            self.entries[next].prev = Some(index);
+        }
+        let entry = &mut self.entries[index];
+        entry.slot = slot;
+        entry.prev = None;
+        entry.next = next;
+        self.heads[slot] = Some(index);
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (slot, prev, next) = {
+            let entry = &self.entries[index];
+            (entry.slot, entry.prev, entry.next)
+        };
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None => self.heads[slot] = next,
+        }
+        if let Some(next) = next {
+            self.entries[next].prev = prev;
+        }
+    }
+
+    /// Free entry `index`, already unlinked, returning its callback
+    fn release(&mut self, index: usize) -> TimerCallback {
+        let entry = &mut self.entries[index];
+        entry.generation = entry.generation.wrapping_add(1);
+        self.free.push(index);
+        self.armed -= 1;
+        entry.callback.take().unwrap()
+    }
+
+    /// Move on to `now_ms`, taking out the timers that expire by then
+    fn advance(&mut self, now_ms: usize, expired: &mut Vec<(TimerHandle, TimerCallback)>) {
+        while self.current_ms < now_ms {
+            if self.armed == 0 {
+                self.current_ms = now_ms;
+                break;
+            }
+            self.current_ms += 1;
+            let now = self.current_ms;
+            // the slots coming round move down, from the top so that a
+            // timer can move down more than one level at once
+            for level in (1..WHEEL_LEVELS).rev() {
+                if now & ((1 << (WHEEL_BITS * level)) - 1) != 0 {
+                    continue;
+                }
+                let slot = level * WHEEL_SIZE + (now >> (WHEEL_BITS * level) & WHEEL_MASK);
+                let mut next = self.heads[slot].take();
+                while let Some(index) = next {
+                    next = self.entries[index].next;
+                    self.place(index);
+                }
+            }
+            let mut next = self.heads[now & WHEEL_MASK].take();
+            while let Some(index) = next {
+                next = self.entries[index].next;
+                let handle = TimerHandle {
+                    index,
+                    generation: self.entries[index].generation,
+                };
+                expired.push((handle, self.release(index)));
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// Pending deadlines of all tasks
+    static ref TIMERS: SpinLock<TimerWheel> = SpinLock::new(TimerWheel::new(get_time_ms()));
+}
+
+/// Arm a timer running `callback` once `expire_ms` has passed
+pub fn add_timer(expire_ms: usize, callback: TimerCallback) -> TimerHandle {
+    TIMERS.exclusive_access().add(expire_ms, callback)
+}
+
+/// Fire every timer whose deadline has passed, called on each tick.
+///
+/// The timers are taken out of the wheel under its lock, so a racing
+/// cancel either gets there first or finds them gone. The wheel is
+/// unlocked before the tasks are touched, as firing an alarm may arm the
+/// next one.
+pub fn check_timer() {
+    let mut expired = Vec::new();
+    TIMERS.exclusive_access().advance(get_time_ms(), &mut expired);
+    for (handle, callback) in expired {
+        match callback {
+            TimerCallback::Wakeup(task) => wakeup_task(task),
+            TimerCallback::Alarm(task) => alarm_expired(task, handle),
+        }
+    }
+}
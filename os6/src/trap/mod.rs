@@ -15,15 +15,22 @@
 mod context;
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::mm::{write_back_pages, MapPermission};
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next, update_syscall_times,
+    account_cpu_time, catch_fault_signal, current_task, current_trap_cx, current_user_token, dump_core,
+    exit_killed_and_run_next,
+    handle_page_fault, handle_signals, hart_id, kernel_stack_guard_owner, map_current_trap_cx,
+    scheduler_tick,
+    preempt_current_and_run_next, update_syscall_times, watchdog_tick, SigInfo, SIGILL, SIGSEGV,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
+use crate::drivers::handle_external_interrupt;
+use crate::fs::writeback_tick;
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sscratch, stval, stvec,
 };
 
 core::arch::global_asm!(include_str!("trap.S"));
@@ -33,8 +40,11 @@ pub fn init() {
 }
 
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kernel_trap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__kernel_trap as usize, TrapMode::Direct);
     }
 }
 
@@ -53,6 +63,8 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_task().unwrap().kernel_stack.check_canary();
+    account_cpu_time(true);
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -62,7 +74,7 @@ pub fn trap_handler() -> ! {
             cx.sepc += 4;
             update_syscall_times(cx.x[17]);
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]]);
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -73,23 +85,50 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            let access = match scause.cause() {
+                Trap::Exception(Exception::StoreFault)
+                | Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+                Trap::Exception(Exception::InstructionFault)
+                | Trap::Exception(Exception::InstructionPageFault) => MapPermission::X,
+                _ => MapPermission::R,
+            };
+            // pages of file mappings are brought in on the first access, a
+            // fault the task catches is handled once it returns to user mode
+            if !handle_page_fault(stval, access) {
+                let info = SigInfo {
+                    signum: SIGSEGV,
+                    addr: stval,
+                    pc: current_trap_cx().sepc,
+                    scause: scause.bits(),
+                };
+                if !catch_fault_signal(info) {
+                    fatal_fault(info, scause.cause(), stval);
+                }
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            let sepc = current_trap_cx().sepc;
+            let info = SigInfo {
+                signum: SIGILL,
+                addr: sepc,
+                pc: sepc,
+                scause: scause.bits(),
+            };
+            if !catch_fault_signal(info) {
+                fatal_fault(info, scause.cause(), stval);
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            watchdog_tick(Some(&current_task().unwrap()), current_trap_cx().sepc);
+            check_timer();
+            writeback_tick();
+            if scheduler_tick(&current_task().unwrap()) {
+                preempt_current_and_run_next();
+            }
         }
         _ => {
             panic!(
@@ -102,10 +141,52 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// Log a fault the current task does not catch, dump its core and let it
+/// exit with `-signum`, as if killed by the signal
+fn fatal_fault(info: SigInfo, cause: Trap, stval: usize) {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let name = inner.name.clone();
+    let overflow = info.signum == SIGSEGV && inner.memory_set.is_stack_guard(info.addr.into());
+    drop(inner);
+    if overflow {
+        println!(
+            "[kernel] user stack overflow in '{}' (pid {}), bad addr = {:#x}, sepc = {:#x}, core dumped.",
+            name,
+            task.getpid(),
+            info.addr,
+            info.pc,
+        );
+    } else {
+        println!(
+            "[kernel] {:?} in '{}' (pid {}), stval = {:#x}, sepc = {:#x}, core dumped.",
+            cause,
+            name,
+            task.getpid(),
+            stval,
+            info.pc,
+        );
+    }
+    dump_core(&task, info.signum, info.scause, stval);
+    drop(task);
+    exit_killed_and_run_next(info.signum);
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // pages of shared file mappings torn down under a TCB lock, by an exit
+    // or exec say, are written back where the disk may be waited for
+    write_back_pages();
+    // deliver pending signals, a killed task never gets back to user mode
+    handle_signals();
+    // a task created by fork or spawn gets the frame only now, one killed
+    // before it ever ran never takes it
+    map_current_trap_cx();
+    account_cpu_time(false);
     set_user_trap_entry();
     let trap_cx_ptr = TRAP_CONTEXT;
+    // the task may come back on another hart than the one it trapped on
+    current_trap_cx().kernel_tp = hart_id();
     let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
@@ -124,9 +205,33 @@ pub fn trap_return() -> ! {
     }
 }
 
+/// Entered from `__kernel_trap` on the hart's trap stack, with the
+/// interrupted kernel sp in sscratch
 #[no_mangle]
 pub fn trap_from_kernel() -> ! {
-    panic!("a trap {:?} from kernel!", scause::read().cause());
+    let scause = scause::read();
+    let stval = stval::read();
+    if let Trap::Exception(
+        Exception::StoreFault
+        | Exception::StorePageFault
+        | Exception::LoadFault
+        | Exception::LoadPageFault,
+    ) = scause.cause()
+    {
+        if let Some(pid) = kernel_stack_guard_owner(stval) {
+            panic!(
+                "kernel stack overflow in pid {}, bad addr = {:#x}, sp = {:#x}!",
+                pid,
+                stval,
+                sscratch::read()
+            );
+        }
+    }
+    panic!(
+        "a trap {:?} from kernel, stval = {:#x}!",
+        scause.cause(),
+        stval
+    );
 }
 
 pub use context::TrapContext;
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{clock_gettime, clock_settime, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME, EINVAL};
+
+/// 连续 10000 次读取单调时钟不应倒退，并测试实时时钟的设置。
+/// 正确输出：Test clock_gettime OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut prev = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut prev), 0);
+    let first = prev;
+    for _ in 0..10000 {
+        let mut now = TimeSpec::default();
+        assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut now), 0);
+        assert!(now.nsec < 1_000_000_000);
+        assert!(now >= prev);
+        prev = now;
+    }
+    // 时钟在推进
+    assert!(prev > first);
+    assert_eq!(clock_gettime(42, &mut prev), -EINVAL);
+    // 单调时钟不能被设置
+    assert_eq!(clock_settime(CLOCK_MONOTONIC, &first), -EINVAL);
+    let epoch = TimeSpec { sec: 1_700_000_000, nsec: 0 };
+    assert_eq!(clock_settime(CLOCK_REALTIME, &epoch), 0);
+    let mut real = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut real), 0);
+    assert!(real >= epoch && real.sec < epoch.sec + 10);
+    let bad = TimeSpec { sec: 0, nsec: 1_000_000_000 };
+    assert_eq!(clock_settime(CLOCK_REALTIME, &bad), -EINVAL);
+    println!("Test clock_gettime OK!");
+    0
+}
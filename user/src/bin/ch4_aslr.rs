@@ -0,0 +1,110 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    brk, exit, fork, get_layout, shmat, shmdt, shmget, spawn, waitpid, AddressLayout,
+    IPC_PRIVATE,
+};
+
+/*
+理想结果：输出 Test 04_20 aslr OK!
+get_layout 报告的栈顶、堆起点与共享内存起点与实际地址一致，偏移按页对齐且不超出窗口；
+fork 出的子进程布局与父进程相同。内核启用 aslr 特性时，每次 exec 得到不同的布局；
+未启用时偏移全为 0，每次布局都相同。
+*/
+
+const KEY: usize = 0x4153;
+const PAGE: usize = 4096;
+const SHM_BASE: usize = 0x6000_0000;
+const STACK_LIMIT: usize = 0x10_0000;
+const STACK_WINDOW: usize = 0x100_0000;
+const HEAP_WINDOW: usize = 0x100_0000;
+const MMAP_WINDOW: usize = 0x1000_0000;
+const CHILDREN: usize = 4;
+
+fn layout() -> AddressLayout {
+    let mut layout = AddressLayout::default();
+    assert_eq!(get_layout(&mut layout), 0);
+    layout
+}
+
+/// 检查布局与本进程的实际地址一致
+fn check(layout: &AddressLayout) {
+    let offsets = &layout.offsets;
+    assert!(offsets.stack % PAGE == 0 && offsets.stack < STACK_WINDOW);
+    assert!(offsets.heap % PAGE == 0 && offsets.heap < HEAP_WINDOW);
+    assert!(offsets.mmap % PAGE == 0 && offsets.mmap < MMAP_WINDOW);
+    let local = 0u8;
+    let sp = &local as *const u8 as usize;
+    assert!(sp < layout.stack_top && sp >= layout.stack_top - STACK_LIMIT);
+    assert_eq!(layout.heap_base, layout.stack_top + offsets.heap);
+    assert!(brk(0) as usize >= layout.heap_base);
+    assert_eq!(layout.mmap_base, SHM_BASE + offsets.mmap);
+}
+
+/// 共享内存：每个子进程的布局
+fn shared(addr: usize) -> &'static mut [AddressLayout] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut AddressLayout, CHILDREN + 1) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let me = layout();
+    check(&me);
+    // 父进程创建的段存在时，说明本进程是 spawn 出的子进程
+    let id = shmget(KEY, 0);
+    if id >= 0 {
+        let addr = shmat(id as usize) as usize;
+        assert!(addr > 0);
+        let data = shared(addr);
+        let n = data[CHILDREN].stack_top;
+        data[n] = me;
+        data[CHILDREN].stack_top = n + 1;
+        assert_eq!(shmdt(addr), 0);
+        exit(0);
+    }
+
+    // 第一个共享内存段从 mmap_base 开始
+    let private = shmget(IPC_PRIVATE, PAGE);
+    assert!(private >= 0);
+    let addr = shmat(private as usize) as usize;
+    assert_eq!(addr, me.mmap_base);
+    assert_eq!(shmdt(addr), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        exit(if layout() == me { 0 } else { 1 });
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    let id = shmget(KEY, PAGE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize) as usize;
+    assert!(addr > 0);
+    let data = shared(addr);
+    for _ in 0..CHILDREN {
+        let pid = spawn("ch4_aslr\0");
+        assert!(pid > 0);
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(data[CHILDREN].stack_top, CHILDREN);
+    let children = &data[..CHILDREN];
+    let enabled = children.iter().any(|child| child.offsets != me.offsets)
+        || me.offsets != Default::default();
+    if enabled {
+        // 每次 exec 重新选择偏移，几乎不可能全部相同
+        assert!(children.iter().any(|child| *child != children[0]));
+    } else {
+        assert!(children.iter().all(|child| *child == me));
+    }
+    println!("aslr {}", if enabled { "enabled" } else { "disabled" });
+    assert_eq!(shmdt(addr), 0);
+    println!("Test 04_20 aslr OK!");
+    0
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, mmap, munmap, open, read, syscall, syscall6, waitpid, OpenFlags, Stat,
+    EFAULT, SYSCALL_EXEC, SYSCALL_FSTAT, SYSCALL_GETTIMEOFDAY, SYSCALL_GET_NAME, SYSCALL_OPENAT,
+    SYSCALL_READ, SYSCALL_SPAWN, SYSCALL_TASK_INFO, SYSCALL_WAIT4, SYSCALL_WRITE,
+};
+
+/*
+理想结果：输出 Test 04_17 efault OK!
+向系统调用传入空指针、内核地址、超出用户地址空间的地址、跨到未映射页的缓冲区，
+以及作为读缓冲区的只读页，系统调用都返回 -14（EFAULT），进程继续运行，内核不会 panic。
+失败的 write 不写入任何数据，失败的 waitpid 不回收子进程。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const FILE: &str = "efault_tmp\0";
+
+/// 各种不可访问的地址：空指针、内核代码、用户地址空间之外、加上长度后溢出
+const BAD: [usize; 4] = [0, 0x80200000, 1 << 40, usize::MAX - 7];
+
+#[no_mangle]
+fn main() -> i32 {
+    for bad in BAD {
+        assert_eq!(syscall(SYSCALL_WRITE, [1, bad, 8]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_GETTIMEOFDAY, [bad, 0, 0]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_TASK_INFO, [bad, 0, 0]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_GET_NAME, [bad, 16, 0]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_OPENAT, [0, bad, 0]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_EXEC, [bad, 0, 0]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_SPAWN, [bad, 0, 0]), -EFAULT);
+    }
+
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    for bad in BAD {
+        assert_eq!(syscall(SYSCALL_READ, [fd, bad, 8]), -EFAULT);
+        assert_eq!(syscall(SYSCALL_FSTAT, [fd, bad, 0]), -EFAULT);
+    }
+
+    // 只映射一页，缓冲区的后半部分落在未映射的页上
+    assert_eq!(mmap(START, PAGE, 3), 0);
+    let straddle = START + PAGE - 4;
+    assert_eq!(syscall(SYSCALL_WRITE, [fd, straddle, 8]), -EFAULT);
+    assert_eq!(syscall(SYSCALL_READ, [fd, straddle, 8]), -EFAULT);
+    assert_eq!(syscall(SYSCALL_GETTIMEOFDAY, [START + PAGE - 8, 0, 0]), -EFAULT);
+    assert_eq!(syscall(SYSCALL_FSTAT, [fd, START + PAGE - 8, 0]), -EFAULT);
+    // 整个缓冲区都在映射内时可以正常写入
+    assert_eq!(syscall(SYSCALL_WRITE, [fd, straddle, 4]), 4);
+    assert_eq!(munmap(START, PAGE), 0);
+
+    // 只读页不能作为 read 的缓冲区，但可以作为 write 的来源
+    assert_eq!(mmap(START, PAGE, 1), 0);
+    assert_eq!(syscall(SYSCALL_READ, [fd, START, 8]), -EFAULT);
+    assert_eq!(syscall(SYSCALL_WRITE, [fd, START, 4]), 4);
+    assert_eq!(munmap(START, PAGE), 0);
+    close(fd);
+
+    // 失败的 write 没有写入任何数据：文件中只有两次成功写入的 8 个 0
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0xffu8; 16];
+    assert_eq!(read(fd as usize, &mut buf), 8);
+    assert!(buf[..8].iter().all(|b| *b == 0));
+    let stat = Stat::new();
+    assert_eq!(syscall(SYSCALL_FSTAT, [fd as usize, &stat as *const _ as usize, 0]), 0);
+    close(fd as usize);
+
+    // waitpid 的指针无效时子进程不会被回收
+    let pid = fork();
+    if pid == 0 {
+        exit(3);
+    }
+    assert!(pid > 0);
+    for bad in BAD {
+        assert_eq!(syscall6(SYSCALL_WAIT4, [pid as usize, bad, 0, 0, 0, 0]), -EFAULT);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+    println!("Test 04_17 efault OK!");
+    0
+}
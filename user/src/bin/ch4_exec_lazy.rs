@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, free_frames, get_time, shmat, shmdt, shmget, spawn, waitpid};
+
+/*
+理想结果：输出 Test 04_15 exec lazy OK!
+程序含 1 MiB 的只读数据，spawn 自身时内核只读取 ELF 头，数据段在访问时才从文件读入，
+子进程刚开始运行时占用的物理页远少于程序大小；读到的数据与文件一致，.bss 为 0 且可写，
+对可写数据段的修改只在本进程内可见。
+*/
+
+const KEY: usize = 0x4c5a;
+const PAGE: usize = 4096;
+const WORDS: usize = 128 * 1024;
+const MAGIC: u64 = 0x5a5a_1234_5a5a_4321;
+/// 子进程访问的只读数据页数
+const TOUCHED: usize = 4;
+/// 允许额外分配的页表页
+const SLACK: usize = 4;
+
+/// 1 MiB 只读数据
+static DATA: [u64; WORDS] = [MAGIC; WORDS];
+/// 可写数据段
+static mut RW: [u64; 512] = [7; 512];
+/// 256 KiB .bss
+static mut BSS: [u8; 64 * PAGE] = [0; 64 * PAGE];
+
+/// 共享内存：[完成标志, 开始时空闲页数, 访问后空闲页数]
+fn shared(addr: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, 3) }
+}
+
+fn child(id: usize) -> i32 {
+    let start = free_frames();
+    let addr = shmat(id) as usize;
+    assert!(addr > 0);
+    let data = shared(addr);
+    data[1] = start;
+    let step = WORDS / TOUCHED;
+    for i in 0..TOUCHED {
+        let word = unsafe { core::ptr::read_volatile(&DATA[i * step + i]) };
+        assert_eq!(word, MAGIC);
+    }
+    data[2] = free_frames();
+    unsafe {
+        assert!(BSS.iter().step_by(PAGE / 2).all(|b| *b == 0));
+        core::ptr::write_volatile(&mut BSS[BSS.len() - 1], 1);
+        assert_eq!(core::ptr::read_volatile(&BSS[BSS.len() - 1]), 1);
+        assert!(RW.iter().all(|w| *w == 7));
+        RW[0] = 8;
+    }
+    data[0] = 1;
+    assert_eq!(shmdt(addr), 0);
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 父进程创建的段存在时，说明本进程是 spawn 出的子进程
+    let id = shmget(KEY, 0);
+    if id >= 0 {
+        exit(child(id as usize));
+    }
+    let id = shmget(KEY, PAGE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize) as usize;
+    assert!(addr > 0);
+    let data = shared(addr);
+
+    let free = free_frames();
+    let start = get_time();
+    let pid = spawn("ch4_exec_lazy\0");
+    assert!(pid > 0);
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let elapsed = get_time() - start;
+    assert_eq!(exit_code, 0);
+    assert_eq!(data[0], 1);
+    let used = free - data[1];
+    println!(
+        "child started with {} frames for a {} KiB image, {} ms",
+        used,
+        WORDS * 8 / 1024,
+        elapsed
+    );
+    // 远小于 1 MiB 数据所需的 256 页
+    assert!(used < WORDS * 8 / PAGE / 4);
+    // 每访问一页只读数据才分配一页
+    assert!(data[1] - data[2] >= TOUCHED && data[1] - data[2] <= TOUCHED + SLACK);
+    // 子进程的修改没有写回文件
+    unsafe {
+        assert_eq!(core::ptr::read_volatile(&RW[0]), 7);
+    }
+    assert_eq!(shmdt(addr), 0);
+    println!("Test 04_15 exec lazy OK!");
+    0
+}
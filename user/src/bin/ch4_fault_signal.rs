@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, mmap, munmap, sigaction_fault, sigreturn, waitpid, SIGILL, SIGSEGV, SIG_DFL,
+};
+
+/*
+理想结果：输出 Test 04_23 fault signal OK!
+写入未映射的地址触发 SIGSEGV，处理函数的第二个参数是出错的地址，它映射该页后 sigreturn，
+写入重新执行并成功。未安装处理函数时，访问非法地址的进程以 -11（-SIGSEGV）结束，
+执行非法指令的进程以 -4（-SIGILL）结束；SIGILL 的处理函数得到非法指令的地址。
+*/
+
+const BAD: usize = 0x2000_0000;
+const PAGE: usize = 4096;
+const PROT_RW: usize = 3;
+const ILL_CAUGHT: i32 = 44;
+
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+fn on_segv(signum: i32, addr: usize) {
+    assert_eq!(signum, SIGSEGV);
+    FAULT_ADDR.store(addr, Ordering::SeqCst);
+    // 映射出错的页，sigreturn 后写入重新执行
+    assert_eq!(mmap(addr & !(PAGE - 1), PAGE, PROT_RW), 0);
+    sigreturn();
+}
+
+fn on_ill(signum: i32, addr: usize) {
+    assert_eq!(signum, SIGILL);
+    // 非法指令在代码段中，处理函数无法跳过它，直接退出
+    assert!(addr != 0 && addr < BAD);
+    exit(ILL_CAUGHT);
+}
+
+fn illegal_instruction() {
+    unsafe {
+        core::arch::asm!("unimp");
+    }
+}
+
+fn run_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sigaction_fault(SIGSEGV, on_segv), SIG_DFL as isize);
+    let bad = (BAD + 8) as *mut usize;
+    unsafe { bad.write_volatile(42) };
+    assert_eq!(FAULT_ADDR.load(Ordering::SeqCst), BAD + 8);
+    assert_eq!(unsafe { bad.read_volatile() }, 42);
+    assert_eq!(munmap(BAD, PAGE), 0);
+
+    // 默认动作：以 -signum 结束
+    let code = run_child(|| {
+        user_lib::signal(SIGSEGV, SIG_DFL);
+        unsafe { ((BAD + 16) as *mut usize).write_volatile(1) };
+    });
+    assert_eq!(code, -SIGSEGV);
+    assert_eq!(run_child(illegal_instruction), -SIGILL);
+    let code = run_child(|| {
+        sigaction_fault(SIGILL, on_ill);
+        illegal_instruction();
+    });
+    assert_eq!(code, ILL_CAUGHT);
+    println!("Test 04_23 fault signal OK!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, free_frames, mmap, munmap, waitpid};
+
+/*
+理想结果：输出 Test 04_9 mmap lazy OK!
+mmap 64 MiB 时不分配物理页，只有被访问的页才分配，访问 16 页后空闲物理页约减少 16 页。
+内核代替用户写入尚未分配的页（waitpid 写回退出码）时同样会分配该页。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const LEN: usize = 64 * 1024 * 1024;
+const TOUCHED: usize = 16;
+/// 允许额外分配的页表页
+const SLACK: usize = 3;
+
+#[no_mangle]
+fn main() -> i32 {
+    let free = free_frames();
+    assert_eq!(mmap(START, LEN, 3), 0);
+    assert!(free - free_frames() <= SLACK);
+    for i in 0..TOUCHED {
+        unsafe {
+            *((START + i * PAGE) as *mut usize) = i;
+        }
+    }
+    let used = free - free_frames();
+    println!("{} frames used after touching {} pages", used, TOUCHED);
+    assert!(used >= TOUCHED && used <= TOUCHED + SLACK);
+    for i in 0..TOUCHED {
+        assert_eq!(unsafe { *((START + i * PAGE) as *const usize) }, i);
+    }
+    // 未访问过的页读出 0
+    assert_eq!(unsafe { *((START + LEN - PAGE) as *const usize) }, 0);
+
+    // 由内核写入尚未分配的页
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    let exit_code = unsafe { &mut *((START + LEN / 2) as *mut i32) };
+    assert_eq!(waitpid(pid as usize, exit_code), pid);
+    assert_eq!(unsafe { *((START + LEN / 2) as *const i32) }, 7);
+
+    assert_eq!(munmap(START, LEN), 0);
+    // 只剩下页表页，包括内核中子进程内核栈所用的页表页
+    assert!(free - free_frames() <= SLACK * 2);
+    println!("Test 04_9 mmap lazy OK!");
+    0
+}
@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, mmap, mmap_file, mprotect, munmap, open, sigaction, sigreturn, waitpid,
+    OpenFlags, EACCES, EINVAL, ENOMEM, MAP_SHARED, SIGSEGV, SIG_DFL,
+};
+
+/*
+理想结果：输出 Test 04_19 mprotect OK!
+把页改为只读后写入会触发 SIGSEGV，处理函数把页改回可写后，写入重新执行并成功；
+只修改区域中间的页会拆分区域；写入代码后改为可执行即可调用（W^X）；
+fork 后共享的只读页改为可写时先复制，父子进程互不影响；
+非对齐地址返回 -22（EINVAL），含未映射页的范围返回 -12（ENOMEM），以只读方式打开的文件的共享映射
+改为可写返回 -13（EACCES）。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const PROT_R: usize = 1;
+const PROT_RW: usize = 3;
+const PROT_RX: usize = 5;
+
+static FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+fn on_segv(signum: i32) {
+    assert_eq!(signum, SIGSEGV);
+    FAULTS.fetch_add(1, Ordering::SeqCst);
+    assert_eq!(mprotect(START, PAGE, PROT_RW), 0);
+    sigreturn();
+}
+
+fn word(page: usize) -> *mut usize {
+    (START + page * PAGE) as *mut usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sigaction(SIGSEGV, on_segv), SIG_DFL as isize);
+
+    // 只读页的写入被处理函数改回可写后重新执行
+    assert_eq!(mmap(START, 3 * PAGE, PROT_RW), 0);
+    unsafe {
+        word(0).write_volatile(1);
+        word(1).write_volatile(2);
+    }
+    assert_eq!(mprotect(START, PAGE, PROT_R), 0);
+    assert_eq!(unsafe { word(0).read_volatile() }, 1);
+    assert_eq!(FAULTS.load(Ordering::SeqCst), 0);
+    unsafe { word(0).write_volatile(3) };
+    assert_eq!(FAULTS.load(Ordering::SeqCst), 1);
+    assert_eq!(unsafe { word(0).read_volatile() }, 3);
+
+    // 只修改中间的页，两侧的页仍可写；拆分后的区域可以分别 munmap
+    assert_eq!(mprotect(START + PAGE, PAGE, PROT_R), 0);
+    unsafe {
+        word(0).write_volatile(4);
+        word(2).write_volatile(5);
+    }
+    assert_eq!(FAULTS.load(Ordering::SeqCst), 1);
+    assert_eq!(unsafe { word(1).read_volatile() }, 2);
+    assert_eq!(mprotect(START + PAGE, PAGE, PROT_RW), 0);
+    assert_eq!(munmap(START + PAGE, PAGE), 0);
+
+    // 错误的参数
+    assert_eq!(mprotect(START + 1, PAGE, PROT_R), -EINVAL);
+    assert_eq!(mprotect(START, 3 * PAGE, PROT_R), -ENOMEM);
+    assert_eq!(mprotect(START, PAGE, 8), -EINVAL);
+    assert_eq!(munmap(START, PAGE), 0);
+    assert_eq!(munmap(START + 2 * PAGE, PAGE), 0);
+
+    // W^X：写入代码后改为只读可执行
+    assert_eq!(mmap(START, PAGE, PROT_RW), 0);
+    let code = START as *mut u32;
+    unsafe {
+        // li a0, 42
+        code.write_volatile(0x02a0_0513);
+        // ret
+        code.add(1).write_volatile(0x0000_8067);
+    }
+    assert_eq!(mprotect(START, PAGE, PROT_RX), 0);
+    let f: extern "C" fn() -> usize = unsafe { core::mem::transmute(START) };
+    assert_eq!(f(), 42);
+    assert_eq!(munmap(START, PAGE), 0);
+
+    // fork 后父子进程共享只读页，改为可写时各自得到副本
+    assert_eq!(mmap(START, PAGE, PROT_RW), 0);
+    unsafe { word(0).write_volatile(6) };
+    assert_eq!(mprotect(START, PAGE, PROT_R), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(mprotect(START, PAGE, PROT_RW), 0);
+        unsafe { word(0).write_volatile(7) };
+        exit(if unsafe { word(0).read_volatile() } == 7 { 0 } else { 1 });
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { word(0).read_volatile() }, 6);
+    assert_eq!(mprotect(START, PAGE, PROT_RW), 0);
+    unsafe { word(0).write_volatile(8) };
+    assert_eq!(FAULTS.load(Ordering::SeqCst), 1);
+    assert_eq!(munmap(START, PAGE), 0);
+
+    // 以只读方式打开的文件，其共享映射不能改为可写
+    let fd = open("mmap.dat\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(mmap_file(START, PAGE, PROT_R, MAP_SHARED, fd as usize, 0), 0);
+    close(fd as usize);
+    assert_eq!(mprotect(START, PAGE, PROT_RW), -EACCES);
+    assert_eq!(mprotect(START, PAGE, PROT_RX), 0);
+    assert_eq!(munmap(START, PAGE), 0);
+
+    println!("Test 04_19 mprotect OK!");
+    0
+}
@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, frame_stats, free_frames, mmap, munmap, shmat, shmdt, shmget, waitpid, yield_,
+    FrameStats, ENOMEM, IPC_PRIVATE,
+};
+
+/*
+理想结果：输出 Test 04_14 oom OK!
+父进程占用约 1/24 的空闲物理页后不断 fork，直到物理页耗尽、fork 返回 -12（ENOMEM），内核不会 panic。
+之前创建的子进程仍在运行（通过共享内存中的计数器确认），全部退出后空闲物理页恢复到开始时的数量。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const MAX_CHILDREN: usize = 60;
+/// 允许额外分配的页表页，包括内核栈所用的页表页
+const SLACK: usize = 4;
+
+/// 共享内存：第 0 个字为退出标志，之后每个子进程一个计数器
+fn shared(addr: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, MAX_CHILDREN + 1) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let id = shmget(IPC_PRIVATE, PAGE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize) as usize;
+    assert!(addr > 0);
+    // 先用一次，使页表页已经分配好
+    assert_eq!(mmap(START, PAGE, 3), 0);
+    unsafe {
+        *(START as *mut u8) = 1;
+    }
+    assert_eq!(munmap(START, PAGE), 0);
+    let free = free_frames();
+
+    let mut stats = FrameStats::default();
+    assert_eq!(frame_stats(&mut stats), 0);
+    assert!(stats.free <= stats.total && stats.total - stats.free <= stats.high_water);
+    // 每次 fork 都要复制这些页
+    let pages = stats.free / 24;
+    assert_eq!(mmap(START, pages * PAGE, 3), 0);
+    for i in 0..pages {
+        unsafe {
+            *((START + i * PAGE) as *mut u8) = 1;
+        }
+    }
+
+    let mut pids = [0usize; MAX_CHILDREN];
+    let mut children = 0;
+    loop {
+        assert!(children < MAX_CHILDREN, "fork never ran out of memory");
+        let pid = fork();
+        if pid == 0 {
+            let data = shared(addr);
+            while unsafe { core::ptr::read_volatile(&data[0]) } == 0 {
+                unsafe {
+                    core::ptr::write_volatile(&mut data[children + 1], data[children + 1] + 1);
+                }
+                yield_();
+            }
+            exit(0);
+        }
+        if pid < 0 {
+            assert_eq!(pid, -ENOMEM);
+            break;
+        }
+        pids[children] = pid as usize;
+        children += 1;
+    }
+    println!("fork failed after {} children", children);
+    assert!(children > 0);
+    assert_eq!(frame_stats(&mut stats), 0);
+    assert!(stats.high_water >= stats.total - stats.free);
+
+    // 子进程仍在运行
+    let data = shared(addr);
+    let mut before = [0usize; MAX_CHILDREN];
+    before[..children].copy_from_slice(&data[1..children + 1]);
+    for i in 0..children {
+        while unsafe { core::ptr::read_volatile(&data[i + 1]) } == before[i] {
+            yield_();
+        }
+    }
+    data[0] = 1;
+    for pid in pids[..children].iter() {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(*pid, &mut exit_code), *pid as isize);
+        assert_eq!(exit_code, 0);
+    }
+
+    assert_eq!(munmap(START, pages * PAGE), 0);
+    assert!(free_frames() + SLACK >= free);
+    // 内存恢复后可以再次 fork
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    assert_eq!(shmdt(addr), 0);
+    println!("Test 04_14 oom OK!");
+    0
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getrusage, mmap, munmap, task_info, waitpid, Rusage, TaskInfo, EINVAL, RUSAGE_SELF,
+};
+
+/*
+理想结果：输出 Test 04_21 rusage OK!
+mmap 只增加映射的页数，访问过的页才计入常驻页数；munmap 后两者都减少，峰值不变。
+fork 出的子进程与父进程共享或复制的页都计入子进程，子进程的常驻页数与父进程相同，峰值从 fork 时算起。
+task_info 与 getrusage 报告的数值一致；未知的 who 返回 -22（EINVAL）。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const PAGES: usize = 16;
+const TOUCHED: usize = 10;
+const FREED: usize = 5;
+
+fn rusage() -> Rusage {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    usage
+}
+
+/// 映射 PAGES 页，访问前 TOUCHED 页，再释放前 FREED 页，检查每一步的计数
+fn round(start: usize) {
+    let base = rusage();
+    assert_eq!(mmap(start, PAGES * PAGE, 3), 0);
+    let mapped = rusage();
+    assert_eq!(mapped.mapped_pages, base.mapped_pages + PAGES);
+    assert_eq!(mapped.resident_pages, base.resident_pages);
+    for i in 0..TOUCHED {
+        unsafe { ((start + i * PAGE) as *mut usize).write_volatile(i) };
+    }
+    let touched = rusage();
+    assert_eq!(touched.resident_pages, base.resident_pages + TOUCHED);
+    assert!(touched.peak_resident_pages >= touched.resident_pages);
+    assert_eq!(munmap(start, FREED * PAGE), 0);
+    let freed = rusage();
+    assert_eq!(freed.resident_pages, touched.resident_pages - FREED);
+    assert_eq!(freed.mapped_pages, touched.mapped_pages - FREED);
+    assert_eq!(freed.peak_resident_pages, touched.peak_resident_pages);
+    assert_eq!(munmap(start + FREED * PAGE, (PAGES - FREED) * PAGE), 0);
+    assert_eq!(rusage().resident_pages, base.resident_pages);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 第一轮让用到的代码页都换入，第二轮的计数只反映 mmap 的页
+    round(START);
+    round(START + PAGES * PAGE);
+
+    let info = TaskInfo::new();
+    assert_eq!(task_info(&info), 0);
+    let usage = rusage();
+    assert_eq!(info.mapped_pages, usage.mapped_pages);
+    assert!(info.resident_pages > 0 && info.resident_pages <= usage.resident_pages);
+    assert!(info.peak_resident_pages <= usage.peak_resident_pages);
+    let mut bad = Rusage::default();
+    assert_eq!(getrusage(1, &mut bad), -EINVAL);
+
+    // 子进程通过退出码报告自己的常驻页数
+    let pid = fork();
+    let usage = rusage();
+    if pid == 0 {
+        let ok = usage.peak_resident_pages == usage.resident_pages;
+        exit(if ok { usage.resident_pages as i32 } else { -1 });
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, usage.resident_pages as i32);
+
+    println!("Test 04_21 rusage OK!");
+    0
+}
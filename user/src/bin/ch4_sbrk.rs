@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{brk, free_frames, mmap, munmap, sbrk, ENOMEM};
+
+/*
+理想结果：输出 Test 04_10 sbrk OK!
+sbrk 增长堆时按需分配物理页，缩小时释放物理页；堆不能长进已映射的区域，也不能低于堆底。
+用户库的堆分配器通过 sbrk 获取内存，能分配并释放 1 MiB。
+*/
+
+const PAGE: usize = 4096;
+const MIB: usize = 1 << 20;
+
+#[no_mangle]
+fn main() -> i32 {
+    let base = sbrk(0);
+    assert!(base > 0);
+    assert_eq!(brk(0), base);
+    let base = base as usize;
+
+    // 增长时只在访问时分配物理页
+    let free = free_frames();
+    assert_eq!(sbrk((PAGE * 4) as isize), base as isize);
+    assert!(free - free_frames() <= 1);
+    for i in 0..PAGE * 4 {
+        unsafe {
+            *((base + i) as *mut u8) = i as u8;
+        }
+    }
+    let used = free - free_frames();
+    assert!(used >= 4 && used <= 5);
+    assert_eq!(sbrk(0), (base + PAGE * 4) as isize);
+    // 缩小时释放物理页
+    assert_eq!(sbrk(-((PAGE * 4) as isize)), (base + PAGE * 4) as isize);
+    assert_eq!(free - free_frames(), used - 4);
+    // 不能低于堆底
+    assert_eq!(sbrk(-1), -ENOMEM);
+    assert_eq!(brk(base - PAGE), base as isize);
+
+    // 不能长进已映射的区域
+    let next = (base + PAGE - 1) / PAGE * PAGE + PAGE * 2;
+    assert_eq!(mmap(next, PAGE, 3), 0);
+    assert_eq!(sbrk((next + PAGE - base) as isize), -ENOMEM);
+    assert_eq!(brk(next + 1), base as isize);
+    assert_eq!(sbrk(0), base as isize);
+    assert_eq!(munmap(next, PAGE), 0);
+
+    // 通过堆分配器分配、释放 1 MiB
+    let mut v = vec![0u8; MIB];
+    for (i, b) in v.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    assert!(v.iter().enumerate().all(|(i, b)| *b == i as u8));
+    assert!(sbrk(0) as usize >= base + MIB);
+    drop(v);
+    // 释放后再次分配复用同一块内存，堆不再增长
+    let top = sbrk(0);
+    let v = vec![1u8; MIB];
+    assert_eq!(v[MIB - 1], 1);
+    assert_eq!(sbrk(0), top);
+    println!("Test 04_10 sbrk OK!");
+    0
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, free_frames, shmat, shmdt, shmget, waitpid, EINVAL};
+
+/*
+理想结果：输出 Test 04_13 shm OK!
+父进程向共享内存段写入数据，子进程（fork 继承的映射，以及按 key 重新 attach 的映射）
+不通过管道或文件就能读到；子进程的写入父进程可见。所有进程 detach 后段内存被释放。
+*/
+
+const KEY: usize = 0x5348;
+const PAGE: usize = 4096;
+const SIZE: usize = PAGE * 3 + 100;
+/// 允许额外分配的页表页
+const SLACK: usize = 2;
+
+fn segment(addr: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, SIZE) }
+}
+
+fn pattern(i: usize) -> u8 {
+    (i * 7 + i / PAGE) as u8
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 先用一次，使页表页已经分配好
+    let id = shmget(KEY, SIZE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize);
+    assert!(addr > 0 && addr as usize % PAGE == 0);
+    assert_eq!(shmdt(addr as usize), 0);
+    let free = free_frames();
+
+    let id = shmget(KEY, SIZE);
+    assert!(id >= 0);
+    // 已存在的段不能按更大的大小获取
+    assert_eq!(shmget(KEY, PAGE * 5), -EINVAL);
+    let addr = shmat(id as usize) as usize;
+    let data = segment(addr);
+    // 新段内容为 0
+    assert!(data.iter().all(|b| *b == 0));
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = pattern(i);
+    }
+    // 同一进程可以再 attach 一次，两处映射同一段内存
+    let again = shmat(id as usize) as usize;
+    assert!(again > 0 && again != addr);
+    assert_eq!(segment(again)[SIZE - 1], pattern(SIZE - 1));
+    assert_eq!(shmdt(again), 0);
+    assert_eq!(shmdt(again), -EINVAL);
+
+    let pid = fork();
+    if pid == 0 {
+        // fork 继承的映射
+        let data = segment(addr);
+        assert!(data.iter().enumerate().all(|(i, b)| *b == pattern(i)));
+        // 按 key 找到同一段
+        let id = shmget(KEY, 0);
+        assert!(id >= 0);
+        let other = shmat(id as usize) as usize;
+        assert!(other > 0);
+        segment(other)[0] = 0xaa;
+        assert_eq!(data[0], 0xaa);
+        assert_eq!(shmdt(other), 0);
+        exit(0);
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 子进程退出时自动 detach，父进程仍然可以访问，并看到子进程的写入
+    assert_eq!(data[0], 0xaa);
+    assert_eq!(data[SIZE - 1], pattern(SIZE - 1));
+
+    // 最后一个 detach 释放段内存
+    assert!(free - free_frames() >= 4);
+    assert_eq!(shmdt(addr), 0);
+    assert!(free_frames() + SLACK >= free);
+    // 段已释放，同一 key 得到新的全 0 段
+    let id = shmget(KEY, SIZE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize) as usize;
+    assert!(segment(addr).iter().all(|b| *b == 0));
+    assert_eq!(shmdt(addr), 0);
+    assert_eq!(shmat(usize::MAX), -EINVAL);
+    println!("Test 04_13 shm OK!");
+    0
+}
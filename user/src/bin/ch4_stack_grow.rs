@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid, SIGSEGV};
+
+/*
+理想结果：输出 Test 04_12 stack grow OK!
+用户栈按需增长：需要约 200 KiB 栈的递归能正常完成；
+访问远低于 sp 的栈空间、以及无限递归仍然以 -11（-SIGSEGV）结束进程。
+*/
+
+const KIB: usize = 1024;
+
+/// 每层占用 1 KiB 栈，返回 0..depth 之和
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; KIB];
+    unsafe {
+        core::ptr::write_volatile(&mut frame[depth % KIB], 1);
+    }
+    if depth == 0 {
+        return 0;
+    }
+    let sum = recurse(depth - 1) + depth;
+    sum + unsafe { core::ptr::read_volatile(&frame[depth % KIB]) } as usize - 1
+}
+
+/// 无限递归
+#[allow(unconditional_recursion)]
+fn runaway(depth: usize) -> usize {
+    let mut frame = [0u8; KIB];
+    unsafe {
+        core::ptr::write_volatile(&mut frame[depth % KIB], depth as u8);
+    }
+    runaway(depth + 1) + unsafe { core::ptr::read_volatile(&frame[0]) } as usize
+}
+
+/// fork 一个执行 `f` 的子进程，返回其退出码
+fn run_child(f: fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        user_lib::exit(f());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 约 200 KiB 的栈
+    let depth = 200;
+    assert_eq!(recurse(depth), depth * (depth + 1) / 2);
+    // 子进程中同样可以增长
+    assert_eq!(
+        run_child(|| if recurse(200) == 200 * 201 / 2 { 0 } else { 1 }),
+        0
+    );
+    // 远低于 sp 的访问不会使栈增长
+    assert_eq!(
+        run_child(|| {
+            let local = 0u8;
+            let stray = &local as *const u8 as usize - 512 * KIB;
+            unsafe {
+                core::ptr::write_volatile(stray as *mut u8, 1);
+            }
+            0
+        }),
+        -SIGSEGV
+    );
+    // 无限递归在栈用尽后结束
+    assert_eq!(
+        run_child(|| {
+            runaway(0);
+            0
+        }),
+        -SIGSEGV
+    );
+    println!("Test 04_12 stack grow OK!");
+    0
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid, SIGSEGV};
+
+/*
+理想结果：输出 Test 04_11 stack overflow OK!
+子进程无限递归，栈越过栈底后访问其下方未映射的保护页，内核报告 user stack overflow
+并以 -11（-SIGSEGV）结束子进程，而不是改写栈下方的数据段。
+*/
+
+/// 每层占用 1 KiB 栈，防止被优化为循环
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    unsafe {
+        core::ptr::write_volatile(&mut frame[depth % 1024], depth as u8);
+    }
+    recurse(depth + 1) + unsafe { core::ptr::read_volatile(&frame[0]) } as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        recurse(0);
+        unreachable!();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    println!("Test 04_11 stack overflow OK!");
+    0
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, frame_stats, free_frames, mmap, munmap, shmat, shmdt, shmget, waitpid,
+    FrameStats, IPC_PRIVATE,
+};
+
+/*
+理想结果：输出 Test 04_16 swap OK!
+先用共享内存段占住大部分空闲物理页，只留下 KEEP 页，再 mmap 并写入 3 倍于此的匿名内存。
+内核把页换出到 swap 文件、访问时再换入，正序与逆序读回的内容都正确；
+内存不足时 fork 仍然成功，子进程看到同样的内容。全部释放后空闲物理页恢复。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+/// 留给本进程的空闲物理页
+const KEEP: usize = 128;
+const PAGES: usize = 3 * KEEP;
+const WORDS: usize = PAGE / 8;
+/// 允许额外分配的页表页
+const SLACK: usize = 4;
+
+fn page(i: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut((START + i * PAGE) as *mut usize, WORDS) }
+}
+
+fn check(i: usize) -> bool {
+    let p = page(i);
+    p[0] == i && p[WORDS / 2] == i * 7 && p[WORDS - 1] == !i
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 先用一次，使页表页已经分配好
+    assert_eq!(mmap(START, PAGE, 3), 0);
+    page(0)[0] = 1;
+    assert_eq!(munmap(START, PAGE), 0);
+    let free = free_frames();
+    let mut stats = FrameStats::default();
+    assert_eq!(frame_stats(&mut stats), 0);
+
+    let id = shmget(IPC_PRIVATE, (stats.free - KEEP) * PAGE);
+    assert!(id >= 0);
+    let addr = shmat(id as usize) as usize;
+    assert!(addr > 0);
+    assert!(free_frames() <= KEEP + SLACK);
+
+    assert_eq!(mmap(START, PAGES * PAGE, 3), 0);
+    for i in 0..PAGES {
+        let p = page(i);
+        p[0] = i;
+        p[WORDS / 2] = i * 7;
+        p[WORDS - 1] = !i;
+    }
+    assert!((0..PAGES).all(check));
+    assert!((0..PAGES).rev().all(check));
+
+    // 子进程得到全部页（包括已换出的页）的副本
+    let pid = fork();
+    if pid == 0 {
+        exit(if (0..PAGES).rev().all(check) { 0 } else { 1 });
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert!((0..PAGES).all(check));
+
+    assert_eq!(munmap(START, PAGES * PAGE), 0);
+    assert_eq!(shmdt(addr), 0);
+    assert!(free_frames() + SLACK >= free);
+    println!("Test 04_16 swap OK!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, get_name, mmap, munmap, syscall, OpenFlags, Stat, StatMode, TaskInfo, TaskStatus,
+    TimeVal, ENAMETOOLONG, SYSCALL_FSTAT, SYSCALL_GETTIMEOFDAY, SYSCALL_GET_NAME, SYSCALL_OPENAT,
+    SYSCALL_SET_NAME, SYSCALL_TASK_INFO, SYSCALL_UNLINKAT,
+};
+
+/*
+理想结果：输出 Test 04_18 uaccess OK!
+跨页、未对齐的结构体和字符串可以正常传给系统调用；
+没有结尾 NUL 的字符串最多读取 256 字节（含 NUL）后返回 -36（ENAMETOOLONG），而不是一直读下去。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+/// 内核从用户空间读取的字符串最大长度，含结尾的 NUL
+const PATH_MAX: usize = 256;
+
+fn memory() -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(START as *mut u8, 2 * PAGE) }
+}
+
+/// 把 `s` 放到 `addr` 处
+fn put(addr: usize, s: &[u8]) -> usize {
+    memory()[addr - START..addr - START + s.len()].copy_from_slice(s);
+    addr
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(mmap(START, 2 * PAGE, 3), 0);
+    let edge = START + PAGE;
+
+    // 跨页且未对齐的 TimeVal
+    let tv = edge - 5;
+    assert_eq!(syscall(SYSCALL_GETTIMEOFDAY, [tv, 0, 0]), 0);
+    let tv = unsafe { core::ptr::read_unaligned(tv as *const TimeVal) };
+    assert!(tv.sec > 0 || tv.usec > 0);
+    let ti = edge - 100;
+    assert_eq!(syscall(SYSCALL_TASK_INFO, [ti, 0, 0]), 0);
+    let ti = unsafe { core::ptr::read_unaligned(ti as *const TaskInfo) };
+    assert!(ti.status == TaskStatus::Running);
+    assert_eq!(ti.syscall_times[SYSCALL_TASK_INFO], 1);
+
+    // 跨页的路径
+    let path = put(edge - 5, b"uaccess_tmp\0");
+    let flags = (OpenFlags::CREATE | OpenFlags::RDWR).bits() as usize;
+    let fd = syscall(SYSCALL_OPENAT, [0, path, flags]);
+    assert!(fd > 0);
+    let st = edge - 3;
+    assert_eq!(syscall(SYSCALL_FSTAT, [fd as usize, st, 0]), 0);
+    let st = unsafe { core::ptr::read_unaligned(st as *const Stat) };
+    assert_eq!(st.mode, StatMode::FILE);
+    assert_eq!(st.nlink, 1);
+    close(fd as usize);
+    assert_eq!(syscall(SYSCALL_UNLINKAT, [0, path, 0]), 0);
+
+    // 没有 NUL 的字符串
+    memory().fill(b'a');
+    assert_eq!(syscall(SYSCALL_OPENAT, [0, START, flags]), -ENAMETOOLONG);
+    assert_eq!(syscall(SYSCALL_SET_NAME, [START, 0, 0]), -ENAMETOOLONG);
+    // 恰好 PATH_MAX 字节（含 NUL）的名字可以读入，长 1 字节就不行
+    let name = edge - PATH_MAX / 2 + 1;
+    memory()[edge - START + PATH_MAX / 2] = 0;
+    assert_eq!(syscall(SYSCALL_SET_NAME, [name - 1, 0, 0]), -ENAMETOOLONG);
+    assert_eq!(syscall(SYSCALL_SET_NAME, [name, 0, 0]), 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(get_name(&mut buf), 7);
+    assert_eq!(&buf, b"aaaaaaa\0");
+    // 跨页的输出缓冲区
+    let out = edge - 3;
+    assert_eq!(syscall(SYSCALL_GET_NAME, [out, 8, 0]), 7);
+    assert_eq!(&memory()[out - START..out - START + 8], b"aaaaaaa\0");
+
+    assert_eq!(munmap(START, 2 * PAGE), 0);
+    println!("Test 04_18 uaccess OK!");
+    0
+}
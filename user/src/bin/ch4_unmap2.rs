@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{mmap, munmap};
+use user_lib::{mmap, munmap, EINVAL};
 
 /*
 理想结果：输出 Test 04_6 ummap2 OK!
@@ -16,8 +16,8 @@ fn main() -> i32 {
     let len: usize = 4096;
     let prot: usize = 3;
     assert_eq!(0, mmap(start, len, prot));
-    assert_eq!(munmap(start, len + 1), -1);
-    assert_eq!(munmap(start + 1, len - 1), -1);
+    assert_eq!(munmap(start, len + 1), -EINVAL);
+    assert_eq!(munmap(start + 1, len - 1), -EINVAL);
     println!("Test 04_6 ummap2 OK!");
     0
 }
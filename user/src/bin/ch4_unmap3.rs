@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{free_frames, mmap, munmap, EINVAL, ENOMEM};
+
+/*
+理想结果：输出 Test 04_7 ummap3 OK!
+范围内有未映射的页时 munmap 失败且不释放任何页；覆盖相邻两个映射区域时一并释放。
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    let prot: usize = 3;
+    // 先映射一次，使页表页已经分配好，之后的空闲页数只受数据页影响
+    assert_eq!(mmap(start, len, prot), 0);
+    assert_eq!(munmap(start, len), 0);
+    let free = free_frames();
+    assert_eq!(mmap(start, len, prot), 0);
+    assert_eq!(mmap(start + len, len * 2, prot), 0);
+    // 第 4 页未映射
+    assert_eq!(munmap(start, len * 4), -EINVAL);
+    assert_eq!(munmap(start - len, len * 2), -EINVAL);
+    unsafe {
+        *(start as *mut u8) = 1;
+        *((start + len * 2) as *mut u8) = 2;
+    }
+    assert_eq!(munmap(start, len * 3), 0);
+    assert_eq!(free_frames(), free);
+    assert_eq!(munmap(start, len), -EINVAL);
+    // 地址越过用户空间上限
+    assert_eq!(mmap(usize::MAX - len + 1, len, prot), -ENOMEM);
+    println!("Test 04_7 ummap3 OK!");
+    0
+}
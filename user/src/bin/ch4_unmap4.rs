@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{free_frames, mmap, munmap, EINVAL};
+
+/*
+理想结果：输出 Test 04_8 ummap4 OK!
+munmap 区域的头部、尾部、中间、整个区域以及跨越相邻两个区域的一段时，
+只释放范围内的页，范围外的页仍然映射且内容不变。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const PROT: usize = 3;
+
+fn page(i: usize) -> usize {
+    START + i * PAGE
+}
+
+/// 每页首字节写入页号
+fn fill(from: usize, to: usize) {
+    for i in from..to {
+        unsafe {
+            *(page(i) as *mut u8) = i as u8;
+        }
+    }
+}
+
+/// 检查仍然映射的页内容不变，已释放的页不再映射
+fn check(from: usize, to: usize, holes: &[(usize, usize)]) {
+    for i in from..to {
+        if holes.iter().any(|&(l, r)| l <= i && i < r) {
+            assert_eq!(munmap(page(i), PAGE), -EINVAL);
+        } else {
+            assert_eq!(unsafe { *(page(i) as *const u8) }, i as u8);
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 先映射一次，使页表页已经分配好，之后的空闲页数只受数据页影响
+    assert_eq!(mmap(page(0), PAGE * 8, PROT), 0);
+    assert_eq!(munmap(page(0), PAGE * 8), 0);
+    let free = free_frames();
+
+    assert_eq!(mmap(page(0), PAGE * 8, PROT), 0);
+    fill(0, 8);
+    // 头部
+    assert_eq!(munmap(page(0), PAGE), 0);
+    assert_eq!(free_frames(), free - 7);
+    // 尾部
+    assert_eq!(munmap(page(7), PAGE), 0);
+    assert_eq!(free_frames(), free - 6);
+    // 中间
+    assert_eq!(munmap(page(3), PAGE * 2), 0);
+    assert_eq!(free_frames(), free - 4);
+    check(0, 8, &[(0, 1), (3, 5), (7, 8)]);
+    // 空洞可以重新映射
+    assert_eq!(mmap(page(3), PAGE * 2, PROT), 0);
+    fill(3, 5);
+    check(1, 7, &[]);
+    // 跨越相邻两个区域：[1, 3) 的后一页与 [3, 5) 的前一页
+    assert_eq!(munmap(page(2), PAGE * 2), 0);
+    assert_eq!(free_frames(), free - 4);
+    check(1, 7, &[(2, 4)]);
+    // 拆分后剩下的整个区域
+    assert_eq!(munmap(page(4), PAGE), 0);
+    assert_eq!(munmap(page(1), PAGE), 0);
+    assert_eq!(munmap(page(5), PAGE * 2), 0);
+    assert_eq!(free_frames(), free);
+    assert_eq!(munmap(page(0), PAGE * 8), -EINVAL);
+    println!("Test 04_8 ummap4 OK!");
+    0
+}
@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, free_frames, getrusage, mmap, munmap, open, read, unlink, waitpid, write,
+    OpenFlags, Rusage, RUSAGE_SELF,
+};
+
+/*
+理想结果：输出 Test 04_22 zero page OK!
+mmap 32 MiB 后读遍每一页，常驻页数不变，空闲物理页只少了页表页；
+之后写入一页恰好多用一个物理页，其余页仍读出 0。
+fork 出的子进程写入只读过的页不影响父进程；内核代替 sys_read 写入只读过的页时同样分配新页。
+*/
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const LEN: usize = 32 * 1024 * 1024;
+const PAGES: usize = LEN / PAGE;
+/// 读遍所有页时允许分配的页表页
+const SLACK: usize = PAGES / 512 + 2;
+const FILE: &str = "zero_page_tmp\0";
+
+fn rusage() -> Rusage {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    usage
+}
+
+fn word(page: usize) -> *mut usize {
+    (START + page * PAGE) as *mut usize
+}
+
+/// 读取前 `pages` 页的第一个字，返回它们的和
+fn read_pages(pages: usize) -> usize {
+    (0..pages).map(|i| unsafe { word(i).read_volatile() }).sum()
+}
+
+/// 从头读取 FILE 到 `buf`
+fn read_file(buf: &mut [u8]) -> isize {
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+fn write_page(page: usize, value: usize) {
+    unsafe { word(page).write_volatile(value) };
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(mmap(START, LEN, 3), 0);
+    // 先执行一遍用到的代码，让代码页都换入，之后的计数只反映 mmap 的页
+    assert_eq!(read_pages(1), 0);
+    write_page(PAGES - 1, 1);
+    let base = rusage();
+    let free = free_frames();
+
+    assert_eq!(read_pages(PAGES - 1), 0);
+    assert_eq!(rusage().resident_pages, base.resident_pages);
+    let used = free - free_frames();
+    println!("{} frames used after reading {} pages", used, PAGES - 1);
+    assert!(used <= SLACK);
+
+    // 第一次写入分配一页，相邻的页仍读出 0
+    write_page(3, 42);
+    assert_eq!(rusage().resident_pages, base.resident_pages + 1);
+    assert_eq!(free - free_frames(), used + 1);
+    assert_eq!(unsafe { word(3).read_volatile() }, 42);
+    assert_eq!(unsafe { word(2).read_volatile() }, 0);
+    assert_eq!(unsafe { word(4).read_volatile() }, 0);
+
+    // 子进程写入只读过的页
+    let pid = fork();
+    if pid == 0 {
+        write_page(5, 7);
+        let ok = unsafe { word(5).read_volatile() } == 7 && unsafe { word(3).read_volatile() } == 42;
+        exit(if ok { 0 } else { 1 });
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { word(5).read_volatile() }, 0);
+
+    // 内核代替 sys_read 写入只读过的页，先读入栈上的缓冲区换入代码页
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"zero"), 4);
+    close(fd);
+    let mut warm = [0u8; 4];
+    assert_eq!(read_file(&mut warm), 4);
+    let before = rusage().resident_pages;
+    let buf = unsafe { core::slice::from_raw_parts_mut(word(6) as *mut u8, 4) };
+    assert_eq!(read_file(buf), 4);
+    assert_eq!(buf, b"zero");
+    assert_eq!(rusage().resident_pages, before + 1);
+    assert_eq!(unlink(FILE), 0);
+
+    // 只有写入过的 3 页占用物理页
+    let before = rusage().resident_pages;
+    assert_eq!(munmap(START, LEN), 0);
+    assert_eq!(rusage().resident_pages, before - 3);
+    println!("Test 04_22 zero page OK!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit_group, fork, free_frames, get_time, sleep, sleep_blocking, waitpid, yield_};
+
+/// 子进程建立一棵三层的进程树（叶子或忙等、或睡眠）后调用 exit_group，
+/// 整棵树都应被杀死并回收，空闲物理页数最终回到初始值。
+/// 正确输出：Test exit_group OK!
+
+const DEPTH: usize = 3;
+
+fn build_tree(depth: usize) -> ! {
+    if depth > 0 {
+        for _ in 0..2 {
+            if fork() == 0 {
+                build_tree(depth - 1);
+            }
+        }
+    }
+    if depth % 2 == 0 {
+        loop {
+            yield_();
+        }
+    } else {
+        loop {
+            sleep_blocking(100_000);
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let free = free_frames();
+    let leader = fork();
+    if leader == 0 {
+        for _ in 0..2 {
+            if fork() == 0 {
+                build_tree(DEPTH - 1);
+            }
+        }
+        // 等待整棵树建立
+        sleep(200);
+        exit_group(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(leader as usize, &mut exit_code), leader);
+    assert_eq!(exit_code, 7);
+    // 被杀死的后代由 initproc 回收
+    let start = get_time();
+    while free_frames() != free {
+        assert!(get_time() - start < 3000, "frames not reclaimed: {} of {} free", free_frames(), free);
+        sleep(10);
+    }
+    println!("Test exit_group OK!");
+    0
+}
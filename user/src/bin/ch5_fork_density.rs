@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{fork, free_frames, get_time, kill, sleep, sleep_blocking, waitpid, SIGKILL};
+
+/// 不断 fork 睡眠的子进程，直到 fork 失败或已用掉 90% 的空闲物理页，
+/// 记录进程数与每个进程占用的物理页数。只读段与父进程共享，每个进程的开销应有上限。
+/// 正确输出：Test fork density OK!
+
+const MAX_CHILDREN: usize = 256;
+/// 内核栈 20 页，加上可写段、用户栈、TrapContext 与页表
+const MAX_FRAMES_PER_CHILD: usize = 40;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let free = free_frames();
+    let mut pids = [0usize; MAX_CHILDREN];
+    let mut count = 0;
+    while count < MAX_CHILDREN && free.saturating_sub(free_frames()) < free / 10 * 9 {
+        let pid = fork();
+        if pid == 0 {
+            loop {
+                sleep_blocking(100_000);
+            }
+        }
+        if pid < 0 {
+            break;
+        }
+        pids[count] = pid as usize;
+        count += 1;
+    }
+    assert!(count > 0);
+    let used = free.saturating_sub(free_frames());
+    println!(
+        "{} processes, {} frames used, {} frames per process",
+        count,
+        used,
+        used / count
+    );
+    assert!(used / count <= MAX_FRAMES_PER_CHILD);
+    for pid in pids[..count].iter() {
+        assert_eq!(kill(*pid, SIGKILL), 0);
+        let mut exit_code = 0;
+        assert_eq!(waitpid(*pid, &mut exit_code), *pid as isize);
+        assert_eq!(exit_code, -SIGKILL);
+    }
+    let start = get_time();
+    while free_frames() != free {
+        assert!(get_time() - start < 3000, "frames not reclaimed");
+        sleep(10);
+    }
+    println!("Test fork density OK!");
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, getrlimit, setrlimit, wait, waitpid, EAGAIN, EPERM, RLIMIT_NPROC};
+
+/// fork 炸弹：每个进程继续 fork，总进程数远超内核上限。
+/// fork 应当干净地失败而不是让内核崩溃，结束后系统仍能正常 fork。
+/// 正确输出：Test fork bomb OK!
+
+const ROUNDS: usize = 10;
+
+fn bomb() -> ! {
+    let mut failed = 0;
+    // 最多 2^ROUNDS 个进程
+    for _ in 0..ROUNDS {
+        if fork() < 0 {
+            failed += 1;
+        }
+    }
+    let mut exit_code: i32 = 0;
+    while wait(&mut exit_code) > 0 {
+        failed += exit_code;
+    }
+    exit(failed);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        bomb();
+    }
+    assert!(pid > 0);
+    let mut failed: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut failed), pid);
+    println!("{} forks failed", failed);
+    assert!(failed > 0);
+    // 子进程数上限
+    let limit = getrlimit(RLIMIT_NPROC);
+    assert!(limit > 0);
+    assert_eq!(setrlimit(RLIMIT_NPROC, limit as usize + 1), -EPERM);
+    assert_eq!(setrlimit(RLIMIT_NPROC, 1), 0);
+    let pid = fork();
+    if pid == 0 {
+        exit(7);
+    }
+    assert!(pid > 0);
+    assert_eq!(fork(), -EAGAIN);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    println!("Test fork bomb OK!");
+    0
+}
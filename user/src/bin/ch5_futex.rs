@@ -0,0 +1,131 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, futex_wait, futex_wake, get_time, kill, pipe, read, shmat, shmget,
+    sleep_blocking, waitpid, write, FutexMutex, EAGAIN, ETIMEDOUT, IPC_PRIVATE, SIGKILL,
+};
+
+/// futex：值不符时 FUTEX_WAIT 立即返回 -EAGAIN，超时返回 -ETIMEDOUT；两个进程经共享内存
+/// 在同一个字上等待和唤醒；排队中被 kill 的进程不再占用唤醒名额。
+/// 最后两个进程争用同一把锁各加 N 次计数，比较 FutexMutex 与以管道令牌实现的内核态锁
+/// （读走一个字节加锁，写回一个字节解锁，每次加解锁都进入内核）的耗时。
+/// 正确输出：Test futex OK!
+
+const N: usize = 2000;
+
+#[repr(C)]
+struct Shared {
+    word: AtomicU32,
+    mutex: FutexMutex,
+    /// 只在持锁时读改写，不用原子加，锁失效时计数就会出错
+    counter: AtomicUsize,
+}
+
+fn shared() -> &'static Shared {
+    let id = shmget(IPC_PRIVATE, 4096);
+    assert!(id >= 0);
+    let addr = shmat(id as usize);
+    assert!(addr > 0);
+    unsafe { &*(addr as *const Shared) }
+}
+
+fn increment(shared: &Shared) {
+    let value = shared.counter.load(Ordering::Relaxed);
+    shared.counter.store(value + 1, Ordering::Relaxed);
+}
+
+/// 两个进程各跑一遍 `body`，返回耗时（毫秒）
+fn contend(body: impl Fn()) -> isize {
+    let start = get_time();
+    let pid = fork();
+    body();
+    if pid == 0 {
+        exit(0);
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    get_time() - start
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let shared = shared();
+    let word = &shared.word;
+
+    // 值不符、超时、无人等待
+    assert_eq!(futex_wait(word, 1, -1), -EAGAIN);
+    let start = get_time();
+    assert_eq!(futex_wait(word, 0, 20), -ETIMEDOUT);
+    assert!(get_time() - start >= 20);
+    assert_eq!(futex_wake(word, 1), 0);
+
+    // 子进程等到值变为 1
+    let pid = fork();
+    if pid == 0 {
+        while word.load(Ordering::SeqCst) == 0 {
+            let ret = futex_wait(word, 0, -1);
+            assert!(ret == 0 || ret == -EAGAIN);
+        }
+        exit(0);
+    }
+    sleep_blocking(20);
+    word.store(1, Ordering::SeqCst);
+    assert_eq!(futex_wake(word, 1), 1);
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // 排队中被 kill
+    let pid = fork();
+    if pid == 0 {
+        futex_wait(word, 1, -1);
+        exit(1);
+    }
+    sleep_blocking(20);
+    assert_eq!(kill(pid as usize, SIGKILL), 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGKILL);
+    assert_eq!(futex_wake(word, 1), 0);
+
+    // 争用：FutexMutex
+    let futex_ms = contend(|| {
+        for _ in 0..N {
+            shared.mutex.lock();
+            increment(shared);
+            shared.mutex.unlock();
+        }
+    });
+    assert_eq!(shared.counter.load(Ordering::SeqCst), 2 * N);
+
+    // 争用：管道令牌
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(write(fds[1], &[0]), 1);
+    shared.counter.store(0, Ordering::SeqCst);
+    let pipe_ms = contend(|| {
+        let mut token = [0u8];
+        for _ in 0..N {
+            assert_eq!(read(fds[0], &mut token), 1);
+            increment(shared);
+            assert_eq!(write(fds[1], &token), 1);
+        }
+    });
+    assert_eq!(shared.counter.load(Ordering::SeqCst), 2 * N);
+    close(fds[0]);
+    close(fds[1]);
+
+    println!(
+        "{} lock/unlock by 2 processes: futex {} ms, pipe {} ms",
+        2 * N,
+        futex_ms,
+        pipe_ms
+    );
+    println!("Test futex OK!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, kill, waitpid, ESRCH, SIGKILL};
+
+/// fork 后立即 kill 仍在就绪队列中的子进程，被 kill 的子进程不应再执行任何用户代码。
+/// 正确输出：Test kill queued OK!
+
+const N: usize = 100;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0usize; N];
+    let mut killed = [false; N];
+    for i in 0..N {
+        let pid = fork();
+        if pid == 0 {
+            // 子进程一旦运行用户代码就以 1 退出
+            exit(1);
+        }
+        pids[i] = pid as usize;
+        // 子进程若已先运行并退出，kill 失败
+        killed[i] = kill(pid as usize, SIGKILL) == 0;
+    }
+    let mut n_killed = 0;
+    for i in 0..N {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pids[i], &mut exit_code), pids[i] as isize);
+        if killed[i] {
+            assert_eq!(exit_code, -SIGKILL);
+            n_killed += 1;
+        } else {
+            assert_eq!(exit_code, 1);
+        }
+    }
+    assert!(kill(pids[0], SIGKILL) == -ESRCH);
+    println!("{} of {} queued tasks killed", n_killed, N);
+    println!("Test kill queued OK!");
+    0
+}
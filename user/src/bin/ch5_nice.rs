@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    exec, exit, fork, get_priority, get_priority_of, get_time, nice, set_priority, set_priority_of,
+    waitpid, EINVAL,
+};
+
+/// 子进程继承父进程的优先级，exec 后保持不变；set_priority 可以指定其他进程，pid 为 0
+/// 表示自己，进程不存在时返回 -3（ESRCH）；nice 相对当前值调整优先级并限制在范围内，
+/// 返回新的优先级。两个优先级为 8 的进程同时自旋，其中一个 nice(6) 降到 2 后，
+/// 它在同一时间窗口内的计数约为另一个的 1/4。
+/// 正确输出：Test nice OK!
+
+fn spin_delay() {
+    let mut j = true;
+    for _ in 0..10 {
+        j = !j;
+    }
+}
+
+const MAX_TIME: isize = 2000;
+
+fn count_until(deadline: isize) -> i32 {
+    let mut acc = 0;
+    loop {
+        spin_delay();
+        acc += 1;
+        if acc % 400 == 0 && get_time() > deadline {
+            return acc;
+        }
+    }
+}
+
+fn wait_child(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_priority(8), 8);
+
+    // fork 继承，exec 保持
+    let pid = fork();
+    if pid == 0 {
+        if get_priority() != 8 {
+            exit(1);
+        }
+        exec("ch5b_priority\0", &[0 as *const u8]);
+        exit(2);
+    }
+    assert_eq!(wait_child(pid), 8);
+
+    // 指定 pid
+    let pid = fork();
+    if pid == 0 {
+        // 等父进程改完
+        while get_priority() == 8 {}
+        exit(get_priority() as i32);
+    }
+    assert_eq!(get_priority_of(pid as usize), 8);
+    assert_eq!(set_priority_of(pid as usize, 1), -EINVAL);
+    assert_eq!(set_priority_of(pid as usize, 12), 12);
+    assert_eq!(get_priority_of(pid as usize), 12);
+    assert_eq!(wait_child(pid), 12);
+    assert_eq!(set_priority_of(pid as usize, 8), -3);
+    assert_eq!(get_priority_of(pid as usize), -3);
+    assert_eq!(set_priority_of(0, 8), 8);
+
+    // 相对调整，限制在范围内
+    let pid = fork();
+    if pid == 0 {
+        let ok = nice(0) == 8
+            && nice(3) == 5
+            && nice(-4) == 9
+            && nice(1 << 40) == 2
+            && nice(isize::MIN) > 9
+            && nice(0) == get_priority();
+        exit(if ok { 0 } else { 1 });
+    }
+    assert_eq!(wait_child(pid), 0);
+    assert_eq!(get_priority(), 8);
+
+    // 降低优先级后得到的 CPU 时间减少
+    let deadline = get_time() + MAX_TIME;
+    let mut pids = [0isize; 2];
+    for (i, increment) in [0isize, 6].iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            if get_priority() != 8 || nice(*increment) != 8 - *increment {
+                exit(-1);
+            }
+            exit(count_until(deadline));
+        }
+        pids[i] = pid;
+    }
+    // the parent only waits, keep it out of the way of the spinners
+    set_priority(2);
+    let counts = [wait_child(pids[0]), wait_child(pids[1])];
+    assert!(counts[0] > 0 && counts[1] > 0);
+    let ratio = counts[1] as usize * 100 / counts[0] as usize;
+    println!(
+        "count(8) = {}, count(2) = {}, ratio = {}%",
+        counts[0], counts[1], ratio
+    );
+    assert!((15..=40).contains(&ratio));
+    println!("Test nice OK!");
+    0
+}
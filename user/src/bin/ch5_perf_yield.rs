@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{perf_read, yield_, PerfStat, ESRCH, PERF_UNSUPPORTED};
+
+/// 用进程自己的周期数与指令数计数测量一次 sys_yield 的开销：
+/// 先预热一轮，再测 5 轮，每轮 1000 次，输出每次的周期数与指令数，
+/// 各轮每次的周期数相差不应超过 10%。硬件未实现的计数读出为 PERF_UNSUPPORTED。
+/// 正确输出：Test perf yield OK!
+
+const ROUNDS: usize = 5;
+const YIELDS: usize = 1000;
+
+fn counters() -> PerfStat {
+    let mut stat = PerfStat::default();
+    assert_eq!(perf_read(0, &mut stat), 0);
+    stat
+}
+
+/// Cycles and instructions per `sys_yield` over one round
+fn round() -> (usize, usize) {
+    let before = counters();
+    for _ in 0..YIELDS {
+        yield_();
+    }
+    let after = counters();
+    assert!(after.cycles >= before.cycles && after.instret >= before.instret);
+    (
+        (after.cycles - before.cycles) / YIELDS,
+        (after.instret - before.instret) / YIELDS,
+    )
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut stat = PerfStat::default();
+    assert_eq!(perf_read(usize::MAX >> 1, &mut stat), -ESRCH);
+    let stat = counters();
+    if stat.cycles == PERF_UNSUPPORTED {
+        println!("cycle counter not implemented, nothing to measure");
+        println!("Test perf yield OK!");
+        return 0;
+    }
+    let instret_supported = stat.instret != PERF_UNSUPPORTED;
+    round();
+    let mut cycles = [0usize; ROUNDS];
+    for i in 0..ROUNDS {
+        let (c, n) = round();
+        cycles[i] = c;
+        if instret_supported {
+            println!("round {}: {} cycles, {} instructions per yield", i, c, n);
+        } else {
+            println!("round {}: {} cycles per yield", i, c);
+        }
+    }
+    let min = *cycles.iter().min().unwrap();
+    let max = *cycles.iter().max().unwrap();
+    assert!(min > 0);
+    assert!(max * 100 <= min * 110, "cycles per yield vary from {} to {}", min, max);
+    println!("Test perf yield OK!");
+    0
+}
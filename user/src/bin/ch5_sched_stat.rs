@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, get_time, getpid, sched_stat, set_priority, waitpid, SchedStat, ESRCH};
+
+/// 两个优先级分别为 4 和 12 的进程同时自旋，
+/// 它们被调度的次数之比应约为 1:3，并检查全局统计。
+/// 正确输出：Test sched stat OK!
+
+fn spin_delay() {
+    let mut j = true;
+    for _ in 0..10 {
+        j = !j;
+    }
+}
+
+const MAX_TIME: isize = 2000;
+
+fn spin_until(deadline: isize) {
+    let mut acc = 0;
+    loop {
+        spin_delay();
+        acc += 1;
+        if acc % 400 == 0 && get_time() > deadline {
+            return;
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    set_priority(2);
+    let deadline = get_time() + MAX_TIME;
+    let mut pids = [0isize; 2];
+    for (i, prio) in [4isize, 12].iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            set_priority(*prio);
+            spin_until(deadline);
+            let mut stat = SchedStat::default();
+            assert_eq!(sched_stat(getpid() as usize, &mut stat), 0);
+            assert!(stat.preemptions > 0);
+            exit(stat.dispatches as i32);
+        }
+        pids[i] = pid;
+    }
+    let mut dispatches = [0i32; 2];
+    for i in 0..2 {
+        assert_eq!(waitpid(pids[i] as usize, &mut dispatches[i]), pids[i]);
+    }
+    let ratio = dispatches[1] as usize * 100 / dispatches[0] as usize;
+    println!(
+        "dispatches(4) = {}, dispatches(12) = {}, ratio = {}%",
+        dispatches[0], dispatches[1], ratio
+    );
+    assert!((200..=400).contains(&ratio));
+    let mut global = SchedStat::default();
+    assert_eq!(sched_stat(0, &mut global), 0);
+    assert!(global.dispatches >= (dispatches[0] + dispatches[1]) as usize);
+    assert!(global.context_switches > 0);
+    assert!(global.timer_interrupts > 0);
+    // 已退出的进程没有统计
+    assert_eq!(sched_stat(pids[0] as usize, &mut global), -ESRCH);
+    println!("Test sched stat OK!");
+    0
+}
@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use user_lib::{get_time, setitimer, sigaction, sigreturn, ITIMER_REAL, SIGALRM, SIG_DFL};
+
+/// 注册 SIGALRM 处理函数后设置 100ms 的定时器并忙等，处理函数应在允许误差内被调用；
+/// 随后测试周期定时器与取消定时器。
+/// 正确输出：Test setitimer OK!
+
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+static FIRED_AT: AtomicIsize = AtomicIsize::new(0);
+
+fn on_alarm(signum: i32) {
+    assert_eq!(signum, SIGALRM);
+    if ALARMS.fetch_add(1, Ordering::SeqCst) == 0 {
+        FIRED_AT.store(get_time(), Ordering::SeqCst);
+    }
+    sigreturn();
+}
+
+/// 忙等直到收到 `n` 次 SIGALRM，超过 `timeout_ms` 则失败
+fn spin_until(n: usize, timeout_ms: isize) {
+    let start = get_time();
+    while ALARMS.load(Ordering::SeqCst) < n {
+        assert!(get_time() - start < timeout_ms, "SIGALRM not delivered");
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(sigaction(SIGALRM, on_alarm), SIG_DFL as isize);
+    // 单次定时器
+    let start = get_time();
+    assert_eq!(setitimer(ITIMER_REAL, 0, 100), 0);
+    spin_until(1, 1000);
+    let elapsed = FIRED_AT.load(Ordering::SeqCst) - start;
+    assert!(elapsed >= 100 && elapsed < 200, "fired after {} ms", elapsed);
+    // 单次定时器不会再次触发
+    let t = get_time();
+    while get_time() - t < 200 {}
+    assert_eq!(ALARMS.load(Ordering::SeqCst), 1);
+    // 周期定时器，每 50ms 触发一次
+    assert_eq!(setitimer(ITIMER_REAL, 50, 50), 0);
+    spin_until(4, 1000);
+    // 取消定时器，返回剩余时间
+    let left = setitimer(ITIMER_REAL, 0, 0);
+    assert!(left >= 0 && left <= 50);
+    let fired = ALARMS.load(Ordering::SeqCst);
+    let t = get_time();
+    while get_time() - t < 200 {}
+    assert_eq!(ALARMS.load(Ordering::SeqCst), fired);
+    // 替换定时器返回旧定时器的剩余时间
+    setitimer(ITIMER_REAL, 0, 1000);
+    let left = setitimer(ITIMER_REAL, 0, 0);
+    assert!(left > 900 && left <= 1000);
+    println!("Test setitimer OK!");
+    0
+}
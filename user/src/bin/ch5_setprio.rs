@@ -3,7 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
-use user_lib::set_priority;
+use user_lib::{get_priority, set_priority, EINVAL};
 
 /// 正确输出：（无报错信息）
 /// Test set_priority OK!
@@ -11,10 +11,16 @@ use user_lib::set_priority;
 #[no_mangle]
 pub fn main() -> i32 {
     assert_eq!(set_priority(10), 10);
-    assert_eq!(set_priority(isize::MAX), isize::MAX);
-    assert_eq!(set_priority(0), -1);
-    assert_eq!(set_priority(1), -1);
-    assert_eq!(set_priority(-10), -1);
+    assert_eq!(get_priority(), 10);
+    assert_eq!(set_priority(60000), 60000);
+    assert_eq!(set_priority(60001), -EINVAL);
+    assert_eq!(set_priority(isize::MAX), -EINVAL);
+    assert_eq!(get_priority(), 60000);
+    assert_eq!(set_priority(2), 2);
+    assert_eq!(set_priority(0), -EINVAL);
+    assert_eq!(set_priority(1), -EINVAL);
+    assert_eq!(set_priority(-10), -EINVAL);
+    assert_eq!(get_priority(), 2);
     println!("Test set_priority OK!");
     0
 }
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, get_time, set_priority, waitpid};
+
+/// 8 个优先级为 16 的进程自旋时，优先级为 2 的后台进程仍应有可观的进展。
+/// 正确输出：Test stride aging OK!
+
+fn spin_delay() {
+    let mut j = true;
+    for _ in 0..10 {
+        j = !j;
+    }
+}
+
+const MAX_TIME: isize = 2000;
+const SPINNERS: usize = 8;
+
+fn count_until(deadline: isize) -> i32 {
+    let mut acc = 0;
+    loop {
+        spin_delay();
+        acc += 1;
+        if acc % 400 == 0 && get_time() > deadline {
+            return acc;
+        }
+    }
+}
+
+fn fork_counter(prio: isize, deadline: isize) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        set_priority(prio);
+        exit(count_until(deadline));
+    }
+    pid as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    set_priority(2);
+    let deadline = get_time() + MAX_TIME;
+    let background = fork_counter(2, deadline);
+    let mut spinners = [0usize; SPINNERS];
+    for pid in spinners.iter_mut() {
+        *pid = fork_counter(16, deadline);
+    }
+    let mut total = 0usize;
+    for pid in spinners.iter() {
+        let mut count: i32 = 0;
+        assert_eq!(waitpid(*pid, &mut count), *pid as isize);
+        total += count as usize;
+    }
+    let mut background_count: i32 = 0;
+    assert_eq!(waitpid(background, &mut background_count), background as isize);
+    let average = total / SPINNERS;
+    println!(
+        "average count(16) = {}, count(2) = {}",
+        average, background_count
+    );
+    // a fair share would be 1/8 of a spinner, accept anything above 1/32
+    assert!(background_count as usize * 32 > average);
+    println!("Test stride aging OK!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{fork, get_time, set_priority, waitpid, exit};
+
+/// 两个优先级分别为 2 和 10 的进程同时自旋，
+/// 它们在同一时间窗口内的计数之比应约为 1:5。
+/// 正确输出：Test stride ratio OK!
+
+fn spin_delay() {
+    let mut j = true;
+    for _ in 0..10 {
+        j = !j;
+    }
+}
+
+const MAX_TIME: isize = 2000;
+
+fn count_until(deadline: isize) -> i32 {
+    let mut acc = 0;
+    loop {
+        spin_delay();
+        acc += 1;
+        if acc % 400 == 0 && get_time() > deadline {
+            return acc;
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the parent only waits, keep it out of the way of the spinners
+    set_priority(2);
+    let deadline = get_time() + MAX_TIME;
+    let mut pids = [0isize; 2];
+    for (i, prio) in [2isize, 10].iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            set_priority(*prio);
+            exit(count_until(deadline));
+        }
+        pids[i] = pid;
+    }
+    let mut counts = [0i32; 2];
+    for i in 0..2 {
+        assert_eq!(waitpid(pids[i] as usize, &mut counts[i]), pids[i]);
+    }
+    let ratio = counts[1] as usize * 100 / counts[0] as usize;
+    println!("count(2) = {}, count(10) = {}, ratio = {}%", counts[0], counts[1], ratio);
+    assert!((350..=650).contains(&ratio));
+    println!("Test stride ratio OK!");
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, get_time, waitpid, yield_};
+
+/// 3 个自旋进程与 1 个频繁 yield 的进程优先级相同。yield 的进程每次只运行很短时间，
+/// 只应被收取一小部分 stride，因此每轮调度中能被多次选中，而不是与自旋进程一样每轮一次。
+/// 正确输出：Test stride yield OK!
+
+const SPINNERS: usize = 3;
+const MAX_TIME: isize = 1000;
+/// 时钟中断间隔（毫秒），即自旋进程的时间片
+const TICK_MS: isize = 10;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let deadline = get_time() + MAX_TIME;
+    let mut pids = [0usize; SPINNERS];
+    for pid in pids.iter_mut() {
+        let child = fork();
+        if child == 0 {
+            while get_time() < deadline {}
+            exit(0);
+        }
+        *pid = child as usize;
+    }
+    let start = get_time();
+    let mut yields = 0;
+    let mut max_gap = 0;
+    let mut last = start;
+    while get_time() < deadline {
+        yield_();
+        yields += 1;
+        let now = get_time();
+        max_gap = max_gap.max(now - last);
+        last = now;
+    }
+    let elapsed = get_time() - start;
+    for pid in pids {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+    }
+    // 每次运行都收取整个 stride 时，每轮 SPINNERS + 1 个时间片只能 yield 一次
+    let full_charge_yields = elapsed / TICK_MS / (SPINNERS as isize + 1);
+    println!(
+        "yields = {}, full stride charging would allow {}, max gap = {}ms",
+        yields, full_charge_yields, max_gap
+    );
+    assert!(yields > 3 * full_charge_yields);
+    // 可运行时不会被饿死：最长等待不超过一轮所有自旋进程的时间片
+    assert!(max_gap <= (SPINNERS as isize + 1) * TICK_MS);
+    println!("Test stride yield OK!");
+    0
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{exit, fork, get_name, set_name, waitpid};
+
+/// 测试进程名的设置、读取、截断以及 fork 后的默认名。
+/// 正确输出：Test task name OK!
+
+fn name_is(expected: &str) -> bool {
+    let mut buf = [0u8; 64];
+    let len = get_name(&mut buf) as usize;
+    &buf[..len] == expected.as_bytes() && buf[len] == 0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert!(name_is("ch5_task_name"));
+    assert_eq!(set_name("renamed\0"), 0);
+    assert!(name_is("renamed"));
+    // 名字过长时截断为 32 字节
+    set_name("0123456789abcdef0123456789abcdef_tail\0");
+    assert!(name_is("0123456789abcdef0123456789abcdef"));
+    // 缓冲区不足时截断并保留结尾的 0
+    let mut small = [0xffu8; 5];
+    assert_eq!(get_name(&mut small), 4);
+    assert_eq!(&small, b"0123\0");
+    set_name("parent\0");
+    let pid = fork();
+    if pid == 0 {
+        exit(if name_is("fork of parent") { 0 } else { 1 });
+    }
+    let mut exit_code: i32 = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("Test task name OK!");
+    0
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, get_time, getpid, pipe, poll, setitimer, sigaction, sigreturn,
+    sleep_blocking, wait, write, PollFd, ITIMER_REAL, POLLIN, SIGALRM, SIG_DFL,
+};
+
+/// 以随机的期限设置并立即取消 10000 个定时器：ITIMER_REAL 设置后用 setitimer(0) 取消，
+/// 带超时的 poll 在管道可读时立即返回并取消其超时。被取消的定时器都不应触发 SIGALRM。
+/// 随后 16 个子进程同时以随机期限设置定时器并睡眠，每个睡眠不早于期限结束，未取消的
+/// 定时器恰好触发一次。
+/// 正确输出：Test timer stress OK!
+
+const TIMERS: usize = 10000;
+const CHILDREN: usize = 16;
+/// 随机期限的上限，毫秒
+const MAX_DEADLINE: usize = 2000;
+
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+
+fn on_alarm(signum: i32) {
+    assert_eq!(signum, SIGALRM);
+    ALARMS.fetch_add(1, Ordering::SeqCst);
+    sigreturn();
+}
+
+/// xorshift 伪随机数
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(sigaction(SIGALRM, on_alarm), SIG_DFL as isize);
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(write(fds[1], b"x"), 1);
+    for i in 0..TIMERS {
+        let deadline = 1 + rng.below(MAX_DEADLINE);
+        if i % 2 == 0 {
+            assert_eq!(setitimer(ITIMER_REAL, 0, deadline), 0);
+            let left = setitimer(ITIMER_REAL, 0, 0);
+            assert!(left >= 0 && left as usize <= deadline);
+        } else {
+            let mut polls = [PollFd {
+                fd: fds[0] as i32,
+                events: POLLIN,
+                revents: 0,
+            }];
+            assert_eq!(poll(&mut polls, deadline as isize), 1);
+        }
+    }
+    close(fds[0]);
+    close(fds[1]);
+    // 所有被取消的期限都已过去
+    sleep_blocking(MAX_DEADLINE + 100);
+    assert_eq!(ALARMS.load(Ordering::SeqCst), 0);
+
+    for _ in 0..CHILDREN {
+        let alarm = 1 + rng.below(200);
+        let sleep = 1 + rng.below(200);
+        let pid = fork();
+        if pid == 0 {
+            assert_eq!(setitimer(ITIMER_REAL, 0, alarm), 0);
+            let start = get_time();
+            sleep_blocking(sleep);
+            let slept = (get_time() - start) as usize;
+            assert!(
+                slept >= sleep,
+                "pid {} slept {} < {} ms",
+                getpid(),
+                slept,
+                sleep
+            );
+            // 等到定时器到期之后
+            while (get_time() - start) as usize <= alarm + 20 {
+                sleep_blocking(10);
+            }
+            exit(ALARMS.load(Ordering::SeqCst) as i32);
+        }
+        assert!(pid > 0);
+    }
+    for _ in 0..CHILDREN {
+        let mut exit_code = 0;
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 1);
+    }
+    println!("Test timer stress OK!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, syscall, times, uname, waitpid, Tms, Utsname, EFAULT, SYSCALL_TIMES,
+};
+
+/// times：以时钟节拍（uname 的 clock_ticks，每秒节拍数）报告本进程的用户态与内核态时间，
+/// 以及已被 waitpid 回收的子进程（连同它们回收的子进程）的时间。子进程忙等约 200ms，
+/// 父进程回收后 cutime + cstime 增长约 200ms 对应的节拍数，自身的 utime 仍很小；
+/// 多次调用得到的各项时间只增不减。
+/// 正确输出：Test times OK!
+
+const BUSY_MS: isize = 200;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut uts = Utsname::new();
+    assert_eq!(uname(&mut uts), 0);
+    let hz = uts.clock_ticks as usize;
+    assert!(hz > 0);
+    let ticks = |ms: isize| ms as usize * hz / 1000;
+
+    let mut before = Tms::default();
+    let start = times(&mut before);
+    assert!(start >= 0);
+    // 只增不减
+    let mut last = before;
+    for _ in 0..100 {
+        let mut now = Tms::default();
+        assert!(times(&mut now) >= start);
+        assert!(now.utime >= last.utime && now.stime >= last.stime);
+        assert!(now.cutime == last.cutime && now.cstime == last.cstime);
+        last = now;
+    }
+    // 地址无效
+    assert_eq!(syscall(SYSCALL_TIMES, [0, 0, 0]), -EFAULT);
+
+    let pid = fork();
+    if pid == 0 {
+        let deadline = get_time() + BUSY_MS;
+        while get_time() < deadline {}
+        exit(0);
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let mut after = Tms::default();
+    let end = times(&mut after);
+    let children = after.cutime + after.cstime - before.cutime - before.cstime;
+    println!(
+        "child {} ticks, own utime {} stime {} ticks, {} ticks passed, {} a second",
+        children,
+        after.utime - before.utime,
+        after.stime - before.stime,
+        end - start,
+        hz
+    );
+    assert!(
+        children >= ticks(BUSY_MS * 3 / 4),
+        "child ran {} ticks",
+        children
+    );
+    assert!(children <= (end - start) as usize + 1);
+    assert!(after.utime - before.utime < ticks(BUSY_MS / 4));
+    println!("Test times OK!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, sched_stat, shmat, shmget, sleep_blocking, waitpid, yield_,
+    SchedStat, EAGAIN, ETIMEDOUT, IPC_PRIVATE,
+};
+
+/// 同一个任务同时被定时器超时和显式唤醒：子进程带 10ms 超时在 futex 上等待，父进程在
+/// 0~15ms 之间的不同时刻唤醒它，两个唤醒相互竞争。只有先到的一个把任务从 Blocked 改为
+/// Ready 并放入就绪队列，任务只入队一次；内核发现重复入队或状态不对时计入全局统计的
+/// state_violations（调试构建下直接 panic），本测试前后该计数不变。
+/// 正确输出：Test wake race OK!
+
+const ROUNDS: u32 = 48;
+const TIMEOUT_MS: isize = 10;
+
+#[repr(C)]
+struct Shared {
+    /// 子进程等待的字，唤醒前改为下一轮的编号
+    word: AtomicU32,
+    /// 父进程开始第几轮
+    go: AtomicU32,
+    /// 子进程完成了几轮
+    done: AtomicU32,
+}
+
+fn violations() -> usize {
+    let mut stat = SchedStat::default();
+    assert_eq!(sched_stat(0, &mut stat), 0);
+    stat.state_violations
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let id = shmget(IPC_PRIVATE, 4096);
+    assert!(id >= 0);
+    let addr = shmat(id as usize);
+    assert!(addr > 0);
+    let shared = unsafe { &*(addr as *const Shared) };
+    let before = violations();
+
+    let pid = fork();
+    if pid == 0 {
+        for round in 0..ROUNDS {
+            while shared.go.load(Ordering::SeqCst) != round {
+                yield_();
+            }
+            let ret = futex_wait(&shared.word, round, TIMEOUT_MS);
+            assert!(ret == 0 || ret == -ETIMEDOUT || ret == -EAGAIN, "{}", ret);
+            shared.done.store(round + 1, Ordering::SeqCst);
+        }
+        exit(0);
+    }
+    let (mut woken, mut timed_out) = (0, 0);
+    for round in 0..ROUNDS {
+        shared.go.store(round, Ordering::SeqCst);
+        // 唤醒落在超时之前、附近和之后
+        sleep_blocking((round % 16) as usize);
+        shared.word.store(round + 1, Ordering::SeqCst);
+        match futex_wake(&shared.word, 1) {
+            1 => woken += 1,
+            _ => timed_out += 1,
+        }
+        while shared.done.load(Ordering::SeqCst) != round + 1 {
+            yield_();
+        }
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(violations(), before);
+    println!(
+        "{} woken, {} timed out or not waiting yet",
+        woken, timed_out
+    );
+    println!("Test wake race OK!");
+    0
+}
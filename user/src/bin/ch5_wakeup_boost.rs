@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, exit, fork, get_time, kill, pipe, read, sleep_blocking, waitpid, write, SIGKILL,
+};
+
+/// 4 个自旋进程占满 CPU 时，模拟一个交互程序：它阻塞在管道上等待“按键”，
+/// 每次被唤醒后先计算一段时间（因此 pass 领先于其他进程），再等待下一次。
+/// 从管道唤醒的进程会被提升到全局最小 pass，应在一两个时间片内运行，
+/// 而不必等自旋进程各跑完一轮。打印按键到响应的平均与最大延迟。
+/// 正确输出：Test wakeup boost OK!
+
+const SPINNERS: usize = 4;
+const KEYS: usize = 20;
+/// 两次按键的间隔（毫秒），长于唤醒提升的最小间隔
+const KEY_INTERVAL_MS: usize = 80;
+/// 每次按键后交互程序计算的时间（毫秒）
+const BURST_MS: isize = 20;
+/// 时钟中断间隔（毫秒），即自旋进程的时间片
+const TICK_MS: isize = 10;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0usize; SPINNERS];
+    for pid in pids.iter_mut() {
+        let child = fork();
+        if child == 0 {
+            loop {}
+        }
+        *pid = child as usize;
+    }
+
+    let mut keys = [0usize; 2];
+    let mut echo = [0usize; 2];
+    assert_eq!(pipe(&mut keys), 0);
+    assert_eq!(pipe(&mut echo), 0);
+    let shell = fork();
+    if shell == 0 {
+        close(keys[1]);
+        close(echo[0]);
+        let (mut total, mut max) = (0, 0);
+        let mut key = [0u8; 8];
+        for _ in 0..KEYS {
+            assert_eq!(read(keys[0], &mut key), 8);
+            let latency = get_time() - isize::from_le_bytes(key);
+            total += latency;
+            max = max.max(latency);
+            let start = get_time();
+            while get_time() < start + BURST_MS {}
+        }
+        let mut result = [0u8; 16];
+        result[..8].copy_from_slice(&total.to_le_bytes());
+        result[8..].copy_from_slice(&max.to_le_bytes());
+        assert_eq!(write(echo[1], &result), 16);
+        exit(0);
+    }
+    close(keys[0]);
+    close(echo[1]);
+    for _ in 0..KEYS {
+        sleep_blocking(KEY_INTERVAL_MS);
+        assert_eq!(write(keys[1], &get_time().to_le_bytes()), 8);
+    }
+    let mut result = [0u8; 16];
+    assert_eq!(read(echo[0], &mut result), 16);
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&result[..8]);
+    let total = isize::from_le_bytes(bytes);
+    bytes.copy_from_slice(&result[8..]);
+    let max = isize::from_le_bytes(bytes);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(shell as usize, &mut exit_code), shell);
+    assert_eq!(exit_code, 0);
+    for pid in pids {
+        assert_eq!(kill(pid, SIGKILL), 0);
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+    }
+    println!(
+        "key to echo latency with {} spinners: mean {}ms, max {}ms",
+        SPINNERS,
+        total / KEYS as isize,
+        max
+    );
+    // 一轮自旋进程要 SPINNERS 个时间片，被提升的进程不必等一轮
+    assert!(max < SPINNERS as isize * TICK_MS);
+    println!("Test wakeup boost OK!");
+    0
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, get_time, setitimer, sigaction, sigreturn, sleep_blocking, waitpid, yield_,
+    ITIMER_REAL, SIGALRM,
+};
+
+/// 一组进程不停地 yield，使就绪队列频繁进出；另一组进程反复 sleep_blocking 1 ms，
+/// 由时钟中断唤醒后重新入队；还有一个进程每 1 ms 收到一次 SIGALRM。
+/// 时钟中断与就绪队列的操作交织在一起，内核不应崩溃，所有进程都应正常结束。
+/// 正确输出：Test wakeup stress OK!
+
+const YIELDERS: usize = 4;
+const SLEEPERS: usize = 4;
+const YIELDS: usize = 5000;
+const SLEEPS: usize = 200;
+const ALARMS: usize = 100;
+
+static ALARMS_TAKEN: AtomicUsize = AtomicUsize::new(0);
+
+fn on_alarm(_signum: i32) {
+    ALARMS_TAKEN.fetch_add(1, Ordering::SeqCst);
+    sigreturn();
+}
+
+fn yielder() -> ! {
+    for _ in 0..YIELDS {
+        yield_();
+    }
+    exit(0)
+}
+
+fn sleeper() -> ! {
+    for _ in 0..SLEEPS {
+        let start = get_time();
+        sleep_blocking(1);
+        assert!(get_time() >= start + 1);
+    }
+    exit(0)
+}
+
+fn alarmed() -> ! {
+    sigaction(SIGALRM, on_alarm);
+    setitimer(ITIMER_REAL, 1, 1);
+    while ALARMS_TAKEN.load(Ordering::SeqCst) < ALARMS {
+        yield_();
+    }
+    setitimer(ITIMER_REAL, 0, 0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0isize; YIELDERS + SLEEPERS + 1];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            if i < YIELDERS {
+                yielder();
+            } else if i < YIELDERS + SLEEPERS {
+                sleeper();
+            } else {
+                alarmed();
+            }
+        }
+        assert!(*pid > 0);
+    }
+    for pid in pids.iter() {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(*pid as usize, &mut exit_code), *pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("Test wakeup stress OK!");
+    0
+}
@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::get_priority;
+
+/// 由 ch5_nice 通过 exec 运行：以自己的优先级作为退出码，exec 前后的优先级应相同。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    get_priority() as i32
+}
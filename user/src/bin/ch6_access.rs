@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, getdents, mkdir, open, pipe, read, rmdir, unlink, write, OpenFlags, STDOUT,
+};
+
+/// 目录只能只读打开，用于 getdents；可写打开返回 -21（EISDIR），对其 read/write 也返回 -21。
+/// 对只写打开的描述符 read、只读打开的描述符 write 返回 -9（EBADF），管道两端与标准输出同样。
+/// 打开不存在的文件返回 -2（ENOENT）。
+/// 正确输出：Test access OK!
+
+const DIR: &str = "access_dir\0";
+const FILE: &str = "access_tmp\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 64];
+
+    assert_eq!(mkdir(DIR), 0);
+    assert_eq!(open(DIR, OpenFlags::WRONLY), -EISDIR);
+    assert_eq!(open(DIR, OpenFlags::RDWR), -EISDIR);
+    assert_eq!(open(DIR, OpenFlags::CREATE | OpenFlags::WRONLY), -EISDIR);
+    let dir = open(DIR, OpenFlags::RDONLY);
+    assert!(dir > 0);
+    let dir = dir as usize;
+    assert_eq!(read(dir, &mut buf), -EISDIR);
+    assert_eq!(write(dir, b"dirent"), -EISDIR);
+    assert!(getdents(dir, &mut buf) >= 0);
+    close(dir);
+    assert_eq!(rmdir(DIR), 0);
+
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"data"), 4);
+    assert_eq!(read(fd, &mut buf), -EBADF);
+    close(fd);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"more"), -EBADF);
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf[..4], b"data");
+    close(fd);
+    assert_eq!(unlink(FILE), 0);
+    assert_eq!(open(FILE, OpenFlags::RDONLY), -ENOENT);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(write(pipe_fd[0], b"x"), -EBADF);
+    assert_eq!(read(pipe_fd[1], &mut buf), -EBADF);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(read(STDOUT, &mut buf), -EBADF);
+
+    println!("Test access OK!");
+    0
+}
@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, open, read, syscall, unlink, wait, write, yield_, OpenFlags,
+    SYSCALL_OPENAT,
+};
+
+/// 以 APPEND 打开的文件每次写入都追加到末尾；以 CREATE | APPEND 打开已存在的文件不会清空它，
+/// 只读打开时 TRUNC 不起作用，可写打开时清空文件；未知的标志位返回 -22（EINVAL）。
+/// 两个子进程同时以整行为单位追加写入，文件中每一行都完整，不会交错。
+/// 正确输出：Test append OK!
+
+const FILE: &str = "append_log\0";
+const CHILDREN: usize = 2;
+const LINES: usize = 100;
+/// 每行的长度，栈上的行缓冲区可能跨页
+const LINE_LEN: usize = 200;
+
+/// 文件的全部内容
+fn content() -> Vec<u8> {
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    data
+}
+
+fn write_file(flags: OpenFlags, data: &[u8]) {
+    let fd = open(FILE, flags);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+/// 第 `child` 个子进程写入的一行，全部由同一个字符组成，以换行结尾
+fn line(child: usize) -> [u8; LINE_LEN] {
+    let mut line = [b'a' + child as u8; LINE_LEN];
+    line[LINE_LEN - 1] = b'\n';
+    line
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    write_file(OpenFlags::CREATE | OpenFlags::WRONLY, b"start\n");
+    // 即使偏移量在文件开头，APPEND 也写到末尾
+    write_file(OpenFlags::WRONLY | OpenFlags::APPEND, b"one\n");
+    write_file(OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::APPEND, b"two\n");
+    assert_eq!(content(), b"start\none\ntwo\n");
+    // 读写打开时，读取的位置跟在追加的内容之后
+    let fd = open(FILE, OpenFlags::RDWR | OpenFlags::APPEND);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"three\n"), 6);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd as usize, &mut buf), 0);
+    close(fd as usize);
+
+    // 只读打开时 TRUNC 不起作用
+    let fd = open(FILE, OpenFlags::RDONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(content(), b"start\none\ntwo\nthree\n");
+    write_file(OpenFlags::WRONLY | OpenFlags::TRUNC, b"new\n");
+    assert_eq!(content(), b"new\n");
+    write_file(OpenFlags::WRONLY | OpenFlags::TRUNC, b"");
+    assert!(content().is_empty());
+
+    // 未知的标志位
+    assert_eq!(syscall(SYSCALL_OPENAT, [0, FILE.as_ptr() as usize, 1 << 20]), -22);
+
+    // 并发追加
+    for child in 0..CHILDREN {
+        let pid = fork();
+        if pid == 0 {
+            let fd = open(FILE, OpenFlags::WRONLY | OpenFlags::APPEND);
+            assert!(fd > 0);
+            let line = line(child);
+            for _ in 0..LINES {
+                assert_eq!(write(fd as usize, &line), LINE_LEN as isize);
+                yield_();
+            }
+            close(fd as usize);
+            exit(0);
+        }
+        assert!(pid > 0);
+    }
+    for _ in 0..CHILDREN {
+        let mut exit_code = -1;
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    let data = content();
+    assert_eq!(data.len(), CHILDREN * LINES * LINE_LEN);
+    let mut counts = [0usize; CHILDREN];
+    for written in data.chunks(LINE_LEN) {
+        let child = (0..CHILDREN)
+            .find(|&child| written == line(child))
+            .expect("interleaved line");
+        counts[child] += 1;
+    }
+    assert!(counts.iter().all(|&count| count == LINES));
+
+    assert_eq!(unlink(FILE), 0);
+    println!("Test append OK!");
+    0
+}
@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, lseek, open, read, unlink, waitpid, write, yield_, OpenFlags, SEEK_SET,
+};
+
+/// 多个进程同时读写各自的文件，块设备上同时有多个请求。每个进程在内存中保留
+/// 文件内容的副本，随机写入并随机读出与副本比较，最后整个读回比较。
+/// 文件远大于块缓存，读写大多要访问磁盘。等待磁盘的进程睡眠，由中断唤醒：
+/// 父进程在它们运行期间查看 /proc/<pid>/stat，应至少看到一次睡眠状态 S。
+/// 正确输出：Test blk stress OK!
+
+const TASKS: usize = 4;
+const FILE_SIZE: usize = 24 * 512;
+const OPS: usize = 200;
+
+/// Linear congruential generator, a different sequence for each task
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize
+    }
+}
+
+fn read_exact(fd: usize, offset: usize, buf: &mut [u8]) {
+    assert_eq!(lseek(fd, offset as isize, SEEK_SET), offset as isize);
+    assert_eq!(read(fd, buf), buf.len() as isize);
+}
+
+/// Random reads and writes on our own file, checked against `oracle`
+fn hammer(id: usize) -> i32 {
+    let name = format!("blk_stress_{}\0", id);
+    let mut rng = Rng(id as u64 + 1);
+    let mut oracle = vec![0u8; FILE_SIZE];
+    oracle.iter_mut().enumerate().for_each(|(i, byte)| *byte = (i * (id + 1)) as u8);
+    let fd = open(&name, OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &oracle), FILE_SIZE as isize);
+    let mut buf = vec![0u8; 4096];
+    for op in 0..OPS {
+        let offset = rng.next() % FILE_SIZE;
+        let len = 1 + rng.next() % (FILE_SIZE - offset).min(buf.len());
+        let buf = &mut buf[..len];
+        if rng.next() % 2 == 0 {
+            buf.fill((id * OPS + op) as u8);
+            assert_eq!(lseek(fd, offset as isize, SEEK_SET), offset as isize);
+            assert_eq!(write(fd, buf), len as isize);
+            oracle[offset..offset + len].copy_from_slice(buf);
+        } else {
+            read_exact(fd, offset, buf);
+            assert!(buf[..] == oracle[offset..offset + len], "task {} op {}", id, op);
+        }
+    }
+    let mut content = vec![0u8; FILE_SIZE];
+    read_exact(fd, 0, &mut content);
+    assert!(content == oracle, "task {} final content", id);
+    close(fd);
+    assert_eq!(unlink(&name), 0);
+    0
+}
+
+/// The state letter in /proc/<pid>/stat, None once the task has exited
+fn task_state(pid: isize) -> Option<char> {
+    let fd = open(&format!("/proc/{}/stat\0", pid), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 128];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    assert!(len > 0);
+    let stat = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    // after the name: state, priority, ...
+    stat.rsplit(')').next().unwrap().trim_start().chars().next()
+}
+
+/// Whether one of `children` is seen asleep before they have all exited.
+/// They only ever wait for the disk.
+fn saw_sleeping(children: &[isize]) -> bool {
+    loop {
+        let states: Vec<Option<char>> = children.iter().map(|pid| task_state(*pid)).collect();
+        if states.contains(&Some('S')) {
+            return true;
+        }
+        if states.iter().all(|state| state.map_or(true, |state| state == 'Z')) {
+            return false;
+        }
+        yield_();
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut children = [0isize; TASKS];
+    for (id, child) in children.iter_mut().enumerate() {
+        *child = fork();
+        if *child == 0 {
+            exit(hammer(id));
+        }
+        assert!(*child > 0);
+    }
+    assert!(saw_sleeping(&children), "no task slept waiting for the disk");
+    for child in children.iter() {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(*child as usize, &mut exit_code), *child);
+        assert_eq!(exit_code, 0);
+    }
+    println!("Test blk stress OK!");
+    0
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    chmod, close, fchmod, fstat, open, read, spawn, unlink, waitpid, write, OpenFlags, Stat,
+};
+
+/// 新建文件的权限为 0o644，应用为 0o755。去掉写权限后以可写方式打开返回 -13（EACCES），且不会清空文件；
+/// chmod 与 fchmod 修改权限，fstat 报告在 perm 中。去掉执行权限的应用不能 spawn，恢复后可以。
+/// 正确输出：Test chmod OK!
+
+const FILE: &str = "chmod_tmp\0";
+const APP: &str = "ch2b_hello_world\0";
+
+fn perm_of(fd: usize) -> u32 {
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    stat.perm
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"data"), 4);
+    assert_eq!(perm_of(fd as usize), 0o644);
+    close(fd as usize);
+
+    assert_eq!(chmod(FILE, 0o444), 0);
+    assert_eq!(open(FILE, OpenFlags::WRONLY), -EACCES);
+    assert_eq!(open(FILE, OpenFlags::RDWR), -EACCES);
+    assert_eq!(open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY), -EACCES);
+    assert_eq!(open(FILE, OpenFlags::WRONLY | OpenFlags::TRUNC), -EACCES);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(perm_of(fd), 0o444);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf[..4], b"data");
+    // bits beyond rwx are dropped
+    assert_eq!(fchmod(fd, 0o4600), 0);
+    assert_eq!(perm_of(fd), 0o600);
+    close(fd);
+    let fd = open(FILE, OpenFlags::WRONLY | OpenFlags::APPEND);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(chmod("chmod_none\0", 0o644), -ENOENT);
+    assert_eq!(unlink(FILE), 0);
+
+    let fd = open(APP, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(perm_of(fd as usize), 0o755);
+    close(fd as usize);
+    assert_eq!(chmod(APP, 0o644), 0);
+    assert_eq!(spawn(APP), -EACCES);
+    assert_eq!(chmod(APP, 0o755), 0);
+    let pid = spawn(APP);
+    assert!(pid > 0);
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("Test chmod OK!");
+    0
+}
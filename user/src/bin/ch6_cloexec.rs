@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, exec, exit, fcntl, fork, pipe, read, waitpid, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD,
+    F_SETFD, FD_CLOEXEC,
+};
+
+/// F_DUPFD 复制到不小于参数的最小空闲描述符。FD_CLOEXEC 属于描述符本身，dup 得到的描述符
+/// 不带它。exec 关闭设置了 FD_CLOEXEC 的管道写端，被执行的 ch6b_cloexec_check 读该管道得到 0，
+/// 没有设置的写端仍可写入。
+/// 正确输出：Test cloexec OK!
+
+/// 一直读到所有写端关闭，返回读到的字节数
+fn read_all(fd: usize, buf: &mut [u8]) -> usize {
+    let mut total = 0;
+    loop {
+        let len = read(fd, &mut buf[total..]);
+        assert!(len >= 0);
+        if len == 0 {
+            return total;
+        }
+        total += len as usize;
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut a = [0usize; 2];
+    let mut b = [0usize; 2];
+    assert_eq!(pipe(&mut a), 0);
+    assert_eq!(pipe(&mut b), 0);
+    // 放到 ch6b_cloexec_check 约定的位置
+    assert_eq!(fcntl(a[0], F_DUPFD, 10), 10);
+    assert_eq!(fcntl(a[1], F_DUPFD, 10), 11);
+    assert_eq!(fcntl(b[1], F_DUPFD, 12), 12);
+    for fd in [a[0], a[1], b[1]] {
+        close(fd);
+    }
+    assert_eq!(fcntl(11, F_GETFD, 0), 0);
+    assert_eq!(fcntl(11, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(11, F_GETFD, 0), FD_CLOEXEC as isize);
+    // dup 与 F_DUPFD 不复制 FD_CLOEXEC，F_DUPFD_CLOEXEC 设置它
+    let fd = dup(11);
+    assert!(fd > 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), 0);
+    close(fd as usize);
+    let fd = fcntl(12, F_DUPFD_CLOEXEC, 0);
+    assert!(fd > 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+    close(fd as usize);
+
+    let pid = fork();
+    if pid == 0 {
+        exec("ch6b_cloexec_check\0", &[0 as *const u8]);
+        exit(-4);
+    }
+    // 只剩子进程中的写端
+    close(11);
+    close(12);
+    let mut buf = [0u8; 16];
+    let len = read_all(b[0], &mut buf);
+    assert_eq!(&buf[..len], b"alive");
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(10);
+    close(b[0]);
+
+    println!("Test cloexec OK!");
+    0
+}
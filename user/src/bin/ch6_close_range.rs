@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::errno::*;
+use user_lib::{close, close_range, dup, open, read, unlink, write, OpenFlags};
+
+/// /proc/files 给出所有进程打开的文件描述符数与打开的文件数，/proc/self/fd 每行列出一个描述符：
+/// 描述符、类型、inode 号与偏移。打开 50 个文件后两个计数都增加 50，dup 只增加描述符数；
+/// close_range 关闭一段描述符，未打开的跳过，first 大于 last 时返回 -22（EINVAL）。关闭后计数回到原值。
+/// 正确输出：Test close_range OK!
+
+const FILES: usize = 50;
+
+fn read_proc(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0, "cannot open {}", path);
+    let mut content = String::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd as usize);
+    content
+}
+
+/// The descriptors and the open files of /proc/files
+fn counts() -> (usize, usize) {
+    let files = read_proc("/proc/files\0");
+    let value = |key: &str| -> usize {
+        let line = files.lines().find(|line| line.starts_with(key)).unwrap();
+        line[key.len()..].trim().parse().unwrap()
+    };
+    (value("OpenDescriptors:"), value("OpenInodeFiles:"))
+}
+
+/// The lines of /proc/self/fd
+fn fds() -> Vec<String> {
+    read_proc("/proc/self/fd\0")
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (base_fds, base_files) = counts();
+    let base_lines = fds();
+    assert!(base_lines.iter().any(|line| line.starts_with("1 chr ")));
+
+    let mut opened = Vec::new();
+    for _ in 0..FILES {
+        let fd = open("close_range_tmp\0", OpenFlags::CREATE | OpenFlags::RDWR);
+        assert!(fd > 0);
+        opened.push(fd as usize);
+    }
+    assert_eq!(write(opened[0], b"hello"), 5);
+    assert_eq!(counts(), (base_fds + FILES, base_files + FILES));
+    let copy = dup(opened[0]);
+    assert!(copy > 0);
+    assert_eq!(counts(), (base_fds + FILES + 1, base_files + FILES));
+    let lines = fds();
+    assert_eq!(lines.len(), base_lines.len() + FILES + 1);
+    // the copy shares the offset
+    let first = format!("{} file ", opened[0]);
+    let line = lines.iter().find(|line| line.starts_with(&first)).unwrap();
+    assert!(line.ends_with(" 5"), "{}", line);
+    let line = lines
+        .iter()
+        .find(|line| line.starts_with(&format!("{} file ", copy)))
+        .unwrap();
+    assert!(line.ends_with(" 5"), "{}", line);
+
+    assert_eq!(close_range(opened[1], opened[0]), -EINVAL);
+    let last = *opened.iter().max().unwrap().max(&(copy as usize));
+    // the range goes past the last descriptor open
+    assert_eq!(close_range(opened[0], last + 10), 0);
+    assert_eq!(counts(), (base_fds, base_files));
+    assert_eq!(fds(), base_lines);
+    assert!(close(opened[0]) < 0);
+    assert_eq!(close_range(opened[0], opened[0]), 0);
+    unlink("close_range_tmp\0");
+    println!("Test close_range OK!");
+    0
+}
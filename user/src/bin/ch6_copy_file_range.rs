@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, copy_file_range, get_time, lseek, open, pipe, read, task_info, unlink, write,
+    OpenFlags, TaskInfo, SEEK_CUR,
+};
+
+/// 比较两种复制 4 MiB 文件的方式：每次 512 字节的 read/write 循环，与在内核中复制的
+/// copy_file_range。后者的系统调用次数少得多，复制结果与源文件相同。给出偏移指针时使用并推进
+/// 指针处的偏移，文件偏移不变。管道返回 -22（EINVAL），同一文件中重叠的范围返回 -22，
+/// 目标只读打开返回 -9（EBADF）。
+/// 正确输出：Test copy_file_range OK!
+
+const SRC: &str = "cfr_src\0";
+const DST_RW: &str = "cfr_rw\0";
+const DST: &str = "cfr_dst\0";
+const SIZE: usize = 4 << 20;
+const CHUNK: usize = 512;
+const SYSCALL_READ: usize = 63;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_COPY_FILE_RANGE: usize = 285;
+
+fn syscall_count(ids: &[usize]) -> u32 {
+    let info = TaskInfo::new();
+    assert_eq!(task_info(&info), 0);
+    ids.iter().map(|id| info.syscall_times[*id]).sum()
+}
+
+fn open_new(path: &str) -> usize {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd > 0);
+    fd as usize
+}
+
+/// Whether the files at `a` and `b` hold the same bytes
+fn same_content(a: &str, b: &str) -> bool {
+    let (fa, fb) = (open(a, OpenFlags::RDONLY), open(b, OpenFlags::RDONLY));
+    assert!(fa > 0 && fb > 0);
+    let (mut buf_a, mut buf_b) = ([0u8; 4096], [0u8; 4096]);
+    let same = loop {
+        let len = read(fa as usize, &mut buf_a);
+        if len != read(fb as usize, &mut buf_b) || buf_a[..len as usize] != buf_b[..len as usize] {
+            break false;
+        }
+        if len == 0 {
+            break true;
+        }
+    };
+    close(fa as usize);
+    close(fb as usize);
+    same
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let src = open_new(SRC);
+    let mut buf = [0u8; 4096];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+    for _ in 0..SIZE / buf.len() {
+        assert_eq!(write(src, &buf), buf.len() as isize);
+    }
+    close(src);
+
+    // read/write
+    let (src, dst) = (open(SRC, OpenFlags::RDONLY) as usize, open_new(DST_RW));
+    let (count, start) = (syscall_count(&[SYSCALL_READ, SYSCALL_WRITE]), get_time());
+    let mut chunk = [0u8; CHUNK];
+    loop {
+        let len = read(src, &mut chunk);
+        if len == 0 {
+            break;
+        }
+        assert_eq!(write(dst, &chunk[..len as usize]), len);
+    }
+    let rw_time = get_time() - start;
+    let rw_calls = syscall_count(&[SYSCALL_READ, SYSCALL_WRITE]) - count;
+    close(src);
+    close(dst);
+
+    // copy_file_range
+    let (src, dst) = (open(SRC, OpenFlags::RDONLY) as usize, open_new(DST));
+    let (count, start) = (syscall_count(&[SYSCALL_COPY_FILE_RANGE]), get_time());
+    loop {
+        let len = copy_file_range(src, None, dst, None, SIZE);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+    }
+    let copy_time = get_time() - start;
+    let copy_calls = syscall_count(&[SYSCALL_COPY_FILE_RANGE]) - count;
+    assert_eq!(lseek(src, 0, SEEK_CUR), SIZE as isize);
+    assert_eq!(lseek(dst, 0, SEEK_CUR), SIZE as isize);
+    println!(
+        "copy 4 MiB: read/write {} syscalls {} ms, copy_file_range {} syscalls {} ms",
+        rw_calls, rw_time, copy_calls, copy_time
+    );
+    assert!(copy_calls * 100 < rw_calls);
+    assert!(same_content(SRC, DST_RW));
+    assert!(same_content(SRC, DST));
+
+    // 指定偏移：文件偏移不变
+    let (mut off_in, mut off_out) = (10isize, (SIZE + 5) as isize);
+    assert_eq!(copy_file_range(src, Some(&mut off_in), dst, Some(&mut off_out), 100), 100);
+    assert_eq!((off_in, off_out), (110, (SIZE + 105) as isize));
+    assert_eq!(lseek(src, 0, SEEK_CUR), SIZE as isize);
+    assert_eq!(lseek(dst, 0, SEEK_CUR), SIZE as isize);
+    // 源文件末尾
+    let mut off_in = SIZE as isize;
+    assert_eq!(copy_file_range(src, Some(&mut off_in), dst, None, 100), 0);
+
+    // 拒绝的情形
+    let mut off_in = 0isize;
+    let mut off_out = 50isize;
+    assert_eq!(copy_file_range(dst, Some(&mut off_in), dst, Some(&mut off_out), 100), -EINVAL);
+    assert_eq!(copy_file_range(dst, None, src, None, 100), -EBADF);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(copy_file_range(src, None, pipe_fd[1], None, 100), -EINVAL);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    close(src);
+    close(dst);
+    for path in [SRC, DST_RW, DST] {
+        assert_eq!(unlink(path), 0);
+    }
+
+    println!("Test copy_file_range OK!");
+    0
+}
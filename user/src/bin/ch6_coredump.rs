@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, exit, fork, kill, open, read, sleep, waitpid, OpenFlags, SIGQUIT, SIGSEGV,
+};
+
+/// 子进程访问空指针或收到 SIGQUIT 后，内核应写出 core.<pid>，检查其文件头。
+/// 正确输出：Test coredump OK!
+
+/// 读取 core.<pid> 的文件头：magic 与 pid, signal, scause, stval, sepc
+fn read_core_header(pid: usize) -> [u64; 5] {
+    let fd = open(format!("core.{}\0", pid).as_str(), OpenFlags::RDONLY);
+    assert!(fd > 0, "core.{} not found", pid);
+    let mut buffer = [0u8; 48];
+    assert_eq!(read(fd as usize, &mut buffer), 48);
+    close(fd as usize);
+    assert_eq!(&buffer[..8], b"RCORE\0\0\x02");
+    let mut header = [0u64; 5];
+    for (i, field) in header.iter_mut().enumerate() {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buffer[8 + i * 8..16 + i * 8]);
+        *field = u64::from_le_bytes(bytes);
+    }
+    header
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 访问空指针
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            (0usize as *mut u8).write_volatile(1);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    let header = read_core_header(pid as usize);
+    assert_eq!(header[0], pid as u64);
+    assert_eq!(header[1], SIGSEGV as u64);
+    // StorePageFault 与 StoreFault 都算
+    assert!(header[2] == 15 || header[2] == 7);
+    assert_eq!(header[3], 0);
+    assert_ne!(header[4], 0);
+
+    // SIGQUIT 默认终止进程并写出 core
+    let pid = fork();
+    if pid == 0 {
+        loop {}
+    }
+    sleep(50);
+    assert_eq!(kill(pid as usize, SIGQUIT), 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGQUIT);
+    let header = read_core_header(pid as usize);
+    assert_eq!(header[0], pid as u64);
+    assert_eq!(header[1], SIGQUIT as u64);
+    assert_eq!(header[2], 0);
+    println!("Test coredump OK!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fstat, open, read, write, OpenFlags, Stat, StatMode};
+
+/// 从 /dev/zero 读出 1 MiB 的 0 写入 /dev/null，每次读写的字节数都是请求的长度；
+/// /dev/null 读到 0 字节，/dev/urandom 两次读出的内容不同。三者都是字符设备。
+/// 正确输出：Test dev OK!
+
+const TOTAL: usize = 1 << 20;
+const CHUNK: usize = 4096;
+
+fn open_dev(path: &str) -> usize {
+    let fd = open(path, OpenFlags::RDWR);
+    assert!(fd > 0);
+    let stat = Stat::new();
+    assert_eq!(fstat(fd as usize, &stat), 0);
+    assert_eq!(stat.mode, StatMode::CHR);
+    fd as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let zero = open_dev("/dev/zero\0");
+    let null = open_dev("/dev/null\0");
+    let mut buf = [0xffu8; CHUNK];
+    let mut copied = 0;
+    while copied < TOTAL {
+        assert_eq!(read(zero, &mut buf), CHUNK as isize);
+        assert!(buf.iter().all(|b| *b == 0));
+        assert_eq!(write(null, &buf), CHUNK as isize);
+        buf.fill(0xff);
+        copied += CHUNK;
+    }
+    assert_eq!(copied, TOTAL);
+    assert_eq!(read(null, &mut buf), 0);
+    close(zero);
+    close(null);
+
+    let urandom = open_dev("dev/urandom\0");
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    assert_eq!(read(urandom, &mut first), 64);
+    assert_eq!(read(urandom, &mut second), 64);
+    assert_ne!(first, second);
+    assert!(first.iter().any(|b| *b != 0));
+    close(urandom);
+
+    println!("Test dev OK!");
+    0
+}
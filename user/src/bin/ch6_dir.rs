@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dirents, fstat, getdents, mkdir, mkdirat, open, rmdir, unlink, write, OpenFlags, Stat,
+    AT_FDCWD, DT_DIR, DT_REG, EEXIST, ENOTDIR,
+};
+
+/// mkdir 创建目录，目录中可以再创建文件和目录；mkdirat 相对于打开的目录或 AT_FDCWD（根目录）。
+/// getdents 列出目录项，缓冲区放不下的目录项留到下一次调用，一个也放不下时返回 -22（EINVAL），
+/// 读完后返回 0。根目录的列表包含运行时创建的文件。unlink 不能删除目录，返回 -21（EISDIR），
+/// 清空后的目录用 rmdir 删除。目录的硬链接数为 2 加上子目录数，创建和删除子目录时随之变化。
+/// 正确输出：Test dir OK!
+
+/// 目录的全部目录项，每次调用最多读 `chunk` 字节
+fn list(path: &str, chunk: usize) -> Vec<(String, u8)> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut entries = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = getdents(fd, &mut buf[..chunk]);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in dirents(&buf[..len as usize]) {
+            entries.push((String::from(entry.name), entry.kind));
+        }
+    }
+    close(fd);
+    entries
+}
+
+/// fstat 得到的硬链接数
+fn nlink(path: &str) -> u32 {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let stat = Stat::new();
+    assert_eq!(fstat(fd as usize, &stat), 0);
+    close(fd as usize);
+    stat.nlink
+}
+
+fn create(path: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"dir"), 3);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("dir_tmp\0"), 0);
+    assert_eq!(mkdir("dir_tmp\0"), -EEXIST);
+    assert_eq!(mkdir("/dir_tmp/sub\0"), 0);
+    assert_eq!(mkdir("no_such_dir/sub\0"), -ENOTDIR);
+    create("dir_tmp/a\0");
+    create("dir_tmp/sub/b\0");
+    // 文件下不能创建目录，目录不能以写方式打开
+    assert_eq!(mkdir("dir_tmp/a/c\0"), -ENOTDIR);
+    assert!(open("dir_tmp\0", OpenFlags::WRONLY) < 0);
+
+    let dirfd = open("dir_tmp\0", OpenFlags::RDONLY);
+    assert!(dirfd > 0);
+    assert_eq!(mkdirat(dirfd, "other\0"), 0);
+    assert_eq!(mkdirat(AT_FDCWD, "dir_tmp/third\0"), 0);
+    // 文件描述符不是目录
+    let fd = open("dir_tmp/a\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(mkdirat(fd, "x\0"), -ENOTDIR);
+    let mut buf = [0u8; 64];
+    assert_eq!(getdents(fd as usize, &mut buf), -ENOTDIR);
+    close(fd as usize);
+    // 一个目录项也放不下
+    assert_eq!(getdents(dirfd as usize, &mut buf[..8]), -22);
+    close(dirfd as usize);
+
+    // 每次只够放一两个目录项，结果与一次读完相同
+    let entries = list("dir_tmp\0", 256);
+    for chunk in [24, 40, 64] {
+        assert_eq!(list("dir_tmp\0", chunk), entries);
+    }
+    let expected = [("sub", DT_DIR), ("a", DT_REG), ("other", DT_DIR), ("third", DT_DIR)];
+    assert_eq!(entries.len(), expected.len());
+    for (name, kind) in expected {
+        assert!(entries.contains(&(String::from(name), kind)));
+    }
+    assert_eq!(list("dir_tmp/sub\0", 256), [(String::from("b"), DT_REG)]);
+
+    // 子目录的 .. 计入父目录的硬链接数，文件不计入
+    let root_nlink = nlink("/\0");
+    assert_eq!(nlink("dir_tmp\0"), 2 + 3);
+    assert_eq!(nlink("dir_tmp/sub\0"), 2);
+    assert_eq!(nlink("dir_tmp/a\0"), 1);
+
+    // 根目录包含运行时创建的文件，删除后不再列出
+    let root = list("/\0", 256);
+    assert!(root.contains(&(String::from("dir_tmp"), DT_DIR)));
+    assert!(root.contains(&(String::from("ch6_dir"), DT_REG)));
+    create("dir_file_tmp\0");
+    assert!(list("/\0", 256).contains(&(String::from("dir_file_tmp"), DT_REG)));
+    assert_eq!(unlink("dir_file_tmp\0"), 0);
+    assert!(!list("/\0", 256).iter().any(|(name, _)| name == "dir_file_tmp"));
+    // unlink 不能删除目录，其中的文件可以
+    assert_eq!(unlink("dir_tmp/sub\0"), -21);
+    assert_eq!(unlink("dir_tmp/sub/b\0"), 0);
+    assert!(list("dir_tmp/sub\0", 256).is_empty());
+    for path in ["dir_tmp/sub\0", "dir_tmp/other\0", "dir_tmp/third\0"] {
+        assert_eq!(rmdir(path), 0);
+    }
+    assert_eq!(nlink("dir_tmp\0"), 2);
+    assert_eq!(unlink("dir_tmp/a\0"), 0);
+    assert_eq!(rmdir("dir_tmp\0"), 0);
+    assert_eq!(nlink("/\0"), root_nlink - 1);
+    assert!(!list("/\0", 256).iter().any(|(name, _)| name == "dir_tmp"));
+
+    println!("Test dir OK!");
+    0
+}
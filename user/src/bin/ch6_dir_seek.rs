@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dirents, fstat, getdents, lseek, mkdir, open, rmdir, unlink, write, OpenFlags, Stat,
+    SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// 目录的偏移量是下一个目录项在目录中的字节偏移：lseek 到 0 从头重新列出，
+/// lseek 到保存的偏移从那里继续；已经列出的目录项被删除后继续列出，不会漏掉也不会重复。
+/// 目录的 SEEK_END 返回目录大小，落在目录项中间的偏移返回 -22（EINVAL）。
+/// 正确输出：Test dir seek OK!
+
+const DIR: &str = "dir_seek";
+const FILES: usize = 8;
+
+/// 从 `fd` 的当前偏移最多列出 `count` 个目录项，每次调用只放得下一个
+fn list(fd: usize, count: usize) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut buf = [0u8; 24];
+    while names.len() < count {
+        let len = getdents(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in dirents(&buf[..len as usize]) {
+            names.push(String::from(entry.name));
+        }
+    }
+    names
+}
+
+fn sorted(mut names: Vec<String>) -> Vec<String> {
+    names.sort();
+    names
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir(DIR), 0);
+    let mut all = Vec::new();
+    for i in 0..FILES {
+        let name = format!("f{}", i);
+        let fd = open(&format!("{}/{}", DIR, name), OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        assert_eq!(write(fd as usize, b"seek"), 4);
+        close(fd as usize);
+        all.push(name);
+    }
+    let fd = open(DIR, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+
+    // 列出一半，回到开头后列出全部
+    let half = list(fd, FILES / 2);
+    assert_eq!(half.len(), FILES / 2);
+    assert!(lseek(fd, 0, SEEK_CUR) > 0);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(sorted(list(fd, usize::MAX)), all);
+
+    // 目录的结尾是它的大小，从结尾列不出目录项
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    assert_eq!(lseek(fd, 0, SEEK_END), stat.size as isize);
+    assert!(list(fd, usize::MAX).is_empty());
+    assert_eq!(lseek(fd, 1, SEEK_SET), -22);
+
+    // 列出一部分，删除已经列出的目录项，从保存的偏移继续
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut seen = list(fd, 3);
+    assert_eq!(seen.len(), 3);
+    let saved = lseek(fd, 0, SEEK_CUR);
+    assert!(saved > 0);
+    assert_eq!(unlink(&format!("{}/{}", DIR, seen[0])), 0);
+    assert_eq!(unlink(&format!("{}/{}", DIR, seen[2])), 0);
+    let rest = list(fd, usize::MAX);
+    // 另一个打开的目录 lseek 到同一偏移，得到相同的剩余目录项
+    let other = open(DIR, OpenFlags::RDONLY);
+    assert!(other > 0);
+    assert_eq!(lseek(other as usize, saved, SEEK_SET), saved);
+    assert_eq!(list(other as usize, usize::MAX), rest);
+    close(other as usize);
+    seen.extend(rest);
+    assert_eq!(sorted(seen), all);
+    close(fd);
+
+    for name in all.iter() {
+        unlink(&format!("{}/{}", DIR, name));
+    }
+    assert_eq!(rmdir(DIR), 0);
+    println!("Test dir seek OK!");
+    0
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, get_time, kill, open, pipe, read, unlink, waitpid, write, OpenFlags,
+    SIGKILL,
+};
+
+/// 磁盘请求由中断完成，等待磁盘的进程睡眠，其间其它进程照常运行。
+/// 计算进程一直循环；读写进程反复写入并读回一个远大于块缓存的文件，结束前把自己的
+/// /proc/self/stat 经管道交给父进程。读写期间计算进程的用户态时间应多于读写进程
+/// 占用的全部 CPU 时间（用户态加内核态）；轮询磁盘时两者大致平分 CPU。
+/// 正确输出：Test disk irq OK!
+
+const FILE: &str = "disk_irq_tmp\0";
+/// 文件的块数，是块缓存的 8 倍
+const BLOCKS: usize = 128;
+const ROUNDS: usize = 8;
+
+/// User and system time in milliseconds from a stat line of /proc
+fn cpu_times(stat: &str) -> (usize, usize) {
+    // after the name: state, priority, pass, utime, stime, resident pages
+    let fields: Vec<&str> = stat.rsplit(')').next().unwrap().split_whitespace().collect();
+    (fields[3].parse().unwrap(), fields[4].parse().unwrap())
+}
+
+fn read_all(fd: usize) -> String {
+    let mut content = String::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = read(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            return content;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+}
+
+fn read_stat(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0, "cannot open {}", path);
+    let stat = read_all(fd as usize);
+    close(fd as usize);
+    stat
+}
+
+/// Write the file and read it back `ROUNDS` times, then report our stat
+fn disk_bound(report: usize) -> ! {
+    let mut block = [0u8; 512];
+    for round in 0..ROUNDS {
+        let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+        assert!(fd > 0);
+        for i in 0..BLOCKS {
+            block.fill((round + i) as u8);
+            assert_eq!(write(fd as usize, &block), block.len() as isize);
+        }
+        close(fd as usize);
+        let fd = open(FILE, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        for i in 0..BLOCKS {
+            assert_eq!(read(fd as usize, &mut block), block.len() as isize);
+            assert!(block.iter().all(|&byte| byte == (round + i) as u8));
+        }
+        close(fd as usize);
+    }
+    let stat = read_stat("/proc/self/stat\0");
+    write(report, stat.as_bytes());
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let cpu = fork();
+    if cpu == 0 {
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+    let cpu_stat = format!("/proc/{}/stat\0", cpu);
+    let (cpu_start, _) = cpu_times(&read_stat(&cpu_stat));
+    let start = get_time();
+
+    let mut report = [0usize; 2];
+    assert_eq!(pipe(&mut report), 0);
+    let disk = fork();
+    if disk == 0 {
+        close(report[0]);
+        disk_bound(report[1]);
+    }
+    close(report[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(disk as usize, &mut exit_code), disk);
+    assert_eq!(exit_code, 0);
+    let elapsed = get_time() - start;
+    let (cpu_end, _) = cpu_times(&read_stat(&cpu_stat));
+    let (disk_utime, disk_stime) = cpu_times(&read_all(report[0]));
+    close(report[0]);
+    assert_eq!(kill(cpu as usize, SIGKILL), 0);
+    assert_eq!(waitpid(cpu as usize, &mut exit_code), cpu);
+    assert_eq!(unlink(FILE), 0);
+
+    let cpu_time = cpu_end - cpu_start;
+    let disk_time = disk_utime + disk_stime;
+    println!(
+        "in {} ms the disk-bound task ran {} ms, the cpu-bound task {} ms",
+        elapsed, disk_time, cpu_time
+    );
+    assert!(cpu_time > disk_time);
+    println!("Test disk irq OK!");
+    0
+}
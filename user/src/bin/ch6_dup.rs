@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, dup2, exit, flush, fork, open, read, unlink, waitpid, write, OpenFlags, EBADF,
+    STDOUT,
+};
+
+/// dup 返回最小的空闲描述符，dup2 把新描述符指向同一个打开的文件（已打开时先关闭），
+/// 它们共享同一个偏移量；fork 出的子进程通过继承的描述符写入时，父进程的偏移量也随之前进。
+/// 把标准输出 dup2 到文件后，println! 的输出写入文件。
+/// 正确输出：Test dup OK!
+
+const FILE: &str = "dup_tmp\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let copy = dup(fd);
+    assert!(copy > fd as isize);
+    let copy = copy as usize;
+    assert_eq!(write(fd, b"a"), 1);
+    assert_eq!(write(copy, b"b"), 1);
+
+    // dup2 到未打开的描述符，以及已打开的描述符
+    assert_eq!(dup2(fd, 10), 10);
+    assert_eq!(write(10, b"c"), 1);
+    let other = open(FILE, OpenFlags::RDONLY);
+    assert!(other > 0);
+    assert_eq!(dup2(fd, other as usize), other);
+    assert_eq!(write(other as usize, b"d"), 1);
+    assert_eq!(close(other as usize), 0);
+    assert_eq!(dup2(fd, fd), fd as isize);
+    assert_eq!(dup2(100, 11), -EBADF);
+    assert_eq!(dup(100), -EBADF);
+    assert_eq!(dup2(fd, 1 << 20), -EBADF);
+
+    // 子进程继承的描述符仍指向同一个文件
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(copy, b"e"), 1);
+        assert_eq!(write(10, b"f"), 1);
+        exit(0);
+    }
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(write(fd, b"g"), 1);
+
+    // 标准输出重定向到文件
+    flush();
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(dup2(fd, STDOUT), STDOUT as isize);
+        println!("h");
+        exit(0);
+    }
+    assert!(pid > 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(fd);
+    close(copy);
+    close(10);
+
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd as usize, &mut buf), 9);
+    assert_eq!(&buf[..9], b"abcdefgh\n");
+    close(fd as usize);
+    assert_eq!(unlink(FILE), 0);
+    println!("Test dup OK!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, close_range, dup2, dup3, exec, exit, fcntl, fork, open, pipe2, unlink, waitpid,
+    OpenFlags, EBADF, EINVAL, FD_CLOEXEC, F_GETFD, O_CLOEXEC,
+};
+
+/// dup3 与 dup2 相同，但可以用 O_CLOEXEC 在复制的同时给新描述符设置 FD_CLOEXEC；
+/// old 与 new 相同时 dup3 返回 -EINVAL，dup2 原样返回。open 同样接受 O_CLOEXEC，
+/// pipe2 的 O_CLOEXEC 作用于两端。反复在 pipe2、open、dup3 之后立即 fork 并 exec
+/// ch6b_fd_leak_check，子进程从不继承这些描述符。
+/// 正确输出：Test dup3 OK!
+
+const FILE: &str = "dup3_file\0";
+const ROUNDS: usize = 16;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 只留下标准输入输出，ch6b_fd_leak_check 检查其余描述符
+    close_range(3, usize::MAX);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(fcntl(fd, F_GETFD, 0), 0);
+
+    assert_eq!(dup3(fd, fd, 0), -EINVAL);
+    assert_eq!(dup3(fd, fd, O_CLOEXEC), -EINVAL);
+    assert_eq!(dup2(fd, fd), fd as isize);
+    assert_eq!(dup3(fd, 20, 1), -EINVAL);
+    assert_eq!(dup3(30, 20, 0), -EBADF);
+    assert_eq!(dup3(fd, 20, O_CLOEXEC), 20);
+    assert_eq!(fcntl(20, F_GETFD, 0), FD_CLOEXEC as isize);
+    // 复制到已打开的描述符先关闭它，不带 O_CLOEXEC 时清除 FD_CLOEXEC
+    assert_eq!(dup3(fd, 20, 0), 20);
+    assert_eq!(fcntl(20, F_GETFD, 0), 0);
+    close(20);
+    close(fd);
+
+    let fd = open(FILE, OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+    assert!(fd > 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+    close(fd as usize);
+
+    for _ in 0..ROUNDS {
+        let mut pipe_fd = [0usize; 2];
+        assert_eq!(pipe2(&mut pipe_fd, O_CLOEXEC), 0);
+        let file = open(FILE, OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+        assert!(file > 0);
+        assert_eq!(dup3(pipe_fd[1], 25, O_CLOEXEC), 25);
+        let pid = fork();
+        if pid == 0 {
+            exec("ch6b_fd_leak_check\0", &[0 as *const u8]);
+            exit(-4);
+        }
+        for fd in [pipe_fd[0], pipe_fd[1], file as usize, 25] {
+            assert_eq!(fcntl(fd, F_GETFD, 0), FD_CLOEXEC as isize);
+            close(fd);
+        }
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+
+    assert_eq!(unlink(FILE), 0);
+    println!("Test dup3 OK!");
+    0
+}
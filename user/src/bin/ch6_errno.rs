@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, kill, lseek, mkdir, munmap, open, pipe2, read, rmdir, setrlimit, signal, syscall,
+    unlink, wait, write, OpenFlags, EAGAIN, EBADF, ECHILD, EEXIST, EFAULT, EINVAL, EISDIR, EMFILE,
+    ENAMETOOLONG, ENOENT, ENOSYS, ENOTDIR, EPIPE, ESPIPE, ESRCH, RLIMIT_NOFILE, SEEK_SET, SIGKILL,
+    SIGPIPE, SIG_IGN, SYSCALL_WRITE,
+};
+
+/// 失败的系统调用返回取负的 Linux 错误码，与 user_lib 导出的常量一致：
+/// 文件不存在 -ENOENT，名字已存在 -EEXIST，描述符未打开 -EBADF，用户地址不可访问 -EFAULT，
+/// 路径中间是文件 -ENOTDIR，以写方式打开目录 -EISDIR，名字放不下目录项 -ENAMETOOLONG，
+/// 参数错误 -EINVAL，没有子进程 -ECHILD，进程不存在 -ESRCH，未知的系统调用号 -ENOSYS，
+/// 写没有读端的管道 -EPIPE，非阻塞读空管道 -EAGAIN，定位管道 -ESPIPE，描述符用完 -EMFILE。
+/// 正确输出：Test errno OK!
+
+const DIR: &str = "errno_dir\0";
+const FILE: &str = "errno_file\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 8];
+    assert_eq!(open("errno_missing\0", OpenFlags::RDONLY), -ENOENT);
+    assert_eq!(mkdir(DIR), 0);
+    assert_eq!(mkdir(DIR), -EEXIST);
+    assert_eq!(open(DIR, OpenFlags::WRONLY), -EISDIR);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(open("errno_file/x\0", OpenFlags::CREATE), -ENOTDIR);
+    assert_eq!(
+        open("errno_a_name_longer_than_an_entry\0", OpenFlags::CREATE),
+        -ENAMETOOLONG
+    );
+
+    assert_eq!(read(100, &mut buf), -EBADF);
+    assert_eq!(syscall(SYSCALL_WRITE, [1, 0, 8]), -EFAULT);
+    assert_eq!(munmap(0x10000001, 4096), -EINVAL);
+    assert_eq!(wait(&mut 0i32), -ECHILD);
+    assert_eq!(kill(99999, SIGKILL), -ESRCH);
+    assert_eq!(syscall(9999, [0, 0, 0]), -ENOSYS);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::NONBLOCK.bits()), 0);
+    assert_eq!(read(pipe_fd[0], &mut buf), -EAGAIN);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_SET), -ESPIPE);
+    signal(SIGPIPE, SIG_IGN);
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"x"), -EPIPE);
+    close(pipe_fd[1]);
+
+    assert_eq!(setrlimit(RLIMIT_NOFILE, 4), 0);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert_eq!(fd, 3);
+    assert_eq!(open(FILE, OpenFlags::RDONLY), -EMFILE);
+    close(fd as usize);
+
+    assert_eq!(unlink(FILE), 0);
+    assert_eq!(unlink(FILE), -ENOENT);
+    assert_eq!(rmdir(DIR), 0);
+    println!("Test errno OK!");
+    0
+}
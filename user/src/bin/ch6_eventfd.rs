@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, eventfd, eventfd_read, eventfd_write, exit, fcntl, fork, pipe, poll, read,
+    sleep_blocking, waitpid, write, PollFd, EAGAIN, EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE,
+    FD_CLOEXEC, F_GETFD, POLLIN, POLLOUT,
+};
+
+/// eventfd：写入累加到计数器，读取取走全部计数（信号量模式每次取 1），计数为 0 时
+/// 读取阻塞或在 EFD_NONBLOCK 下返回 -EAGAIN；fork 后父子进程共享同一计数器；
+/// EFD_CLOEXEC 设置 FD_CLOEXEC。最后用一次 poll 同时等待 eventfd 和管道，
+/// 子进程分别在其上发出事件，父进程的事件循环把两个事件都收到。
+/// 正确输出：Test eventfd OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 累加与取走
+    let efd = eventfd(3, EFD_NONBLOCK);
+    assert!(efd >= 0);
+    let efd = efd as usize;
+    assert_eq!(eventfd_read(efd), Ok(3));
+    assert_eq!(eventfd_read(efd), Err(-EAGAIN));
+    assert_eq!(eventfd_write(efd, 2), 0);
+    assert_eq!(eventfd_write(efd, 5), 0);
+    // 不足 8 字节的缓冲区什么也不读
+    let mut short = [0u8; 4];
+    assert_eq!(read(efd, &mut short), 0);
+    assert_eq!(eventfd_read(efd), Ok(7));
+    assert_eq!(fcntl(efd, F_GETFD, 0), 0);
+    close(efd);
+
+    // 信号量模式
+    let efd = eventfd(2, EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) as usize;
+    assert_eq!(fcntl(efd, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(eventfd_read(efd), Ok(1));
+    assert_eq!(eventfd_read(efd), Ok(1));
+    assert_eq!(eventfd_read(efd), Err(-EAGAIN));
+    close(efd);
+    assert_eq!(eventfd(0, 1 << 3), -22);
+
+    // fork 后共享计数器，读取阻塞到子进程写入
+    let efd = eventfd(0, 0) as usize;
+    let pid = fork();
+    if pid == 0 {
+        sleep_blocking(30);
+        assert_eq!(eventfd_write(efd, 42), 0);
+        exit(0);
+    }
+    assert_eq!(eventfd_read(efd), Ok(42));
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // 事件循环：一次 poll 同时等待 eventfd 和管道
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut fds = [PollFd::new(efd, POLLIN), PollFd::new(pipe_fd[0], POLLIN)];
+    assert_eq!(poll(&mut fds, 20), 0);
+    let mut out = [PollFd::new(efd, POLLOUT)];
+    assert_eq!(poll(&mut out, 0), 1);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        sleep_blocking(30);
+        assert_eq!(write(pipe_fd[1], b"msg"), 3);
+        sleep_blocking(30);
+        assert_eq!(eventfd_write(efd, 1), 0);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let (mut got_pipe, mut got_event) = (false, false);
+    while !(got_pipe && got_event) {
+        let ready = poll(&mut fds, 1000);
+        assert!(ready > 0, "event loop timed out");
+        if fds[0].revents & POLLIN != 0 {
+            assert!(got_pipe, "eventfd fired before the pipe");
+            assert_eq!(eventfd_read(efd), Ok(1));
+            got_event = true;
+        }
+        if fds[1].revents & POLLIN != 0 {
+            let mut buf = [0u8; 8];
+            assert_eq!(read(pipe_fd[0], &mut buf), 3);
+            assert_eq!(&buf[..3], b"msg");
+            got_pipe = true;
+        }
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(pipe_fd[0]);
+    close(efd);
+
+    println!("Test eventfd OK!");
+    0
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{chmod, close, exec, open, read, spawn, unlink, write, OpenFlags};
+
+/// 执行一个带执行权限的文本文件、一个只有 40 字节的 ELF 和一个截断到 1024 字节的 ELF，
+/// exec 与 spawn 都应返回 -8（ENOEXEC），内核不会崩溃，调用者继续运行。
+/// 正确输出：Test exec bad elf OK!
+
+const APP: &str = "ch2b_hello_world\0";
+const FILE: &str = "bad_elf\0";
+
+/// Make `FILE` an executable holding `data`
+fn make_executable(data: &[u8]) {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+    assert_eq!(chmod(FILE, 0o755), 0);
+}
+
+fn expect_rejected(what: &str) {
+    assert_eq!(exec(FILE, &[core::ptr::null::<u8>()]), -ENOEXEC, "{}", what);
+    assert_eq!(spawn(FILE), -ENOEXEC, "{}", what);
+    println!("{} rejected", what);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    make_executable(b"#!/bin/sh\necho this is not an elf file\n");
+    expect_rejected("text file");
+
+    let mut elf = [0u8; 1024];
+    let fd = open(APP, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(read(fd as usize, &mut elf), elf.len() as isize);
+    close(fd as usize);
+    assert_eq!(&elf[..4], b"\x7fELF");
+    make_executable(&elf[..40]);
+    expect_rejected("elf header cut short");
+    make_executable(&elf);
+    expect_rejected("elf segments cut short");
+
+    assert_eq!(unlink(FILE), 0);
+    println!("Test exec bad elf OK!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{close, getrlimit, open, setrlimit, OpenFlags, EMFILE, EPERM, RLIMIT_NOFILE};
+
+/// 测试文件描述符数量上限：超过上限时 open 返回 -24（EMFILE）而不是耗尽内核内存。
+/// 正确输出：Test fd limit OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let limit = getrlimit(RLIMIT_NOFILE);
+    assert!(limit > 3);
+    assert_eq!(setrlimit(RLIMIT_NOFILE, limit as usize + 1), -EPERM);
+    assert_eq!(setrlimit(RLIMIT_NOFILE, 10), 0);
+    assert_eq!(getrlimit(RLIMIT_NOFILE), 10);
+    let mut fds = [0usize; 16];
+    let mut n = 0;
+    loop {
+        let fd = open("fdlimit\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+        if fd < 0 {
+            assert_eq!(fd, -EMFILE);
+            break;
+        }
+        assert!(n < fds.len());
+        fds[n] = fd as usize;
+        n += 1;
+    }
+    // 0, 1, 2 已被标准输入输出占用
+    assert_eq!(n, 10 - 3);
+    close(fds[0]);
+    let fd = open("fdlimit\0", OpenFlags::RDONLY);
+    assert_eq!(fd as usize, fds[0]);
+    for &fd in fds[..n].iter() {
+        close(fd);
+    }
+    println!("Test fd limit OK!");
+    0
+}
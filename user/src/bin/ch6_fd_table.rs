@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    close, close_range, exit, fork, lseek, open, read, setrlimit, unlink, waitpid, write,
+    OpenFlags, RLIMIT_NOFILE, SEEK_CUR,
+};
+
+/// 描述符表按需增长到 RLIMIT_NOFILE：调高上限后打开 1000 个文件，fork 出的子进程能读到全部，
+/// 且与父进程共享每个打开文件的偏移；关闭一些描述符后重新打开，总是先得到最小的空闲描述符，
+/// 关闭最大的描述符后重新打开还得到它。
+/// 正确输出：Test fd table OK!
+
+const NAME: &str = "fd_table\0";
+const FILES: usize = 1000;
+const CONTENT: &[u8] = b"fdtb";
+
+fn open_one() -> isize {
+    open(NAME, OpenFlags::RDONLY)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(NAME, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, CONTENT), CONTENT.len() as isize);
+    close(fd as usize);
+
+    assert_eq!(setrlimit(RLIMIT_NOFILE, FILES + 24), 0);
+    let mut fds = Vec::with_capacity(FILES);
+    for i in 0..FILES {
+        let fd = open_one();
+        // 0, 1, 2 已被标准输入输出占用
+        assert_eq!(fd, i as isize + 3);
+        fds.push(fd as usize);
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        let mut buf = [0u8; 8];
+        for &fd in fds.iter() {
+            assert_eq!(read(fd, &mut buf), CONTENT.len() as isize);
+            assert_eq!(&buf[..CONTENT.len()], CONTENT);
+        }
+        exit(fds.len() as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, FILES as i32);
+    // 子进程读过的偏移是共享的
+    for &fd in fds.iter() {
+        assert_eq!(lseek(fd, 0, SEEK_CUR), CONTENT.len() as isize);
+    }
+
+    // 重新打开时从最小的空闲描述符开始
+    let closed = [fds[700], fds[7], fds[300]];
+    for &fd in closed.iter() {
+        assert_eq!(close(fd), 0);
+    }
+    for &fd in [fds[7], fds[300], fds[700]].iter() {
+        assert_eq!(open_one(), fd as isize);
+    }
+    // 最大的描述符关闭后表缩短，重新打开还得到它
+    let last = *fds.last().unwrap();
+    assert_eq!(close(last), 0);
+    assert_eq!(close(last - 1), 0);
+    assert_eq!(open_one(), (last - 1) as isize);
+    assert_eq!(open_one(), last as isize);
+
+    assert_eq!(close_range(3, last), 0);
+    assert_eq!(open_one(), 3);
+    close(3);
+    unlink(NAME);
+    println!("Test fd table OK!");
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, fstat, mkfifo, open, read, spawn, unlink, waitpid, write, OpenFlags, Stat, StatMode,
+};
+
+/// mkfifo 创建命名管道，已存在时返回 -17（EEXIST）。没有读端时以 O_NONBLOCK 只写打开返回 -6（ENXIO），
+/// 以 O_NONBLOCK 只读打开立即成功。unlink 后已打开的两端仍可通信。
+/// 再分别 spawn 两个互不相关的程序 ch6b_fifo_reader 与 ch6b_fifo_writer，通过同名管道传递 1 MiB 数据，二者都应返回 0。
+/// 正确输出：Test fifo OK!
+
+const FIFO: &str = "fifo_tmp\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkfifo(FIFO), 0);
+    assert_eq!(mkfifo(FIFO), -EEXIST);
+    assert_eq!(open(FIFO, OpenFlags::WRONLY | OpenFlags::NONBLOCK), -ENXIO);
+    let reader = open(FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    assert!(reader > 0);
+    let reader = reader as usize;
+    // the read end is open, so this does not block
+    let writer = open(FIFO, OpenFlags::WRONLY);
+    assert!(writer > 0);
+    let writer = writer as usize;
+    let stat = Stat::new();
+    assert_eq!(fstat(writer, &stat), 0);
+    assert_eq!(stat.mode, StatMode::FIFO);
+
+    assert_eq!(unlink(FIFO), 0);
+    assert_eq!(write(writer, b"named"), 5);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(reader, &mut buf), 5);
+    assert_eq!(&buf[..5], b"named");
+    close(writer);
+    assert_eq!(read(reader, &mut buf), 0);
+    close(reader);
+
+    assert_eq!(mkfifo(FIFO), 0);
+    let reader = spawn("ch6b_fifo_reader\0");
+    let writer = spawn("ch6b_fifo_writer\0");
+    assert!(reader > 0 && writer > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(writer as usize, &mut exit_code), writer);
+    assert_eq!(exit_code, 0);
+    assert_eq!(waitpid(reader as usize, &mut exit_code), reader);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unlink(FIFO), 0);
+    println!("Test fifo OK!");
+    0
+}
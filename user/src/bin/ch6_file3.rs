@@ -13,7 +13,7 @@ pub fn main() -> i32 {
     let fname = "fname3\0";
     for i in 0..10 {
         let fd = open(fname, OpenFlags::CREATE | OpenFlags::WRONLY);
-        if fd == -1 {
+        if fd < 0 {
             panic!("failed to crate file");
         }
         let fd = fd as usize;
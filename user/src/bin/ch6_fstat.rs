@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_settime, close, fstat, link, mmap, munmap, open, pipe, read, unlink, write, OpenFlags,
+    Stat, StatMode, TimeSpec, CLOCK_REALTIME, STDIN, STDOUT,
+};
+
+/// fstat 报告文件的大小与三个时间戳：写入更新 mtime 和 ctime，读取只更新 atime，
+/// 建立硬链接只更新 ctime。标准输入输出是字符设备，管道是 FIFO，大小为其中待读的字节数。
+/// 跨页的 Stat 结构体同样能正确写入。
+/// 正确输出：Test fstat times OK!
+
+const FILE: &str = "fstat_tmp\0";
+const LINK: &str = "fstat_link\0";
+const LENGTH: usize = 5000;
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+/// 设置的实时时钟，单位为秒
+const T0: usize = 1_700_000_000;
+/// 各步骤之间时钟前进的秒数，足以区分前后两次时间戳
+const STEP: usize = 100;
+
+fn set_clock(sec: usize) {
+    assert_eq!(clock_settime(CLOCK_REALTIME, &TimeSpec { sec, nsec: 0 }), 0);
+}
+
+fn stat_of(fd: usize) -> Stat {
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    stat
+}
+
+/// 时间戳在 `sec` 之后的一个步长内
+fn around(time: u64, sec: usize) -> bool {
+    time >= sec as u64 && time < (sec + STEP) as u64
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    set_clock(T0);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let data = [b'x'; LENGTH];
+    assert_eq!(write(fd, &data), LENGTH as isize);
+    let stat = stat_of(fd);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.size, LENGTH as u64);
+    assert_eq!(stat.nlink, 1);
+    assert!(around(stat.mtime, T0) && around(stat.ctime, T0) && around(stat.atime, T0));
+    close(fd);
+
+    // 读取只更新 atime
+    set_clock(T0 + STEP);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf), buf.len() as isize);
+    let read_stat = stat_of(fd);
+    assert!(around(read_stat.atime, T0 + STEP));
+    assert_eq!(read_stat.mtime, stat.mtime);
+    assert_eq!(read_stat.ctime, stat.ctime);
+
+    // 硬链接只更新 ctime
+    set_clock(T0 + 2 * STEP);
+    assert_eq!(link(FILE, LINK), 0);
+    let link_stat = stat_of(fd);
+    assert_eq!(link_stat.nlink, 2);
+    assert!(around(link_stat.ctime, T0 + 2 * STEP));
+    assert_eq!(link_stat.mtime, stat.mtime);
+    assert_eq!(unlink(LINK), 0);
+
+    // 跨页的 Stat
+    assert_eq!(mmap(START, 2 * PAGE, 3), 0);
+    let straddling = unsafe { &*((START + PAGE - 40) as *const Stat) };
+    assert_eq!(fstat(fd, straddling), 0);
+    assert_eq!(straddling.size, LENGTH as u64);
+    assert_eq!(straddling.ctime, stat_of(fd).ctime);
+    assert_eq!(munmap(START, 2 * PAGE), 0);
+    close(fd);
+    assert_eq!(unlink(FILE), 0);
+
+    assert_eq!(stat_of(STDIN).mode, StatMode::CHR);
+    assert_eq!(stat_of(STDOUT).mode, StatMode::CHR);
+
+    // 管道的大小是待读的字节数
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(write(pipe_fd[1], b"abc"), 3);
+    let pipe_stat = stat_of(pipe_fd[0]);
+    assert_eq!(pipe_stat.mode, StatMode::FIFO);
+    assert_eq!(pipe_stat.size, 3);
+    assert_eq!(read(pipe_fd[0], &mut buf), 3);
+    assert_eq!(stat_of(pipe_fd[1]).size, 0);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    println!("Test fstat times OK!");
+    0
+}
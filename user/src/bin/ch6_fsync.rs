@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, fsync, open, pipe, read, sync, unlink, write, OpenFlags, STDIN, STDOUT,
+};
+
+/// fsync 把文件写回磁盘，对管道和标准输入输出什么也不做，返回 0；sync 写回全部缓存。
+/// 第一次启动写入 fsync_tmp 并保留它，在 shell 中输入 exit 关机后，
+/// `make unpack FILE=fsync_tmp` 应输出下面的 DATA。不重新打包镜像再次启动时，
+/// 本测试检查上次写入的内容并删除该文件。
+/// 正确输出：Test fsync OK!
+
+const FILE: &str = "fsync_tmp\0";
+const DATA: &[u8] = b"written before the power went off\n";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 第二次启动
+    let fd = open(FILE, OpenFlags::RDONLY);
+    if fd > 0 {
+        let mut buf = [0u8; 64];
+        let len = read(fd as usize, &mut buf);
+        close(fd as usize);
+        assert_eq!(&buf[..len as usize], DATA);
+        assert_eq!(unlink(FILE), 0);
+        println!("Test fsync persisted OK!");
+    }
+
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, DATA), DATA.len() as isize);
+    assert_eq!(fsync(fd), 0);
+    close(fd);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    for fd in [pipe_fd[0], pipe_fd[1], STDIN, STDOUT] {
+        assert_eq!(fsync(fd), 0);
+    }
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(fsync(pipe_fd[0]), -EBADF);
+    assert_eq!(sync(), 0);
+
+    println!("Test fsync OK!");
+    0
+}
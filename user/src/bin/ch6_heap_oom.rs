@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{close, exit, fork, open, pipe, read, unlink, waitpid, write, OpenFlags};
+
+/// 子进程不断创建管道，每个管道占用内核堆上 4 KiB 的缓冲区，直到描述符用完
+/// 或内核堆将满。堆将满时 pipe 与 open 都应返回 -ENOMEM 而不是让内核崩溃。
+/// 父进程不断 fork 这样的子进程直到出现 -ENOMEM，随后让子进程全部退出，
+/// 内核应继续正常运行，open 与 pipe 重新成功。
+/// 正确输出：Test heap oom OK!
+
+const MAX_CHILDREN: usize = 32;
+
+/// Fill the kernel heap with pipes, report how it ended, then hold on to
+/// them until `release` is closed
+fn hog(report: usize, release: usize) -> ! {
+    let mut pipes = 0;
+    let status = loop {
+        let mut fds = [0usize; 2];
+        match pipe(&mut fds) {
+            0 => pipes += 1,
+            err if err == -ENOMEM => break b'M',
+            // out of descriptors
+            _ => break b'F',
+        }
+    };
+    if status == b'M' {
+        assert_eq!(open("heap_oom\0", OpenFlags::RDONLY), -ENOMEM);
+        println!("ENOMEM after {} pipes", pipes);
+    }
+    assert_eq!(write(report, &[status]), 1);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(release, &mut buf), 0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut report = [0usize; 2];
+    let mut release = [0usize; 2];
+    assert_eq!(pipe(&mut report), 0);
+    assert_eq!(pipe(&mut release), 0);
+    let mut pids = [0isize; MAX_CHILDREN];
+    let mut children = 0;
+    let mut out_of_memory = false;
+    while children < MAX_CHILDREN && !out_of_memory {
+        let pid = fork();
+        if pid == 0 {
+            close(report[0]);
+            close(release[1]);
+            hog(report[1], release[0]);
+        }
+        assert!(pid > 0, "fork failed before the heap ran low");
+        pids[children] = pid;
+        children += 1;
+        let mut status = [0u8; 1];
+        assert_eq!(read(report[0], &mut status), 1);
+        out_of_memory = status[0] == b'M';
+    }
+    assert!(out_of_memory, "{} children did not fill the kernel heap", children);
+    assert_eq!(open("heap_oom\0", OpenFlags::RDONLY), -ENOMEM);
+
+    close(release[1]);
+    for pid in &pids[..children] {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(*pid as usize, &mut exit_code), *pid);
+        assert_eq!(exit_code, 0);
+    }
+    let fd = open("heap_oom\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlink("heap_oom\0"), 0);
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    close(fds[0]);
+    close(fds[1]);
+    println!("Test heap oom OK!");
+    0
+}
@@ -0,0 +1,134 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{
+    close, exit, fork, fsync, getrusage, open, pipe, read, unlink, wait4, write, OpenFlags, Rusage,
+    RUSAGE_SELF,
+};
+
+/// 按进程统计 I/O：getrusage 给出读写的字节数、代为发出的块设备请求数与等待它们完成的微秒数，
+/// /proc/self/io 再按普通文件、管道与控制台分开字节数。读 1 MiB 的文件，读到的字节数一致，
+/// 块设备读请求不少于文件的块数（块缓存只有 16 块，装不下它）；小文件读过一次后留在缓存里，
+/// 再读几乎不发请求。wait4 给出子进程自己的 I/O。
+/// 正确输出：Test io acct OK!
+
+const BIG: &str = "io_acct_big\0";
+const SMALL: &str = "io_acct_small\0";
+const CHUNK: usize = 4096;
+const BIG_SIZE: usize = 1 << 20;
+const SMALL_SIZE: usize = 2048;
+const BLOCK_SZ: usize = 512;
+
+fn rusage() -> Rusage {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    usage
+}
+
+fn write_file(path: &str, size: usize) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let mut buf = [0u8; CHUNK];
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let mut written = 0;
+    while written < size {
+        let len = (size - written).min(CHUNK);
+        assert_eq!(write(fd as usize, &buf[..len]), len as isize);
+        written += len;
+    }
+    assert_eq!(fsync(fd as usize), 0);
+    close(fd as usize);
+}
+
+/// Read all of `path`, returns the bytes read
+fn read_file(path: &str) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; CHUNK];
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    total
+}
+
+/// The counter `name` of `/proc/self/io`
+fn proc_io(name: &str) -> usize {
+    let fd = open("/proc/self/io\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 512];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let content = String::from(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    write_file(BIG, BIG_SIZE);
+    let file_read = proc_io("FileRead");
+    let before = rusage();
+    assert_eq!(read_file(BIG), BIG_SIZE);
+    let after = rusage();
+    // the read of /proc/self/io itself counts too
+    assert!(proc_io("FileRead") >= file_read + BIG_SIZE);
+    assert_eq!(after.read_bytes - before.read_bytes, BIG_SIZE);
+    assert!(after.block_reads - before.block_reads >= BIG_SIZE / BLOCK_SZ - 16);
+    assert!(after.block_wait_us > before.block_wait_us);
+
+    write_file(SMALL, SMALL_SIZE);
+    assert_eq!(read_file(SMALL), SMALL_SIZE);
+    let before = rusage();
+    assert_eq!(read_file(SMALL), SMALL_SIZE);
+    let after = rusage();
+    assert_eq!(after.read_bytes - before.read_bytes, SMALL_SIZE);
+    assert!(after.block_reads - before.block_reads <= 2);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (pipe_read, pipe_written) = (proc_io("PipeRead"), proc_io("PipeWritten"));
+    assert_eq!(write(pipe_fd[1], &[7u8; 100]), 100);
+    let mut buf = [0u8; 100];
+    assert_eq!(read(pipe_fd[0], &mut buf), 100);
+    assert_eq!(proc_io("PipeRead"), pipe_read + 100);
+    assert_eq!(proc_io("PipeWritten"), pipe_written + 100);
+    let console_written = proc_io("ConsoleWritten");
+    println!("io acct: console");
+    assert!(proc_io("ConsoleWritten") > console_written);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(pipe_fd[1], &[1u8; 10]), 10);
+        exit(0);
+    }
+    let mut status = 0;
+    let mut usage = Rusage::default();
+    assert_eq!(wait4(pid, &mut status, Some(&mut usage)), pid);
+    assert_eq!(usage.written_bytes, 10);
+    assert_eq!(usage.read_bytes, 0);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    assert_eq!(unlink(BIG), 0);
+    assert_eq!(unlink(SMALL), 0);
+    println!("Test io acct OK!");
+    0
+}
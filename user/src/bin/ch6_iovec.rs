@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, open, pipe, read, readv, unlink, writev, IoVec, OpenFlags,
+};
+
+/// writev 把三段消息（其中一段为空）一次写入文件，文件内容是连续的一段；readv 一次读取，
+/// 按顺序分到各个缓冲区，返回所有缓冲区合计的字节数，读到文件末尾时只填满前面的部分。
+/// 超过 64 个 iovec 返回 -22（EINVAL），非法地址返回 -14（EFAULT）。管道同样支持。
+/// 正确输出：Test iovec OK!
+
+const FILE: &str = "iovec_tmp\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let iov = [
+        IoVec::new(b"Hello, "),
+        IoVec::new(b""),
+        IoVec::new(b"scattered "),
+        IoVec::new(b"world!"),
+    ];
+    assert_eq!(writev(fd, &iov), 23);
+    assert_eq!(writev(fd, &[]), 0);
+    let too_many = [IoVec::new(b"x"); 65];
+    assert_eq!(writev(fd, &too_many), -EINVAL);
+    let bad = [IoVec::new(b"ok"), IoVec { base: 0, len: 4 }];
+    assert_eq!(writev(fd, &bad), -EFAULT);
+    close(fd);
+
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buf = [0u8; 64];
+    assert_eq!(read(fd, &mut buf), 23);
+    assert_eq!(&buf[..23], b"Hello, scattered world!");
+    close(fd);
+
+    // 读到文件末尾时只填满前面的缓冲区
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let (mut a, mut b, mut c) = ([0u8; 5], [0u8; 0], [0u8; 32]);
+    let iov = [IoVec::new_mut(&mut a), IoVec::new_mut(&mut b), IoVec::new_mut(&mut c)];
+    assert_eq!(readv(fd, &iov), 23);
+    assert_eq!(&a, b"Hello");
+    assert_eq!(&c[..18], b", scattered world!");
+    assert_eq!(readv(fd, &iov), 0);
+    close(fd);
+    assert_eq!(unlink(FILE), 0);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(writev(pipe_fd[1], &[IoVec::new(b"pi"), IoVec::new(b"pe")]), 4);
+    let (mut a, mut b) = ([0u8; 3], [0u8; 3]);
+    assert_eq!(readv(pipe_fd[0], &[IoVec::new_mut(&mut a), IoVec::new_mut(&mut b)]), 4);
+    assert_eq!((&a, &b[..1]), (b"pip", &b"e"[..]));
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    println!("Test iovec OK!");
+    0
+}
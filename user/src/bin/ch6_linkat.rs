@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, fstat, linkat, mkdir, open, read, rmdir, unlink, unlinkat, write, OpenFlags, Stat,
+    AT_FDCWD, AT_REMOVEDIR,
+};
+
+/// linkat 可以在不同目录之间建立硬链接，linkat 与 unlinkat 的路径相对于各自的目录描述符或
+/// AT_FDCWD（根目录）。父目录不存在返回 -20（ENOTDIR），最后一级不存在返回 -2（ENOENT），
+/// 新名字已存在返回 -17（EEXIST），对目录建立链接或 unlink 目录返回 -21（EISDIR），
+/// 无效的目录描述符返回 -9（EBADF），新名字超过 27 字节返回 -36（ENAMETOOLONG），
+/// 用 open 创建也一样。带 AT_REMOVEDIR 的 unlinkat 删除空目录，
+/// 目录非空返回 -39（ENOTEMPTY），不是目录返回 -20。
+/// 正确输出：Test linkat OK!
+
+fn nlink(path: &str) -> u32 {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let stat = Stat::new();
+    assert_eq!(fstat(fd as usize, &stat), 0);
+    close(fd as usize);
+    stat.nlink
+}
+
+fn content(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let len = read(fd as usize, buf);
+    assert!(len >= 0);
+    close(fd as usize);
+    len as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("lk_a\0"), 0);
+    assert_eq!(mkdir("lk_b\0"), 0);
+    let fd = open("lk_a/f\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"linkat"), 6);
+    close(fd as usize);
+
+    // 跨目录的硬链接
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", AT_FDCWD, "/lk_b/g\0"), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(content("lk_b/g\0", &mut buf), 6);
+    assert_eq!(&buf[..6], b"linkat");
+    assert_eq!(nlink("lk_a/f\0"), 2);
+
+    // 相对于目录描述符
+    let dirfd = open("lk_b\0", OpenFlags::RDONLY);
+    assert!(dirfd > 0);
+    assert_eq!(linkat(dirfd, "g\0", AT_FDCWD, "lk_h\0"), 0);
+    assert_eq!(nlink("lk_h\0"), 3);
+
+    // 各种错误
+    assert_eq!(linkat(AT_FDCWD, "lk_none/f\0", dirfd, "x\0"), -ENOTDIR);
+    assert_eq!(linkat(AT_FDCWD, "lk_a/none\0", dirfd, "x\0"), -ENOENT);
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", AT_FDCWD, "lk_none/x\0"), -ENOTDIR);
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", AT_FDCWD, "lk_a/f/x\0"), -ENOTDIR);
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", dirfd, "g\0"), -EEXIST);
+    assert_eq!(linkat(AT_FDCWD, "lk_a\0", dirfd, "x\0"), -EISDIR);
+    assert_eq!(linkat(99, "f\0", dirfd, "x\0"), -EBADF);
+    // 名字最长 27 字节
+    let long = "abcdefghijklmnopqrstuvwxyzAB\0";
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", dirfd, long), -ENAMETOOLONG);
+    let fd = open("lk_a/abcdefghijklmnopqrstuvwxyzAB\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert_eq!(fd, -ENAMETOOLONG);
+    assert_eq!(linkat(AT_FDCWD, "lk_a/f\0", dirfd, &long[1..]), 0);
+    assert_eq!(nlink("lk_b/bcdefghijklmnopqrstuvwxyzAB\0"), 4);
+    assert_eq!(unlinkat(dirfd, &long[1..], 0), 0);
+    let fd = open("lk_h\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(unlinkat(fd, "x\0", 0), -ENOTDIR);
+    close(fd as usize);
+
+    // 删除链接与目录
+    assert_eq!(unlinkat(dirfd, "g\0", 0), 0);
+    assert_eq!(unlinkat(dirfd, "g\0", 0), -ENOENT);
+    assert_eq!(nlink("lk_a/f\0"), 2);
+    close(dirfd as usize);
+    assert_eq!(unlink("lk_a\0"), -EISDIR);
+    assert_eq!(rmdir("lk_a\0"), -ENOTEMPTY);
+    assert_eq!(rmdir("lk_h\0"), -ENOTDIR);
+    assert_eq!(rmdir("lk_none\0"), -ENOENT);
+    assert_eq!(unlinkat(AT_FDCWD, "lk_b\0", AT_REMOVEDIR), 0);
+    assert!(open("lk_b\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(unlink("lk_a/f\0"), 0);
+    assert_eq!(rmdir("lk_a\0"), 0);
+    assert_eq!(nlink("lk_h\0"), 1);
+    assert_eq!(unlink("lk_h\0"), 0);
+
+    println!("Test linkat OK!");
+    0
+}
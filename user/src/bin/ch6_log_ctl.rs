@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    drop_privilege, exit, fork, log_ctl, waitpid, EINVAL, EPERM, LOG_FS, LOG_INFO, LOG_MM, LOG_PID,
+    LOG_TRACE,
+};
+
+/// 运行时修改内核日志级别与模块掩码：返回值为旧的掩码左移 3 位再或上旧的级别，
+/// -1 表示不修改；级别越界时失败。放弃特权的进程及其之后 fork 的子进程不能再修改。
+/// 结束时恢复原来的设置。
+/// 正确输出：Test log ctl OK!
+
+fn settings(level: isize, mask: isize) -> isize {
+    mask << 3 | level
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let old = log_ctl(-1, -1);
+    assert!(old >= 0);
+    let (old_level, old_mask) = (old & 7, old >> 3);
+    assert_eq!(log_ctl(LOG_TRACE + 1, -1), -EINVAL);
+    assert_eq!(log_ctl(-2, -1), -EINVAL);
+    assert_eq!(log_ctl(-1, -2), -EINVAL);
+    assert_eq!(log_ctl(-1, -1), old);
+
+    assert_eq!(log_ctl(LOG_INFO, LOG_MM | LOG_FS | LOG_PID), old);
+    assert_eq!(log_ctl(-1, -1), settings(LOG_INFO, LOG_MM | LOG_FS | LOG_PID));
+    assert_eq!(log_ctl(-1, LOG_MM), settings(LOG_INFO, LOG_MM | LOG_FS | LOG_PID));
+    assert_eq!(log_ctl(-1, -1), settings(LOG_INFO, LOG_MM));
+    assert_eq!(log_ctl(old_level, old_mask), settings(LOG_INFO, LOG_MM));
+    assert_eq!(log_ctl(-1, -1), old);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(drop_privilege(), 0);
+        assert_eq!(log_ctl(-1, -1), -EPERM);
+        assert_eq!(log_ctl(LOG_TRACE, -1), -EPERM);
+        let pid = fork();
+        if pid == 0 {
+            assert_eq!(log_ctl(-1, -1), -EPERM);
+            exit(0);
+        }
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the parent keeps its privilege, the settings are untouched
+    assert_eq!(log_ctl(-1, -1), old);
+    println!("Test log ctl OK!");
+    0
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, mmap_file, msync, munmap, open, read, OpenFlags, MAP_PRIVATE, MAP_SHARED,
+};
+
+/// 映射打包进文件系统的 mmap.dat（"line 000\n" 到 "line 999\n" 共 1000 行），
+/// 读取内容；通过 MAP_PRIVATE 映射的写入不影响文件，通过 MAP_SHARED 映射的写入
+/// 在 msync 或 munmap 后写回文件，重新打开文件检查内容，最后恢复原内容。
+/// 正确输出：Test mmap file OK!
+
+const START: usize = 0x10000000;
+const PAGE: usize = 4096;
+const FILE_SIZE: usize = 9000;
+const LINE_LEN: usize = 9;
+const PROT_RW: usize = 3;
+
+fn mapped() -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(START as *mut u8, PAGE * 3) }
+}
+
+fn map(flags: usize, offset: usize) {
+    let fd = open("mmap.dat\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(mmap_file(START, PAGE * 3, PROT_RW, flags, fd as usize, offset), 0);
+    // 映射持有文件，关闭后仍然有效
+    close(fd as usize);
+}
+
+/// 重新打开文件，读出第 `n` 行
+fn file_line(n: usize) -> [u8; LINE_LEN] {
+    let fd = open("mmap.dat\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut line = [0u8; LINE_LEN];
+    let mut buf = [0u8; 512];
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for (i, b) in buf[..len as usize].iter().enumerate() {
+            if (n * LINE_LEN..(n + 1) * LINE_LEN).contains(&(total + i)) {
+                line[total + i - n * LINE_LEN] = *b;
+            }
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    // 写回不会让文件变长
+    assert_eq!(total, FILE_SIZE);
+    line
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 读取
+    map(MAP_SHARED, 0);
+    let data = mapped();
+    for i in 0..FILE_SIZE / LINE_LEN {
+        let line = format!("line {:03}\n", i);
+        assert_eq!(&data[i * LINE_LEN..(i + 1) * LINE_LEN], line.as_bytes());
+    }
+    // 文件末尾之后的部分为 0
+    assert!(data[FILE_SIZE..].iter().all(|b| *b == 0));
+
+    // msync 之后写入对 read 可见
+    data[500 * LINE_LEN..500 * LINE_LEN + 4].copy_from_slice(b"LINE");
+    assert_eq!(msync(START, PAGE * 3), 0);
+    assert_eq!(&file_line(500), b"LINE 500\n");
+    // munmap 时写回
+    data[999 * LINE_LEN..999 * LINE_LEN + 4].copy_from_slice(b"LAST");
+    assert_eq!(munmap(START, PAGE * 3), 0);
+    assert_eq!(&file_line(999), b"LAST 999\n");
+    assert_eq!(&file_line(0), b"line 000\n");
+
+    // 私有映射，从第二页开始
+    map(MAP_PRIVATE, PAGE);
+    let data = mapped();
+    assert_eq!(&data[500 * LINE_LEN - PAGE..501 * LINE_LEN - PAGE], b"LINE 500\n");
+    data[500 * LINE_LEN - PAGE..500 * LINE_LEN - PAGE + 4].copy_from_slice(b"lost");
+    assert_eq!(munmap(START, PAGE * 3), 0);
+    assert_eq!(&file_line(500), b"LINE 500\n");
+
+    // 恢复原内容
+    map(MAP_SHARED, 0);
+    let data = mapped();
+    data[500 * LINE_LEN..500 * LINE_LEN + 4].copy_from_slice(b"line");
+    data[999 * LINE_LEN..999 * LINE_LEN + 4].copy_from_slice(b"line");
+    assert_eq!(munmap(START, PAGE * 3), 0);
+    assert_eq!(&file_line(500), b"line 500\n");
+    assert_eq!(&file_line(999), b"line 999\n");
+    println!("Test mmap file OK!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, dup, exit, fcntl, fork, pipe, read, waitpid, write, OpenFlags, F_GETFL, F_SETFL, STDIN,
+};
+
+/// 设置了 O_NONBLOCK 的管道读端在管道为空时读取返回 -11（EAGAIN），对端写入后可以读到数据；
+/// 写端在管道满时写入返回 -11，只写得下一部分时返回写入的字节数。O_NONBLOCK 属于打开的文件，
+/// dup 得到的描述符共享它，F_GETFL 能读到它。标准输入同样支持。
+/// 正确输出：Test nonblock OK!
+
+const NONBLOCK: usize = OpenFlags::NONBLOCK.bits() as usize;
+/// 内核管道缓冲区的容量
+const PIPE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (rfd, wfd) = (pipe_fd[0], pipe_fd[1]);
+    assert_eq!(fcntl(rfd, F_GETFL, 0), OpenFlags::RDONLY.bits() as isize);
+    assert_eq!(fcntl(rfd, F_SETFL, NONBLOCK), 0);
+    assert_eq!(fcntl(rfd, F_GETFL, 0), NONBLOCK as isize);
+    let dup_fd = dup(rfd);
+    assert!(dup_fd > 0);
+    assert_eq!(fcntl(dup_fd as usize, F_GETFL, 0), NONBLOCK as isize);
+    close(dup_fd as usize);
+
+    // 管道为空
+    let mut buf = [0u8; 16];
+    assert_eq!(read(rfd, &mut buf), -EAGAIN);
+    // 子进程写入后可以读到
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(wfd, b"ready"), 5);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(read(rfd, &mut buf), 5);
+    assert_eq!(&buf[..5], b"ready");
+    assert_eq!(read(rfd, &mut buf), -EAGAIN);
+
+    // 写端：写满后返回 EAGAIN
+    assert_eq!(fcntl(wfd, F_SETFL, NONBLOCK), 0);
+    assert_eq!(fcntl(wfd, F_GETFL, 0), (OpenFlags::WRONLY | OpenFlags::NONBLOCK).bits() as isize);
+    let data = [b'x'; PIPE_SIZE + 100];
+    assert_eq!(write(wfd, &data), PIPE_SIZE as isize);
+    assert_eq!(write(wfd, &data), -EAGAIN);
+    assert_eq!(read(rfd, &mut buf), buf.len() as isize);
+    assert_eq!(write(wfd, &data), buf.len() as isize);
+    // 清除 O_NONBLOCK 后恢复阻塞
+    assert_eq!(fcntl(wfd, F_SETFL, 0), 0);
+    assert_eq!(fcntl(wfd, F_GETFL, 0), OpenFlags::WRONLY.bits() as isize);
+    // 写端全部关闭后读完剩下的数据得到 0
+    close(wfd);
+    let mut total = 0;
+    loop {
+        let len = read(rfd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    assert_eq!(total, PIPE_SIZE);
+    close(rfd);
+
+    // 没有输入时读标准输入
+    assert_eq!(fcntl(STDIN, F_SETFL, NONBLOCK), 0);
+    assert_eq!(read(STDIN, &mut buf), -EAGAIN);
+    assert_eq!(fcntl(STDIN, F_SETFL, 0), 0);
+    assert_eq!(fcntl(STDIN, F_GETFL, 0), OpenFlags::RDONLY.bits() as isize);
+
+    println!("Test nonblock OK!");
+    0
+}
@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, exit, fork, getpgid, getpid, killpg, pipe, read, setpgid, sleep_blocking, tcgetpgrp,
+    tcsetpgrp, waitpid, write, SIGINT, STDIN,
+};
+
+/// 进程组：fork 继承父进程的进程组，setpgid 可以让自己或子进程成为新组的组长或加入已有的组。
+/// 按 shell 的做法建立三个进程的管道线（生产者 | 转发 | 消费者），三个进程同属以第一个进程为
+/// 组长的进程组，并设为控制台前台组；向该组发一次 SIGINT（与 Ctrl-C 相同的投递路径），
+/// 三个进程都以 -SIGINT 退出，本进程不受影响。
+/// 手动检查：在 shell 中运行 ch6b_yes | ch6b_wc | ch6b_wc，按一次 Ctrl-C，三个进程都应退出
+/// 并回到提示符。
+/// 正确输出：Test pgid OK!
+
+/// 把 `input` 读到的数据写到 `output`，`input` 为 None 时不停写 "y\n"
+fn stage(input: Option<usize>, output: Option<usize>) -> ! {
+    let mut buf = [b'y', b'\n', 0, 0, 0, 0, 0, 0];
+    loop {
+        let len = match input {
+            Some(fd) => read(fd, &mut buf),
+            None => 2,
+        };
+        if len <= 0 {
+            exit(1);
+        }
+        if let Some(fd) = output {
+            if write(fd, &buf[..len as usize]) < 0 {
+                exit(2);
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let own = getpgid(0);
+    assert!(own > 0);
+    assert_eq!(getpgid(getpid() as usize), own);
+    assert_eq!(getpgid(99999), -ESRCH);
+
+    // fork 继承进程组，子进程可以自立为组长
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(getpgid(0), own);
+        assert_eq!(setpgid(0, 0), 0);
+        assert_eq!(getpgid(0), getpid());
+        exit(0);
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 只能移动自己和子进程，只能加入已有的组
+    assert_eq!(setpgid(1, 0), -ESRCH);
+    assert_eq!(setpgid(0, 99999), -EPERM);
+
+    // 三个进程的管道线
+    let mut a = [0usize; 2];
+    let mut b = [0usize; 2];
+    assert_eq!(pipe(&mut a), 0);
+    assert_eq!(pipe(&mut b), 0);
+    let mut pids = [0isize; 3];
+    for i in 0..3 {
+        let pgid = if i == 0 { 0 } else { pids[0] as usize };
+        let pid = fork();
+        if pid == 0 {
+            setpgid(0, pgid);
+            let (input, output) = match i {
+                0 => (None, Some(a[1])),
+                1 => (Some(a[0]), Some(b[1])),
+                _ => (Some(b[0]), None),
+            };
+            for fd in [a[0], a[1], b[0], b[1]] {
+                if Some(fd) != input && Some(fd) != output {
+                    close(fd);
+                }
+            }
+            stage(input, output);
+        }
+        assert!(pid > 0);
+        setpgid(pid as usize, pgid);
+        pids[i] = pid;
+    }
+    for fd in [a[0], a[1], b[0], b[1]] {
+        close(fd);
+    }
+    let leader = pids[0];
+    for pid in pids {
+        assert_eq!(getpgid(pid as usize), leader);
+    }
+    assert_eq!(getpgid(0), own);
+    let old = tcgetpgrp(STDIN);
+    assert_eq!(tcsetpgrp(STDIN, leader), 0);
+    assert_eq!(tcgetpgrp(STDIN), leader);
+
+    sleep_blocking(50);
+    assert_eq!(killpg(leader as usize, SIGINT), 0);
+    for pid in pids {
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, -SIGINT);
+    }
+    // 组内已没有进程
+    assert_eq!(killpg(leader as usize, SIGINT), -ESRCH);
+    assert_eq!(tcsetpgrp(STDIN, old), 0);
+    println!("Test pgid OK!");
+    0
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup2, exec, exit, flush, fork, pipe, read, signal, waitpid, write, SIGPIPE, SIG_IGN,
+    STDOUT,
+};
+
+/// 父进程写、子进程读：超过管道容量的数据分多次传完，读写两端都会阻塞等待对方；
+/// 所有写端关闭（包括持有写端的进程直接退出）后读到 0；所有读端关闭后写入产生 SIGPIPE，
+/// 忽略 SIGPIPE 时写入返回 -32（EPIPE）。把标准输出接到管道上再 exec，父进程能读到被执行程序的输出。
+/// 正确输出：Test pipe OK!
+
+/// 多于内核管道缓冲区的容量
+const LENGTH: usize = 10000;
+
+fn byte(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+fn wait_child(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// 一直读到所有写端关闭，返回读到的字节数
+fn read_all(fd: usize, buf: &mut [u8]) -> usize {
+    let mut total = 0;
+    loop {
+        let len = read(fd, &mut buf[total..]);
+        assert!(len >= 0);
+        if len == 0 {
+            return total;
+        }
+        total += len as usize;
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 父进程写，子进程读
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        let mut buf = [0u8; LENGTH + 1];
+        let len = read_all(pipe_fd[0], &mut buf);
+        close(pipe_fd[0]);
+        let ok = len == LENGTH && (0..LENGTH).all(|i| buf[i] == byte(i));
+        exit(if ok { 0 } else { 1 });
+    }
+    close(pipe_fd[0]);
+    let mut data = [0u8; LENGTH];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = byte(i);
+    }
+    assert_eq!(write(pipe_fd[1], &data), LENGTH as isize);
+    close(pipe_fd[1]);
+    assert_eq!(wait_child(pid), 0);
+
+    // 持有写端的子进程退出时不关闭描述符，读端也能读到 0
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(pipe_fd[1], b"bye"), 3);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 16];
+    assert_eq!(read_all(pipe_fd[0], &mut buf), 3);
+    assert_eq!(&buf[..3], b"bye");
+    close(pipe_fd[0]);
+    assert_eq!(wait_child(pid), 0);
+
+    // 所有读端关闭后写入：默认终止进程，忽略时返回 EPIPE
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    let pid = fork();
+    if pid == 0 {
+        write(pipe_fd[1], b"lost");
+        exit(0);
+    }
+    assert_eq!(wait_child(pid), -SIGPIPE);
+    signal(SIGPIPE, SIG_IGN);
+    assert_eq!(write(pipe_fd[1], b"lost"), -32);
+    close(pipe_fd[1]);
+
+    // 标准输出接到管道上再 exec
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    flush();
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(dup2(pipe_fd[1], STDOUT), STDOUT as isize);
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+        exec("ch2b_hello_world\0", &[0 as *const u8]);
+        exit(-4);
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 64];
+    let len = read_all(pipe_fd[0], &mut buf);
+    close(pipe_fd[0]);
+    assert_eq!(&buf[..len], b"Hello, world from user mode program!\n");
+    assert_eq!(wait_child(pid), 0);
+
+    println!("Test pipe OK!");
+    0
+}
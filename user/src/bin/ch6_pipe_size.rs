@@ -0,0 +1,230 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{
+    close, exit, fcntl, fork, pipe, pipe2, read, signal, waitpid, write, OpenFlags, EAGAIN, EPERM,
+    FD_CLOEXEC, F_GETFD, F_GETFL, F_GETPIPE_SZ, F_SETPIPE_SZ, O_CLOEXEC, PIPE_BUF, SIGPIPE,
+    SIG_IGN, STDOUT,
+};
+
+/// 管道容量与 PIPE_BUF：pipe2 可以设置 O_NONBLOCK 和 O_CLOEXEC；管道默认容量 4096，
+/// F_SETPIPE_SZ 按页向上取整，超过 64 KiB 返回 -1（EPERM），小于管道中的数据返回 -16（EBUSY）。
+/// 不超过 PIPE_BUF 的写入要么整体写入要么不写，大的写入可以只写入一部分；大的写入阻塞时
+/// 读端全部关闭，返回已写入的字节数，之后再写返回 -32（EPIPE）。最后多个写者经 4 KiB 的
+/// 管道向一个读者传送 10 MiB：小的写入逐条校验从未交错，大的写入校验字节总和。
+/// 正确输出：Test pipe size OK!
+
+const NONBLOCK: u32 = OpenFlags::NONBLOCK.bits();
+const PAGE: usize = 4096;
+const WRITERS: usize = 4;
+/// 每个写者用小的写入发送的字节数
+const SMALL_BYTES: usize = 1024 * 1024;
+/// 每个写者大的写入的次数，和每次的字节数
+const CHUNKS: usize = 525;
+const CHUNK: usize = 3000;
+/// 记录头：写者编号、序号、负载长度
+const HEADER: usize = 6;
+
+fn wait_child(pid: isize) -> i32 {
+    assert!(pid > 0);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// 读满 `buf`，写端全部关闭时返回 false
+fn read_exact(fd: usize, buf: &mut [u8]) -> bool {
+    let mut total = 0;
+    while total < buf.len() {
+        let len = read(fd, &mut buf[total..]);
+        assert!(len >= 0);
+        if len == 0 {
+            assert_eq!(total, 0);
+            return false;
+        }
+        total += len as usize;
+    }
+    true
+}
+
+fn payload_byte(id: usize, seq: usize, i: usize) -> u8 {
+    (id * 31 + seq * 7 + i) as u8
+}
+
+fn chunk_byte(id: usize, i: usize) -> u8 {
+    (id * 13 + i % 251) as u8
+}
+
+/// 写者 `id` 发送一条条记录，每条一次写入，不超过 PIPE_BUF
+fn small_writer(id: usize, fd: usize) -> i32 {
+    let mut record = [0u8; PIPE_BUF];
+    let mut seed = id as u32 + 1;
+    let (mut seq, mut sent) = (0, 0);
+    while sent < SMALL_BYTES {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let len = (seed >> 16) as usize % (PIPE_BUF - HEADER + 1);
+        record[0] = id as u8;
+        record[1..4].copy_from_slice(&(seq as u32).to_le_bytes()[..3]);
+        record[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+        for i in 0..len {
+            record[HEADER + i] = payload_byte(id, seq, i);
+        }
+        if write(fd, &record[..HEADER + len]) != (HEADER + len) as isize {
+            return 1;
+        }
+        seq += 1;
+        sent += HEADER + len;
+    }
+    0
+}
+
+/// 写者 `id` 用大于 PIPE_BUF 的写入发送
+fn large_writer(id: usize, fd: usize) -> i32 {
+    let mut chunk = [0u8; CHUNK];
+    for (i, b) in chunk.iter_mut().enumerate() {
+        *b = chunk_byte(id, i);
+    }
+    for _ in 0..CHUNKS {
+        if write(fd, &chunk) != CHUNK as isize {
+            return 1;
+        }
+    }
+    0
+}
+
+/// 启动 WRITERS 个写者，返回它们的 pid 和读端
+fn spawn_writers(writer: fn(usize, usize) -> i32) -> ([isize; WRITERS], usize) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fcntl(pipe_fd[0], F_GETPIPE_SZ, 0), PAGE as isize);
+    let mut pids = [0isize; WRITERS];
+    for (id, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            close(pipe_fd[0]);
+            exit(writer(id, pipe_fd[1]));
+        }
+    }
+    close(pipe_fd[1]);
+    (pids, pipe_fd[0])
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // pipe2 的标志
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, 1), -22);
+    assert_eq!(pipe2(&mut pipe_fd, NONBLOCK | O_CLOEXEC), 0);
+    let (rfd, wfd) = (pipe_fd[0], pipe_fd[1]);
+    assert_eq!(fcntl(rfd, F_GETFL, 0), NONBLOCK as isize);
+    assert_eq!(fcntl(wfd, F_GETFD, 0), FD_CLOEXEC as isize);
+
+    // 容量
+    assert_eq!(fcntl(wfd, F_GETPIPE_SZ, 0), PAGE as isize);
+    assert_eq!(fcntl(STDOUT, F_GETPIPE_SZ, 0), -9);
+    assert_eq!(fcntl(wfd, F_SETPIPE_SZ, PAGE + 1), 2 * PAGE as isize);
+    assert_eq!(fcntl(rfd, F_GETPIPE_SZ, 0), 2 * PAGE as isize);
+    assert_eq!(fcntl(wfd, F_SETPIPE_SZ, 1 << 20), -EPERM);
+    let data = vec![b'x'; 3 * PAGE];
+    assert_eq!(write(wfd, &data), 2 * PAGE as isize);
+    assert_eq!(write(wfd, b"more"), -EAGAIN);
+    assert_eq!(fcntl(wfd, F_SETPIPE_SZ, PAGE), -16);
+    let mut buf = vec![0u8; 2 * PAGE];
+    assert_eq!(read(rfd, &mut buf[..PAGE + 100]), PAGE as isize + 100);
+    assert_eq!(fcntl(wfd, F_SETPIPE_SZ, 0), PAGE as isize);
+    // 缩小后数据不变
+    assert_eq!(read(rfd, &mut buf), PAGE as isize - 100);
+    assert!(buf[..PAGE - 100].iter().all(|&b| b == b'x'));
+
+    // 不超过 PIPE_BUF 的写入整体写入，大的写入写入放得下的部分
+    assert_eq!(write(wfd, &data[..PAGE - 100]), PAGE as isize - 100);
+    assert_eq!(write(wfd, &data[..PIPE_BUF]), -EAGAIN);
+    assert_eq!(write(wfd, &data[..101]), -EAGAIN);
+    assert_eq!(write(wfd, &data[..100]), 100);
+    assert_eq!(read(rfd, &mut buf[..300]), 300);
+    assert_eq!(write(wfd, &data[..PIPE_BUF + 1]), 300);
+    close(rfd);
+    close(wfd);
+
+    // 大的写入阻塞时读端全部关闭
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        signal(SIGPIPE, SIG_IGN);
+        let written = write(pipe_fd[1], &data[..2 * PAGE + 100]);
+        let ok = written >= 1000 && written <= PAGE as isize + 1000;
+        exit(if ok && write(pipe_fd[1], b"lost") == -32 {
+            0
+        } else {
+            1
+        });
+    }
+    close(pipe_fd[1]);
+    assert!(read_exact(pipe_fd[0], &mut buf[..1000]));
+    close(pipe_fd[0]);
+    assert_eq!(wait_child(pid), 0);
+
+    // 小的写入不交错：逐条解析记录
+    let (pids, rfd) = spawn_writers(small_writer);
+    let mut next_seq = [0usize; WRITERS];
+    let mut received = 0;
+    let mut record = [0u8; PIPE_BUF];
+    while read_exact(rfd, &mut record[..HEADER]) {
+        let id = record[0] as usize;
+        assert!(id < WRITERS);
+        let mut seq = [0u8; 4];
+        seq[..3].copy_from_slice(&record[1..4]);
+        assert_eq!(u32::from_le_bytes(seq) as usize, next_seq[id]);
+        let len = u16::from_le_bytes([record[4], record[5]]) as usize;
+        assert!(HEADER + len <= PIPE_BUF);
+        assert!(read_exact(rfd, &mut record[HEADER..HEADER + len]));
+        for i in 0..len {
+            assert_eq!(record[HEADER + i], payload_byte(id, next_seq[id], i));
+        }
+        next_seq[id] += 1;
+        received += HEADER + len;
+    }
+    close(rfd);
+    for pid in pids {
+        assert_eq!(wait_child(pid), 0);
+    }
+
+    // 大的写入可以交错：校验每种字节的个数和总和
+    let (pids, rfd) = spawn_writers(large_writer);
+    let mut counts = vec![0usize; 256];
+    loop {
+        let len = read(rfd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for &b in &buf[..len as usize] {
+            counts[b as usize] += 1;
+        }
+        received += len as usize;
+    }
+    close(rfd);
+    for pid in pids {
+        assert_eq!(wait_child(pid), 0);
+    }
+    let mut expected = vec![0usize; 256];
+    for id in 0..WRITERS {
+        for i in 0..CHUNK {
+            expected[chunk_byte(id, i) as usize] += CHUNKS;
+        }
+    }
+    assert!(counts == expected);
+    let sum = |counts: &[usize]| -> usize { counts.iter().enumerate().map(|(b, n)| b * n).sum() };
+    assert_eq!(sum(&counts), sum(&expected));
+    assert!(received >= WRITERS * (SMALL_BYTES + CHUNKS * CHUNK));
+    assert!(received >= 10 * 1024 * 1024);
+
+    println!("Test pipe size OK!");
+    0
+}
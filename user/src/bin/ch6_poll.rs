@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, get_time, open, pipe, poll, read, sleep_blocking, unlink, waitpid, write,
+    OpenFlags, PollFd, POLLHUP, POLLIN, POLLNVAL, POLLOUT,
+};
+
+/// poll 等待管道：子进程 100ms 后写入，父进程 500ms 超时的 poll 提前醒来并得到 POLLIN；
+/// 无人写入时等满超时返回 0。写端有空间时 POLLOUT，写端全部关闭后读端得到 POLLHUP，
+/// 未打开的描述符得到 POLLNVAL，负数描述符被忽略，普通文件总是就绪。
+/// 正确输出：Test poll OK!
+
+const FILE: &str = "poll_tmp\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (rfd, wfd) = (pipe_fd[0], pipe_fd[1]);
+
+    // 子进程 100ms 后写入
+    let pid = fork();
+    if pid == 0 {
+        close(rfd);
+        sleep_blocking(100);
+        assert_eq!(write(wfd, b"wake"), 4);
+        exit(0);
+    }
+    let start = get_time();
+    let mut fds = [PollFd::new(rfd, POLLIN)];
+    assert_eq!(poll(&mut fds, 500), 1);
+    let elapsed = get_time() - start;
+    assert!(elapsed >= 90 && elapsed < 400, "woke after {} ms", elapsed);
+    assert_eq!(fds[0].revents, POLLIN);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(rfd, &mut buf), 4);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // 超时
+    let start = get_time();
+    assert_eq!(poll(&mut fds, 100), 0);
+    assert!(get_time() - start >= 100);
+    assert_eq!(fds[0].revents, 0);
+    assert_eq!(poll(&mut fds, 0), 0);
+
+    // 写端有空间，未打开的描述符，被忽略的负数描述符，普通文件
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let mut fds = [
+        PollFd::new(rfd, POLLIN),
+        PollFd::new(wfd, POLLIN | POLLOUT),
+        PollFd::new(99, POLLIN),
+        PollFd { fd: -1, events: POLLIN, revents: 0 },
+        PollFd::new(fd as usize, POLLIN | POLLOUT),
+    ];
+    assert_eq!(poll(&mut fds, -1), 3);
+    let revents: [u16; 5] = [0, POLLOUT, POLLNVAL, 0, POLLIN | POLLOUT];
+    for (poll_fd, revents) in fds.iter().zip(revents) {
+        assert_eq!(poll_fd.revents, revents);
+    }
+    close(fd as usize);
+    assert_eq!(unlink(FILE), 0);
+
+    // 写端全部关闭
+    close(wfd);
+    let mut fds = [PollFd::new(rfd, POLLIN)];
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert_eq!(fds[0].revents, POLLHUP);
+    close(rfd);
+
+    println!("Test poll OK!");
+    0
+}
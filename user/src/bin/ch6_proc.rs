@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::errno::*;
+use user_lib::{
+    close, exit, fork, getpid, lseek, open, pipe, read, set_name, waitpid, write, OpenFlags,
+    SEEK_SET,
+};
+
+/// /proc/self/stat 与 /proc/<pid>/stat 给出 pid、名字与状态；/proc/meminfo 与 /proc/fs 给出内存与文件系统统计。
+/// /proc 下的文件在打开时生成内容：子进程退出后已打开的文件仍可读出原内容，再打开则返回 -2（ENOENT）。
+/// /proc 下的文件只读，写入返回 -9（EBADF）。
+/// 正确输出：Test proc OK!
+
+/// Read all of `fd` from its current offset
+fn read_all(fd: usize) -> String {
+    let mut content = String::new();
+    let mut buf = [0u8; 16];
+    loop {
+        let len = read(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            return content;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+}
+
+fn read_proc(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0, "cannot open {}", path);
+    let content = read_all(fd as usize);
+    close(fd as usize);
+    content
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_name("proc_test"), 0);
+    let pid = getpid();
+    let stat = read_proc("/proc/self/stat\0");
+    assert!(stat.starts_with(format!("{} (proc_test) R ", pid).as_str()), "{}", stat);
+    assert_eq!(read_proc(format!("/proc/{}/stat\0", pid).as_str()), stat);
+
+    let meminfo = read_proc("/proc/meminfo\0");
+    assert!(meminfo.starts_with("MemTotal: "));
+    assert!(meminfo.contains("\nMemFree: "));
+    let fs = read_proc("/proc/fs\0");
+    assert!(fs.starts_with("Blocks: "));
+    assert!(fs.contains("\nCacheHits: "));
+    assert_eq!(open("/proc/nothing\0", OpenFlags::RDONLY), -ENOENT);
+
+    let fd = open("/proc/meminfo\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"MemTotal: 0 kB\n"), -EBADF);
+    close(fd as usize);
+
+    // the child names itself, then blocks until its stat is open and exits
+    let mut ready_fd = [0usize; 2];
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut ready_fd), 0);
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let child = fork();
+    if child == 0 {
+        close(ready_fd[0]);
+        close(pipe_fd[1]);
+        set_name("proc_child");
+        write(ready_fd[1], b"r");
+        let mut byte = [0u8; 1];
+        read(pipe_fd[0], &mut byte);
+        exit(0);
+    }
+    close(ready_fd[1]);
+    close(pipe_fd[0]);
+    let mut byte = [0u8; 1];
+    assert_eq!(read(ready_fd[0], &mut byte), 1);
+    close(ready_fd[0]);
+    let path = format!("/proc/{}/stat\0", child);
+    let fd = open(path.as_str(), OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut head = [0u8; 4];
+    assert_eq!(read(fd, &mut head), 4);
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    let rest = read_all(fd);
+    assert!(rest.contains("(proc_child) "), "{}", rest);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert!(read_all(fd).starts_with(format!("{} (", child).as_str()));
+    close(fd);
+    assert_eq!(open(path.as_str(), OpenFlags::RDONLY), -ENOENT);
+
+    println!("Test proc OK!");
+    0
+}
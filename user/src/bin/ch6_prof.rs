@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, drop_privilege, exit, fork, open, prof_ctl, read, waitpid, yield_, OpenFlags, EINVAL,
+    EPERM, SYSCALL_YIELD,
+};
+
+/// 系统调用延迟直方图：打开统计后内核为每个系统调用号记录次数、总延迟、最大延迟和按 log2
+/// 分桶的计数，/proc/syscalls 每个调用过的编号一行。调用 N 次 yield 后 yield 一行的次数
+/// 至少为 N（其他进程等待子进程时也会 yield），各桶之和等于次数；关闭后不再记录，
+/// 清零后该行消失。放弃特权的进程不能开关统计。结束时恢复原来的设置。
+/// 正确输出：Test prof OK!
+
+const YIELDS: u64 = 100;
+
+fn read_proc(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0, "cannot open {}", path);
+    let mut content = String::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd as usize);
+    content
+}
+
+/// Count, total, worst latency and the sum of the buckets of `id`, None if
+/// it has no line
+fn profile_of(id: usize) -> Option<(u64, u64, u64, u64)> {
+    let text = read_proc("/proc/syscalls\0");
+    assert!(text.starts_with("# id count total max log2("), "{}", text);
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields[0].parse::<usize>().unwrap() != id {
+            continue;
+        }
+        let number = |i: usize| fields[i].parse::<u64>().unwrap();
+        let mut in_buckets = 0;
+        for bucket in &fields[4..] {
+            let (log2, count) = bucket.split_once(':').unwrap();
+            assert!(log2.parse::<u32>().unwrap() < 32);
+            in_buckets += count.parse::<u64>().unwrap();
+        }
+        return Some((number(1), number(2), number(3), in_buckets));
+    }
+    None
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let old = prof_ctl(-1, false);
+    assert!(old == 0 || old == 1);
+    assert_eq!(prof_ctl(2, false), -EINVAL);
+    assert_eq!(prof_ctl(-2, false), -EINVAL);
+
+    assert_eq!(prof_ctl(1, true), old);
+    for _ in 0..YIELDS {
+        yield_();
+    }
+    assert_eq!(prof_ctl(0, false), 1);
+    let (count, total, max, in_buckets) = profile_of(SYSCALL_YIELD).unwrap();
+    assert!(count >= YIELDS, "only {} yields", count);
+    assert_eq!(in_buckets, count);
+    assert!(max <= total);
+
+    // 关闭后不再记录
+    for _ in 0..10 {
+        yield_();
+    }
+    assert_eq!(profile_of(SYSCALL_YIELD).unwrap().0, count);
+    // 清零
+    assert_eq!(prof_ctl(-1, true), 0);
+    assert!(profile_of(SYSCALL_YIELD).is_none());
+
+    let pid = fork();
+    if pid == 0 {
+        drop_privilege();
+        assert_eq!(prof_ctl(-1, false), -EPERM);
+        assert_eq!(prof_ctl(1, false), -EPERM);
+        exit(0);
+    }
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(prof_ctl(old, false), 0);
+    println!("Test prof OK!");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, dirents, dup2, exec, exit, fork, getdents, open, pipe, read, unlink, waitpid, write,
+    OpenFlags, STDIN,
+};
+
+/// shell 的重定向与管道：`<` 把文件接到第一个命令的标准输入，`>` 把最后一个命令的标准输出
+/// 截断写入文件，都在 fork 之后、exec 之前用 dup2 完成，非 CLOEXEC 的描述符在 exec 后保留。
+/// 通过管道把脚本交给 shell，执行 `ch6b_ls | ch6b_count > out.txt`，out.txt 中应是根目录
+/// 的目录项数；再执行 `ch6b_count < out.txt > out2.txt`，out2.txt 中应是 1。
+/// 标准输入不是终端时，shell 读到结束就退出。
+/// 正确输出：Test redirect OK!
+
+const SCRIPT: &str = "ch6b_ls | ch6b_count > out.txt\nch6b_count < out.txt > out2.txt\n";
+
+/// 读出整个文件
+fn read_file(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut len = 0;
+    loop {
+        let n = read(fd, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 预先建好两个输出文件，写入较长的内容，检查 `>` 会截断
+    for path in ["out.txt\0", "out2.txt\0"] {
+        let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd >= 0);
+        assert_eq!(write(fd as usize, b"junk junk junk junk\n"), 20);
+        close(fd as usize);
+    }
+    // 根目录的目录项数，此后不再变化
+    let dir = open("/\0", OpenFlags::RDONLY);
+    assert!(dir >= 0);
+    let mut buf = [0u8; 512];
+    let mut entries = 0;
+    loop {
+        let len = getdents(dir as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        entries += dirents(&buf[..len as usize]).count();
+    }
+    close(dir as usize);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(dup2(pipe_fd[0], STDIN), STDIN as isize);
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+        exec("ch6b_user_shell\0", &[0 as *const u8]);
+        exit(-4);
+    }
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], SCRIPT.as_bytes()), SCRIPT.len() as isize);
+    close(pipe_fd[1]);
+    let mut exit_code = 1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    let len = read_file("out.txt\0", &mut buf);
+    assert_eq!(&buf[..len], format!("{}\n", entries).as_bytes());
+    let len = read_file("out2.txt\0", &mut buf);
+    assert_eq!(&buf[..len], b"1\n");
+    assert_eq!(unlink("out.txt\0"), 0);
+    assert_eq!(unlink("out2.txt\0"), 0);
+    println!("Test redirect OK!");
+    0
+}
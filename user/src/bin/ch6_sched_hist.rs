@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, get_time, open, prof_ctl, read, sleep_blocking, uname, waitpid, OpenFlags,
+    Utsname,
+};
+
+/// 调度直方图：打开统计后内核记录每次切换的开销和被切换下去的任务运行的时间片长度，
+/// 主动让出（yield、阻塞、退出）与时钟抢占分开统计，/proc/sched_hist 每种一行。
+/// 两个忙等的子进程轮流运行时，被抢占的时间片大多落在时钟中断间隔所在的 log2 桶或其下一个桶，
+/// 平均长度在间隔的一半到两倍之间（按默认的 stride 调度，每个时钟中断都抢占）。
+/// 每种切换的开销与时间片次数相同。结束时恢复原来的设置。
+/// 正确输出：Test sched hist OK!
+
+/// 每个子进程忙等的毫秒数
+const SPIN_MS: isize = 300;
+
+fn read_proc(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0, "cannot open {}", path);
+    let mut content = String::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd as usize);
+    content
+}
+
+/// 一行的次数、总和与各桶 (log2, 次数)
+struct Line {
+    count: u64,
+    total: u64,
+    buckets: Vec<(u32, u64)>,
+}
+
+fn line_of(text: &str, name: &str) -> Line {
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields[0] != name {
+            continue;
+        }
+        let buckets: Vec<(u32, u64)> = fields[4..]
+            .iter()
+            .map(|bucket| {
+                let (log2, count) = bucket.split_once(':').unwrap();
+                (log2.parse().unwrap(), count.parse().unwrap())
+            })
+            .collect();
+        let line = Line {
+            count: fields[1].parse().unwrap(),
+            total: fields[2].parse().unwrap(),
+            buckets,
+        };
+        assert_eq!(line.buckets.iter().map(|(_, count)| count).sum::<u64>(), line.count);
+        return line;
+    }
+    panic!("no {} in {}", name, text);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut utsname = Utsname::new();
+    assert_eq!(uname(&mut utsname), 0);
+    let interval_us = 1_000_000 / utsname.clock_ticks;
+    let interval_log2 = u64::BITS - 1 - interval_us.leading_zeros();
+
+    let old = prof_ctl(1, true);
+    assert!(old == 0 || old == 1);
+    let mut pids = Vec::new();
+    for _ in 0..2 {
+        let pid = fork();
+        if pid == 0 {
+            let start = get_time();
+            while get_time() - start < SPIN_MS {}
+            exit(0);
+        }
+        pids.push(pid);
+    }
+    // 阻塞而不是 yield，让两个子进程轮流运行
+    sleep_blocking(SPIN_MS as usize * 2);
+    for pid in pids {
+        let mut exit_code = 1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(prof_ctl(0, false), 1);
+
+    let text = read_proc("/proc/sched_hist\0");
+    assert!(text.starts_with("# name count total max log2:count"), "{}", text);
+    let preempted = line_of(&text, "slice_preempted");
+    let voluntary = line_of(&text, "slice_voluntary");
+    assert_eq!(line_of(&text, "switch_preempted").count, preempted.count);
+    assert_eq!(line_of(&text, "switch_voluntary").count, voluntary.count);
+    // 至少有子进程退出和本进程睡眠这几次主动切换
+    assert!(voluntary.count >= 3);
+    assert!(preempted.count >= 10, "only {} preemptions", preempted.count);
+    let near: u64 = preempted
+        .buckets
+        .iter()
+        .filter(|(log2, _)| *log2 + 1 == interval_log2 || *log2 == interval_log2)
+        .map(|(_, count)| count)
+        .sum();
+    assert!(near * 2 > preempted.count, "{}", text);
+    let mean = preempted.total / preempted.count;
+    assert!(mean * 2 >= interval_us && mean <= interval_us * 2, "{}", text);
+
+    prof_ctl(old, true);
+    println!("Test sched hist OK!");
+    0
+}
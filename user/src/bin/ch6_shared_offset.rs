@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, dup, exit, fork, fstat, lseek, open, pipe, read, unlink, waitpid, write, OpenFlags,
+    Stat, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// fork 与 dup 得到的描述符共享同一个打开的文件，也就共享偏移：父进程写 "AB"、子进程写 "CD"，
+/// 文件长 4 字节，内容是两者的某种先后拼接，不会互相覆盖。重新 open 得到独立的偏移。
+/// lseek 移动共享的偏移，对管道返回 -29（ESPIPE），超过 u32::MAX 的偏移返回 -22（EINVAL），
+/// 越过 u32::MAX 的写入返回 -27（EFBIG）。
+/// 正确输出：Test shared offset OK!
+
+const FILE: &str = "shared_offset_tmp\0";
+const ROUNDS: usize = 50;
+
+fn file_size(fd: usize) -> usize {
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    stat.size as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(fd, b"CD"), 2);
+        exit(0);
+    }
+    assert_eq!(write(fd, b"AB"), 2);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(file_size(fd), 4);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 4);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd, &mut buf), 4);
+    assert!(&buf[..4] == b"ABCD" || &buf[..4] == b"CDAB");
+
+    // 交替写入许多次，每次写入都占据自己的位置
+    let pid = fork();
+    if pid == 0 {
+        for _ in 0..ROUNDS {
+            assert_eq!(write(fd, b"cd"), 2);
+        }
+        exit(0);
+    }
+    for _ in 0..ROUNDS {
+        assert_eq!(write(fd, b"ab"), 2);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(file_size(fd), 4 + 4 * ROUNDS);
+    assert_eq!(lseek(fd, 4, SEEK_SET), 4);
+    let (mut ab, mut cd) = (0, 0);
+    for _ in 0..2 * ROUNDS {
+        assert_eq!(read(fd, &mut buf[..2]), 2);
+        match &buf[..2] {
+            b"ab" => ab += 1,
+            b"cd" => cd += 1,
+            other => panic!("torn write {:?}", other),
+        }
+    }
+    assert_eq!((ab, cd), (ROUNDS, ROUNDS));
+
+    // dup 共享偏移，再次 open 不共享
+    let dup_fd = dup(fd);
+    assert!(dup_fd > 0);
+    let dup_fd = dup_fd as usize;
+    assert_eq!(lseek(dup_fd, -2, SEEK_END), (2 + 4 * ROUNDS) as isize);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), (2 + 4 * ROUNDS) as isize);
+    let other = open(FILE, OpenFlags::RDONLY);
+    assert!(other > 0);
+    let other = other as usize;
+    assert_eq!(lseek(other, 0, SEEK_CUR), 0);
+    assert_eq!(read(other, &mut buf[..2]), 2);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), (2 + 4 * ROUNDS) as isize);
+    assert_eq!(lseek(fd, -1, SEEK_SET), -EINVAL);
+    assert_eq!(lseek(fd, -1000, SEEK_CUR), -EINVAL);
+    assert_eq!(lseek(fd, 1 << 32, SEEK_SET), -EINVAL);
+    assert_eq!(lseek(fd, (2 + 4 * ROUNDS) as isize, SEEK_SET), (2 + 4 * ROUNDS) as isize);
+    assert_eq!(lseek(fd, u32::MAX as isize, SEEK_CUR), -EINVAL);
+    let max = u32::MAX as isize - 1;
+    assert_eq!(lseek(fd, max, SEEK_SET), max);
+    assert_eq!(write(fd, b"tail"), -EFBIG);
+    assert_eq!(file_size(fd), 4 + 4 * ROUNDS);
+    close(other);
+    close(dup_fd);
+    close(fd);
+    assert_eq!(unlink(FILE), 0);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_CUR), -ESPIPE);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    println!("Test shared offset OK!");
+    0
+}
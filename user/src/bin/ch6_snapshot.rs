@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, drop_privilege, exit, fork, fs_restore, fs_snapshot, fstat, open, read, unlink,
+    waitpid, write, OpenFlags, Stat, ENOENT, EPERM, ESTALE,
+};
+
+/// 快照整个文件系统后改写、删除、新建文件，再恢复快照：文件内容回到快照时的样子，
+/// 快照之后新建的文件消失。打开在已被恢复释放的文件上的描述符读与 fstat 返回 -ESTALE，
+/// 打开在快照前就存在的文件上的描述符照常可用。放弃特权的进程不能快照或恢复。
+/// 正确输出：Test snapshot OK!
+
+const FILE_A: &str = "snapshot_a\0";
+const FILE_B: &str = "snapshot_b\0";
+const FILE_NEW: &str = "snapshot_new\0";
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+fn check_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 64];
+    assert_eq!(read(fd as usize, &mut buf), data.len() as isize);
+    assert_eq!(&buf[..data.len()], data);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    write_file(FILE_A, b"original a");
+    write_file(FILE_B, b"original b");
+    assert!(fs_snapshot() > 0);
+    let fd_a = open(FILE_A, OpenFlags::RDONLY);
+    assert!(fd_a > 0);
+
+    write_file(FILE_A, b"changed after the snapshot");
+    assert_eq!(unlink(FILE_B), 0);
+    write_file(FILE_NEW, b"new");
+    let fd_new = open(FILE_NEW, OpenFlags::RDWR);
+    assert!(fd_new > 0);
+
+    assert!(fs_restore() > 0);
+    check_file(FILE_A, b"original a");
+    check_file(FILE_B, b"original b");
+    assert_eq!(open(FILE_NEW, OpenFlags::RDONLY), -ENOENT);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd_new as usize, &mut buf), -ESTALE);
+    assert_eq!(write(fd_new as usize, b"x"), -ESTALE);
+    assert_eq!(fstat(fd_new as usize, &Stat::new()), -ESTALE);
+    assert_eq!(read(fd_a as usize, &mut buf), 10);
+    assert_eq!(&buf[..10], b"original a");
+    close(fd_new as usize);
+    close(fd_a as usize);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(drop_privilege(), 0);
+        assert_eq!(fs_snapshot(), -EPERM);
+        assert_eq!(fs_restore(), -EPERM);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(unlink(FILE_A), 0);
+    assert_eq!(unlink(FILE_B), 0);
+    println!("Test snapshot OK!");
+    0
+}
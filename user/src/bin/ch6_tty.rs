@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{
+    close, getpgid, ioctl, open, tcgetlflag, tcgetpgrp, tcsetlflag, tcsetpgrp, OpenFlags, ECHO,
+    ICANON, ISIG, STDIN, STDOUT,
+};
+
+/// 控制台默认处于规范模式：回显、按行读取、Ctrl-C 产生 SIGINT。ioctl 可以切换为原始模式再切回，
+/// 标准输入与标准输出是同一个控制台。设置接收 Ctrl-C 的进程组必须有存在的进程，0 表示没有。
+/// 对不是控制台的文件 ioctl 返回 -25（ENOTTY），未知请求返回 -22（EINVAL）。
+/// 正确输出：Test tty OK!
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let canonical = (ISIG | ICANON | ECHO) as isize;
+    assert_eq!(tcgetlflag(STDIN), canonical);
+    assert_eq!(tcsetlflag(STDIN, 0), 0);
+    assert_eq!(tcgetlflag(STDOUT), 0);
+    assert_eq!(tcsetlflag(STDIN, ICANON | ECHO), 0);
+    assert_eq!(tcgetlflag(STDIN), (ICANON | ECHO) as isize);
+    assert_eq!(tcsetlflag(STDIN, 0o100000), -EINVAL);
+    assert_eq!(tcsetlflag(STDIN, ISIG | ICANON | ECHO), 0);
+    assert_eq!(tcgetlflag(STDIN), canonical);
+
+    let old = tcgetpgrp(STDIN);
+    assert!(old >= 0);
+    assert_eq!(tcsetpgrp(STDIN, getpgid(0)), 0);
+    assert_eq!(tcgetpgrp(STDOUT), getpgid(0));
+    assert_eq!(tcsetpgrp(STDIN, 99999), -ESRCH);
+    assert_eq!(tcsetpgrp(STDIN, -1), -EINVAL);
+    assert_eq!(tcsetpgrp(STDIN, old), 0);
+
+    let fd = open("/dev/null\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(tcgetlflag(fd as usize), -ENOTTY);
+    close(fd as usize);
+    assert_eq!(tcgetlflag(99), -EBADF);
+    let mut word = 0u32;
+    assert_eq!(ioctl(STDIN, 0x1234, &mut word as *mut u32 as usize), -EINVAL);
+
+    println!("Test tty OK!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    gettid, syscall, syscall6, thread_create, uname, Utsname, ENOSYS, FEATURE_ASLR,
+    FEATURE_EVENTFD, FEATURE_FS, FEATURE_FUTEX, FEATURE_MMAP, FEATURE_PIPE, FEATURE_SELFTEST,
+    FEATURE_SHM, FEATURE_SIGNALS, FEATURE_SPAWN, FEATURE_STRIDE, SYSCALL_UNAME,
+};
+
+/// uname 报告内核名称、版本（提交与构建日期）以及可选系统调用的特性位，探测程序据此
+/// 打印内核支持的特性。不存在的系统调用号（包括本用户库有封装而内核未实现的线程调用、
+/// 超出统计表范围的大编号）返回 -ENOSYS，而不是让内核 panic。
+/// 正确输出：Test uname OK!
+
+const FEATURES: [(u64, &str); 11] = [
+    (FEATURE_MMAP, "mmap"),
+    (FEATURE_SPAWN, "spawn"),
+    (FEATURE_SIGNALS, "signals"),
+    (FEATURE_SHM, "shm"),
+    (FEATURE_PIPE, "pipe"),
+    (FEATURE_FS, "fs"),
+    (FEATURE_STRIDE, "stride"),
+    (FEATURE_FUTEX, "futex"),
+    (FEATURE_EVENTFD, "eventfd"),
+    (FEATURE_ASLR, "aslr"),
+    (FEATURE_SELFTEST, "selftest"),
+];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut uts = Utsname::new();
+    assert_eq!(uname(&mut uts), 0);
+    assert_eq!(Utsname::field(&uts.sysname), "rCore");
+    assert_eq!(Utsname::field(&uts.machine), "riscv64");
+    assert!(Utsname::field(&uts.version).starts_with('#'));
+    println!(
+        "{} {} {} {} {}",
+        Utsname::field(&uts.sysname),
+        Utsname::field(&uts.nodename),
+        Utsname::field(&uts.release),
+        Utsname::field(&uts.version),
+        Utsname::field(&uts.machine)
+    );
+    print!("features:");
+    for (bit, name) in FEATURES.iter() {
+        if uts.features & bit != 0 {
+            print!(" {}", name);
+        }
+    }
+    println!("");
+    assert!(uts.features & FEATURE_MMAP != 0 && uts.features & FEATURE_SPAWN != 0);
+    // 不认识的特性位都为 0
+    let known = FEATURES.iter().fold(0, |known, (bit, _)| known | bit);
+    assert_eq!(uts.features & !known, 0);
+    // 地址无效
+    assert!(syscall(SYSCALL_UNAME, [0, 0, 0]) < 0);
+
+    // 不存在的系统调用
+    assert_eq!(thread_create(0, 0), -ENOSYS);
+    assert_eq!(gettid(), -ENOSYS);
+    assert_eq!(syscall(9999, [1, 2, 3]), -ENOSYS);
+    assert_eq!(syscall6(usize::MAX, [0; 6]), -ENOSYS);
+    println!("Test uname OK!");
+    0
+}
@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{
+    exit, fork, getrusage, setpgid, sleep_blocking, unlink, wait4, waitpid, wexitstatus, wifexited,
+    wifsignaled, wtermsig, Rusage, ECHILD, RUSAGE_SELF, SIGSEGV,
+};
+
+/// wait4 等待指定子进程、任意子进程（-1）或进程组 -pgid 中的任意子进程，并返回其资源使用：
+/// 按进程组回收时只回收组内的子进程，组内没有子进程后返回 -10（ECHILD），组外的子进程不受影响；
+/// 资源使用中的 CPU 时间与内存峰值不小于子进程自己统计到的值；
+/// 等待状态区分正常退出（低 7 位为 0，退出码在下一个字节）和被信号终止（低 7 位为信号编号），
+/// 访问空指针的子进程报告为被 SIGSEGV 终止，exit(-1) 的子进程报告为退出码 255。
+/// 正确输出：Test wait4 OK!
+
+/// 用户态 CPU 时间至少这么多微秒后才退出
+const BUSY_US: usize = 30_000;
+/// 子进程访问的堆页数
+const TOUCHED_PAGES: usize = 32;
+
+fn group() {
+    // 组外的子进程，最后才回收
+    let outsider = fork();
+    if outsider == 0 {
+        sleep_blocking(50);
+        exit(7);
+    }
+    let mut members: Vec<isize> = Vec::new();
+    for i in 0..3 {
+        let pgid = members.first().map_or(0, |pid| *pid as usize);
+        let pid = fork();
+        if pid == 0 {
+            setpgid(0, pgid);
+            sleep_blocking(10 * i);
+            exit(10 + i as i32);
+        }
+        setpgid(pid as usize, pgid);
+        members.push(pid);
+    }
+    let pgid = members[0];
+    let mut codes = Vec::new();
+    for _ in 0..members.len() {
+        let mut status = 0;
+        let pid = wait4(-pgid, &mut status, None);
+        assert!(members.contains(&pid));
+        assert!(wifexited(status));
+        codes.push(wexitstatus(status));
+    }
+    codes.sort();
+    assert_eq!(codes, [10, 11, 12]);
+    // 组内已经没有子进程，组外的子进程还在
+    let mut status = 0;
+    assert_eq!(wait4(-pgid, &mut status, None), -ECHILD);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(outsider as usize, &mut exit_code), outsider);
+    assert_eq!(exit_code, 7);
+}
+
+fn usage() {
+    let pid = fork();
+    if pid == 0 {
+        let mut pages = vec![0u8; TOUCHED_PAGES * 4096];
+        for page in pages.chunks_mut(4096) {
+            page[0] = 1;
+        }
+        let mut own = Rusage::default();
+        loop {
+            assert_eq!(getrusage(RUSAGE_SELF, &mut own), 0);
+            if own.utime_us >= BUSY_US {
+                break;
+            }
+        }
+        exit(pages.iter().filter(|byte| **byte == 1).count() as i32);
+    }
+    let mut status = 0;
+    let mut usage = Rusage::default();
+    assert_eq!(wait4(pid, &mut status, Some(&mut usage)), pid);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), TOUCHED_PAGES as i32);
+    assert!(usage.utime_us >= BUSY_US);
+    // 忙等不过几百毫秒
+    assert!(usage.utime_us + usage.stime_us < 10_000_000);
+    assert!(usage.peak_resident_pages >= TOUCHED_PAGES);
+}
+
+fn signals() {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            core::ptr::null_mut::<u8>().write_volatile(1);
+        }
+        unreachable!();
+    }
+    let mut status = 0;
+    assert_eq!(wait4(pid, &mut status, None), pid);
+    assert!(wifsignaled(status));
+    assert!(!wifexited(status));
+    assert_eq!(wtermsig(status), SIGSEGV);
+    unlink(format!("core.{}\0", pid).as_str());
+    // exit(-1) 与被 SIGHUP 终止不同
+    let pid = fork();
+    if pid == 0 {
+        exit(-1);
+    }
+    assert_eq!(wait4(-1, &mut status, None), pid);
+    assert!(wifexited(status));
+    assert_eq!(wexitstatus(status), 255);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    group();
+    usage();
+    signals();
+    println!("Test wait4 OK!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::*;
+use user_lib::{fcntl, read, write, F_GETFD};
+
+/// 由 ch6_cloexec 通过 exec 运行：fd 10 是一个管道的读端，其写端 fd 11 设置了 FD_CLOEXEC，
+/// exec 后应已关闭，于是读到 0；fd 12 是另一个管道的写端，没有 FD_CLOEXEC，应仍然可写。
+/// 返回 0 表示符合预期。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    if fcntl(11, F_GETFD, 0) != -EBADF {
+        println!("cloexec fd 11 survived exec");
+        return 1;
+    }
+    if fcntl(12, F_GETFD, 0) != 0 {
+        println!("fd 12 is gone or marked cloexec");
+        return 2;
+    }
+    let mut buf = [0u8; 8];
+    if read(10, &mut buf) != 0 {
+        println!("read from fd 10 did not see the end of the pipe");
+        return 3;
+    }
+    if write(12, b"alive") != 5 {
+        return 4;
+    }
+    0
+}
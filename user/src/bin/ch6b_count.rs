@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{read, STDIN};
+
+/// 读到标准输入结束，输出其行数，如 ch6b_ls | ch6b_count > out.txt。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 256];
+    let mut lines = 0;
+    loop {
+        let len = read(STDIN, &mut buf);
+        if len < 0 {
+            return -1;
+        }
+        if len == 0 {
+            break;
+        }
+        lines += buf[..len as usize].iter().filter(|c| **c == b'\n').count();
+    }
+    println!("{}", lines);
+    0
+}
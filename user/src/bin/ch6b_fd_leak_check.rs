@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fcntl, EBADF, F_GETFD};
+
+/// 由 ch6_dup3 在 fork 之后立即 exec：除标准输入输出外 fd 3 到 31 都应已关闭。
+/// 返回 0 表示没有描述符漏过 exec，否则返回第一个仍然打开的描述符。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for fd in 3..32 {
+        if fcntl(fd, F_GETFD, 0) != -EBADF {
+            println!("fd {} survived exec", fd);
+            return fd as i32;
+        }
+    }
+    0
+}
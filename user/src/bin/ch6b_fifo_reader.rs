@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, OpenFlags};
+
+/// 由 ch6_fifo 通过 spawn 运行：打开命名管道 fifo_tmp 的读端（等待写端打开），
+/// 读到写端关闭为止，检查共 1 MiB 且第 i 个字节为 i % 251。返回 0 表示符合预期。
+
+const TOTAL: usize = 1 << 20;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("fifo_tmp\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        return 1;
+    }
+    let fd = fd as usize;
+    let mut buf = [0u8; 1000];
+    let mut total = 0;
+    loop {
+        let len = read(fd, &mut buf);
+        if len < 0 {
+            return 2;
+        }
+        if len == 0 {
+            break;
+        }
+        for (i, byte) in buf[..len as usize].iter().enumerate() {
+            if *byte != ((total + i) % 251) as u8 {
+                println!("byte {} is wrong", total + i);
+                return 3;
+            }
+        }
+        total += len as usize;
+    }
+    close(fd);
+    if total != TOTAL {
+        println!("read {} bytes", total);
+        return 4;
+    }
+    0
+}
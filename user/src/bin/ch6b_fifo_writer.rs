@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{close, open, write, OpenFlags};
+
+/// 由 ch6_fifo 通过 spawn 运行：打开命名管道 fifo_tmp 的写端（等待读端打开），
+/// 写入 1 MiB 数据，第 i 个字节为 i % 251。返回 0 表示全部写入。
+
+const TOTAL: usize = 1 << 20;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("fifo_tmp\0", OpenFlags::WRONLY);
+    if fd < 0 {
+        return 1;
+    }
+    let fd = fd as usize;
+    let mut buf = [0u8; 4096];
+    let mut written = 0;
+    while written < TOTAL {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = ((written + i) % 251) as u8;
+        }
+        if write(fd, &buf) != buf.len() as isize {
+            return 2;
+        }
+        written += buf.len();
+    }
+    close(fd);
+    0
+}
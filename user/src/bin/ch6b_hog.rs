@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, getpid};
+
+/// 在 shell 中运行（内核需以 make run SCHED=fifo 构建，时钟中断不会抢占）：
+/// 在用户态忙等 8 秒，从不让出处理器。
+/// 约 5 秒后内核应报告 lockup: pid N 'ch6b_hog' has run for 500 ticks without a switch，
+/// 之后本程序正常结束。
+
+const SECONDS: isize = 8;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("pid {} spins for {} s", getpid(), SECONDS);
+    let end = get_time() + SECONDS * 1000;
+    while get_time() < end {}
+    println!("hog done");
+    0
+}
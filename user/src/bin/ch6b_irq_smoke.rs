@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, kill, open, read, unlink, waitpid, write, OpenFlags, SIGKILL, STDIN};
+
+/// 在 shell 中运行：子进程不停地写入并读回一个文件，磁盘的完成中断不断到来，
+/// 同时父进程逐行读入标准输入并回显，串口的接收中断与之交替。
+/// 随意输入几行，每行都应立即回显，子进程的进度也应继续输出，系统不应卡住；
+/// 输入空行后结束子进程。
+
+const BLOCKS: usize = 16;
+
+fn hammer() -> ! {
+    let mut buf = [0u8; 512];
+    let mut rounds = 0usize;
+    loop {
+        let fd = open("irq_smoke\0", OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+        assert!(fd >= 0);
+        let fd = fd as usize;
+        buf.fill(rounds as u8);
+        for _ in 0..BLOCKS {
+            assert_eq!(write(fd, &buf), buf.len() as isize);
+        }
+        close(fd);
+        let fd = open("irq_smoke\0", OpenFlags::RDONLY) as usize;
+        for _ in 0..BLOCKS {
+            assert_eq!(read(fd, &mut buf), buf.len() as isize);
+            assert!(buf.iter().all(|&b| b == rounds as u8));
+        }
+        close(fd);
+        rounds += 1;
+        if rounds % 16 == 0 {
+            println!("[disk] {} rounds", rounds);
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        hammer();
+    }
+    let mut buf = [0u8; 256];
+    let mut at_line_start = true;
+    loop {
+        let len = read(STDIN, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        let input = &buf[..len as usize];
+        if at_line_start && input == b"\n" {
+            break;
+        }
+        print!("{}", core::str::from_utf8(input).unwrap_or("?"));
+        at_line_start = input.last() == Some(&b'\n');
+    }
+    kill(pid as usize, SIGKILL);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    unlink("irq_smoke\0");
+    println!("irq smoke done");
+    exit(0)
+}
@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, overflow_kernel_stack, ENOSYS};
+
+/// 在 shell 中运行（内核需以 make run SELFTEST=1 构建）：内核为本进程无限递归，
+/// 内核栈越过栈底后访问其下方未映射的保护页。
+/// 内核应报告 kernel stack overflow in pid N（N 为下面输出的 pid）并停机，
+/// 而不是破坏相邻进程的内核栈后在别处出错。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("pid {} overflows its kernel stack", getpid());
+    if overflow_kernel_stack() == -ENOSYS {
+        println!("kernel built without self-tests, run with SELFTEST=1");
+        return -1;
+    }
+    panic!("kernel stack overflow not detected");
+}
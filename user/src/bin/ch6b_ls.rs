@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dirents, fstat, getdents, open, OpenFlags, Stat, DT_DIR, DT_FIFO};
+
+/// 像 `ls -l` 一样列出根目录下的所有文件与目录：类型与权限、链接数、大小和名字，目录名后加 `/`
+
+/// `drwxr-xr-x` for a directory with mode 0o755
+fn mode_string(kind: u8, perm: u32) -> [u8; 10] {
+    let mut text = *b"-rwxrwxrwx";
+    text[0] = match kind {
+        DT_DIR => b'd',
+        DT_FIFO => b'p',
+        _ => b'-',
+    };
+    for bit in 0..9 {
+        if perm & (1 << (8 - bit)) == 0 {
+            text[1 + bit] = b'-';
+        }
+    }
+    text
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("Error when opening /");
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut buf = [0u8; 512];
+    let mut path = [0u8; 64];
+    loop {
+        let len = getdents(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for entry in dirents(&buf[..len as usize]) {
+            // a named pipe must not wait for a writer here
+            path[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+            path[entry.name.len()] = 0;
+            let path = core::str::from_utf8(&path[..entry.name.len() + 1]).unwrap();
+            let stat = Stat::new();
+            let file = open(path, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+            if file >= 0 {
+                fstat(file as usize, &stat);
+                close(file as usize);
+            }
+            let mode = mode_string(entry.kind, stat.perm);
+            let suffix = if entry.kind == DT_DIR { "/" } else { "" };
+            println!(
+                "{} {:>2} {:>8} {}{}",
+                core::str::from_utf8(&mode).unwrap(),
+                stat.nlink,
+                stat.size,
+                entry.name,
+                suffix
+            );
+        }
+    }
+    close(fd);
+    0
+}
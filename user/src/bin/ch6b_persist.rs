@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, unlink, write, OpenFlags};
+
+/// 在 shell 中运行两次，中间重启：第一次运行写入一个文件后立即退出，不调用
+/// fsync；随后在 shell 中输入 exit 关机，再重新启动系统并再次运行本程序，
+/// 第二次运行读回文件并检查内容，最后删除该文件。
+/// 正确输出（第二次运行）：Test persist OK!
+
+const FILE: &str = "persist\0";
+const LEN: usize = 3000;
+
+fn pattern(i: usize) -> u8 {
+    (i * 7 + i / 256) as u8
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::RDONLY);
+    if fd < 0 {
+        let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        let mut buf = [0u8; LEN];
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = pattern(i);
+        }
+        assert_eq!(write(fd as usize, &buf), LEN as isize);
+        close(fd as usize);
+        println!("{} bytes written, now type exit and boot again", LEN);
+        return 0;
+    }
+    let mut buf = [0u8; LEN + 1];
+    let mut got = 0;
+    loop {
+        let len = read(fd as usize, &mut buf[got..]);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        got += len as usize;
+    }
+    close(fd as usize);
+    assert_eq!(got, LEN, "the file lost its last writes");
+    for (i, byte) in buf[..LEN].iter().enumerate() {
+        assert_eq!(*byte, pattern(i), "byte {} lost", i);
+    }
+    assert_eq!(unlink(FILE), 0);
+    println!("Test persist OK!");
+    0
+}
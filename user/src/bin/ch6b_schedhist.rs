@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, prof_ctl, read, OpenFlags};
+
+/// 在 shell 中运行：ch6b_schedhist [on|off|reset]。不带参数时输出 /proc/sched_hist，
+/// 每种切换和时间片一段，每个 log2 桶一行：范围、次数和按比例的长条；
+/// on、off 打开或关闭统计，reset 清零。切换开销的单位是周期（没有周期计数器时为时钟滴答），
+/// 时间片的单位是微秒。
+
+/// 长条最多的字符数
+const BAR: u64 = 40;
+
+/// 输出一行直方图
+fn print_line(line: &str) {
+    let mut fields = line.split(' ');
+    let name = fields.next().unwrap();
+    let count: u64 = fields.next().unwrap().parse().unwrap();
+    let total: u64 = fields.next().unwrap().parse().unwrap();
+    let max: u64 = fields.next().unwrap().parse().unwrap();
+    let mean = if count == 0 { 0 } else { total / count };
+    println!("{}: {} in all, mean {}, max {}", name, count, mean, max);
+    for bucket in fields {
+        let (log2, n) = bucket.split_once(':').unwrap();
+        let log2: u32 = log2.parse().unwrap();
+        let n: u64 = n.parse().unwrap();
+        let width = (n * BAR + count - 1) / count;
+        print!("  {:>10} ..{:>10} {:>6} ", 1u64 << log2, (1u64 << (log2 + 1)) - 1, n);
+        for _ in 0..width {
+            print!("#");
+        }
+        println!("");
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        let result = match argv[1] {
+            "on" => prof_ctl(1, false),
+            "off" => prof_ctl(0, false),
+            "reset" => prof_ctl(-1, true),
+            _ => {
+                println!("usage: ch6b_schedhist [on|off|reset]");
+                return -1;
+            }
+        };
+        if result < 0 {
+            println!("Error: not allowed to profile");
+            return -1;
+        }
+        return 0;
+    }
+    let fd = open("/proc/sched_hist\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("Error: cannot open /proc/sched_hist");
+        return -1;
+    }
+    let mut text = [0u8; 1024];
+    let mut len = 0;
+    loop {
+        let read_len = read(fd as usize, &mut text[len..]);
+        if read_len <= 0 {
+            break;
+        }
+        len += read_len as usize;
+    }
+    close(fd as usize);
+    let text = core::str::from_utf8(&text[..len]).unwrap();
+    let mut lines = text.lines();
+    println!("{}", lines.next().unwrap().trim_start_matches("# "));
+    for line in lines {
+        print_line(line);
+    }
+    0
+}
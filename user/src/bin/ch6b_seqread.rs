@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{close, get_time, open, prof_ctl, read, unlink, write, OpenFlags, STDOUT};
+
+/// 在 shell 中运行：ch6b_seqread。写一个 4 MiB 的文件，打开系统调用延迟统计后像 cat 一样
+/// 用 512 字节的缓冲区把它顺序读到 /dev/null，再用 64 KiB 的缓冲区读一遍，输出两遍的用时
+/// 和 /proc/syscalls。比较 read(63) 的延迟分布；在改动前后的内核上各运行一次即得前后对比。
+
+const FILE_SIZE: usize = 4 << 20;
+const CHUNK: usize = 4096;
+
+fn cat(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let null = open("/dev/null\0", OpenFlags::WRONLY);
+    assert!(null >= 0);
+    let start = get_time();
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, buf);
+        if len <= 0 {
+            break;
+        }
+        write(null as usize, &buf[..len as usize]);
+        total += len as usize;
+    }
+    assert_eq!(total, FILE_SIZE);
+    close(null as usize);
+    close(fd as usize);
+    get_time() - start
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "seqread\0",
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+    );
+    assert!(fd >= 0);
+    let data = [b's'; CHUNK];
+    for _ in 0..FILE_SIZE / CHUNK {
+        assert_eq!(write(fd as usize, &data), CHUNK as isize);
+    }
+    close(fd as usize);
+
+    let old = prof_ctl(1, true);
+    if old < 0 {
+        println!("Error: not allowed to profile");
+        return -1;
+    }
+    let mut small = [0u8; 512];
+    let small_ms = cat("seqread\0", &mut small);
+    // 用户栈放不下
+    let mut large = vec![0u8; 64 * 1024];
+    let large_ms = cat("seqread\0", &mut large);
+    prof_ctl(0, false);
+    println!(
+        "4 MiB read in {} ms with 512 B reads, {} ms with 64 KiB reads",
+        small_ms, large_ms
+    );
+
+    let fd = open("/proc/syscalls\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        write(STDOUT, &buf[..len as usize]);
+    }
+    close(fd as usize);
+    unlink("seqread\0");
+    prof_ctl(old, true);
+    0
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, prof_ctl, read, unlink, write, writev, IoVec, OpenFlags, STDOUT};
+
+/// 在 shell 中运行：ch6b_sysprof。打开系统调用延迟统计，用 16 次 write 和 1 次 writev 各写
+/// 8 KiB，再把文件读两遍（第二遍命中块缓存），然后输出 /proc/syscalls。比较 write(64)
+/// 与 writev(66)、两遍 read(63) 的延迟；在改动前后的内核上各运行一次即得前后对比。
+
+const CHUNK: usize = 512;
+const CHUNKS: usize = 16;
+
+fn create(path: &str) -> usize {
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+    );
+    assert!(fd >= 0);
+    fd as usize
+}
+
+fn read_through(path: &str) {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; CHUNK];
+    while read(fd as usize, &mut buf) > 0 {}
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let old = prof_ctl(1, true);
+    if old < 0 {
+        println!("Error: not allowed to profile");
+        return -1;
+    }
+    let data = [b'x'; CHUNK];
+
+    let fd = create("prof_write\0");
+    for _ in 0..CHUNKS {
+        assert_eq!(write(fd, &data), CHUNK as isize);
+    }
+    close(fd);
+    let fd = create("prof_writev\0");
+    let iov = [IoVec::new(&data); CHUNKS];
+    assert_eq!(writev(fd, &iov), (CHUNK * CHUNKS) as isize);
+    close(fd);
+    read_through("prof_write\0");
+    read_through("prof_write\0");
+
+    prof_ctl(0, false);
+    let fd = open("/proc/syscalls\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        write(STDOUT, &buf[..len as usize]);
+    }
+    close(fd as usize);
+    unlink("prof_write\0");
+    unlink("prof_writev\0");
+    prof_ctl(old, true);
+    0
+}
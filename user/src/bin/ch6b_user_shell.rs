@@ -7,58 +7,196 @@ extern crate alloc;
 extern crate user_lib;
 
 const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
-const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, waitpid};
+use user_lib::{
+    close, dup2, exec, exit, flush, fork, open, pipe, setpgid, shutdown, signal_name, tcgetlflag,
+    tcsetpgrp, wait4, wexitstatus, wifsignaled, wtermsig, OpenFlags, STDIN, STDOUT,
+};
+
+/// Commands joined by `|`, with the input of the first one and the output
+/// of the last one maybe redirected. Names end with `\0` for the syscalls.
+struct Pipeline {
+    cmds: Vec<String>,
+    input: Option<String>,
+    output: Option<String>,
+}
+
+fn with_nul(name: &str) -> String {
+    let mut name = String::from(name);
+    name.push('\0');
+    name
+}
+
+/// Parse `a < in | b | c > out`: `<` may only follow the first command and
+/// `>` the last one, each at most once. Returns why the line is wrong.
+fn parse(line: &str) -> Result<Pipeline, &'static str> {
+    let segments: Vec<&str> = line.split('|').collect();
+    let mut pipeline = Pipeline {
+        cmds: Vec::new(),
+        input: None,
+        output: None,
+    };
+    for (i, segment) in segments.iter().enumerate() {
+        let mut words = segment.split_whitespace();
+        let cmd = match words.next() {
+            Some(cmd) if !cmd.starts_with('<') && !cmd.starts_with('>') => cmd,
+            _ => return Err("missing command"),
+        };
+        pipeline.cmds.push(with_nul(cmd));
+        while let Some(word) = words.next() {
+            let (target, file) = if let Some(file) = word.strip_prefix('<') {
+                if i > 0 {
+                    return Err("redirection in the middle of a pipeline");
+                }
+                (&mut pipeline.input, file)
+            } else if let Some(file) = word.strip_prefix('>') {
+                if i + 1 < segments.len() {
+                    return Err("redirection in the middle of a pipeline");
+                }
+                (&mut pipeline.output, file)
+            } else {
+                return Err("arguments are not supported");
+            };
+            // `>out` as well as `> out`
+            let file = match file {
+                "" => words.next().ok_or("missing file name")?,
+                file => file,
+            };
+            if target.replace(with_nul(file)).is_some() {
+                return Err("redirected twice");
+            }
+        }
+    }
+    Ok(pipeline)
+}
+
+/// Open `path` as `fd` of the current process, which is about to exec
+fn redirect(path: &str, flags: OpenFlags, fd: usize) -> bool {
+    let file = open(path, flags);
+    if file < 0 {
+        println!("Error when opening file {}", path.trim_end_matches('\0'));
+        return false;
+    }
+    assert_eq!(dup2(file as usize, fd), fd as isize);
+    close(file as usize);
+    true
+}
+
+/// Run `pipeline` in a process group of its own, which gets Ctrl-C
+fn run(pipeline: &Pipeline) {
+    let cmds = &pipeline.cmds;
+    // pipes_fd[i] connects cmds[i] to cmds[i + 1]
+    let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+    for _ in 1..cmds.len() {
+        let mut pipe_fd = [0usize; 2];
+        assert_eq!(pipe(&mut pipe_fd), 0);
+        pipes_fd.push(pipe_fd);
+    }
+    flush();
+    let mut children: Vec<isize> = Vec::new();
+    for (i, cmd) in cmds.iter().enumerate() {
+        // the pipeline is a process group led by its first command, both
+        // sides join it so that neither waits
+        let pgid = children.first().map_or(0, |pid| *pid as usize);
+        let pid = fork();
+        if pid == 0 {
+            setpgid(0, pgid);
+            // receive input from the previous command, or from the file
+            if i > 0 {
+                assert_eq!(dup2(pipes_fd[i - 1][0], STDIN), STDIN as isize);
+            } else if let Some(input) = &pipeline.input {
+                if !redirect(input, OpenFlags::RDONLY, STDIN) {
+                    exit(-4);
+                }
+            }
+            // send output to the next command, or to the file
+            if i + 1 < cmds.len() {
+                assert_eq!(dup2(pipes_fd[i][1], STDOUT), STDOUT as isize);
+            } else if let Some(output) = &pipeline.output {
+                let flags = OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY;
+                if !redirect(output, flags, STDOUT) {
+                    exit(-4);
+                }
+            }
+            // otherwise the reader never sees the end of its input
+            for pipe_fd in pipes_fd.iter() {
+                close(pipe_fd[0]);
+                close(pipe_fd[1]);
+            }
+            // child process, the fds set up above survive exec
+            if exec(cmd.as_str(), &[0 as *const u8]) < 0 {
+                println!("Error when executing!");
+                exit(-4);
+            }
+            unreachable!();
+        }
+        setpgid(pid as usize, pgid);
+        children.push(pid);
+    }
+    for pipe_fd in pipes_fd.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    // Ctrl-C stops the whole pipeline rather than the shell
+    tcsetpgrp(STDIN, children[0]);
+    // reap the whole group, in whatever order its processes end
+    for _ in 0..children.len() {
+        let mut status: i32 = 0;
+        let pid = wait4(-children[0], &mut status, None);
+        assert!(children.contains(&pid));
+        if wifsignaled(status) {
+            match signal_name(wtermsig(status)) {
+                Some(name) => println!("Shell: Process {} killed by {}", pid, name),
+                None => println!("Shell: Process {} killed by signal {}", pid, wtermsig(status)),
+            }
+        } else {
+            println!("Shell: Process {} exited with code {}", pid, wexitstatus(status));
+        }
+    }
+    tcsetpgrp(STDIN, 0);
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // commands may also come from a pipe or a file, which end
+    let interactive = tcgetlflag(STDIN) >= 0;
     let mut line: String = String::new();
     print!(">> ");
     flush();
+    // the console echoes and edits the line, it only hands over whole lines
     loop {
         let c = getchar();
         match c {
-            LF | CR => {
-                print!("\n");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str(), &[0 as *const u8]) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+            // the end of a script
+            0 if !interactive => {
+                println!("");
+                return 0;
+            }
+            // a read cut short by a signal
+            0 => {}
+            LF => {
+                if line.trim() == "exit" {
+                    // written files reach the disk before power goes
+                    shutdown(false);
+                    println!("Shell: not allowed to shut down");
+                } else if !line.trim().is_empty() {
+                    // `a | b` sends the output of a to the input of b,
+                    // `cmd > file` the output of the last command to file
+                    // and `cmd < file` file to the input of the first one
+                    match parse(&line) {
+                        Ok(pipeline) => run(&pipeline),
+                        Err(err) => println!("Shell: {}", err),
                     }
-                    line.clear();
                 }
+                line.clear();
                 print!(">> ");
                 flush();
             }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{}", BS as char);
-                    print!(" ");
-                    print!("{}", BS as char);
-                    flush();
-                    line.pop();
-                }
-            }
             _ => {
-                print!("{}", c as char);
-                flush();
                 line.push(c as char);
             }
         }
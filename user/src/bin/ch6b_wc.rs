@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{read, STDIN};
+
+/// 在 shell 中运行：逐行读入标准输入，读到空行后输出行数与字节数（不计换行）。
+/// 把几百个字符粘贴进 QEMU 控制台再输入空行，输出的字节数应与粘贴的一致。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 256];
+    let (mut lines, mut bytes) = (0, 0);
+    let mut at_line_start = true;
+    loop {
+        let len = read(STDIN, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        let input = &buf[..len as usize];
+        if at_line_start && input == b"\n" {
+            break;
+        }
+        for &c in input {
+            if c == b'\n' {
+                lines += 1;
+            } else {
+                bytes += 1;
+            }
+        }
+        at_line_start = input.last() == Some(&b'\n');
+    }
+    println!("{} lines, {} bytes", lines, bytes);
+    0
+}
@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{write, STDOUT};
+
+/// 在 shell 中运行：ch6b_yes | ch6b_wc | ch6b_wc，不停向标准输出写 "y"。
+/// 三个进程属于同一进程组，按一次 Ctrl-C 应全部退出（shell 报告退出码 -2）并回到提示符。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    loop {
+        if write(STDOUT, b"y\n") < 0 {
+            return 1;
+        }
+    }
+}
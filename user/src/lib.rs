@@ -5,6 +5,8 @@
 
 #[macro_use]
 pub mod console;
+#[path = "../../os6/src/syscall/errno.rs"]
+pub mod errno;
 mod lang_items;
 mod syscall;
 
@@ -15,15 +17,43 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
 pub use console::{flush, STDIN, STDOUT};
+pub use errno::*;
 pub use syscall::*;
 
-const USER_HEAP_SIZE: usize = 16384;
+/// Smallest step the heap grows by
+const USER_HEAP_GROW: usize = 16384;
 
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+/// Buddy heap that asks the kernel for more memory through sbrk
+struct UserHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // a block of the rounded up size aligned to it surely fits in twice that
+        let size = layout.size().max(layout.align()).next_power_of_two();
+        let grow = (size * 2).max(USER_HEAP_GROW);
+        let base = sys_sbrk(grow as isize);
+        if base < 0 {
+            return core::ptr::null_mut();
+        }
+        heap.add_to_heap(base as usize, base as usize + grow);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+}
 
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: UserHeap = UserHeap(LockedHeap::empty());
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -48,10 +78,6 @@ fn clear_bss() {
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     clear_bss();
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -82,6 +108,10 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const APPEND = 1 << 11;
+        const NONBLOCK = 1 << 12;
+        /// [`FD_CLOEXEC`] on the new descriptor from the start
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -98,6 +128,16 @@ impl TimeVal {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     UnInit,
@@ -119,14 +159,162 @@ pub struct TaskInfo {
     pub status: TaskStatus,
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub time: usize,
+    pub resident_pages: usize,
+    pub mapped_pages: usize,
+    pub peak_resident_pages: usize,
+}
+
+/// Scheduler statistics of a process, or of the system for pid 0
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SchedStat {
+    pub dispatches: usize,
+    pub wait_us: usize,
+    pub preemptions: usize,
+    pub context_switches: usize,
+    pub timer_interrupts: usize,
+    /// Tasks the kernel found queued twice or in the wrong state
+    pub state_violations: usize,
+}
+
+/// Cycles and instructions retired by a process while it ran
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct PerfStat {
+    pub cycles: usize,
+    pub instret: usize,
+}
+
+/// A count of [`PerfStat`] the hardware does not implement
+pub const PERF_UNSUPPORTED: usize = usize::MAX;
+
+/// Physical frame usage of the whole system
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    pub high_water: usize,
+}
+
+/// Memory usage of a process in pages, shared frames count for every
+/// process mapping them, its CPU times and its I/O: the bytes read and
+/// written through descriptors, the block device requests issued on its
+/// behalf and the microseconds it waited for them. Of a child reaped by
+/// [`wait4`] only the peak, the times, which include the children it waited
+/// for, and its own I/O. `/proc/<pid>/io` splits the bytes by kind of file.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rusage {
+    pub resident_pages: usize,
+    pub mapped_pages: usize,
+    pub peak_resident_pages: usize,
+    pub utime_us: usize,
+    pub stime_us: usize,
+    pub read_bytes: usize,
+    pub written_bytes: usize,
+    pub block_reads: usize,
+    pub block_writes: usize,
+    pub block_wait_us: usize,
+}
+
+/// CPU times in clock ticks, [`Utsname::clock_ticks`] a second, see [`times`]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    /// User time of the children waited for, and of the ones they waited for
+    pub cutime: usize,
+    /// Kernel time of the same children
+    pub cstime: usize,
+}
+
+/// How far ASLR moved the stack, the heap and the shared memory base
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LayoutOffsets {
+    pub stack: usize,
+    pub heap: usize,
+    pub mmap: usize,
+}
+
+/// Address space layout of the current process
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AddressLayout {
+    pub stack_top: usize,
+    pub heap_base: usize,
+    pub mmap_base: usize,
+    pub offsets: LayoutOffsets,
+}
+
+/// Names of the kernel and the features it was built with, see [`uname`].
+/// The names are 0-terminated, read them with [`Utsname::field`].
+#[repr(C)]
+pub struct Utsname {
+    pub sysname: [u8; 65],
+    pub nodename: [u8; 65],
+    pub release: [u8; 65],
+    /// The commit and the date of the build
+    pub version: [u8; 65],
+    pub machine: [u8; 65],
+    pub domainname: [u8; 65],
+    /// `FEATURE_*` bits
+    pub features: u64,
+    /// Clock ticks a second of [`times`]
+    pub clock_ticks: u64,
+}
+
+impl Utsname {
+    pub fn new() -> Self {
+        Self {
+            sysname: [0; 65],
+            nodename: [0; 65],
+            release: [0; 65],
+            version: [0; 65],
+            machine: [0; 65],
+            domainname: [0; 65],
+            features: 0,
+            clock_ticks: 0,
+        }
+    }
+    /// A name up to its terminating 0
+    pub fn field(name: &[u8; 65]) -> &str {
+        let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        core::str::from_utf8(&name[..len]).unwrap_or("?")
+    }
 }
 
+/// [`Utsname::features`]: `mmap`, `munmap`, `mprotect` and `msync`
+pub const FEATURE_MMAP: u64 = 1 << 0;
+pub const FEATURE_SPAWN: u64 = 1 << 1;
+/// `kill`, `sigaction`, `sigreturn` and `setitimer`
+pub const FEATURE_SIGNALS: u64 = 1 << 2;
+/// `shmget`, `shmat` and `shmdt`
+pub const FEATURE_SHM: u64 = 1 << 3;
+/// `pipe`, `mkfifo` and `ppoll`
+pub const FEATURE_PIPE: u64 = 1 << 4;
+/// `linkat`, `unlinkat`, `mkdirat` and `getdents`
+pub const FEATURE_FS: u64 = 1 << 5;
+/// `set_priority` with the stride scheduler
+pub const FEATURE_STRIDE: u64 = 1 << 6;
+pub const FEATURE_FUTEX: u64 = 1 << 7;
+pub const FEATURE_EVENTFD: u64 = 1 << 8;
+/// The kernel randomizes the address space layout
+pub const FEATURE_ASLR: u64 = 1 << 9;
+/// The kernel has the self-test syscalls
+pub const FEATURE_SELFTEST: u64 = 1 << 10;
+
 impl TaskInfo {
     pub fn new() -> Self {
         TaskInfo {
             status: TaskStatus::UnInit,
             syscall_times: [0; MAX_SYSCALL_NUM],
             time: 0,
+            resident_pages: 0,
+            mapped_pages: 0,
+            peak_resident_pages: 0,
         }
     }
 }
@@ -142,8 +330,19 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// size in bytes, for a pipe the bytes waiting to be read
+    pub size: u64,
+    /// time of last access, seconds since the epoch
+    pub atime: u64,
+    /// time of last modification
+    pub mtime: u64,
+    /// time of last status change
+    pub ctime: u64,
+    /// permission bits, `rwx` for the owner, the group and others like
+    /// `0o644`, 0 outside the filesystem. `mode` only tells the type.
+    pub perm: u32,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u32; 5],
 }
 
 impl Stat {
@@ -153,7 +352,12 @@ impl Stat {
             ino: 0,
             mode: StatMode::NULL,
             nlink: 0,
-            pad: [0; 7],
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            perm: 0,
+            pad: [0; 5],
         }
     }
 }
@@ -167,6 +371,10 @@ impl Default for Stat {
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
+        /// FIFO, a pipe
+        const FIFO  = 0o010000;
+        /// character device, the console
+        const CHR   = 0o020000;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
@@ -174,7 +382,8 @@ bitflags! {
     }
 }
 
-const AT_FDCWD: isize = -100;
+/// `dirfd` standing for the current directory, the root
+pub const AT_FDCWD: isize = -100;
 
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits)
@@ -187,10 +396,31 @@ pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 
+/// Close the open descriptors from `first` to `last`, both included
+pub fn close_range(first: usize, last: usize) -> isize {
+    if (first..=last).contains(&STDOUT) {
+        console::flush();
+    }
+    sys_close_range(first, last, 0)
+}
+
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
 
+/// [`lseek`] from the start of the file
+pub const SEEK_SET: usize = 0;
+/// [`lseek`] from the current offset
+pub const SEEK_CUR: usize = 1;
+/// [`lseek`] from the end of the file
+pub const SEEK_END: usize = 2;
+
+/// Move the offset of the open file `fd`, shared with its duplicates and
+/// the children forked since it was opened. Returns the new offset.
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
@@ -203,10 +433,198 @@ pub fn unlink(path: &str) -> isize {
     sys_unlinkat(AT_FDCWD as usize, path, 0)
 }
 
+/// Copy up to `len` bytes from `fd_in` to `fd_out` inside the kernel. An
+/// offset given is used and advanced instead of the offset of its file.
+/// Returns the bytes copied, 0 at the end of `fd_in`.
+pub fn copy_file_range(
+    fd_in: usize,
+    off_in: Option<&mut isize>,
+    fd_out: usize,
+    off_out: Option<&mut isize>,
+    len: usize,
+) -> isize {
+    let ptr = |offset: Option<&mut isize>| {
+        offset.map_or(core::ptr::null_mut(), |offset| offset as *mut isize)
+    };
+    sys_copy_file_range(fd_in, ptr(off_in), fd_out, ptr(off_out), len, 0)
+}
+
+/// Link `old_path` relative to `old_dirfd` as `new_path` relative to
+/// `new_dirfd`, either fd may be [`AT_FDCWD`]
+pub fn linkat(old_dirfd: isize, old_path: &str, new_dirfd: isize, new_path: &str) -> isize {
+    sys_linkat(old_dirfd as usize, old_path, new_dirfd as usize, new_path, 0)
+}
+
+/// [`unlinkat`] flag to remove an empty directory instead of a file
+pub const AT_REMOVEDIR: usize = 0x200;
+
+/// Remove `path` relative to the open directory `dirfd`
+pub fn unlinkat(dirfd: isize, path: &str, flags: usize) -> isize {
+    sys_unlinkat(dirfd as usize, path, flags)
+}
+
+/// Remove the empty directory at `path`
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD as usize, path, AT_REMOVEDIR)
+}
+
 pub fn fstat(fd: usize, st: &Stat) -> isize {
     sys_fstat(fd, st)
 }
 
+/// Write what the kernel caches of file `fd` back to the disk
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+
+/// Write everything the kernel caches back to the disk
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD as usize, path, 0o755)
+}
+
+/// Create a directory at `path` relative to the open directory `dirfd`
+pub fn mkdirat(dirfd: isize, path: &str) -> isize {
+    sys_mkdirat(dirfd as usize, path, 0o755)
+}
+
+/// Set the permission bits of the file at `path`, like `0o644`. Only the
+/// owner's are enforced: opening to write needs `0o200`, running `0o100`.
+pub fn chmod(path: &str, mode: u32) -> isize {
+    sys_fchmodat(AT_FDCWD as usize, path, mode)
+}
+
+/// Set the permission bits of the open file `fd`
+pub fn fchmod(fd: usize, mode: u32) -> isize {
+    sys_fchmod(fd, mode)
+}
+
+/// Create a named pipe at `path`, open it to get one of its ends
+pub fn mkfifo(path: &str) -> isize {
+    sys_mkfifo(path)
+}
+
+/// Fill `buf` with records of the next entries of the directory `fd`, see
+/// [`dirents`]. Returns the bytes written, 0 at the end. The offset of
+/// `fd` is where the next call goes on, [`lseek`] to 0 starts over.
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents(fd, buf)
+}
+
+/// Type of a directory entry for a directory
+pub const DT_DIR: u8 = 4;
+/// Type of a directory entry for a regular file
+pub const DT_REG: u8 = 8;
+/// Type of a directory entry for a named pipe
+pub const DT_FIFO: u8 = 1;
+
+/// A record written by [`getdents`]
+pub struct Dirent<'a> {
+    pub ino: u64,
+    /// [`DT_DIR`], [`DT_REG`] or [`DT_FIFO`]
+    pub kind: u8,
+    pub name: &'a str,
+}
+
+/// The records in `buf`, the part of a buffer filled by [`getdents`]
+pub fn dirents(buf: &[u8]) -> impl Iterator<Item = Dirent<'_>> {
+    let mut rest = buf;
+    core::iter::from_fn(move || {
+        if rest.len() < 11 {
+            return None;
+        }
+        let mut ino = [0u8; 8];
+        ino.copy_from_slice(&rest[..8]);
+        let ino = u64::from_le_bytes(ino);
+        let reclen = u16::from_le_bytes([rest[8], rest[9]]) as usize;
+        let kind = rest[10];
+        let name = &rest[11..reclen];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        let name = core::str::from_utf8(&name[..name_len]).unwrap();
+        rest = &rest[reclen..];
+        Some(Dirent { ino, kind, name })
+    })
+}
+
+/// A buffer of [`readv`] and [`writev`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoVec {
+    pub base: usize,
+    pub len: usize,
+}
+
+impl IoVec {
+    /// A buffer for [`writev`] to take from
+    pub fn new(buf: &[u8]) -> Self {
+        Self {
+            base: buf.as_ptr() as usize,
+            len: buf.len(),
+        }
+    }
+    /// A buffer for [`readv`] to fill
+    pub fn new_mut(buf: &mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr() as usize,
+            len: buf.len(),
+        }
+    }
+}
+
+/// Write the buffers of `iov` one after another with a single write,
+/// returns the total bytes written
+pub fn writev(fd: usize, iov: &[IoVec]) -> isize {
+    sys_writev(fd, iov)
+}
+
+/// Fill the buffers of `iov` one after another with a single read, returns
+/// the total bytes read
+pub fn readv(fd: usize, iov: &[IoVec]) -> isize {
+    sys_readv(fd, iov)
+}
+
+/// An entry of [`poll`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PollFd {
+    /// Entries with a negative fd are skipped
+    pub fd: i32,
+    /// Events to wait for
+    pub events: u16,
+    /// Events that happened
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: u16) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// There is data to read
+pub const POLLIN: u16 = 0x001;
+/// Writing does not block
+pub const POLLOUT: u16 = 0x004;
+/// All read ends of a pipe are closed, reported even if not asked for
+pub const POLLERR: u16 = 0x008;
+/// All write ends of a pipe are closed, reported even if not asked for
+pub const POLLHUP: u16 = 0x010;
+/// The fd is not open
+pub const POLLNVAL: u16 = 0x020;
+
+/// Wait until one of `fds` is ready, or for `timeout_ms`, forever if it is
+/// negative. Returns how many are ready, 0 on timeout.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    sys_ppoll(fds, timeout_ms)
+}
+
 pub fn mail_read(buf: &mut [u8]) -> isize {
     sys_mail_read(buf)
 }
@@ -220,6 +638,27 @@ pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
 
+/// Kill every other process, write everything back to the disk and power
+/// off the machine, telling it whether for a `failure`. Only initproc and
+/// the shell it started may, -EPERM for anyone else.
+pub fn shutdown(failure: bool) -> isize {
+    console::flush();
+    sys_shutdown(failure)
+}
+
+/// Make the kernel overflow our kernel stack, which brings it down. -ENOSYS
+/// if the kernel was built without its self-tests.
+pub fn overflow_kernel_stack() -> isize {
+    console::flush();
+    sys_overflow_kernel_stack()
+}
+
+/// Exit, killing every descendant instead of leaving them to initproc
+pub fn exit_group(exit_code: i32) -> ! {
+    console::flush();
+    sys_exit_group(exit_code);
+}
+
 pub fn yield_() -> isize {
     sys_yield()
 }
@@ -228,10 +667,18 @@ pub fn get_time() -> isize {
     let time = TimeVal::new();
     match sys_get_time(&time, 0) {
         0 => ((time.sec & 0xffff) * 1000 + time.usec / 1000) as isize,
-        _ => -1,
+        err => err,
     }
 }
 
+pub fn clock_gettime(clock_id: usize, ts: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, ts)
+}
+
+pub fn clock_settime(clock_id: usize, ts: &TimeSpec) -> isize {
+    sys_clock_settime(clock_id, ts)
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
@@ -245,13 +692,32 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
 }
 
 pub fn set_priority(prio: isize) -> isize {
-    sys_set_priority(prio)
+    sys_set_priority(prio, 0)
+}
+
+pub fn get_priority() -> isize {
+    sys_get_priority(0)
+}
+
+/// Set the priority of process `pid`, -3 (ESRCH) if there is none
+pub fn set_priority_of(pid: usize, prio: isize) -> isize {
+    sys_set_priority(prio, pid)
+}
+
+pub fn get_priority_of(pid: usize) -> isize {
+    sys_get_priority(pid)
+}
+
+/// Lower the priority by `increment`, or raise it if negative, within its
+/// bounds. Returns the new priority.
+pub fn nice(increment: isize) -> isize {
+    sys_nice(increment)
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
+        match sys_wait4(-1, exit_code as *mut _, 0, core::ptr::null_mut()) {
+            n if n == -EAGAIN => {
                 sys_yield();
             }
             n => {
@@ -261,10 +727,148 @@ pub fn wait(exit_code: &mut i32) -> isize {
     }
 }
 
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+/// Terminates the process and writes `core.<pid>` by default
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGKILL: i32 = 9;
+pub const SIGSEGV: i32 = 11;
+/// Raised by writing to a pipe whose read ends are all closed
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+
+/// Name of the signal `signum` among the ones above
+pub fn signal_name(signum: i32) -> Option<&'static str> {
+    Some(match signum {
+        SIGHUP => "SIGHUP",
+        SIGINT => "SIGINT",
+        SIGQUIT => "SIGQUIT",
+        SIGILL => "SIGILL",
+        SIGKILL => "SIGKILL",
+        SIGSEGV => "SIGSEGV",
+        SIGPIPE => "SIGPIPE",
+        SIGALRM => "SIGALRM",
+        SIGTERM => "SIGTERM",
+        SIGCHLD => "SIGCHLD",
+        _ => return None,
+    })
+}
+
+/// Default action, which terminates the process for most signals
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+pub const ITIMER_REAL: usize = 0;
+
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Key of a shared memory segment no other process can look up
+pub const IPC_PRIVATE: usize = 0;
+
+pub const RUSAGE_SELF: isize = 0;
+
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+
+/// Get the limit of `resource`, -EINVAL if it is unknown
+pub fn getrlimit(resource: usize) -> isize {
+    let mut old = 0usize;
+    match sys_prlimit(resource, core::ptr::null(), &mut old) {
+        0 => old as isize,
+        err => err,
+    }
+}
+
+pub fn setrlimit(resource: usize, limit: usize) -> isize {
+    sys_prlimit(resource, &limit, core::ptr::null_mut())
+}
+
+pub fn kill(pid: usize, signal: i32) -> isize {
+    sys_kill(pid, signal)
+}
+
+/// Send `signal` to every process of group `pgid`
+pub fn killpg(pgid: usize, signal: i32) -> isize {
+    sys_kill((pgid as isize).wrapping_neg() as usize, signal)
+}
+
+/// Move process `pid`, the current one if 0, into group `pgid`, a new group
+/// led by it if `pgid` is 0. Only the current process and its children can
+/// be moved.
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+/// The process group of `pid`, the current process if 0
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+/// Install `handler`, which is called with the signal number and must end
+/// with [`sigreturn`]. Returns the previous handler.
+pub fn sigaction(signum: i32, handler: fn(i32)) -> isize {
+    sys_sigaction(signum, handler as usize)
+}
+
+/// Install `handler` for a fault signal, SIGSEGV or SIGILL, it gets the
+/// faulting address, or that of the faulting instruction for SIGILL, as
+/// its second argument
+pub fn sigaction_fault(signum: i32, handler: fn(i32, usize)) -> isize {
+    sys_sigaction(signum, handler as usize)
+}
+
+/// Restore the action of `signum` to `SIG_DFL` or `SIG_IGN`
+pub fn signal(signum: i32, action: usize) -> isize {
+    sys_sigaction(signum, action)
+}
+
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
+/// Returns the milliseconds left on the replaced timer
+pub fn setitimer(which: usize, interval_ms: usize, value_ms: usize) -> isize {
+    sys_setitimer(which, interval_ms, value_ms)
+}
+
+/// Raise SIGALRM once in `secs` seconds, 0 cancels a pending alarm.
+/// Returns the seconds left on the previous alarm, rounded up.
+pub fn alarm(secs: usize) -> usize {
+    let left_ms = setitimer(ITIMER_REAL, 0, secs * 1000);
+    (left_ms as usize + 999) / 1000
+}
+
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
+        match sys_wait4(pid as isize, exit_code as *mut _, 0, core::ptr::null_mut()) {
+            n if n == -EAGAIN => {
+                sys_yield();
+            }
+            n => {
+                return n;
+            }
+        }
+    }
+}
+
+/// [`wait4`] option: store the wait status rather than the bare exit code
+pub const WSTATUS: usize = 1;
+
+/// Wait for the child `pid`, any child if -1, any child in the process
+/// group `-pid` if below, and reap it. `status` gets its wait status, see
+/// [`wifexited`], and `usage` its usage. Returns its pid, -ECHILD without
+/// such a child.
+pub fn wait4(pid: isize, status: &mut i32, usage: Option<&mut Rusage>) -> isize {
+    let usage = usage.map_or(core::ptr::null_mut(), |usage| usage as *mut _);
+    loop {
+        match sys_wait4(pid, status as *mut _, WSTATUS, usage) {
+            n if n == -EAGAIN => {
                 sys_yield();
             }
             n => {
@@ -274,6 +878,26 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     }
 }
 
+/// Whether the wait status `status` is of a child that exited by itself
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+/// The low byte of the exit code of a child that exited by itself
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// Whether the wait status `status` is of a child killed by a signal
+pub fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0
+}
+
+/// The signal that killed the child
+pub fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
 pub fn sleep_blocking(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
@@ -285,11 +909,99 @@ pub fn sleep(period_ms: usize) {
     }
 }
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    sys_mmap_file(start, len, prot, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0)
+}
+
+pub fn mmap_file(start: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap_file(start, len, prot, flags, fd, offset)
+}
+
+pub fn msync(start: usize, len: usize) -> isize {
+    sys_msync(start, len)
+}
+
+/// Change the permissions of mapped pages, `prot` as for `mmap`
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+pub fn sbrk(increment: isize) -> isize {
+    sys_sbrk(increment)
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
-    sys_munmap(start, len)
+    sys_munmap2(start, len)
+}
+
+pub fn shmget(key: usize, size: usize) -> isize {
+    sys_shmget(key, size)
+}
+
+pub fn shmat(shmid: usize) -> isize {
+    sys_shmat(shmid)
+}
+
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// Sleep while `word` holds `val`, for `timeout_ms` or forever if it is
+/// negative. Returns 0 when woken, which may be spurious, `-EAGAIN` or
+/// `-ETIMEDOUT`.
+pub fn futex_wait(word: &AtomicU32, val: u32, timeout_ms: isize) -> isize {
+    sys_futex(word, FUTEX_WAIT, val as usize, timeout_ms)
+}
+
+/// Wake up to `count` tasks sleeping on `word`, returns how many
+pub fn futex_wake(word: &AtomicU32, count: usize) -> isize {
+    sys_futex(word, FUTEX_WAKE, count, 0)
+}
+
+/// A mutex that only enters the kernel when contended.
+///
+/// The word is 0 when unlocked, 1 when locked and 2 when locked with
+/// waiters maybe sleeping on it, after Drepper's "Futexes Are Tricky". To be
+/// shared between processes it has to live in shared memory, see [`shmat`].
+#[repr(transparent)]
+pub struct FutexMutex {
+    state: AtomicU32,
+}
+
+impl FutexMutex {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(0),
+        }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn lock(&self) {
+        if self.try_lock() {
+            return;
+        }
+        // whoever takes it from here on may leave sleepers behind
+        while self.state.swap(2, Ordering::Acquire) != 0 {
+            futex_wait(&self.state, 2, -1);
+        }
+    }
+
+    pub fn unlock(&self) {
+        if self.state.swap(0, Ordering::Release) == 2 {
+            futex_wake(&self.state, 1);
+        }
+    }
 }
 
 pub fn spawn(path: &str) -> isize {
@@ -299,14 +1011,256 @@ pub fn spawn(path: &str) -> isize {
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+
+/// [`fcntl`] command: duplicate to the lowest free fd not below `arg`
+pub const F_DUPFD: usize = 0;
+/// [`fcntl`] command: get the flags of the descriptor, [`FD_CLOEXEC`]
+pub const F_GETFD: usize = 1;
+/// [`fcntl`] command: set the flags of the descriptor
+pub const F_SETFD: usize = 2;
+/// [`fcntl`] command: get the access mode, [`OpenFlags::NONBLOCK`] and
+/// [`OpenFlags::APPEND`]
+pub const F_GETFL: usize = 3;
+/// [`fcntl`] command: set or clear [`OpenFlags::NONBLOCK`] and
+/// [`OpenFlags::APPEND`]
+pub const F_SETFL: usize = 4;
+/// [`fcntl`] command: [`F_DUPFD`] with [`FD_CLOEXEC`] on the new fd
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+/// [`fcntl`] command: let a pipe hold `arg` bytes, rounded up to whole
+/// pages and at most 64 KiB, returns the new capacity
+pub const F_SETPIPE_SZ: usize = 1031;
+/// [`fcntl`] command: get the capacity of a pipe
+pub const F_GETPIPE_SZ: usize = 1032;
+/// Descriptor flag: closed by [`exec`]
+pub const FD_CLOEXEC: usize = 1;
+
+/// Get or set the flags of the open file `fd`
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+/// [`ioctl`] request: get the local modes of the console, a `u32`
+pub const TCGETS: usize = 0x5401;
+/// [`ioctl`] request: set the local modes of the console from a `u32`
+pub const TCSETS: usize = 0x5402;
+/// [`ioctl`] request: get the process group Ctrl-C is sent to, an `i32`
+pub const TIOCGPGRP: usize = 0x540f;
+/// [`ioctl`] request: send Ctrl-C to the process group in an `i32`, 0 for
+/// none
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Local mode: Ctrl-C sends SIGINT
+pub const ISIG: u32 = 0o1;
+/// Local mode: input is edited and read line by line
+pub const ICANON: u32 = 0o2;
+/// Local mode: typed bytes are echoed
+pub const ECHO: u32 = 0o10;
+
+/// Configure the console through `fd`, `arg` points to the value `request`
+/// gets or sets
+pub fn ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    sys_ioctl(fd, request, arg)
+}
+
+/// The local modes of the console `fd`, negative on error
+pub fn tcgetlflag(fd: usize) -> isize {
+    let mut modes = 0u32;
+    match ioctl(fd, TCGETS, &mut modes as *mut u32 as usize) {
+        0 => modes as isize,
+        err => err,
+    }
+}
+
+/// Set the local modes of the console `fd`, 0 turns it raw
+pub fn tcsetlflag(fd: usize, modes: u32) -> isize {
+    ioctl(fd, TCSETS, &modes as *const u32 as usize)
+}
+
+/// The process group Ctrl-C on the console `fd` is sent to, 0 for none
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid = 0i32;
+    match ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+/// Send Ctrl-C on the console `fd` to every process of group `pgid`, or to
+/// none if it is 0
+pub fn tcsetpgrp(fd: usize, pgid: isize) -> isize {
+    let pgid = pgid as i32;
+    ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)
+}
+
+/// Make `new_fd` refer to the same open file as `old_fd`, closing it first
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    sys_dup2(old_fd, new_fd)
+}
+
+/// Like [`dup2`], with [`FD_CLOEXEC`] on `new_fd` at once for [`O_CLOEXEC`]
+/// in `flags`. -EINVAL if `old_fd` is `new_fd`.
+pub fn dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    sys_dup3(old_fd, new_fd, flags)
+}
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    pipe2(pipe_fd, 0)
+}
+
+/// [`pipe2`] and [`dup3`] flag: [`FD_CLOEXEC`] on the new fds, the bits of
+/// [`OpenFlags::CLOEXEC`]
+pub const O_CLOEXEC: u32 = OpenFlags::CLOEXEC.bits();
+/// Writes to a pipe of at most this many bytes are never interleaved with
+/// those of other writers
+pub const PIPE_BUF: usize = 512;
+
+/// Like [`pipe`], `flags` are [`OpenFlags::NONBLOCK`] and [`O_CLOEXEC`]
+pub fn pipe2(pipe_fd: &mut [usize], flags: u32) -> isize {
+    let mut fds = [0u32; 2];
+    let ret = sys_pipe2(&mut fds, flags);
+    if ret == 0 {
+        pipe_fd[0] = fds[0] as usize;
+        pipe_fd[1] = fds[1] as usize;
+    }
+    ret
+}
+
+/// [`eventfd`] flag: reads take 1 instead of the whole count
+pub const EFD_SEMAPHORE: u32 = 1;
+/// [`eventfd`] flag: [`FD_CLOEXEC`] on the new fd
+pub const EFD_CLOEXEC: u32 = 1 << 19;
+/// [`eventfd`] flag: [`OpenFlags::NONBLOCK`]
+pub const EFD_NONBLOCK: u32 = OpenFlags::NONBLOCK.bits();
+
+/// Create an event counter starting at `initval`, returns its fd. Writing
+/// 8 bytes adds them to the counter, reading 8 bytes takes it.
+pub fn eventfd(initval: u32, flags: u32) -> isize {
+    sys_eventfd(initval, flags)
+}
+
+/// Read the counter of the eventfd `fd`, the negated errno if it fails
+pub fn eventfd_read(fd: usize) -> Result<u64, isize> {
+    let mut buf = [0u8; 8];
+    match read(fd, &mut buf) {
+        8 => Ok(u64::from_ne_bytes(buf)),
+        ret if ret < 0 => Err(ret),
+        _ => Err(-EINVAL),
+    }
+}
+
+/// Add `value` to the counter of the eventfd `fd`
+pub fn eventfd_write(fd: usize, value: u64) -> isize {
+    match write(fd, &value.to_ne_bytes()) {
+        8 => 0,
+        ret if ret < 0 => ret,
+        _ => -EINVAL,
+    }
 }
 
 pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
 
+pub fn set_name(name: &str) -> isize {
+    sys_set_name(name)
+}
+
+pub fn get_name(buf: &mut [u8]) -> isize {
+    sys_get_name(buf)
+}
+
+pub fn sched_stat(pid: usize, stat: &mut SchedStat) -> isize {
+    sys_sched_stat(pid, stat)
+}
+
+/// Kernel log levels of [`log_ctl`]
+pub const LOG_OFF: isize = 0;
+pub const LOG_ERROR: isize = 1;
+pub const LOG_WARN: isize = 2;
+pub const LOG_INFO: isize = 3;
+pub const LOG_DEBUG: isize = 4;
+pub const LOG_TRACE: isize = 5;
+/// Bits of the module mask of [`log_ctl`]
+pub const LOG_TASK: isize = 1 << 0;
+pub const LOG_MM: isize = 1 << 1;
+pub const LOG_FS: isize = 1 << 2;
+pub const LOG_DRIVERS: isize = 1 << 3;
+pub const LOG_SYSCALL: isize = 1 << 4;
+pub const LOG_PID: isize = 1 << 8;
+pub const LOG_TIME: isize = 1 << 9;
+
+/// Set the kernel log level and module mask, -1 keeps either as it is.
+/// Returns the previous mask shifted left by 3 ored with the previous level,
+/// -EPERM if the caller dropped its privilege or -EINVAL if the level is out
+/// of range.
+pub fn log_ctl(level: isize, mask: isize) -> isize {
+    sys_log_ctl(level, mask)
+}
+
+/// Turn the syscall latency histograms of `/proc/syscalls` and the switch
+/// and slice histograms of `/proc/sched_hist` off for 0 and on for 1, -1
+/// keeps it as it is; `reset` forgets what they recorded so far.
+/// Returns 1 if they were on and 0 if not, -EPERM if the caller dropped its
+/// privilege or -EINVAL if `on` is out of range.
+pub fn prof_ctl(on: isize, reset: bool) -> isize {
+    sys_prof_ctl(on, reset)
+}
+
+/// Copy the blocks in use of the root filesystem to the region its image
+/// reserves for a snapshot, replacing the one there. Returns the blocks
+/// copied, -EPERM if the caller dropped its privilege, -ENOSPC if they do
+/// not fit or -EROFS if the filesystem is read-only.
+pub fn fs_snapshot() -> isize {
+    sys_fs_snapshot(0)
+}
+
+/// Put the root filesystem back as it was at the last [`fs_snapshot`].
+/// Descriptors open on files freed or replaced by it fail with -ESTALE
+/// from then on. Returns the blocks copied, -EPERM if the caller dropped
+/// its privilege, -ENOENT if there is no snapshot, -EBUSY if pages are
+/// swapped out or -EROFS if the filesystem is read-only.
+pub fn fs_restore() -> isize {
+    sys_fs_snapshot(1)
+}
+
+/// Give up the privilege to change kernel settings, for this process and
+/// the children it forks from now on
+pub fn drop_privilege() -> isize {
+    sys_drop_privilege()
+}
+
+/// Counters of process `pid`, the caller for 0
+pub fn perf_read(pid: usize, stat: &mut PerfStat) -> isize {
+    sys_perf_read(pid, stat)
+}
+
+/// Number of free physical frames
+pub fn free_frames() -> usize {
+    sys_free_frames() as usize
+}
+
+pub fn frame_stats(stats: &mut FrameStats) -> isize {
+    sys_frame_stats(stats)
+}
+
+/// Describe the kernel, see [`Utsname`]
+pub fn uname(buf: &mut Utsname) -> isize {
+    sys_uname(buf)
+}
+
+pub fn get_layout(layout: &mut AddressLayout) -> isize {
+    sys_get_layout(layout)
+}
+
+pub fn getrusage(who: isize, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage)
+}
+
+/// Fill `buf` with the CPU times of the caller and of its children waited
+/// for, returns the clock ticks since boot
+pub fn times(buf: &mut Tms) -> isize {
+    sys_times(buf)
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
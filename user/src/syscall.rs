@@ -1,32 +1,90 @@
-use crate::TaskInfo;
+use crate::{AddressLayout, Utsname, FrameStats, PerfStat, Rusage, SchedStat, TaskInfo, Tms};
 
-use super::{Stat, TimeVal};
+use super::{IoVec, PollFd, Stat, TimeSpec, TimeVal};
+use core::sync::atomic::AtomicU32;
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
+pub const SYSCALL_GETDENTS: usize = 61;
+pub const SYSCALL_LSEEK: usize = 62;
+pub const SYSCALL_PPOLL: usize = 73;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_READV: usize = 65;
+pub const SYSCALL_WRITEV: usize = 66;
+pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
+pub const SYSCALL_FCHMOD: usize = 52;
+pub const SYSCALL_FCHMODAT: usize = 53;
 pub const SYSCALL_FSTAT: usize = 80;
+pub const SYSCALL_SYNC: usize = 81;
+pub const SYSCALL_FSYNC: usize = 82;
 pub const SYSCALL_EXIT: usize = 93;
+pub const SYSCALL_EXIT_GROUP: usize = 94;
+pub const SYSCALL_FUTEX: usize = 98;
 pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_SETITIMER: usize = 103;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_SETPGID: usize = 154;
+pub const SYSCALL_GETPGID: usize = 155;
+pub const SYSCALL_UNAME: usize = 160;
+pub const SYSCALL_GETRUSAGE: usize = 165;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
+pub const SYSCALL_CLOCK_SETTIME: usize = 112;
+pub const SYSCALL_CLOCK_GETTIME: usize = 113;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
+pub const SYSCALL_WAIT4: usize = 429;
+pub const SYSCALL_MMAP_FILE: usize = 430;
+pub const SYSCALL_MUNMAP2: usize = 431;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_PRLIMIT: usize = 261;
+pub const SYSCALL_COPY_FILE_RANGE: usize = 285;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
+pub const SYSCALL_GET_PRIORITY: usize = 141;
+pub const SYSCALL_BRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
+pub const SYSCALL_MSYNC: usize = 227;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
+pub const SYSCALL_CLOSE_RANGE: usize = 436;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_FCNTL: usize = 25;
+pub const SYSCALL_IOCTL: usize = 29;
 pub const SYSCALL_PIPE: usize = 59;
+pub const SYSCALL_EVENTFD: usize = 19;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_SET_NAME: usize = 411;
+pub const SYSCALL_GET_NAME: usize = 412;
+pub const SYSCALL_SCHED_STAT: usize = 413;
+pub const SYSCALL_FREE_FRAMES: usize = 414;
+pub const SYSCALL_FRAME_STATS: usize = 416;
+pub const SYSCALL_SBRK: usize = 415;
+pub const SYSCALL_GET_LAYOUT: usize = 417;
+pub const SYSCALL_DUP2: usize = 418;
+pub const SYSCALL_SHUTDOWN: usize = 419;
+pub const SYSCALL_MKFIFO: usize = 420;
+pub const SYSCALL_OVERFLOW_KERNEL_STACK: usize = 421;
+pub const SYSCALL_PERF_READ: usize = 422;
+pub const SYSCALL_LOG_CTL: usize = 423;
+pub const SYSCALL_DROP_PRIVILEGE: usize = 424;
+pub const SYSCALL_PROF_CTL: usize = 425;
+pub const SYSCALL_NICE: usize = 426;
+pub const SYSCALL_FS_SNAPSHOT: usize = 427;
+pub const SYSCALL_DUP3: usize = 428;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -88,6 +146,14 @@ pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
+pub fn sys_close_range(first: usize, last: usize, flags: usize) -> isize {
+    syscall(SYSCALL_CLOSE_RANGE, [first, last, flags])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -99,6 +165,20 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_copy_file_range(
+    fd_in: usize,
+    off_in: *mut isize,
+    fd_out: usize,
+    off_out: *mut isize,
+    len: usize,
+    flags: usize,
+) -> isize {
+    syscall6(
+        SYSCALL_COPY_FILE_RANGE,
+        [fd_in, off_in as usize, fd_out, off_out as usize, len, flags],
+    )
+}
+
 pub fn sys_linkat(
     old_dirfd: usize,
     old_path: &str,
@@ -123,10 +203,61 @@ pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
 
+pub fn sys_mkdirat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_fchmod(fd: usize, mode: u32) -> isize {
+    syscall(SYSCALL_FCHMOD, [fd, mode as usize, 0])
+}
+
+pub fn sys_fchmodat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_FCHMODAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_mkfifo(path: &str) -> isize {
+    syscall(SYSCALL_MKFIFO, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_getdents(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETDENTS, [fd, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_readv(fd: usize, iov: &[IoVec]) -> isize {
+    syscall(SYSCALL_READV, [fd, iov.as_ptr() as usize, iov.len()])
+}
+
+pub fn sys_writev(fd: usize, iov: &[IoVec]) -> isize {
+    syscall(SYSCALL_WRITEV, [fd, iov.as_ptr() as usize, iov.len()])
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    syscall(
+        SYSCALL_PPOLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout_ms as usize],
+    )
+}
+
 pub fn sys_fstat(fd: usize, st: &Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
 }
 
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_overflow_kernel_stack() -> isize {
+    syscall(SYSCALL_OVERFLOW_KERNEL_STACK, [0, 0, 0])
+}
+
+pub fn sys_shutdown(failure: bool) -> isize {
+    syscall(SYSCALL_SHUTDOWN, [failure as usize, 0, 0])
+}
+
 pub fn sys_mail_read(buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_MAIL_READ,
@@ -146,6 +277,18 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0]);
+    panic!("sys_exit_group never returns!");
+}
+
+pub fn sys_futex(uaddr: &AtomicU32, op: usize, val: usize, timeout_ms: isize) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as *const AtomicU32 as usize, op, val, timeout_ms as usize, 0, 0],
+    )
+}
+
 pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
@@ -158,6 +301,14 @@ pub fn sys_get_time(time: &TimeVal, tz: usize) -> isize {
     syscall(SYSCALL_GETTIMEOFDAY, [time as *const _ as usize, tz, 0])
 }
 
+pub fn sys_clock_gettime(clock_id: usize, ts: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, ts as *mut _ as usize, 0])
+}
+
+pub fn sys_clock_settime(clock_id: usize, ts: &TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_SETTIME, [clock_id, ts as *const _ as usize, 0])
+}
+
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
@@ -173,22 +324,104 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+pub fn sys_wait4(pid: isize, xstatus: *mut i32, options: usize, usage: *mut Rusage) -> isize {
+    syscall6(
+        SYSCALL_WAIT4,
+        [pid as usize, xstatus as usize, options, usage as usize, 0, 0],
+    )
+}
+
+pub fn sys_kill(pid: usize, signal: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid, signal as usize, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_sigaction(signum: i32, handler: usize) -> isize {
+    syscall(SYSCALL_SIGACTION, [signum as usize, handler, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, interval_ms: usize, value_ms: usize) -> isize {
+    syscall(SYSCALL_SETITIMER, [which, interval_ms, value_ms])
+}
+
+pub fn sys_prlimit(resource: usize, new: *const usize, old: *mut usize) -> isize {
+    syscall(SYSCALL_PRLIMIT, [resource, new as usize, old as usize])
 }
 
-pub fn sys_set_priority(prio: isize) -> isize {
-    syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
+pub fn sys_set_priority(prio: isize, pid: usize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio as usize, pid, 0])
+}
+
+pub fn sys_get_priority(pid: usize) -> isize {
+    syscall(SYSCALL_GET_PRIORITY, [pid, 0, 0])
+}
+
+pub fn sys_nice(increment: isize) -> isize {
+    syscall(SYSCALL_NICE, [increment as usize, 0, 0])
 }
 
 pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
     syscall(SYSCALL_MMAP, [start, len, prot])
 }
 
+pub fn sys_mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP_FILE, [start, len, prot, flags, fd, offset])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
+pub fn sys_sbrk(increment: isize) -> isize {
+    syscall(SYSCALL_SBRK, [increment as usize, 0, 0])
+}
+
+pub fn sys_msync(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [start, len, 0])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_shmget(key: usize, size: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, 0])
+}
+
+pub fn sys_shmat(shmid: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, 0, 0])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_munmap2(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP2, [start, len, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -197,14 +430,90 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
-pub fn sys_pipe(pipe: &mut [usize]) -> isize {
-    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, request, arg])
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    syscall(SYSCALL_DUP2, [old_fd, new_fd, 0])
+}
+
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+pub fn sys_pipe2(pipe: &mut [u32; 2], flags: u32) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_eventfd(initval: u32, flags: u32) -> isize {
+    syscall(SYSCALL_EVENTFD, [initval as usize, flags as usize, 0])
 }
 
 pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+pub fn sys_set_name(name: &str) -> isize {
+    syscall(SYSCALL_SET_NAME, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_get_name(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GET_NAME, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_sched_stat(pid: usize, stat: &mut SchedStat) -> isize {
+    syscall(SYSCALL_SCHED_STAT, [pid, stat as *mut _ as usize, 0])
+}
+
+pub fn sys_perf_read(pid: usize, stat: &mut PerfStat) -> isize {
+    syscall(SYSCALL_PERF_READ, [pid, stat as *mut _ as usize, 0])
+}
+
+pub fn sys_log_ctl(level: isize, mask: isize) -> isize {
+    syscall(SYSCALL_LOG_CTL, [level as usize, mask as usize, 0])
+}
+
+pub fn sys_prof_ctl(on: isize, reset: bool) -> isize {
+    syscall(SYSCALL_PROF_CTL, [on as usize, reset as usize, 0])
+}
+
+pub fn sys_fs_snapshot(op: usize) -> isize {
+    syscall(SYSCALL_FS_SNAPSHOT, [op, 0, 0])
+}
+
+pub fn sys_drop_privilege() -> isize {
+    syscall(SYSCALL_DROP_PRIVILEGE, [0, 0, 0])
+}
+
+pub fn sys_free_frames() -> isize {
+    syscall(SYSCALL_FREE_FRAMES, [0, 0, 0])
+}
+
+pub fn sys_frame_stats(stats: &mut FrameStats) -> isize {
+    syscall(SYSCALL_FRAME_STATS, [stats as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_uname(buf: &mut Utsname) -> isize {
+    syscall(SYSCALL_UNAME, [buf as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_get_layout(layout: &mut AddressLayout) -> isize {
+    syscall(SYSCALL_GET_LAYOUT, [layout as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
+pub fn sys_times(buf: &mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [buf as *mut _ as usize, 0, 0])
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }